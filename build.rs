@@ -139,12 +139,25 @@ fn main() {
         }
     }
 
+    // A variable with the same name as a function, like `buffer-file-name',
+    // shares the symbol of the function instead of getting one of its own.
+    let shared: Vec<String> = all_defvar
+        .iter()
+        .filter(|(_, name, _, _)| all_defun.iter().any(|(_, _, lisp_name)| lisp_name == name))
+        .map(|(ident, _, _, _)| ident.clone())
+        .collect();
+    for ident in &shared {
+        let shares_const = all_defun.iter().any(|(_, name, _)| name.eq_ignore_ascii_case(ident));
+        assert!(shares_const, "defvar {ident} must have the same name as its function");
+    }
+    let is_shared = |ident: &String| shared.contains(ident);
+
     let out_dir = std::env::var("OUT_DIR").unwrap();
     // println!("cargo:warning={out_dir}/sym.rs");
     let dest_path = Path::new(&out_dir).join("sym.rs");
     let mut f = File::create(dest_path).unwrap();
 
-    let symbol_len = all_defsym.len() + all_defun.len() + all_defvar.len() + 2;
+    let symbol_len = all_defsym.len() + all_defun.len() + all_defvar.len() - shared.len() + 2;
     writeln!(
         f,
         "
@@ -170,7 +183,7 @@ pub(in crate::core) static BUILTIN_SYMBOLS: [SymbolCell; {symbol_len}] = [
         writeln!(f, "    SymbolCell::new_static(\"{sym_name}\"),").unwrap();
     }
 
-    for (_, name, _, _) in &all_defvar {
+    for (_, name, _, _) in all_defvar.iter().filter(|x| !is_shared(&x.0)) {
         #[rustfmt::skip]
         writeln!(f, "    SymbolCell::new_static_special(\"{name}\"),").unwrap();
     }
@@ -188,7 +201,7 @@ pub(in crate::core) static BUILTIN_SYMBOLS: [SymbolCell; {symbol_len}] = [
     let all_elements = special
         .iter()
         .chain(all_defsym.iter().map(|x| &x.0))
-        .chain(all_defvar.iter().map(|x| &x.0))
+        .chain(all_defvar.iter().map(|x| &x.0).filter(|x| !is_shared(x)))
         .chain(all_defun.iter().map(|x| &x.1))
         .enumerate();
     for (idx, element) in all_elements {
@@ -215,6 +228,14 @@ pub(crate) fn init_symbols() {{
         unsafe {{ sym.set_func((*func).into()).unwrap(); }}
     }}
 }}
+
+/// Every builtin subroutine along with the symbol it is defined on.
+pub(crate) fn subrs() -> impl Iterator<Item = (Symbol<'static>, &'static crate::core::object::SubrFn)> {{
+    BUILTIN_SYMBOLS[{defun_start}..]
+        .iter()
+        .map(|sym| unsafe {{ Symbol::from_ptr(sym as *const _) }})
+        .zip(SUBR_DEFS.iter().copied())
+}}
 "
     )
     .unwrap();
@@ -270,6 +291,9 @@ use rune_core::macros::list;
             value.insert_str(len - 1, "; cx");
         }
         writeln!(f, "env.vars.insert(sym::{ident}, cx.add({value}));").unwrap();
+        if shared.contains(&ident) {
+            writeln!(f, "sym::{ident}.make_special();").unwrap();
+        }
         if DefvarType::Bool == ty {
             bool_vars.push(ident);
         }
@@ -282,6 +306,8 @@ use rune_core::macros::list;
     }
     writeln!(f, "; cx];").unwrap();
     writeln!(f, "env.vars.insert(sym::BYTE_BOOLEAN_VARS, bool_vars);").unwrap();
+    writeln!(f, "crate::eval::init_errors(env, cx);").unwrap();
+    writeln!(f, "crate::buffer::init_variables(env, cx);").unwrap();
 
     writeln!(f, "}}").unwrap();
 }
@@ -20,7 +20,8 @@ pub(crate) fn expand(function: Function, spec: Spec) -> TokenStream {
     let lisp_name = spec.name.unwrap_or_else(|| map_function_name(&subr_name));
     let (required, optional, rest) = parse_call_signature(&function.args, spec.required);
 
-    let arg_conversion = get_arg_conversion(&function.args);
+    let arg_conversion = get_arg_conversion(&function.args, &lisp_name);
+    let params = function.args.iter().filter(|x| x.is_positional_arg()).count() as u16;
 
     let create_args = if !function.args.iter().any(|x| matches!(x, ArgType::Env(MUT))) {
         // If mut Env is not needed, then we can just pass a slice from the
@@ -95,6 +96,7 @@ pub(crate) fn expand(function: Function, spec: Spec) -> TokenStream {
         pub(crate) const #struct_name: crate::core::object::SubrFn = crate::core::object::SubrFn {
             name: #lisp_name,
             subr: #func_name,
+            params: #params,
             args: crate::core::object::FnArgs {
                 required: #required,
                 optional: #optional,
@@ -107,8 +109,12 @@ pub(crate) fn expand(function: Function, spec: Spec) -> TokenStream {
     }
 }
 
-fn get_arg_conversion(args: &[ArgType]) -> Vec<TokenStream> {
+fn get_arg_conversion(args: &[ArgType], lisp_name: &str) -> Vec<TokenStream> {
     let is_mut = args.iter().any(|ty| matches!(ty, ArgType::Context(MUT)));
+    // Name the function in type errors from converting its arguments
+    let try_arg = quote! {
+        .map_err(|e| crate::core::error::TypeError::add_func_name(e, #lisp_name))?
+    };
     args.iter()
         .enumerate()
         .map(|(idx, arg_type)| match arg_type {
@@ -117,14 +123,14 @@ fn get_arg_conversion(args: &[ArgType]) -> Vec<TokenStream> {
             // Rt<Gc<..>>
             ArgType::Rt(gc) => match gc {
                 Gc::Obj => quote! {&args[#idx]},
-                Gc::Other => quote! {args[#idx].try_as()?},
+                Gc::Other => quote! {args[#idx].try_as()#try_arg},
             },
             // Gc<..>
             ArgType::Gc(gc) => {
                 let bind = quote! {args[#idx].bind(cx)};
                 match gc {
                     Gc::Obj => bind,
-                    Gc::Other => quote! { std::convert::TryFrom::try_from(#bind)? },
+                    Gc::Other => quote! { std::convert::TryFrom::try_from(#bind)#try_arg },
                 }
             }
             // &[Gc<..>]
@@ -133,7 +139,7 @@ fn get_arg_conversion(args: &[ArgType]) -> Vec<TokenStream> {
                     quote! {crate::core::gc::Rt::bind_slice(&args[(#idx).min(args.len())..], cx)};
                 match gc {
                     Gc::Obj => bind,
-                    Gc::Other => quote! {crate::core::object::try_from_slice(#bind)?},
+                    Gc::Other => quote! {crate::core::object::try_from_slice(#bind)#try_arg},
                 }
             }
             // &[Rt<Gc<..>>]
@@ -150,7 +156,7 @@ fn get_arg_conversion(args: &[ArgType]) -> Vec<TokenStream> {
             ArgType::OptionRt => {
                 quote! {
                     match args.get(#idx) {
-                        Some(x) => crate::core::gc::Rt::try_as_option(x)?,
+                        Some(x) => crate::core::gc::Rt::try_as_option(x)#try_arg,
                         None => None,
                     }
                 }
@@ -160,17 +166,17 @@ fn get_arg_conversion(args: &[ArgType]) -> Vec<TokenStream> {
                 let bind = quote! {x.bind(cx)};
                 quote! {
                     match args.get(#idx) {
-                        Some(x) => crate::core::object::Gc::try_from_option(#bind)?,
+                        Some(x) => crate::core::object::Gc::try_from_option(#bind)#try_arg,
                         None => None,
                     }
                 }
             }
             ArgType::Other => {
                 if is_mut {
-                    quote! { std::convert::TryFrom::try_from(&args[#idx])? }
+                    quote! { std::convert::TryFrom::try_from(&args[#idx])#try_arg }
                 } else {
                     let bind = quote! {args[#idx].bind(cx)};
-                    quote! { std::convert::TryFrom::try_from(#bind)? }
+                    quote! { std::convert::TryFrom::try_from(#bind)#try_arg }
                 }
             }
         })
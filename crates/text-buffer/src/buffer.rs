@@ -32,6 +32,8 @@ pub struct Buffer {
     total: Metric,
     metrics: BufferMetrics,
     new_gap_size: usize,
+    /// Incremented every time the text is changed.
+    modified_tick: usize,
 }
 
 impl Debug for Buffer {
@@ -49,6 +51,7 @@ impl Debug for Buffer {
             .field("metrics", &self.metrics)
             .field("total_chars", &self.total.chars)
             .field("new_gap_size", &self.new_gap_size)
+            .field("modified_tick", &self.modified_tick)
             .finish()
     }
 }
@@ -162,6 +165,7 @@ impl From<String> for Buffer {
             total,
             metrics,
             new_gap_size: calc_start_gap_size(len),
+            modified_tick: 0,
         }
     }
 }
@@ -189,6 +193,7 @@ impl From<&str> for Buffer {
             total: metrics.len(),
             new_gap_size,
             metrics,
+            modified_tick: 0,
         }
     }
 }
@@ -292,6 +297,7 @@ impl Buffer {
         if slice.is_empty() {
             return;
         }
+        self.modified_tick += 1;
         self.metrics.insert(self.to_abs_pos(self.cursor), MetricBuilder::new(slice));
         if self.gap_len() < slice.len() {
             self.grow(slice);
@@ -339,6 +345,7 @@ impl Buffer {
             let end = GapMetric { bytes: end_bytes, chars: end_chars };
             self.metrics.delete(self.to_abs_pos(beg), self.to_abs_pos(end));
             self.delete_byte_range(beg, end);
+            self.modified_tick += 1;
         }
     }
 
@@ -599,6 +606,13 @@ impl Buffer {
         GapMetric { bytes, chars }
     }
 
+    /// The number of changes made to the text. This can be compared with an
+    /// earlier value to tell if the text was changed in between.
+    #[inline]
+    pub const fn modified_tick(&self) -> usize {
+        self.modified_tick
+    }
+
     #[inline]
     pub fn len_bytes(&self) -> usize {
         debug_assert_eq!(self.total.bytes + self.gap_len(), self.data.len());
@@ -810,6 +824,20 @@ mod test {
         assert_eq!(buffer, "heworld");
     }
 
+    #[test]
+    fn test_modified_tick() {
+        let mut buffer = Buffer::from("hello");
+        let tick = buffer.modified_tick();
+        buffer.insert("");
+        buffer.delete_range(2, 2);
+        buffer.set_cursor(3);
+        assert_eq!(buffer.modified_tick(), tick);
+        buffer.insert_char('x');
+        buffer.delete_backwards(1);
+        assert_eq!(buffer.modified_tick(), tick + 2);
+        assert_eq!(buffer, "hello");
+    }
+
     #[test]
     fn delete_forwards() {
         let world = "world";
@@ -0,0 +1,117 @@
+;;; ert.el --- A subset of the Emacs Lisp Regression Testing library  -*- lexical-binding: t; -*-
+
+;;; Commentary:
+
+;; This implements enough of ERT to run simple test suites: tests are defined
+;; with `ert-deftest', checked with `should', `should-not' and `should-error',
+;; and run with `ert-run-tests-batch'.  Test results are reported with
+;; `message', which goes to stderr in batch mode.
+
+;;; Code:
+
+(defvar ert--tests nil
+  "The names of all defined tests, most recently defined first.")
+
+(define-error 'ert-test-failed "Test failed")
+
+(defmacro ert-deftest (name _args &rest body)
+  "Define NAME as a test that runs BODY.
+A docstring and keyword arguments like `:tags' at the start of BODY
+are accepted but ignored."
+  (declare (indent 2) (doc-string 3))
+  (when (stringp (car body))
+    (setq body (cdr body)))
+  (while (keywordp (car body))
+    (setq body (cdr (cdr body))))
+  `(progn
+     (put ',name 'ert--test (lambda () ,@body))
+     (setq ert--tests (cons ',name (delq ',name ert--tests)))
+     ',name))
+
+(defun ert-fail (data)
+  "Signal that the current test failed with DATA."
+  (signal 'ert-test-failed (list data)))
+
+(defmacro should (form)
+  "Fail the current test if FORM evaluates to nil."
+  (let ((value (make-symbol "value")))
+    `(let ((,value ,form))
+       (or ,value (ert-fail (list '(should ,form) :value ,value))))))
+
+(defmacro should-not (form)
+  "Fail the current test if FORM evaluates to non-nil."
+  (let ((value (make-symbol "value")))
+    `(let ((,value ,form))
+       (if ,value (ert-fail (list '(should-not ,form) :value ,value)) nil))))
+
+(defun ert--error-type-p (err type)
+  "Return non-nil if the error ERR matches TYPE.
+TYPE is an error symbol or a list of them."
+  (let ((types (if (listp type) type (list type)))
+        (conditions (or (get (car err) 'error-conditions) (list (car err)))))
+    (or (memq 'error types)
+        (let ((found nil))
+          (while (and conditions (not found))
+            (setq found (memq (car conditions) types))
+            (setq conditions (cdr conditions)))
+          found))))
+
+(defmacro should-error (form &rest keys)
+  "Fail the current test if FORM does not signal an error.
+If KEYS has a `:type', the error must also match that type.
+Return the error that was signaled."
+  (let ((err (make-symbol "err"))
+        (type (plist-get keys :type)))
+    `(let ((,err (condition-case err (progn ,form nil) (error err))))
+       (cond ((null ,err)
+              (ert-fail (list '(should-error ,form) :value nil)))
+             ((and ,type (not (ert--error-type-p ,err ,type)))
+              (ert-fail (list '(should-error ,form) :condition ,err)))
+             (t ,err)))))
+
+(defun ert--select-tests (selector)
+  "Return the names of the tests matching SELECTOR in definition order.
+SELECTOR is nil or t for all tests, a regexp matched against test
+names, or the name of a single test."
+  (let ((tests nil))
+    (dolist (name ert--tests)
+      (when (cond ((memq selector '(nil t)) t)
+                  ((stringp selector) (string-match selector (symbol-name name)))
+                  (t (eq selector name)))
+        (setq tests (cons name tests))))
+    tests))
+
+(defun ert-run-test (name)
+  "Run the test NAME.
+Return nil if it passed, or the error it signaled."
+  (condition-case err
+      (progn (funcall (get name 'ert--test)) nil)
+    (error err)))
+
+(defun ert-run-tests-batch (&optional selector)
+  "Run the tests matching SELECTOR and report the results.
+See `ert--select-tests' for the meaning of SELECTOR.  Return the
+names of the tests that failed."
+  (let* ((tests (ert--select-tests selector))
+         (total (length tests))
+         (count 0)
+         (failed nil))
+    (message "Running %s tests" total)
+    (dolist (name tests)
+      (let ((result (ert-run-test name)))
+        (setq count (1+ count))
+        (if (null result)
+            (message "   passed  %s/%s  %s" count total name)
+          (setq failed (cons name failed))
+          (message "   FAILED  %s/%s  %s: %s" count total name result))))
+    (message "Ran %s tests, %s passed, %s failed"
+             total (- total (length failed)) (length failed))
+    (nreverse failed)))
+
+(defun ert-run-tests-batch-and-exit (&optional selector)
+  "Like `ert-run-tests-batch', but exit with a non-zero status on failure."
+  (kill-emacs (if (ert-run-tests-batch selector) 1 0)))
+
+(provide 'ert)
+
+;;; ert.el ends here
@@ -173,6 +173,9 @@
 ;; RUNE-BOOTSTRAP
 (load "stubs")
 
+;; RUNE-BOOTSTRAP
+(load "rune")
+
 ;; ;; We don't want to store loaddefs.el in the repository because it is
 ;; ;; a generated file; but it is required in order to compile the lisp files.
 ;; ;; When bootstrapping, we cannot generate loaddefs.el until an
@@ -0,0 +1,153 @@
+;;; rune-tests.el --- Lisp level tests for rune  -*- lexical-binding: t; -*-
+
+;;; Commentary:
+
+;; These tests are run by `cargo test' after bootstrapping.  They can also be
+;; run with `(ert-run-tests-batch "^rune-")' after loading this file.
+
+;;; Code:
+
+(require 'ert)
+
+(ert-deftest rune-test-should ()
+  (should (= (+ 1 2) 3))
+  (should-not (eq 'a 'b))
+  (should (should-error (car 1)))
+  (should (equal (should-error (signal 'wrong-type-argument '(x))
+                               :type 'wrong-type-argument)
+                 '(wrong-type-argument x))))
+
+(ert-deftest rune-test-closures ()
+  (let ((counter (let ((n 0)) (lambda () (setq n (1+ n))))))
+    (funcall counter)
+    (should (= (funcall counter) 2))))
+
+(ert-deftest rune-test-strings ()
+  "String primitives."
+  (should (equal (concat "foo" "bar") "foobar"))
+  (should (equal (substring "hello" 1 3) "el"))
+  (should (equal (format "%s-%s" 1 'a) "1-a")))
+
+(ert-deftest rune-test-non-local-exit ()
+  (should (eq (catch 'done (throw 'done 'thrown) 'not-thrown) 'thrown))
+  (let ((x 1))
+    (ignore-errors (unwind-protect (error "boom") (setq x 2)))
+    (should (= x 2))))
+
+(ert-deftest rune-test-permissions ()
+  "Capabilities granted with `with-permissions'."
+  (should (equal (with-permissions '(:network t) (+ 1 2)) 3))
+  (should (equal (should-error (with-permissions nil
+                                 (make-process :name "x" :command '("true"))))
+                 '(permission-denied subprocess nil))))
+
+;; `setf' is the macro from gv.el, so places defined with `gv-define-setter'
+;; and friends apply to it like in Emacs.
+
+(ert-deftest rune-test-setf ()
+  (let ((x (list 1 2 3)))
+    (setf (car x) 4 (cdr (cdr x)) nil)
+    (should (equal x '(4 2))))
+  (let ((x (list 1 2 3)))
+    (should (= (setf (nth 1 x) 5) 5))
+    (should (equal x '(1 5 3))))
+  (let ((x (vector 1 2)) (y (list 1 2)))
+    (setf (aref x 0) 3 (elt x 1) 4 (elt y 1) 4)
+    (should (equal (list x y) '([3 4] (1 4)))))
+  (let ((h (make-hash-table)))
+    (setf (gethash 'a h) 1)
+    (should (= (gethash 'a h) 1)))
+  (let ((x nil))
+    (setf (alist-get 'a x) 1 (alist-get 'b x) 2)
+    (should (equal x '((b . 2) (a . 1))))
+    (setf (alist-get 'a x nil t) nil)
+    (should (equal x '((b . 2)))))
+  (let ((x nil))
+    (setf (plist-get x 'a) 1)
+    (should (equal x '(a 1))))
+  (setf (symbol-value 'rune-test-setf-var) 3)
+  (should (= (symbol-value 'rune-test-setf-var) 3)))
+
+(defun rune-test-second (x) (car (cdr x)))
+(gv-define-setter rune-test-second (val x) `(setcar (cdr ,x) ,val))
+(defmacro rune-test-third (x) `(car (cddr ,x)))
+
+(ert-deftest rune-test-setf-places ()
+  (let ((x (list 1 2 3)))
+    (setf (rune-test-second x) 4 (rune-test-third x) 5)
+    (should (equal x '(1 4 5))))
+  (should (equal (macroexpand '(setf (car x) 1)) '(setcar x 1)))
+  (should-error (eval '(setf (rune-test-unknown-place x) 1) t)))
+
+;; The tables below are from the Emacs Lisp manual and Emacs' own
+;; floatfns-tests.el, to keep numeric results identical to Emacs.
+
+(ert-deftest rune-test-division ()
+  "Integer division truncates, `%' follows the dividend and `mod' the divisor."
+  (dolist (case '(((/ 6 2) 3) ((/ 5 2) 2) ((/ 5.0 2) 2.5) ((/ 5 2.0) 2.5)
+                  ((/ 4.0) 0.25) ((/ 4) 0) ((/ 25 3 2) 4) ((/ -17 6) -2)
+                  ((/ 5 2 2.0) 1.25)
+                  ((% 9 4) 1) ((% -9 4) -1) ((% 9 -4) 1) ((% -9 -4) -1)
+                  ((mod 9 4) 1) ((mod -9 4) 3) ((mod 9 -4) -3) ((mod -9 -4) -1)
+                  ((mod 5.5 2.5) 0.5) ((mod -5.5 2.5) 2.0) ((mod 0 3) 0)))
+    (should (equal (list (car case) (eval (car case))) case)))
+  (should (= 0 (mod (1+ most-positive-fixnum) 2.0)))
+  (should (equal (should-error (/ 1 0)) '(arith-error)))
+  (should (equal (should-error (% 1 0)) '(arith-error)))
+  (should (equal (should-error (mod 1 0)) '(arith-error)))
+  (should (should-error (% 1.0 2))))
+
+(ert-deftest rune-test-rounding ()
+  "Rounding functions, with ties rounded to even."
+  (dolist (case '(((floor 1.2) 1) ((floor 1.7) 1) ((floor -1.2) -2) ((floor -1.7) -2)
+                  ((ceiling 1.2) 2) ((ceiling 1.7) 2) ((ceiling -1.2) -1) ((ceiling -1.7) -1)
+                  ((truncate 1.2) 1) ((truncate 1.7) 1) ((truncate -1.2) -1) ((truncate -1.7) -1)
+                  ((round 1.2) 1) ((round 1.7) 2) ((round -1.2) -1) ((round -1.7) -2)
+                  ((round 2.5) 2) ((round -2.5) -2) ((round 3.5) 4)
+                  ((floor 5.99 3) 1) ((floor -7 2) -4) ((ceiling -7 2) -3)
+                  ((truncate -7 2) -3) ((round -7 2) -4) ((round 5 2) 2) ((round 7 2) 4)
+                  ((floor 0.3 0.1) 2) ((round 7 2.0) 4)
+                  ((ffloor 2.5) 2.0) ((fceiling 2.5) 3.0) ((ftruncate -1.7) -1.0)
+                  ((fround 2.5) 2.0) ((fround 3.5) 4.0) ((fround -2.5) -2.0)))
+    (should (equal (list (car case) (eval (car case))) case)))
+  (dolist (f '(ffloor fceiling fround ftruncate))
+    (should (should-error (funcall f 0))))
+  (should (= (floor 54043195528445955 3) (floor 54043195528445955 3.0))))
+
+(ert-deftest rune-test-divide-extreme-sign ()
+  (dolist (f '(ceiling floor round truncate))
+    (should (= (funcall f most-negative-fixnum -1.0) (- most-negative-fixnum)))))
+
+(ert-deftest rune-test-special-round ()
+  "Infinities, NaNs and zero divisors."
+  (dolist (f '(ceiling floor round truncate))
+    (let ((ns '(-1e+INF 1e+INF -1 -0.0 0.0 0 1 -1e+NaN 1e+NaN)))
+      (dolist (n ns)
+        (if (not (<= (abs n) 1))
+            (should-error (funcall f n))
+          (should (= n (funcall f n)))
+          (dolist (d '(-1e+INF 1e+INF))
+            (should (eq 0 (funcall f n d)))))
+        (dolist (d ns)
+          (when (or (zerop d) (= (abs n) 1e+INF) (not (= n n)) (not (= d d)))
+            (should-error (funcall f n d))))))))
+
+(define-error 'rune-test-error "Rune test error" 'arith-error)
+(define-error 'rune-test-child-error "Rune test child error" '(rune-test-error file-error))
+
+(ert-deftest rune-test-define-error ()
+  (should (equal (get 'rune-test-child-error 'error-conditions)
+                 '(rune-test-child-error rune-test-error arith-error error file-error)))
+  (should (equal (condition-case err (signal 'rune-test-child-error '(1))
+                   (file-error (cons 'file err)))
+                 '(file rune-test-child-error 1)))
+  (should (eq (condition-case nil (signal 'rune-test-error nil)
+                (file-error 'file)
+                (arith-error 'arith))
+              'arith))
+  (should (eq (condition-case nil (/ 1 0) ((debug arith-error) 'arith)) 'arith))
+  (should-error (signal 'rune-test-error nil) :type 'arith-error)
+  (should (equal (error-message-string '(rune-test-error 1 "two"))
+                 "Rune test error: 1, \"two\"")))
+
+;;; rune-tests.el ends here
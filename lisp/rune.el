@@ -0,0 +1,16 @@
+;;; rune.el --- Lisp definitions for rune's own features  -*- lexical-binding: t; -*-
+
+;;; Commentary:
+
+;; Macros and functions that go with primitives rune has and Emacs
+;; doesn't.
+
+;;; Code:
+
+(defmacro with-permissions (permissions &rest body)
+  "Evaluate BODY with only the capabilities in PERMISSIONS.
+See `call-with-permissions' for the format of PERMISSIONS."
+  (declare (indent 1))
+  `(call-with-permissions ,permissions (lambda () ,@body)))
+
+;;; rune.el ends here
@@ -36,3 +36,19 @@
        (let ((val ,@body))
          (message "RETURN: %s: %s" ,type val)
          val)))
+
+(defmacro cl-assert (form &optional show-args string &rest args)
+  "stub of macro for bootstrapping, until cl-macs defines it
+Verify that FORM returns non-nil; signal an error if not.  SHOW-ARGS
+means to include the arguments of FORM in the error.  STRING and ARGS
+are passed to `error' instead if STRING is non-nil."
+  (let ((sargs (and show-args
+                    (delq nil (mapcar (lambda (x)
+                                        (unless (macroexp-const-p x) x))
+                                      (cdr-safe form))))))
+    `(progn
+       (or ,form
+           (cl--assertion-failed
+            ',form ,@(if (or string sargs args)
+                         `(,string (list ,@sargs) (list ,@args)))))
+       nil)))
@@ -1,10 +1,13 @@
 //! builtin lisp data structures.
 use crate::core::cons::Cons;
-use crate::core::gc::Context;
+use crate::core::env::{sym, Env};
+use crate::core::gc::{Context, Rt, Rto};
 use crate::core::object::{
-    ByteFn, ByteString, FnArgs, Gc, IntoObject, LispVec, Object, RecordBuilder, Symbol, NIL,
+    ByteFn, ByteString, FnArgs, Gc, IntoObject, LispString, LispVec, Object, ObjectType,
+    RecordBuilder, Symbol, NIL,
 };
-use anyhow::{ensure, Result};
+use crate::permissions::{check_file, Capability};
+use anyhow::{bail, ensure, Result};
 use rune_macros::defun;
 
 #[defun]
@@ -72,6 +75,16 @@ fn vector<'ob>(objects: &[Object<'ob>]) -> Vec<Object<'ob>> {
     objects.into()
 }
 
+#[defun]
+fn make_bool_vector(length: usize, init: Object) -> Vec<bool> {
+    vec![!init.is_nil(); length]
+}
+
+#[defun]
+fn bool_vector(objects: &[Object]) -> Vec<bool> {
+    objects.iter().map(|x| !x.is_nil()).collect()
+}
+
 #[defun]
 fn record<'ob>(type_: Object<'ob>, slots: &[Object<'ob>], cx: &'ob Context) -> RecordBuilder<'ob> {
     let mut record = cx.vec_with_capacity(1 + slots.len());
@@ -96,6 +109,35 @@ fn garbage_collect(cx: &mut Context) -> bool {
     true
 }
 
+/// Collect garbage and write the graph of reachable objects to FILE, with
+/// each object's type and size and the roots and objects that refer to it.
+/// FORMAT is `dot' for a Graphviz graph or `json'. It defaults to `json' if
+/// FILE ends in ".json" and `dot' otherwise. Return the number of objects.
+#[defun]
+fn dump_heap_snapshot(
+    file: &Rto<Gc<&LispString>>,
+    format: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let file: &str = file.untag(cx);
+    let file = file.to_owned();
+    let json = match format.map(|x| x.bind(cx).untag()) {
+        None | Some(ObjectType::NIL) => file.ends_with(".json"),
+        Some(ObjectType::Symbol(sym::JSON)) => true,
+        Some(ObjectType::Symbol(sym::DOT)) => false,
+        Some(other) => bail!("Invalid heap snapshot format: {other}"),
+    };
+    check_file(Capability::Write, &file, env, cx)?;
+    let snapshot = cx.heap_snapshot();
+    let contents = if json { snapshot.to_json().to_string() } else { snapshot.to_dot() };
+    std::fs::write(&file, contents)?;
+    Ok(snapshot.objects.len())
+}
+
+defsym!(JSON);
+defsym!(DOT);
+
 #[cfg(test)]
 mod test {
     use rune_core::macros::root;
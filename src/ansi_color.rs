@@ -0,0 +1,365 @@
+//! Translating ANSI escape sequences.
+//!
+//! Process output often contains SGR escape sequences that select colors and
+//! other attributes. `ansi-color-filter-apply' removes them along with any
+//! other escape sequences, and `ansi-color-apply-segments' splits the text
+//! into runs with the face that the sequences select. Strings don't carry
+//! text properties yet, so `ansi-color-apply' returns the same text as
+//! `ansi-color-filter-apply' but keeps track of the attributes.
+//!
+//! Output arrives in arbitrary chunks, so the attributes in effect and any
+//! incomplete escape sequence at the end of a string are kept in
+//! `ansi-color-context' for the next call, like Emacs.
+use crate::core::{
+    cons::Cons,
+    env::{intern, sym, Env},
+    gc::{Context, Rt},
+    object::{List, Object, ObjectType, NIL},
+};
+use crate::fns::slice_into_list;
+use anyhow::Result;
+use rune_core::macros::list;
+use rune_macros::defun;
+
+const NAMES: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+const COLORS: [&str; 8] =
+    ["black", "red3", "green3", "yellow3", "blue2", "magenta3", "cyan3", "gray90"];
+const BRIGHT_COLORS: [&str; 8] =
+    ["gray30", "red2", "green2", "yellow2", "blue1", "magenta2", "cyan2", "white"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Color {
+    Named(u8),
+    Bright(u8),
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Return the name or `#rrggbb` value of the color.
+    fn value(self) -> String {
+        let (r, g, b) = match self {
+            Color::Named(i) | Color::Indexed(i @ 0..=7) => return COLORS[i as usize].to_owned(),
+            Color::Bright(i) => return BRIGHT_COLORS[i as usize].to_owned(),
+            Color::Indexed(i @ 8..=15) => return BRIGHT_COLORS[i as usize - 8].to_owned(),
+            // a 6x6x6 color cube followed by 24 shades of gray
+            Color::Indexed(i @ 16..=231) => {
+                let level = |x: u8| if x == 0 { 0 } else { 55 + x * 40 };
+                let i = i - 16;
+                (level(i / 36), level(i / 6 % 6), level(i % 6))
+            }
+            Color::Indexed(i) => {
+                let gray = 8 + (i - 232) * 10;
+                (gray, gray, gray)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        };
+        format!("#{r:02x}{g:02x}{b:02x}")
+    }
+}
+
+/// The attributes selected by SGR sequences.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Sgr {
+    bold: bool,
+    faint: bool,
+    italic: bool,
+    underline: bool,
+    blink: bool,
+    inverse: bool,
+    foreground: Option<Color>,
+    background: Option<Color>,
+}
+
+impl Sgr {
+    /// Update the attributes with the parameters of an SGR sequence.
+    fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Self::default();
+        }
+        let mut params = params.iter().copied();
+        while let Some(code) = params.next() {
+            match code {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                2 => self.faint = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                5 | 6 => self.blink = true,
+                7 => self.inverse = true,
+                21 | 22 => (self.bold, self.faint) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                25 => self.blink = false,
+                27 => self.inverse = false,
+                30..=37 => self.foreground = Some(Color::Named((code - 30) as u8)),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(Color::Named((code - 40) as u8)),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(Color::Bright((code - 90) as u8)),
+                100..=107 => self.background = Some(Color::Bright((code - 100) as u8)),
+                38 | 48 => {
+                    let mut next = || params.next().map(|x| x.min(255) as u8);
+                    let color = match next() {
+                        Some(5) => next().map(Color::Indexed),
+                        Some(2) => match (next(), next(), next()) {
+                            (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                            _ => None,
+                        },
+                        _ => None,
+                    };
+                    if code == 38 {
+                        self.foreground = color.or(self.foreground);
+                    } else {
+                        self.background = color.or(self.background);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Return the parameters of an SGR sequence that selects these
+    /// attributes.
+    fn codes(&self) -> Vec<u16> {
+        let flags = [
+            self.bold,
+            self.faint,
+            self.italic,
+            self.underline,
+            self.blink,
+            false,
+            self.inverse,
+        ];
+        let mut codes: Vec<u16> = (1..).zip(flags).filter(|x| x.1).map(|x| x.0).collect();
+        for (color, base) in [(self.foreground, 30), (self.background, 40)] {
+            match color {
+                None => {}
+                Some(Color::Named(i)) => codes.push(base + i as u16),
+                Some(Color::Bright(i)) => codes.push(base + 60 + i as u16),
+                Some(Color::Indexed(i)) => codes.extend([base + 8, 5, i as u16]),
+                Some(Color::Rgb(r, g, b)) => {
+                    codes.extend([base + 8, 2, r as u16, g as u16, b as u16]);
+                }
+            }
+        }
+        codes
+    }
+
+    /// Return the list of faces for these attributes.
+    fn face<'ob>(&self, cx: &'ob Context) -> Object<'ob> {
+        let face = |name: &str| -> Object<'ob> { intern(&format!("ansi-color-{name}"), cx).into() };
+        let mut faces = Vec::new();
+        let flags = [
+            (self.bold, "bold"),
+            (self.faint, "faint"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.blink, "slow-blink"),
+            (self.inverse, "inverse"),
+        ];
+        faces.extend(flags.iter().filter(|x| x.0).map(|x| face(x.1)));
+        match self.foreground {
+            None => {}
+            Some(Color::Named(i)) => faces.push(face(NAMES[i as usize])),
+            Some(Color::Bright(i)) => faces.push(face(&format!("bright-{}", NAMES[i as usize]))),
+            Some(color) => faces.push(list![sym::KW_FOREGROUND, cx.add(color.value()); cx]),
+        }
+        if let Some(color) = self.background {
+            faces.push(list![sym::KW_BACKGROUND, cx.add(color.value()); cx]);
+        }
+        slice_into_list(&faces, None, cx)
+    }
+}
+
+/// Split `input` into runs of text with the attributes that apply to them,
+/// starting with the attributes in `state`. Escape sequences are removed. An
+/// incomplete escape sequence at the end is returned separately.
+fn parse(input: &str, state: &mut Sgr) -> (Vec<(String, Sgr)>, String) {
+    let mut segments: Vec<(String, Sgr)> = Vec::new();
+    let mut text = String::new();
+    let mut rest = input;
+    while let Some(start) = rest.find('\x1b') {
+        text.push_str(&rest[..start]);
+        let escape = &rest[start..];
+        let len = match escape.as_bytes().get(1) {
+            None => return finish(segments, text, state, escape),
+            // CSI: parameter bytes and intermediate bytes, then a final byte
+            Some(b'[') => match escape.bytes().skip(2).position(|x| !(0x20..0x40).contains(&x)) {
+                None => return finish(segments, text, state, escape),
+                Some(i) => {
+                    let end = i + 2;
+                    if escape.as_bytes()[end] == b'm' {
+                        let params = &escape[2..end];
+                        let params: Vec<u16> = match params {
+                            "" => Vec::new(),
+                            _ => params.split(';').map(|x| x.parse().unwrap_or(0)).collect(),
+                        };
+                        let mut next = state.clone();
+                        next.apply(&params);
+                        if next != *state && !text.is_empty() {
+                            segments.push((std::mem::take(&mut text), state.clone()));
+                        }
+                        *state = next;
+                    }
+                    end + 1
+                }
+            },
+            // OSC: ends with BEL or ST
+            Some(b']') => {
+                let bel = escape.find('\x07').map(|x| x + 1);
+                let st = escape.find("\x1b\\").map(|x| x + 2);
+                match bel.into_iter().chain(st).min() {
+                    Some(end) => end,
+                    None => return finish(segments, text, state, escape),
+                }
+            }
+            // any other escape is followed by a single character
+            Some(_) => 1 + escape[1..].chars().next().map_or(0, char::len_utf8),
+        };
+        rest = &escape[len..];
+    }
+    text.push_str(rest);
+    finish(segments, text, state, "")
+}
+
+fn finish(
+    mut segments: Vec<(String, Sgr)>,
+    text: String,
+    state: &Sgr,
+    fragment: &str,
+) -> (Vec<(String, Sgr)>, String) {
+    if !text.is_empty() {
+        segments.push((text, state.clone()));
+    }
+    (segments, fragment.to_owned())
+}
+
+/// Read the attributes and the incomplete escape sequence that were left by
+/// the previous string from `ansi-color-context'.
+fn load_context(env: &Rt<Env>, cx: &Context) -> (Sgr, String) {
+    let mut state = Sgr::default();
+    let mut fragment = String::new();
+    let Some(context) = env.vars.get(sym::ANSI_COLOR_CONTEXT) else {
+        return (state, fragment);
+    };
+    if let ObjectType::Cons(context) = context.bind(cx).untag() {
+        let codes: Vec<u16> = match List::try_from(context.car()) {
+            Ok(codes) => codes
+                .elements()
+                .filter_map(|x| match x.ok()?.untag() {
+                    ObjectType::Int(x) => u16::try_from(x).ok(),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        if !codes.is_empty() {
+            state.apply(&codes);
+        }
+        if let ObjectType::Cons(rest) = context.cdr().untag() {
+            if let ObjectType::String(x) = rest.car().untag() {
+                fragment = x.to_string();
+            }
+        }
+    }
+    (state, fragment)
+}
+
+/// Save `state` and `fragment` in `ansi-color-context' as (CODES FRAGMENT),
+/// or nil if there is nothing to carry over.
+fn save_context(state: &Sgr, fragment: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let codes: Vec<Object> = state.codes().into_iter().map(|x| cx.add(x as i64)).collect();
+    let context = if codes.is_empty() && fragment.is_empty() {
+        NIL
+    } else {
+        list![slice_into_list(&codes, None, cx), cx.add(fragment); cx]
+    };
+    env.set_var(sym::ANSI_COLOR_CONTEXT, context, cx)
+}
+
+fn apply_string(string: &str, env: &mut Rt<Env>, cx: &Context) -> Result<Vec<(String, Sgr)>> {
+    let (mut state, mut input) = load_context(env, cx);
+    input.push_str(string);
+    let (segments, fragment) = parse(&input, &mut state);
+    save_context(&state, &fragment, env, cx)?;
+    Ok(segments)
+}
+
+/// Return STRING with all escape sequences removed. An incomplete escape
+/// sequence at the end is saved in `ansi-color-context' and completed by the
+/// next call.
+#[defun]
+fn ansi_color_filter_apply(string: &str, env: &mut Rt<Env>, cx: &Context) -> Result<String> {
+    Ok(apply_string(string, env, cx)?.into_iter().map(|x| x.0).collect())
+}
+
+/// Translate the SGR escape sequences in STRING and return the text without
+/// them. The attributes in effect at the end are saved in
+/// `ansi-color-context' and apply to the start of the next string. Use
+/// `ansi-color-apply-segments' to get the faces.
+#[defun]
+fn ansi_color_apply(string: &str, env: &mut Rt<Env>, cx: &Context) -> Result<String> {
+    ansi_color_filter_apply(string, env, cx)
+}
+
+/// Translate the SGR escape sequences in STRING into a list of (TEXT . FACE)
+/// runs, where FACE is a list of `ansi-color-' faces and (:foreground COLOR)
+/// or (:background COLOR) specs. Uses `ansi-color-context' the same way as
+/// `ansi-color-apply'.
+#[defun]
+fn ansi_color_apply_segments<'ob>(
+    string: &str,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let segments: Vec<Object> = apply_string(string, env, cx)?
+        .into_iter()
+        .map(|(text, state)| Cons::new(cx.add(text), state.face(cx), cx).into())
+        .collect();
+    Ok(slice_into_list(&segments, None, cx))
+}
+
+defvar!(ANSI_COLOR_CONTEXT);
+defsym!(KW_FOREGROUND);
+defsym!(KW_BACKGROUND);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    fn texts(input: &str) -> (Vec<(String, Vec<u16>)>, String) {
+        let (segments, fragment) = parse(input, &mut Sgr::default());
+        (
+            segments.into_iter().map(|(text, state)| (text, state.codes())).collect(),
+            fragment,
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let (segments, fragment) = texts("a\x1b[1;31mb\x1b[22mc\x1b[0md\x1b[K\x1b]8;;x\x07e\x1b[3");
+        let expected = [("a", vec![]), ("b", vec![1, 31]), ("c", vec![31]), ("de", vec![])];
+        let expected: Vec<_> = expected.into_iter().map(|(x, y)| (x.to_owned(), y)).collect();
+        assert_eq!(segments, expected);
+        assert_eq!(fragment, "\x1b[3");
+        let (segments, _) = texts("\x1b[38;5;208;48;2;1;2;3mx");
+        assert_eq!(segments[0].1, [38, 5, 208, 48, 2, 1, 2, 3]);
+        assert_eq!(Color::Indexed(208).value(), "#ff8700");
+        assert_eq!(Color::Indexed(244).value(), "#808080");
+    }
+
+    #[test]
+    fn test_ansi_color_apply() {
+        assert_lisp("(ansi-color-filter-apply \"\x1b[1mbold\x1b[0m plain\")", "\"bold plain\"");
+        assert_lisp(
+            "(let ((ansi-color-context nil))
+               (list (ansi-color-apply \"a\x1b[32mb\x1b[\")
+                     (car ansi-color-context)
+                     (length (car (cdr ansi-color-context)))
+                     (ansi-color-apply-segments \"1mc\")))",
+            "(\"ab\" (32) 2 ((\"c\" ansi-color-bold ansi-color-green)))",
+        );
+    }
+}
@@ -1,16 +1,25 @@
 //! Arithmetic operators.
-use crate::core::object::{Gc, IntoObject, Number, NumberType, ObjectType};
+use crate::core::{
+    error::{Type, TypeError},
+    gc::Context,
+    object::{Gc, IntoObject, Number, NumberType, Object, ObjectType, MAX_FIXNUM, MIN_FIXNUM},
+};
+use crate::data::LispError;
+use anyhow::{bail, Result};
 use float_cmp::ApproxEq;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
 use rune_macros::defun;
-use std::cmp::PartialEq;
+use std::cmp::{Ordering, PartialEq};
 use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
 
 /// Similar to the object type [NumberType], but contains a float instead of a
 /// reference to a float. This makes it easier to construct and mutate.
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum NumberValue {
     Int(i64),
     Float(f64),
+    Big(BigInt),
 }
 
 impl Number<'_> {
@@ -18,6 +27,46 @@ impl Number<'_> {
         match self.untag() {
             NumberType::Int(x) => NumberValue::Int(x),
             NumberType::Float(x) => NumberValue::Float(**x),
+            NumberType::BigInt(x) => NumberValue::Big(x.get()),
+        }
+    }
+}
+
+impl NumberValue {
+    /// Convert a bignum to a fixnum if it is small enough to fit in one.
+    /// Arithmetic results should always be normalized, so that integers have
+    /// a single representation.
+    pub(crate) fn normalize(self) -> Self {
+        match self {
+            NumberValue::Big(x) => match x.to_i64() {
+                Some(x) if (MIN_FIXNUM..=MAX_FIXNUM).contains(&x) => NumberValue::Int(x),
+                _ => NumberValue::Big(x),
+            },
+            x => x,
+        }
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        match self {
+            NumberValue::Int(x) => *x == 0,
+            NumberValue::Float(x) => *x == 0.0,
+            NumberValue::Big(x) => x.is_zero(),
+        }
+    }
+
+    fn is_negative(&self) -> bool {
+        match self {
+            NumberValue::Int(x) => *x < 0,
+            NumberValue::Float(x) => *x < 0.0,
+            NumberValue::Big(x) => x.is_negative(),
+        }
+    }
+
+    pub(crate) fn to_f64(&self) -> f64 {
+        match self {
+            NumberValue::Int(x) => *x as f64,
+            NumberValue::Float(x) => *x,
+            NumberValue::Big(x) => x.to_f64().unwrap_or(f64::NAN),
         }
     }
 }
@@ -26,25 +75,34 @@ impl IntoObject for NumberValue {
     type Out<'ob> = ObjectType<'ob>;
 
     fn into_obj<const C: bool>(self, block: &crate::core::gc::Block<C>) -> Gc<Self::Out<'_>> {
-        match self {
-            NumberValue::Int(x) => x.into(),
+        match self.normalize() {
+            NumberValue::Int(x) if (MIN_FIXNUM..=MAX_FIXNUM).contains(&x) => x.into(),
+            NumberValue::Int(x) => block.add(BigInt::from(x)),
             NumberValue::Float(x) => block.add(x),
+            NumberValue::Big(x) => block.add(x),
         }
     }
 }
 
+/// Apply an arithmetic operation. Fixnum operations that overflow are
+/// promoted to bignums, and any float operand makes the result a float.
 fn arith(
     cur: NumberValue,
     next: NumberValue,
-    int_fn: fn(i64, i64) -> i64,
+    int_fn: fn(i64, i64) -> Option<i64>,
+    big_fn: fn(BigInt, BigInt) -> BigInt,
     float_fn: fn(f64, f64) -> f64,
 ) -> NumberValue {
     use NumberValue as N;
     match (cur, next) {
-        (N::Int(l), N::Int(r)) => N::Int(int_fn(l, r)),
-        (N::Int(l), N::Float(r)) => N::Float(float_fn(l as f64, r)),
-        (N::Float(l), N::Int(r)) => N::Float(float_fn(l, r as f64)),
-        (N::Float(l), N::Float(r)) => N::Float(float_fn(l, r)),
+        (N::Int(l), N::Int(r)) => match int_fn(l, r) {
+            Some(x) => N::Int(x),
+            None => N::Big(big_fn(l.into(), r.into())).normalize(),
+        },
+        (l @ N::Float(_), r) | (l, r @ N::Float(_)) => N::Float(float_fn(l.to_f64(), r.to_f64())),
+        (N::Big(l), N::Big(r)) => N::Big(big_fn(l, r)).normalize(),
+        (N::Big(l), N::Int(r)) => N::Big(big_fn(l, r.into())).normalize(),
+        (N::Int(l), N::Big(r)) => N::Big(big_fn(l.into(), r)).normalize(),
     }
 }
 
@@ -56,8 +114,12 @@ impl Neg for NumberValue {
     type Output = Self;
     fn neg(self) -> Self::Output {
         match self {
-            NumberValue::Int(x) => NumberValue::Int(-x),
+            NumberValue::Int(x) => match x.checked_neg() {
+                Some(x) => NumberValue::Int(x),
+                None => NumberValue::Big(-BigInt::from(x)),
+            },
             NumberValue::Float(x) => NumberValue::Float(-x),
+            NumberValue::Big(x) => NumberValue::Big(-x).normalize(),
         }
     }
 }
@@ -65,35 +127,35 @@ impl Neg for NumberValue {
 impl Add for NumberValue {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Add::add, Add::add)
+        arith(self, rhs, i64::checked_add, Add::add, Add::add)
     }
 }
 
 impl Sub for NumberValue {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Sub::sub, Sub::sub)
+        arith(self, rhs, i64::checked_sub, Sub::sub, Sub::sub)
     }
 }
 
 impl Mul for NumberValue {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Mul::mul, Mul::mul)
+        arith(self, rhs, i64::checked_mul, Mul::mul, Mul::mul)
     }
 }
 
 impl Div for NumberValue {
     type Output = Self;
     fn div(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Div::div, Div::div)
+        arith(self, rhs, i64::checked_div, Div::div, Div::div)
     }
 }
 
 impl Rem for NumberValue {
     type Output = Self;
     fn rem(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Rem::rem, Rem::rem)
+        arith(self, rhs, i64::checked_rem, Rem::rem, Rem::rem)
     }
 }
 
@@ -102,6 +164,7 @@ impl PartialEq<i64> for Number<'_> {
         match self.val() {
             NumberValue::Int(num) => num == *other,
             NumberValue::Float(num) => num == *other as f64,
+            NumberValue::Big(num) => num == BigInt::from(*other),
         }
     }
 }
@@ -111,21 +174,20 @@ impl PartialEq<f64> for Number<'_> {
         match self.val() {
             NumberValue::Int(num) => num as f64 == *other,
             NumberValue::Float(num) => num.approx_eq(*other, (f64::EPSILON, 2)),
+            NumberValue::Big(num) => num.to_f64() == Some(*other),
         }
     }
 }
 
 impl PartialOrd for NumberValue {
-    fn partial_cmp(&self, other: &NumberValue) -> Option<std::cmp::Ordering> {
-        match self {
-            NumberValue::Int(lhs) => match other {
-                NumberValue::Int(rhs) => lhs.partial_cmp(rhs),
-                NumberValue::Float(rhs) => (*lhs as f64).partial_cmp(rhs),
-            },
-            NumberValue::Float(lhs) => match other {
-                NumberValue::Int(rhs) => lhs.partial_cmp(&(*rhs as f64)),
-                NumberValue::Float(rhs) => lhs.partial_cmp(rhs),
-            },
+    fn partial_cmp(&self, other: &NumberValue) -> Option<Ordering> {
+        use NumberValue as N;
+        match (self, other) {
+            (N::Int(lhs), N::Int(rhs)) => lhs.partial_cmp(rhs),
+            (N::Big(lhs), N::Big(rhs)) => lhs.partial_cmp(rhs),
+            (N::Int(lhs), N::Big(rhs)) => BigInt::from(*lhs).partial_cmp(rhs),
+            (N::Big(lhs), N::Int(rhs)) => lhs.partial_cmp(&BigInt::from(*rhs)),
+            (lhs, rhs) => lhs.to_f64().partial_cmp(&rhs.to_f64()),
         }
     }
 }
@@ -155,9 +217,28 @@ pub(crate) fn mul(numbers: &[Number]) -> NumberValue {
     numbers.iter().fold(NumberValue::Int(1), |acc, x| acc * x.val())
 }
 
+/// Return NUMBER divided by each of DIVISORS in turn, or 1/NUMBER if there
+/// are no DIVISORS. Integer division truncates toward zero. If any argument
+/// is a float the whole division is done in floating point, so `(/ 5 2 2.0)'
+/// is 1.25 rather than 1.0.
 #[defun(name = "/")]
-pub(crate) fn div(number: Number, divisors: &[Number]) -> NumberValue {
-    divisors.iter().fold(number.val(), |acc, x| acc / x.val())
+pub(crate) fn div(number: Number, divisors: &[Number], cx: &Context) -> Result<NumberValue> {
+    let (dividend, divisors) = match divisors {
+        [] => (NumberValue::Int(1), std::slice::from_ref(&number)),
+        _ => (number.val(), divisors),
+    };
+    let is_float = |x: &Number| matches!(x.untag(), NumberType::Float(_));
+    if is_float(&number) || divisors.iter().any(is_float) {
+        let quotient = divisors.iter().fold(dividend.to_f64(), |acc, x| acc / x.val().to_f64());
+        return Ok(NumberValue::Float(quotient));
+    }
+    divisors.iter().try_fold(dividend, |acc, x| {
+        let x = x.val();
+        if x.is_zero() {
+            return Err(LispError::arith_error(cx).into());
+        }
+        Ok(acc / x)
+    })
 }
 
 #[defun(name = "1+")]
@@ -175,6 +256,7 @@ pub(crate) fn num_eq(number: Number, numbers: &[Number]) -> bool {
     match number.val() {
         NumberValue::Int(num) => numbers.iter().all(|&x| x == num),
         NumberValue::Float(num) => numbers.iter().all(|&x| x == num),
+        num @ NumberValue::Big(_) => numbers.iter().all(|x| x.val() == num),
     }
 }
 
@@ -183,13 +265,17 @@ pub(crate) fn num_ne(number: Number, numbers: &[Number]) -> bool {
     match number.val() {
         NumberValue::Int(num) => numbers.iter().all(|&x| x != num),
         NumberValue::Float(num) => numbers.iter().all(|&x| x != num),
+        num @ NumberValue::Big(_) => numbers.iter().all(|x| x.val() != num),
     }
 }
 
 fn cmp(number: Number, numbers: &[Number], cmp: fn(&NumberValue, &NumberValue) -> bool) -> bool {
     numbers
         .iter()
-        .try_fold(number.val(), |acc, &x| cmp(&acc, &x.val()).then_some(NumberValue::Int(0)))
+        .try_fold(number.val(), |acc, &x| {
+            let x = x.val();
+            cmp(&acc, &x).then_some(x)
+        })
         .is_some()
 }
 
@@ -223,15 +309,43 @@ fn logand(int_or_markers: &[Gc<i64>]) -> i64 {
     int_or_markers.iter().fold(-1, |accum, x| accum & x.untag())
 }
 
+/// Return X modulo Y. The result has the sign of Y, so `(mod -1 3)' is 2.
+/// X and Y may be floats.
 #[defun(name = "mod")]
-pub(crate) fn modulo(x: Number, y: Number) -> NumberValue {
-    x.val() % y.val()
+pub(crate) fn modulo(x: Number, y: Number, cx: &Context) -> Result<NumberValue> {
+    use NumberValue as N;
+    match (x.val(), y.val()) {
+        (x @ N::Float(_), y) | (x, y @ N::Float(_)) => {
+            let (x, y) = (x.to_f64(), y.to_f64());
+            let rem = x % y;
+            let differ = if y < 0.0 { rem > 0.0 } else { rem < 0.0 };
+            Ok(N::Float(if differ { rem + y } else { rem }))
+        }
+        (_, y) if y.is_zero() => Err(LispError::arith_error(cx).into()),
+        (x, y) => {
+            let rem = x % y.clone();
+            let differ = !rem.is_zero() && rem.is_negative() != y.is_negative();
+            Ok(if differ { rem + y } else { rem })
+        }
+    }
 }
 
+/// Return the remainder of X divided by Y. The result has the sign of X, so
+/// `(% -1 3)' is -1. Both arguments must be integers.
 #[defun(name = "%")]
-pub(crate) fn remainder(x: i64, y: i64) -> i64 {
+pub(crate) fn remainder(x: Number, y: Number, cx: &Context) -> Result<NumberValue> {
     // TODO: Handle markers
-    x % y
+    for arg in [x, y] {
+        if let NumberType::Float(_) = arg.untag() {
+            let obj: Object = arg.into();
+            bail!(TypeError::new(Type::Int, obj.untag()));
+        }
+    }
+    let y = y.val();
+    if y.is_zero() {
+        return Err(LispError::arith_error(cx).into());
+    }
+    Ok(x.val() % y)
 }
 
 #[expect(clippy::trivially_copy_pass_by_ref)]
@@ -299,8 +413,16 @@ mod test {
         let roots = &RootSet::default();
         let cx = &Context::new(roots);
 
-        assert_eq!(div(cx.add_as(12.0), &[]), NumberValue::Float(12.0));
-        assert_eq!(div(12.into(), &[5.into(), 2.into()]), NumberValue::Int(1));
+        assert_eq!(div(cx.add_as(4.0), &[], cx).unwrap(), NumberValue::Float(0.25));
+        assert_eq!(div(4.into(), &[], cx).unwrap(), NumberValue::Int(0));
+        assert_eq!(div(12.into(), &[5.into(), 2.into()], cx).unwrap(), NumberValue::Int(1));
+        assert_eq!(div((-7).into(), &[2.into()], cx).unwrap(), NumberValue::Int(-3));
+        assert_eq!(
+            div(5.into(), &[2.into(), cx.add_as(2.0)], cx).unwrap(),
+            NumberValue::Float(1.25)
+        );
+        assert!(div(5.into(), &[0.into()], cx).is_err());
+        assert!(div(cx.add_as(5.0), &[0.into()], cx).unwrap().to_f64().is_infinite());
     }
 
     #[test]
@@ -341,6 +463,29 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_bignum() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let max = NumberValue::Int(MAX_FIXNUM);
+        let big = max.clone() + NumberValue::Int(1);
+        assert_eq!(big, NumberValue::Big(BigInt::from(MAX_FIXNUM) + 1));
+        assert_eq!(big.clone() - NumberValue::Int(1), max);
+        assert!(matches!(cx.add(big.clone()).untag(), ObjectType::BigInt(_)));
+        assert!(matches!(cx.add(max.clone()).untag(), ObjectType::Int(MAX_FIXNUM)));
+
+        // i64 overflow is promoted instead of wrapping
+        let square = NumberValue::Int(i64::MAX) * NumberValue::Int(i64::MAX);
+        assert_eq!(square, NumberValue::Big(BigInt::from(i64::MAX) * i64::MAX));
+        assert_eq!(-NumberValue::Int(i64::MIN), NumberValue::Big(-BigInt::from(i64::MIN)));
+
+        let big_obj: Number = cx.add_as(BigInt::from(MAX_FIXNUM) + 1);
+        assert!(less_than(1.into(), &[big_obj]));
+        assert!(greater_than(cx.add_as(1e30), &[big_obj]));
+        assert_eq!(add(&[big_obj, cx.add_as(0.5)]), NumberValue::Float(big.to_f64() + 0.5));
+        assert!(num_eq(big_obj, &[cx.add_as(BigInt::from(MAX_FIXNUM) + 1)]));
+    }
+
     #[test]
     fn test_other() {
         let roots = &RootSet::default();
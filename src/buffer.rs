@@ -1,15 +1,15 @@
 //! Buffer operations.
 use crate::{
     core::{
-        env::{Env, INTERNED_SYMBOLS},
+        env::{sym, Env, INTERNED_SYMBOLS},
         error::{Type, TypeError},
         gc::{Context, Rt},
-        object::{Gc, LispBuffer, Object, ObjectType, OptionalFlag, NIL},
+        object::{BufferData, Gc, LispBuffer, Object, ObjectType, OptionalFlag, Symbol, NIL},
     },
     fns::slice_into_list,
 };
 use anyhow::{bail, Result};
-use rune_core::hashmap::HashMap;
+use rune_core::{hashmap::HashMap, macros::list};
 use rune_macros::defun;
 use std::sync::LazyLock;
 use std::sync::Mutex;
@@ -25,7 +25,7 @@ pub(crate) fn set_buffer<'ob>(
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
     let buffer = resolve_buffer(buffer_or_name, cx)?;
-    env.set_buffer(buffer);
+    env.set_buffer(buffer, cx);
     Ok(cx.add(buffer))
 }
 
@@ -43,10 +43,98 @@ fn resolve_buffer<'ob>(buffer_or_name: Object, cx: &'ob Context) -> Result<&'ob
     }
 }
 
+/// Mark the current buffer as modified if FLAG is non-nil, or as unmodified
+/// otherwise. Marking it unmodified releases the lock on its visited file.
 #[defun]
-fn set_buffer_modified_p(flag: Object) -> Object {
-    // TODO: implement
-    flag
+fn set_buffer_modified_p<'ob>(flag: Object<'ob>, env: &mut Rt<Env>) -> Result<Object<'ob>> {
+    let buffer = env.current_buffer.get_mut();
+    if flag.is_nil() {
+        unlock_visited(buffer)?;
+        buffer.set_unmodified();
+    } else {
+        buffer.save_tick = None;
+    }
+    Ok(flag)
+}
+
+/// Release the lock on the visited file of `buffer` if it was modified.
+fn unlock_visited(buffer: &BufferData) -> Result<()> {
+    match buffer.file_name.as_deref().filter(|_| buffer.is_modified()) {
+        Some(file) => crate::filelock::unlock(file),
+        None => Ok(()),
+    }
+}
+
+/// Return t if BUFFER was modified since its file was last read or saved.
+/// BUFFER defaults to the current buffer.
+#[defun]
+fn buffer_modified_p(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> Result<bool> {
+    match buffer {
+        Some(buffer) => env.with_buffer(buffer.untag(), |b| b.is_modified()),
+        None => Ok(env.current_buffer.get().is_modified()),
+    }
+}
+
+/// Return BUFFER's tick counter, which is incremented each time its text is
+/// changed. BUFFER defaults to the current buffer.
+#[defun]
+fn buffer_modified_tick(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> Result<usize> {
+    match buffer {
+        Some(buffer) => env.with_buffer(buffer.untag(), |b| b.text.modified_tick()),
+        None => Ok(env.current_buffer.get().text.modified_tick()),
+    }
+}
+
+/// Return the name of the file BUFFER is visiting, or nil if it is not
+/// visiting a file. BUFFER defaults to the current buffer.
+#[defun]
+fn buffer_file_name(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> Result<Option<String>> {
+    match buffer {
+        Some(buffer) => env.with_buffer(buffer.untag(), |b| b.file_name.clone()),
+        None => Ok(env.current_buffer.get().file_name.clone()),
+    }
+}
+
+/// Watcher of the variables that mirror the current buffer, which updates
+/// the buffer when SYMBOL is set or bound to NEWVAL. The variables are not
+/// buffer-local, so they are set from each buffer that is made current
+/// instead.
+#[defun]
+fn buffer_variable_watcher(
+    symbol: Symbol,
+    newval: Object,
+    _operation: Object,
+    _where: Object,
+    env: &mut Rt<Env>,
+) -> Result<()> {
+    match symbol {
+        sym::BUFFER_FILE_NAME => {
+            let name = match newval.untag() {
+                ObjectType::NIL => None,
+                ObjectType::String(name) => Some(name.to_string()),
+                _ => bail!(TypeError::new(Type::String, newval)),
+            };
+            env.current_buffer.get_mut().file_name = name;
+        }
+        sym::BUFFER_FILE_CODING_SYSTEM => {
+            if let Some(eol) = crate::coding::coding_system_eol(newval)? {
+                env.current_buffer.get_mut().eol = eol;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Set the variables that mirror the current buffer and watch them with
+/// [`buffer_variable_watcher`].
+pub(crate) fn init_variables(env: &mut Rt<Env>, cx: &Context) {
+    for var in [sym::BUFFER_FILE_NAME, sym::BUFFER_FILE_CODING_SYSTEM] {
+        let watchers = list![sym::BUFFER_VARIABLE_WATCHER; cx];
+        crate::data::put(var, sym::WATCHERS, watchers, env);
+        var.set_trapped_write(true);
+    }
+    env.sync_buffer_variables(cx);
 }
 
 #[defun]
@@ -118,7 +206,7 @@ pub(crate) fn get_buffer_create<'ob>(
             }
         }
         ObjectType::Buffer(_) => Ok(buffer_or_name),
-        other => Err(TypeError::new(Type::BufferOrName, other).into()),
+        other => Err(TypeError::one_of(&[Type::Buffer, Type::String], other).into()),
     }
 }
 
@@ -136,7 +224,7 @@ pub(crate) fn get_buffer<'ob>(
             }
         }
         ObjectType::Buffer(_) => Ok(buffer_or_name),
-        other => Err(TypeError::new(Type::BufferOrName, other).into()),
+        other => Err(TypeError::one_of(&[Type::Buffer, Type::String], other).into()),
     }
 }
 
@@ -167,7 +255,7 @@ fn unique_buffer_name(name: &str, ignore: Option<&str>, buffer_list: &BufferMap)
     while !valid_name(&new_name) {
         if name.starts_with(' ') {
             // use rand to find uniq names faster
-            let rand = rand::random::<u32>();
+            let rand = crate::replay::random() as u32;
             new_name = format!("{name}-{rand}");
         } else {
             new_name = format!("{name}<{number}>");
@@ -181,10 +269,17 @@ fn unique_buffer_name(name: &str, ignore: Option<&str>, buffer_list: &BufferMap)
 fn kill_buffer(buffer_or_name: Option<Object>, cx: &Context, env: &mut Rt<Env>) -> bool {
     match buffer_or_name {
         Some(buffer) => match resolve_buffer(buffer, cx) {
-            Ok(b) => env.with_buffer_mut(b, |b| b.kill()).unwrap_or(false),
+            Ok(b) => env
+                .with_buffer_mut(b, |b| {
+                    let _ = unlock_visited(b);
+                    b.kill()
+                })
+                .unwrap_or(false),
             Err(_) => false,
         },
         None => {
+            // failing to remove the lock should not stop the buffer being killed
+            let _ = unlock_visited(env.current_buffer.get());
             let killed = env.current_buffer.get_mut().kill();
             // todo, we need to select a new buffer
             env.current_buffer.release();
@@ -199,10 +294,38 @@ fn buffer_base_buffer(_buffer: OptionalFlag) -> bool {
     false
 }
 
+/// Create a buffer that is not part of the buffer list, so it can only be
+/// reached through the returned object. This is used for temporary buffers
+/// like the one behind `with-output-to-string'.
+pub(crate) fn create_internal_buffer<'ob>(name: &str, cx: &'ob Context) -> &'ob LispBuffer {
+    let global = INTERNED_SYMBOLS.lock().unwrap();
+    let buffer = global.create_buffer(name);
+    // SAFETY: This can be 'static because it is stored in the global block.
+    cx.bind(unsafe { &*(buffer as *const LispBuffer) })
+}
+
+/// Return the live buffer visiting the absolute file name `filename`.
+pub(crate) fn file_buffer<'ob>(
+    filename: &str,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Option<&'ob LispBuffer> {
+    // Don't hold the buffer list while looking at the buffers. Buffers open
+    // on other threads are skipped so that two threads can't wait on each
+    // other.
+    let buffers: Vec<&LispBuffer> = BUFFERS.lock().unwrap().values().copied().collect();
+    let visiting = |b: &&LispBuffer| {
+        env.try_with_buffer(b, |b| b.file_name.as_deref() == Some(filename))
+            .unwrap_or(false)
+    };
+    buffers.into_iter().find(visiting).map(|b| cx.bind(b))
+}
+
+/// Return the buffer visiting file FILENAME, or nil if there is none.
 #[defun]
-fn get_file_buffer(_filename: &str) -> bool {
-    // TODO: implement file buffers
-    false
+fn get_file_buffer<'ob>(filename: &str, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let filename = crate::fileio::expand_file_name(filename, None, env, cx)?;
+    Ok(file_buffer(&filename, env, cx).map_or(NIL, |b| cx.add(b)))
 }
 
 #[defun]
@@ -268,4 +391,31 @@ mod test {
         let buffer = get_buffer_create(cx.add("test_create_buffer"), Some(NIL), cx).unwrap();
         assert!(matches!(buffer.untag(), ObjectType::Buffer(_)));
     }
+
+    #[test]
+    fn test_buffer_variables() {
+        crate::interpreter::assert_lisp_with_vars(
+            "(let ((buffer (get-buffer-create \"test_buffer_file_name\")))
+               (save-current-buffer
+                 (set-buffer buffer)
+                 (setq buffer-file-name \"/tmp/visited\"))
+               (list buffer-file-name
+                     (buffer-file-name buffer)
+                     (save-current-buffer (set-buffer buffer) buffer-file-name)
+                     (save-current-buffer
+                       (set-buffer buffer)
+                       (let ((buffer-file-name nil)) (buffer-file-name)))
+                     (buffer-file-name buffer)))",
+            "(nil \"/tmp/visited\" \"/tmp/visited\" nil \"/tmp/visited\")",
+        );
+        crate::interpreter::assert_lisp_with_vars(
+            "(let ((buffer (get-buffer-create \"test_buffer_coding_system\")))
+               (save-current-buffer
+                 (set-buffer buffer)
+                 (setq buffer-file-coding-system 'utf-8-dos))
+               (list buffer-file-coding-system
+                     (save-current-buffer (set-buffer buffer) buffer-file-coding-system)))",
+            "(utf-8-unix utf-8-dos)",
+        );
+    }
 }
@@ -1,6 +1,5 @@
 #![expect(unstable_name_collisions)]
 //! The main bytecode interpeter.
-use crate::core::cons::Cons;
 use crate::core::env::{sym, CallFrame, Env};
 use crate::core::gc::{Context, IntoRoot, Rt, Rto, Slot};
 use crate::core::object::{
@@ -8,9 +7,9 @@ use crate::core::object::{
     WithLifetime, NIL,
 };
 use crate::data::LispError;
-use crate::eval::{ErrorType, EvalError, EvalResult};
+use crate::eval::{EvalError, EvalResult};
 use anyhow::{bail, Result};
-use rune_core::macros::{bail_err, rebind, root};
+use rune_core::macros::{rebind, root};
 use rune_macros::{defun, Trace};
 use sptr::Strict;
 
@@ -62,7 +61,7 @@ impl ProgramCounter {
             let value = *self.pc;
             self.pc = self.pc.add(1);
             if cfg!(feature = "debug_bytecode") && crate::debug::debug_enabled() {
-                println!("  arg: {value}");
+                tracing::trace!("  arg: {value}");
             }
             value.into()
         }
@@ -74,7 +73,7 @@ impl ProgramCounter {
             let value = u16::from_le(self.pc.cast::<u16>().read_unaligned());
             self.pc = self.pc.add(2);
             if cfg!(feature = "debug_bytecode") && crate::debug::debug_enabled() {
-                println!("  arg: {value}");
+                tracing::trace!("  arg: {value}");
             }
             value
         }
@@ -135,7 +134,13 @@ impl<'ob> RootedVM<'_, '_, '_> {
     fn varref(&mut self, idx: u16, cx: &'ob Context) -> Result<()> {
         let symbol = self.get_const(idx as usize, cx);
         if let ObjectType::Symbol(sym) = symbol.untag() {
-            let Some(var) = self.env.vars.get(sym) else { bail!("Void Variable: {sym}") };
+            if sym.is_const() {
+                self.env.stack.push(symbol);
+                return Ok(());
+            }
+            let Some(var) = self.env.vars.get(sym) else {
+                bail!(LispError::void_variable(sym, cx))
+            };
             let var = var.bind(cx);
             self.env.stack.push(var);
             Ok(())
@@ -144,25 +149,29 @@ impl<'ob> RootedVM<'_, '_, '_> {
         }
     }
 
-    fn varset(&mut self, idx: usize, cx: &Context) -> Result<()> {
-        let obj = self.get_const(idx, cx);
-        let symbol: Symbol = obj.try_into()?;
+    fn varset(&mut self, idx: usize, cx: &mut Context) -> Result<()> {
+        let symbol: Symbol = self.get_const(idx, cx).try_into()?;
         let value = self.env.stack.pop(cx);
-        crate::data::set(symbol, value, self.env)?;
-        Ok(())
+        root!(symbol, cx);
+        root!(value, cx);
+        crate::watchers::set(symbol, value, self.env, cx)
     }
 
-    fn varbind(&mut self, idx: u16, cx: &'ob Context) {
+    fn varbind(&mut self, idx: u16, cx: &mut Context) -> Result<(), EvalError> {
+        crate::eval::check_specpdl_size(self.env, cx)?;
         let value = self.env.stack.pop(cx);
         let symbol = self.get_const(idx as usize, cx);
         let ObjectType::Symbol(sym) = symbol.untag() else {
             unreachable!("Varbind was not a symbol: {:?}", symbol)
         };
-        self.env.varbind(sym, value, cx);
+        root!(sym, cx);
+        root!(value, cx);
+        crate::watchers::varbind(sym, value, self.env, cx)?;
+        Ok(())
     }
 
-    fn unbind(&mut self, idx: u16, cx: &'ob Context) {
-        self.env.unbind(idx, cx);
+    fn unbind(&mut self, idx: u16, cx: &mut Context) -> Result<()> {
+        crate::watchers::unbind(idx, self.env, cx)
     }
 
     fn get_const(&self, i: usize, cx: &'ob Context) -> Object<'ob> {
@@ -223,6 +232,8 @@ impl<'ob> RootedVM<'_, '_, '_> {
     }
 
     fn call(&mut self, arg_cnt: u16, cx: &'ob mut Context) -> Result<(), EvalError> {
+        crate::keyboard::maybe_quit(self.env)?;
+        crate::sandbox::check_limits(self.env, cx)?;
         let arg_cnt = usize::from(arg_cnt);
         let func: Function = self.env.stack[arg_cnt].bind(cx).try_into()?;
         let name = match func.untag() {
@@ -230,6 +241,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
             _ => String::from("lambda"),
         };
         if let FunctionType::ByteFn(next_fn) = func.untag() {
+            crate::eval::check_eval_depth(self.env, cx)?;
             // If bytecode, add another frame and resume execution.
             // OpCode::Return will remove the call frame.
             let len = self.env.stack.len();
@@ -260,33 +272,12 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 Err(e) => e,
             };
 
-            // we will fix this once we can handle different error types
-            #[expect(clippy::never_loop)]
             while let Some(handler) = self.handlers.bind_mut(cx).pop() {
-                match handler.condition.untag() {
-                    ObjectType::Symbol(sym::ERROR) => {}
-                    ObjectType::Cons(conditions) => {
-                        for condition in conditions {
-                            let condition = condition?;
-                            // TODO: Handle different error symbols
-                            if condition != sym::DEBUG && condition != sym::ERROR {
-                                bail_err!("non-error conditions {condition} not yet supported")
-                            }
-                        }
-                    }
-                    x => bail_err!("Invalid condition handler: {x}"),
+                if !err.handled_by(*handler.condition, self.env, cx)? {
+                    continue;
                 }
 
-                let error = if let EvalError { error: ErrorType::Signal(id), .. } = err {
-                    let Some((sym, data)) = self.env.get_exception(id) else {
-                        unreachable!("Exception not found")
-                    };
-                    Cons::new(sym, data, cx)
-                } else {
-                    // TODO: Need to remove the anyhow branch once
-                    // full errors are implemented
-                    Cons::new(sym::ERROR, format!("{err}"), cx)
-                };
+                let error = err.to_lisp(self.env, cx);
                 self.unwind(handler.stack_frame, cx);
                 self.env.stack.truncate(handler.stack_size);
                 self.env.stack.push(Object::from(error));
@@ -309,13 +300,11 @@ impl<'ob> RootedVM<'_, '_, '_> {
             };
 
             if Self::debug_enabled() {
-                println!("[");
                 for (idx, x) in self.env.stack.frames().iter().rev().enumerate() {
-                    println!("    {idx}: {x},");
+                    tracing::trace!("stack {idx}: {x}");
                 }
-                println!("]");
                 let byte_offset = self.pc.pc as i64 - self.pc.range.start as i64 - 1;
-                println!("op :{byte_offset}: {op:?}");
+                tracing::trace!("op :{byte_offset}: {op:?}");
             }
             match op {
                 op::StackRef0 => self.env.stack.push_ref(0, cx),
@@ -368,19 +357,19 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let idx = self.pc.arg2();
                     self.varset(idx.into(), cx)?;
                 }
-                op::VarBind0 => self.varbind(0, cx),
-                op::VarBind1 => self.varbind(1, cx),
-                op::VarBind2 => self.varbind(2, cx),
-                op::VarBind3 => self.varbind(3, cx),
-                op::VarBind4 => self.varbind(4, cx),
-                op::VarBind5 => self.varbind(5, cx),
+                op::VarBind0 => self.varbind(0, cx)?,
+                op::VarBind1 => self.varbind(1, cx)?,
+                op::VarBind2 => self.varbind(2, cx)?,
+                op::VarBind3 => self.varbind(3, cx)?,
+                op::VarBind4 => self.varbind(4, cx)?,
+                op::VarBind5 => self.varbind(5, cx)?,
                 op::VarBindN => {
                     let idx = self.pc.arg1();
-                    self.varbind(idx, cx);
+                    self.varbind(idx, cx)?;
                 }
                 op::VarBindN2 => {
                     let idx = self.pc.arg2();
-                    self.varbind(idx, cx);
+                    self.varbind(idx, cx)?;
                 }
                 op::Call0 => self.call(0, cx)?,
                 op::Call1 => self.call(1, cx)?,
@@ -396,19 +385,19 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let idx = self.pc.arg2();
                     self.call(idx, cx)?;
                 }
-                op::Unbind0 => self.unbind(0, cx),
-                op::Unbind1 => self.unbind(1, cx),
-                op::Unbind2 => self.unbind(2, cx),
-                op::Unbind3 => self.unbind(3, cx),
-                op::Unbind4 => self.unbind(4, cx),
-                op::Unbind5 => self.unbind(5, cx),
+                op::Unbind0 => self.unbind(0, cx)?,
+                op::Unbind1 => self.unbind(1, cx)?,
+                op::Unbind2 => self.unbind(2, cx)?,
+                op::Unbind3 => self.unbind(3, cx)?,
+                op::Unbind4 => self.unbind(4, cx)?,
+                op::Unbind5 => self.unbind(5, cx)?,
                 op::UnbindN => {
                     let idx = self.pc.arg1();
-                    self.unbind(idx, cx);
+                    self.unbind(idx, cx)?;
                 }
                 op::UnbindN2 => {
                     let idx = self.pc.arg2();
-                    self.unbind(idx, cx);
+                    self.unbind(idx, cx)?;
                 }
                 op::PopHandler => {
                     self.handlers.pop();
@@ -508,7 +497,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let newlet = self.env.stack.pop(cx);
                     let idx = self.env.stack.pop(cx);
                     let top = self.env.stack.top();
-                    top.set(data::aset(top.bind(cx), idx.try_into()?, newlet)?);
+                    top.set(data::aset(top.bind(cx), idx.try_into()?, newlet, cx)?);
                 }
                 op::SymbolValue => {
                     let top = self.env.stack.top().bind_as(cx)?;
@@ -521,9 +510,11 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 }
                 op::Set => {
                     let newlet = self.env.stack.pop(cx);
-                    let top = self.env.stack.top().bind_as(cx)?;
-                    let value = data::set(top, newlet, self.env)?;
-                    self.env.stack.top().set(value);
+                    let top: Symbol = self.env.stack.top().bind_as(cx)?;
+                    root!(top, cx);
+                    root!(newlet, cx);
+                    crate::watchers::set(top, newlet, self.env, cx)?;
+                    self.env.stack.top().set(newlet.bind(cx));
                 }
                 op::Fset => {
                     let def = self.env.stack.pop(cx);
@@ -636,6 +627,10 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     self.env.stack.push(cnst);
                 }
                 op::Goto => {
+                    // loops are compiled to backward jumps, so this is where
+                    // we check for quit
+                    crate::keyboard::maybe_quit(self.env)?;
+                    crate::sandbox::check_limits(self.env, cx)?;
                     let offset = self.pc.arg2();
                     self.pc.goto(offset);
                 }
@@ -742,12 +737,12 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 op::Setcar => {
                     let newcar = self.env.stack.pop(cx);
                     let top = self.env.stack.top();
-                    top.set(data::setcar(top.bind_as(cx)?, newcar)?);
+                    top.set(data::setcar(top.bind_as(cx)?, newcar, cx)?);
                 }
                 op::Setcdr => {
                     let newcdr = self.env.stack.pop(cx);
                     let top = self.env.stack.top();
-                    top.set(data::setcdr(top.bind_as(cx)?, newcdr)?);
+                    top.set(data::setcdr(top.bind_as(cx)?, newcdr, cx)?);
                 }
                 op::CarSafe => {
                     let top = self.env.stack.top();
@@ -762,8 +757,17 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let top = self.env.stack.top();
                     top.set(fns::nconc(&[top.bind_as(cx)?, list2.try_into()?])?);
                 }
-                op::Quo => todo!("Quo bytecode"),
-                op::Rem => todo!("Rem bytecode"),
+                op::Quo => {
+                    let arg1 = self.env.stack.pop(cx);
+                    let top = self.env.stack.top();
+                    let args = &[arg1.try_into()?];
+                    top.set(cx.add(arith::div(top.bind_as(cx)?, args, cx)?));
+                }
+                op::Rem => {
+                    let arg1 = self.env.stack.pop(cx);
+                    let top = self.env.stack.top();
+                    top.set(cx.add(arith::remainder(top.bind_as(cx)?, arg1.try_into()?, cx)?));
+                }
                 op::Numberp => {
                     let top = self.env.stack.top();
                     top.set(data::numberp(top.bind(cx)));
@@ -909,6 +913,70 @@ fn fetch_bytecode(_object: Object) {
     // TODO: Implement
 }
 
+/// Return a listing of the opcodes in `func`, with the constants they refer
+/// to.
+pub(crate) fn disassemble_bytecode(func: &ByteFn) -> String {
+    use std::fmt::Write as _;
+    let codes = func.codes();
+    let consts = func.consts();
+    let mut out = String::new();
+    _ = writeln!(out, "byte code:");
+    _ = writeln!(out, "  args: {}", func.args.into_arg_spec());
+    _ = writeln!(out, "  depth: {}", func.depth);
+    let mut pc = 0;
+    while pc < codes.len() {
+        let Ok(op) = opcode::OpCode::try_from(codes[pc]) else {
+            _ = writeln!(out, "{pc}\t<invalid opcode {}>", codes[pc]);
+            pc += 1;
+            continue;
+        };
+        let name = op.name();
+        let size = op.operand_bytes();
+        let operand = match size {
+            0 => op.implicit_operand(),
+            1 => codes.get(pc + 1).map(|x| usize::from(*x)),
+            _ => codes.get(pc + 1..pc + 3).map(|x| u16::from_le_bytes([x[0], x[1]]).into()),
+        };
+        _ = write!(out, "{pc}\t{name}");
+        if let Some(operand) = operand {
+            _ = write!(out, "\t{operand}");
+            if op.has_constant_operand() {
+                if let Some(constant) = consts.get(operand) {
+                    _ = write!(out, "\t{constant}");
+                }
+            }
+        }
+        out.push('\n');
+        pc += 1 + size;
+    }
+    out
+}
+
+/// Print the disassembled bytecode of OBJECT, which can be a compiled function
+/// or a symbol naming one. The output goes to BUFFER, which defaults to
+/// `standard-output'.
+#[defun]
+fn disassemble(
+    object: &Rto<Object>,
+    buffer: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let object = object.bind(cx);
+    let func = match object.untag() {
+        ObjectType::Symbol(symbol) => match symbol.follow_indirect(cx) {
+            Some(func) => func.into(),
+            None => bail!("Symbol's function definition is void: {symbol}"),
+        },
+        _ => object,
+    };
+    let ObjectType::ByteFn(func) = func.untag() else {
+        bail!("Not a compiled function: {object}");
+    };
+    let listing = disassemble_bytecode(func);
+    crate::print::write_to_stream(&listing, buffer, env, cx)
+}
+
 pub(crate) fn call<'ob>(
     func: &Rto<&ByteFn>,
     arg_cnt: usize,
@@ -932,6 +1000,7 @@ pub(crate) fn call<'ob>(
 #[cfg(test)]
 mod test {
     use crate::core::{
+        cons::Cons,
         gc::RootSet,
         object::{HashTable, IntoObject},
     };
@@ -1264,4 +1333,45 @@ mod test {
         root!(inner, cx);
         check_bytecode!(outer, [inner], 7, cx);
     }
+
+    #[test]
+    fn test_disassemble() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        use OpCode as O;
+        make_bytecode!(
+            bytecode,
+            257,
+            [
+                O::Constant0,
+                O::StackRef1,
+                O::VarRefN,
+                1,
+                O::Plus,
+                O::Goto,
+                0x08,
+                0x00,
+                O::Return,
+                O::Call2,
+                O::ListN,
+                3
+            ],
+            [1, sym::FLOOR],
+            cx
+        );
+        let expect = "byte code:
+  args: 257
+  depth: 10
+0\tconstant\t0\t1
+1\tstack-ref\t1
+2\tvarref\t1\tfloor
+4\tplus
+5\tgoto\t8
+8\treturn
+9\tcall\t2
+10\tlist\t3
+";
+        assert_eq!(disassemble_bytecode(bytecode.bind(cx)), expect);
+    }
 }
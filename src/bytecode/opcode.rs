@@ -260,3 +260,221 @@ pub(crate) enum OpCode {
     Constant62 = 254,
     Constant63 = 255,
 }
+
+impl OpCode {
+    /// The number of operand bytes that follow this opcode in the bytecode.
+    pub(crate) fn operand_bytes(self) -> usize {
+        use OpCode as op;
+        match self {
+            op::StackRefN
+            | op::VarRefN
+            | op::VarSetN
+            | op::VarBindN
+            | op::CallN
+            | op::UnbindN
+            | op::ListN
+            | op::ConcatN
+            | op::InsertN
+            | op::StackSetN
+            | op::DiscardN => 1,
+            op::StackRefN2
+            | op::VarRefN2
+            | op::VarSetN2
+            | op::VarBindN2
+            | op::CallN2
+            | op::UnbindN2
+            | op::PushCondtionCase
+            | op::PushCatch
+            | op::ConstantN2
+            | op::Goto
+            | op::GotoIfNil
+            | op::GotoIfNonNil
+            | op::GotoIfNilElsePop
+            | op::GotoIfNonNilElsePop
+            | op::StackSetN2 => 2,
+            _ => 0,
+        }
+    }
+
+    /// Return true if the operand of this opcode is an index into the
+    /// constant vector.
+    pub(crate) fn has_constant_operand(self) -> bool {
+        matches!(self as u8, 8..=31 | 129 | 192..=255)
+    }
+
+    /// The name of this opcode as shown by the disassembler, which is the
+    /// name Emacs uses without the "byte-" prefix. Variants that only differ
+    /// in how their operand is encoded share a name, e.g. `Call2` and `CallN`
+    /// are both `call`.
+    pub(crate) fn name(self) -> &'static str {
+        use OpCode as op;
+        match self {
+            op::StackRef0
+            | op::StackRef1
+            | op::StackRef2
+            | op::StackRef3
+            | op::StackRef4
+            | op::StackRef5
+            | op::StackRefN
+            | op::StackRefN2 => "stack-ref",
+            op::VarRef0
+            | op::VarRef1
+            | op::VarRef2
+            | op::VarRef3
+            | op::VarRef4
+            | op::VarRef5
+            | op::VarRefN
+            | op::VarRefN2 => "varref",
+            op::VarSet0
+            | op::VarSet1
+            | op::VarSet2
+            | op::VarSet3
+            | op::VarSet4
+            | op::VarSet5
+            | op::VarSetN
+            | op::VarSetN2 => "varset",
+            op::VarBind0
+            | op::VarBind1
+            | op::VarBind2
+            | op::VarBind3
+            | op::VarBind4
+            | op::VarBind5
+            | op::VarBindN
+            | op::VarBindN2 => "varbind",
+            op::Call0
+            | op::Call1
+            | op::Call2
+            | op::Call3
+            | op::Call4
+            | op::Call5
+            | op::CallN
+            | op::CallN2 => "call",
+            op::Unbind0
+            | op::Unbind1
+            | op::Unbind2
+            | op::Unbind3
+            | op::Unbind4
+            | op::Unbind5
+            | op::UnbindN
+            | op::UnbindN2 => "unbind",
+            op::PopHandler => "pophandler",
+            op::PushCondtionCase => "pushconditioncase",
+            op::PushCatch => "pushcatch",
+            op::Nth => "nth",
+            op::Symbolp => "symbolp",
+            op::Consp => "consp",
+            op::Stringp => "stringp",
+            op::Listp => "listp",
+            op::Eq => "eq",
+            op::Memq => "memq",
+            op::Not => "not",
+            op::Car => "car",
+            op::Cdr => "cdr",
+            op::Cons => "cons",
+            op::List1 | op::List2 | op::List3 | op::List4 | op::ListN => "list",
+            op::Length => "length",
+            op::Aref => "aref",
+            op::Aset => "aset",
+            op::SymbolValue => "symbol-value",
+            op::SymbolFunction => "symbol-function",
+            op::Set => "set",
+            op::Fset => "fset",
+            op::Get => "get",
+            op::Substring => "substring",
+            op::Concat2 | op::Concat3 | op::Concat4 | op::ConcatN => "concat",
+            op::Sub1 => "sub1",
+            op::Add1 => "add1",
+            op::EqlSign => "eqlsign",
+            op::GreaterThan => "gtr",
+            op::LessThan => "lss",
+            op::LessThanOrEqual => "leq",
+            op::GreaterThanOrEqual => "geq",
+            op::Diff => "diff",
+            op::Negate => "negate",
+            op::Plus => "plus",
+            op::Max => "max",
+            op::Min => "min",
+            op::Multiply => "mult",
+            op::Point => "point",
+            op::GotoChar => "goto-char",
+            op::Insert | op::InsertN => "insert",
+            op::PointMax => "point-max",
+            op::PointMin => "point-min",
+            op::CharAfter => "char-after",
+            op::FollowingChar => "following-char",
+            op::PrecedingChar => "preceding-char",
+            op::CurrentColumn => "current-column",
+            op::IndentTo => "indent-to",
+            op::EndOfLineP => "eolp",
+            op::EndOfBufferP => "eobp",
+            op::BeginningOfLineP => "bolp",
+            op::BeginningOfBufferP => "bobp",
+            op::CurrentBuffer => "current-buffer",
+            op::SetBuffer => "set-buffer",
+            op::SaveCurrentBuffer1 => "save-current-buffer",
+            op::ForwardChar => "forward-char",
+            op::ForwardWord => "forward-word",
+            op::SkipCharsForward => "skip-chars-forward",
+            op::SkipCharsBackward => "skip-chars-backward",
+            op::ForwardLine => "forward-line",
+            op::CharSyntax => "char-syntax",
+            op::BufferSubstring => "buffer-substring",
+            op::DeleteRegion => "delete-region",
+            op::NarrowToRegion => "narrow-to-region",
+            op::Widen => "widen",
+            op::EndOfLine => "end-of-line",
+            op::ConstantN2 => "constant",
+            op::Goto => "goto",
+            op::GotoIfNil => "goto-if-nil",
+            op::GotoIfNonNil => "goto-if-not-nil",
+            op::GotoIfNilElsePop => "goto-if-nil-else-pop",
+            op::GotoIfNonNilElsePop => "goto-if-not-nil-else-pop",
+            op::Return => "return",
+            op::Discard | op::DiscardN => "discard",
+            op::Duplicate => "dup",
+            op::SaveExcursion => "save-excursion",
+            op::SaveRestriction => "save-restriction",
+            op::UnwindProtect => "unwind-protect",
+            op::SetMarker => "set-marker",
+            op::MatchBeginning => "match-beginning",
+            op::MatchEnd => "match-end",
+            op::Upcase => "upcase",
+            op::Downcase => "downcase",
+            op::StringEqlSign => "string=",
+            op::StringLessThan => "string<",
+            op::Equal => "equal",
+            op::Nthcdr => "nthcdr",
+            op::Elt => "elt",
+            op::Member => "member",
+            op::Assq => "assq",
+            op::Nreverse => "nreverse",
+            op::Setcar => "setcar",
+            op::Setcdr => "setcdr",
+            op::CarSafe => "car-safe",
+            op::CdrSafe => "cdr-safe",
+            op::Nconc => "nconc",
+            op::Quo => "quo",
+            op::Rem => "rem",
+            op::Numberp => "numberp",
+            op::Integerp => "integerp",
+            op::StackSetN | op::StackSetN2 => "stack-set",
+            op::Switch => "switch",
+            // Constant0 to Constant63
+            _ => "constant",
+        }
+    }
+
+    /// The operand that is encoded in the opcode itself, like the 2 of
+    /// `Call2`.
+    pub(crate) fn implicit_operand(self) -> Option<usize> {
+        let code = self as u8;
+        let operand = match code {
+            0..=5 | 8..=13 | 16..=21 | 24..=29 | 32..=37 | 40..=45 => code % 8,
+            67..=70 => code - 66,
+            80..=82 => code - 78,
+            192..=255 => code - 192,
+            _ => return None,
+        };
+        Some(operand.into())
+    }
+}
@@ -0,0 +1,251 @@
+//! A small infix expression evaluator.
+//!
+//! This covers the arithmetic subset of `calc-eval' that is useful for quick
+//! scripting: numbers, variables, + - * / ^ and parentheses, with the usual
+//! precedence. `^' is right associative and binds tighter than unary minus,
+//! so -2^2 is -4. Integer arithmetic is exact and promotes to bignums;
+//! division of integers that do not divide evenly produces a float, since
+//! there are no fractions.
+use crate::arith::NumberValue;
+use crate::core::object::{List, Number, ObjectType};
+use anyhow::{anyhow, bail, ensure, Result};
+use num_bigint::BigInt;
+use rune_macros::defun;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+struct Parser<'a, 'v> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    vars: &'v [(String, NumberValue)],
+}
+
+impl<'a, 'v> Parser<'a, 'v> {
+    fn new(source: &'a str, vars: &'v [(String, NumberValue)]) -> Self {
+        Self { source, chars: source.char_indices().peekable(), vars }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    /// Consume `chr` if it is the next character after any whitespace.
+    fn eat(&mut self, chr: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if(|(_, c)| *c == chr).is_some()
+    }
+
+    fn parse(mut self) -> Result<NumberValue> {
+        let value = self.expr()?;
+        self.skip_whitespace();
+        if let Some((i, _)) = self.chars.peek() {
+            bail!("Unexpected `{}' in expression: {}", &self.source[*i..], self.source);
+        }
+        Ok(value)
+    }
+
+    fn expr(&mut self) -> Result<NumberValue> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = value + self.term()?;
+            } else if self.eat('-') {
+                value = value - self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<NumberValue> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value = value * self.unary()?;
+            } else if self.eat('/') {
+                value = divide(value, self.unary()?)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<NumberValue> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<NumberValue> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            // the exponent may itself be negated or raised to a power
+            power(base, self.unary()?)
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn primary(&mut self) -> Result<NumberValue> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some((_, '(')) => {
+                self.chars.next();
+                let value = self.expr()?;
+                ensure!(self.eat(')'), "Missing `)' in expression: {}", self.source);
+                Ok(value)
+            }
+            Some((start, c)) if c.is_ascii_digit() || c == '.' => self.number(start),
+            Some((start, c)) if c.is_alphabetic() || c == '_' => {
+                let mut end = start;
+                while let Some((i, c)) =
+                    self.chars.next_if(|(_, c)| c.is_alphanumeric() || *c == '_')
+                {
+                    end = i + c.len_utf8();
+                }
+                self.variable(&self.source[start..end])
+            }
+            Some((i, _)) => {
+                bail!("Unexpected `{}' in expression: {}", &self.source[i..], self.source)
+            }
+            None => bail!("Unexpected end of expression: {}", self.source),
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<NumberValue> {
+        let mut end = start;
+        let mut float = false;
+        self.digits(&mut end);
+        if let Some((i, _)) = self.chars.next_if(|(_, c)| *c == '.') {
+            float = true;
+            end = i + 1;
+            self.digits(&mut end);
+        }
+        // only treat `e' as an exponent when digits follow it
+        let rest = &self.source[end..];
+        let exponent =
+            rest.strip_prefix(['e', 'E']).map(|x| x.strip_prefix(['+', '-']).unwrap_or(x));
+        if exponent.is_some_and(|x| x.starts_with(|c: char| c.is_ascii_digit())) {
+            float = true;
+            self.chars.next();
+            if let Some((i, _)) = self.chars.next_if(|(_, c)| matches!(c, '+' | '-')) {
+                end = i + 1;
+            }
+            self.digits(&mut end);
+        }
+        let text = &self.source[start..end];
+        if float {
+            let value = text.parse().map_err(|_| anyhow!("Invalid number `{text}'"))?;
+            Ok(NumberValue::Float(value))
+        } else {
+            let value: BigInt = text.parse()?;
+            Ok(NumberValue::Big(value).normalize())
+        }
+    }
+
+    /// Consume a run of digits, moving `end` past them.
+    fn digits(&mut self, end: &mut usize) {
+        while let Some((i, _)) = self.chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            *end = i + 1;
+        }
+    }
+
+    fn variable(&self, name: &str) -> Result<NumberValue> {
+        if let Some((_, value)) = self.vars.iter().find(|(var, _)| *var == name) {
+            return Ok(value.clone());
+        }
+        match name {
+            "pi" => Ok(NumberValue::Float(std::f64::consts::PI)),
+            "e" => Ok(NumberValue::Float(std::f64::consts::E)),
+            _ => bail!("Unknown variable `{name}' in expression: {}", self.source),
+        }
+    }
+}
+
+/// Divide exactly when both operands are integers that divide evenly, and
+/// in floating point otherwise.
+fn divide(x: NumberValue, y: NumberValue) -> Result<NumberValue> {
+    use NumberValue as N;
+    match (&x, &y) {
+        (N::Float(_), _) | (_, N::Float(_)) => Ok(N::Float(x.to_f64() / y.to_f64())),
+        _ => {
+            ensure!(!y.is_zero(), "Division by zero");
+            if (x.clone() % y.clone()).is_zero() {
+                Ok(x / y)
+            } else {
+                Ok(N::Float(x.to_f64() / y.to_f64()))
+            }
+        }
+    }
+}
+
+fn power(base: NumberValue, exponent: NumberValue) -> Result<NumberValue> {
+    use NumberValue as N;
+    let big = |x: NumberValue| match x {
+        N::Int(x) => BigInt::from(x),
+        N::Big(x) => x,
+        N::Float(_) => unreachable!(),
+    };
+    Ok(match (base, exponent) {
+        (base @ (N::Int(_) | N::Big(_)), N::Int(exp)) if exp >= 0 => {
+            let exp = u32::try_from(exp).map_err(|_| anyhow!("Exponent {exp} is too large"))?;
+            N::Big(big(base).pow(exp)).normalize()
+        }
+        (base, exp) => N::Float(base.to_f64().powf(exp.to_f64())),
+    })
+}
+
+/// Evaluate the infix arithmetic expression EXPR and return the result.
+/// EXPR may use numbers, + - * / ^ and parentheses. Variables are looked up
+/// in the plist VARS, whose keys are symbols and whose values are numbers,
+/// and `pi' and `e' are predefined. Integer results are exact; dividing
+/// integers that do not divide evenly returns a float.
+#[defun]
+fn calc_eval_basic(expr: &str, vars: Option<List>) -> Result<NumberValue> {
+    let mut bindings = Vec::new();
+    if let Some(vars) = vars {
+        let mut elements = vars.elements();
+        while let Some(name) = elements.next() {
+            let name = name?;
+            let ObjectType::Symbol(symbol) = name.untag() else {
+                bail!("Invalid variable name: {name}")
+            };
+            let value = elements.next().ok_or_else(|| anyhow!("Missing value for {name}"))??;
+            let value: Number = value.try_into()?;
+            bindings.push((symbol.name().to_owned(), value.val()));
+        }
+    }
+    Parser::new(expr, &bindings).parse()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_calc_eval_basic() {
+        assert_lisp("(calc-eval-basic \"1 + 2 * 3\")", "7");
+        assert_lisp("(calc-eval-basic \"(1 + 2) * 3\")", "9");
+        assert_lisp("(calc-eval-basic \"2 ^ 3 ^ 2\")", "512");
+        assert_lisp("(calc-eval-basic \"-2^2 + 10 - 3 - 2\")", "1");
+        assert_lisp("(calc-eval-basic \"7 / 2\")", "3.5");
+        assert_lisp("(calc-eval-basic \"8 / 2 / 2\")", "2");
+        assert_lisp("(calc-eval-basic \"2 ^ -1\")", "0.5");
+        assert_lisp("(calc-eval-basic \"1.5e1 * 2\")", "30.0");
+        assert_lisp("(calc-eval-basic \"2^64\")", "18446744073709551616");
+    }
+
+    #[test]
+    fn test_calc_variables_and_errors() {
+        assert_lisp("(calc-eval-basic \"x * (y - 1)\" '(x 3 y 2.5))", "4.5");
+        assert_lisp("(calc-eval-basic \"e * 2\" '(e 2))", "4");
+        assert_lisp("(condition-case nil (calc-eval-basic \"1 / 0\") (error 'failed))", "failed");
+        assert_lisp("(condition-case nil (calc-eval-basic \"(1 + 2\") (error 'failed))", "failed");
+        assert_lisp("(condition-case nil (calc-eval-basic \"1 2\") (error 'failed))", "failed");
+        assert_lisp("(condition-case nil (calc-eval-basic \"z + 1\") (error 'failed))", "failed");
+    }
+}
@@ -0,0 +1,170 @@
+//! Character folding.
+//!
+//! Character folding lets a search for a base character also match the
+//! characters that decompose to it, so that "e" matches "é", "ê" or "ｅ". The
+//! table of variants is built from the Unicode canonical and compatibility
+//! decompositions the first time it is needed. A character is a variant of
+//! its base if its decomposition is the base followed only by combining
+//! marks. Folding only goes one way: searching for "é" matches only "é".
+//!
+//! `char-fold-to-regexp' turns a search string into a regexp that matches its
+//! folded forms. Searches for strings typed by the user should go through
+//! `search-string-regexp', which folds when `search-default-mode' is
+//! `char-fold-to-regexp'.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::OptionalFlag,
+};
+use crate::data::LispError;
+use crate::search::{lisp_regex_to_rust, regexp_quote};
+use anyhow::{bail, Result};
+use fancy_regex::Regex;
+use rune_core::hashmap::HashMap;
+use rune_macros::defun;
+use std::sync::LazyLock;
+use unicode_normalization::char::{decompose_canonical, decompose_compatible, is_combining_mark};
+
+/// The variants of every base character that has any, in code point order.
+/// Characters above the supplementary ideographic plane have no
+/// decompositions to a different base, so they are not scanned.
+static VARIANTS: LazyLock<HashMap<char, Vec<char>>> = LazyLock::new(|| {
+    let mut table: HashMap<char, Vec<char>> = HashMap::default();
+    for c in '\u{80}'..='\u{2FFFF}' {
+        if let Some(base) = fold_base(c) {
+            table.entry(base).or_default().push(c);
+        }
+    }
+    table
+});
+
+/// The base character of `c`, if `c` decomposes to it.
+fn fold_base(c: char) -> Option<char> {
+    let mut decomposition = Vec::new();
+    decompose_canonical(c, |x| decomposition.push(x));
+    if decomposition == [c] {
+        decomposition.clear();
+        decompose_compatible(c, |x| decomposition.push(x));
+    }
+    match decomposition[..] {
+        [base, ref marks @ ..]
+            if base != c
+                && !is_combining_mark(base)
+                && marks.iter().copied().all(is_combining_mark) =>
+        {
+            Some(base)
+        }
+        _ => None,
+    }
+}
+
+/// Characters that can't appear unescaped in a bracket expression.
+fn special_in_brackets(c: char) -> bool {
+    matches!(c, '[' | ']' | '^' | '-' | '\\' | '&' | '~')
+}
+
+/// Return a regexp that matches STRING and its character folded forms. Each
+/// character that has variants is replaced by a bracket expression matching
+/// the character or any of its variants; other characters are quoted like
+/// with `regexp-quote'. If LAX is non-nil, each run of spaces matches any
+/// run of spaces and tabs. If FROM is non-nil, the regexp starts at that
+/// character of STRING.
+#[defun]
+pub(crate) fn char_fold_to_regexp(string: &str, lax: OptionalFlag, from: Option<usize>) -> String {
+    let mut regexp = String::new();
+    let mut chars = string.chars().skip(from.unwrap_or(0)).peekable();
+    while let Some(c) = chars.next() {
+        if c == ' ' && lax.is_some() {
+            while chars.next_if_eq(&' ').is_some() {}
+            regexp.push_str("[ \t]+");
+            continue;
+        }
+        match VARIANTS.get(&c) {
+            Some(variants) if !special_in_brackets(c) => {
+                regexp.push('[');
+                regexp.push(c);
+                regexp.extend(variants);
+                regexp.push(']');
+            }
+            _ => regexp.push_str(&regexp_quote(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regexp
+}
+
+/// Return the regexp used to search for STRING typed by the user. This folds
+/// characters if `search-default-mode' is `char-fold-to-regexp', and
+/// otherwise matches STRING literally.
+#[defun]
+pub(crate) fn search_string_regexp(string: &str, env: &Rt<Env>, cx: &Context) -> String {
+    match env.vars.get(sym::SEARCH_DEFAULT_MODE) {
+        Some(mode) if mode.bind(cx) == sym::CHAR_FOLD_TO_REGEXP => {
+            char_fold_to_regexp(string, None, None)
+        }
+        _ => regexp_quote(string),
+    }
+}
+
+/// Return the position of the first occurrence of NEEDLE in HAYSTACK, with
+/// characters folded like with `char-fold-to-regexp'. The search starts at
+/// START-POS if non-nil. Return nil if there is no match.
+#[defun]
+fn char_fold_string_search(
+    needle: &str,
+    haystack: &str,
+    start_pos: Option<usize>,
+    cx: &Context,
+) -> Result<Option<usize>> {
+    let start = start_pos.unwrap_or(0);
+    // positions are in characters, but the regexp matches bytes
+    let mut offsets = haystack.char_indices().map(|x| x.0).chain([haystack.len()]);
+    let Some(byte_start) = offsets.nth(start) else {
+        bail!(LispError::args_out_of_range(cx.add(haystack), start as i64, cx))
+    };
+    let re = Regex::new(&lisp_regex_to_rust(&char_fold_to_regexp(needle, None, None)))?;
+    let rest = &haystack[byte_start..];
+    Ok(re.find(rest)?.map(|x| start + rest[..x.start()].chars().count()))
+}
+
+defvar!(SEARCH_DEFAULT_MODE);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::{assert_lisp, assert_lisp_with_vars};
+
+    #[test]
+    fn test_fold_base() {
+        assert_eq!(fold_base('é'), Some('e'));
+        assert_eq!(fold_base('ｅ'), Some('e'));
+        assert_eq!(fold_base('Å'), Some('A'));
+        assert_eq!(fold_base('e'), None);
+        assert_eq!(fold_base('ß'), None);
+        assert!(VARIANTS[&'e'].contains(&'é'));
+    }
+
+    #[test]
+    fn test_char_fold_to_regexp() {
+        assert_lisp_with_vars(
+            "(list (search-string-regexp \"a.\")
+                   (let ((search-default-mode 'char-fold-to-regexp))
+                     (string-match (search-string-regexp \"cafe\") \"café\")))",
+            "(\"a\\\\.\" 0)",
+        );
+        assert_lisp("(string-match (char-fold-to-regexp \"cafe\") \"un café\")", "3");
+        assert_lisp("(string-match (char-fold-to-regexp \"é\") \"e\")", "nil");
+        assert_lisp("(string-match (char-fold-to-regexp \"1.\") \"12\")", "nil");
+        assert_lisp("(string-match (char-fold-to-regexp \"a  b\" t) \"xa   b\")", "1");
+        assert_lisp("(string-match (char-fold-to-regexp \"xyz\" nil 1) \"yz\")", "0");
+        assert_lisp("(string-match (char-fold-to-regexp \"xyz\" nil 1) \"xy\")", "nil");
+        assert_lisp("(char-fold-string-search \"resume\" \"my résumé\")", "3");
+        assert_lisp("(char-fold-string-search \"resume\" \"my resume\" 4)", "nil");
+        assert_lisp("(char-fold-string-search \"e\" \"résumé\" 5)", "5");
+        assert_lisp("(char-fold-string-search \"sum\" \"résumé\")", "2");
+        assert_lisp("(char-fold-string-search \"e\" \"résumé\" 6)", "nil");
+        assert_lisp(
+            "(condition-case err (char-fold-string-search \"e\" \"é\" 2) (args-out-of-range err))",
+            "(args-out-of-range \"é\" 2)",
+        );
+    }
+}
@@ -1,10 +1,14 @@
 //! Character and string utilities.
 use crate::core::{
-    gc::Context,
-    object::{int_to_char, Gc, Object, OptionalFlag},
+    env::{intern, sym, Env},
+    gc::{Context, Rt},
+    object::{int_to_char, Gc, Object, ObjectType, OptionalFlag, NIL},
 };
-use anyhow::Result;
+use crate::data::LispError;
+use anyhow::{bail, Result};
 use rune_macros::defun;
+use unicode_general_category::{get_general_category, GeneralCategory};
+use unicode_width::UnicodeWidthChar;
 
 #[defun]
 fn unibyte_string(bytes: &[Gc<i64>]) -> Result<Vec<u8>> {
@@ -53,3 +57,350 @@ fn make_string<'ob>(
         Ok(cx.add(string))
     }
 }
+
+/// Return the first character of STRING, or 0 if it is empty.
+#[defun]
+fn string_to_char(string: &str) -> char {
+    string.chars().next().unwrap_or('\0')
+}
+
+/// Return a string containing the single character CHR.
+#[defun]
+fn char_to_string(chr: char) -> String {
+    format!("{chr}")
+}
+
+/// Return t if CHARACTER is an uppercase letter.
+#[defun]
+fn char_uppercase_p(character: char) -> bool {
+    character.is_uppercase()
+}
+
+/// Return t if CHARACTER is a letter.
+#[defun]
+fn char_alphabetic_p(character: char) -> bool {
+    character.is_alphabetic()
+}
+
+/// Return t if CHARACTER is a letter or a number.
+#[defun]
+fn char_alphanumeric_p(character: char) -> bool {
+    character.is_alphanumeric()
+}
+
+/// The abbreviation of the Unicode general category of `chr`.
+fn general_category(chr: char) -> &'static str {
+    use GeneralCategory as G;
+    match get_general_category(chr) {
+        G::UppercaseLetter => "Lu",
+        G::LowercaseLetter => "Ll",
+        G::TitlecaseLetter => "Lt",
+        G::ModifierLetter => "Lm",
+        G::OtherLetter => "Lo",
+        G::NonspacingMark => "Mn",
+        G::SpacingMark => "Mc",
+        G::EnclosingMark => "Me",
+        G::DecimalNumber => "Nd",
+        G::LetterNumber => "Nl",
+        G::OtherNumber => "No",
+        G::ConnectorPunctuation => "Pc",
+        G::DashPunctuation => "Pd",
+        G::OpenPunctuation => "Ps",
+        G::ClosePunctuation => "Pe",
+        G::InitialPunctuation => "Pi",
+        G::FinalPunctuation => "Pf",
+        G::OtherPunctuation => "Po",
+        G::MathSymbol => "Sm",
+        G::CurrencySymbol => "Sc",
+        G::ModifierSymbol => "Sk",
+        G::OtherSymbol => "So",
+        G::SpaceSeparator => "Zs",
+        G::LineSeparator => "Zl",
+        G::ParagraphSeparator => "Zp",
+        G::Control => "Cc",
+        G::Format => "Cf",
+        G::Surrogate => "Cs",
+        G::PrivateUse => "Co",
+        _ => "Cn",
+    }
+}
+
+/// Map `chr` with a case conversion, returning the character itself when the
+/// mapping is not a single character.
+fn single_char(chr: char, mut mapping: impl Iterator<Item = char>) -> char {
+    match (mapping.next(), mapping.next()) {
+        (Some(c), None) => c,
+        _ => chr,
+    }
+}
+
+/// Return the value of the Unicode property PROPNAME of CHARACTER. The supported
+/// properties are `general-category', `decimal-digit-value', `digit-value',
+/// `numeric-value', `uppercase', `lowercase' and `titlecase'. Other properties
+/// return nil.
+#[defun]
+fn get_char_code_property<'ob>(character: char, propname: Object, cx: &'ob Context) -> Object<'ob> {
+    match propname.untag() {
+        ObjectType::Symbol(sym::GENERAL_CATEGORY) => intern(general_category(character), cx).into(),
+        ObjectType::Symbol(sym::DECIMAL_DIGIT_VALUE | sym::DIGIT_VALUE) => {
+            character.to_digit(10).map_or(NIL, |x| cx.add(i64::from(x)))
+        }
+        ObjectType::Symbol(sym::NUMERIC_VALUE) => {
+            character.to_digit(10).map_or(NIL, |x| cx.add(f64::from(x)))
+        }
+        ObjectType::Symbol(sym::UPPERCASE) => {
+            cx.add(single_char(character, character.to_uppercase()) as i64)
+        }
+        ObjectType::Symbol(sym::LOWERCASE) => {
+            cx.add(single_char(character, character.to_lowercase()) as i64)
+        }
+        // titlecase is the same as uppercase except for a few digraphs
+        ObjectType::Symbol(sym::TITLECASE) => {
+            let title = match character {
+                '\u{01C4}'..='\u{01C6}' => '\u{01C5}',
+                '\u{01C7}'..='\u{01C9}' => '\u{01C8}',
+                '\u{01CA}'..='\u{01CC}' => '\u{01CB}',
+                '\u{01F1}'..='\u{01F3}' => '\u{01F2}',
+                _ => single_char(character, character.to_uppercase()),
+            };
+            cx.add(title as i64)
+        }
+        _ => NIL,
+    }
+}
+
+/// Return the number of columns `chr` takes up when displayed. A tab is
+/// counted as `tab_width` columns.
+pub(crate) fn char_columns(chr: char, tab_width: usize) -> usize {
+    match chr {
+        '\t' => tab_width,
+        '\n' => 0,
+        // displayed as ^C
+        '\0'..='\x1F' | '\x7F' => 2,
+        // displayed as an octal escape like \200
+        '\u{80}'..='\u{9F}' => 4,
+        // combining marks and format characters have no width of their own,
+        // and East Asian wide and fullwidth characters take up two columns
+        c => c.width().unwrap_or(1),
+    }
+}
+
+/// Return the value of `tab-width', or 8 if it is not a sensible width.
+pub(crate) fn tab_width(env: &Rt<Env>, cx: &Context) -> usize {
+    match env.vars.get(sym::TAB_WIDTH).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::Int(x)) if (1..=1000).contains(&x) => x as usize,
+        _ => 8,
+    }
+}
+
+/// Return the number of columns `string` takes up when displayed.
+pub(crate) fn string_columns(string: &str, tab_width: usize) -> usize {
+    string.chars().map(|c| char_columns(c, tab_width)).sum()
+}
+
+/// Return the width of CHAR in columns when it is displayed. Wide East Asian
+/// characters take two columns, combining characters none, and a tab takes
+/// `tab-width' columns.
+#[defun]
+fn char_width(char: char, env: &Rt<Env>, cx: &Context) -> usize {
+    char_columns(char, tab_width(env, cx))
+}
+
+/// Return the width of STRING in columns when it is displayed. FROM and TO
+/// are the character indexes of the part of STRING to measure, and may be
+/// negative to count from the end. See `char-width'.
+#[defun]
+fn string_width(
+    string: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    let len = string.chars().count() as i64;
+    let index = |x: i64| if x < 0 { x + len } else { x };
+    let (from_arg, to_arg) = (from.unwrap_or(0), to.unwrap_or(len));
+    let (from, to) = (index(from_arg), index(to_arg));
+    if from < 0 || to > len || from > to {
+        bail!(LispError::substring_out_of_range(cx.add(string), from_arg, to_arg, cx));
+    }
+    let tab_width = tab_width(env, cx);
+    let part = string.chars().skip(from as usize).take((to - from) as usize);
+    Ok(part.map(|c| char_columns(c, tab_width)).sum())
+}
+
+/// Return the part of `string` between `start` and `end` columns. A wide
+/// character that straddles either column is left out, and the space it
+/// leaves is filled with `padding` if it is given. `padding` also fills the
+/// result out to `end` if `string` is too short. If `ellipsis` is given it
+/// replaces the end of the result when `string` is truncated.
+fn truncate_to_width(
+    string: &str,
+    end: usize,
+    start: usize,
+    padding: Option<char>,
+    ellipsis: &str,
+    tab_width: usize,
+) -> String {
+    let width = |c| char_columns(c, tab_width);
+    let mut chars = string.chars().peekable();
+    let mut column = 0;
+    while column < start {
+        let Some(c) = chars.next() else { break };
+        column += width(c);
+    }
+    let mut result = String::new();
+    if column < start {
+        // the string ends before the start column
+        if let Some(padding) = padding {
+            result.extend(std::iter::repeat(padding).take(end.saturating_sub(start)));
+        }
+        return result;
+    }
+    if let Some(padding) = padding {
+        result.extend(std::iter::repeat(padding).take(column - start));
+    }
+    let rest: String = chars.clone().collect();
+    let truncated = column + string_columns(&rest, tab_width) > end;
+    let ellipsis = if truncated { ellipsis } else { "" };
+    let end = end.saturating_sub(string_columns(ellipsis, tab_width)).max(column);
+    while let Some(&c) = chars.peek() {
+        if column + width(c) > end {
+            break;
+        }
+        column += width(c);
+        result.push(c);
+        chars.next();
+    }
+    if let Some(padding) = padding {
+        result.extend(std::iter::repeat(padding).take(end - column));
+    }
+    result.push_str(ellipsis);
+    result
+}
+
+/// Truncate STR to fit in END-COLUMN columns, leaving out the text before
+/// START-COLUMN. A wide character that straddles either column is left out,
+/// and if PADDING is a character it fills the space that leaves. PADDING also
+/// fills out the result to END-COLUMN if STR is too short. If ELLIPSIS is
+/// non-nil and STR is truncated, the end of the result is replaced with
+/// ELLIPSIS if it is a string, or with `truncate-string-ellipsis' otherwise.
+#[defun]
+fn truncate_string_to_width(
+    str: &str,
+    end_column: usize,
+    start_column: Option<usize>,
+    padding: Option<Object>,
+    ellipsis: Option<Object>,
+    _ellipsis_text_property: OptionalFlag,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    let padding = match padding {
+        Some(x) if !x.is_nil() => Some(char::try_from(x)?),
+        _ => None,
+    };
+    let ellipsis = match ellipsis.map(|x| x.untag()) {
+        None | Some(ObjectType::NIL) => "",
+        Some(ObjectType::String(x)) => &**x,
+        Some(_) => match env.vars.get(sym::TRUNCATE_STRING_ELLIPSIS).map(|x| x.bind(cx).untag()) {
+            Some(ObjectType::String(x)) => &**x,
+            _ => "...",
+        },
+    };
+    let start = start_column.unwrap_or(0);
+    Ok(truncate_to_width(str, end_column, start, padding, ellipsis, tab_width(env, cx)))
+}
+
+defvar!(TRUNCATE_STRING_ELLIPSIS);
+
+defsym!(GENERAL_CATEGORY);
+defsym!(DECIMAL_DIGIT_VALUE);
+defsym!(DIGIT_VALUE);
+defsym!(NUMERIC_VALUE);
+defsym!(UPPERCASE);
+defsym!(LOWERCASE);
+defsym!(TITLECASE);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+
+    #[test]
+    fn test_char_predicates() {
+        assert!(char_uppercase_p('A'));
+        assert!(char_uppercase_p('Ä'));
+        assert!(!char_uppercase_p('a'));
+        assert!(char_alphabetic_p('λ'));
+        assert!(!char_alphabetic_p('1'));
+        assert!(char_alphanumeric_p('1'));
+        assert!(!char_alphanumeric_p('-'));
+        assert_eq!(string_to_char(""), '\0');
+        assert_eq!(string_to_char("abc"), 'a');
+        assert_eq!(char_to_string('λ'), "λ");
+    }
+
+    #[test]
+    fn test_char_code_property() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let prop =
+            |c, p: crate::core::object::Symbol| get_char_code_property(c, p.into(), cx).to_string();
+        assert_eq!(prop('A', sym::GENERAL_CATEGORY), "Lu");
+        assert_eq!(prop('a', sym::GENERAL_CATEGORY), "Ll");
+        assert_eq!(prop('7', sym::GENERAL_CATEGORY), "Nd");
+        assert_eq!(prop(' ', sym::GENERAL_CATEGORY), "Zs");
+        assert_eq!(prop('(', sym::GENERAL_CATEGORY), "Ps");
+        assert_eq!(prop('\u{01C5}', sym::GENERAL_CATEGORY), "Lt");
+        assert_eq!(prop('\u{0301}', sym::GENERAL_CATEGORY), "Mn");
+        assert_eq!(prop('\u{2167}', sym::GENERAL_CATEGORY), "Nl");
+        assert_eq!(prop('€', sym::GENERAL_CATEGORY), "Sc");
+        assert_eq!(prop('\u{E000}', sym::GENERAL_CATEGORY), "Co");
+        assert_eq!(prop('\u{0378}', sym::GENERAL_CATEGORY), "Cn");
+        assert_eq!(prop('7', sym::DECIMAL_DIGIT_VALUE), "7");
+        assert_eq!(prop('x', sym::DIGIT_VALUE), "nil");
+        assert_eq!(prop('a', sym::UPPERCASE), ('A' as i64).to_string());
+        assert_eq!(prop('ß', sym::UPPERCASE), ('ß' as i64).to_string());
+        assert_eq!(prop('\u{01C6}', sym::TITLECASE), 0x01C5.to_string());
+        assert_eq!(prop('a', sym::NIL), "nil");
+    }
+
+    #[test]
+    fn test_char_columns() {
+        assert_eq!(char_columns('a', 8), 1);
+        assert_eq!(char_columns('\t', 4), 4);
+        assert_eq!(char_columns('\x01', 8), 2);
+        assert_eq!(char_columns('中', 8), 2);
+        assert_eq!(char_columns('ｱ', 8), 1);
+        assert_eq!(char_columns('\u{301}', 8), 0);
+        assert_eq!(char_columns('😀', 8), 2);
+        assert_eq!(char_columns('\u{94D}', 8), 0);
+        assert_eq!(char_columns('\u{1F300}', 8), 2);
+        assert_eq!(char_columns('\u{1F321}', 8), 1);
+        assert_eq!(string_columns("e\u{301}中文", 8), 5);
+        crate::interpreter::assert_lisp(
+            "(list (string-width \"abc\" 1) (string-width \"abc\" -2 -1)
+                   (condition-case err (string-width \"abc\" 0 5) (args-out-of-range err)))",
+            "(2 1 (args-out-of-range \"abc\" 0 5))",
+        );
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        let truncate = |s, end, start, padding, ellipsis| {
+            truncate_to_width(s, end, start, padding, ellipsis, 8)
+        };
+        assert_eq!(truncate("abcdef", 3, 0, None, ""), "abc");
+        assert_eq!(truncate("abcdef", 4, 1, None, ""), "bcd");
+        assert_eq!(truncate("abcdef", 10, 0, None, ""), "abcdef");
+        assert_eq!(truncate("ab", 4, 0, Some(' '), ""), "ab  ");
+        assert_eq!(truncate("中文字", 3, 0, None, ""), "中");
+        assert_eq!(truncate("中文字", 3, 0, Some('.'), ""), "中.");
+        assert_eq!(truncate("中文字", 4, 1, Some('.'), ""), ".文");
+        assert_eq!(truncate("abcdefgh", 6, 0, None, "..."), "abc...");
+        assert_eq!(truncate("abc", 6, 0, None, "..."), "abc");
+        assert_eq!(truncate("ab", 6, 4, Some(' '), ""), "  ");
+    }
+}
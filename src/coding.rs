@@ -0,0 +1,266 @@
+//! Line ending conversion for file I/O.
+//!
+//! Files are always read and written as UTF-8, but their line endings are
+//! converted so that text in a buffer only ever uses `\n`. When a file is
+//! read its convention is detected, unless `coding-system-for-read' names
+//! one, and a buffer visiting the file writes it back the same way. A coding
+//! system whose name ends in `-unix', `-dos' or `-mac' uses `\n', `\r\n' or
+//! `\r'; with any other name the line endings are detected.
+//!
+//! Bytes that are not valid UTF-8 are decoded to raw byte characters, like
+//! the `eight-bit' characters of Emacs, and are encoded back to the same
+//! bytes, so that a file is written back unchanged. A file that already
+//! contains the characters used for raw bytes can't be decoded.
+use crate::core::{
+    env::{intern, sym, Env},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, OptionalFlag, Symbol},
+};
+use anyhow::{bail, Result};
+use rune_core::macros::list;
+use rune_macros::defun;
+
+/// A line ending convention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Eol {
+    #[default]
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl Eol {
+    /// Return the line endings named by the coding system `name`, or `None`
+    /// if they should be detected.
+    fn from_coding_system(name: &str) -> Option<Self> {
+        if name.ends_with("-unix") || matches!(name, "binary" | "no-conversion" | "raw-text") {
+            Some(Self::Unix)
+        } else if name.ends_with("-dos") {
+            Some(Self::Dos)
+        } else if name.ends_with("-mac") {
+            Some(Self::Mac)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the UTF-8 coding system with these line endings.
+    pub(crate) fn coding_system(self) -> &'static str {
+        match self {
+            Self::Unix => "utf-8-unix",
+            Self::Dos => "utf-8-dos",
+            Self::Mac => "utf-8-mac",
+        }
+    }
+}
+
+/// Detect the line endings of `text`, or return `None` if it has no line
+/// breaks. Text that mixes conventions is treated as Unix, so that its
+/// carriage returns are kept as they are.
+pub(crate) fn detect_eol(text: &str) -> Option<Eol> {
+    let lf = text.matches('\n').count();
+    let cr = text.matches('\r').count();
+    match (lf, cr) {
+        (0, 0) => None,
+        (0, _) => Some(Eol::Mac),
+        _ if lf == cr && text.matches("\r\n").count() == lf => Some(Eol::Dos),
+        _ => Some(Eol::Unix),
+    }
+}
+
+/// Convert the line endings of `text` from `eol` to `\n`.
+pub(crate) fn decode(text: &str, eol: Eol) -> String {
+    match eol {
+        Eol::Unix => text.to_owned(),
+        Eol::Dos => text.replace("\r\n", "\n"),
+        Eol::Mac => text.replace('\r', "\n"),
+    }
+}
+
+/// Convert the line endings of `text` from `\n` to `eol` and encode it as
+/// UTF-8. Raw byte characters are written as the bytes they stand for.
+pub(crate) fn encode(text: &str, eol: Eol) -> Vec<u8> {
+    let text = match eol {
+        Eol::Unix => text.to_owned(),
+        Eol::Dos => text.replace('\n', "\r\n"),
+        Eol::Mac => text.replace('\n', "\r"),
+    };
+    if !text.chars().any(|c| raw_byte(c).is_some()) {
+        return text.into_bytes();
+    }
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match raw_byte(c) {
+            Some(byte) => bytes.push(byte),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    bytes
+}
+
+/// A raw byte is decoded to the character `RAW_BYTE_BASE + byte`. These are
+/// the last 128 characters of Unicode, which are for private use.
+const RAW_BYTE_BASE: u32 = 0x10_FF00;
+
+/// Return the byte that `c` stands for if it is a raw byte character.
+fn raw_byte(c: char) -> Option<u8> {
+    let byte = u32::from(c).checked_sub(RAW_BYTE_BASE)?;
+    u8::try_from(byte).ok().filter(|x| *x >= 0x80)
+}
+
+/// Decode `bytes` as UTF-8, turning bytes that are not valid UTF-8 into raw
+/// byte characters.
+fn decode_utf8(bytes: &[u8]) -> Result<String> {
+    let mut text = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        let valid = chunk.valid();
+        if valid.chars().any(|c| raw_byte(c).is_some()) {
+            bail!("Text contains characters reserved for raw bytes and can't be decoded");
+        }
+        text.push_str(valid);
+        for &byte in chunk.invalid() {
+            text.push(char::from_u32(RAW_BYTE_BASE + u32::from(byte)).unwrap());
+        }
+    }
+    Ok(text)
+}
+
+fn coding_system_name<'ob>(obj: Object<'ob>) -> Result<&'ob str> {
+    match obj.untag() {
+        ObjectType::Symbol(sym) => Ok(sym.name()),
+        _ => bail!("Invalid coding system: {obj}"),
+    }
+}
+
+/// Return the line endings named by `coding_system`, or `None` if it is nil
+/// or they should be detected.
+pub(crate) fn coding_system_eol(coding_system: Object) -> Result<Option<Eol>> {
+    if coding_system.is_nil() {
+        return Ok(None);
+    }
+    Ok(Eol::from_coding_system(coding_system_name(coding_system)?))
+}
+
+/// Return the line endings named by the coding system in `var`, if any.
+fn var_eol(var: Symbol, env: &Rt<Env>, cx: &Context) -> Result<Option<Eol>> {
+    match env.vars.get(var) {
+        Some(value) => coding_system_eol(value.bind(cx)),
+        None => Ok(None),
+    }
+}
+
+/// Decode the contents of a file. Returns the text and its line endings,
+/// which are `None` if it has no line breaks.
+pub(crate) fn decode_file(
+    bytes: &[u8],
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<(String, Option<Eol>)> {
+    let text = decode_utf8(bytes)?;
+    let eol = var_eol(sym::CODING_SYSTEM_FOR_READ, env, cx)?.or_else(|| detect_eol(&text));
+    Ok((decode(&text, eol.unwrap_or_default()), eol))
+}
+
+/// Return the line endings to write a file with, given those of the buffer
+/// it comes from. `coding-system-for-write' takes precedence.
+pub(crate) fn write_eol(buffer_eol: Eol, env: &Rt<Env>, cx: &Context) -> Result<Eol> {
+    Ok(var_eol(sym::CODING_SYSTEM_FOR_WRITE, env, cx)?.unwrap_or(buffer_eol))
+}
+
+/// Record the coding system used by the last file operation in
+/// `last-coding-system-used'.
+pub(crate) fn set_last_used(eol: Option<Eol>, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let name = eol.map_or("undecided", Eol::coding_system);
+    env.set_var(sym::LAST_CODING_SYSTEM_USED, intern(name, cx).into(), cx)
+}
+
+/// Use CODING-SYSTEM to write the visited file of the current buffer. If it
+/// does not name a line ending convention the current one is kept. The
+/// buffer is marked as modified unless NOMODIFY is non-nil.
+#[defun]
+fn set_buffer_file_coding_system(
+    coding_system: Object,
+    _force: OptionalFlag,
+    nomodify: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let eol = Eol::from_coding_system(coding_system_name(coding_system)?);
+    let buffer = env.current_buffer.get_mut();
+    if let Some(eol) = eol {
+        buffer.eol = eol;
+    }
+    if nomodify.is_none() {
+        buffer.save_tick = None;
+    }
+    env.sync_buffer_variables(cx);
+    Ok(false)
+}
+
+/// Return the line ending type of CODING-SYSTEM: 0 for `\n', 1 for `\r\n'
+/// and 2 for `\r'. If it is detected, return a vector of the coding systems
+/// for each type instead.
+#[defun]
+fn coding_system_eol_type<'ob>(coding_system: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let name = coding_system_name(coding_system)?;
+    Ok(match Eol::from_coding_system(name) {
+        Some(Eol::Unix) => cx.add(0),
+        Some(Eol::Dos) => cx.add(1),
+        Some(Eol::Mac) => cx.add(2),
+        None => {
+            let mut types = cx.vec_new();
+            for suffix in ["unix", "dos", "mac"] {
+                types.push(intern(&format!("{name}-{suffix}"), cx).into());
+            }
+            cx.add(types)
+        }
+    })
+}
+
+/// Return a list of the coding systems that could decode STRING, or just the
+/// most likely one if HIGHEST is non-nil.
+#[defun]
+fn detect_coding_string<'ob>(string: &str, highest: OptionalFlag, cx: &'ob Context) -> Object<'ob> {
+    let name = detect_eol(string).map_or("undecided", Eol::coding_system);
+    let coding_system = intern(name, cx).into();
+    match highest {
+        Some(()) => coding_system,
+        None => list![coding_system; cx],
+    }
+}
+
+defvar!(BUFFER_FILE_CODING_SYSTEM);
+defvar!(CODING_SYSTEM_FOR_READ);
+defvar!(CODING_SYSTEM_FOR_WRITE);
+defvar!(LAST_CODING_SYSTEM_USED);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eol() {
+        assert_eq!(detect_eol("a\nb\n"), Some(Eol::Unix));
+        assert_eq!(detect_eol("a\r\nb\r\n"), Some(Eol::Dos));
+        assert_eq!(detect_eol("a\rb\r"), Some(Eol::Mac));
+        assert_eq!(detect_eol("a\r\nb\n"), Some(Eol::Unix));
+        assert_eq!(detect_eol("a\r\nb\r"), Some(Eol::Unix));
+        assert_eq!(detect_eol("ab"), None);
+        for (text, eol) in [("a\r\nb\r\n", Eol::Dos), ("a\rb", Eol::Mac), ("a\r\nb\n", Eol::Unix)] {
+            assert_eq!(encode(&decode(text, eol), eol), text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_raw_bytes() {
+        // Latin-1, a truncated UTF-8 sequence and DOS line endings
+        let bytes = b"caf\xe9\r\n\xff\r\n\xf0\x9f\x98";
+        let text = decode(&decode_utf8(bytes).unwrap(), Eol::Dos);
+        assert_eq!(text.chars().count(), 10);
+        assert_eq!(text.chars().filter(|c| raw_byte(*c).is_some()).count(), 5);
+        assert_eq!(encode(&text, Eol::Dos), bytes);
+        let text = decode_utf8("é\n".as_bytes()).unwrap();
+        assert_eq!(text, "é\n");
+        assert!(decode_utf8("\u{10FFFF}".as_bytes()).is_err());
+    }
+}
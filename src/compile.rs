@@ -0,0 +1,304 @@
+//! Running compilations and finding the errors in their output.
+//!
+//! `compilation-start' runs a shell command in the `*compilation*' buffer.
+//! Errors are found by matching each line of the output against
+//! `compilation-error-regexp-alist', whose elements are lists of the form
+//! (REGEXP FILE LINE COLUMN) giving the regexp and the numbers of the
+//! subexpressions that match the file name, line and column. LINE and
+//! COLUMN may be nil. Each error is returned as a `compilation-error'
+//! record.
+use crate::buffer::get_buffer_create;
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Block, Context, Rt},
+    object::{Gc, IntoObject, List, Object, ObjectType, RecordBuilder, NIL},
+};
+use crate::fns::slice_into_list;
+use crate::insdel::{replace_region, with_current_buffer};
+use crate::search::lisp_regex_to_rust;
+use anyhow::{bail, Result};
+use fancy_regex::Regex;
+use rune_core::macros::{list, root};
+use rune_macros::defun;
+
+/// The default value of `compilation-error-regexp-alist'.
+const DEFAULT_ERROR_REGEXPS: &[(&str, i64, Option<i64>, Option<i64>)] = &[
+    // rustc: "  --> src/main.rs:3:5"
+    (r"^ *--> \([^:]+\):\([0-9]+\):\([0-9]+\)", 1, Some(2), Some(3)),
+    // python: "  File "main.py", line 3, in <module>"
+    (r#"^ *File "\([^"]+\)", line \([0-9]+\)"#, 1, Some(2), None),
+    // GNU: "main.c:3:5: error: ..." or "main.c:3: ..."
+    (r"^\([^: \t][^:]*\):\([0-9]+\):\(?:\([0-9]+\):\)?", 1, Some(2), Some(3)),
+];
+
+pub(crate) struct ErrorRegexps;
+
+impl IntoObject for ErrorRegexps {
+    type Out<'ob> = ObjectType<'ob>;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let mut alist = NIL;
+        for &(regexp, file, line, column) in DEFAULT_ERROR_REGEXPS.iter().rev() {
+            let entry = list![regexp, file, line, column; block];
+            alist = Cons::new(entry, alist, block).into();
+        }
+        alist
+    }
+}
+
+/// An element of `compilation-error-regexp-alist'.
+struct Matcher {
+    regex: Regex,
+    file: usize,
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+fn matchers(alist: Object) -> Result<Vec<Matcher>> {
+    let group = |x: Option<Object>| -> Result<Option<usize>> {
+        match x.map(|x| x.untag()) {
+            None | Some(ObjectType::NIL) => Ok(None),
+            Some(ObjectType::Int(x)) if x >= 0 => Ok(Some(x as usize)),
+            Some(other) => bail!("Invalid subexpression number: {other}"),
+        }
+    };
+    let mut matchers = Vec::new();
+    for entry in List::try_from(alist)?.elements() {
+        let entry = entry?;
+        // Emacs also allows symbols naming predefined entries, which we don't
+        // have
+        let ObjectType::Cons(entry) = entry.untag() else { continue };
+        let fields: Vec<Object> = entry.elements().collect::<Result<_>>()?;
+        let ObjectType::String(regexp) = fields[0].untag() else {
+            bail!("Invalid compilation error regexp: {}", fields[0])
+        };
+        let Some(file) = group(fields.get(1).copied())? else {
+            bail!("Missing file subexpression in {entry}")
+        };
+        matchers.push(Matcher {
+            regex: Regex::new(&lisp_regex_to_rust(regexp))?,
+            file,
+            line: group(fields.get(2).copied())?,
+            column: group(fields.get(3).copied())?,
+        });
+    }
+    Ok(matchers)
+}
+
+#[derive(Debug, PartialEq)]
+struct CompileError {
+    file: String,
+    line: Option<i64>,
+    column: Option<i64>,
+    message: String,
+}
+
+/// Find the errors in `text`. Each line is matched against the regexps in
+/// order and the first match is used.
+fn parse_errors(text: &str, matchers: &[Matcher]) -> Result<Vec<CompileError>> {
+    let mut errors = Vec::new();
+    for line in text.lines() {
+        for matcher in matchers {
+            let Some(captures) = matcher.regex.captures(line)? else { continue };
+            let Some(file) = captures.get(matcher.file) else { continue };
+            let number =
+                |group: Option<usize>| captures.get(group?).and_then(|x| x.as_str().parse().ok());
+            errors.push(CompileError {
+                file: file.as_str().to_owned(),
+                line: number(matcher.line),
+                column: number(matcher.column),
+                message: line.to_owned(),
+            });
+            break;
+        }
+    }
+    Ok(errors)
+}
+
+fn error_object<'ob>(error: CompileError, cx: &'ob Context) -> Object<'ob> {
+    let mut record = cx.vec_with_capacity(5);
+    record.push(sym::COMPILATION_ERROR.into());
+    record.push(cx.add(error.file));
+    record.push(cx.add(error.line));
+    record.push(cx.add(error.column));
+    record.push(cx.add(error.message));
+    cx.add(RecordBuilder(record))
+}
+
+/// Return the field at `index` of the `compilation-error' record `error`.
+fn error_field(error: Object, index: usize) -> Result<Object> {
+    if let ObjectType::Record(record) = error.untag() {
+        if record.len() == 5 && record[0].get() == sym::COMPILATION_ERROR {
+            return Ok(record[index].get());
+        }
+    }
+    bail!("Not a compilation error: {error}")
+}
+
+/// Parse the errors in SOURCE, a string or a buffer, and return a list of
+/// `compilation-error' records. ALIST defaults to
+/// `compilation-error-regexp-alist'.
+#[defun]
+fn compilation_parse_errors<'ob>(
+    source: Object,
+    alist: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let alist = match alist {
+        Some(alist) => alist,
+        None => match env.vars.get(sym::COMPILATION_ERROR_REGEXP_ALIST) {
+            Some(alist) => alist.bind(cx),
+            None => NIL,
+        },
+    };
+    let matchers = matchers(alist)?;
+    let errors = match source.untag() {
+        ObjectType::String(text) => parse_errors(text, &matchers)?,
+        ObjectType::Buffer(buffer) => {
+            let text = env.with_buffer(buffer, |buffer| buffer.text.to_string())?;
+            parse_errors(&text, &matchers)?
+        }
+        _ => bail!("Invalid compilation output: {source}"),
+    };
+    let errors: Vec<_> = errors.into_iter().map(|x| error_object(x, cx)).collect();
+    Ok(slice_into_list(&errors, None, cx))
+}
+
+/// Return non-nil if OBJECT is a `compilation-error' record.
+#[defun]
+fn compilation_error_p(object: Object) -> bool {
+    error_field(object, 0).is_ok()
+}
+
+/// Return the file name of the compilation error ERROR.
+#[defun]
+fn compilation_error_file(error: Object) -> Result<Object> {
+    error_field(error, 1)
+}
+
+/// Return the line number of the compilation error ERROR, or nil.
+#[defun]
+fn compilation_error_line(error: Object) -> Result<Object> {
+    error_field(error, 2)
+}
+
+/// Return the column of the compilation error ERROR, or nil.
+#[defun]
+fn compilation_error_column(error: Object) -> Result<Object> {
+    error_field(error, 3)
+}
+
+/// Return the line of output that the compilation error ERROR was found in.
+#[defun]
+fn compilation_error_message(error: Object) -> Result<Object> {
+    error_field(error, 4)
+}
+
+/// Run COMMAND with the shell in the `*compilation*' buffer, replacing its
+/// previous contents, and return the buffer. The process is added to
+/// `compilation-in-progress', and its output is inserted into the buffer
+/// by `accept-process-output'. MODE and NAME-FUNCTION are ignored.
+#[defun]
+fn compilation_start<'ob>(
+    command: &str,
+    _mode: Option<Object>,
+    _name_function: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let buffer = get_buffer_create(cx.add("*compilation*"), None, cx)?;
+    let ObjectType::Buffer(lisp_buffer) = buffer.untag() else { unreachable!() };
+    root!(lisp_buffer, cx);
+    let dir = std::env::current_dir()?;
+    let header = format!(
+        "-*- mode: compilation; default-directory: {:?} -*-\n\n{command}\n",
+        format!("{}{}", dir.display(), std::path::MAIN_SEPARATOR)
+    );
+    with_current_buffer(lisp_buffer, env, cx, |env, cx| {
+        let end = env.current_buffer.get().text.len_chars() + 1;
+        replace_region(1, end, &header, env, cx)?;
+        env.current_buffer.get_mut().text.set_cursor(header.chars().count());
+        Ok(())
+    })?;
+    let buffer = lisp_buffer.bind(cx).into();
+    let shell = if cfg!(windows) { ["cmd", "/c"] } else { ["sh", "-c"] };
+    let args = vec![shell[0].to_owned(), shell[1].to_owned(), command.to_owned()];
+    let process = crate::process::start_process("compilation", args, buffer, env, cx)?;
+    let running = match env.vars.get(sym::COMPILATION_IN_PROGRESS) {
+        Some(running) => running.bind(cx),
+        None => NIL,
+    };
+    env.set_var(sym::COMPILATION_IN_PROGRESS, Cons::new(process, running, cx).into(), cx)?;
+    Ok(buffer)
+}
+
+defvar!(COMPILATION_ERROR_REGEXP_ALIST, crate::compile::ErrorRegexps);
+defvar!(COMPILATION_IN_PROGRESS);
+defsym!(COMPILATION_ERROR);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::interpreter::assert_lisp_with_vars;
+
+    #[test]
+    fn test_parse_errors() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let matchers = matchers(ErrorRegexps.into_obj(cx).into()).unwrap();
+        let output = "\
+error[E0308]: mismatched types
+  --> src/main.rs:3:5
+main.c:10:2: error: expected ';'
+Makefile:4: recipe failed
+  File \"main.py\", line 7, in <module>
+warning: unused variable";
+        let error = |file: &str, line, column, message: &str| CompileError {
+            file: file.to_owned(),
+            line: Some(line),
+            column,
+            message: message.to_owned(),
+        };
+        assert_eq!(
+            parse_errors(output, &matchers).unwrap(),
+            [
+                error("src/main.rs", 3, Some(5), "  --> src/main.rs:3:5"),
+                error("main.c", 10, Some(2), "main.c:10:2: error: expected ';'"),
+                error("Makefile", 4, None, "Makefile:4: recipe failed"),
+                error("main.py", 7, None, "  File \"main.py\", line 7, in <module>"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compilation_parse_errors() {
+        assert_lisp_with_vars(
+            "(let ((error (car (compilation-parse-errors \"a.el:1:2: oops\"))))
+               (list (compilation-error-p error) (compilation-error-file error)
+                     (compilation-error-line error) (compilation-error-column error)
+                     (compilation-error-message error)))",
+            "(t \"a.el\" 1 2 \"a.el:1:2: oops\")",
+        );
+        assert_lisp_with_vars(
+            "(mapcar (lambda (x) (list (compilation-error-file x) (compilation-error-line x)
+                                       (compilation-error-column x)))
+                     (compilation-parse-errors \"x 12 y\" '((\"^\\\\(x\\\\) \\\\([0-9]+\\\\)\" 1 2))))",
+            "((\"x\" 12 nil))",
+        );
+        assert_lisp_with_vars("(compilation-error-p '(compilation-error))", "nil");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_compilation_start() {
+        assert_lisp_with_vars(
+            "(let ((buffer (compilation-start \"printf 'lib.rs:%d:1: error' 4\")))
+               (while (accept-process-output (car compilation-in-progress) 5))
+               (mapcar #'compilation-error-line (compilation-parse-errors buffer)))",
+            "(4)",
+        );
+    }
+}
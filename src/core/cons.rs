@@ -1,9 +1,10 @@
 use super::gc::{Block, GcHeap, GcState, Trace};
-use super::object::{CloneIn, Gc, IntoObject, ObjCell, Object, ObjectType, NIL};
+use super::object::{display_nested, CloneIn, Gc, IntoObject, ObjCell, Object, ObjectType, NIL};
 use crate::NewtypeMarkable;
 use anyhow::{anyhow, Result};
 use rune_core::hashmap::HashSet;
 use rune_macros::Trace;
+use std::cell::Cell;
 use std::fmt::{self, Debug, Display, Write};
 
 mod iter;
@@ -14,7 +15,7 @@ mod sealed {
     use super::*;
     #[derive(Eq)]
     pub(crate) struct ConsInner {
-        pub(super) mutable: bool,
+        pub(super) mutable: Cell<bool>,
         pub(super) car: ObjCell,
         pub(super) cdr: ObjCell,
     }
@@ -47,7 +48,7 @@ impl Cons {
     // the stack. Otherwise it could outlive it's objects since it has no
     // lifetimes.
     unsafe fn new_unchecked(car: Object, cdr: Object) -> ConsInner {
-        ConsInner { mutable: true, car: ObjCell::new(car), cdr: ObjCell::new(cdr) }
+        ConsInner { mutable: Cell::new(true), car: ObjCell::new(car), cdr: ObjCell::new(cdr) }
     }
 
     /// Create a new cons cell
@@ -80,7 +81,7 @@ impl Cons {
     }
 
     pub(in crate::core) fn mark_const(&mut self) {
-        self.0.mutable = false;
+        self.0.mutable.set(false);
     }
 }
 
@@ -93,8 +94,18 @@ impl ConsInner {
         self.cdr.get()
     }
 
+    pub(crate) fn is_mutable(&self) -> bool {
+        self.mutable.get()
+    }
+
+    /// Prevent this cons cell from being modified. This is used for literals
+    /// in source code, where mutation would change the code itself.
+    pub(crate) fn make_read_only(&self) {
+        self.mutable.set(false);
+    }
+
     pub(crate) fn set_car(&self, new_car: Object) -> Result<()> {
-        if self.mutable {
+        if self.mutable.get() {
             unsafe { self.car.as_mut().set(new_car) }
             Ok(())
         } else {
@@ -103,7 +114,7 @@ impl ConsInner {
     }
 
     pub(crate) fn set_cdr(&self, new_cdr: Object) -> Result<()> {
-        if self.mutable {
+        if self.mutable.get() {
             unsafe { self.cdr.as_mut().set(new_cdr) }
             Ok(())
         } else {
@@ -149,29 +160,36 @@ impl Cons {
             return f.write_str("#0");
         }
 
-        f.write_char('(')?;
-        let mut cons = self;
-
-        loop {
-            cons.car().untag().display_walk(f, seen)?;
-            match cons.cdr().untag() {
-                ObjectType::Cons(tail) => {
-                    cons = tail;
-                    f.write_char(' ')?;
+        display_nested(f, |f, length| {
+            f.write_char('(')?;
+            let mut cons = self;
+            let mut count = 0;
+            loop {
+                if length.is_some_and(|n| count >= n) {
+                    f.write_str("...")?;
+                    break;
+                }
+                count += 1;
+                cons.car().untag().display_walk(f, seen)?;
+                match cons.cdr().untag() {
+                    ObjectType::Cons(tail) => {
+                        cons = tail;
+                        f.write_char(' ')?;
+                    }
+                    ObjectType::NIL => break,
+                    x => {
+                        write!(f, " . ")?;
+                        x.display_walk(f, seen)?;
+                        break;
+                    }
                 }
-                ObjectType::NIL => break,
-                x => {
-                    write!(f, " . ")?;
-                    x.display_walk(f, seen)?;
+                if cons.is_backref(seen) {
+                    f.write_str(". #0")?;
                     break;
                 }
             }
-            if cons.is_backref(seen) {
-                f.write_str(". #0")?;
-                break;
-            }
-        }
-        f.write_char(')')
+            f.write_char(')')
+        })
     }
 
     fn is_backref(&self, seen: &mut HashSet<*const u8>) -> bool {
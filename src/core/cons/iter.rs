@@ -1,7 +1,12 @@
 use super::super::object::{List, ListType, Object, ObjectType};
 use super::Cons;
-use crate::core::gc::Rto;
+use crate::core::{
+    env::sym,
+    error::read_printed,
+    gc::{Context, Rto},
+};
 use anyhow::Result;
+use rune_core::macros::list;
 
 #[derive(Clone)]
 pub(crate) struct ConsIter<'ob> {
@@ -25,20 +30,21 @@ impl<'ob> Iterator for ConsIter<'ob> {
     type Item = Result<&'ob Cons, ConsError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let cons = match self.cons? {
+        let cons = match self.cons.clone()? {
             Ok(c) => c,
             Err(e) => return Some(Err(e)),
         };
-        self.cons = match cons.cdr().untag() {
+        let tail = cons.cdr();
+        self.cons = match tail.untag() {
             ObjectType::Cons(next) => Some(Ok(next)),
             ObjectType::NIL => None,
-            _ => Some(Err(ConsError::NonNilCdr)),
+            _ => Some(Err(ConsError::non_nil_cdr(tail))),
         };
 
         // Floyds cycle detection algorithm
         self.fast = advance(advance(self.fast));
-        if let (Some(Ok(slow)), Some(fast)) = (self.cons, self.fast) {
-            if std::ptr::eq(slow, fast) {
+        if let (Some(Ok(slow)), Some(fast)) = (&self.cons, self.fast) {
+            if std::ptr::eq(*slow, fast) {
                 self.cons = Some(Err(ConsError::CircularList));
             }
         }
@@ -64,6 +70,13 @@ impl ElemIter<'_> {
         self.clone().fallible().count()
     }
 
+    /// The number of elements, counting only up to the point where the list
+    /// is found to be dotted or circular. Unlike [`len`](Self::len) this never
+    /// fails, and it always terminates.
+    pub(crate) fn safe_len(&self) -> usize {
+        self.0.clone().take_while(Result::is_ok).count()
+    }
+
     /// Take the rest of the list as a cons.
     pub(crate) fn rest(&self) -> Result<Option<&Cons>, ConsError> {
         self.0.cons.transpose()
@@ -82,16 +95,41 @@ impl<'ob> Iterator for ElemIter<'ob> {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// An error from walking a list. The error can outlive the list, so it
+/// doesn't hold any objects.
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) enum ConsError {
-    NonNilCdr,
+    /// The list was terminated by a non-nil object, which is kept as it was
+    /// printed
+    NonNilCdr(String),
+    /// The list loops back on itself
     CircularList,
 }
 
+impl ConsError {
+    fn non_nil_cdr(tail: Object) -> Self {
+        // The tail of a dotted list is never a cons, so it is safe to print
+        Self::NonNilCdr(tail.to_string())
+    }
+
+    /// The `(ERROR-SYMBOL . DATA)` form of this error, as seen by
+    /// `condition-case'. The tail of a dotted list is read back from its
+    /// printed form, and a circular list has no data.
+    pub(crate) fn bind<'ob>(&self, cx: &'ob Context) -> &'ob Cons {
+        let error = match self {
+            ConsError::NonNilCdr(tail) => {
+                list![sym::WRONG_TYPE_ARGUMENT, sym::LISTP, read_printed(tail, cx); cx]
+            }
+            ConsError::CircularList => list![sym::CIRCULAR_LIST; cx],
+        };
+        error.as_cons()
+    }
+}
+
 impl std::fmt::Display for ConsError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            ConsError::NonNilCdr => write!(f, "non-nil cdr at end of list"),
+            ConsError::NonNilCdr(tail) => write!(f, "Wrong type argument: listp, {tail}"),
             ConsError::CircularList => write!(f, "Circular list"),
         }
     }
@@ -99,17 +137,26 @@ impl std::fmt::Display for ConsError {
 
 impl std::error::Error for ConsError {}
 
+/// A rooted iterator over the elements of a list, created with
+/// [`rooted_iter`](crate::rooted_iter). Like [`ElemIter`], this will detect
+/// circular lists and non-nil list terminators, but it can be held across
+/// garbage collections.
 pub(crate) struct ElemStreamIter<'rt> {
     elem: Option<&'rt mut Rto<Object<'static>>>,
     cons: Option<Result<&'rt mut Rto<&'static Cons>, ConsError>>,
+    // Floyds cycle detection. `cons` is the fast pointer, and this trails
+    // behind at half speed.
+    slow: Option<&'rt mut Rto<&'static Cons>>,
+    steps: usize,
 }
 
 impl<'rt> ElemStreamIter<'rt> {
     pub(crate) fn new(
         elem: Option<&'rt mut Rto<Object<'static>>>,
         cons: Option<&'rt mut Rto<&'static Cons>>,
+        slow: Option<&'rt mut Rto<&'static Cons>>,
     ) -> Self {
-        Self { elem, cons: cons.map(Ok) }
+        Self { elem, cons: cons.map(Ok), slow, steps: 0 }
     }
 }
 
@@ -121,19 +168,34 @@ impl fallible_streaming_iterator::FallibleStreamingIterator for ElemStreamIter<'
         if let Some(cons) = &mut self.cons {
             let cons = match cons {
                 Ok(x) => x,
-                Err(e) => return Err(*e),
+                Err(e) => return Err(e.clone()),
             };
             let elem = self.elem.as_mut().expect("Element should never be None while Cons is Some");
             let car = unsafe { cons.bind_unchecked().car() };
             elem.set(car);
-            match unsafe { cons.bind_unchecked().cdr().untag() } {
+            let tail = unsafe { cons.bind_unchecked().cdr() };
+            match tail.untag() {
                 ObjectType::Cons(next) => {
                     // dissociate the borrow of cons from cell
                     let x = unsafe { std::mem::transmute::<&Cons, &Cons>(next) };
                     cons.set(x);
+                    self.steps += 1;
+                    let slow = self.slow.as_mut().expect("Slow should be Some while Cons is Some");
+                    if self.steps % 2 == 0 {
+                        // The list may have been modified, so only advance
+                        // while there is somewhere to go
+                        if let ObjectType::Cons(next) =
+                            unsafe { slow.bind_unchecked().cdr().untag() }
+                        {
+                            slow.set(unsafe { std::mem::transmute::<&Cons, &Cons>(next) });
+                        }
+                    }
+                    if std::ptr::eq(x, unsafe { slow.bind_unchecked() }) {
+                        self.cons = Some(Err(ConsError::CircularList));
+                    }
                 }
                 ObjectType::NIL => self.cons = None,
-                _ => self.cons = Some(Err(ConsError::NonNilCdr)),
+                _ => self.cons = Some(Err(ConsError::non_nil_cdr(tail))),
             }
         } else {
             self.elem = None;
@@ -208,8 +270,10 @@ macro_rules! rooted_iter {
         // Create roots, but don't initialize them
         let mut elem;
         let mut cons;
+        let mut slow;
         let mut root_elem;
         let mut root_cons;
+        let mut root_slow;
         // use match to ensure that $value is not evaled inside the unsafe block
         let slot = match $value {
             value => unsafe { $crate::core::gc::IntoRoot::into_root(value) },
@@ -223,12 +287,18 @@ macro_rules! rooted_iter {
             unsafe {
                 elem = $crate::core::gc::Slot::new(object::NIL);
                 cons = $crate::core::gc::Slot::new(object::WithLifetime::with_lifetime(head));
+                slow = $crate::core::gc::Slot::new(object::WithLifetime::with_lifetime(head));
                 root_elem = gc::__StackRoot::new(&mut elem, $cx.get_root_set());
                 root_cons = gc::__StackRoot::new(&mut cons, $cx.get_root_set());
-                cons::ElemStreamIter::new(Some(root_elem.as_mut()), Some(root_cons.as_mut()))
+                root_slow = gc::__StackRoot::new(&mut slow, $cx.get_root_set());
+                cons::ElemStreamIter::new(
+                    Some(root_elem.as_mut()),
+                    Some(root_cons.as_mut()),
+                    Some(root_slow.as_mut()),
+                )
             }
         } else {
-            $crate::core::cons::ElemStreamIter::new(None, None)
+            $crate::core::cons::ElemStreamIter::new(None, None, None)
         };
     };
 }
@@ -275,6 +345,20 @@ mod test {
         assert!(iter.fallible().nth(3).is_err());
     }
 
+    #[test]
+    fn safe_len() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let list = list![1, 2, 3; cx];
+        assert_eq!(list.as_list().unwrap().safe_len(), 3);
+        let dotted = Cons::new(1, Cons::new(2, 3, cx), cx);
+        assert_eq!(dotted.elements().safe_len(), 2);
+        assert!(dotted.elements().len().is_err());
+        list.as_cons().cdr().as_cons().cdr().as_cons().set_cdr(list).unwrap();
+        assert!(list.as_list().unwrap().safe_len() >= 3);
+        assert!(list.as_list().unwrap().len().is_err());
+    }
+
     #[test]
     fn cons_iter() {
         let roots = &RootSet::default();
@@ -304,4 +388,36 @@ mod test {
         };
         func().unwrap();
     }
+
+    #[test]
+    fn stream_iter_improper() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let func = |list: Object| -> Result<usize> {
+            let mut count = 0;
+            rooted_iter!(iter, list, cx);
+            while iter.next()?.is_some() {
+                count += 1;
+            }
+            Ok(count)
+        };
+        let dotted = Cons::new(1, Cons::new(2, 3, cx), cx);
+        let err = func(dotted.into()).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<ConsError>(), Some(ConsError::NonNilCdr(x)) if x == "3")
+        );
+        let error = err.downcast_ref::<ConsError>().unwrap().bind(cx);
+        assert_eq!(error.to_string(), "(wrong-type-argument listp 3)");
+
+        for len in 1..=5 {
+            let list = crate::fns::slice_into_list(&vec![cx.add(1); len], None, cx);
+            let mut last = list.as_cons();
+            while let ObjectType::Cons(next) = last.cdr().untag() {
+                last = next;
+            }
+            last.set_cdr(list).unwrap();
+            let err = func(list).unwrap_err();
+            assert!(matches!(err.downcast_ref::<ConsError>(), Some(ConsError::CircularList)));
+        }
+    }
 }
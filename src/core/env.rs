@@ -1,6 +1,7 @@
 use super::gc::{Context, ObjectMap, Rto, Slot};
-use super::object::{LispBuffer, Object, OpenBuffer, Symbol, WithLifetime};
-use anyhow::{anyhow, Result};
+use super::object::{LispBuffer, Object, OpenBuffer, Symbol, WithLifetime, NIL};
+use crate::data::LispError;
+use anyhow::{ensure, Result};
 use rune_macros::Trace;
 use std::cell::OnceCell;
 
@@ -23,6 +24,10 @@ pub(crate) struct Env<'a> {
     #[no_trace]
     pub(crate) current_buffer: CurrentBuffer<'a>,
     pub(crate) stack: LispStack<'a>,
+    #[no_trace]
+    pub(crate) random_state: crate::fns::RandomState,
+    #[no_trace]
+    pub(crate) permissions: crate::permissions::Permissions,
 }
 
 #[derive(Debug)]
@@ -81,9 +86,9 @@ impl PartialEq<LispBuffer> for CurrentBuffer<'_> {
 
 // RootedEnv created by #[derive(Trace)]
 impl<'a> RootedEnv<'a> {
-    pub(crate) fn set_var(&mut self, sym: Symbol, value: Object) -> Result<()> {
+    pub(crate) fn set_var(&mut self, sym: Symbol, value: Object, cx: &Context) -> Result<()> {
         if sym.is_const() {
-            Err(anyhow!("Attempt to set a constant symbol: {sym}"))
+            Err(LispError::setting_constant(sym, cx).into())
         } else {
             self.vars.insert(sym, value);
             Ok(())
@@ -119,6 +124,21 @@ impl<'a> RootedEnv<'a> {
         self.vars.insert(var, value);
     }
 
+    /// The variable of the innermost dynamic binding and the value it had
+    /// before, or `None` if it was void.
+    pub(crate) fn last_binding<'ob>(
+        &self,
+        cx: &'ob Context,
+    ) -> Option<(Symbol<'ob>, Option<Object<'ob>>)> {
+        let (var, value) = self.binding_stack.bind_ref(cx).last()?;
+        Some((**var, value.as_ref().map(|x| **x)))
+    }
+
+    /// The number of dynamic bindings currently in effect.
+    pub(crate) fn binding_depth(&self) -> usize {
+        self.binding_stack.len()
+    }
+
     pub(crate) fn unbind(&mut self, count: u16, cx: &Context) {
         for _ in 0..count {
             match self.binding_stack.bind_mut(cx).pop() {
@@ -131,11 +151,12 @@ impl<'a> RootedEnv<'a> {
         }
     }
 
-    pub(crate) fn defvar(&mut self, var: Symbol, value: Object) -> Result<()> {
+    pub(crate) fn defvar(&mut self, var: Symbol, value: Object, cx: &Context) -> Result<()> {
+        ensure!(!var.is_const(), LispError::setting_constant(var, cx));
         // TOOD: Handle `eval-sexp` on defvar, which should always update the
         // value
         if self.vars.get(var).is_none() {
-            self.set_var(var, value)?;
+            self.set_var(var, value, cx)?;
             var.make_special();
         }
 
@@ -149,11 +170,23 @@ impl<'a> RootedEnv<'a> {
         Ok(())
     }
 
-    pub(crate) fn set_buffer(&mut self, buffer: &LispBuffer) {
+    pub(crate) fn set_buffer(&mut self, buffer: &LispBuffer, cx: &Context) {
         if buffer == self.current_buffer.buf_ref {
             return;
         }
         self.current_buffer.set(buffer);
+        self.sync_buffer_variables(cx);
+    }
+
+    /// Set `buffer-file-name' and `buffer-file-coding-system' from the current
+    /// buffer. These variables are not buffer-local, so this has to be called
+    /// whenever the current buffer or its visited file changes.
+    pub(crate) fn sync_buffer_variables(&mut self, cx: &Context) {
+        let buffer = self.current_buffer.get();
+        let file_name = buffer.file_name.clone().map_or(NIL, |x| cx.add(x));
+        let coding_system = intern(buffer.eol.coding_system(), cx);
+        self.vars.insert(sym::BUFFER_FILE_NAME, file_name);
+        self.vars.insert(sym::BUFFER_FILE_CODING_SYSTEM, coding_system.into());
     }
 
     pub(crate) fn with_buffer<T>(
@@ -181,4 +214,32 @@ impl<'a> RootedEnv<'a> {
             Ok(func(&mut buffer))
         }
     }
+
+    /// Like [`Env::with_buffer`], but returns `None` instead of waiting if
+    /// `buffer` is open on another thread, or if it was killed.
+    pub(crate) fn try_with_buffer<T>(
+        &self,
+        buffer: &LispBuffer,
+        mut func: impl FnMut(&OpenBuffer) -> T,
+    ) -> Option<T> {
+        if self.current_buffer == *buffer {
+            Some(func(self.current_buffer.get()))
+        } else {
+            Some(func(&buffer.try_lock()?))
+        }
+    }
+
+    /// Like [`Env::with_buffer_mut`], but returns `None` instead of waiting if
+    /// `buffer` is open on another thread, or if it was killed.
+    pub(crate) fn try_with_buffer_mut<T>(
+        &mut self,
+        buffer: &LispBuffer,
+        mut func: impl FnMut(&mut OpenBuffer) -> T,
+    ) -> Option<T> {
+        if self.current_buffer == *buffer {
+            Some(func(self.current_buffer.get_mut()))
+        } else {
+            Some(func(&mut buffer.try_lock()?))
+        }
+    }
 }
@@ -75,6 +75,11 @@ impl SymbolMap {
     pub(crate) fn get(&self, name: &str) -> Option<Symbol> {
         self.map.get(name)
     }
+
+    /// Iterate over every interned symbol, in no particular order.
+    pub(crate) fn symbols(&self) -> impl Iterator<Item = Symbol<'_>> + '_ {
+        self.map.map.values().map(|x| unsafe { x.with_lifetime() })
+    }
 }
 
 // This file includes all symbol definitions. Generated by build.rs
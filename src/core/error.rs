@@ -1,6 +1,11 @@
+use super::cons::Cons;
+use super::env::sym;
+use super::gc::Context;
+use super::object::{Object, Symbol};
+use rune_core::macros::list;
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub(crate) enum Type {
     Int,
     Char,
@@ -9,7 +14,6 @@ pub(crate) enum Type {
     Record,
     HashTable,
     Sequence,
-    BufferOrName,
     String,
     Symbol,
     Float,
@@ -17,32 +21,153 @@ pub(crate) enum Type {
     Number,
     List,
     Buffer,
+    BoolVector,
+    TreesitNode,
+    Sqlite,
+}
+
+impl Type {
+    /// The predicate for this type, used in the data of a
+    /// `wrong-type-argument' signal.
+    fn predicate(self) -> Symbol<'static> {
+        match self {
+            Type::Int => sym::INTEGERP,
+            Type::Char => sym::CHARACTERP,
+            Type::Cons => sym::CONSP,
+            Type::Vec => sym::VECTORP,
+            Type::Record => sym::RECORDP,
+            Type::HashTable => sym::HASH_TABLE_P,
+            Type::Sequence => sym::SEQUENCEP,
+            Type::String => sym::STRINGP,
+            Type::Symbol => sym::SYMBOLP,
+            Type::Float => sym::FLOATP,
+            Type::Func => sym::FUNCTIONP,
+            Type::Number => sym::NUMBERP,
+            Type::List => sym::LISTP,
+            Type::Buffer => sym::BUFFERP,
+            Type::BoolVector => sym::BOOL_VECTOR_P,
+            Type::TreesitNode => sym::TREESIT_NODE_P,
+            Type::Sqlite => sym::SQLITEP,
+        }
+    }
 }
 
 /// Error provided if object was the wrong type
 #[derive(Debug, PartialEq)]
 pub(crate) struct TypeError {
-    expect: Type,
+    /// The types that would have been accepted.
+    expect: Vec<Type>,
     actual: Type,
     print: String,
+    /// The name of the function that signaled the error, if known.
+    func: Option<&'static str>,
 }
 
 impl std::error::Error for TypeError {}
 
 impl Display for TypeError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        let Self { expect, actual, print } = self;
-        write!(f, "expected {expect:?}, found {actual:?}: {print}")
+        let Self { expect, actual, print, func } = self;
+        if let Some(func) = func {
+            write!(f, "{func}: ")?;
+        }
+        write!(f, "expected ")?;
+        match expect.as_slice() {
+            [] => write!(f, "nothing")?,
+            [x] => write!(f, "{x:?}")?,
+            [x, y] => write!(f, "{x:?} or {y:?}")?,
+            [init @ .., last] => {
+                for x in init {
+                    write!(f, "{x:?}, ")?;
+                }
+                write!(f, "or {last:?}")?;
+            }
+        }
+        write!(f, ", found {actual:?}: {print}")
     }
 }
 
 impl TypeError {
     /// Get a type error from an object.
     pub(crate) fn new<'ob, T>(expect: Type, obj: T) -> Self
+    where
+        T: Into<super::object::ObjectType<'ob>>,
+    {
+        Self::one_of(&[expect], obj)
+    }
+
+    /// Get a type error from an object that could have been any of the
+    /// `expect` types.
+    pub(crate) fn one_of<'ob, T>(expect: &[Type], obj: T) -> Self
     where
         T: Into<super::object::ObjectType<'ob>>,
     {
         let obj = obj.into();
-        Self { expect, actual: obj.get_type(), print: obj.to_string() }
+        Self {
+            expect: expect.to_vec(),
+            actual: obj.get_type(),
+            print: obj.to_string(),
+            func: None,
+        }
+    }
+
+    /// The error as `(wrong-type-argument PREDICATE VALUE)`, the form it is
+    /// bound to in a `condition-case' handler. The object itself isn't kept,
+    /// since it would not survive garbage collection, so VALUE is read back
+    /// from its printed form, or is that string if it can't be read.
+    pub(crate) fn to_lisp<'ob>(&self, cx: &'ob Context) -> &'ob Cons {
+        let predicate = self.expect.first().map_or(sym::NIL, |x| x.predicate());
+        let value = read_printed(&self.print, cx);
+        list![sym::WRONG_TYPE_ARGUMENT, predicate, value; cx].try_into().unwrap()
+    }
+
+    /// Record `name` as the function that signaled `err` if it is a type
+    /// error that does not already have one. Used by the `defun` wrappers when
+    /// converting arguments, which can fail with any kind of error.
+    pub(crate) fn add_func_name(
+        err: impl Into<anyhow::Error>,
+        name: &'static str,
+    ) -> anyhow::Error {
+        let mut err = err.into();
+        if let Some(type_err) = err.downcast_mut::<Self>() {
+            type_err.func.get_or_insert(name);
+        }
+        err
+    }
+}
+
+/// Read back an object that an error kept in its printed form `print`, or
+/// return the string itself if it can't be read.
+pub(crate) fn read_printed<'ob>(print: &str, cx: &'ob Context) -> Object<'ob> {
+    match crate::reader::read(print, cx) {
+        Ok((value, end)) if end == print.len() => value,
+        _ => cx.add(print),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::object::ObjectType;
+
+    #[test]
+    fn display() {
+        let err = TypeError::new(Type::Int, ObjectType::NIL);
+        assert_eq!(err.to_string(), "expected Int, found Symbol: nil");
+        let err = TypeError::one_of(&[Type::String, Type::Symbol], ObjectType::Int(1));
+        assert_eq!(err.to_string(), "expected String or Symbol, found Int: 1");
+        let err = TypeError::one_of(&[Type::Cons, Type::Vec, Type::String], ObjectType::Int(1));
+        let err = TypeError::add_func_name(err, "elt");
+        assert_eq!(err.to_string(), "elt: expected Cons, Vec, or String, found Int: 1");
+    }
+
+    #[test]
+    fn to_lisp() {
+        let roots = &crate::core::gc::RootSet::default();
+        let cx = &Context::new(roots);
+        let err = TypeError::new(Type::Int, ObjectType::NIL);
+        assert_eq!(err.to_lisp(cx).to_string(), "(wrong-type-argument integerp nil)");
+        let err = TypeError::new(Type::List, cx.add("a b").untag());
+        assert_eq!(err.to_lisp(cx).to_string(), "(wrong-type-argument listp \"a b\")");
     }
 }
@@ -1,4 +1,5 @@
 mod root;
+mod snapshot;
 mod trace;
 #[macro_use]
 mod context;
@@ -6,4 +7,5 @@ mod heap;
 pub(crate) use context::*;
 pub(crate) use heap::*;
 pub(crate) use root::*;
+pub(crate) use snapshot::*;
 pub(crate) use trace::*;
@@ -1,8 +1,7 @@
-use super::GcState;
-use super::Trace;
+use super::{GcState, HeapSnapshot, Recorder, Referrer, Trace};
 use crate::core::object::GcString;
-use crate::core::object::LispHashTable;
 use crate::core::object::{Gc, IntoObject, Object, UninternedSymbolMap, WithLifetime};
+use crate::core::object::{LispHashTable, LispSqlite, Record};
 use bumpalo::collections::Vec as GcVec;
 use std::cell::{Cell, RefCell};
 use std::fmt::Debug;
@@ -39,6 +38,12 @@ pub(crate) struct Block<const CONST: bool> {
     // track of the memory and free it only after the table is garbage
     // collected. Kind of a hack.
     pub(in crate::core) lisp_hashtables: RefCell<Vec<*const LispHashTable>>,
+    // Database handles are tracked so that their connections can be closed
+    // once the handle is no longer reachable.
+    pub(in crate::core) sqlite_handles: RefCell<Vec<*const LispSqlite>>,
+    // Records that stand for a value kept in Rust, which is released once the
+    // last of them is gone. See `crate::handle`.
+    pub(in crate::core) handles: RefCell<Vec<*const Record>>,
     pub(in crate::core) uninterned_symbol_map: UninternedSymbolMap,
 }
 
@@ -51,10 +56,20 @@ pub(crate) struct Context<'rt> {
     pub(crate) block: Block<false>,
     root_set: &'rt RootSet,
     next_limit: usize,
+    // Bytes reclaimed by all previous garbage collections.
+    collected_bytes: usize,
+    // The most bytes the heap may hold. See `Context::heap_exhausted`.
+    heap_limit: Option<usize>,
+    // The from-space of the last collection, emptied to be the to-space of the
+    // next one. Reusing it saves going back to the allocator every time, which
+    // matters when loading a file collects after each top-level form.
+    spare_space: Option<bumpalo::Bump>,
 }
 
 impl Drop for Context<'_> {
     fn drop(&mut self) {
+        // Collect into a fresh space, so that it holds no memory if it is empty
+        self.spare_space = None;
         self.garbage_collect(true);
         if self.block.objects.allocated_bytes() == 0 {
             return;
@@ -141,12 +156,26 @@ impl<'ob, 'rt> Context<'rt> {
     const MIN_GC_BYTES: usize = 2000;
     const GC_GROWTH_FACTOR: usize = 12; // divide by 10
     pub(crate) fn new(roots: &'rt RootSet) -> Self {
-        Self { block: Block::new_local(), root_set: roots, next_limit: Self::MIN_GC_BYTES }
+        Self {
+            block: Block::new_local(),
+            root_set: roots,
+            next_limit: Self::MIN_GC_BYTES,
+            collected_bytes: 0,
+            heap_limit: None,
+            spare_space: None,
+        }
     }
 
     pub(crate) fn from_block(block: Block<false>, roots: &'rt RootSet) -> Self {
         Block::assert_unique();
-        Context { block, root_set: roots, next_limit: Self::MIN_GC_BYTES }
+        Context {
+            block,
+            root_set: roots,
+            next_limit: Self::MIN_GC_BYTES,
+            collected_bytes: 0,
+            heap_limit: None,
+            spare_space: None,
+        }
     }
 
     pub(crate) fn bind<T>(&'ob self, obj: T) -> <T as WithLifetime<'ob>>::Out
@@ -160,14 +189,60 @@ impl<'ob, 'rt> Context<'rt> {
         self.root_set
     }
 
+    /// The number of bytes allocated by this context since it was created,
+    /// including those that have since been garbage collected.
+    pub(crate) fn total_allocated_bytes(&self) -> usize {
+        self.collected_bytes + self.heap_bytes()
+    }
+
+    /// The number of bytes currently held by the heap.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        used_bytes(&self.block.objects)
+    }
+
+    pub(crate) fn heap_limit(&self) -> Option<usize> {
+        self.heap_limit
+    }
+
+    /// Limit the heap to `limit` bytes, or remove the limit if it is `None`.
+    /// The limit is not enforced by allocation, which can't fail, but by
+    /// evaluation checking [`Context::heap_exhausted`] at safe points.
+    pub(crate) fn set_heap_limit(&mut self, limit: Option<usize>) {
+        self.heap_limit = limit;
+    }
+
+    /// Return true if the heap holds more than `limit` bytes even after an
+    /// emergency garbage collection.
+    pub(crate) fn heap_exhausted(&mut self, limit: usize) -> bool {
+        if self.heap_bytes() <= limit {
+            return false;
+        }
+        self.collect(None);
+        self.heap_bytes() > limit
+    }
+
     pub(crate) fn garbage_collect(&mut self, force: bool) {
-        let bytes = self.block.objects.allocated_bytes();
+        let bytes = self.heap_bytes();
         if cfg!(not(test)) && !force && bytes < self.next_limit {
             return;
         }
+        self.collect(None);
+    }
+
+    /// Collect garbage and return a snapshot of the objects that are still
+    /// reachable, and what refers to them.
+    pub(crate) fn heap_snapshot(&mut self) -> HeapSnapshot {
+        let recorder = self.collect(Some(Recorder::new()));
+        let roots = self.root_set.roots.borrow().len();
+        HeapSnapshot::new(roots, recorder.expect("recorder was dropped"), self)
+    }
 
-        let mut state = GcState::new();
-        for x in self.root_set.roots.borrow().iter() {
+    fn collect(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+        let bytes = self.heap_bytes();
+        let mut state = GcState::new(self.spare_space.take().unwrap_or_default());
+        state.recorder = recorder;
+        for (i, x) in self.root_set.roots.borrow().iter().enumerate() {
+            state.set_referrer(Referrer::Root(i));
             // SAFETY: The contract of root structs will ensure that it removes
             // itself from this list before it drops.
             unsafe {
@@ -177,7 +252,9 @@ impl<'ob, 'rt> Context<'rt> {
 
         state.trace_stack();
 
-        self.next_limit = (state.to_space.allocated_bytes() * Self::GC_GROWTH_FACTOR) / 10;
+        let live = used_bytes(&state.to_space);
+        self.next_limit = (live * Self::GC_GROWTH_FACTOR) / 10;
+        self.collected_bytes += bytes.saturating_sub(live);
         self.block.drop_stack.borrow_mut().clear();
         // Find all hashtables that have not been moved (i.e. They are no longer
         // accessible) and drop them. Otherwise, update the object pointer.
@@ -191,11 +268,43 @@ impl<'ob, 'rt> Context<'rt> {
                 false
             }
         });
+        // Likewise release the connection of every unreachable database handle.
+        self.block.sqlite_handles.borrow_mut().retain_mut(|ptr| {
+            let handle = unsafe { &**ptr };
+            if let Some(fwd) = handle.forwarding_ptr() {
+                *ptr = fwd.as_ptr().cast::<LispSqlite>();
+                true
+            } else {
+                crate::sqlite::finalize(handle.id());
+                false
+            }
+        });
+        self.block.handles.borrow_mut().retain_mut(|ptr| {
+            let record = unsafe { &**ptr };
+            if let Some(fwd) = record.forwarding_ptr() {
+                *ptr = fwd.as_ptr().cast::<Record>();
+                true
+            } else {
+                release_handle(record);
+                false
+            }
+        });
 
-        self.block.objects = state.to_space;
+        let mut from_space = std::mem::replace(&mut self.block.objects, state.to_space);
+        // Nothing points into the old space anymore. Resetting it frees all but
+        // its largest chunk, which is kept for the next collection.
+        from_space.reset();
+        self.spare_space = Some(from_space);
+        state.recorder
     }
 }
 
+/// The bytes allocated in `space`, not counting the free end of the chunk it
+/// is allocating from. A recycled space starts out with a whole free chunk.
+fn used_bytes(space: &bumpalo::Bump) -> usize {
+    space.allocated_bytes() - space.chunk_capacity()
+}
+
 impl Deref for Context<'_> {
     type Target = Block<false>;
 
@@ -210,10 +319,19 @@ impl AsRef<Block<false>> for Context<'_> {
     }
 }
 
+fn release_handle(record: &Record) {
+    if let [tag, id] = &record[..] {
+        crate::handle::release(tag.get(), id.get());
+    }
+}
+
 impl<const CONST: bool> Drop for Block<CONST> {
     // Only one block can exist in a thread at a time. This part of that
     // contract.
     fn drop(&mut self) {
+        for ptr in self.handles.get_mut().drain(..) {
+            release_handle(unsafe { &*ptr });
+        }
         SINGLETON_CHECK.with(|s| {
             assert!(s.get(), "Context singleton check was overwritten");
             s.set(false);
@@ -255,6 +373,40 @@ mod test {
         cx.garbage_collect(true);
     }
 
+    #[test]
+    fn test_heap_exhausted() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        for _ in 0..1000 {
+            let _ = Cons::new(1, 2, cx);
+        }
+        // garbage is collected before giving up
+        assert!(!cx.heap_exhausted(10_000));
+        let mut big = cx.vec_with_capacity(5000);
+        big.resize(5000, crate::core::object::NIL);
+        let big = cx.add(big);
+        root!(big, cx);
+        assert!(cx.heap_exhausted(10_000));
+        assert!(matches!(big.bind(cx).untag(), ObjectType::Vec(_)));
+    }
+
+    #[test]
+    fn test_recycle_space() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let string = cx.add("live");
+        root!(string, cx);
+        for _ in 0..3 {
+            for _ in 0..1000 {
+                let _ = Cons::new(1, 2, cx);
+            }
+            cx.garbage_collect(true);
+            assert!(cx.spare_space.is_some());
+            assert!(cx.heap_bytes() < 1000);
+            assert_eq!(string.bind(cx), "live");
+        }
+    }
+
     #[test]
     fn test_move_values() {
         let roots = &RootSet::default();
@@ -1,4 +1,5 @@
 use super::{GcState, Trace};
+use crate::core::object::RawObj;
 use std::{
     alloc::Layout,
     cell::{Cell, UnsafeCell},
@@ -125,6 +126,11 @@ pub(in crate::core) trait Markable {
     fn move_value(&self, _to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
         None
     }
+
+    /// The object this value refers to, used to name it in heap snapshots.
+    fn raw_obj(&self) -> Option<RawObj> {
+        None
+    }
 }
 
 impl<'a, T: Markable<Value = NonNull<T>>> Markable for &'a T {
@@ -2,7 +2,7 @@ use super::{
     super::{cons::Cons, object::Object},
     GcState, Markable,
 };
-use super::{Block, Context, RootSet, Trace};
+use super::{Block, Context, Referrer, RootSet, Trace};
 use crate::core::object::{Gc, GcPtr, IntoObject, ObjectType, OptionalFlag, Untag, WithLifetime};
 use rune_core::hashmap::IndexMap;
 use std::hash::{Hash, Hasher};
@@ -287,11 +287,19 @@ where
     fn trace(&self, state: &mut GcState) {
         if let Some((new, moved)) = self.get().move_value(&state.to_space) {
             unsafe { self.set(new) };
+            let raw = self.get().raw_obj();
+            if let Some(raw) = raw {
+                state.record(raw, moved);
+            }
             if moved {
+                let root = raw.and_then(|raw| state.set_referrer(Referrer::Object(raw)));
                 self.get().trace(state);
                 // finish tracing anything connected to this object. This will
                 // help them be co-located in memory.
                 state.trace_stack();
+                if let Some(root) = root {
+                    state.set_referrer(root);
+                }
             }
         }
     }
@@ -725,6 +733,73 @@ where
     }
 }
 
+/// The number of arguments an [`ArgBuffer`] holds without allocating.
+pub(crate) const INLINE_ARGS: usize = 8;
+
+/// The arguments of a call while they are being evaluated. Most calls take
+/// only a few arguments, so up to [`INLINE_ARGS`] of them are stored inline
+/// and the buffer only allocates once a call takes more than that.
+pub(crate) struct ArgBuffer<'ob> {
+    inline: [Slot<Object<'ob>>; INLINE_ARGS],
+    len: usize,
+    // Holds all the arguments once there are too many to store inline
+    spilled: Vec<Slot<Object<'ob>>>,
+}
+
+impl Default for ArgBuffer<'_> {
+    fn default() -> Self {
+        use crate::core::object::NIL;
+        Self { inline: std::array::from_fn(|_| Slot::new(NIL)), len: 0, spilled: Vec::new() }
+    }
+}
+
+impl<'ob> ArgBuffer<'ob> {
+    fn as_slice(&self) -> &[Slot<Object<'ob>>] {
+        if self.spilled.is_empty() {
+            &self.inline[..self.len]
+        } else {
+            &self.spilled
+        }
+    }
+}
+
+impl<'a> Rt<ArgBuffer<'a>> {
+    pub(crate) fn push<U: IntoRoot<Slot<Object<'a>>>>(&mut self, item: U) {
+        let buffer = self.inner_mut();
+        let item = unsafe { item.into_root() };
+        if buffer.spilled.is_empty() && buffer.len < INLINE_ARGS {
+            buffer.inline[buffer.len] = item;
+            buffer.len += 1;
+        } else {
+            if buffer.spilled.is_empty() {
+                let inline = buffer.inline.iter().map(|x| Slot::new(*x.get()));
+                buffer.spilled.extend(inline);
+            }
+            buffer.spilled.push(item);
+        }
+    }
+
+    /// Whether the arguments no longer fit inline.
+    pub(crate) fn spilled(&self) -> bool {
+        !self.inner().spilled.is_empty()
+    }
+}
+
+impl<'a> Deref for Rt<ArgBuffer<'a>> {
+    type Target = [Rt<Slot<Object<'a>>>];
+    fn deref(&self) -> &Self::Target {
+        let slice = self.inner().as_slice();
+        // SAFETY: `Rt<T>` has the same memory layout as `T`.
+        unsafe { &*(slice as *const [Slot<Object<'a>>] as *const [Rt<Slot<Object<'a>>>]) }
+    }
+}
+
+impl Trace for ArgBuffer<'_> {
+    fn trace(&self, state: &mut GcState) {
+        self.as_slice().trace(state);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::core::object::NIL;
@@ -776,4 +851,24 @@ mod test {
         let val = map.get(key.bind(cx)).unwrap().bind(cx);
         assert_eq!(val, "val");
     }
+
+    #[test]
+    fn test_arg_buffer() {
+        let root = &RootSet::default();
+        let cx = &mut Context::new(root);
+        root!(args, new(ArgBuffer), cx);
+        for i in 0..INLINE_ARGS {
+            args.push(cx.add(i.to_string()));
+        }
+        assert!(!args.spilled());
+        cx.garbage_collect(true);
+        assert_eq!(args[INLINE_ARGS - 1].bind(cx), &*(INLINE_ARGS - 1).to_string());
+        args.push(cx.add("last"));
+        assert!(args.spilled());
+        cx.garbage_collect(true);
+        let args = Rt::bind_slice(args, cx);
+        assert_eq!(args.len(), INLINE_ARGS + 1);
+        assert_eq!(args[0], "0");
+        assert_eq!(args[INLINE_ARGS], "last");
+    }
 }
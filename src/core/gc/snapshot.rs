@@ -0,0 +1,196 @@
+//! Snapshots of the object graph, for debugging rooting bugs and leaks.
+use super::{Context, Recorder, Referrer};
+use crate::core::object::{format_float, Object, ObjectType, RawObj};
+use rune_core::hashmap::HashMap;
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::mem::{size_of, size_of_val};
+
+/// The longest printed value shown for an object.
+const MAX_VALUE_LEN: usize = 40;
+
+/// An object that survived garbage collection.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ObjectInfo {
+    /// The lisp type of the object, or the type of a record.
+    pub(crate) kind: String,
+    /// The bytes the object itself uses on the heap, not counting the
+    /// objects it refers to.
+    pub(crate) size: usize,
+    /// A short description of atoms, or the length of arrays.
+    pub(crate) value: Option<String>,
+}
+
+/// What refers to an object in a [`HeapSnapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Source {
+    /// A root by its index in the root set.
+    Root(usize),
+    /// An object by its index in [`HeapSnapshot::objects`].
+    Object(usize),
+}
+
+/// The reachable objects and references between them at the end of a
+/// garbage collection. Taken with [`Context::heap_snapshot`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HeapSnapshot {
+    /// The number of roots in the root set.
+    pub(crate) roots: usize,
+    pub(crate) objects: Vec<ObjectInfo>,
+    /// References from a source to an index in `objects`.
+    pub(crate) edges: Vec<(Source, usize)>,
+}
+
+impl HeapSnapshot {
+    pub(in crate::core) fn new(roots: usize, recorder: Recorder, cx: &Context) -> Self {
+        let ids: HashMap<RawObj, usize> =
+            recorder.objects.iter().enumerate().map(|(i, x)| (*x, i)).collect();
+        let objects = recorder
+            .objects
+            .iter()
+            // SAFETY: The recorded objects are the ones that were just moved
+            // to the current heap.
+            .map(|x| describe(cx.bind(unsafe { Object::from_raw(*x) })))
+            .collect();
+        let edges = recorder
+            .edges
+            .iter()
+            .filter_map(|(from, to)| {
+                let from = match from {
+                    Referrer::Root(root) => Source::Root(*root),
+                    Referrer::Object(obj) => Source::Object(*ids.get(obj)?),
+                };
+                Some((from, *ids.get(to)?))
+            })
+            .collect();
+        Self { roots, objects, edges }
+    }
+
+    /// The total shallow size of all objects.
+    pub(crate) fn total_size(&self) -> usize {
+        self.objects.iter().map(|x| x.size).sum()
+    }
+
+    /// Render the snapshot as a Graphviz digraph.
+    pub(crate) fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph heap {\n  node [shape=box];\n");
+        let mut roots: Vec<_> = self
+            .edges
+            .iter()
+            .filter_map(|(from, _)| match from {
+                Source::Root(root) => Some(*root),
+                Source::Object(_) => None,
+            })
+            .collect();
+        roots.sort_unstable();
+        roots.dedup();
+        for root in roots {
+            writeln!(dot, "  r{root} [label=\"root {root}\", shape=ellipse];").unwrap();
+        }
+        for (i, object) in self.objects.iter().enumerate() {
+            let mut label = format!("{}\n{} bytes", object.kind, object.size);
+            if let Some(value) = &object.value {
+                write!(label, "\n{value}").unwrap();
+            }
+            writeln!(dot, "  o{i} [label={label:?}];").unwrap();
+        }
+        for (from, to) in &self.edges {
+            match from {
+                Source::Root(root) => writeln!(dot, "  r{root} -> o{to};").unwrap(),
+                Source::Object(from) => writeln!(dot, "  o{from} -> o{to};").unwrap(),
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the snapshot as JSON. Objects are identified by their index in
+    /// the `objects` array.
+    pub(crate) fn to_json(&self) -> Value {
+        let objects: Vec<_> = self
+            .objects
+            .iter()
+            .map(|x| json!({ "type": x.kind, "size": x.size, "value": x.value }))
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|(from, to)| match from {
+                Source::Root(root) => json!({ "root": root, "to": to }),
+                Source::Object(from) => json!({ "from": from, "to": to }),
+            })
+            .collect();
+        json!({ "roots": self.roots, "objects": objects, "edges": edges })
+    }
+}
+
+fn describe(obj: Object) -> ObjectInfo {
+    let len = |len: usize| Some(format!("length {len}"));
+    let (kind, size, value) = match obj.untag() {
+        ObjectType::Int(_) | ObjectType::SubrFn(_) => unreachable!("immediate value on the heap"),
+        ObjectType::Float(x) => ("float", size_of_val(x), Some(format_float(**x))),
+        ObjectType::Symbol(x) => ("symbol", size_of_val(x.get()), Some(x.name().to_owned())),
+        ObjectType::Cons(x) => ("cons", size_of_val(x), None),
+        ObjectType::Vec(x) => {
+            ("vector", size_of_val(x) + x.len() * size_of::<Object>(), len(x.len()))
+        }
+        ObjectType::Record(x) => {
+            let kind = x.first().map_or_else(|| "record".into(), |x| x.get().to_string());
+            let size = size_of_val(x) + x.len() * size_of::<Object>();
+            return describe_as(kind, size, len(x.len()));
+        }
+        ObjectType::HashTable(x) => {
+            let size = size_of_val(x) + x.len() * 2 * size_of::<Object>();
+            ("hash-table", size, len(x.len()))
+        }
+        ObjectType::String(x) => ("string", size_of_val(x) + x.len(), Some(x.to_string())),
+        ObjectType::ByteString(x) => ("string", size_of_val(x) + x.len(), len(x.len())),
+        ObjectType::ByteFn(x) => ("compiled-function", size_of_val(x), None),
+        ObjectType::Buffer(x) => ("buffer", size_of_val(x), Some(x.to_string())),
+        ObjectType::BoolVector(x) => ("bool-vector", size_of_val(x) + x.len() / 8, len(x.len())),
+        ObjectType::BigInt(x) => ("integer", size_of_val(x), Some(x.to_string())),
+        ObjectType::TreesitNode(x) => ("treesit-node", size_of_val(x), None),
+        ObjectType::Sqlite(x) => ("sqlite", size_of_val(x), None),
+    };
+    describe_as(kind.into(), size, value)
+}
+
+fn describe_as(kind: String, size: usize, value: Option<String>) -> ObjectInfo {
+    let value = value.map(|x| match x.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => format!("{}...", &x[..end]),
+        None => x,
+    });
+    ObjectInfo { kind, size, value }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::{list, root};
+
+    #[test]
+    fn test_snapshot() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let obj = list!["leaf", 1.5; cx];
+        root!(obj, cx);
+        let _ = cx.add("unreachable");
+        let snapshot = cx.heap_snapshot();
+        let kinds: Vec<_> = snapshot.objects.iter().map(|x| x.kind.as_str()).collect();
+        assert_eq!(kinds.len(), 4);
+        assert_eq!(kinds.iter().filter(|x| **x == "cons").count(), 2);
+        assert!(kinds.contains(&"string") && kinds.contains(&"float"));
+
+        let root = snapshot.edges.iter().find(|(from, _)| matches!(from, Source::Root(_)));
+        let head = root.unwrap().1;
+        assert_eq!(snapshot.objects[head].kind, "cons");
+        let leaf = snapshot.objects.iter().position(|x| x.value.as_deref() == Some("leaf"));
+        let leaf = leaf.unwrap();
+        assert!(snapshot.edges.contains(&(Source::Object(head), leaf)));
+        assert!(snapshot.to_dot().contains(&format!("o{head} -> o{leaf};")));
+        assert_eq!(snapshot.to_json()["objects"].as_array().unwrap().len(), 4);
+        // the object is still usable after the snapshot
+        assert_eq!(obj.bind(cx).to_string(), "(\"leaf\" 1.5)");
+    }
+}
@@ -9,11 +9,16 @@ pub(crate) trait Trace {
 pub(crate) struct GcState {
     stack: Vec<RawObj>,
     pub(in crate::core) to_space: bumpalo::Bump,
+    /// Set when taking a heap snapshot, to record the object graph as it is
+    /// traced.
+    pub(in crate::core) recorder: Option<Recorder>,
 }
 
 impl GcState {
-    pub fn new() -> Self {
-        GcState { stack: Vec::new(), to_space: bumpalo::Bump::new() }
+    /// Create the state for a collection that moves live objects into
+    /// `to_space`.
+    pub fn new(to_space: bumpalo::Bump) -> Self {
+        GcState { stack: Vec::new(), to_space, recorder: None }
     }
 
     pub fn push(&mut self, obj: Object) {
@@ -22,10 +27,53 @@ impl GcState {
 
     pub fn trace_stack(&mut self) {
         while let Some(raw) = self.stack.pop() {
+            self.set_referrer(Referrer::Object(raw));
             let obj = unsafe { Object::from_raw(raw) };
             obj.trace(self);
         }
     }
+
+    /// Attribute the references found from now on to `referrer`, returning
+    /// the previous referrer. Does nothing unless taking a heap snapshot.
+    pub(in crate::core) fn set_referrer(&mut self, referrer: Referrer) -> Option<Referrer> {
+        let recorder = self.recorder.as_mut()?;
+        Some(std::mem::replace(&mut recorder.referrer, referrer))
+    }
+
+    /// Record a reference from the object being traced to `obj`, which has
+    /// already been moved to the to-space. `moved` is true the first time
+    /// `obj` is reached.
+    pub(in crate::core) fn record(&mut self, obj: RawObj, moved: bool) {
+        if let Some(recorder) = &mut self.recorder {
+            if moved {
+                recorder.objects.push(obj);
+            }
+            recorder.edges.push((recorder.referrer, obj));
+        }
+    }
+}
+
+/// What holds a reference to an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(in crate::core) enum Referrer {
+    /// An entry in the [`RootSet`](super::RootSet), by index.
+    Root(usize),
+    Object(RawObj),
+}
+
+/// The object graph seen during a garbage collection.
+#[derive(Debug)]
+pub(in crate::core) struct Recorder {
+    pub(in crate::core) referrer: Referrer,
+    /// Every object that survived, at its new address.
+    pub(in crate::core) objects: Vec<RawObj>,
+    pub(in crate::core) edges: Vec<(Referrer, RawObj)>,
+}
+
+impl Recorder {
+    pub(in crate::core) fn new() -> Self {
+        Self { referrer: Referrer::Root(0), objects: Vec::new(), edges: Vec::new() }
+    }
 }
 
 impl Trace for usize {
@@ -9,26 +9,36 @@
 //! aligned. All objects should be bound to a lifetime to ensure sound operation
 //! of the vm.
 
+mod bignum;
+mod bool_vector;
 mod buffer;
 mod cell;
 mod convert;
 mod float;
 mod func;
 mod hashtable;
+mod sequence;
+mod sqlite;
 mod string;
 mod symbol;
 mod tagged;
+mod treesit_node;
 mod vector;
 
+pub(crate) use bignum::*;
+pub(crate) use bool_vector::*;
 pub(crate) use buffer::*;
 pub(super) use cell::*;
 pub(crate) use convert::*;
 pub(crate) use float::*;
 pub(crate) use func::*;
 pub(crate) use hashtable::*;
+pub(crate) use sequence::*;
+pub(crate) use sqlite::*;
 pub(crate) use string::*;
 pub(crate) use symbol::*;
 pub(crate) use tagged::*;
+pub(crate) use treesit_node::*;
 pub(crate) use vector::*;
 
 use std::fmt::Write as _;
@@ -0,0 +1,106 @@
+use super::{CloneIn, Gc, IntoObject};
+use crate::{
+    core::gc::{Block, GcHeap, GcState, Trace},
+    NewtypeMarkable,
+};
+use macro_attr_2018::macro_attr;
+use newtype_derive_2018::*;
+use num_bigint::{BigInt, Sign};
+use rune_macros::Trace;
+use std::{cell::Cell, fmt};
+
+pub(crate) struct BigIntInner {
+    is_const: bool,
+    sign: Sign,
+    // The magnitude of the integer, least significant digit first
+    digits: Cell<*const [u32]>,
+}
+
+macro_attr! {
+    /// An integer that is too large to fit in a fixnum. Bignums are immutable,
+    /// so the digits are stored directly in the GC heap and converted to a
+    /// [`BigInt`] when they are needed for arithmetic.
+    #[derive(PartialEq, Eq, Trace, NewtypeDebug!, NewtypeDisplay!, NewtypeDeref!, NewtypeMarkable!)]
+    pub(crate) struct LispBigInt(GcHeap<BigIntInner>);
+}
+
+impl LispBigInt {
+    // SAFETY: `digits` must be valid for the lifetime of the allocator.
+    pub(in crate::core) unsafe fn new(sign: Sign, digits: *const [u32], constant: bool) -> Self {
+        let inner = BigIntInner { is_const: constant, sign, digits: Cell::new(digits) };
+        Self(GcHeap::new(inner, constant))
+    }
+}
+
+impl BigIntInner {
+    fn digits(&self) -> &[u32] {
+        unsafe { &*self.digits.get() }
+    }
+
+    pub(crate) fn get(&self) -> BigInt {
+        BigInt::from_slice(self.sign, self.digits())
+    }
+}
+
+impl PartialEq for BigIntInner {
+    fn eq(&self, other: &Self) -> bool {
+        self.sign == other.sign && self.digits() == other.digits()
+    }
+}
+
+impl Eq for BigIntInner {}
+
+impl std::hash::Hash for BigIntInner {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.sign.hash(state);
+        self.digits().hash(state);
+    }
+}
+
+impl Trace for BigIntInner {
+    fn trace(&self, state: &mut GcState) {
+        assert!(!self.is_const, "Attempt to trace constant bignum");
+        let new = state.to_space.alloc_slice_copy(self.digits());
+        self.digits.set(new);
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispBigInt {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        self.get().into_obj(bk)
+    }
+}
+
+impl fmt::Display for BigIntInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+impl fmt::Debug for BigIntInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_bignum() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let big: BigInt = "-123456789012345678901234567890".parse().unwrap();
+        let obj: Gc<&LispBigInt> = cx.add_as(big.clone());
+        assert_eq!(obj.untag().get(), big);
+        assert_eq!(obj.to_string(), "-123456789012345678901234567890");
+        let other: Gc<&LispBigInt> = cx.add_as(big.clone());
+        assert_eq!(obj, other);
+        root!(obj, cx);
+        cx.garbage_collect(true);
+        assert_eq!(obj.bind(cx).untag().get(), big);
+    }
+}
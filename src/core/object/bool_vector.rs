@@ -0,0 +1,162 @@
+use super::{CloneIn, Gc, IntoObject};
+use crate::{
+    core::gc::{Block, GcHeap, GcState, Trace},
+    NewtypeMarkable,
+};
+use anyhow::{anyhow, ensure, Result};
+use macro_attr_2018::macro_attr;
+use newtype_derive_2018::*;
+use rune_macros::Trace;
+use std::{cell::Cell, fmt};
+
+pub(super) const WORD_BITS: usize = u64::BITS as usize;
+
+pub(crate) struct BoolVectorInner {
+    is_const: bool,
+    len: usize,
+    // Bits are stored least significant first. Any bits past `len` in the last
+    // word are always zero, so words can be compared and counted directly.
+    words: Cell<*const [Cell<u64>]>,
+}
+
+macro_attr! {
+    /// A fixed size vector of booleans. This is stored as a bitset, so it is
+    /// much more compact than a vector of `t` and `nil`.
+    #[derive(PartialEq, Eq, Trace, NewtypeDebug!, NewtypeDisplay!, NewtypeDeref!, NewtypeMarkable!)]
+    pub(crate) struct BoolVector(GcHeap<BoolVectorInner>);
+}
+
+impl BoolVector {
+    // SAFETY: `words` must be valid for the lifetime of the allocator.
+    pub(in crate::core) unsafe fn new(
+        words: *const [Cell<u64>],
+        len: usize,
+        constant: bool,
+    ) -> Self {
+        let inner = BoolVectorInner { is_const: constant, len, words: Cell::new(words) };
+        Self(GcHeap::new(inner, constant))
+    }
+}
+
+impl BoolVectorInner {
+    fn words(&self) -> &[Cell<u64>] {
+        unsafe { &*self.words.get() }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Option<bool> {
+        if idx < self.len {
+            let word = self.words()[idx / WORD_BITS].get();
+            Some(word & (1 << (idx % WORD_BITS)) != 0)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn set(&self, idx: usize, value: bool) -> Result<()> {
+        ensure!(!self.is_const, "Attempt to mutate constant bool-vector");
+        if idx >= self.len {
+            let len = self.len;
+            return Err(anyhow!("index {idx} is out of bounds. Length was {len}"));
+        }
+        let cell = &self.words()[idx / WORD_BITS];
+        let mask = 1 << (idx % WORD_BITS);
+        cell.set(if value { cell.get() | mask } else { cell.get() & !mask });
+        Ok(())
+    }
+
+    /// The number of true elements.
+    pub(crate) fn count(&self) -> usize {
+        self.words().iter().map(|x| x.get().count_ones() as usize).sum()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|i| self.get(i).unwrap())
+    }
+}
+
+impl PartialEq for BoolVectorInner {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.words() == other.words()
+    }
+}
+
+impl Eq for BoolVectorInner {}
+
+impl Trace for BoolVectorInner {
+    fn trace(&self, state: &mut GcState) {
+        assert!(!self.is_const, "Attempt to trace constant bool-vector");
+        let words = self.words().iter().map(|x| Cell::new(x.get()));
+        let new = state.to_space.alloc_slice_fill_iter(words);
+        self.words.set(new);
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for BoolVector {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        self.iter().collect::<Vec<bool>>().into_obj(bk)
+    }
+}
+
+impl fmt::Display for BoolVectorInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#&{}\"", self.len)?;
+        let bytes = self.words().iter().flat_map(|x| x.get().to_le_bytes());
+        for byte in bytes.take(self.len.div_ceil(8)) {
+            match byte {
+                b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+                b' '..=b'~' => write!(f, "{}", byte as char)?,
+                _ => write!(f, "\\{byte:03o}")?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+impl fmt::Debug for BoolVectorInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_bool_vector() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let bits: Vec<bool> = (0..70).map(|i| i % 3 == 0).collect();
+        let vec: Gc<&BoolVector> = cx.add_as(bits.clone());
+        assert_eq!(vec.untag().len(), 70);
+        assert_eq!(vec.untag().count(), 24);
+        assert!(vec.untag().iter().eq(bits.iter().copied()));
+        vec.untag().set(1, true).unwrap();
+        assert_eq!(vec.untag().get(1), Some(true));
+        assert_eq!(vec.untag().get(70), None);
+        assert!(vec.untag().set(70, true).is_err());
+        root!(vec, cx);
+        cx.garbage_collect(true);
+        assert_eq!(vec.bind(cx).untag().get(1), Some(true));
+        assert_eq!(vec.bind(cx).untag().count(), 25);
+    }
+
+    #[test]
+    fn test_bool_vector_display() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(cx.add(vec![true; 5]).to_string(), "#&5\"\\037\"");
+        assert_eq!(cx.add(vec![false, true, true, true, true, true, true]).to_string(), "#&7\"~\"");
+        assert_eq!(cx.add(Vec::<bool>::new()).to_string(), "#&0\"\"");
+    }
+}
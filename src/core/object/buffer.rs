@@ -1,9 +1,11 @@
 use super::{Gc, Object, ObjectType, TagType, WithLifetime};
 use crate::{
+    coding::Eol,
     core::{
         error::{Type, TypeError},
         gc::{Block, Context, GcHeap, GcState, Trace},
     },
+    syntax::{ParseCache, SyntaxTable},
     NewtypeMarkable,
 };
 use anyhow::{bail, Result};
@@ -14,6 +16,7 @@ use std::{
     fmt::Display,
     ops::{Deref, DerefMut},
     sync::{Mutex, MutexGuard},
+    time::SystemTime,
 };
 use text_buffer::Buffer as TextBuffer;
 
@@ -114,6 +117,58 @@ impl DerefMut for OpenBuffer<'_> {
 pub(crate) struct BufferData {
     pub(crate) name: String,
     pub(crate) text: TextBuffer,
+    /// The absolute name of the file this buffer is visiting.
+    pub(crate) file_name: Option<String>,
+    /// The modification time of the visited file when it was last read or
+    /// written.
+    pub(crate) modtime: Option<SystemTime>,
+    /// A checksum of the contents of the visited file when it was last read
+    /// or written.
+    pub(crate) checksum: Option<u64>,
+    /// The modified tick of the text when it was last saved, or `None` if the
+    /// buffer was explicitly marked as modified.
+    pub(crate) save_tick: Option<usize>,
+    /// Whether the visited file was backed up before it was first saved.
+    pub(crate) backed_up: bool,
+    /// The file the buffer is auto-saved to, if auto-saving is enabled.
+    pub(crate) auto_save_file_name: Option<String>,
+    /// The modified tick of the text when it was last auto-saved.
+    pub(crate) auto_save_tick: usize,
+    /// The line endings used to write the visited file.
+    pub(crate) eol: Eol,
+    /// The syntax classes of characters in this buffer.
+    pub(crate) syntax: SyntaxTable,
+    /// The last parse of the text with the syntax table.
+    pub(crate) parse: Option<ParseCache>,
+}
+
+impl BufferData {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            text: TextBuffer::new(),
+            file_name: None,
+            modtime: None,
+            checksum: None,
+            save_tick: Some(0),
+            backed_up: false,
+            auto_save_file_name: None,
+            auto_save_tick: 0,
+            eol: Eol::default(),
+            syntax: SyntaxTable::default(),
+            parse: None,
+        }
+    }
+
+    /// Whether the text was changed since the buffer was last saved.
+    pub(crate) fn is_modified(&self) -> bool {
+        self.save_tick != Some(self.text.modified_tick())
+    }
+
+    /// Mark the current text as saved.
+    pub(crate) fn set_unmodified(&mut self) {
+        self.save_tick = Some(self.text.modified_tick());
+    }
 }
 
 #[derive(Debug)]
@@ -135,9 +190,7 @@ impl LispBuffer {
     }
 
     pub(crate) unsafe fn new(name: String, _: &Block<true>) -> LispBuffer {
-        let new = LispBufferInner {
-            text_buffer: Mutex::new(Some(BufferData { name, text: TextBuffer::new() })),
-        };
+        let new = LispBufferInner { text_buffer: Mutex::new(Some(BufferData::new(name))) };
         Self(GcHeap::new(new, true))
     }
 
@@ -148,6 +201,16 @@ impl LispBuffer {
         }
         Ok(OpenBuffer { data: guard, back_ref: self })
     }
+
+    /// Like `lock`, but returns `None` instead of waiting if the buffer is
+    /// open on another thread, or if it was killed.
+    pub(in crate::core) fn try_lock(&self) -> Option<OpenBuffer<'_>> {
+        let guard = self.text_buffer.try_lock().ok()?;
+        if guard.is_none() {
+            return None;
+        }
+        Some(OpenBuffer { data: guard, back_ref: self })
+    }
 }
 
 impl PartialEq for LispBufferInner {
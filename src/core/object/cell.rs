@@ -53,6 +53,7 @@ impl Trace for ObjCell {
         let cell = unsafe { self.as_mut() };
         if let Some((new, moved)) = cell.get().move_value(&state.to_space) {
             cell.set(new);
+            state.record(new.into_raw(), moved);
             if moved {
                 state.push(new);
             }
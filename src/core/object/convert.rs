@@ -6,7 +6,8 @@ use crate::data::LispError;
 
 use super::{
     super::error::{Type, TypeError},
-    ByteString, LispHashTable, LispString, LispVec, OptionalFlag, NIL, TRUE,
+    BoolVector, ByteString, LispHashTable, LispSqlite, LispString, LispTreesitNode, LispVec,
+    OptionalFlag, NIL, TRUE,
 };
 use super::{Gc, LispFloat, Object, ObjectType, Symbol};
 use anyhow::Context;
@@ -128,6 +129,9 @@ define_unbox!(HashTable, &'ob LispHashTable);
 define_unbox!(String, &'ob LispString);
 define_unbox!(ByteString, String, &'ob ByteString);
 define_unbox!(Vec, &'ob LispVec);
+define_unbox!(BoolVector, &'ob BoolVector);
+define_unbox!(TreesitNode, &'ob LispTreesitNode);
+define_unbox!(Sqlite, &'ob LispSqlite);
 define_unbox!(Symbol, Symbol<'ob>);
 
 impl<'ob, T> From<Option<T>> for Object<'ob>
@@ -33,19 +33,69 @@ impl<'new> CloneIn<'new, &'new LispFloat> for LispFloat {
     }
 }
 
-impl Display for LispFloat {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let float = **self;
-        if float.fract() == 0.0_f64 {
-            write!(f, "{float:.1}")
+/// Format a float the way Emacs prints it. This is the shortest string that
+/// reads back as the same value, and it always has a decimal point or an
+/// exponent so that it reads as a float.
+pub(crate) fn format_float(float: f64) -> String {
+    if float.is_nan() {
+        let sign = if float.is_sign_negative() { "-" } else { "" };
+        return format!("{sign}0.0e+NaN");
+    }
+    if float.is_infinite() {
+        let sign = if float.is_sign_negative() { "-" } else { "" };
+        return format!("{sign}1.0e+INF");
+    }
+    // LowerExp uses the shortest digits that round trip (e.g. "-1.25e-7")
+    let sci = format!("{float:e}");
+    let (mantissa, exp) = sci.split_once('e').expect("float exponent missing");
+    let exp: i32 = exp.parse().expect("float exponent was not an integer");
+    let digits = mantissa.chars().filter(char::is_ascii_digit).count() as i32;
+    // Emacs uses "%.Ng", where N is the smallest precision of at least 15 that
+    // round trips. That switches to exponent notation outside of this range.
+    if exp < -4 || exp >= digits.max(15) {
+        let sign = if exp < 0 { '-' } else { '+' };
+        format!("{mantissa}e{sign}{:02}", exp.abs())
+    } else {
+        let fixed = format!("{float}");
+        if fixed.contains('.') {
+            fixed
         } else {
-            write!(f, "{float}")
+            fixed + ".0"
         }
     }
 }
 
+impl Display for LispFloat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&format_float(**self))
+    }
+}
+
 impl Debug for LispFloat {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{self}")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::format_float;
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(0.1), "0.1");
+        assert_eq!(format_float(1.0), "1.0");
+        assert_eq!(format_float(-0.0), "-0.0");
+        assert_eq!(format_float(100_000.0), "100000.0");
+        assert_eq!(format_float(123_456_789_012_345.0), "123456789012345.0");
+        assert_eq!(format_float(1e15), "1e+15");
+        assert_eq!(format_float(1.5e16), "1.5e+16");
+        assert_eq!(format_float(1.234_567_890_123_456_7e16), "12345678901234568.0");
+        assert_eq!(format_float(0.0001), "0.0001");
+        assert_eq!(format_float(0.000_012_5), "1.25e-05");
+        assert_eq!(format_float(1e-300), "1e-300");
+        assert_eq!(format_float(f64::INFINITY), "1.0e+INF");
+        assert_eq!(format_float(f64::NEG_INFINITY), "-1.0e+INF");
+        assert_eq!(format_float(f64::NAN), "0.0e+NaN");
+    }
+}
@@ -172,6 +172,9 @@ pub(crate) type BuiltInFn =
 #[derive(Eq)]
 pub(crate) struct SubrFn {
     pub(crate) subr: BuiltInFn,
+    /// The number of positional arguments the wrapped Rust function takes,
+    /// which `args` has to agree with.
+    pub(crate) params: u16,
     pub(crate) args: FnArgs,
     pub(crate) name: &'static str,
 }
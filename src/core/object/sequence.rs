@@ -0,0 +1,74 @@
+//! Operations shared by all sequence types.
+use super::{
+    BoolVector, ByteFn, ByteString, LispHashTable, LispString, LispVec, Object, ObjectType,
+};
+use crate::core::{
+    cons::Cons,
+    error::{Type, TypeError},
+};
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+/// An object made up of some number of elements. This is implemented for lists,
+/// arrays, and hash tables, and for [`Object`] by dispatching on the type.
+pub(crate) trait Sequence {
+    /// The number of elements in the sequence.
+    fn seq_len(&self) -> Result<usize>;
+
+    /// Compare the length of the sequence to `n`. This is cheaper than
+    /// [`seq_len`](Self::seq_len) for lists, because they only need to be
+    /// walked up to `n` elements.
+    fn seq_len_cmp(&self, n: usize) -> Result<Ordering> {
+        Ok(self.seq_len()?.cmp(&n))
+    }
+}
+
+impl Sequence for Cons {
+    fn seq_len(&self) -> Result<usize> {
+        Ok(self.elements().len()?)
+    }
+
+    fn seq_len_cmp(&self, n: usize) -> Result<Ordering> {
+        let mut len = 0;
+        for elem in self.elements().take(n.saturating_add(1)) {
+            elem?;
+            len += 1;
+        }
+        Ok(len.cmp(&n))
+    }
+}
+
+macro_rules! sequence_len {
+    ($($ty:ty),+) => {$(
+        impl Sequence for $ty {
+            fn seq_len(&self) -> Result<usize> {
+                Ok(self.len())
+            }
+        }
+    )+};
+}
+
+sequence_len!(LispVec, LispString, ByteString, ByteFn, BoolVector, LispHashTable);
+
+impl Sequence for Object<'_> {
+    fn seq_len(&self) -> Result<usize> {
+        match self.untag() {
+            ObjectType::NIL => Ok(0),
+            ObjectType::Cons(x) => x.seq_len(),
+            ObjectType::Vec(x) => x.seq_len(),
+            ObjectType::String(x) => x.seq_len(),
+            ObjectType::ByteString(x) => x.seq_len(),
+            ObjectType::ByteFn(x) => x.seq_len(),
+            ObjectType::BoolVector(x) => x.seq_len(),
+            ObjectType::HashTable(x) => x.seq_len(),
+            obj => bail!(TypeError::new(Type::Sequence, obj)),
+        }
+    }
+
+    fn seq_len_cmp(&self, n: usize) -> Result<Ordering> {
+        match self.untag() {
+            ObjectType::Cons(x) => x.seq_len_cmp(n),
+            _ => Ok(self.seq_len()?.cmp(&n)),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use super::{CloneIn, Gc, IntoObject};
+use crate::{
+    core::gc::{Block, GcHeap, GcState, Trace},
+    NewtypeMarkable,
+};
+use macro_attr_2018::macro_attr;
+use newtype_derive_2018::*;
+use rune_macros::Trace;
+use std::{fmt, ptr::NonNull};
+
+/// The id of an open database, used to create a [`LispSqlite`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct SqliteHandle(pub(crate) i64);
+
+#[derive(PartialEq, Eq)]
+pub(crate) struct SqliteInner {
+    id: i64,
+}
+
+macro_attr! {
+    /// A handle to an SQLite database. The connection itself lives in a
+    /// registry keyed by the id. The allocating block keeps track of every
+    /// handle, and when one is garbage collected its connection is released
+    /// by [`crate::sqlite::finalize`].
+    #[derive(PartialEq, Eq, Trace, NewtypeDebug!, NewtypeDisplay!, NewtypeDeref!, NewtypeMarkable!)]
+    pub(crate) struct LispSqlite(GcHeap<SqliteInner>);
+}
+
+impl LispSqlite {
+    pub(in crate::core) fn new(id: i64, constant: bool) -> Self {
+        Self(GcHeap::new(SqliteInner { id }, constant))
+    }
+
+    pub(in crate::core) fn forwarding_ptr(&self) -> Option<NonNull<u8>> {
+        use crate::core::gc::AllocState as A;
+        match self.0.allocation_state() {
+            A::Forwarded(f) => Some(f),
+            A::Global => panic!("global sqlite allocation found in local heap"),
+            A::Unmoved => None,
+        }
+    }
+}
+
+impl SqliteInner {
+    pub(crate) fn id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl Trace for SqliteInner {
+    fn trace(&self, _: &mut GcState) {}
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispSqlite {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        // the copy is finalized separately
+        crate::sqlite::retain(self.id);
+        SqliteHandle(self.id).into_obj(bk)
+    }
+}
+
+impl fmt::Display for SqliteInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#<sqlite {}>", self.id)
+    }
+}
+
+impl fmt::Debug for SqliteInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
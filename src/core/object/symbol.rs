@@ -8,10 +8,10 @@ use std::cell::Cell;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
-use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 
 mod sealed {
-    use super::{AtomicBool, AtomicPtr, SymbolName};
+    use super::{AtomicBool, AtomicPtr, FunctionCache, SymbolName};
 
     pub(crate) struct SymbolCellInner {
         pub(super) name: SymbolName,
@@ -19,6 +19,13 @@ mod sealed {
         // https://github.com/crossbeam-rs/crossbeam/issues/748
         pub(super) func: Option<AtomicPtr<u8>>,
         pub(super) special: AtomicBool,
+        /// Whether the variable has watchers that must be called before it
+        /// changes.
+        pub(super) trapped_write: AtomicBool,
+        /// The function at the end of the chain of aliases starting at this
+        /// symbol, as of some [`FUNCTION_CELL_VERSION`](super::FUNCTION_CELL_VERSION).
+        pub(super) func_cache: FunctionCache,
+        pub(super) keyword: bool,
     }
 
     impl SymbolCellInner {
@@ -41,6 +48,64 @@ use super::Function;
 /// need to halt all running threads. This has not been implemented yet.
 pub(crate) type SymbolCell = GcHeap<SymbolCellInner>;
 
+/// Incremented whenever the function cell of any symbol changes, which
+/// invalidates the function cached by every symbol. Changing a function is
+/// rare compared to calling one, so this is simpler than tracking which
+/// aliases lead to each symbol.
+static FUNCTION_CELL_VERSION: AtomicUsize = AtomicUsize::new(1);
+
+/// The function a symbol resolved to the last time it was called, so that
+/// calling it again can skip following its aliases. This is a seqlock: `seq`
+/// is odd while the cache is being written, and readers throw away anything
+/// they read while it changed. Threads that find the cache busy don't wait and
+/// just skip it.
+pub(in crate::core) struct FunctionCache {
+    seq: AtomicUsize,
+    version: AtomicUsize,
+    func: AtomicPtr<u8>,
+}
+
+impl FunctionCache {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            version: AtomicUsize::new(0),
+            func: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    /// Return the cached function if it is still valid. The outer `None`
+    /// means the cache missed, and the inner one that the symbol has no
+    /// function.
+    fn get(&self, version: usize) -> Option<Option<*const u8>> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq & 1 == 1 {
+            return None;
+        }
+        let cached_version = self.version.load(Ordering::Acquire);
+        let func = self.func.load(Ordering::Acquire);
+        if self.seq.load(Ordering::Acquire) != seq || cached_version != version {
+            return None;
+        }
+        Some((!func.is_null()).then_some(func.cast_const()))
+    }
+
+    fn set(&self, version: usize, func: Option<*const u8>) {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq & 1 == 1
+            || self
+                .seq
+                .compare_exchange(seq, seq + 1, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        self.version.store(version, Ordering::Release);
+        self.func.store(func.unwrap_or(std::ptr::null()).cast_mut(), Ordering::Release);
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
 #[derive(Debug)]
 enum SymbolName {
     Interned(&'static str),
@@ -113,6 +178,15 @@ impl<'a> Symbol<'a> {
     pub(crate) fn is_special(self) -> bool {
         self.special.load(Ordering::Acquire)
     }
+
+    pub(crate) fn set_trapped_write(self, trapped: bool) {
+        self.trapped_write.store(trapped, Ordering::Release);
+    }
+
+    /// Whether the variable has watchers. See [`crate::watchers`].
+    pub(crate) fn is_trapped_write(self) -> bool {
+        self.trapped_write.load(Ordering::Acquire)
+    }
 }
 
 unsafe impl Send for Symbol<'_> {}
@@ -139,6 +213,11 @@ impl<'a> Markable for Symbol<'a> {
         let val = self.get().move_value(to_space);
         val.map(|(ptr, moved)| (unsafe { Self::from_ptr(ptr.as_ptr()) }, moved))
     }
+
+    fn raw_obj(&self) -> Option<super::RawObj> {
+        let obj: super::Object = (*self).into();
+        Some(obj.into_raw())
+    }
 }
 
 impl Trace for SymbolCellInner {
@@ -224,13 +303,16 @@ impl SymbolCell {
     fn new_normal(name: &'static str, block: &Block<true>) -> Self {
         // We have to do this workaround because starts_with is not const
         if name.as_bytes()[0] == b':' {
-            Self::new_const(name, block)
+            Self::new_keyword(name, block)
         } else {
             GcHeap::new(
                 SymbolCellInner {
                     name: SymbolName::Interned(name),
                     func: Some(Self::EMTPTY),
                     special: AtomicBool::new(false),
+                    trapped_write: AtomicBool::new(false),
+                    func_cache: FunctionCache::new(),
+                    keyword: false,
                 },
                 true,
             )
@@ -240,12 +322,15 @@ impl SymbolCell {
     pub(in crate::core) const fn new_static(name: &'static str) -> Self {
         // We have to do this workaround because starts_with is not const
         if name.as_bytes()[0] == b':' {
-            Self::new_static_const(name)
+            Self::new_static_keyword(name)
         } else {
             GcHeap::new_pure(SymbolCellInner {
                 name: SymbolName::Interned(name),
                 func: Some(Self::EMTPTY),
                 special: AtomicBool::new(false),
+                trapped_write: AtomicBool::new(false),
+                func_cache: FunctionCache::new(),
+                keyword: false,
             })
         }
     }
@@ -255,25 +340,47 @@ impl SymbolCell {
             name: SymbolName::Interned(name),
             func: Some(Self::EMTPTY),
             special: AtomicBool::new(true),
+            trapped_write: AtomicBool::new(false),
+            func_cache: FunctionCache::new(),
+            keyword: false,
         })
     }
 
-    fn new_const(name: &'static str, _block: &Block<true>) -> Self {
+    /// Keywords are constant and evaluate to themselves. They have no function
+    /// cell and can't be bound as variables.
+    fn new_keyword(name: &'static str, _block: &Block<true>) -> Self {
         GcHeap::new(
             SymbolCellInner {
                 name: SymbolName::Interned(name),
                 func: None,
                 special: AtomicBool::new(true),
+                trapped_write: AtomicBool::new(false),
+                func_cache: FunctionCache::new(),
+                keyword: true,
             },
             true,
         )
     }
 
+    const fn new_static_keyword(name: &'static str) -> Self {
+        GcHeap::new_pure(SymbolCellInner {
+            name: SymbolName::Interned(name),
+            func: None,
+            special: AtomicBool::new(true),
+            trapped_write: AtomicBool::new(false),
+            func_cache: FunctionCache::new(),
+            keyword: true,
+        })
+    }
+
     pub(in crate::core) const fn new_static_const(name: &'static str) -> Self {
         GcHeap::new_pure(SymbolCellInner {
             name: SymbolName::Interned(name),
             func: None,
             special: AtomicBool::new(true),
+            trapped_write: AtomicBool::new(false),
+            func_cache: FunctionCache::new(),
+            keyword: false,
         })
     }
 
@@ -286,6 +393,9 @@ impl SymbolCell {
                 name: SymbolName::Uninterned(Cell::new(name)),
                 func: Some(Self::EMTPTY),
                 special: AtomicBool::new(false),
+                trapped_write: AtomicBool::new(false),
+                func_cache: FunctionCache::new(),
+                keyword: false,
             },
             C,
         )
@@ -312,6 +422,11 @@ impl SymbolCellInner {
         self.func.is_none()
     }
 
+    /// Check if the symbol is an interned keyword like `:foo'.
+    pub(crate) fn is_keyword(&self) -> bool {
+        self.keyword
+    }
+
     pub(crate) fn has_func(&self) -> bool {
         match &self.func {
             Some(func) => !func.load(Ordering::Acquire).is_null(),
@@ -335,10 +450,23 @@ impl SymbolCellInner {
     }
 
     /// Follow the chain of symbols to find the function at the end, if any.
+    /// The result is cached until the function of any symbol changes.
     pub(crate) fn follow_indirect<'ob>(&self, cx: &'ob Context) -> Option<Function<'ob>> {
+        // Read the version first, so a function changed while we follow the
+        // chain leaves the cache stale rather than wrong.
+        let version = FUNCTION_CELL_VERSION.load(Ordering::Acquire);
+        if let Some(func) = self.func_cache.get(version) {
+            return func.map(|ptr| unsafe { Gc::from_raw_ptr(ptr.cast_mut()) });
+        }
+        let func = self.follow_indirect_uncached(cx);
+        self.func_cache.set(version, func.map(|x| x.into_ptr()));
+        func
+    }
+
+    fn follow_indirect_uncached<'ob>(&self, cx: &'ob Context) -> Option<Function<'ob>> {
         let func = self.func(cx)?;
         match func.untag() {
-            FunctionType::Symbol(sym) => sym.follow_indirect(cx),
+            FunctionType::Symbol(sym) => sym.follow_indirect_uncached(cx),
             _ => Some(func),
         }
     }
@@ -354,12 +482,14 @@ impl SymbolCellInner {
         };
         let val = func.into_ptr().cast_mut();
         fn_cell.store(val, Ordering::Release);
+        FUNCTION_CELL_VERSION.fetch_add(1, Ordering::AcqRel);
         Ok(())
     }
 
     pub(crate) fn unbind_func(&self) {
         if let Some(func) = &self.func {
             func.store(Self::NULL, Ordering::Release);
+            FUNCTION_CELL_VERSION.fetch_add(1, Ordering::AcqRel);
         }
     }
 }
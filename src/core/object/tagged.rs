@@ -5,7 +5,8 @@ use super::{
         error::{Type, TypeError},
         gc::Block,
     },
-    ByteFnPrototype, ByteString, GcString, LispBuffer,
+    BoolVector, ByteFnPrototype, ByteString, GcString, LispBigInt, LispBuffer, LispSqlite,
+    LispTreesitNode, SqliteHandle, TreesitNode, WORD_BITS,
 };
 use super::{
     ByteFn, HashTable, LispFloat, LispHashTable, LispString, LispVec, Record, RecordBuilder,
@@ -16,13 +17,15 @@ use crate::core::{
     gc::{DropStackElem, GcState, Markable, Trace},
 };
 use bumpalo::collections::Vec as GcVec;
+use num_bigint::BigInt;
 use private::{Tag, TaggedPtr};
 use rune_core::hashmap::HashSet;
 use sptr::Strict;
+use std::cell::Cell;
 use std::marker::PhantomData;
 use std::{fmt, ptr::NonNull};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) struct RawObj {
     ptr: *const u8,
 }
@@ -288,6 +291,10 @@ object_trait_impls!(LispVec);
 object_trait_impls!(Record);
 object_trait_impls!(LispHashTable);
 object_trait_impls!(LispBuffer);
+object_trait_impls!(BoolVector);
+object_trait_impls!(LispBigInt);
+object_trait_impls!(LispTreesitNode);
+object_trait_impls!(LispSqlite);
 
 /// Trait for types that can be managed by the GC. This trait is implemented for
 /// as many types as possible, even for types that are already Gc managed, Like
@@ -480,14 +487,80 @@ impl IntoObject for RecordBuilder<'_> {
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
+            let handle = match self.0[..] {
+                [tag, id] => crate::handle::retain(tag, id),
+                _ => false,
+            };
             // record is the same layout as lispvec, just a different newtype wrapper
             let ptr = self.0.into_bump_slice_mut() as *mut [Object];
             let ptr = block.objects.alloc(LispVec::new(ptr, C));
+            // records in the constant heap are never collected
+            if handle && !C {
+                block
+                    .handles
+                    .borrow_mut()
+                    .push(std::ptr::from_ref::<LispVec>(ptr).cast::<Record>());
+            }
             <&Record>::tag_ptr(ptr)
         }
     }
 }
 
+impl IntoObject for Vec<bool> {
+    type Out<'ob> = &'ob BoolVector;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let mut words = GcVec::with_capacity_in(self.len().div_ceil(WORD_BITS), &block.objects);
+        for chunk in self.chunks(WORD_BITS) {
+            let word = chunk.iter().rev().fold(0, |acc, bit| (acc << 1) | u64::from(*bit));
+            words.push(Cell::new(word));
+        }
+        unsafe {
+            let ptr = words.into_bump_slice() as *const [Cell<u64>];
+            let ptr = block.objects.alloc(BoolVector::new(ptr, self.len(), C));
+            <&BoolVector>::tag_ptr(ptr)
+        }
+    }
+}
+
+impl IntoObject for BigInt {
+    type Out<'ob> = &'ob LispBigInt;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let (sign, digits) = self.to_u32_digits();
+        unsafe {
+            let ptr = block.objects.alloc_slice_copy(&digits) as *const [u32];
+            let ptr = block.objects.alloc(LispBigInt::new(sign, ptr, C));
+            <&LispBigInt>::tag_ptr(ptr)
+        }
+    }
+}
+
+impl IntoObject for TreesitNode {
+    type Out<'ob> = &'ob LispTreesitNode;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        unsafe {
+            let path = block.objects.alloc_slice_copy(&self.path) as *const [u32];
+            let ptr =
+                block.objects.alloc(LispTreesitNode::new(self.parser, self.generation, path, C));
+            <&LispTreesitNode>::tag_ptr(ptr)
+        }
+    }
+}
+
+impl IntoObject for SqliteHandle {
+    type Out<'ob> = &'ob LispSqlite;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        unsafe {
+            let ptr = block.objects.alloc(LispSqlite::new(self.0, C));
+            block.sqlite_handles.borrow_mut().push(ptr);
+            <&LispSqlite>::tag_ptr(ptr)
+        }
+    }
+}
+
 impl IntoObject for HashTable<'_> {
     type Out<'ob> = &'ob LispHashTable;
 
@@ -518,6 +591,10 @@ mod private {
         SubrFn,
         ByteFn,
         Buffer,
+        BoolVector,
+        BigInt,
+        TreesitNode,
+        Sqlite,
     }
 
     /// Trait for tagged pointers. Anything that can be stored and passed around
@@ -624,6 +701,10 @@ impl<'a> TaggedPtr for ObjectType<'a> {
                 Tag::Record => ObjectType::Record(<&Record>::from_obj_ptr(ptr)),
                 Tag::HashTable => ObjectType::HashTable(<&LispHashTable>::from_obj_ptr(ptr)),
                 Tag::Buffer => ObjectType::Buffer(<&LispBuffer>::from_obj_ptr(ptr)),
+                Tag::BoolVector => ObjectType::BoolVector(<&BoolVector>::from_obj_ptr(ptr)),
+                Tag::BigInt => ObjectType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
+                Tag::TreesitNode => ObjectType::TreesitNode(<&LispTreesitNode>::from_obj_ptr(ptr)),
+                Tag::Sqlite => ObjectType::Sqlite(<&LispSqlite>::from_obj_ptr(ptr)),
             }
         }
     }
@@ -642,6 +723,10 @@ impl<'a> TaggedPtr for ObjectType<'a> {
             ObjectType::ByteFn(x) => TaggedPtr::tag(x).into(),
             ObjectType::SubrFn(x) => TaggedPtr::tag(x).into(),
             ObjectType::Buffer(x) => TaggedPtr::tag(x).into(),
+            ObjectType::BoolVector(x) => TaggedPtr::tag(x).into(),
+            ObjectType::BigInt(x) => TaggedPtr::tag(x).into(),
+            ObjectType::TreesitNode(x) => TaggedPtr::tag(x).into(),
+            ObjectType::Sqlite(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
@@ -717,6 +802,7 @@ impl<'a> TaggedPtr for NumberType<'a> {
             match tag {
                 Tag::Int => NumberType::Int(i64::from_obj_ptr(ptr)),
                 Tag::Float => NumberType::Float(<&LispFloat>::from_obj_ptr(ptr)),
+                Tag::BigInt => NumberType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
                 _ => unreachable!(),
             }
         }
@@ -726,12 +812,13 @@ impl<'a> TaggedPtr for NumberType<'a> {
         match self {
             NumberType::Int(x) => TaggedPtr::tag(x).into(),
             NumberType::Float(x) => TaggedPtr::tag(x).into(),
+            NumberType::BigInt(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
 
-const MAX_FIXNUM: i64 = i64::MAX >> 8;
-const MIN_FIXNUM: i64 = i64::MIN >> 8;
+pub(crate) const MAX_FIXNUM: i64 = i64::MAX >> 8;
+pub(crate) const MIN_FIXNUM: i64 = i64::MIN >> 8;
 
 impl TaggedPtr for i64 {
     type Ptr = i64;
@@ -884,6 +971,54 @@ impl TaggedPtr for &LispHashTable {
     }
 }
 
+impl TaggedPtr for &BoolVector {
+    type Ptr = BoolVector;
+    const TAG: Tag = Tag::BoolVector;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispBigInt {
+    type Ptr = LispBigInt;
+    const TAG: Tag = Tag::BigInt;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispTreesitNode {
+    type Ptr = LispTreesitNode;
+    const TAG: Tag = Tag::TreesitNode;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispSqlite {
+    type Ptr = LispSqlite;
+    const TAG: Tag = Tag::Sqlite;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
 impl TaggedPtr for &LispBuffer {
     type Ptr = LispBuffer;
     const TAG: Tag = Tag::Buffer;
@@ -925,8 +1060,9 @@ macro_rules! cast_gc {
 pub(crate) enum NumberType<'ob> {
     Int(i64) = Tag::Int as u8,
     Float(&'ob LispFloat) = Tag::Float as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
 }
-cast_gc!(NumberType<'ob> => i64, &LispFloat);
+cast_gc!(NumberType<'ob> => i64, &LispFloat, &LispBigInt);
 
 /// Represents a tagged pointer to a number value
 pub(crate) type Number<'ob> = Gc<NumberType<'ob>>;
@@ -1008,6 +1144,10 @@ pub(crate) enum ObjectType<'ob> {
     ByteFn(&'ob ByteFn) = Tag::ByteFn as u8,
     SubrFn(&'static SubrFn) = Tag::SubrFn as u8,
     Buffer(&'static LispBuffer) = Tag::Buffer as u8,
+    BoolVector(&'ob BoolVector) = Tag::BoolVector as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
+    TreesitNode(&'ob LispTreesitNode) = Tag::TreesitNode as u8,
+    Sqlite(&'ob LispSqlite) = Tag::Sqlite as u8,
 }
 
 /// The Object defintion that contains all other possible lisp objects. This
@@ -1028,7 +1168,11 @@ cast_gc!(ObjectType<'ob> => NumberType<'ob>,
          &'ob ByteString,
          &'ob ByteFn,
          &'ob SubrFn,
-         &'ob LispBuffer
+         &'ob LispBuffer,
+         &'ob BoolVector,
+         &'ob LispBigInt,
+         &'ob LispTreesitNode,
+         &'ob LispSqlite
 );
 
 impl ObjectType<'_> {
@@ -1048,6 +1192,10 @@ impl ObjectType<'_> {
             ObjectType::ByteString(_) => Type::String,
             ObjectType::ByteFn(_) | ObjectType::SubrFn(_) => Type::Func,
             ObjectType::Buffer(_) => Type::Buffer,
+            ObjectType::BoolVector(_) => Type::BoolVector,
+            ObjectType::BigInt(_) => Type::Int,
+            ObjectType::TreesitNode(_) => Type::TreesitNode,
+            ObjectType::Sqlite(_) => Type::Sqlite,
         }
     }
 }
@@ -1136,7 +1284,7 @@ impl<'ob> TryFrom<Object<'ob>> for Number<'ob> {
 
     fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
         match value.get_tag() {
-            Tag::Int | Tag::Float => unsafe { Ok(cast_gc(value)) },
+            Tag::Int | Tag::Float | Tag::BigInt => unsafe { Ok(cast_gc(value)) },
             _ => Err(TypeError::new(Type::Number, value)),
         }
     }
@@ -1286,6 +1434,39 @@ impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispVec> {
     }
 }
 
+impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob BoolVector> {
+    type Error = TypeError;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::BoolVector => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::BoolVector, value)),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispTreesitNode> {
+    type Error = TypeError;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::TreesitNode => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::TreesitNode, value)),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispSqlite> {
+    type Error = TypeError;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::Sqlite => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::Sqlite, value)),
+        }
+    }
+}
+
 impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispBuffer> {
     type Error = TypeError;
 
@@ -1332,6 +1513,10 @@ where
             ObjectType::Record(x) => x.clone_in(bk).into(),
             ObjectType::HashTable(x) => x.clone_in(bk).into(),
             ObjectType::Buffer(x) => x.clone_in(bk).into(),
+            ObjectType::BoolVector(x) => x.clone_in(bk).into(),
+            ObjectType::BigInt(x) => x.clone_in(bk).into(),
+            ObjectType::TreesitNode(x) => x.clone_in(bk).into(),
+            ObjectType::Sqlite(x) => x.clone_in(bk).into(),
         };
         let Ok(x) = Gc::<U>::try_from(obj) else { unreachable!() };
         x
@@ -1352,6 +1537,10 @@ impl<T> Trace for Gc<T> {
             ObjectType::Symbol(x) => x.trace(state),
             ObjectType::ByteFn(x) => x.trace(state),
             ObjectType::Buffer(x) => x.trace(state),
+            ObjectType::BoolVector(x) => x.trace(state),
+            ObjectType::BigInt(x) => x.trace(state),
+            ObjectType::TreesitNode(x) => x.trace(state),
+            ObjectType::Sqlite(x) => x.trace(state),
         }
     }
 }
@@ -1366,11 +1555,19 @@ where
     fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
         self.untag().move_value(to_space).map(|(x, moved)| (x.tag(), moved))
     }
+
+    fn raw_obj(&self) -> Option<RawObj> {
+        Some(self.as_obj().into_raw())
+    }
 }
 
 impl Markable for Object<'_> {
     type Value = Self;
 
+    fn raw_obj(&self) -> Option<RawObj> {
+        Some(self.as_obj().into_raw())
+    }
+
     fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
         let data = match self.untag() {
             ObjectType::Int(_) | ObjectType::SubrFn(_) | ObjectType::NIL => return None,
@@ -1383,6 +1580,10 @@ impl Markable for Object<'_> {
             ObjectType::ByteString(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::ByteFn(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Buffer(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::BoolVector(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::BigInt(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::TreesitNode(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::Sqlite(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Symbol(x) => {
                 // Need to handle specially because a symbol is not a pointer,
                 // but rather an offset
@@ -1399,6 +1600,10 @@ impl Markable for Object<'_> {
 impl Markable for Function<'_> {
     type Value = Self;
 
+    fn raw_obj(&self) -> Option<RawObj> {
+        Some(self.as_obj().into_raw())
+    }
+
     fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
         let data = match self.untag() {
             FunctionType::SubrFn(_) => return None,
@@ -1418,6 +1623,10 @@ impl Markable for Function<'_> {
 impl Markable for List<'_> {
     type Value = Self;
 
+    fn raw_obj(&self) -> Option<RawObj> {
+        Some(self.as_obj().into_raw())
+    }
+
     fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
         let data = match self.untag() {
             ListType::Cons(x) => cast_pair(x.move_value(to_space)?),
@@ -1545,9 +1754,71 @@ impl<T> Eq for Gc<T> {}
 
 use std::hash::{Hash, Hasher};
 impl<T> Hash for Gc<T> {
+    /// Objects are compared with `equal', so strings, floats and bignums are
+    /// hashed by their contents. This lets a fresh string find a key in a
+    /// hash table. Everything else is hashed by address.
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.ptr.hash(state);
+        match self.as_obj().untag() {
+            ObjectType::String(x) => str::hash(x, state),
+            ObjectType::ByteString(x) => <[u8]>::hash(x, state),
+            // 0.0 and -0.0 are `equal'
+            ObjectType::Float(x) => (if **x == 0.0 { 0.0 } else { **x }).to_bits().hash(state),
+            ObjectType::BigInt(x) => x.hash(state),
+            _ => self.ptr.hash(state),
+        }
+    }
+}
+
+/// How much of a nested object is printed, like `print-level' and
+/// `print-length'. Lists, vectors and records nested more than `level` deep
+/// are printed as `...`, and so are their elements after the first `length`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PrintLimits {
+    pub(crate) level: Option<usize>,
+    pub(crate) length: Option<usize>,
+}
+
+thread_local! {
+    static PRINT_LIMITS: Cell<PrintLimits> = const { Cell::new(PrintLimits { level: None, length: None }) };
+}
+
+impl PrintLimits {
+    /// The limits that objects are currently printed with.
+    pub(crate) fn current() -> Self {
+        PRINT_LIMITS.get()
+    }
+
+    /// Whether `len` elements are more than can be printed.
+    pub(crate) fn exceeds_length(self, len: usize) -> bool {
+        self.length.is_some_and(|n| len > n)
+    }
+
+    /// The limits for the elements of an object printed with these.
+    pub(crate) fn nested(self) -> Self {
+        Self { level: self.level.map(|x| x.saturating_sub(1)), ..self }
+    }
+}
+
+/// Call `f` with objects printed under `limits`.
+pub(crate) fn with_print_limits<T>(limits: PrintLimits, f: impl FnOnce() -> T) -> T {
+    let prev = PRINT_LIMITS.replace(limits);
+    let result = f();
+    PRINT_LIMITS.set(prev);
+    result
+}
+
+/// Print the elements of a list, vector or record with `elements`, which is
+/// passed the maximum number of them to print. The elements are printed one
+/// level deeper, and the whole object is printed as `...` if it is too deep.
+pub(crate) fn display_nested(
+    f: &mut fmt::Formatter,
+    elements: impl FnOnce(&mut fmt::Formatter, Option<usize>) -> fmt::Result,
+) -> fmt::Result {
+    let limits = PrintLimits::current();
+    if limits.level == Some(0) {
+        return f.write_str("...");
     }
+    with_print_limits(limits.nested(), || elements(f, limits.length))
 }
 
 impl fmt::Display for ObjectType<'_> {
@@ -1582,6 +1853,10 @@ impl ObjectType<'_> {
             ObjectType::SubrFn(x) => D::fmt(x, f),
             ObjectType::Float(x) => D::fmt(x, f),
             ObjectType::Buffer(x) => D::fmt(x, f),
+            ObjectType::BoolVector(x) => D::fmt(x, f),
+            ObjectType::BigInt(x) => D::fmt(x, f),
+            ObjectType::TreesitNode(x) => D::fmt(x, f),
+            ObjectType::Sqlite(x) => D::fmt(x, f),
         }
     }
 }
@@ -0,0 +1,131 @@
+use super::{CloneIn, Gc, IntoObject};
+use crate::{
+    core::gc::{Block, GcHeap, GcState, Trace},
+    NewtypeMarkable,
+};
+use macro_attr_2018::macro_attr;
+use newtype_derive_2018::*;
+use rune_macros::Trace;
+use std::{cell::Cell, fmt};
+
+/// The location of a node in the parse tree of a parser, used to create a
+/// [`LispTreesitNode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TreesitNode {
+    pub(crate) parser: i64,
+    pub(crate) generation: u64,
+    pub(crate) path: Vec<u32>,
+}
+
+pub(crate) struct TreesitNodeInner {
+    is_const: bool,
+    parser: i64,
+    generation: u64,
+    // The index of the child taken at each level, starting from the root
+    path: Cell<*const [u32]>,
+}
+
+macro_attr! {
+    /// A node in a tree-sitter parse tree. The tree itself is owned by its
+    /// parser, so the node only records how to find it again: the id of the
+    /// parser, the generation of the tree it came from, and the path of child
+    /// indices from the root. Once the parser reparses the buffer, the node
+    /// becomes outdated.
+    #[derive(PartialEq, Eq, Trace, NewtypeDebug!, NewtypeDisplay!, NewtypeDeref!, NewtypeMarkable!)]
+    pub(crate) struct LispTreesitNode(GcHeap<TreesitNodeInner>);
+}
+
+impl LispTreesitNode {
+    // SAFETY: `path` must be valid for the lifetime of the allocator.
+    pub(in crate::core) unsafe fn new(
+        parser: i64,
+        generation: u64,
+        path: *const [u32],
+        constant: bool,
+    ) -> Self {
+        let inner =
+            TreesitNodeInner { is_const: constant, parser, generation, path: Cell::new(path) };
+        Self(GcHeap::new(inner, constant))
+    }
+}
+
+impl TreesitNodeInner {
+    pub(crate) fn parser(&self) -> i64 {
+        self.parser
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub(crate) fn path(&self) -> &[u32] {
+        unsafe { &*self.path.get() }
+    }
+
+    pub(crate) fn get(&self) -> TreesitNode {
+        TreesitNode { parser: self.parser, generation: self.generation, path: self.path().to_vec() }
+    }
+}
+
+impl PartialEq for TreesitNodeInner {
+    fn eq(&self, other: &Self) -> bool {
+        self.parser == other.parser
+            && self.generation == other.generation
+            && self.path() == other.path()
+    }
+}
+
+impl Eq for TreesitNodeInner {}
+
+impl Trace for TreesitNodeInner {
+    fn trace(&self, state: &mut GcState) {
+        assert!(!self.is_const, "Attempt to trace constant treesit node");
+        let new = state.to_space.alloc_slice_copy(self.path());
+        self.path.set(new);
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispTreesitNode {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        self.get().into_obj(bk)
+    }
+}
+
+impl fmt::Display for TreesitNodeInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match crate::treesit::describe_node(self.parser, self.generation, self.path()) {
+            Some(description) => write!(f, "#<treesit-node {description}>"),
+            None => write!(f, "#<treesit-node outdated>"),
+        }
+    }
+}
+
+impl fmt::Debug for TreesitNodeInner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_treesit_node() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let node = TreesitNode { parser: 3, generation: 1, path: vec![0, 2, 1] };
+        let obj: Gc<&LispTreesitNode> = cx.add_as(node.clone());
+        assert_eq!(obj.untag().get(), node);
+        let other: Gc<&LispTreesitNode> = cx.add_as(node.clone());
+        assert_eq!(obj, other);
+        let sibling = TreesitNode { path: vec![0, 2, 2], ..node.clone() };
+        let sibling: Gc<&LispTreesitNode> = cx.add_as(sibling);
+        assert_ne!(obj, sibling);
+        root!(obj, cx);
+        cx.garbage_collect(true);
+        assert_eq!(obj.bind(cx).untag().get(), node);
+    }
+}
@@ -1,4 +1,4 @@
-use super::{CloneIn, Gc, IntoObject, MutObjCell, ObjCell, Object};
+use super::{display_nested, CloneIn, Gc, IntoObject, MutObjCell, ObjCell, Object};
 use crate::{
     core::gc::{Block, GcHeap, GcState, Trace},
     NewtypeMarkable,
@@ -13,12 +13,13 @@ use std::{
     cell::Cell,
     fmt::{self, Write},
     ops::Deref,
-    ptr::addr_of,
+    ptr::{addr_of, NonNull},
 };
 
 #[derive(Eq)]
 pub(crate) struct LispVecInner {
     is_const: bool,
+    read_only: Cell<bool>,
     inner: Cell<*const [ObjCell]>,
 }
 
@@ -56,8 +57,18 @@ impl LispVecInner {
         unsafe { &*self.inner.get() }
     }
 
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.is_const || self.read_only.get()
+    }
+
+    /// Prevent this vector from being modified. This is used for literals in
+    /// source code, where mutation would change the code itself.
+    pub(crate) fn make_read_only(&self) {
+        self.read_only.set(true);
+    }
+
     pub(crate) fn try_mut(&self) -> Result<&[MutObjCell]> {
-        if self.is_const {
+        if self.is_read_only() {
             Err(anyhow!("Attempt to mutate constant Vector"))
         } else {
             // SAFETY: ObjCell and MutObjCell have the same representation.
@@ -115,7 +126,7 @@ impl fmt::Debug for LispVecInner {
 impl LispVecInner {
     unsafe fn new(ptr: *const [Object], is_const: bool) -> Self {
         let ptr = ptr as *mut [ObjCell];
-        Self { is_const, inner: Cell::new(ptr) }
+        Self { is_const, read_only: Cell::new(false), inner: Cell::new(ptr) }
     }
 
     pub(super) fn display_walk(
@@ -129,14 +140,20 @@ impl LispVecInner {
         }
         seen.insert(ptr);
 
-        f.write_char('[')?;
-        for (i, x) in self.iter().enumerate() {
-            if i != 0 {
-                f.write_char(' ')?;
+        display_nested(f, |f, length| {
+            f.write_char('[')?;
+            for (i, x) in self.iter().enumerate() {
+                if i != 0 {
+                    f.write_char(' ')?;
+                }
+                if length.is_some_and(|n| i >= n) {
+                    f.write_str("...")?;
+                    break;
+                }
+                x.get().untag().display_walk(f, seen)?;
             }
-            x.get().untag().display_walk(f, seen)?;
-        }
-        f.write_char(']')
+            f.write_char(']')
+        })
     }
 }
 
@@ -163,19 +180,34 @@ impl fmt::Display for Record {
 }
 
 impl Record {
+    pub(in crate::core) fn forwarding_ptr(&self) -> Option<NonNull<u8>> {
+        use crate::core::gc::AllocState as A;
+        match self.0.allocation_state() {
+            A::Forwarded(f) => Some(f),
+            A::Global => panic!("global record allocation found in local heap"),
+            A::Unmoved => None,
+        }
+    }
+
     fn display_walk(&self, f: &mut fmt::Formatter, seen: &mut HashSet<*const u8>) -> fmt::Result {
         let ptr = (self as *const Self).cast();
         if seen.contains(&ptr) {
             return write!(f, "#0");
         }
         seen.insert(ptr);
-        write!(f, "#s(")?;
-        for (i, x) in self.iter().enumerate() {
-            if i != 0 {
-                f.write_char(' ')?;
+        display_nested(f, |f, length| {
+            write!(f, "#s(")?;
+            for (i, x) in self.iter().enumerate() {
+                if i != 0 {
+                    f.write_char(' ')?;
+                }
+                if length.is_some_and(|n| i >= n) {
+                    f.write_str("...")?;
+                    break;
+                }
+                x.get().untag().display_walk(f, seen)?;
             }
-            x.get().untag().display_walk(f, seen)?;
-        }
-        f.write_char(')')
+            f.write_char(')')
+        })
     }
 }
@@ -0,0 +1,203 @@
+//! Reading and writing comma and tab separated values.
+//!
+//! Parsing follows RFC 4180: fields containing the separator, a double
+//! quote or a line break are enclosed in double quotes, and double quotes
+//! inside them are doubled. Rows may end in either CRLF or LF. Tab separated
+//! values use the same rules with a tab as the separator.
+use crate::core::{
+    env::Env,
+    gc::{Context, Rt},
+    object::{List, Object, ObjectType},
+};
+use crate::fns::slice_into_list;
+use anyhow::{bail, ensure, Result};
+use rune_macros::defun;
+use std::fmt::Write as _;
+
+fn separator(separator: Option<char>) -> Result<char> {
+    let separator = separator.unwrap_or(',');
+    ensure!(!matches!(separator, '"' | '\r' | '\n'), "Invalid CSV separator: {separator:?}");
+    Ok(separator)
+}
+
+/// Split `text` into rows of fields.
+fn parse(text: &str, separator: char) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let mut field = String::new();
+        let quoted = chars.next_if_eq(&'"').is_some();
+        if quoted {
+            let start = line;
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(chr) => {
+                        if chr == '\n' {
+                            line += 1;
+                        }
+                        field.push(chr);
+                    }
+                    None => bail!("Unterminated quoted field starting on line {start}"),
+                }
+            }
+        } else {
+            while let Some(chr) = chars.next_if(|x| *x != separator && *x != '\r' && *x != '\n') {
+                field.push(chr);
+            }
+        }
+        row.push(field);
+        let end = chars.next();
+        match end {
+            Some(chr) if chr == separator => {
+                // a separator at the end of the text is followed by an empty field
+                if chars.peek().is_none() {
+                    row.push(String::new());
+                }
+            }
+            Some('\r' | '\n') | None => {
+                if end == Some('\r') {
+                    chars.next_if_eq(&'\n');
+                }
+                rows.push(std::mem::take(&mut row));
+                line += 1;
+            }
+            Some(chr) => bail!("Unexpected {chr:?} after quoted field on line {line}"),
+        }
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+fn rows_to_list<'ob>(rows: Vec<Vec<String>>, cx: &'ob Context) -> Object<'ob> {
+    let rows: Vec<Object> = rows
+        .into_iter()
+        .map(|row| {
+            let fields: Vec<Object> = row.into_iter().map(|x| cx.add(x)).collect();
+            slice_into_list(&fields, None, cx)
+        })
+        .collect();
+    slice_into_list(&rows, None, cx)
+}
+
+/// Parse STRING as comma separated values and return a list of rows, each a
+/// list of field strings. SEPARATOR is the character between fields, and
+/// defaults to a comma; use ?\\t for tab separated values.
+#[defun]
+fn parse_csv_string<'ob>(
+    string: &str,
+    separator: Option<char>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    Ok(rows_to_list(parse(string, self::separator(separator)?)?, cx))
+}
+
+/// Parse the text of the current buffer between START and END as comma
+/// separated values, like `parse-csv-string'.
+#[defun]
+fn parse_csv_region<'ob>(
+    start: usize,
+    end: usize,
+    separator: Option<char>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    let (before, after) = env.current_buffer.get().slice_with_gap(start, end)?;
+    let text = format!("{before}{after}");
+    Ok(rows_to_list(parse(&text, self::separator(separator)?)?, cx))
+}
+
+/// Write `field`, quoting it if needed.
+fn write_field(out: &mut String, field: &str, separator: char) {
+    if field.contains([separator, '"', '\r', '\n']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+/// Return ROWS formatted as comma separated values, with each row ending in
+/// a newline. Each row is a list of fields, which may be strings, numbers
+/// or symbols; nil is written as an empty field. Fields are quoted when they
+/// contain the separator, a double quote or a line break. SEPARATOR defaults
+/// to a comma.
+#[defun]
+fn csv_format_rows(rows: List, separator: Option<char>) -> Result<String> {
+    let separator = self::separator(separator)?;
+    let mut out = String::new();
+    for row in rows.elements() {
+        let row: List = row?.try_into()?;
+        for (i, field) in row.elements().enumerate() {
+            if i > 0 {
+                out.push(separator);
+            }
+            let field = field?;
+            match field.untag() {
+                ObjectType::String(x) => write_field(&mut out, x, separator),
+                ObjectType::NIL => {}
+                ObjectType::Symbol(x) => write_field(&mut out, x.name(), separator),
+                ObjectType::Int(_) | ObjectType::Float(_) | ObjectType::BigInt(_) => {
+                    write!(out, "{field}")?;
+                }
+                _ => bail!("Invalid CSV field: {field}"),
+            }
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    fn rows(x: &[&[&str]]) -> Vec<Vec<String>> {
+        x.iter().map(|row| row.iter().map(|x| (*x).to_owned()).collect()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("", ',').unwrap(), rows(&[]));
+        assert_eq!(parse("a,b\n1,2\n", ',').unwrap(), rows(&[&["a", "b"], &["1", "2"]]));
+        assert_eq!(parse("a,b\r\n1,2", ',').unwrap(), rows(&[&["a", "b"], &["1", "2"]]));
+        assert_eq!(
+            parse("\"x,\"\"y\"\"\",\"line\nbreak\"\n", ',').unwrap(),
+            rows(&[&["x,\"y\"", "line\nbreak"]])
+        );
+        assert_eq!(parse("a,,\n,b\n", ',').unwrap(), rows(&[&["a", "", ""], &["", "b"]]));
+        assert_eq!(parse("a,", ',').unwrap(), rows(&[&["a", ""]]));
+        assert_eq!(parse("a\n\nb\n", ',').unwrap(), rows(&[&["a"], &[""], &["b"]]));
+        assert_eq!(parse("a\tb c\n", '\t').unwrap(), rows(&[&["a", "b c"]]));
+        assert!(parse("\"open", ',').is_err());
+        assert!(parse("\"a\"b,c", ',').is_err());
+    }
+
+    #[test]
+    fn test_csv_defuns() {
+        assert_lisp(
+            "(parse-csv-string \"a,\\\"b,c\\\"\\n1,2\\n\")",
+            "((\"a\" \"b,c\") (\"1\" \"2\"))",
+        );
+        assert_lisp("(parse-csv-string \"a\\tb\\n\" ?\\t)", "((\"a\" \"b\"))");
+        assert_lisp(
+            "(csv-format-rows '((\"a\" \"b,c\" \"say \\\"hi\\\"\") (1 2.5 nil sym)))",
+            "\"a,\\\"b,c\\\",\\\"say \\\"\\\"hi\\\"\\\"\\\"\\n1,2.5,,sym\\n\"",
+        );
+        assert_lisp(
+            "(progn
+               (set-buffer (get-buffer-create \"csv-region\"))
+               (insert \"skip\\nx,y\\n3,4\\n\")
+               (parse-csv-region 6 (point-max)))",
+            "((\"x\" \"y\") (\"3\" \"4\"))",
+        );
+    }
+}
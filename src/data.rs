@@ -3,13 +3,17 @@ use crate::core::{
     cons::Cons,
     env::{sym, Env, INTERNED_SYMBOLS},
     error::{Type, TypeError},
-    gc::{Context, Rt},
+    gc::{Context, Rt, Rto},
     object::{
-        IntoObject, List, ListType, Number, Object, ObjectType, SubrFn, Symbol, WithLifetime, NIL,
+        BoolVector, Gc, IntoObject, List, ListType, Number, Object, ObjectType, SubrFn, Symbol,
+        WithLifetime, NIL,
     },
 };
-use anyhow::{anyhow, Result};
-use rune_core::{hashmap::HashSet, macros::list};
+use anyhow::{ensure, Result};
+use rune_core::{
+    hashmap::HashSet,
+    macros::{list, root},
+};
 use rune_macros::defun;
 use std::sync::LazyLock;
 use std::sync::Mutex;
@@ -37,18 +41,70 @@ pub(crate) fn defalias<'ob>(
     symbol: Symbol<'ob>,
     definition: Object,
     _docstring: Option<&str>,
+    env: &mut Rt<Env>,
 ) -> Result<Symbol<'ob>> {
-    fset(symbol, definition)
+    fset(symbol, definition)?;
+    record_declarations(symbol, definition, env);
+    Ok(symbol)
+}
+
+/// Record the `declare' forms at the start of the body of DEFINITION as
+/// properties of SYMBOL. Unknown declarations are ignored.
+fn record_declarations(symbol: Symbol, definition: Object, env: &mut Rt<Env>) {
+    let ObjectType::Cons(mut func) = definition.untag() else { return };
+    // (macro . FUNC)
+    if func.car() == sym::MACRO {
+        let ObjectType::Cons(inner) = func.cdr().untag() else { return };
+        func = inner;
+    }
+    let skip = match func.car().untag() {
+        // (lambda ARGS . BODY)
+        ObjectType::Symbol(sym::LAMBDA) => 2,
+        // (closure ENV ARGS . BODY)
+        ObjectType::Symbol(sym::CLOSURE) => 3,
+        _ => return,
+    };
+    let body: Vec<_> = func.elements().skip(skip).map_while(Result::ok).collect();
+    let body = match body.as_slice() {
+        [doc, rest @ ..] if matches!(doc.untag(), ObjectType::String(_)) && !rest.is_empty() => {
+            rest
+        }
+        all => all,
+    };
+    for form in body {
+        let ObjectType::Cons(form) = form.untag() else { break };
+        if form.car() != sym::DECLARE {
+            break;
+        }
+        // (declare (PROP VALUE)...)
+        for spec in form.elements().skip(1).map_while(Result::ok) {
+            let ObjectType::Cons(spec) = spec.untag() else { continue };
+            let prop = match spec.car().untag() {
+                ObjectType::Symbol(sym::INDENT) => sym::LISP_INDENT_FUNCTION,
+                ObjectType::Symbol(sym::DOC_STRING) => sym::DOC_STRING_ELT,
+                ObjectType::Symbol(sym::DEBUG) => sym::EDEBUG_FORM_SPEC,
+                _ => continue,
+            };
+            let value = match spec.cdr().untag() {
+                ObjectType::Cons(values) => values.car(),
+                _ => NIL,
+            };
+            put(symbol, prop, value, env);
+        }
+    }
 }
 
 #[defun]
 pub(crate) fn set<'ob>(
-    place: Symbol,
-    newlet: Object<'ob>,
+    place: &Rto<Gc<Symbol>>,
+    newlet: &Rto<Object>,
     env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
-    env.set_var(place, newlet)?;
-    Ok(newlet)
+    let place = place.untag(cx);
+    root!(place, cx);
+    crate::watchers::set(place, newlet, env, cx)?;
+    Ok(newlet.bind(cx))
 }
 
 #[defun]
@@ -91,7 +147,7 @@ pub(crate) fn default_value<'ob>(
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
     // TODO: Implement buffer locals
-    symbol_value(symbol, env, cx).ok_or_else(|| anyhow!("Void variable: {symbol}"))
+    symbol_value(symbol, env, cx).ok_or_else(|| LispError::void_variable(symbol, cx).into())
 }
 
 #[defun]
@@ -108,6 +164,10 @@ pub(crate) fn symbol_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Option<Object<'ob>> {
+    if symbol.is_const() {
+        // nil, t, and keywords evaluate to themselves
+        return Some(cx.bind(symbol.into()));
+    }
     env.vars.get(symbol).map(|x| x.bind(cx))
 }
 
@@ -134,18 +194,24 @@ pub(crate) fn fmakunbound(symbol: Symbol) -> Symbol {
 
 #[defun]
 pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+    symbol.is_const() || env.vars.get(symbol).is_some()
 }
 
 #[defun]
-pub(crate) fn makunbound<'ob>(symbol: Symbol<'ob>, env: &mut Rt<Env>) -> Symbol<'ob> {
-    env.vars.remove(symbol);
-    symbol
+pub(crate) fn makunbound<'ob>(
+    symbol: &Rto<Gc<Symbol>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Symbol<'ob>> {
+    let symbol = symbol.untag(cx);
+    root!(symbol, cx);
+    crate::watchers::makunbound(symbol, env, cx)?;
+    Ok(symbol.bind(cx))
 }
 
 #[defun]
 pub(crate) fn default_boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+    symbol.is_const() || env.vars.get(symbol).is_some()
 }
 
 #[defun]
@@ -185,7 +251,10 @@ pub(crate) fn stringp(object: Object) -> bool {
 
 #[defun]
 pub(crate) fn numberp(object: Object) -> bool {
-    matches!(object.untag(), ObjectType::Int(_) | ObjectType::Float(_))
+    matches!(
+        object.untag(),
+        ObjectType::Int(_) | ObjectType::Float(_) | ObjectType::BigInt(_)
+    )
 }
 
 #[defun]
@@ -204,6 +273,11 @@ pub(crate) fn recordp(object: Object) -> bool {
     matches!(object.untag(), ObjectType::Record(_))
 }
 
+#[defun]
+fn bool_vector_p(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::BoolVector(_))
+}
+
 #[defun]
 pub(crate) fn consp(object: Object) -> bool {
     matches!(object.untag(), ObjectType::Cons(_))
@@ -212,14 +286,21 @@ pub(crate) fn consp(object: Object) -> bool {
 #[defun]
 pub(crate) fn keywordp(object: Object) -> bool {
     match object.untag() {
-        ObjectType::Symbol(s) => s.name().starts_with(':'),
+        ObjectType::Symbol(s) => s.is_keyword(),
         _ => false,
     }
 }
 
+/// Return the decimal representation of NUMBER as a string. Floats use the
+/// shortest representation that reads back as the same value.
+#[defun]
+fn number_to_string(number: Number) -> String {
+    number.to_string()
+}
+
 #[defun]
 pub(crate) fn integerp(object: Object) -> bool {
-    matches!(object.untag(), ObjectType::Int(_))
+    matches!(object.untag(), ObjectType::Int(_) | ObjectType::BigInt(_))
 }
 
 #[defun]
@@ -269,13 +350,15 @@ fn string_to_number<'ob>(string: &str, base: Option<i64>, cx: &'ob Context) -> N
 
 #[defun]
 pub(crate) fn defvar<'ob>(
-    symbol: Symbol,
-    initvalue: Option<Object<'ob>>,
-    _docstring: Option<&str>,
+    symbol: &Rto<Gc<Symbol>>,
+    initvalue: Option<&Rto<Object>>,
+    _docstring: Option<&Rto<Object>>,
     env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
-    let value = initvalue.unwrap_or_default();
-    set(symbol, value, env)
+    let value = initvalue.map_or(NIL, |x| x.bind(cx));
+    root!(value, cx);
+    set(symbol, value, env, cx)
 }
 
 #[defun]
@@ -308,72 +391,65 @@ fn ash(value: i64, count: i64) -> i64 {
     }
 }
 
+/// Convert IDX to an index into SEQUENCE, which has LEN elements. Signal
+/// `args-out-of-range' if it is negative or past the end.
+pub(crate) fn check_index(sequence: Object, idx: i64, len: usize, cx: &Context) -> Result<usize> {
+    match usize::try_from(idx) {
+        Ok(i) if i < len => Ok(i),
+        _ => Err(LispError::args_out_of_range(sequence, idx, cx).into()),
+    }
+}
+
 #[defun]
 pub(crate) fn aset<'ob>(
     array: Object<'ob>,
-    idx: usize,
+    idx: i64,
     newlet: Object<'ob>,
+    cx: &Context,
 ) -> Result<Object<'ob>> {
     match array.untag() {
         ObjectType::Vec(vec) => {
-            let vec = vec.try_mut()?;
-            if idx < vec.len() {
-                vec[idx].set(newlet);
-                Ok(newlet)
-            } else {
-                let len = vec.len();
-                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
-            }
+            let vec = vec.try_mut().map_err(|_| LispError::setting_constant(array, cx))?;
+            let idx = check_index(array, idx, vec.len(), cx)?;
+            vec[idx].set(newlet);
+            Ok(newlet)
         }
         ObjectType::Record(vec) => {
-            let vec = vec.try_mut()?;
-            if idx < vec.len() {
-                vec[idx].set(newlet);
-                Ok(newlet)
-            } else {
-                let len = vec.len();
-                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
-            }
+            let vec = vec.try_mut().map_err(|_| LispError::setting_constant(array, cx))?;
+            let idx = check_index(array, idx, vec.len(), cx)?;
+            vec[idx].set(newlet);
+            Ok(newlet)
+        }
+        ObjectType::BoolVector(vec) => {
+            let idx = check_index(array, idx, vec.len(), cx)?;
+            vec.set(idx, !newlet.is_nil())?;
+            Ok(newlet)
         }
         x => Err(TypeError::new(Type::Sequence, x).into()),
     }
 }
 
 #[defun]
-pub(crate) fn aref<'ob>(array: Object<'ob>, idx: usize, cx: &'ob Context) -> Result<Object<'ob>> {
+pub(crate) fn aref<'ob>(array: Object<'ob>, idx: i64, cx: &'ob Context) -> Result<Object<'ob>> {
     match array.untag() {
-        ObjectType::Vec(vec) => match vec.get(idx) {
-            Some(x) => Ok(x.get()),
-            None => {
-                let len = vec.len();
-                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
-            }
-        },
-        ObjectType::Record(vec) => match vec.get(idx) {
-            Some(x) => Ok(x.get()),
-            None => {
-                let len = vec.len();
-                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
-            }
-        },
-        ObjectType::String(string) => match string.chars().nth(idx) {
-            Some(x) => Ok((i64::from(x as u32)).into()),
-            None => {
-                let len = string.len();
-                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
-            }
-        },
-        ObjectType::ByteString(string) => match string.get(idx) {
-            Some(x) => Ok((i64::from(*x)).into()),
-            None => {
-                let len = string.len();
-                Err(anyhow!("index {idx} is out of bounds. Length was {len}"))
-            }
-        },
-        ObjectType::ByteFn(fun) => match fun.index(idx, cx) {
-            Some(x) => Ok(x),
-            None => Err(anyhow!("index {idx} is out of bounds")),
-        },
+        ObjectType::Vec(vec) => Ok(vec[check_index(array, idx, vec.len(), cx)?].get()),
+        ObjectType::Record(vec) => Ok(vec[check_index(array, idx, vec.len(), cx)?].get()),
+        ObjectType::String(string) => {
+            let idx = check_index(array, idx, string.len(), cx)?;
+            let chr = string.chars().nth(idx).unwrap();
+            Ok(i64::from(chr as u32).into())
+        }
+        ObjectType::ByteString(string) => {
+            Ok(i64::from(string[check_index(array, idx, string.len(), cx)?]).into())
+        }
+        ObjectType::BoolVector(vec) => {
+            Ok(vec.get(check_index(array, idx, vec.len(), cx)?).unwrap().into())
+        }
+        ObjectType::ByteFn(fun) => {
+            let idx = check_index(array, idx, fun.len(), cx)?;
+            fun.index(idx, cx)
+                .ok_or_else(|| LispError::args_out_of_range(array, idx as i64, cx).into())
+        }
         x => Err(TypeError::new(Type::Sequence, x).into()),
     }
 }
@@ -381,7 +457,7 @@ pub(crate) fn aref<'ob>(array: Object<'ob>, idx: usize, cx: &'ob Context) -> Res
 #[defun]
 fn type_of(object: Object) -> Object {
     match object.untag() {
-        ObjectType::Int(_) => sym::INTEGER.into(),
+        ObjectType::Int(_) | ObjectType::BigInt(_) => sym::INTEGER.into(),
         ObjectType::Float(_) => sym::FLOAT.into(),
         ObjectType::Symbol(_) => sym::SYMBOL.into(),
         ObjectType::Cons(_) => sym::CONS.into(),
@@ -392,6 +468,9 @@ fn type_of(object: Object) -> Object {
         ObjectType::String(_) | ObjectType::ByteString(_) => sym::STRING.into(),
         ObjectType::SubrFn(_) => sym::SUBR.into(),
         ObjectType::Buffer(_) => sym::BUFFER.into(),
+        ObjectType::BoolVector(_) => sym::BOOL_VECTOR.into(),
+        ObjectType::TreesitNode(_) => sym::TREESIT_NODE.into(),
+        ObjectType::Sqlite(_) => sym::SQLITE.into(),
     }
 }
 
@@ -448,14 +527,14 @@ pub(crate) fn cdr_safe(object: Object) -> Object {
 }
 
 #[defun]
-pub(crate) fn setcar<'ob>(cell: &Cons, newcar: Object<'ob>) -> Result<Object<'ob>> {
-    cell.set_car(newcar)?;
+pub(crate) fn setcar<'ob>(cell: &Cons, newcar: Object<'ob>, cx: &Context) -> Result<Object<'ob>> {
+    cell.set_car(newcar).map_err(|_| LispError::setting_constant(cell, cx))?;
     Ok(newcar)
 }
 
 #[defun]
-pub(crate) fn setcdr<'ob>(cell: &Cons, newcdr: Object<'ob>) -> Result<Object<'ob>> {
-    cell.set_cdr(newcdr)?;
+pub(crate) fn setcdr<'ob>(cell: &Cons, newcdr: Object<'ob>, cx: &Context) -> Result<Object<'ob>> {
+    cell.set_cdr(newcdr).map_err(|_| LispError::setting_constant(cell, cx))?;
     Ok(newcdr)
 }
 
@@ -492,6 +571,20 @@ impl std::fmt::Display for LispError {
 }
 
 defsym!(WRONG_NUMBER_OF_ARGUMENTS);
+defsym!(WRONG_TYPE_ARGUMENT);
+defsym!(SEQUENCEP);
+defsym!(CIRCULAR_LIST);
+defsym!(SETTING_CONSTANT);
+defsym!(ARGS_OUT_OF_RANGE);
+defsym!(VOID_FUNCTION);
+defsym!(ARITH_ERROR);
+defsym!(OVERFLOW_ERROR);
+
+defsym!(INDENT);
+defsym!(DOC_STRING);
+defsym!(LISP_INDENT_FUNCTION);
+defsym!(DOC_STRING_ELT);
+defsym!(EDEBUG_FORM_SPEC);
 impl LispError {
     pub(crate) fn new(message: &Cons) -> Self {
         Self { message: unsafe { message.with_lifetime() } }
@@ -511,11 +604,159 @@ impl LispError {
         let list = list![sym::WRONG_NUMBER_OF_ARGUMENTS, func, expected, actual; cx];
         Self::new(list.try_into().unwrap())
     }
+
+    /// Error for an index `idx` that is not valid for `obj`.
+    pub(crate) fn args_out_of_range<'ob>(
+        obj: impl Into<Object<'ob>>,
+        idx: i64,
+        cx: &'ob Context,
+    ) -> Self {
+        let list = list![sym::ARGS_OUT_OF_RANGE, obj.into(), idx; cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for a region from `start` to `end` that is not inside the
+    /// buffer.
+    pub(crate) fn region_out_of_range(start: usize, end: usize, cx: &Context) -> Self {
+        let list = list![sym::ARGS_OUT_OF_RANGE, start, end; cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for a range from `from` to `to` that is not inside `obj`.
+    pub(crate) fn substring_out_of_range<'ob>(
+        obj: impl Into<Object<'ob>>,
+        from: i64,
+        to: i64,
+        cx: &'ob Context,
+    ) -> Self {
+        let list = list![sym::ARGS_OUT_OF_RANGE, obj.into(), from, to; cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for reading `symbol` when it has no value.
+    pub(crate) fn void_variable<'ob>(symbol: impl Into<Object<'ob>>, cx: &'ob Context) -> Self {
+        let list = list![sym::VOID_VARIABLE, symbol.into(); cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for calling `symbol` when it has no function definition.
+    pub(crate) fn void_function<'ob>(symbol: impl Into<Object<'ob>>, cx: &'ob Context) -> Self {
+        let list = list![sym::VOID_FUNCTION, symbol.into(); cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for dividing an integer by zero.
+    pub(crate) fn arith_error(cx: &Context) -> Self {
+        let list = list![sym::ARITH_ERROR; cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for a number that can't be converted to an integer, like an
+    /// infinite or NaN float.
+    pub(crate) fn overflow_error<'ob>(number: impl Into<Object<'ob>>, cx: &'ob Context) -> Self {
+        let list = list![sym::OVERFLOW_ERROR, number.into(); cx];
+        Self::new(list.try_into().unwrap())
+    }
+
+    /// Error for an attempt to modify `obj` when it is constant.
+    pub(crate) fn setting_constant<'ob>(obj: impl Into<Object<'ob>>, cx: &'ob Context) -> Self {
+        let list = list![sym::SETTING_CONSTANT, obj.into(); cx];
+        Self::new(list.try_into().unwrap())
+    }
 }
 
 unsafe impl Send for LispError {}
 unsafe impl Sync for LispError {}
 
+/// Combine the bool-vectors A and B element-wise with `op`. If DEST is
+/// given, the result is stored there and DEST is returned if it changed, or
+/// nil otherwise. Without DEST a new bool-vector is returned.
+fn bool_vector_binop<'ob>(
+    a: &BoolVector,
+    b: &BoolVector,
+    dest: Option<&'ob BoolVector>,
+    op: impl Fn(bool, bool) -> bool,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    ensure!(a.len() == b.len(), "Wrong length argument: {a}, {b}");
+    let result: Vec<bool> = a.iter().zip(b.iter()).map(|(a, b)| op(a, b)).collect();
+    let Some(dest) = dest else { return Ok(cx.add(result)) };
+    ensure!(dest.len() == a.len(), "Wrong length argument: {a}, {dest}");
+    let changed = dest.iter().zip(&result).any(|(old, new)| old != *new);
+    for (idx, bit) in result.into_iter().enumerate() {
+        dest.set(idx, bit)?;
+    }
+    Ok(if changed { dest.into() } else { NIL })
+}
+
+/// Return the union of bool-vectors A and B, storing it in C if given.
+#[defun]
+fn bool_vector_union<'ob>(
+    a: &BoolVector,
+    b: &BoolVector,
+    c: Option<&'ob BoolVector>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    bool_vector_binop(a, b, c, |a, b| a || b, cx)
+}
+
+/// Return the intersection of bool-vectors A and B, storing it in C if given.
+#[defun]
+fn bool_vector_intersection<'ob>(
+    a: &BoolVector,
+    b: &BoolVector,
+    c: Option<&'ob BoolVector>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    bool_vector_binop(a, b, c, |a, b| a && b, cx)
+}
+
+/// Return the elements of bool-vector A that are not in B, storing the result
+/// in C if given.
+#[defun]
+fn bool_vector_set_difference<'ob>(
+    a: &BoolVector,
+    b: &BoolVector,
+    c: Option<&'ob BoolVector>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    bool_vector_binop(a, b, c, |a, b| a && !b, cx)
+}
+
+/// Return the exclusive or of bool-vectors A and B, storing it in C if given.
+#[defun]
+fn bool_vector_exclusive_or<'ob>(
+    a: &BoolVector,
+    b: &BoolVector,
+    c: Option<&'ob BoolVector>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    bool_vector_binop(a, b, c, |a, b| a != b, cx)
+}
+
+/// Return the complement of bool-vector A, storing it in B if given.
+#[defun]
+fn bool_vector_not<'ob>(
+    a: &BoolVector,
+    b: Option<&'ob BoolVector>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    bool_vector_binop(a, a, b, |a, _| !a, cx)
+}
+
+/// Return t if every true element of bool-vector A is also true in B.
+#[defun]
+fn bool_vector_subsetp(a: &BoolVector, b: &BoolVector) -> Result<bool> {
+    ensure!(a.len() == b.len(), "Wrong length argument: {a}, {b}");
+    Ok(a.iter().zip(b.iter()).all(|(a, b)| !a || b))
+}
+
+/// Return the number of true elements in bool-vector A.
+#[defun]
+fn bool_vector_count_population(a: &BoolVector) -> usize {
+    a.count()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -528,6 +769,68 @@ mod test {
         assert_eq!(ash(256, -8), 1);
         assert_eq!(ash(-8, 1), -16);
     }
+
+    #[test]
+    fn test_number_to_string() {
+        use crate::core::gc::RootSet;
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        assert_eq!(number_to_string(12.into()), "12");
+        assert_eq!(number_to_string(cx.add_as(0.1)), "0.1");
+        assert_eq!(number_to_string(cx.add_as(3.0)), "3.0");
+        assert_eq!(number_to_string(cx.add_as(1e20)), "1e+20");
+    }
+
+    #[test]
+    fn test_bool_vector() {
+        use crate::core::{gc::RootSet, object::Gc};
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        let a: Gc<&BoolVector> = cx.add_as(vec![true, true, false, false]);
+        let b: Gc<&BoolVector> = cx.add_as(vec![true, false, true, false]);
+        let (a, b) = (a.untag(), b.untag());
+        let union = bool_vector_union(a, b, None, cx).unwrap();
+        assert_eq!(union, cx.add(vec![true, true, true, false]));
+        let inter = bool_vector_intersection(a, b, None, cx).unwrap();
+        assert_eq!(inter, cx.add(vec![true, false, false, false]));
+        let xor = bool_vector_exclusive_or(a, b, None, cx).unwrap();
+        assert_eq!(xor, cx.add(vec![false, true, true, false]));
+        assert_eq!(bool_vector_count_population(a), 2);
+        assert!(!bool_vector_subsetp(a, b).unwrap());
+        let ObjectType::BoolVector(inter) = inter.untag() else { unreachable!() };
+        assert!(bool_vector_subsetp(inter, a).unwrap());
+
+        // storing into a destination returns nil when nothing changes
+        let dest: Gc<&BoolVector> = cx.add_as(vec![false; 4]);
+        let dest = dest.untag();
+        let result = bool_vector_union(a, b, Some(dest), cx).unwrap();
+        assert_eq!(result, union);
+        assert_eq!(bool_vector_union(a, b, Some(dest), cx).unwrap(), NIL);
+
+        let short: Gc<&BoolVector> = cx.add_as(vec![true]);
+        assert!(bool_vector_union(a, short.untag(), None, cx).is_err());
+
+        let vec = cx.add(vec![false; 3]);
+        aset(vec, 1, sym::TRUE.into(), cx).unwrap();
+        assert_eq!(aref(vec, 1, cx).unwrap(), sym::TRUE.into());
+        assert_eq!(aref(vec, 0, cx).unwrap(), NIL);
+        assert_eq!(type_of(vec), sym::BOOL_VECTOR.into());
+    }
+
+    #[test]
+    fn test_declarations() {
+        use crate::interpreter::assert_lisp;
+        assert_lisp(
+            "(progn (defalias 'data-test-decl #'(lambda (x) \"doc\" (declare (indent 1) (doc-string 2)) x)) (list (get 'data-test-decl 'lisp-indent-function) (get 'data-test-decl 'doc-string-elt) (data-test-decl 3)))",
+            "(1 2 3)",
+        );
+        assert_lisp(
+            "(progn (defalias 'data-test-macro (cons 'macro #'(lambda (x) (declare (debug t)) x))) (get 'data-test-macro 'edebug-form-spec))",
+            "t",
+        );
+        assert_lisp("(declare (indent 1))", "nil");
+    }
 }
 
 defsym!(MANY);
@@ -537,3 +840,6 @@ defsym!(COMPILED_FUNCTION);
 defsym!(HASH_TABLE);
 defsym!(BUFFER);
 defsym!(SUBR);
+
+defvar!(MOST_POSITIVE_FIXNUM, crate::core::object::MAX_FIXNUM);
+defvar!(MOST_NEGATIVE_FIXNUM, crate::core::object::MIN_FIXNUM);
@@ -11,18 +11,22 @@ pub(crate) fn debug_enabled() -> bool {
     }
 }
 
+/// Turn on `debug!` messages, along with the log level they need to be
+/// printed.
 pub(crate) fn enable_debug() {
     FLAG.store(true, Ordering::Release);
+    crate::logging::show_debug();
 }
 
 pub(crate) fn disable_debug() {
     FLAG.store(false, Ordering::Release);
+    crate::logging::hide_debug();
 }
 
 macro_rules! debug {
     ($($arg:tt)*) => {{
         if crate::debug::debug_enabled() {
-            println!($($arg)*);
+            tracing::debug!($($arg)*);
         }
     }}
 }
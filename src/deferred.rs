@@ -0,0 +1,246 @@
+//! Deferred work that runs in short slices while rune is idle.
+//!
+//! `run-when-idle-chunked' queues items to be passed to a function once rune
+//! has been idle for a while, like jit-lock's stealth fontification. Items
+//! are processed until a time slice runs out, and then the job yields back to
+//! the event loop so that input is not held up. It carries on in the same
+//! idle period if no input arrives, and otherwise in the next one.
+//!
+//! A job is a record `#s(idle-chunked-job FUNCTION QUEUE SECS SLICE TIMER
+//! CONTINUATION)`. TIMER is the repeating idle timer that starts the job in
+//! each idle period, and is nil once the queue is empty. CONTINUATION is the
+//! one-shot idle timer that resumes it after it yields.
+use crate::core::{
+    env::{sym, CallFrame, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, ObjectType, RecordBuilder, Symbol, NIL},
+};
+use crate::fns::slice_into_list;
+use crate::timer::{add_idle_timer, remove_timer};
+use anyhow::{bail, Result};
+use rune_core::macros::{list, root};
+use rune_macros::defun;
+use std::time::{Duration, Instant};
+
+/// How long a job waits after yielding before it carries on, which gives the
+/// event loop a chance to see input.
+const YIELD_SECS: f64 = 0.001;
+
+const QUEUE: usize = 2;
+const TIMER: usize = 5;
+const CONTINUATION: usize = 6;
+
+/// The parts of a job record.
+struct Job<'ob> {
+    function: Object<'ob>,
+    queue: Object<'ob>,
+    secs: f64,
+    slice: f64,
+    timer: Object<'ob>,
+    continuation: Object<'ob>,
+}
+
+fn job_parts(job: Object) -> Result<Job> {
+    if let ObjectType::Record(record) = job.untag() {
+        if let [tag, function, queue, secs, slice, timer, continuation] = &record[..] {
+            if let (sym::IDLE_CHUNKED_JOB, ObjectType::Float(secs), ObjectType::Float(slice)) =
+                (tag.get(), secs.get().untag(), slice.get().untag())
+            {
+                return Ok(Job {
+                    function: function.get(),
+                    queue: queue.get(),
+                    secs: **secs,
+                    slice: **slice,
+                    timer: timer.get(),
+                    continuation: continuation.get(),
+                });
+            }
+        }
+    }
+    bail!("Not an idle job: {job}")
+}
+
+fn set_field(job: Object, index: usize, value: Object) -> Result<()> {
+    let ObjectType::Record(record) = job.untag() else { unreachable!() };
+    record.try_mut()?[index].set(value);
+    Ok(())
+}
+
+/// Convert `obj` to seconds, using `default` if it is nil or missing.
+fn seconds(obj: Option<Object>, default: f64) -> Result<f64> {
+    match obj.map(|x| x.untag()) {
+        None | Some(ObjectType::NIL) => Ok(default),
+        Some(ObjectType::Int(x)) => Ok(x as f64),
+        Some(ObjectType::Float(x)) => Ok(**x),
+        Some(x) => bail!("Invalid number of seconds: {x}"),
+    }
+}
+
+fn var_seconds(var: Symbol, env: &Rt<Env>, cx: &Context) -> Result<f64> {
+    seconds(env.vars.get(var).map(|x| x.bind(cx)), 0.0)
+}
+
+/// Start the idle timer for `job` if it has work queued and none is running.
+fn activate<'ob>(job: Object<'ob>, env: &mut Rt<Env>, cx: &'ob Context) -> Result<()> {
+    let parts = job_parts(job)?;
+    if parts.queue.is_nil() || !parts.timer.is_nil() {
+        return Ok(());
+    }
+    let args = list![job; cx];
+    let timer = add_idle_timer(parts.secs, true, sym::IDLE_CHUNKED_RUN.into(), args, env, cx)?;
+    set_field(job, TIMER, timer)
+}
+
+/// Call FUNCTION with each of ITEMS once rune has been idle for SECS
+/// seconds. Items are processed for at most SLICE seconds at a time before
+/// yielding to the event loop, and the rest are processed later in the same
+/// idle period if there is no input, or in the next one. SECS defaults to
+/// `idle-chunked-delay' and SLICE to `idle-chunked-time-slice'. Returns the
+/// job, which can be passed to `idle-chunked-enqueue' to add more work.
+#[defun]
+fn run_when_idle_chunked<'ob>(
+    function: Object<'ob>,
+    items: Object<'ob>,
+    secs: Option<Object<'ob>>,
+    slice: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let secs = seconds(secs, var_seconds(sym::IDLE_CHUNKED_DELAY, env, cx)?)?;
+    let slice = seconds(slice, var_seconds(sym::IDLE_CHUNKED_TIME_SLICE, env, cx)?)?;
+    let items: Vec<_> = items.as_list()?.collect::<Result<_, _>>()?;
+    let mut record = cx.vec_with_capacity(7);
+    record.push(sym::IDLE_CHUNKED_JOB.into());
+    record.push(function);
+    record.push(slice_into_list(&items, None, cx));
+    record.push(cx.add(secs.max(0.0)));
+    record.push(cx.add(slice.max(0.0)));
+    record.push(NIL);
+    record.push(NIL);
+    let job = cx.add(RecordBuilder(record));
+    activate(job, env, cx)?;
+    Ok(job)
+}
+
+/// Add ITEMS to the end of the work queued for JOB.
+#[defun]
+fn idle_chunked_enqueue<'ob>(
+    job: Object<'ob>,
+    items: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let mut queue: Vec<_> = job_parts(job)?.queue.as_list()?.collect::<Result<_, _>>()?;
+    for item in items.as_list()? {
+        queue.push(item?);
+    }
+    set_field(job, QUEUE, slice_into_list(&queue, None, cx))?;
+    activate(job, env, cx)?;
+    Ok(job)
+}
+
+/// Return the items still queued for JOB.
+#[defun]
+fn idle_chunked_pending(job: Object) -> Result<Object> {
+    Ok(job_parts(job)?.queue)
+}
+
+/// Stop JOB and drop the work queued for it. Returns the items that were
+/// still queued.
+#[defun]
+fn idle_chunked_cancel<'ob>(
+    job: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let parts = job_parts(job)?;
+    for timer in [parts.timer, parts.continuation] {
+        if !timer.is_nil() {
+            remove_timer(timer, env, cx)?;
+        }
+    }
+    for index in [QUEUE, TIMER, CONTINUATION] {
+        set_field(job, index, NIL)?;
+    }
+    Ok(parts.queue)
+}
+
+/// Process the work queued for JOB for one time slice. This is called by the
+/// idle timers of the job.
+#[defun]
+fn idle_chunked_run(job: &Rto<Object>, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let parts = job_parts(job.bind(cx))?;
+    if !parts.continuation.is_nil() {
+        remove_timer(parts.continuation, env, cx)?;
+        set_field(job.bind(cx), CONTINUATION, NIL)?;
+    }
+    let function: Function = parts.function.try_into()?;
+    root!(function, cx);
+    let deadline = Instant::now() + Duration::try_from_secs_f64(parts.slice)?;
+    let mut result = Ok(());
+    loop {
+        let ObjectType::Cons(queue) = job_parts(job.bind(cx))?.queue.untag() else { break };
+        // remove the item first, so one that signals an error is not retried
+        set_field(job.bind(cx), QUEUE, queue.cdr())?;
+        let frame = &mut CallFrame::new(env);
+        frame.push_arg(queue.car());
+        if let Err(e) = function.call(frame, None, cx) {
+            result = Err(e.into());
+            break;
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+    let parts = job_parts(job.bind(cx))?;
+    if parts.queue.is_nil() {
+        if !parts.timer.is_nil() {
+            remove_timer(parts.timer, env, cx)?;
+        }
+        set_field(job.bind(cx), TIMER, NIL)?;
+    } else {
+        let idle = crate::keyboard::idle_since().map(|x| crate::replay::now().saturating_sub(x));
+        let secs = idle.unwrap_or_default().as_secs_f64() + YIELD_SECS;
+        let args = list![job.bind(cx); cx];
+        let timer = add_idle_timer(secs, false, sym::IDLE_CHUNKED_RUN.into(), args, env, cx)?;
+        set_field(job.bind(cx), CONTINUATION, timer)?;
+    }
+    result.map(|()| false)
+}
+
+defsym!(IDLE_CHUNKED_JOB);
+defvar!(IDLE_CHUNKED_DELAY, 0.5);
+defvar!(IDLE_CHUNKED_TIME_SLICE, 0.05);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_idle_chunked() {
+        assert_lisp(
+            "(let* ((done nil)
+                    (job (run-when-idle-chunked (lambda (x) (setq done (cons x done))) '(1 2) 0 0)))
+               (sleep-for 0.01)
+               (list done (idle-chunked-pending job) (length timer-idle-list)))",
+            "(nil (1 2) 1)",
+        );
+        crate::keyboard::start_idle();
+        assert_lisp(
+            "(let* ((done nil)
+                    (job (run-when-idle-chunked (lambda (x) (setq done (cons x done))) '(1 2 3) 0 0)))
+               (sleep-for 0.05)
+               (let ((first (reverse done)))
+                 (idle-chunked-enqueue job '(4 5))
+                 (sleep-for 0.05)
+                 (list first (reverse done) (idle-chunked-pending job) timer-idle-list)))",
+            "((1 2 3) (1 2 3 4 5) nil nil)",
+        );
+        assert_lisp(
+            "(let ((job (run-when-idle-chunked #'car '(1 2) 10)))
+               (list (idle-chunked-cancel job) (idle-chunked-pending job) timer-idle-list))",
+            "((1 2) nil nil)",
+        );
+        crate::keyboard::stop_idle();
+    }
+}
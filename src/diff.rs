@@ -0,0 +1,356 @@
+//! Computing and applying unified diffs.
+//!
+//! Diffs are computed line by line with the `similar` crate. Patches are
+//! applied the same way as `patch`: each hunk is first tried where its header
+//! says it should go, adjusted by how much the earlier hunks changed the line
+//! count, and otherwise at the closest place where its context and removed
+//! lines match. There is no fuzz, so a hunk whose context has changed fails.
+use crate::core::{
+    env::Env,
+    gc::{Context, Rt, Rto},
+    object::{Object, ObjectType},
+};
+use crate::fns::slice_into_list;
+use crate::insdel::{replace_region, with_current_buffer};
+use anyhow::{bail, ensure, Result};
+use rune_core::macros::root;
+use rune_macros::defun;
+use similar::TextDiff;
+
+/// The text of SOURCE, which is a string or a buffer.
+fn source_text(source: Object, env: &Rt<Env>) -> Result<String> {
+    match source.untag() {
+        ObjectType::String(string) => Ok(string.to_string()),
+        ObjectType::Buffer(buffer) => env.with_buffer(buffer, |buffer| buffer.text.to_string()),
+        _ => bail!("Invalid diff source: {source}"),
+    }
+}
+
+/// Return a unified diff of OLD and NEW, which are strings or buffers. The
+/// file names in the header are OLD-LABEL and NEW-LABEL, which default to
+/// "a" and "b". CONTEXT is the number of unchanged lines shown around each
+/// change, which defaults to 3. Return the empty string if there are no
+/// differences.
+#[defun]
+fn diff_unified(
+    old: Object,
+    new: Object,
+    old_label: Option<&str>,
+    new_label: Option<&str>,
+    context: Option<usize>,
+    env: &Rt<Env>,
+) -> Result<String> {
+    let old = source_text(old, env)?;
+    let new = source_text(new, env)?;
+    if old == new {
+        return Ok(String::new());
+    }
+    let diff = TextDiff::from_lines(&old, &new);
+    let old_label = old_label.unwrap_or("a");
+    let new_label = new_label.unwrap_or("b");
+    let mut unified = diff.unified_diff();
+    unified.context_radius(context.unwrap_or(3)).header(old_label, new_label);
+    Ok(unified.to_string())
+}
+
+#[derive(Debug, PartialEq)]
+enum Line {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct Hunk {
+    /// The line in the old text where the hunk starts, counting from 1.
+    old_start: usize,
+    new_start: usize,
+    lines: Vec<Line>,
+    /// The text of the hunk in the patch, including its header.
+    text: String,
+}
+
+impl Hunk {
+    /// The lines of the hunk before and after it is applied.
+    fn old_and_new(&self, reverse: bool) -> (Vec<&str>, Vec<&str>) {
+        let mut old = Vec::new();
+        let mut new = Vec::new();
+        for line in &self.lines {
+            match line {
+                Line::Context(x) => {
+                    old.push(x.as_str());
+                    new.push(x.as_str());
+                }
+                Line::Remove(x) => old.push(x),
+                Line::Add(x) => new.push(x),
+            }
+        }
+        if reverse {
+            (new, old)
+        } else {
+            (old, new)
+        }
+    }
+
+    /// Handle a "\ No newline at end of file" line, which means that the line
+    /// before it has no newline.
+    fn no_newline(&mut self, marker: &str) {
+        if let Some(Line::Context(x) | Line::Remove(x) | Line::Add(x)) = self.lines.last_mut() {
+            if x.ends_with('\n') {
+                x.pop();
+            }
+        }
+        self.text.push_str(marker);
+    }
+}
+
+/// Parse a hunk header such as "@@ -1,3 +1,4 @@" into the start line and
+/// length of the old and new ranges. A range without a length has one line.
+fn parse_hunk_header(header: &str) -> Result<[usize; 4]> {
+    let range = |x: Option<&str>, prefix| -> Result<[usize; 2]> {
+        let Some(range) = x.and_then(|x| x.strip_prefix(prefix)) else {
+            bail!("Invalid hunk header: {}", header.trim_end())
+        };
+        Ok(match range.split_once(',') {
+            Some((start, len)) => [start.parse()?, len.parse()?],
+            None => [range.parse()?, 1],
+        })
+    };
+    let mut fields = header.strip_prefix("@@ ").unwrap_or_default().split(' ');
+    let [old_start, old_len] = range(fields.next(), '-')?;
+    let [new_start, new_len] = range(fields.next(), '+')?;
+    Ok([old_start, old_len, new_start, new_len])
+}
+
+/// Parse the hunks of the unified diff `patch`. Each hunk contains as many
+/// lines as its header says, and everything between the hunks, such as the
+/// file headers, is ignored.
+fn parse_patch(patch: &str) -> Result<Vec<Hunk>> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    let mut old_left = 0;
+    let mut new_left = 0;
+    for line in patch.split_inclusive('\n') {
+        if old_left == 0 && new_left == 0 {
+            // the last line of a hunk can be followed by a newline marker
+            if let Some(hunk) = hunks.last_mut().filter(|_| line.starts_with('\\')) {
+                hunk.no_newline(line);
+                continue;
+            }
+            if line.starts_with("@@ ") {
+                let [old_start, old_len, new_start, new_len] = parse_hunk_header(line)?;
+                (old_left, new_left) = (old_len, new_len);
+                let text = line.to_owned();
+                hunks.push(Hunk { old_start, new_start, lines: Vec::new(), text });
+            }
+            continue;
+        }
+        let hunk = hunks.last_mut().unwrap();
+        let text = line.get(1..).unwrap_or_default().to_owned();
+        let line_kind = match line.as_bytes()[0] {
+            b' ' => Line::Context(text),
+            // an empty context line that lost its space
+            b'\n' => Line::Context(line.to_owned()),
+            b'-' => Line::Remove(text),
+            b'+' => Line::Add(text),
+            b'\\' => {
+                hunk.no_newline(line);
+                continue;
+            }
+            _ => bail!("Invalid line in hunk: {}", line.trim_end()),
+        };
+        match line_kind {
+            Line::Context(_) => {
+                old_left = old_left.saturating_sub(1);
+                new_left = new_left.saturating_sub(1);
+            }
+            Line::Remove(_) => old_left = old_left.saturating_sub(1),
+            Line::Add(_) => new_left = new_left.saturating_sub(1),
+        }
+        hunk.lines.push(line_kind);
+        hunk.text.push_str(line);
+    }
+    ensure!(old_left == 0 && new_left == 0, "Patch ends in the middle of a hunk");
+    Ok(hunks)
+}
+
+/// Apply `hunks` to `text`, or unapply them if `reverse` is true.
+fn apply_hunks(text: &str, hunks: &[Hunk], reverse: bool) -> Result<String> {
+    let mut lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut offset: isize = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let (old, new) = hunk.old_and_new(reverse);
+        let line = if reverse { hunk.new_start } else { hunk.old_start };
+        // a hunk that only adds lines starts after the line in its header
+        let start = if old.is_empty() { line } else { line.saturating_sub(1) };
+        let expected = start.saturating_add_signed(offset).min(lines.len());
+        let Some(pos) = find_lines(&lines, &old, expected) else {
+            bail!("Hunk #{} FAILED at {line}", i + 1)
+        };
+        lines.splice(pos..pos + old.len(), new.iter().copied());
+        offset += new.len() as isize - old.len() as isize;
+        offset += pos as isize - expected as isize;
+    }
+    Ok(lines.concat())
+}
+
+/// Find the position of `needle` in `lines` that is closest to `expected`.
+fn find_lines(lines: &[&str], needle: &[&str], expected: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(needle.len())?;
+    let matches = |pos: usize| lines[pos..pos + needle.len()] == *needle;
+    let expected = expected.min(last);
+    (0..=last).find_map(|distance| {
+        let after = expected + distance;
+        let before = expected.checked_sub(distance);
+        if after <= last && matches(after) {
+            Some(after)
+        } else {
+            before.filter(|&x| matches(x))
+        }
+    })
+}
+
+fn apply_to_source<'ob>(
+    target: &Rto<Object>,
+    hunks: &[Hunk],
+    reverse: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let reverse = reverse.is_some_and(|x| !x.is_nil());
+    let patched = apply_hunks(&source_text(target.bind(cx), env)?, hunks, reverse)?;
+    match target.untag(cx) {
+        ObjectType::Buffer(buffer) => {
+            root!(buffer, cx);
+            with_current_buffer(buffer, env, cx, |env, cx| {
+                let end = env.current_buffer.get().text.len_chars() + 1;
+                replace_region(1, end, &patched, env, cx)
+            })?;
+            Ok(target.bind(cx))
+        }
+        _ => Ok(cx.add(patched)),
+    }
+}
+
+/// Apply the unified diff PATCH to TARGET, a string or a buffer. If TARGET
+/// is a string the patched string is returned. If it is a buffer its
+/// contents are replaced and the buffer is returned. If REVERSE is non-nil
+/// the patch is undone instead. Signal an error if any hunk does not apply,
+/// in which case TARGET is left unchanged.
+#[defun]
+fn diff_apply_patch<'ob>(
+    target: &Rto<Object>,
+    patch: &str,
+    reverse: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let hunks = parse_patch(patch)?;
+    apply_to_source(target, &hunks, reverse, env, cx)
+}
+
+/// Apply HUNK, a single hunk of a unified diff starting with its "@@" line,
+/// to TARGET. This is like `diff-apply-patch', but signals an error if HUNK
+/// does not contain exactly one hunk.
+#[defun]
+fn diff_apply_hunk<'ob>(
+    target: &Rto<Object>,
+    hunk: &str,
+    reverse: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let hunks = parse_patch(hunk)?;
+    ensure!(hunks.len() == 1, "Expected a single hunk, found {}", hunks.len());
+    apply_to_source(target, &hunks, reverse, env, cx)
+}
+
+/// Split the unified diff PATCH into a list of its hunks, each of which is a
+/// string starting with its "@@" line.
+#[defun]
+fn diff_hunks<'ob>(patch: &str, cx: &'ob Context) -> Result<Object<'ob>> {
+    let hunks: Vec<_> = parse_patch(patch)?.into_iter().map(|x| cx.add(x.text)).collect();
+    Ok(slice_into_list(&hunks, None, cx))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    const OLD: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+
+    fn diff(old: &str, new: &str) -> String {
+        TextDiff::from_lines(old, new).unified_diff().header("a", "b").to_string()
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let hunk = "@@ -1,2 +1,2 @@\n-x\n+y\n z\n\\ No newline at end of file\n";
+        let hunks = parse_patch(&format!("--- a\n+++ b\n{hunk}")).unwrap();
+        assert_eq!(
+            hunks,
+            [Hunk {
+                old_start: 1,
+                new_start: 1,
+                lines: vec![
+                    Line::Remove("x\n".into()),
+                    Line::Add("y\n".into()),
+                    Line::Context("z".into())
+                ],
+                text: hunk.into(),
+            }]
+        );
+        // removed lines that look like file headers are still part of the hunk
+        let hunks = parse_patch("@@ -1,2 +0,0 @@\n--- a\n-+++ b\n").unwrap();
+        assert_eq!(hunks[0].lines.len(), 2);
+        assert!(parse_patch("@@ -1,2 +1,2 @@\n x\n").is_err());
+        assert_eq!(parse_hunk_header("@@ -3 +0,0 @@").unwrap(), [3, 1, 0, 0]);
+        assert!(parse_hunk_header("@@ 3 4 @@").is_err());
+    }
+
+    #[test]
+    fn test_apply_roundtrip() {
+        let new = "zero\none\ntwo\nthree\n4\nfive\nsix\nseven\neight\nnine\nten\neleven";
+        let hunks = parse_patch(&diff(OLD, new)).unwrap();
+        assert_eq!(apply_hunks(OLD, &hunks, false).unwrap(), new);
+        assert_eq!(apply_hunks(new, &hunks, true).unwrap(), OLD);
+        // the hunks still apply after lines are added before them
+        let shifted = format!("extra\nextra\n{OLD}");
+        let patched = apply_hunks(&shifted, &hunks, false).unwrap();
+        assert_eq!(patched, format!("extra\nextra\n{new}"));
+        // but not when their context is gone
+        assert!(apply_hunks("one\n", &hunks, false).is_err());
+    }
+
+    #[test]
+    fn test_apply_to_empty() {
+        let hunks = parse_patch(&diff("", "a\nb\n")).unwrap();
+        assert_eq!(apply_hunks("", &hunks, false).unwrap(), "a\nb\n");
+        assert_eq!(apply_hunks("a\nb\n", &hunks, true).unwrap(), "");
+    }
+
+    #[test]
+    fn test_diff_lisp() {
+        assert_lisp("(diff-unified \"a\n\" \"a\n\")", "\"\"");
+        assert_lisp(
+            "(diff-unified \"a\nb\n\" \"a\nc\n\" \"old\" \"new\" 0)",
+            "\"--- old\n+++ new\n@@ -2 +2 @@\n-b\n+c\n\"",
+        );
+        assert_lisp(
+            "(let ((patch (diff-unified \"a\nb\nc\n\" \"A\nb\nC\n\" nil nil 0)))
+               (list (length (diff-hunks patch))
+                     (diff-apply-patch \"a\nb\nc\n\" patch)
+                     (diff-apply-hunk \"a\nb\nc\n\" (car (diff-hunks patch)))
+                     (diff-apply-patch \"A\nb\nC\n\" patch t)))",
+            "(2 \"A\nb\nC\n\" \"A\nb\nc\n\" \"a\nb\nc\n\")",
+        );
+        assert_lisp(
+            "(let ((buffer (get-buffer-create \"diff-test\")))
+               (set-buffer buffer)
+               (insert \"x\ny\n\")
+               (diff-apply-patch buffer (diff-unified \"x\ny\n\" \"x\nz\n\"))
+               (diff-unified buffer \"x\nz\n\"))",
+            "\"\"",
+        );
+    }
+}
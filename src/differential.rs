@@ -0,0 +1,121 @@
+//! Differential tests between the interpreter and the bytecode VM.
+//!
+//! Every form in [`CORPUS`] is evaluated by the interpreter, and is also
+//! compiled with `byte-compile` and run by the VM. Both backends have to
+//! produce the same value, or signal the same error. This requires the
+//! compiler, so the environment is bootstrapped first.
+use crate::core::{
+    env::{intern, sym, Env},
+    gc::{Context, RootSet, Rt},
+    object::{Gc, LispString, NIL, TRUE},
+};
+use crate::{interpreter, reader};
+use rune_core::macros::root;
+
+const CORPUS: &[&str] = &[
+    // arithmetic
+    "(+ 1 2 3)",
+    "(- 10 4.5)",
+    "(* 2 (/ 9 2))",
+    "(list (1+ 1) (1- 1) (% 7 3) (max 1 5 3) (min 4 2))",
+    "(list (< 1 2) (> 1 2) (<= 2 2) (>= 1 2) (= 1 1.0))",
+    // binding
+    "(let ((x 1) (y 2)) (+ x y))",
+    "(let* ((x 1) (y (+ x 1))) (* x y))",
+    "(let ((x 1)) (setq x (+ x 10)) x)",
+    "(let ((x 1)) (let ((x 2)) x))",
+    // control flow
+    "(if nil 1 2 3)",
+    "(cond ((eq 1 2) 'a) ((eq 1 1) 'b) (t 'c))",
+    "(and 1 2 nil 3)",
+    "(or nil nil 4)",
+    "(let ((i 0) (sum 0)) (while (< i 10) (setq sum (+ sum i)) (setq i (1+ i))) sum)",
+    "(prog1 1 2 3)",
+    "(prog2 1 2 3)",
+    "(progn)",
+    // closures
+    "(let ((x 5)) (funcall (lambda (y) (+ x y)) 2))",
+    "(let ((f (let ((n 0)) (lambda () (setq n (1+ n)))))) (funcall f) (funcall f))",
+    "(mapcar (lambda (x) (* x x)) '(1 2 3))",
+    "(apply #'+ 1 2 '(3 4))",
+    "(funcall (lambda (a &optional b &rest c) (list a b c)) 1 2 3 4)",
+    // data
+    "(let ((l (list 1 2 3))) (setcar l 4) l)",
+    "(let ((v (make-vector 3 0))) (aset v 1 'x) v)",
+    "(nreverse (list 1 2 3))",
+    "(concat \"foo\" \"bar\")",
+    "(substring \"hello\" 1 3)",
+    "(nth 2 '(a b c d))",
+    "(assq 'b '((a . 1) (b . 2)))",
+    "(memq 'c '(a b c d))",
+    "(equal '(1 [2 \"3\"]) (list 1 (vector 2 \"3\")))",
+    "(length '(1 2 3))",
+    // non-local exits
+    "(catch 'done (throw 'done 7) 8)",
+    "(condition-case nil (car 1) (error 'caught))",
+    "(condition-case err (signal 'wrong-type-argument '(x)) (error (car err)))",
+    "(let ((x 1)) (unwind-protect (setq x 2) (setq x 3)) x)",
+    // errors
+    "(car 1)",
+    "(funcall 'rune--undefined-function)",
+    "(error \"Boom %d\" 1)",
+];
+
+/// Evaluate `form`, returning its printed value, or the error symbol if it
+/// signals. The form is wrapped in a `condition-case` so that both backends
+/// report errors the same way.
+fn eval(form: &str, compile: bool, env: &mut Rt<Env>, cx: &mut Context) -> String {
+    let wrapped = format!("(condition-case err {form} (error (list 'signal (car err))))");
+    let code = if compile {
+        format!("(funcall (byte-compile '(lambda () {wrapped})))")
+    } else {
+        wrapped
+    };
+    let obj = reader::read(&code, cx).unwrap().0;
+    root!(obj, cx);
+    root!(lexical, TRUE, cx);
+    match interpreter::eval(obj, Some(lexical), env, cx) {
+        Ok(val) => val.to_string(),
+        Err(e) => panic!("evaluating {code} failed: {e}"),
+    }
+}
+
+fn bootstrap(env: &mut Rt<Env>, cx: &mut Context) {
+    sym::init_symbols();
+    crate::core::env::init_variables(cx, env);
+    crate::data::defalias(intern("not", cx), (sym::NULL).into(), None, env).unwrap();
+    crate::buffer::get_buffer_create(cx.add("*scratch*"), Some(NIL), cx).unwrap();
+    let file: Gc<&LispString> = cx.add_as("bootstrap.el");
+    root!(file, cx);
+    crate::lread::load(file, None, None, cx, env).unwrap();
+}
+
+/// Run `test` in a bootstrapped environment.
+pub(crate) fn with_bootstrap(test: impl FnOnce(&mut Rt<Env>, &mut Context) + Send + 'static) {
+    // loading the bootstrap files needs more stack than the default test thread
+    let thread = std::thread::Builder::new().stack_size(64 * 1024 * 1024).spawn(move || {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        bootstrap(env, cx);
+        test(env, cx);
+    });
+    thread.unwrap().join().unwrap();
+}
+
+#[test]
+fn interpreter_matches_bytecode() {
+    with_bootstrap(|env, cx| {
+        let mut failures = Vec::new();
+        for form in CORPUS {
+            let interpreted = eval(form, false, env, cx);
+            let compiled = eval(form, true, env, cx);
+            if interpreted != compiled {
+                failures.push(format!(
+                    "{form}\n  interpreter: {interpreted}\n  bytecode:    {compiled}"
+                ));
+            }
+        }
+        assert!(failures.is_empty(), "backends disagree:\n{}", failures.join("\n"));
+    });
+}
@@ -1,24 +1,241 @@
 //! Buffer editing utilities.
+use crate::buffer::get_buffer_create;
 use crate::core::{
-    env::{ArgSlice, Env},
+    env::{sym, ArgSlice, Env},
     gc::{Context, Rt},
     object::{Object, ObjectType},
 };
+use crate::data::LispError;
+use crate::insdel::{signal_after_change, signal_before_change};
 use anyhow::{bail, ensure, Result};
+use num_traits::ToPrimitive;
 use rune_macros::defun;
-use std::{fmt::Write as _, io::Write};
+use std::fmt::Write as _;
 
+const MESSAGES_BUFFER: &str = "*Messages*";
+
+/// Display a message made by passing ARGS to `format-message'. In batch mode
+/// the message is written to stderr. Every message is also logged to the
+/// `*Messages*' buffer.
 #[defun]
-fn message(format_string: &str, args: &[Object]) -> Result<String> {
-    let message = format(format_string, args)?;
-    println!("MESSAGE: {message}");
-    std::io::stdout().flush()?;
-    Ok(message)
+fn message(
+    format_string: Option<&str>,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<Option<String>> {
+    // TODO: a nil format string should clear the echo area
+    let Some(format_string) = format_string else { return Ok(None) };
+    let message = format_message(format_string, Rt::bind_slice(env.stack.arg_slice(args), cx))?;
+    show_message(&message, env, cx)?;
+    Ok(Some(message))
+}
+
+/// Display the already formatted `message` like `message'.
+pub(crate) fn show_message(message: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let batch = env.vars.get(sym::NONINTERACTIVE).is_some_and(|x| !x.bind(cx).is_nil());
+    if batch {
+        crate::print::write_stderr(&format!("{message}\n"))?;
+    }
+    log_message(message, MESSAGES_BUFFER, env, cx)
+}
+
+/// Append `message` to the log buffer `buffer_name`. If `message-log-max` is
+/// nil nothing is logged, and if it is an integer the oldest lines are removed
+/// so that the log holds at most that many lines.
+fn log_message(message: &str, buffer_name: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let max_lines = match env.vars.get(sym::MESSAGE_LOG_MAX).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::NIL) => return Ok(()),
+        Some(ObjectType::Int(max)) => Some(usize::try_from(max).unwrap_or(0)),
+        _ => None,
+    };
+    let ObjectType::Buffer(buffer) = get_buffer_create(cx.add(buffer_name), None, cx)?.untag()
+    else {
+        unreachable!("get-buffer-create did not return a buffer")
+    };
+    env.with_buffer_mut(buffer, |buffer| {
+        let text = &mut buffer.text;
+        text.set_cursor(text.len_chars());
+        text.insert(message);
+        text.insert_char('\n');
+        let Some(max_lines) = max_lines else { return };
+        let contents = text.as_str();
+        let extra = contents.matches('\n').count().saturating_sub(max_lines);
+        if extra > 0 {
+            let end = contents.match_indices('\n').nth(extra - 1).map_or(0, |(i, _)| i + 1);
+            let end_chars = contents[..end].chars().count();
+            text.delete_range(0, end_chars);
+        }
+    })
 }
 
+defvar!(MESSAGE_LOG_MAX, 1000);
 defvar!(MESSAGE_NAME);
 defvar!(MESSAGE_TYPE, "new message");
 
+/// A parsed format specification: `%[flags][width][.precision]character`.
+#[derive(Default)]
+struct FormatSpec {
+    left_align: bool,
+    zero_pad: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    character: char,
+}
+
+impl FormatSpec {
+    /// Parse a spec from the text following a `%`. Returns the spec and the
+    /// number of bytes it used.
+    fn parse(text: &str) -> Result<(Self, usize)> {
+        let mut spec = FormatSpec::default();
+        let mut chars = text.char_indices().peekable();
+        while let Some((_, c)) = chars.next_if(|(_, c)| "-0+ #".contains(*c)) {
+            match c {
+                '-' => spec.left_align = true,
+                '0' => spec.zero_pad = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                _ => spec.alternate = true,
+            }
+        }
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            spec.width = spec.width * 10 + c.to_digit(10).unwrap() as usize;
+        }
+        if chars.next_if(|(_, c)| *c == '.').is_some() {
+            let mut precision = 0;
+            while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                precision = precision * 10 + c.to_digit(10).unwrap() as usize;
+            }
+            spec.precision = Some(precision);
+        }
+        let Some((idx, character)) = chars.next() else {
+            bail!("Format string ends in middle of format specifier")
+        };
+        spec.character = character;
+        Ok((spec, idx + character.len_utf8()))
+    }
+
+    /// Add the sign flags to a formatted number.
+    fn sign(&self, number: String) -> String {
+        if number.starts_with('-') {
+            number
+        } else if self.plus {
+            format!("+{number}")
+        } else if self.space {
+            format!(" {number}")
+        } else {
+            number
+        }
+    }
+
+    /// Pad `text` to the field width.
+    fn pad(&self, text: String, numeric: bool) -> String {
+        let len = text.chars().count();
+        if len >= self.width {
+            return text;
+        }
+        let fill = self.width - len;
+        if self.left_align {
+            text + &" ".repeat(fill)
+        } else if self.zero_pad && numeric {
+            let sign_len = text.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+            let (sign, digits) = text.split_at(sign_len);
+            format!("{sign}{}{digits}", "0".repeat(fill))
+        } else {
+            " ".repeat(fill) + &text
+        }
+    }
+}
+
+/// Format `float` like the C printf conversion `%e`.
+fn format_exponent(float: f64, precision: usize) -> String {
+    let sci = format!("{float:.precision$e}");
+    let (mantissa, exp) = sci.split_once('e').expect("float exponent missing");
+    let exp: i32 = exp.parse().expect("float exponent was not an integer");
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exp.abs())
+}
+
+/// Format `float` like the C printf conversion `%g`. This uses `%e` if the
+/// exponent is less than -4 or not less than the precision, and `%f`
+/// otherwise. Trailing zeros are removed unless `alternate` is set.
+fn format_general(float: f64, precision: usize, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let exp = {
+        let sci = format!("{float:.prec$e}", prec = precision - 1);
+        sci.split_once('e').expect("float exponent missing").1.parse::<i64>().unwrap()
+    };
+    let strip = |text: &str| -> String {
+        if alternate || !text.contains('.') {
+            text.to_owned()
+        } else {
+            text.trim_end_matches('0').trim_end_matches('.').to_owned()
+        }
+    };
+    if exp < -4 || exp >= precision as i64 {
+        let sci = format_exponent(float, precision - 1);
+        let (mantissa, exp) = sci.split_once('e').unwrap();
+        format!("{}e{exp}", strip(mantissa))
+    } else {
+        let decimals = (precision as i64 - 1 - exp) as usize;
+        strip(&format!("{float:.decimals$}"))
+    }
+}
+
+/// Format a non finite float the way printf does.
+fn format_non_finite(float: f64) -> Option<String> {
+    if float.is_nan() {
+        Some("nan".to_owned())
+    } else if float.is_infinite() {
+        Some(if float < 0.0 { "-inf" } else { "inf" }.to_owned())
+    } else {
+        None
+    }
+}
+
+fn format_integer_arg(spec: &FormatSpec, obj: Object) -> Result<String> {
+    let text = match obj.untag() {
+        ObjectType::Int(x) => match spec.character {
+            'o' if spec.alternate => format!("{x:#o}").replace("0o", "0"),
+            'o' => format!("{x:o}"),
+            'x' if spec.alternate => format!("{x:#x}"),
+            'x' => format!("{x:x}"),
+            'X' if spec.alternate => format!("{x:#X}").replace("0x", "0X"),
+            'X' => format!("{x:X}"),
+            _ => x.to_string(),
+        },
+        ObjectType::BigInt(x) => match spec.character {
+            'o' => format!("{:o}", x.get()),
+            'x' => format!("{:x}", x.get()),
+            'X' => format!("{:X}", x.get()),
+            _ => x.get().to_string(),
+        },
+        // floats are truncated towards zero
+        ObjectType::Float(x) => format!("{}", x.trunc()),
+        _ => bail!("Format specifier doesn't match argument type"),
+    };
+    Ok(spec.sign(text))
+}
+
+fn format_float_arg(spec: &FormatSpec, obj: Object) -> Result<String> {
+    let float = match obj.untag() {
+        ObjectType::Int(x) => x as f64,
+        ObjectType::Float(x) => **x,
+        ObjectType::BigInt(x) => x.get().to_f64().unwrap_or(f64::NAN),
+        _ => bail!("Format specifier doesn't match argument type"),
+    };
+    let precision = spec.precision.unwrap_or(6);
+    let text = format_non_finite(float).unwrap_or_else(|| match spec.character {
+        'e' => format_exponent(float, precision),
+        'f' => format!("{float:.precision$}"),
+        _ => format_general(float, precision, spec.alternate),
+    });
+    Ok(spec.sign(text))
+}
+
 #[defun]
 fn format(string: &str, objects: &[Object]) -> Result<String> {
     let mut result = String::new();
@@ -39,23 +256,33 @@ fn format(string: &str, objects: &[Object]) -> Result<String> {
     };
     while let Some(start) = remaining.find(&mut is_format_char) {
         result += &remaining[..start];
-        let Some(specifier) = remaining.as_bytes().get(start + 1) else {
-            bail!("Format string ends in middle of format specifier")
-        };
+        let (spec, len) = FormatSpec::parse(&remaining[start + 1..])?;
         // "%%" inserts a single "%" in the output
-        if *specifier == b'%' {
+        if spec.character == '%' {
             result.push('%');
         } else {
-            // TODO: currently handles all format types the same. Need to check the modifier characters.
             let Some(val) = arguments.next() else {
                 bail!("Not enough arguments for format string")
             };
-            match val.untag() {
-                ObjectType::String(string) => write!(result, "{string}")?,
-                obj => write!(result, "{obj}")?,
-            }
+            let (text, numeric) = match spec.character {
+                's' | 'S' => {
+                    let text = match val.untag() {
+                        ObjectType::String(string) if spec.character == 's' => string.to_string(),
+                        obj => obj.to_string(),
+                    };
+                    match spec.precision {
+                        Some(precision) => (text.chars().take(precision).collect(), false),
+                        None => (text, false),
+                    }
+                }
+                'c' => (char::try_from(*val)?.to_string(), false),
+                'd' | 'o' | 'x' | 'X' => (format_integer_arg(&spec, *val)?, true),
+                'e' | 'f' | 'g' => (format_float_arg(&spec, *val)?, true),
+                c => bail!("Invalid format operation %{c}"),
+            };
+            result += &spec.pad(text, numeric);
         }
-        remaining = &remaining[start + 2..];
+        remaining = &remaining[start + 1 + len..];
     }
     result += remaining;
     ensure!(arguments.next().is_none(), "Too many arguments for format string");
@@ -63,7 +290,7 @@ fn format(string: &str, objects: &[Object]) -> Result<String> {
 }
 
 #[defun]
-fn format_message(string: &str, objects: &[Object]) -> Result<String> {
+pub(crate) fn format_message(string: &str, objects: &[Object]) -> Result<String> {
     let formatted = format(string, objects)?;
     // TODO: implement support for `text-quoting-style`.
     Ok(formatted
@@ -73,24 +300,28 @@ fn format_message(string: &str, objects: &[Object]) -> Result<String> {
 }
 
 #[defun]
-fn string_to_char(string: &str) -> char {
-    string.chars().next().unwrap_or('\0')
-}
-
-#[defun]
-fn char_to_string(chr: char) -> String {
-    format!("{chr}")
-}
-
-#[defun]
-pub(crate) fn insert(args: ArgSlice, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
-    let env = &mut **env; // Deref into rooted type so we can split the borrow
-    let buffer = env.current_buffer.get_mut();
-    let args = Rt::bind_slice(env.stack.arg_slice(args), cx);
-    for arg in args {
-        buffer.insert(*arg)?;
+pub(crate) fn insert(args: ArgSlice, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let args_slice = Rt::bind_slice(env.stack.arg_slice(args), cx);
+    if args_slice
+        .iter()
+        .all(|x| matches!(x.untag(), ObjectType::String(s) if s.is_empty()))
+    {
+        return Ok(());
     }
-    Ok(())
+    let beg = env.current_buffer.get().text.cursor().chars() + 1;
+    signal_before_change(beg, beg, env, cx)?;
+    // the hooks may have moved point
+    let beg = env.current_buffer.get().text.cursor().chars() + 1;
+    {
+        let env = &mut **env; // Deref into rooted type so we can split the borrow
+        let buffer = env.current_buffer.get_mut();
+        let args = Rt::bind_slice(env.stack.arg_slice(args), cx);
+        for arg in args {
+            buffer.insert(*arg)?;
+        }
+    }
+    let end = env.current_buffer.get().text.cursor().chars() + 1;
+    signal_after_change(beg, end, 0, env, cx)
 }
 
 // TODO: this should not throw and error. Buffer will always be present.
@@ -122,8 +353,18 @@ pub(crate) fn point_marker(env: &mut Rt<Env>) -> usize {
 }
 
 #[defun]
-fn delete_region(start: usize, end: usize, env: &mut Rt<Env>) -> Result<()> {
-    env.current_buffer.get_mut().delete(start, end)
+fn delete_region(start: usize, end: usize, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let (start, end) = (start.min(end), start.max(end));
+    let max = env.current_buffer.get().text.len_chars() + 1;
+    if start < 1 || end > max {
+        bail!(LispError::region_out_of_range(start, end, cx));
+    }
+    if start == end {
+        return Ok(());
+    }
+    signal_before_change(start, end, env, cx)?;
+    env.current_buffer.get_mut().delete(start, end)?;
+    signal_after_change(start, start, end - start, env, cx)
 }
 
 #[defun]
@@ -149,10 +390,7 @@ fn system_name() -> String {
 #[cfg(test)]
 mod test {
     use crate::core::object::NIL;
-    use crate::{
-        buffer::{get_buffer_create, set_buffer},
-        core::gc::RootSet,
-    };
+    use crate::{buffer::set_buffer, core::gc::RootSet};
     use rune_core::macros::root;
 
     use super::*;
@@ -173,6 +411,60 @@ mod test {
         assert!(format("`%s' %s%s%s", &[0.into(), 1.into(), 2.into(), 3.into()]).is_ok());
     }
 
+    #[test]
+    fn test_format_specs() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let float = |x: f64| -> Object { cx.add(x) };
+        assert_eq!(
+            &format("%5d|%-5d|%05d", &[42.into(), 42.into(), (-42).into()]).unwrap(),
+            "   42|42   |-0042"
+        );
+        assert_eq!(
+            &format("%+d %x %X %#o", &[1.into(), 255.into(), 255.into(), 8.into()]).unwrap(),
+            "+1 ff FF 010"
+        );
+        assert_eq!(&format("%d", &[float(2.7)]).unwrap(), "2");
+        assert_eq!(&format("%c", &[97.into()]).unwrap(), "a");
+        assert_eq!(&format("%.2s", &[cx.add("hello")]).unwrap(), "he");
+        assert_eq!(&format("%S", &[cx.add("hi")]).unwrap(), "\"hi\"");
+        assert_eq!(&format("%f", &[float(1.5)]).unwrap(), "1.500000");
+        assert_eq!(&format("%.2f", &[1.into()]).unwrap(), "1.00");
+        assert_eq!(&format("%e", &[float(12345.678)]).unwrap(), "1.234568e+04");
+        assert_eq!(&format("%.1e", &[float(0.000_25)]).unwrap(), "2.5e-04");
+        assert_eq!(&format("%g", &[float(0.0001)]).unwrap(), "0.0001");
+        assert_eq!(&format("%g", &[float(0.000_01)]).unwrap(), "1e-05");
+        assert_eq!(&format("%g", &[float(123_456.0)]).unwrap(), "123456");
+        assert_eq!(&format("%g", &[float(1_234_567.0)]).unwrap(), "1.23457e+06");
+        assert_eq!(&format("%.3g|%#.3g", &[float(2.5), float(2.5)]).unwrap(), "2.5|2.50");
+        assert_eq!(&format("%g", &[float(f64::INFINITY)]).unwrap(), "inf");
+        assert_eq!(&format("%s %S", &[float(0.1), float(1.0)]).unwrap(), "0.1 1.0");
+        assert!(format("%d", &[cx.add("x")]).is_err());
+        assert!(format("%5", &[1.into()]).is_err());
+    }
+
+    #[test]
+    fn test_message_log() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let name = "*messages-test*";
+        let max: Object = 2.into();
+        env.vars.insert(sym::MESSAGE_LOG_MAX, max);
+        for message in ["one", "two", "three"] {
+            log_message(message, name, env, cx).unwrap();
+        }
+        let buffer = get_buffer_create(cx.add(name), None, cx).unwrap();
+        let ObjectType::Buffer(buffer) = buffer.untag() else { unreachable!() };
+        let text = env.with_buffer(buffer, |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "two\nthree\n");
+
+        env.vars.insert(sym::MESSAGE_LOG_MAX, NIL);
+        log_message("four", name, env, cx).unwrap();
+        let text = env.with_buffer(buffer, |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "two\nthree\n");
+    }
+
     #[test]
     fn test_insert() {
         let roots = &RootSet::default();
@@ -203,7 +495,12 @@ mod test {
         insert(ArgSlice::new(2), env, cx).unwrap();
 
         assert_eq!(env.current_buffer.get(), "hello world");
-        delete_region(2, 4, env).unwrap();
+        delete_region(2, 4, env, cx).unwrap();
         assert_eq!(env.current_buffer.get(), "hlo world");
+        crate::interpreter::assert_lisp(
+            "(progn (set-buffer (get-buffer-create \"delete-region-range\"))
+               (condition-case err (delete-region 0 999) (args-out-of-range err)))",
+            "(args-out-of-range 0 999)",
+        );
     }
 }
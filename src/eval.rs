@@ -4,19 +4,19 @@ use crate::core::env::{sym, ArgSlice, CallFrame, Env};
 use crate::core::error::{Type, TypeError};
 use crate::core::gc::{Rt, Rto};
 use crate::core::object::{
-    display_slice, FnArgs, Function, LispString, ObjectType, Symbol, TagType, NIL,
+    with_print_limits, FnArgs, Function, LispString, ObjectType, PrintLimits, Symbol, TagType, NIL,
 };
 use crate::core::{
     gc::Context,
     object::{FunctionType, Gc, Object},
 };
 use crate::data::LispError;
-use crate::fns::{assq, eq};
+use crate::fns::{assq, eq, memq};
 use crate::rooted_iter;
 use anyhow::{anyhow, bail, ensure, Result};
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
-use rune_core::macros::{bail_err, call, list, root};
+use rune_core::macros::{bail_err, call, list, rebind, root};
 use rune_macros::defun;
 use std::fmt::{Display, Formatter};
 
@@ -66,27 +66,125 @@ impl EvalError {
         error.into()
     }
 
+    /// Describe the error for a user. Unlike the `Display` output, this
+    /// includes the symbol and data of a signal, like `(void-function foo)`.
+    pub(crate) fn describe(&self, env: &Rt<Env>, cx: &Context) -> String {
+        match self.error {
+            ErrorType::Signal(id) => match env.get_exception(id) {
+                Some((sym, data)) => Cons::new(sym.bind(cx), data.bind(cx), cx).to_string(),
+                None => "Signal".to_owned(),
+            },
+            _ => self.to_string().trim_end().to_owned(),
+        }
+    }
+
+    /// Check if this error was signaled with `symbol` as the error symbol.
+    pub(crate) fn is_signal(&self, symbol: Symbol, env: &Rt<Env>) -> bool {
+        match self.error {
+            ErrorType::Signal(id) => env.get_exception(id).is_some_and(|(sym, _)| sym == &symbol),
+            _ => false,
+        }
+    }
+
+    /// The error as `(ERROR-SYMBOL . DATA)`, the form it is bound to in a
+    /// `condition-case' handler.
+    pub(crate) fn to_lisp<'ob>(&self, env: &Rt<Env>, cx: &'ob Context) -> &'ob Cons {
+        match &self.error {
+            ErrorType::Signal(id) => {
+                let Some((sym, data)) = env.get_exception(*id) else {
+                    unreachable!("Exception not found")
+                };
+                Cons::new(sym.bind(cx), data.bind(cx), cx)
+            }
+            ErrorType::Err(err) => {
+                if let Some(lisp_error) = err.downcast_ref::<LispError>() {
+                    lisp_error.bind(cx)
+                } else if let Some(cons_error) = err.downcast_ref::<ConsError>() {
+                    cons_error.bind(cx)
+                } else if let Some(type_error) = err.downcast_ref::<TypeError>() {
+                    type_error.to_lisp(cx)
+                } else {
+                    // TODO: Need to remove the anyhow branch once
+                    // full errors are implemented
+                    Cons::new(sym::ERROR, format!("{err}"), cx)
+                }
+            }
+            ErrorType::Throw(_) => unreachable!("Error type throw was not handled"),
+        }
+    }
+
+    /// Check if a `condition-case' handler for `condition` catches this error.
+    /// `condition` is an error symbol, a list of them, or t to catch any
+    /// error. Throws are never caught.
+    pub(crate) fn handled_by(
+        &self,
+        condition: Object,
+        env: &Rt<Env>,
+        cx: &Context,
+    ) -> Result<bool> {
+        if matches!(self.error, ErrorType::Throw(_)) {
+            return Ok(false);
+        }
+        let conditions = match self.to_lisp(env, cx).car().untag() {
+            ObjectType::Symbol(symbol) => error_conditions(symbol, env, cx),
+            _ => NIL,
+        };
+        let handles = |condition: Object| match condition.untag() {
+            ObjectType::TRUE => Ok(true),
+            // `debug' only asks for the debugger to be entered
+            ObjectType::Symbol(sym::DEBUG) => Ok(false),
+            ObjectType::Symbol(_) => Ok(!memq(condition, conditions.try_into()?)?.is_nil()),
+            _ => Err(anyhow!("Invalid condition handler: {condition}")),
+        };
+        match condition.untag() {
+            ObjectType::Cons(names) => {
+                for name in names {
+                    if handles(name?)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            _ => handles(condition),
+        }
+    }
+
     pub(crate) fn with_trace(error: anyhow::Error, name: &str, args: &[Rto<Object>]) -> Self {
-        let display = display_slice(args);
-        let trace = format!("{name} {display}").into_boxed_str();
-        Self { backtrace: vec![trace], error: ErrorType::Err(error) }
+        Self { backtrace: vec![frame(name, args)], error: ErrorType::Err(error) }
     }
 
     pub(crate) fn add_trace(mut self, name: &str, args: &[Rto<Object>]) -> Self {
-        let display = display_slice(args);
-        self.backtrace.push(format!("{name} {display}").into_boxed_str());
+        self.backtrace.push(frame(name, args));
         self
     }
 
     pub(crate) fn print_backtrace(&self) {
         println!("BEGIN_BACKTRACE");
         for (i, x) in self.backtrace.iter().enumerate() {
-            println!("{i}: {x}");
+            let prefix = format!("{i}: ");
+            let indent = " ".repeat(prefix.len());
+            println!("{prefix}{}", x.replace('\n', &format!("\n{indent}")));
         }
         println!("END_BACKTRACE");
     }
 }
 
+/// The limits that backtrace frames are printed with. Frames are printed as
+/// an error unwinds through them, so they can't wait to be told how much to
+/// show. These are the defaults of `eval-expression-print-level' and
+/// `eval-expression-print-length'.
+const BACKTRACE_LIMITS: PrintLimits = PrintLimits { level: Some(4), length: Some(12) };
+
+/// A backtrace frame for a call of `name`, pretty printed as a form.
+fn frame(name: &str, args: &[Rto<Object>]) -> Box<str> {
+    // SAFETY: The arguments are rooted and nothing is allocated while they
+    // are printed.
+    let args: Vec<Object> = args.iter().map(|x| unsafe { x.bind_unchecked() }).collect();
+    with_print_limits(BACKTRACE_LIMITS, || {
+        crate::pp::pretty_print_call(name, &args, crate::pp::DEFAULT_WIDTH).into_boxed_str()
+    })
+}
+
 impl From<anyhow::Error> for EvalError {
     fn from(e: anyhow::Error) -> Self {
         Self::new_error(e)
@@ -177,24 +275,8 @@ fn run_hooks<'ob>(hooks: ArgSlice, env: &mut Rt<Env>, cx: &'ob mut Context) -> R
         let hook = env.stack[hook_count - i - 1].bind(cx);
         match hook.untag() {
             ObjectType::Symbol(sym) => {
-                if let Some(val) = env.vars.get(sym) {
-                    let val = val.bind(cx);
-                    match val.untag() {
-                        ObjectType::Cons(hook_list) => {
-                            rooted_iter!(hooks, hook_list, cx);
-                            while let Some(hook) = hooks.next()? {
-                                let func = hook.try_as()?;
-                                call!(func; env, cx)?;
-                            }
-                        }
-                        ObjectType::NIL => {}
-                        _ => {
-                            let func: Function = val.try_into()?;
-                            root!(func, cx);
-                            call!(func; env, cx)?;
-                        }
-                    }
-                }
+                root!(sym, cx);
+                run_hook(sym, env, cx)?;
             }
             x => bail!(TypeError::new(Type::Symbol, x)),
         }
@@ -202,6 +284,64 @@ fn run_hooks<'ob>(hooks: ArgSlice, env: &mut Rt<Env>, cx: &'ob mut Context) -> R
     Ok(NIL)
 }
 
+/// Call each function in the hook variable `hook` with no arguments.
+pub(crate) fn run_hook(hook: &Rto<Symbol>, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let Some(val) = env.vars.get(hook.bind(cx)) else { return Ok(()) };
+    let val = val.bind(cx);
+    match val.untag() {
+        ObjectType::Cons(hook_list) => {
+            rooted_iter!(hooks, hook_list, cx);
+            while let Some(hook) = hooks.next()? {
+                let func = hook.try_as()?;
+                call!(func; env, cx)?;
+            }
+        }
+        ObjectType::NIL => {}
+        _ => {
+            let func: Function = val.try_into()?;
+            root!(func, cx);
+            call!(func; env, cx)?;
+        }
+    }
+    Ok(())
+}
+
+/// Find a replacement for SYMBOL, which has no function definition, by
+/// calling each function in the abnormal hook `undefined-function-functions'
+/// with SYMBOL. The first non-nil result is used as the function. If there is
+/// none, signal `void-function'.
+pub(crate) fn undefined_function<'ob>(
+    symbol: &Rto<Symbol>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Function<'ob>> {
+    let hook = match env.vars.get(sym::UNDEFINED_FUNCTION_FUNCTIONS) {
+        Some(value) => value.bind(cx),
+        None => NIL,
+    };
+    // A single function is treated as a hook with one element
+    let hook = match hook.untag() {
+        ObjectType::Cons(_) | ObjectType::NIL => hook,
+        _ => list![hook; cx],
+    };
+    rooted_iter!(hooks, hook, cx);
+    root!(found, NIL, cx);
+    while let Some(func) = hooks.next()? {
+        let func: &Rto<Function> = func.try_as()?;
+        let arg: Object = symbol.bind(cx).into();
+        let result = call!(func, arg; env, cx)?;
+        if !result.is_nil() {
+            found.set(result);
+            break;
+        }
+    }
+    let found = found.bind(cx);
+    if found.is_nil() {
+        bail!(LispError::void_function(symbol.bind(cx), cx))
+    }
+    Ok(found.try_into()?)
+}
+
 #[defun]
 fn run_hook_with_args<'ob>(
     hook: &Rto<Object>,
@@ -294,6 +434,24 @@ pub(crate) fn macroexpand<'ob>(
     environment: Option<&Rto<Object>>,
     cx: &'ob mut Context,
     env: &mut Rt<Env>,
+) -> Result<Object<'ob>> {
+    let new_form = macroexpand_1(form, environment, cx, env)?;
+    root!(new_form, cx); // polonius
+    if eq(new_form.bind(cx), form.bind(cx)) {
+        Ok(form.bind(cx))
+    } else {
+        // recursively expand the macro's
+        macroexpand(new_form, environment, cx, env)
+    }
+}
+
+/// Expand `form` once if it is a macro call. Other forms are returned
+/// unchanged.
+pub(crate) fn macroexpand_1<'ob>(
+    form: &Rto<Object>,
+    environment: Option<&Rto<Object>>,
+    cx: &'ob mut Context,
+    env: &mut Rt<Env>,
 ) -> Result<Object<'ob>> {
     let ObjectType::Cons(cons) = form.untag(cx) else { return Ok(form.bind(cx)) };
     let ObjectType::Symbol(sym) = cons.car().untag() else { return Ok(form.bind(cx)) };
@@ -313,18 +471,10 @@ pub(crate) fn macroexpand<'ob>(
     }
     root!(macro_func, cx);
     let name = sym.name().to_owned();
-    let new_form = macro_func.call(&mut frame, Some(&name), cx)?;
-    drop(frame);
-    root!(new_form, cx); // polonius
-    if eq(new_form.bind(cx), form.bind(cx)) {
-        Ok(form.bind(cx))
-    } else {
-        // recursively expand the macro's
-        macroexpand(new_form, environment, cx, env)
-    }
+    Ok(macro_func.call(&mut frame, Some(&name), cx)?)
 }
 
-fn get_macro_func<'ob>(name: Symbol, cx: &'ob Context) -> Option<Function<'ob>> {
+pub(crate) fn get_macro_func<'ob>(name: Symbol, cx: &'ob Context) -> Option<Function<'ob>> {
     if let Some(callable) = name.follow_indirect(cx) {
         if let Ok((sym::MACRO, cdr)) = callable.as_cons_pair() {
             return Some(cdr.tag());
@@ -366,7 +516,9 @@ fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
             Ok(from_args(args))
         }
         FunctionType::Symbol(sym) => {
-            let Some(func) = sym.follow_indirect(cx) else { bail!("Void Function: {sym}") };
+            let Some(func) = sym.follow_indirect(cx) else {
+                bail!(LispError::void_function(sym, cx))
+            };
             func_arity(func, cx)
         }
     }
@@ -382,6 +534,103 @@ fn internal__define_uninitialized_variable<'ob>(
     NIL
 }
 
+/// The errors signaled by primitives, with their messages and parents, in
+/// the order they are defined. Lisp libraries define more with
+/// `define-error'.
+const STANDARD_ERRORS: &[(Symbol<'static>, &str, Option<Symbol<'static>>)] = &[
+    (sym::ERROR, "error", None),
+    (sym::QUIT, "Quit", None),
+    (sym::MINIBUFFER_QUIT, "Quit", Some(sym::QUIT)),
+    (sym::USER_ERROR, "", Some(sym::ERROR)),
+    (sym::WRONG_LENGTH_ARGUMENT, "Wrong length argument", Some(sym::ERROR)),
+    (sym::WRONG_TYPE_ARGUMENT, "Wrong type argument", Some(sym::ERROR)),
+    (sym::ARGS_OUT_OF_RANGE, "Args out of range", Some(sym::ERROR)),
+    (sym::VOID_FUNCTION, "Symbol's function definition is void", Some(sym::ERROR)),
+    (
+        sym::CYCLIC_FUNCTION_INDIRECTION,
+        "Symbol's chain of function indirections contains a loop",
+        Some(sym::ERROR),
+    ),
+    (
+        sym::CYCLIC_VARIABLE_INDIRECTION,
+        "Symbol's chain of variable indirections contains a loop",
+        Some(sym::ERROR),
+    ),
+    (sym::CIRCULAR_LIST, "List contains a loop", Some(sym::ERROR)),
+    (sym::VOID_VARIABLE, "Symbol's value as variable is void", Some(sym::ERROR)),
+    (sym::SETTING_CONSTANT, "Attempt to set a constant symbol", Some(sym::ERROR)),
+    (sym::INVALID_READ_SYNTAX, "Invalid read syntax", Some(sym::ERROR)),
+    (sym::INVALID_FUNCTION, "Invalid function", Some(sym::ERROR)),
+    (sym::WRONG_NUMBER_OF_ARGUMENTS, "Wrong number of arguments", Some(sym::ERROR)),
+    (sym::NO_CATCH, "No catch for tag", Some(sym::ERROR)),
+    (sym::END_OF_FILE, "End of file during parsing", Some(sym::ERROR)),
+    (sym::ARITH_ERROR, "Arithmetic error", Some(sym::ERROR)),
+    (sym::BEGINNING_OF_BUFFER, "Beginning of buffer", Some(sym::ERROR)),
+    (sym::END_OF_BUFFER, "End of buffer", Some(sym::ERROR)),
+    (sym::BUFFER_READ_ONLY, "Buffer is read-only", Some(sym::ERROR)),
+    (sym::TEXT_READ_ONLY, "Text is read-only", Some(sym::BUFFER_READ_ONLY)),
+    (sym::SEARCH_FAILED, "Search failed", Some(sym::ERROR)),
+    (sym::INVALID_REGEXP, "Invalid regexp", Some(sym::ERROR)),
+    (sym::SCAN_ERROR, "Scan error", Some(sym::ERROR)),
+    (sym::DOMAIN_ERROR, "Arithmetic domain error", Some(sym::ARITH_ERROR)),
+    (sym::RANGE_ERROR, "Arithmetic range error", Some(sym::ARITH_ERROR)),
+    (sym::SINGULARITY_ERROR, "Arithmetic singularity error", Some(sym::DOMAIN_ERROR)),
+    (sym::OVERFLOW_ERROR, "Arithmetic overflow error", Some(sym::RANGE_ERROR)),
+    (sym::UNDERFLOW_ERROR, "Arithmetic underflow error", Some(sym::RANGE_ERROR)),
+    (
+        sym::EXCESSIVE_LISP_NESTING,
+        "Lisp nesting exceeds `max-lisp-eval-depth'",
+        Some(sym::ERROR),
+    ),
+    (
+        sym::EXCESSIVE_VARIABLE_BINDING,
+        "Variable binding depth exceeds max-specpdl-size",
+        Some(sym::ERROR),
+    ),
+    (sym::MEMORY_EXHAUSTED, "Memory exhausted", Some(sym::ERROR)),
+    (sym::FILE_ERROR, "File error", Some(sym::ERROR)),
+    (sym::FILE_MISSING, "No such file or directory", Some(sym::FILE_ERROR)),
+    (sym::FILE_ALREADY_EXISTS, "File already exists", Some(sym::FILE_ERROR)),
+    (sym::FILE_SUPERSESSION, "File changed on disk", Some(sym::FILE_ERROR)),
+    (sym::FILE_LOCKED, "File is locked", Some(sym::FILE_ERROR)),
+    (sym::PERMISSION_DENIED, "Permission denied", Some(sym::FILE_ERROR)),
+    (sym::SANDBOX_VIOLATION, "Sandbox limit exceeded", Some(sym::ERROR)),
+    (sym::CL_ASSERTION_FAILED, "Assertion failed", Some(sym::ERROR)),
+    (sym::RUST_PANIC, "Rust panic", Some(sym::ERROR)),
+    (sym::FUTURE_TIMEOUT, "Timed out waiting for future", Some(sym::ERROR)),
+];
+
+/// Give the standard errors their `error-conditions' and `error-message'
+/// properties.
+pub(crate) fn init_errors(env: &mut Rt<Env>, cx: &Context) {
+    for &(name, message, parent) in STANDARD_ERRORS {
+        let parents = match parent {
+            Some(parent) => error_conditions(parent, env, cx),
+            None => NIL,
+        };
+        let conditions = Cons::new(name, parents, cx);
+        env.set_prop(name, sym::ERROR_CONDITIONS, conditions.into());
+        env.set_prop(name, sym::ERROR_MESSAGE, cx.add(message));
+    }
+}
+
+/// The conditions of the error `symbol`: the symbol itself followed by the
+/// errors it inherits from, as stored in its `error-conditions' property.
+/// A symbol that was never defined as an error is treated as a kind of
+/// `error', except for `quit', which is not an error.
+pub(crate) fn error_conditions<'ob>(
+    symbol: Symbol,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let conditions = crate::data::get(symbol, sym::ERROR_CONDITIONS, env, cx);
+    match conditions.untag() {
+        ObjectType::NIL if symbol == sym::QUIT => list![sym::QUIT; cx],
+        ObjectType::NIL => list![symbol, sym::ERROR; cx],
+        _ => conditions,
+    }
+}
+
 #[defun]
 fn signal(mut error_symbol: Object, data: Object, env: &mut Rt<Env>) -> Result<bool> {
     if error_symbol.is_nil() && data.is_nil() {
@@ -390,6 +639,70 @@ fn signal(mut error_symbol: Object, data: Object, env: &mut Rt<Env>) -> Result<b
     Err(EvalError::signal(error_symbol, data, env).into())
 }
 
+/// Signal `error_symbol` with a message made by passing `args` to
+/// `format-message'.
+fn signal_message(
+    error_symbol: Symbol,
+    format_string: &str,
+    args: &[Object],
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let message = crate::editfns::format_message(format_string, args)?;
+    let data = list![cx.add(message); cx];
+    Err(EvalError::signal(error_symbol.into(), data, env).into())
+}
+
+/// Signal an error, making a message by passing ARGS to `format-message'.
+#[defun]
+fn error(format_string: &str, args: ArgSlice, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let args: Vec<_> = Rt::bind_slice(env.stack.arg_slice(args), cx).to_vec();
+    signal_message(sym::ERROR, format_string, &args, env, cx)
+}
+
+/// Signal a user error, making a message by passing ARGS to `format-message'.
+/// This is like `error' except that the error was caused by the user rather
+/// than a bug.
+#[defun]
+fn user_error(
+    format_string: &str,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let args: Vec<_> = Rt::bind_slice(env.stack.arg_slice(args), cx).to_vec();
+    signal_message(sym::USER_ERROR, format_string, &args, env, cx)
+}
+
+/// Signal that the assertion FORM failed. If STRING is non-nil it is passed
+/// to `error' along with SARGS and ARGS, otherwise `cl-assertion-failed' is
+/// signaled with FORM followed by the values in SARGS.
+#[defun]
+#[expect(non_snake_case)]
+fn cl__assertion_failed(
+    form: Object,
+    string: Option<&str>,
+    sargs: Option<Object>,
+    args: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let sargs = sargs.unwrap_or_default();
+    match string {
+        Some(string) => {
+            let mut objects = Vec::new();
+            for arg in sargs.as_list()?.chain(args.unwrap_or_default().as_list()?) {
+                objects.push(arg?);
+            }
+            signal_message(sym::ERROR, string, &objects, env, cx)
+        }
+        None => {
+            let data = Cons::new(form, sargs, cx);
+            Err(EvalError::signal(sym::CL_ASSERTION_FAILED.into(), data.into(), env).into())
+        }
+    }
+}
+
 #[defun]
 fn special_variable_p(symbol: Symbol) -> bool {
     symbol.is_special()
@@ -400,20 +713,24 @@ fn set_default_toplevel_value<'ob>(
     symbol: Symbol,
     value: Object,
     env: &'ob mut Rt<Env>,
+    cx: &Context,
 ) -> Result<Object<'ob>> {
-    env.set_var(symbol, value)?;
+    env.set_var(symbol, value, cx)?;
     Ok(NIL)
 }
 
 #[defun]
 fn set_default<'ob>(
-    symbol: Symbol,
-    value: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+    symbol: &Rto<Gc<Symbol>>,
+    value: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
 ) -> Result<Object<'ob>> {
     // TODO: implement buffer local variables
-    env.set_var(symbol, value)?;
-    Ok(value)
+    let symbol = symbol.untag(cx);
+    root!(symbol, cx);
+    crate::watchers::set(symbol, value, env, cx)?;
+    Ok(value.bind(cx))
 }
 
 impl Rto<Function<'_>> {
@@ -425,9 +742,11 @@ impl Rto<Function<'_>> {
     ) -> EvalResult<'ob> {
         debug!("calling: {self}");
         let name = name.unwrap_or("lambda");
+        check_eval_depth(frame, cx)?;
         frame.finalize_arguments();
         let arg_cnt = frame.arg_count();
         cx.garbage_collect(false);
+        check_heap_limit(frame, cx)?;
         match self.untag(cx) {
             FunctionType::ByteFn(f) => {
                 root!(f, cx);
@@ -435,6 +754,7 @@ impl Rto<Function<'_>> {
                     .map_err(|e| e.add_trace(name, frame.arg_slice()))
             }
             FunctionType::SubrFn(f) => {
+                crate::sandbox::check_subr(f.name, frame, cx)?;
                 (*f).call(arg_cnt, frame, cx).map_err(|e| add_trace(e, name, frame.arg_slice()))
             }
             FunctionType::Cons(_) => {
@@ -442,10 +762,13 @@ impl Rto<Function<'_>> {
                     .map_err(|e| e.add_trace(name, frame.arg_slice()))
             }
             FunctionType::Symbol(sym) => {
-                let Some(func) = sym.follow_indirect(cx) else { bail_err!("Void Function: {sym}") };
+                root!(sym, cx);
+                let func = match sym.bind(cx).follow_indirect(cx) {
+                    Some(func) => func,
+                    None => rebind!(undefined_function(sym, frame, cx)?),
+                };
                 if let Ok((sym::AUTOLOAD, _)) = func.as_cons_pair() {
                     // TODO: inifinite loop if autoload does not resolve
-                    root!(sym, cx);
                     crate::eval::autoload_do_load(self.cast(), None, None, frame, cx)
                         .map_err(|e| add_trace(e, name, frame.arg_slice()))?;
                     let Some(func) = sym.bind(cx).follow_indirect(cx) else {
@@ -456,7 +779,7 @@ impl Rto<Function<'_>> {
                     func.call(frame, Some(&name), cx)
                 } else {
                     root!(func, cx);
-                    let name = sym.name().to_owned();
+                    let name = sym.bind(cx).name().to_owned();
                     func.call(frame, Some(&name), cx)
                 }
             }
@@ -464,6 +787,60 @@ impl Rto<Function<'_>> {
     }
 }
 
+/// Read the value of a numeric limit variable such as `max-lisp-eval-depth'.
+/// Returns `None` if the variable is unbound or not a natural number.
+fn limit_value(env: &Rt<Env>, var: Symbol, cx: &Context) -> Option<usize> {
+    match env.vars.get(var)?.bind(cx).untag() {
+        ObjectType::Int(x) => usize::try_from(x).ok(),
+        _ => None,
+    }
+}
+
+/// Signal `excessive-lisp-nesting' if the call stack is deeper than
+/// `max-lisp-eval-depth'. This is checked on every function call so that
+/// runaway recursion becomes a lisp error instead of overflowing the native
+/// stack.
+pub(crate) fn check_eval_depth(env: &mut Rt<Env>, cx: &Context) -> Result<(), EvalError> {
+    let depth = env.stack.current_frame();
+    match limit_value(env, sym::MAX_LISP_EVAL_DEPTH, cx) {
+        Some(max) if depth > max => {
+            Err(EvalError::signal(sym::EXCESSIVE_LISP_NESTING.into(), list![depth; cx], env))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Signal `memory-exhausted' if the heap is larger than `heap-size-limit' or
+/// the limit set on the context, even after collecting garbage. This lets a
+/// runaway program be stopped with a lisp error instead of running the
+/// process out of memory.
+pub(crate) fn check_heap_limit(env: &mut Rt<Env>, cx: &mut Context) -> Result<(), EvalError> {
+    let limit = match (limit_value(env, sym::HEAP_SIZE_LIMIT, cx), cx.heap_limit()) {
+        (Some(var), Some(context)) => var.min(context),
+        (Some(limit), None) | (None, Some(limit)) => limit,
+        (None, None) => return Ok(()),
+    };
+    if cx.heap_exhausted(limit) {
+        let data = list![cx.heap_bytes(), limit; cx];
+        Err(EvalError::signal(sym::MEMORY_EXHAUSTED.into(), data, env))
+    } else {
+        Ok(())
+    }
+}
+
+/// Signal `excessive-variable-binding' if there are more dynamic bindings
+/// than `max-specpdl-size'. This should be checked before adding a new
+/// binding.
+pub(crate) fn check_specpdl_size(env: &mut Rt<Env>, cx: &Context) -> Result<(), EvalError> {
+    let size = env.binding_depth();
+    match limit_value(env, sym::MAX_SPECPDL_SIZE, cx) {
+        Some(max) if size >= max => {
+            Err(EvalError::signal(sym::EXCESSIVE_VARIABLE_BINDING.into(), NIL, env))
+        }
+        _ => Ok(()),
+    }
+}
+
 pub(crate) fn add_trace(err: anyhow::Error, name: &str, args: &[Rto<Object>]) -> EvalError {
     match err.downcast::<EvalError>() {
         Ok(err) => err.add_trace(name, args),
@@ -482,6 +859,8 @@ defsym!(AND_REST, "&rest");
 defsym!(LAMBDA);
 defsym!(CLOSURE);
 defsym!(CONDITION_CASE);
+defsym!(IGNORE_ERRORS);
+defsym!(WITH_DEMOTED_ERRORS);
 defsym!(UNWIND_PROTECT);
 defsym!(SAVE_EXCURSION);
 defsym!(SAVE_CURRENT_BUFFER);
@@ -495,15 +874,52 @@ defsym!(DEFCONST);
 defsym!(COND);
 defsym!(LET);
 defsym!(LET_STAR, "let*");
+defsym!(DLET);
 defsym!(IF);
 defsym!(AND);
 defsym!(OR);
 defsym!(INTERACTIVE);
+defsym!(DECLARE);
 defsym!(CATCH);
 defsym!(THROW);
-defsym!(ERROR);
 defsym!(DEBUG);
 defsym!(VOID_VARIABLE);
+defsym!(ERROR_CONDITIONS);
+defsym!(ERROR_MESSAGE);
+defsym!(MINIBUFFER_QUIT);
+defsym!(WRONG_LENGTH_ARGUMENT);
+defsym!(CYCLIC_FUNCTION_INDIRECTION);
+defsym!(CYCLIC_VARIABLE_INDIRECTION);
+defsym!(INVALID_READ_SYNTAX);
+defsym!(INVALID_FUNCTION);
+defsym!(NO_CATCH);
+defsym!(END_OF_FILE);
+defsym!(BEGINNING_OF_BUFFER);
+defsym!(END_OF_BUFFER);
+defsym!(BUFFER_READ_ONLY);
+defsym!(TEXT_READ_ONLY);
+defsym!(SEARCH_FAILED);
+defsym!(INVALID_REGEXP);
+defsym!(SCAN_ERROR);
+defsym!(DOMAIN_ERROR);
+defsym!(RANGE_ERROR);
+defsym!(SINGULARITY_ERROR);
+defsym!(UNDERFLOW_ERROR);
+defsym!(FILE_ERROR);
+defsym!(FILE_MISSING);
+defsym!(FILE_ALREADY_EXISTS);
+defsym!(FILE_SUPERSESSION);
+defsym!(EXCESSIVE_LISP_NESTING);
+defsym!(EXCESSIVE_VARIABLE_BINDING);
+defsym!(MEMORY_EXHAUSTED);
+defsym!(CL_ASSERTION_FAILED);
+defsym!(WITH_OUTPUT_TO_STRING);
+defsym!(WITH_NO_WARNINGS);
+defsym!(WITH_SUPPRESSED_WARNINGS);
 
 defvar!(DEBUG_ON_ERROR, false);
+defvar!(MAX_LISP_EVAL_DEPTH, 1600);
+defvar!(MAX_SPECPDL_SIZE, 2500);
+defvar!(HEAP_SIZE_LIMIT);
 defvar!(INTERNAL_MAKE_INTERPRETED_CLOSURE_FUNCTION);
+defvar!(UNDEFINED_FUNCTION_FUNCTIONS);
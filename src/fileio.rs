@@ -3,14 +3,27 @@ use crate::core::{
     cons::Cons,
     env::{sym, Env},
     error::{Type, TypeError},
-    gc::{Context, Rt},
-    object::{Number, Object, ObjectType, OptionalFlag},
+    gc::{Context, Rt, Rto},
+    object::{
+        BufferData, Gc, LispBuffer, LispString, Number, Object, ObjectType, OpenBuffer,
+        OptionalFlag, Symbol, NIL,
+    },
 };
+use crate::eval::EvalError;
+use crate::insdel::{signal_after_change, signal_before_change};
+use crate::permissions::{check_file, Capability};
+use crate::timefns::timestamp;
 use anyhow::{bail, ensure, Result};
+use rune_core::macros::list;
 use rune_macros::defun;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
 use std::path::{Component, Path, MAIN_SEPARATOR};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 defvar!(FILE_NAME_HANDLER_ALIST);
+defvar_bool!(FILE_PRECIOUS_FLAG, false);
+defvar_bool!(WRITE_REGION_INHIBIT_FSYNC, false);
 
 #[defun]
 pub(crate) fn expand_file_name(
@@ -116,7 +129,7 @@ fn file_name_directory(filename: &str) -> Option<String> {
 
 /// Returns the non-directory part of `filename`
 #[defun]
-fn file_name_nondirectory(filename: &str) -> &str {
+pub(crate) fn file_name_nondirectory(filename: &str) -> &str {
     if filename.ends_with(MAIN_SEPARATOR) {
         return "";
     }
@@ -194,37 +207,400 @@ fn test_case_sensative_call() {
     let _ = file_name_case_insensitive_p("/");
 }
 
+/// Write `contents` to `file`, or add it to the end if `append` is true. The
+/// file is synced to disk unless `write-region-inhibit-fsync' is non-nil.
+///
+/// If `file-precious-flag' is non-nil the contents are written to a new
+/// temporary file in the same directory, which is then renamed over `file`,
+/// so a crash never leaves it half written. The new file gets the
+/// permissions, and where possible the owner, of the one it replaces.
+pub(crate) fn write_file(
+    file: &str,
+    contents: &[u8],
+    append: bool,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    use std::io::Write;
+    let is_set = |var: Symbol| env.vars.get(var).is_some_and(|x| !x.bind(cx).is_nil());
+    let sync = !is_set(sym::WRITE_REGION_INHIBIT_FSYNC);
+    if append || !is_set(sym::FILE_PRECIOUS_FLAG) {
+        let mut out = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(file)?;
+        out.write_all(contents)?;
+        if sync {
+            out.sync_all()?;
+        }
+        return Ok(());
+    }
+    // write through symbolic links instead of replacing them
+    let target = std::fs::canonicalize(file).unwrap_or_else(|_| file.into());
+    let name = target.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    // A fresh file, so that a file or symbolic link planted under the same
+    // name is never written through
+    let (temp, mut out) = loop {
+        let temp = target.with_file_name(format!(".{name}.rune-{:08x}", rand::random::<u32>()));
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(out) => break (temp, out),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let mut write = || -> std::io::Result<()> {
+        out.write_all(contents)?;
+        if let Ok(metadata) = std::fs::metadata(&target) {
+            out.set_permissions(metadata.permissions())?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // only root can give a file away, so this is best effort
+                let _ = std::os::unix::fs::fchown(&out, Some(metadata.uid()), Some(metadata.gid()));
+            }
+        }
+        if sync {
+            out.sync_all()?;
+        }
+        std::fs::rename(&temp, &target)?;
+        // the rename is only durable once the directory is synced too
+        #[cfg(unix)]
+        if sync {
+            let dir = target.parent().filter(|x| !x.as_os_str().is_empty());
+            std::fs::File::open(dir.unwrap_or(std::path::Path::new(".")))?.sync_all()?;
+        }
+        Ok(())
+    };
+    if let Err(e) = write() {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Write the text of the current buffer between START and END to FILENAME.
+/// If START is nil, write the whole buffer; if it is a string, write that
+/// string instead. If APPEND is non-nil, add the text to the end of the
+/// file. If VISIT is t the buffer is marked as visiting FILENAME and as
+/// unmodified; if it is a string the buffer visits that file name instead.
+/// Any other non-nil VISIT only suppresses the message, as in
+/// `with-temp-file'. Unless APPEND is non-nil, FILENAME is replaced in one
+/// step if `file-precious-flag' is non-nil. The file is synced to disk
+/// unless `write-region-inhibit-fsync' is non-nil.
 #[defun]
 #[expect(clippy::too_many_arguments)]
 fn write_region(
-    start: i64,
-    end: i64,
+    start: Object,
+    end: Option<usize>,
     filename: &str,
     append: OptionalFlag,
-    visit: OptionalFlag,
+    visit: Option<Object>,
     lockname: OptionalFlag,
     mustbenew: OptionalFlag,
-    env: &Rt<Env>,
+    env: &mut Rt<Env>,
+    cx: &Context,
 ) -> Result<()> {
-    use std::io::Write;
-    ensure!(append.is_none(), "append not implemented");
-    ensure!(visit.is_none(), "visit not implemented");
+    let visit_name = match visit.map(|x| x.untag()) {
+        Some(ObjectType::String(name)) => Some(expand_file_name(name, None, env, cx)?),
+        Some(ObjectType::TRUE) => Some(expand_file_name(filename, None, env, cx)?),
+        _ => None,
+    };
     ensure!(lockname.is_none(), "lockname not implemented");
     ensure!(mustbenew.is_none(), "mustbenew not implemented");
-    // Open filename for writing
-    let mut file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(filename)
-        .unwrap();
+    check_file(Capability::Write, filename, env, cx)?;
     let b = env.current_buffer.get();
-    let (s1, s2) = b.slice_with_gap(start as usize, end as usize)?;
-    write!(file, "{s1}")?;
-    write!(file, "{s2}")?;
+    let text = match start.untag() {
+        ObjectType::String(string) => string.to_string(),
+        ObjectType::NIL => {
+            let (s1, s2) = b.slice_with_gap(1, b.text.len_chars() + 1)?;
+            format!("{s1}{s2}")
+        }
+        _ => {
+            let start: usize = start.try_into()?;
+            let Some(end) = end else { bail!(TypeError::new(Type::Int, NIL)) };
+            let (s1, s2) = b.slice_with_gap(start, end)?;
+            format!("{s1}{s2}")
+        }
+    };
+    let eol = crate::coding::write_eol(b.eol, env, cx)?;
+    let bytes = crate::coding::encode(&text, eol);
+    write_file(filename, &bytes, append.is_some(), env, cx)?;
+    crate::coding::set_last_used(Some(eol), env, cx)?;
+    if let Some(visit_name) = visit_name {
+        // appending leaves the file with more than was written
+        let contents = if append.is_some() { std::fs::read(filename)? } else { bytes };
+        let buffer = env.current_buffer.get_mut();
+        buffer.eol = eol;
+        record_visited_file(buffer, file_modtime(filename), &contents);
+        buffer.file_name = Some(visit_name);
+        buffer.set_unmodified();
+        env.sync_buffer_variables(cx);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_write_region() {
+    let file = std::env::temp_dir().join(format!("rune-write-region-{}", std::process::id()));
+    let file = file.to_str().unwrap();
+    crate::interpreter::assert_lisp(
+        &format!(
+            "(progn
+               (set-buffer (get-buffer-create \"write-region\"))
+               (insert \"abc\")
+               (write-region nil nil \"{file}\" nil 0)
+               (write-region \"d\" nil \"{file}\" t)
+               (write-region 2 3 \"{file}\" t))"
+        ),
+        "nil",
+    );
+    let contents = std::fs::read_to_string(file).unwrap();
+    std::fs::remove_file(file).unwrap();
+    assert_eq!(contents, "abcdb");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_write_region_precious() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = std::env::temp_dir().join(format!("rune-precious-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("precious.txt");
+    let link = dir.join("link.txt");
+    std::fs::write(&path, "old").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    let file = link.to_str().unwrap();
+    crate::interpreter::assert_lisp_with_vars(
+        &format!(
+            "(let ((file-precious-flag t) (write-region-inhibit-fsync t))
+               (write-region \"new\" nil \"{file}\"))"
+        ),
+        "nil",
+    );
+    assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    // syncing also syncs the directory after the rename
+    crate::interpreter::assert_lisp_with_vars(
+        &format!(
+            "(let ((file-precious-flag t) (write-region-inhibit-fsync nil))
+               (write-region \"newer\" nil \"{file}\"))"
+        ),
+        "nil",
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "newer");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Return the modification time of `file`, or `None` if it does not exist.
+pub(crate) fn file_modtime(file: &str) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|x| x.modified()).ok()
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Return a checksum of the contents of `file`, or `None` if it can't be
+/// read.
+fn file_checksum(file: &str) -> Option<u64> {
+    std::fs::read(file).ok().map(|x| checksum(&x))
+}
+
+/// Record the version of the visited file that `buffer` last read or wrote,
+/// from the `contents` that were read or written and the modification time
+/// of the file, which is `None` if it doesn't exist. The modification time of
+/// a file that is read should be taken before reading it, so that a change
+/// made in between is noticed.
+pub(crate) fn record_visited_file(
+    buffer: &mut BufferData,
+    modtime: Option<SystemTime>,
+    contents: &[u8],
+) {
+    buffer.modtime = modtime;
+    buffer.checksum = modtime.map(|_| checksum(contents));
+}
+
+/// Whether the file visited by `buffer` was changed on disk since it was last
+/// read or written. A file with a new modification time but the same
+/// contents, like one that was only touched, is not considered changed.
+pub(crate) fn visited_file_changed(buffer: &BufferData) -> bool {
+    let Some(file) = &buffer.file_name else { return false };
+    file_modtime(file) != buffer.modtime
+        && (buffer.checksum.is_none() || file_checksum(file) != buffer.checksum)
+}
+
+/// Read the contents of the absolute file name `filename`. Signals
+/// `file-missing' if it does not exist.
+pub(crate) fn read_file(filename: &str, env: &mut Rt<Env>, cx: &Context) -> Result<Vec<u8>> {
+    check_file(Capability::Read, filename, env, cx)?;
+    match std::fs::read(filename) {
+        Ok(bytes) => Ok(bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let data = list!["Opening input file", "No such file or directory", filename; cx];
+            Err(EvalError::signal(sym::FILE_MISSING.into(), data, env).into())
+        }
+        Err(e) => bail!("Opening input file {filename}: {e}"),
+    }
+}
+
+/// Insert the contents of FILENAME after point in the current buffer and
+/// return a list of the absolute file name and the number of characters
+/// inserted. BEG and END are the byte offsets of the part of the file to
+/// insert. If REPLACE is non-nil the text of the buffer is replaced instead.
+/// If VISIT is non-nil the buffer is marked as visiting FILENAME and as
+/// unmodified, even if the file does not exist.
+#[defun]
+fn insert_file_contents<'ob>(
+    filename: &Rto<Gc<&LispString>>,
+    visit: OptionalFlag,
+    beg: Option<usize>,
+    end: Option<usize>,
+    replace: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let filename = expand_file_name(filename.untag(cx), None, env, cx)?;
+    let buffer = env.current_buffer.get();
+    let old_len = if replace.is_some() { buffer.text.len_chars() } else { 0 };
+    let change_beg = if replace.is_some() { 1 } else { buffer.text.cursor().chars() + 1 };
+    signal_before_change(change_beg, change_beg + old_len, env, cx)?;
+    if visit.is_some() {
+        let buffer = env.current_buffer.get_mut();
+        buffer.file_name = Some(filename.clone());
+        record_visited_file(buffer, None, &[]);
+    }
+    let modtime = file_modtime(&filename);
+    let bytes = read_file(&filename, env, cx)?;
+    let end = end.unwrap_or(bytes.len()).min(bytes.len());
+    let beg = beg.unwrap_or(0).min(end);
+    let (text, eol) = crate::coding::decode_file(&bytes[beg..end], env, cx)?;
+    crate::coding::set_last_used(eol, env, cx)?;
+    let buffer = env.current_buffer.get_mut();
+    if replace.is_some() {
+        let len = buffer.text.len_chars();
+        buffer.text.delete_range(0, len);
+    }
+    let point = buffer.text.cursor().chars();
+    buffer.text.insert(&text);
+    buffer.text.set_cursor(point);
+    let inserted = text.chars().count();
+    if visit.is_some() {
+        record_visited_file(buffer, modtime, &bytes);
+        buffer.eol = eol.unwrap_or_default();
+        buffer.set_unmodified();
+        // the buffer matches the file, so it should not stay locked
+        crate::filelock::unlock(&filename)?;
+        env.sync_buffer_variables(cx);
+    }
+    signal_after_change(point + 1, point + 1 + inserted, old_len, env, cx)?;
+    Ok(list![filename, inserted; cx])
+}
+
+#[test]
+fn test_insert_file_contents() {
+    let dir = std::env::temp_dir();
+    let file = dir.join(format!("rune-insert-file-{}", std::process::id()));
+    let out = dir.join(format!("rune-insert-file-out-{}", std::process::id()));
+    std::fs::write(&file, "h\u{e9}llo\n").unwrap();
+    let (file, out) = (file.to_str().unwrap(), out.to_str().unwrap());
+    crate::interpreter::assert_lisp(
+        &format!(
+            "(progn
+               (set-buffer (get-buffer-create \"insert-file-contents\"))
+               (insert \"ab\")
+               (list (cadr (insert-file-contents \"{file}\" nil 1 3))
+                     (point)
+                     (progn
+                       (insert \"c\")
+                       (write-region nil nil \"{out}\" nil 0)
+                       (cadr (insert-file-contents \"{file}\" nil nil nil t)))
+                     (point)
+                     (point-max)))"
+        ),
+        "(1 2 6 0 7)",
+    );
+    let contents = std::fs::read_to_string(out).unwrap();
+    std::fs::remove_file(file).unwrap();
+    std::fs::remove_file(out).unwrap();
+    assert_eq!(contents, "abc\u{e9}");
+    crate::interpreter::assert_lisp(
+        "(condition-case err (insert-file-contents \"/rune/missing\") (file-missing (car err)))",
+        "file-missing",
+    );
+}
+
+/// Return the modification time of the file visited by the current buffer
+/// when it was last read or saved, or 0 if it is not known.
+#[defun]
+fn visited_file_modtime<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    match env.current_buffer.get().modtime {
+        Some(time) => timestamp(time.duration_since(UNIX_EPOCH).unwrap_or_default(), cx),
+        None => cx.add(0),
+    }
+}
+
+/// Return t if the file visited by BUFFER has not changed on disk since it
+/// was last read or saved. A file that was touched without changing its
+/// contents counts as unchanged. BUFFER defaults to the current buffer.
+#[defun]
+fn verify_visited_file_modtime(buffer: Option<Gc<&LispBuffer>>, env: &Rt<Env>) -> Result<bool> {
+    let verify = |b: &OpenBuffer| match (&b.file_name, b.modtime) {
+        (Some(_), Some(_)) => !visited_file_changed(b),
+        _ => true,
+    };
+    match buffer {
+        Some(buffer) => env.with_buffer(buffer.untag(), verify),
+        None => Ok(verify(env.current_buffer.get())),
+    }
+}
+
+/// Record the current modification time and contents of the visited file as
+/// the version last read or saved, so it is no longer considered changed on
+/// disk.
+#[defun]
+fn set_visited_file_modtime(time_flag: OptionalFlag, env: &mut Rt<Env>) -> Result<()> {
+    ensure!(time_flag.is_none(), "time-flag not implemented");
+    let buffer = env.current_buffer.get_mut();
+    match buffer.file_name.clone() {
+        Some(file) => {
+            buffer.modtime = file_modtime(&file);
+            buffer.checksum = file_checksum(&file);
+        }
+        None => buffer.modtime = None,
+    }
     Ok(())
 }
 
+#[test]
+fn test_visit_file() {
+    let file = std::env::temp_dir().join(format!("rune-visit-file-{}", std::process::id()));
+    let file = file.to_str().unwrap();
+    crate::interpreter::assert_lisp(
+        &format!(
+            "(progn
+               (set-buffer (get-buffer-create \"visit-file\"))
+               (insert \"abc\")
+               (list (buffer-modified-p)
+                     (progn (write-region nil nil \"{file}\" nil t) (buffer-modified-p))
+                     (buffer-file-name)
+                     (consp (visited-file-modtime))
+                     (verify-visited-file-modtime)
+                     (progn (insert \"d\") (set-buffer-modified-p nil) (buffer-modified-p))))"
+        ),
+        &format!("(t nil \"{file}\" t t nil)"),
+    );
+    std::fs::remove_file(file).unwrap();
+}
+
 /// Concatenate components to directory, inserting path separators as required.
 #[defun]
 fn file_name_concat(directory: &str, rest_components: &[Object]) -> Result<String> {
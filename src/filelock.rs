@@ -1,13 +1,288 @@
+//! Lock files that stop two editors from changing the same file.
+//!
+//! This follows the Emacs protocol, so rune and Emacs see each other's locks.
+//! The lock for `DIR/FILE` is `DIR/.#FILE`, a symbolic link whose target is
+//! `USER@HOST.PID`. Where symbolic links can't be made it is a regular file
+//! with the same contents. A buffer visiting a file locks it when it is first
+//! modified, and unlocks it when it is saved, reverted or killed. If another
+//! live process holds the lock, `ask-user-about-lock' decides whether to take
+//! it over, and without that function `file-locked' is signaled.
+use crate::core::{
+    env::{sym, CallFrame, Env},
+    gc::{Context, Rt, Rto},
+    object::{Gc, LispString, Object, NIL, TRUE},
+};
+use crate::eval::EvalError;
+use crate::fileio::{expand_file_name, file_name_nondirectory};
+use anyhow::Result;
+use rune_core::macros::{list, root};
 use rune_macros::defun;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
-use crate::core::object::OptionalFlag;
+/// Return the name of the lock file for `file`.
+fn lock_name(file: &str) -> PathBuf {
+    let lock = format!(".#{}", file_name_nondirectory(file));
+    match Path::new(file).parent() {
+        Some(dir) => dir.join(lock),
+        None => PathBuf::from(lock),
+    }
+}
+
+fn host_name() -> String {
+    hostname::get().ok().and_then(|x| x.into_string().ok()).unwrap_or_default()
+}
+
+/// The lock owner string of this process.
+fn this_owner() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".into());
+    format!("{user}@{}.{}", host_name(), std::process::id())
+}
+
+/// Read the owner of the lock file `lock`, if there is one.
+fn read_lock(lock: &Path) -> Option<String> {
+    match std::fs::read_link(lock) {
+        Ok(target) => Some(target.to_string_lossy().into_owned()),
+        Err(_) => std::fs::read_to_string(lock).ok(),
+    }
+}
+
+fn create_lock(lock: &Path, owner: &str) -> std::io::Result<()> {
+    #[cfg(unix)]
+    match std::os::unix::fs::symlink(owner, lock) {
+        Err(e) if e.kind() != ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+    use std::io::Write;
+    std::fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(lock)?
+        .write_all(owner.as_bytes())
+}
+
+/// Split an owner string into the user, host and pid. Emacs may add the boot
+/// time after the pid, separated by a colon.
+fn parse_owner(owner: &str) -> Option<(&str, &str, u32)> {
+    let (user, rest) = owner.split_once('@')?;
+    let rest = rest.split(':').next()?;
+    let (host, pid) = rest.rsplit_once('.')?;
+    Some((user, host, pid.parse().ok()?))
+}
+
+/// Return true if `owner` is a process on this machine that no longer exists.
+/// Locks held on other machines are never stale, because we can't tell.
+fn is_stale(owner: &str) -> bool {
+    let Some((_, host, pid)) = parse_owner(owner) else { return false };
+    if host != host_name() {
+        return false;
+    }
+    #[cfg(unix)]
+    {
+        let pid = pid as libc::pid_t;
+        // SAFETY: signal 0 only checks that the process exists
+        let alive = unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+        !alive
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        false
+    }
+}
+
+fn owner_user(owner: &str) -> &str {
+    parse_owner(owner).map_or(owner, |(user, ..)| user)
+}
 
+/// Return the owner of the lock on `file` if it is held by another live
+/// process. A stale lock is removed.
+fn other_owner(lock: &Path) -> Result<Option<String>> {
+    match read_lock(lock) {
+        Some(owner) if owner == this_owner() => Ok(None),
+        Some(owner) if is_stale(&owner) => {
+            std::fs::remove_file(lock)?;
+            Ok(None)
+        }
+        owner => Ok(owner),
+    }
+}
+
+/// Ask `ask-user-about-lock' what to do about `file` being locked by `owner`.
+/// Returns true if we should take the lock over.
+fn ask_user(file: &str, owner: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let user = owner_user(owner);
+    let Some(function) = sym::ASK_USER_ABOUT_LOCK.follow_indirect(cx) else {
+        let data = list![file, user; cx];
+        return Err(EvalError::signal(sym::FILE_LOCKED.into(), data, env).into());
+    };
+    root!(function, cx);
+    let frame = &mut CallFrame::new(env);
+    frame.push_arg(cx.add(file));
+    frame.push_arg(cx.add(user));
+    Ok(!function.call(frame, None, cx)?.is_nil())
+}
+
+/// Lock `file` for this process. Does nothing if `create-lockfiles' is nil.
+pub(crate) fn lock(file: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    if !env.vars.get(sym::CREATE_LOCKFILES).is_some_and(|x| !x.bind(cx).is_nil()) {
+        return Ok(());
+    }
+    let lock = lock_name(file);
+    let owner = this_owner();
+    if let Some(other) = other_owner(&lock)? {
+        if !ask_user(file, &other, env, cx)? {
+            return Ok(());
+        }
+        match std::fs::remove_file(&lock) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    } else if read_lock(&lock).is_some() {
+        // already ours
+        return Ok(());
+    }
+    match create_lock(&lock, &owner) {
+        // the directory may be read-only, which should not stop editing
+        Err(e) if e.kind() != ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => Err(e.into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Remove the lock on `file` if this process holds it.
+pub(crate) fn unlock(file: &str) -> Result<()> {
+    let lock = lock_name(file);
+    if read_lock(&lock).is_some_and(|x| x == this_owner()) {
+        match std::fs::remove_file(&lock) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Lock the visited file of the current buffer if this is the first change
+/// since it was read or saved. Call this before modifying the buffer.
+pub(crate) fn lock_before_modify(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let buffer = env.current_buffer.get();
+    if buffer.is_modified() {
+        return Ok(());
+    }
+    match buffer.file_name.clone() {
+        Some(file) => lock(&file, env, cx),
+        None => Ok(()),
+    }
+}
+
+/// Return nil if FILENAME is not locked, t if it is locked by this process,
+/// or the name of the user who holds the lock.
 #[defun]
-fn lock_buffer(_file: OptionalFlag) {
-    // TODO: implement
+fn file_locked_p<'ob>(filename: &str, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let filename = expand_file_name(filename, None, env, cx)?;
+    let lock = lock_name(&filename);
+    Ok(match read_lock(&lock) {
+        None => NIL,
+        Some(owner) if owner == this_owner() => TRUE,
+        Some(owner) => cx.add(owner_user(&owner)),
+    })
 }
 
+/// Lock FILE for this process. If another process holds the lock, call
+/// `ask-user-about-lock' with FILE and the name of its user. It should
+/// return t to take the lock over or nil to carry on without it. If it is
+/// not defined, signal `file-locked'.
 #[defun]
-fn unlock_buffer() {
-    // TODO: implement
+fn lock_file(file: &Rto<Gc<&LispString>>, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let file = expand_file_name(file.untag(cx), None, env, cx)?;
+    lock(&file, env, cx)?;
+    Ok(false)
+}
+
+/// Remove the lock on FILE if this process holds it.
+#[defun]
+fn unlock_file(file: &str, env: &Rt<Env>, cx: &Context) -> Result<bool> {
+    unlock(&expand_file_name(file, None, env, cx)?)?;
+    Ok(false)
+}
+
+/// Lock FILE, which defaults to the visited file of the current buffer, if
+/// the current buffer is modified.
+#[defun]
+fn lock_buffer(file: Option<&Rto<Object>>, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let buffer = env.current_buffer.get();
+    if !buffer.is_modified() {
+        return Ok(false);
+    }
+    let file = match file.map(|x| x.bind(cx)).filter(|x| !x.is_nil()) {
+        Some(file) => expand_file_name(file.try_into()?, None, env, cx)?,
+        None => match buffer.file_name.clone() {
+            Some(file) => file,
+            None => return Ok(false),
+        },
+    };
+    lock(&file, env, cx)?;
+    Ok(false)
+}
+
+/// Unlock the visited file of the current buffer if the buffer is modified.
+#[defun]
+fn unlock_buffer(env: &Rt<Env>) -> Result<bool> {
+    let buffer = env.current_buffer.get();
+    if let Some(file) = buffer.file_name.as_deref().filter(|_| buffer.is_modified()) {
+        unlock(file)?;
+    }
+    Ok(false)
+}
+
+defsym!(ASK_USER_ABOUT_LOCK);
+defsym!(FILE_LOCKED);
+defvar_bool!(CREATE_LOCKFILES, true);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp_with_vars;
+
+    #[test]
+    fn test_lock_files() {
+        let dir = std::env::temp_dir().join(format!("rune-filelock-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("locked.txt");
+        std::fs::write(&path, "text\n").unwrap();
+        let file = path.to_str().unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(let ((buffer (find-file-noselect \"{file}\")))
+                   (set-buffer buffer)
+                   (list (file-locked-p \"{file}\")
+                         (progn (insert \"more \") (file-locked-p \"{file}\"))
+                         (progn (save-buffer) (file-locked-p \"{file}\"))))"
+            ),
+            "(nil t nil)",
+        );
+
+        let other = dir.join("other.txt");
+        let other_file = other.to_str().unwrap();
+        std::fs::write(dir.join(".#other.txt"), "someone@elsewhere.1").unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(list (file-locked-p \"{other_file}\")
+                       (condition-case err (lock-file \"{other_file}\") (file-locked (cdr err)))
+                       (progn (fset 'ask-user-about-lock (lambda (_file _user) nil))
+                              (lock-file \"{other_file}\")
+                              (file-locked-p \"{other_file}\"))
+                       (progn (fset 'ask-user-about-lock (lambda (_file _user) t))
+                              (lock-file \"{other_file}\")
+                              (file-locked-p \"{other_file}\"))
+                       (progn (fmakunbound 'ask-user-about-lock)
+                              (unlock-file \"{other_file}\")
+                              (file-locked-p \"{other_file}\")))"
+            ),
+            &format!("(\"someone\" (\"{other_file}\" \"someone\") \"someone\" t nil)"),
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
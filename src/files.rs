@@ -0,0 +1,551 @@
+//! Visiting files in buffers.
+//!
+//! These are small versions of the commands in files.el, built on the
+//! primitives in fileio.rs. A buffer visiting a file records the modification
+//! time and a checksum of the file when it was read or saved, and saving
+//! signals `file-supersession' if the file was changed on disk since then. A
+//! file with a new modification time but the same contents is not considered
+//! changed. The first save of a buffer backs up the old file when
+//! `make-backup-files' is non-nil.
+//!
+//! Buffers with auto-saving enabled are written to `#FILE#' by a timer every
+//! `auto-save-timeout' seconds when they have unsaved changes. The auto-save
+//! file is deleted when the buffer is saved, and `recover-file' restores a
+//! file from it.
+use crate::buffer::{file_buffer, generate_new_buffer_name, get_buffer_create, BUFFERS};
+use crate::coding::{decode_file, encode, set_last_used, write_eol, Eol};
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Gc, LispBuffer, LispString, Object, ObjectType, OptionalFlag, Symbol, NIL},
+};
+use crate::editfns::show_message;
+use crate::eval::EvalError;
+use crate::fileio::{
+    expand_file_name, file_modtime, file_name_nondirectory, read_file, record_visited_file,
+    visited_file_changed, write_file,
+};
+use crate::insdel::{signal_after_change, signal_before_change};
+use crate::permissions::{check_file, Capability};
+use anyhow::{bail, ensure, Result};
+use rune_core::macros::list;
+use rune_macros::defun;
+use std::path::Path;
+
+/// Return the name of the backup file for FILE.
+#[defun]
+fn make_backup_file_name(file: &str) -> String {
+    format!("{file}~")
+}
+
+/// Return a buffer visiting FILENAME, creating it if needed. If FILENAME
+/// does not exist the buffer is empty and saving it creates the file.
+#[defun]
+fn find_file_noselect<'ob>(
+    filename: &str,
+    _nowarn: OptionalFlag,
+    _rawfile: OptionalFlag,
+    wildcards: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    ensure!(wildcards.is_none(), "wildcards not implemented");
+    let filename = expand_file_name(filename, None, env, cx)?;
+    if let Some(buffer) = file_buffer(&filename, env, cx) {
+        return Ok(cx.add(buffer));
+    }
+    ensure!(!Path::new(&filename).is_dir(), "{filename} is a directory");
+    let modtime = file_modtime(&filename);
+    let (bytes, (text, eol)) = match modtime {
+        Some(_) => {
+            let bytes = read_file(&filename, env, cx)?;
+            let decoded = decode_file(&bytes, env, cx)?;
+            (bytes, decoded)
+        }
+        None => (Vec::new(), (String::new(), None)),
+    };
+    let name = generate_new_buffer_name(file_name_nondirectory(&filename), None);
+    let buffer = get_buffer_create(cx.add(name), None, cx)?;
+    let ObjectType::Buffer(b) = buffer.untag() else {
+        unreachable!("get-buffer-create did not return a buffer")
+    };
+    env.with_buffer_mut(b, |b| {
+        b.text.insert(&text);
+        b.text.set_cursor(0);
+        b.file_name = Some(filename.clone());
+        record_visited_file(b, modtime, &bytes);
+        b.eol = eol.unwrap_or_default();
+        b.set_unmodified();
+    })?;
+    if is_set(sym::AUTO_SAVE_DEFAULT, env, cx) {
+        let auto_save = auto_save_name(&filename);
+        if file_modtime(&auto_save) > modtime {
+            let msg = format!("{filename} has auto save data; consider M-x recover-file");
+            show_message(&msg, env, cx)?;
+        }
+        env.with_buffer_mut(b, |b| b.auto_save_file_name = Some(auto_save.clone()))?;
+        schedule_auto_save(env, cx)?;
+    }
+    crate::recentf::add_file(&filename, env, cx)?;
+    Ok(buffer)
+}
+
+/// Visit FILENAME in a buffer and make it the current buffer.
+#[defun]
+fn find_file<'ob>(
+    filename: &str,
+    wildcards: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let buffer = find_file_noselect(filename, None, None, wildcards, env, cx)?;
+    crate::buffer::set_buffer(buffer, env, cx)
+}
+
+fn is_set(var: Symbol, env: &Rt<Env>, cx: &Context) -> bool {
+    env.vars.get(var).is_some_and(|x| !x.bind(cx).is_nil())
+}
+
+/// Back up `file` before it is first overwritten, if `make-backup-files' is
+/// non-nil. The file is renamed unless `backup-by-copying' is non-nil.
+fn backup_file(file: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    if env.current_buffer.get().backed_up
+        || !is_set(sym::MAKE_BACKUP_FILES, env, cx)
+        || !Path::new(file).exists()
+    {
+        return Ok(());
+    }
+    let backup = make_backup_file_name(file);
+    check_file(Capability::Write, &backup, env, cx)?;
+    if is_set(sym::BACKUP_BY_COPYING, env, cx) {
+        std::fs::copy(file, &backup)?;
+    } else {
+        std::fs::rename(file, &backup)?;
+    }
+    env.current_buffer.get_mut().backed_up = true;
+    Ok(())
+}
+
+/// Add a newline to the end of the current buffer if it is not empty and
+/// does not already end in one. Point is left where it was.
+fn add_final_newline(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let text = &env.current_buffer.get().text;
+    let len = text.len_chars();
+    if len == 0 || text.to_string().ends_with('\n') {
+        return Ok(());
+    }
+    signal_before_change(len + 1, len + 1, env, cx)?;
+    let text = &mut env.current_buffer.get_mut().text;
+    let point = text.cursor().chars();
+    text.set_cursor(len);
+    text.insert("\n");
+    text.set_cursor(point);
+    signal_after_change(len + 1, len + 2, 0, env, cx)
+}
+
+/// Save the current buffer to its visited file if it was modified. Signals
+/// `file-supersession' if the file was changed on disk since it was visited;
+/// use `set-visited-file-modtime' to save over it anyway. A newline is added
+/// to the end of the file first if `require-final-newline' is non-nil. The
+/// file is written with the line endings of `buffer-file-coding-system'.
+/// Returns t if the buffer was written.
+#[defun]
+fn save_buffer(_arg: OptionalFlag, env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let buffer = env.current_buffer.get();
+    if !buffer.is_modified() {
+        show_message("(No changes need to be saved)", env, cx)?;
+        return Ok(false);
+    }
+    let Some(file) = buffer.file_name.clone() else {
+        bail!("Buffer {} is not visiting a file", buffer.name)
+    };
+    check_file(Capability::Write, &file, env, cx)?;
+    if visited_file_changed(env.current_buffer.get()) {
+        let data = list!["File changed on disk since it was visited", file; cx];
+        return Err(EvalError::signal(sym::FILE_SUPERSESSION.into(), data, env).into());
+    }
+    if is_set(sym::REQUIRE_FINAL_NEWLINE, env, cx) {
+        add_final_newline(env, cx)?;
+    }
+    backup_file(&file, env, cx)?;
+    let eol = write_eol(env.current_buffer.get().eol, env, cx)?;
+    set_last_used(Some(eol), env, cx)?;
+    let bytes = encode(&env.current_buffer.get().text.to_string(), eol);
+    write_file(&file, &bytes, false, env, cx)?;
+    let buffer = env.current_buffer.get_mut();
+    buffer.eol = eol;
+    record_visited_file(buffer, file_modtime(&file), &bytes);
+    crate::filelock::unlock(&file)?;
+    buffer.set_unmodified();
+    env.sync_buffer_variables(cx);
+    let auto_save = env.current_buffer.get().auto_save_file_name.clone();
+    if let Some(auto_save) = auto_save.filter(|_| is_set(sym::DELETE_AUTO_SAVE_FILES, env, cx)) {
+        match std::fs::remove_file(auto_save) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    show_message(&format!("Wrote {file}"), env, cx)?;
+    Ok(true)
+}
+
+/// Replace the text of the current buffer with the contents of its visited
+/// file and mark it as unmodified. Point stays where it was if possible.
+#[defun]
+fn revert_buffer(
+    _ignore_auto: OptionalFlag,
+    _noconfirm: OptionalFlag,
+    _preserve_modes: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let Some(file) = env.current_buffer.get().file_name.clone() else {
+        bail!("Buffer does not seem to be associated with any file")
+    };
+    let modtime = file_modtime(&file);
+    let bytes = read_file(&file, env, cx)?;
+    let (text, eol) = decode_file(&bytes, env, cx)?;
+    let old_len = replace_text(&text, env, cx)?;
+    let buffer = env.current_buffer.get_mut();
+    buffer.eol = eol.unwrap_or_default();
+    record_visited_file(buffer, modtime, &bytes);
+    crate::filelock::unlock(&file)?;
+    buffer.set_unmodified();
+    env.sync_buffer_variables(cx);
+    signal_after_change(1, text.chars().count() + 1, old_len, env, cx)?;
+    Ok(true)
+}
+
+/// Replace the text of the current buffer with `text`, leaving the cursor
+/// where it was if possible. Runs the before-change hooks but leaves the after-change
+/// hooks to the caller. Returns the length of the old text.
+fn replace_text(text: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<usize> {
+    let len = env.current_buffer.get().text.len_chars();
+    signal_before_change(1, len + 1, env, cx)?;
+    let buffer = env.current_buffer.get_mut();
+    let point = buffer.text.cursor().chars();
+    let len = buffer.text.len_chars();
+    buffer.text.delete_range(0, len);
+    buffer.text.insert(text);
+    buffer.text.set_cursor(point.min(buffer.text.len_chars()));
+    Ok(len)
+}
+
+/// Return the auto-save file name for the visited file `file`.
+fn auto_save_name(file: &str) -> String {
+    let name = format!("#{}#", file_name_nondirectory(file));
+    match Path::new(file).parent() {
+        Some(dir) => dir.join(name).to_string_lossy().into_owned(),
+        None => name,
+    }
+}
+
+/// Return the name of the file the current buffer is auto-saved to. This is
+/// `#FILE#' next to the visited file, or `#%BUFFER#' in `default-directory'
+/// if the buffer is not visiting a file.
+#[defun]
+fn make_auto_save_file_name(env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let buffer = env.current_buffer.get();
+    match &buffer.file_name {
+        Some(file) => Ok(auto_save_name(file)),
+        None => expand_file_name(&format!("#%{}#", buffer.name), None, env, cx),
+    }
+}
+
+/// Make sure the auto-save timer is running, unless `auto-save-timeout' is
+/// nil or zero.
+fn schedule_auto_save(env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let timeout = match env.vars.get(sym::AUTO_SAVE_TIMEOUT).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::Int(x)) if x > 0 => x as f64,
+        Some(ObjectType::Float(x)) if **x > 0.0 => **x,
+        _ => return Ok(()),
+    };
+    let auto_save = Some(sym::DO_AUTO_SAVE.into());
+    let timers = crate::timer::timers(env, cx)?;
+    if !timers.into_iter().any(|x| crate::timer::timer_function(x) == auto_save) {
+        crate::timer::add_timer(timeout, Some(timeout), sym::DO_AUTO_SAVE.into(), NIL, env, cx)?;
+    }
+    Ok(())
+}
+
+/// Turn auto-saving of the current buffer on if ARG is nil or positive, or
+/// off otherwise. If ARG is `toggle', switch it. Returns t if auto-saving is
+/// now on.
+#[defun]
+fn auto_save_mode(arg: Option<Object>, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let enabled = env.current_buffer.get().auto_save_file_name.is_some();
+    let enable = match arg.map(|x| x.untag()) {
+        None | Some(ObjectType::NIL) => true,
+        Some(ObjectType::Int(n)) => n > 0,
+        Some(ObjectType::Symbol(sym::TOGGLE)) => !enabled,
+        Some(_) => true,
+    };
+    let name = if enable { Some(make_auto_save_file_name(env, cx)?) } else { None };
+    env.current_buffer.get_mut().auto_save_file_name = name;
+    if enable {
+        schedule_auto_save(env, cx)?;
+    }
+    Ok(enable)
+}
+
+/// Write each buffer that has auto-saving enabled and was changed since it
+/// was last saved or auto-saved to its auto-save file. Only the current
+/// buffer is auto-saved if CURRENT-ONLY is non-nil. Unless NO-MESSAGE is
+/// non-nil a message is shown when something was written.
+#[defun]
+fn do_auto_save(
+    no_message: OptionalFlag,
+    current_only: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let buffers: Vec<&LispBuffer> = match current_only {
+        Some(()) => vec![env.current_buffer.get().lisp_buffer(cx)],
+        None => BUFFERS.lock().unwrap().values().map(|x| cx.bind(*x)).collect(),
+    };
+    let mut saved = false;
+    for buffer in buffers {
+        // skip buffers open on other threads
+        let pending = env.try_with_buffer(buffer, |b| {
+            let file = b.auto_save_file_name.clone()?;
+            let tick = b.text.modified_tick();
+            (b.is_modified() && tick != b.auto_save_tick).then(|| (file, b.text.to_string(), tick))
+        });
+        let Some((file, text, tick)) = pending.flatten() else { continue };
+        check_file(Capability::Write, &file, env, cx)?;
+        if let Err(e) = std::fs::write(&file, encode(&text, Eol::Unix)) {
+            let msg = format!("Auto-saving {file}: {e}");
+            show_message(&msg, env, cx)?;
+            continue;
+        }
+        env.try_with_buffer_mut(buffer, |b| b.auto_save_tick = tick);
+        saved = true;
+    }
+    if saved && no_message.is_none() {
+        show_message("Auto-saving...done", env, cx)?;
+    }
+    Ok(())
+}
+
+/// Visit FILE and replace its text with the contents of its auto-save file.
+/// The auto-save file must be newer than FILE. Returns the buffer, which is
+/// made current and is left modified.
+#[defun]
+fn recover_file<'ob>(
+    file: &Rto<Gc<&LispString>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let file = expand_file_name(file.untag(cx), None, env, cx)?;
+    let auto_save = auto_save_name(&file);
+    let saved = file_modtime(&auto_save);
+    if saved.is_none() || saved <= file_modtime(&file) {
+        bail!("Auto-save file {auto_save} not current");
+    }
+    let (text, _) = decode_file(&read_file(&auto_save, env, cx)?, env, cx)?;
+    let buffer = find_file_noselect(&file, None, None, None, env, cx)?;
+    crate::buffer::set_buffer(buffer, env, cx)?;
+    let old_len = replace_text(&text, env, cx)?;
+    let b = env.current_buffer.get_mut();
+    b.text.set_cursor(0);
+    b.save_tick = None;
+    b.auto_save_tick = b.text.modified_tick();
+    b.auto_save_file_name = Some(auto_save);
+    signal_after_change(1, text.chars().count() + 1, old_len, env, cx)?;
+    Ok(cx.add(env.current_buffer.get().lisp_buffer(cx)))
+}
+
+defsym!(TOGGLE);
+defvar_bool!(MAKE_BACKUP_FILES, true);
+defvar_bool!(BACKUP_BY_COPYING, false);
+defvar_bool!(AUTO_SAVE_DEFAULT, true);
+defvar!(AUTO_SAVE_TIMEOUT, 30);
+defvar_bool!(DELETE_AUTO_SAVE_FILES, true);
+defvar_bool!(REQUIRE_FINAL_NEWLINE, false);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp_with_vars;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_visit_file() {
+        let dir = std::env::temp_dir().join(format!("rune-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("visit.txt");
+        std::fs::write(&path, "one\n").unwrap();
+        let file = path.to_str().unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(let ((buffer (find-file-noselect \"{file}\")))
+                   (set-buffer buffer)
+                   (list (buffer-name)
+                         (buffer-file-name)
+                         buffer-file-name
+                         (eq buffer (find-file-noselect \"{file}\"))
+                         (eq buffer (get-file-buffer \"{file}\"))
+                         (buffer-modified-p)
+                         (progn (insert \"zero\\n\") (buffer-modified-p))
+                         (save-buffer)
+                         (buffer-modified-p)
+                         (save-buffer)))"
+            ),
+            &format!("(\"visit.txt\" \"{file}\" \"{file}\" t t nil t t nil nil)"),
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "zero\none\n");
+        assert_eq!(std::fs::read_to_string(format!("{file}~")).unwrap(), "one\n");
+
+        std::fs::write(&path, "external\n").unwrap();
+        let file_handle = std::fs::File::options().write(true).open(&path).unwrap();
+        file_handle
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1000))
+            .unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (set-buffer (get-file-buffer \"{file}\"))
+                   (insert \"x\")
+                   (list (verify-visited-file-modtime)
+                         (condition-case err (save-buffer) (file-supersession (car err)))
+                         (progn (set-visited-file-modtime) (save-buffer))))"
+            ),
+            "(nil file-supersession t)",
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "zero\nxone\n");
+        assert_eq!(std::fs::read_to_string(format!("{file}~")).unwrap(), "one\n");
+
+        // touching the file without changing it is not a supersession threat
+        let file_handle = std::fs::File::options().write(true).open(&path).unwrap();
+        file_handle
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(2000))
+            .unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (set-buffer (get-file-buffer \"{file}\"))
+                   (insert \"z\")
+                   (list (verify-visited-file-modtime) (save-buffer)))"
+            ),
+            "(t t)",
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "zero\nxzone\n");
+
+        std::fs::write(&path, "new\n").unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (set-buffer (get-file-buffer \"{file}\"))
+                   (insert \"y\")
+                   (list (revert-buffer) (buffer-modified-p) (point) (point-max)))"
+            ),
+            "(t nil 4 5)",
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_raw_bytes() {
+        let dir = std::env::temp_dir().join(format!("rune-raw-bytes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("latin-1.txt");
+        std::fs::write(&path, b"caf\xe9\n\xff").unwrap();
+        let file = path.to_str().unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (set-buffer (find-file-noselect \"{file}\"))
+                   (list (point-max)
+                         (progn (insert \"x\") (save-buffer))
+                         (revert-buffer)
+                         (point-max)))"
+            ),
+            "(7 t t 8)",
+        );
+        assert_eq!(std::fs::read(&path).unwrap(), b"xcaf\xe9\n\xff");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let dir = std::env::temp_dir().join(format!("rune-line-endings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dos = dir.join("dos.txt");
+        let mac = dir.join("mac.txt");
+        std::fs::write(&dos, "a\r\nb").unwrap();
+        std::fs::write(&mac, "a\rb\r").unwrap();
+        let (dos_file, mac_file) = (dos.to_str().unwrap(), mac.to_str().unwrap());
+        assert_lisp_with_vars(
+            &format!(
+                "(let ((require-final-newline t))
+                   (set-buffer (find-file-noselect \"{dos_file}\"))
+                   (list buffer-file-coding-system
+                         (point-max)
+                         (progn (insert \"x\") (save-buffer))
+                         (point)
+                         last-coding-system-used))"
+            ),
+            "(utf-8-dos 4 t 2 utf-8-dos)",
+        );
+        assert_eq!(std::fs::read(&dos).unwrap(), b"xa\r\nb\r\n");
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (set-buffer (find-file-noselect \"{mac_file}\"))
+                   (insert \"x\")
+                   (save-buffer)
+                   (let ((coding-system-for-write 'utf-8-unix))
+                     (write-region nil nil \"{mac_file}\" nil t))
+                   (list buffer-file-coding-system (coding-system-eol-type 'utf-8-dos)))"
+            ),
+            "(utf-8-unix 1)",
+        );
+        assert_eq!(std::fs::read(&mac).unwrap(), b"xa\nb\n");
+        assert_lisp_with_vars(
+            "(list (detect-coding-string \"a\\r\\n\" t)
+                   (detect-coding-string \"a\")
+                   (coding-system-eol-type 'utf-8))",
+            "(utf-8-dos (undecided) [utf-8-unix utf-8-dos utf-8-mac])",
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_auto_save() {
+        let dir = std::env::temp_dir().join(format!("rune-auto-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("auto.txt");
+        let auto_save = dir.join("#auto.txt#");
+        std::fs::write(&path, "text\n").unwrap();
+        let file = path.to_str().unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (setq auto-save-timeout 0.01)
+                   (set-buffer (find-file-noselect \"{file}\"))
+                   (insert \"more \")
+                   (sleep-for 0.05)
+                   (list (make-auto-save-file-name) (timerp (car timer-list))))"
+            ),
+            &format!("(\"{}\" t)", auto_save.to_str().unwrap()),
+        );
+        assert_eq!(std::fs::read_to_string(&auto_save).unwrap(), "more text\n");
+
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let file_handle = std::fs::File::options().write(true).open(&path).unwrap();
+        file_handle.set_modified(old).unwrap();
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (recover-file \"{file}\")
+                   (list (buffer-modified-p)
+                         (point-max)
+                         (progn (set-visited-file-modtime) (save-buffer))
+                         (auto-save-mode 0)))"
+            ),
+            "(t 11 t nil)",
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "more text\n");
+        assert!(!auto_save.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+//! Filling text.
+//!
+//! Filling rewraps the words of a paragraph so that its lines are as long as
+//! possible without going past `fill-column'. Paragraphs are separated by
+//! blank lines. Runs of whitespace between words are squeezed to one space,
+//! or to two after the end of a sentence if `sentence-end-double-space' is
+//! non-nil. Columns are counted in characters and lines are always filled
+//! flush left.
+//!
+//! The lines after the first start with the fill prefix. This is
+//! `fill-prefix' if it is non-nil. Otherwise, if `adaptive-fill-mode' is
+//! non-nil, the prefix is found by matching `adaptive-fill-regexp' at the
+//! start of the first two lines of the paragraph, so that indented, quoted
+//! and commented paragraphs keep their shape.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{ObjectType, OptionalFlag, Symbol, NIL},
+};
+use crate::data::LispError;
+use crate::insdel::replace_region;
+use crate::search::lisp_regex_to_rust;
+use anyhow::{bail, Result};
+use fancy_regex::Regex;
+use rune_macros::defun;
+
+/// The settings that control filling, read from the environment.
+struct Filler {
+    column: usize,
+    prefix: Option<String>,
+    adaptive: Option<Regex>,
+    double_space: bool,
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Whether `word` ends a sentence, ignoring closing quotes and parens.
+fn ends_sentence(word: &str) -> bool {
+    word.trim_end_matches(['"', '\'', ')', ']']).ends_with(['.', '?', '!'])
+}
+
+impl Filler {
+    fn new(env: &Rt<Env>, cx: &Context) -> Result<Self> {
+        let var = |name: Symbol| env.vars.get(name).map_or(NIL, |x| x.bind(cx));
+        let column = match var(sym::FILL_COLUMN).untag() {
+            ObjectType::Int(column) if column > 0 => column as usize,
+            x => bail!("Invalid fill-column: {x}"),
+        };
+        let prefix = match var(sym::FILL_PREFIX).untag() {
+            ObjectType::String(prefix) if !prefix.is_empty() => Some(prefix.to_string()),
+            _ => None,
+        };
+        let adaptive = match var(sym::ADAPTIVE_FILL_REGEXP).untag() {
+            ObjectType::String(regexp) if !var(sym::ADAPTIVE_FILL_MODE).is_nil() => {
+                Some(Regex::new(&format!("^(?:{})", lisp_regex_to_rust(regexp)))?)
+            }
+            _ => None,
+        };
+        let double_space = !var(sym::SENTENCE_END_DOUBLE_SPACE).is_nil();
+        Ok(Self { column, prefix, adaptive, double_space })
+    }
+
+    /// Whether `line` is blank, apart from the fill prefix.
+    fn is_blank(&self, line: &str) -> bool {
+        let line = line.trim_end();
+        let prefix = self.prefix.as_deref().unwrap_or_default().trim_end();
+        line.strip_prefix(prefix).unwrap_or(line).trim().is_empty()
+    }
+
+    /// The text at the start of `line` matched by `adaptive-fill-regexp'.
+    fn adaptive_match<'a>(regex: &Regex, line: &'a str) -> &'a str {
+        match regex.find(line) {
+            Ok(Some(found)) => &line[..found.end()],
+            _ => "",
+        }
+    }
+
+    /// The prefix the first line of a paragraph starts with, and the prefix
+    /// for the lines after it. An adaptive prefix comes from the second line
+    /// if it is a prefix of the first line's or is whitespace as wide as it.
+    /// In a paragraph of one line, any prefix that isn't whitespace is
+    /// replaced by whitespace on the later lines, which hangs them under the
+    /// text of a list item.
+    fn prefixes(&self, lines: &[&str]) -> (String, String) {
+        let first = lines[0];
+        if let Some(prefix) = &self.prefix {
+            let leader = if first.starts_with(prefix.as_str()) {
+                prefix.as_str()
+            } else {
+                indentation(first)
+            };
+            return (leader.to_owned(), prefix.clone());
+        }
+        let Some(regex) = &self.adaptive else {
+            return (indentation(first).to_owned(), String::new());
+        };
+        let leader = Self::adaptive_match(regex, first);
+        let prefix = match lines.get(1) {
+            None if leader.trim().is_empty() => leader.to_owned(),
+            None => " ".repeat(width(leader)),
+            Some(second) => {
+                let prefix = Self::adaptive_match(regex, second);
+                let hanging = prefix.trim().is_empty() && width(prefix) == width(leader);
+                if leader.starts_with(prefix) || hanging {
+                    prefix.to_owned()
+                } else {
+                    String::new()
+                }
+            }
+        };
+        (leader.to_owned(), prefix)
+    }
+
+    /// Add the words of `line` to `words`, each with whether it ends a
+    /// sentence that should be followed by two spaces. That is a sentence
+    /// followed by two spaces or the end of the line.
+    fn words<'a>(&self, line: &'a str, words: &mut Vec<(&'a str, bool)>) {
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let (word, after) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+            rest = after.trim_start();
+            let spaces = after.chars().take_while(|x| x.is_whitespace()).count();
+            let sentence_end =
+                self.double_space && ends_sentence(word) && (rest.is_empty() || spaces >= 2);
+            words.push((word, sentence_end));
+        }
+    }
+
+    /// Fill `text` as one paragraph.
+    fn fill_paragraph(&self, text: &str) -> String {
+        let lines: Vec<&str> = text.lines().collect();
+        if lines.is_empty() {
+            return text.to_owned();
+        }
+        let (leader, prefix) = self.prefixes(&lines);
+        let mut words = Vec::new();
+        self.words(&lines[0][leader.len()..], &mut words);
+        for line in &lines[1..] {
+            let content = line.strip_prefix(prefix.as_str()).unwrap_or(line);
+            self.words(content, &mut words);
+        }
+        if words.is_empty() {
+            return text.to_owned();
+        }
+        let mut line_width = width(&leader);
+        let mut filled = leader;
+        // the spaces owed before the next word, or None at the start of a line
+        let mut gap = None;
+        for (word, sentence_end) in words {
+            let word_width = width(word);
+            match gap {
+                Some(gap) if line_width + gap + word_width > self.column => {
+                    filled.push('\n');
+                    filled.push_str(&prefix);
+                    line_width = width(&prefix);
+                }
+                Some(gap) => {
+                    filled.push_str(&" ".repeat(gap));
+                    line_width += gap;
+                }
+                None => {}
+            }
+            filled.push_str(word);
+            line_width += word_width;
+            gap = Some(if sentence_end { 2 } else { 1 });
+        }
+        if text.ends_with('\n') {
+            filled.push('\n');
+        }
+        filled
+    }
+
+    /// Fill each paragraph of `text`, leaving the blank lines between them.
+    fn fill_region(&self, text: &str) -> String {
+        let mut filled = String::new();
+        let mut paragraph = String::new();
+        for line in text.split_inclusive('\n') {
+            if self.is_blank(line) {
+                filled.push_str(&self.fill_paragraph(&paragraph));
+                filled.push_str(line);
+                paragraph.clear();
+            } else {
+                paragraph.push_str(line);
+            }
+        }
+        filled.push_str(&self.fill_paragraph(&paragraph));
+        filled
+    }
+}
+
+/// The start and end of each line of `chars`. The end includes the newline.
+fn line_bounds(chars: &[char]) -> Vec<(usize, usize)> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '\n' {
+            lines.push((start, i + 1));
+            start = i + 1;
+        }
+    }
+    lines.push((start, chars.len()));
+    lines
+}
+
+/// The index of the line in `lines` that contains `pos`.
+fn line_at(lines: &[(usize, usize)], pos: usize) -> usize {
+    lines.iter().position(|(_, end)| pos < *end).unwrap_or(lines.len() - 1)
+}
+
+/// Expand the region from `from` to `to` to whole lines, and fill the text
+/// in it with `fill`.
+fn fill_lines(
+    from: usize,
+    to: usize,
+    fill: impl FnOnce(&Filler, &str) -> String,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let (from, to) = (from.min(to), from.max(to));
+    let chars: Vec<char> = env.current_buffer.get().text.to_string().chars().collect();
+    if from < 1 || to > chars.len() + 1 {
+        bail!(LispError::region_out_of_range(from, to, cx));
+    }
+    let lines = line_bounds(&chars);
+    let start = lines[line_at(&lines, from - 1)].0;
+    let (last_start, last_end) = lines[line_at(&lines, to - 1)];
+    // a region that ends at the start of a line doesn't include that line
+    let end = if to > from && to - 1 == last_start { last_start } else { last_end };
+    let text: String = chars[start..end].iter().collect();
+    let filled = fill(&Filler::new(env, cx)?, &text);
+    if filled != text {
+        replace_region(start + 1, end + 1, &filled, env, cx)?;
+    }
+    Ok(())
+}
+
+/// Fill each paragraph in the region from FROM to TO. The region is extended
+/// to whole lines. JUSTIFY, NOSQUEEZE and TO-EOP are not supported and are
+/// ignored.
+#[defun]
+fn fill_region(
+    from: usize,
+    to: usize,
+    _justify: OptionalFlag,
+    _nosqueeze: OptionalFlag,
+    _to_eop: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    fill_lines(from, to, Filler::fill_region, env, cx)
+}
+
+/// Fill the region from FROM to TO as one paragraph, joining any paragraphs
+/// in it. The region is extended to whole lines. JUSTIFY, NOSQUEEZE and
+/// SQUEEZE-AFTER are not supported and are ignored.
+#[defun]
+fn fill_region_as_paragraph(
+    from: usize,
+    to: usize,
+    _justify: OptionalFlag,
+    _nosqueeze: OptionalFlag,
+    _squeeze_after: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    fill_lines(from, to, Filler::fill_paragraph, env, cx)
+}
+
+/// Fill the paragraph at point, or the one after it if point is between
+/// paragraphs, or the one before if there are none after it. Return non-nil
+/// if there was a paragraph to fill. JUSTIFY and REGION are not supported
+/// and are ignored.
+#[defun]
+fn fill_paragraph(
+    _justify: OptionalFlag,
+    _region: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let filler = Filler::new(env, cx)?;
+    let buffer = env.current_buffer.get();
+    let chars: Vec<char> = buffer.text.to_string().chars().collect();
+    let point = buffer.text.cursor().chars();
+    let lines = line_bounds(&chars);
+    let blank = |i: usize| {
+        let (start, end) = lines[i];
+        filler.is_blank(&chars[start..end].iter().collect::<String>())
+    };
+    let here = line_at(&lines, point);
+    let found = (here..lines.len()).find(|x| !blank(*x));
+    let Some(line) = found.or_else(|| (0..here).rev().find(|x| !blank(*x))) else {
+        return Ok(false);
+    };
+    let first = (0..=line).rev().take_while(|x| !blank(*x)).last().unwrap_or(line);
+    let last = (line..lines.len()).take_while(|x| !blank(*x)).last().unwrap_or(line);
+    let (start, end) = (lines[first].0, lines[last].1);
+    fill_lines(start + 1, end + 1, Filler::fill_paragraph, env, cx)?;
+    Ok(true)
+}
+
+defvar!(FILL_PREFIX);
+defvar_bool!(ADAPTIVE_FILL_MODE, true);
+defvar!(ADAPTIVE_FILL_REGEXP, "[-–!|#%;>*·•‣⁃◦ \t]*");
+defvar_bool!(SENTENCE_END_DOUBLE_SPACE, true);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::eval_in_buffer;
+
+    /// Evaluate `form` in a buffer that contains `text` with `fill-column'
+    /// set to `column`, and return the text of the buffer.
+    fn fill(text: &str, column: i64, form: &str) -> String {
+        eval_in_buffer(text, &format!("(progn (setq fill-column {column}) {form})")).1
+    }
+
+    fn fill_all(text: &str, column: i64) -> String {
+        fill(text, column, "(fill-region 1 (point-max))")
+    }
+
+    #[test]
+    fn test_fill_region() {
+        assert_eq!(fill_all("aaa bbb ccc ddd eee\n", 10), "aaa bbb\nccc ddd\neee\n");
+        assert_eq!(fill_all("a\nb   c\n\n\nd\ne", 10), "a b c\n\n\nd e");
+        assert_eq!(fill_all("averyveryverylongword b", 5), "averyveryverylongword\nb");
+        assert_eq!(fill_all("One.  Two three.\nFour. Five", 70), "One.  Two three.  Four. Five");
+        assert_eq!(
+            fill_all("  - one two three. four five six\n", 14),
+            "  - one two\n    three.\n    four five\n    six\n"
+        );
+        assert_eq!(fill_all(";; aaa bbb\n;; ccc ddd eee", 12), ";; aaa bbb\n;; ccc ddd\n;; eee");
+        assert_eq!(fill_all("  aaa bbb\nccc ddd", 8), "  aaa\nbbb ccc\nddd");
+        let form = "(condition-case err (fill-region 1 99) (args-out-of-range err))";
+        assert_eq!(eval_in_buffer("a b", form).0, "(args-out-of-range 1 99)");
+    }
+
+    #[test]
+    fn test_fill_prefix() {
+        let filled =
+            fill("> a b c d\n>\n> e", 6, "(progn (setq fill-prefix \"> \") (fill-region 1 16))");
+        assert_eq!(filled, "> a b\n> c d\n>\n> e");
+    }
+
+    #[test]
+    fn test_fill_paragraph() {
+        let text = "aaa bbb ccc\n\nddd eee fff\nggg\n\n";
+        let filled = fill(text, 7, "(progn (goto-char 12) (fill-paragraph))");
+        assert_eq!(filled, "aaa bbb ccc\n\nddd eee\nfff ggg\n\n");
+        let filled = fill(text, 7, "(progn (goto-char 30) (fill-paragraph))");
+        assert_eq!(filled, "aaa bbb ccc\n\nddd eee\nfff ggg\n\n");
+        let filled = fill(text, 7, "(progn (goto-char 2) (fill-region-as-paragraph 1 17))");
+        assert_eq!(filled, "aaa bbb\nccc ddd\neee fff\nggg\n\n");
+    }
+}
@@ -4,10 +4,14 @@ use crate::{
     core::{
         cons::Cons,
         gc::Context,
-        object::{Number, NumberType, Object},
+        object::{LispFloat, Number, NumberType, Object},
     },
+    data::LispError,
 };
 
+use anyhow::Result;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
 use rune_macros::defun;
 
 #[inline(always)]
@@ -15,58 +19,177 @@ fn coerce(arg: Number) -> f64 {
     match arg.untag() {
         NumberType::Int(i) => i as f64,
         NumberType::Float(f) => **f,
+        NumberType::BigInt(b) => b.get().to_f64().unwrap_or(f64::NAN),
     }
 }
 
-#[defun]
-fn floor(arg: Number, divisor: Option<Number>) -> i64 {
-    let num = match divisor {
-        Some(div) => arg.val() / div.val(),
-        None => arg.val(),
-    };
-    match num {
-        NumberValue::Int(i) => i,
-        NumberValue::Float(f) => f.floor() as i64,
+/// Convert a float that has already been rounded to an integer, promoting it
+/// to a bignum if it does not fit in a fixnum. Infinities and NaNs signal
+/// `overflow-error'.
+fn float_to_int(f: f64, cx: &Context) -> Result<NumberValue> {
+    match BigInt::from_f64(f) {
+        Some(x) => Ok(NumberValue::Big(x).normalize()),
+        None => Err(LispError::overflow_error(cx.add(f), cx).into()),
     }
 }
 
-#[defun]
-fn ceiling(arg: Number) -> i64 {
-    match arg.untag() {
-        NumberType::Int(i) => i,
-        NumberType::Float(f) => f.ceil() as i64,
+/// Return a finite number as an exact integer mantissa and a binary
+/// exponent, so that it equals `mantissa * 2^(exponent + MIN_EXP)`.
+fn exact(number: NumberValue) -> Option<(BigInt, usize)> {
+    // The smallest exponent of a float, including subnormals
+    const MIN_EXP: i64 = -1074;
+    match number {
+        NumberValue::Int(x) => Some((BigInt::from(x), -MIN_EXP as usize)),
+        NumberValue::Big(x) => Some((x, -MIN_EXP as usize)),
+        NumberValue::Float(f) if f.is_finite() => {
+            let bits = f.to_bits();
+            let exp = ((bits >> 52) & 0x7ff) as i64;
+            let fraction = bits & ((1 << 52) - 1);
+            // Subnormals have no implicit leading bit
+            let (mantissa, exp) = if exp == 0 { (fraction, 1) } else { (fraction | 1 << 52, exp) };
+            let mantissa = BigInt::from(mantissa);
+            let mantissa = if f.is_sign_negative() { -mantissa } else { mantissa };
+            Some((mantissa, (exp - 1075 - MIN_EXP) as usize))
+        }
+        NumberValue::Float(_) => None,
     }
 }
 
-#[defun]
-fn fceiling(arg: Number) -> f64 {
-    match arg.untag() {
-        NumberType::Int(i) => i as f64,
-        NumberType::Float(f) => f.ceil(),
+/// How a quotient is rounded to an integer.
+#[derive(Debug, Clone, Copy)]
+enum Rounding {
+    Floor,
+    Ceiling,
+    /// To the nearest integer, with ties to even.
+    Round,
+    Truncate,
+}
+
+impl Rounding {
+    fn float(self, f: f64) -> f64 {
+        match self {
+            Rounding::Floor => f.floor(),
+            Rounding::Ceiling => f.ceil(),
+            Rounding::Round => f.round_ties_even(),
+            Rounding::Truncate => f.trunc(),
+        }
+    }
+
+    /// Divide `n` by `d`, which must not be zero, rounding the quotient.
+    fn divide<T: Signed + Clone + PartialOrd>(self, n: T, d: T) -> T {
+        let quotient = n.clone() / d.clone();
+        let rem = n % d.clone();
+        if rem.is_zero() {
+            return quotient;
+        }
+        // The exact quotient is positive if the remainder and divisor have
+        // the same sign
+        let positive = rem.is_negative() == d.is_negative();
+        let away_from_zero = match self {
+            Rounding::Floor => !positive,
+            Rounding::Ceiling => positive,
+            Rounding::Round => {
+                let twice = rem.abs() + rem.abs();
+                let two = T::one() + T::one();
+                twice > d.abs() || (twice == d.abs() && !(quotient.clone() % two).is_zero())
+            }
+            Rounding::Truncate => false,
+        };
+        match (away_from_zero, positive) {
+            (false, _) => quotient,
+            (true, true) => quotient + T::one(),
+            (true, false) => quotient - T::one(),
+        }
+    }
+
+    /// Round ARG, or ARG divided by DIVISOR, to an integer. Quotients are
+    /// computed exactly, even when an argument is a float.
+    fn apply(self, arg: Number, divisor: Option<Number>, cx: &Context) -> Result<NumberValue> {
+        use NumberValue as N;
+        let Some(divisor) = divisor else {
+            return match arg.val() {
+                N::Float(f) => float_to_int(self.float(f), cx),
+                int => Ok(int),
+            };
+        };
+        let (n, d) = (arg.val(), divisor.val());
+        if d.is_zero() {
+            return Err(LispError::arith_error(cx).into());
+        }
+        if let (N::Int(n), N::Int(d)) = (&n, &d) {
+            return Ok(N::Int(self.divide(*n, *d)));
+        }
+        let finite = |x: &N| !matches!(x, N::Float(f) if !f.is_finite());
+        if finite(&n) && matches!(d, N::Float(f) if f.is_infinite()) {
+            return Ok(N::Int(0));
+        }
+        let overflow = |x: &N| LispError::overflow_error(cx.add(x.clone()), cx);
+        let Some((n_mantissa, n_exp)) = exact(n.clone()) else { return Err(overflow(&n).into()) };
+        let Some((d_mantissa, d_exp)) = exact(d.clone()) else { return Err(overflow(&d).into()) };
+        // scale both to the same exponent so that their quotient is exact
+        let scale = n_exp.min(d_exp);
+        let n = n_mantissa << (n_exp - scale);
+        let d = d_mantissa << (d_exp - scale);
+        Ok(N::Big(self.divide(n, d)).normalize())
     }
 }
 
+/// Return the largest integer no greater than ARG. With DIVISOR, return the
+/// largest integer no greater than ARG divided by DIVISOR.
 #[defun]
-fn round(arg: Number) -> i64 {
-    match arg.untag() {
-        NumberType::Int(i) => i,
-        NumberType::Float(f) => f.round() as i64,
-    }
+fn floor(arg: Number, divisor: Option<Number>, cx: &Context) -> Result<NumberValue> {
+    Rounding::Floor.apply(arg, divisor, cx)
 }
 
+/// Return the smallest integer no less than ARG, or ARG divided by DIVISOR.
 #[defun]
-fn truncate(arg: Number) -> i64 {
-    match arg.untag() {
-        NumberType::Int(i) => i,
-        NumberType::Float(f) => f.trunc() as i64,
-    }
+fn ceiling(arg: Number, divisor: Option<Number>, cx: &Context) -> Result<NumberValue> {
+    Rounding::Ceiling.apply(arg, divisor, cx)
+}
+
+/// Return the nearest integer to ARG, or ARG divided by DIVISOR. Ties round
+/// to the even integer, so `(round 2.5)' is 2.
+#[defun]
+fn round(arg: Number, divisor: Option<Number>, cx: &Context) -> Result<NumberValue> {
+    Rounding::Round.apply(arg, divisor, cx)
+}
+
+/// Return ARG, or ARG divided by DIVISOR, rounded toward zero.
+#[defun]
+fn truncate(arg: Number, divisor: Option<Number>, cx: &Context) -> Result<NumberValue> {
+    Rounding::Truncate.apply(arg, divisor, cx)
+}
+
+/// Return the largest integer no greater than the float ARG, as a float.
+#[defun]
+fn ffloor(arg: &LispFloat) -> f64 {
+    Rounding::Floor.float(**arg)
+}
+
+/// Return the smallest integer no less than the float ARG, as a float.
+#[defun]
+fn fceiling(arg: &LispFloat) -> f64 {
+    Rounding::Ceiling.float(**arg)
+}
+
+/// Return the nearest integer to the float ARG, as a float. Ties round to
+/// even.
+#[defun]
+fn fround(arg: &LispFloat) -> f64 {
+    Rounding::Round.float(**arg)
+}
+
+/// Return the float ARG rounded toward zero, as a float.
+#[defun]
+fn ftruncate(arg: &LispFloat) -> f64 {
+    Rounding::Truncate.float(**arg)
 }
 
 #[defun]
 fn float<'ob>(arg: Number<'ob>, cx: &'ob Context) -> Number<'ob> {
     match arg.untag() {
-        NumberType::Int(i) => cx.add_as(i as f64),
         NumberType::Float(_) => arg,
+        _ => cx.add_as(coerce(arg)),
     }
 }
 
@@ -107,8 +230,8 @@ fn tan(arg: Number) -> f64 {
 #[defun]
 fn isnan(arg: Number) -> bool {
     match arg.untag() {
-        NumberType::Int(_) => false,
         NumberType::Float(f) => f.is_nan(),
+        _ => false,
     }
 }
 
@@ -126,7 +249,11 @@ fn exp(arg: Number) -> f64 {
 fn expt(x: Number, y: Number) -> NumberValue {
     // If either is a float, we use the float version
     match (x.untag(), y.untag()) {
-        (NumberType::Int(x), NumberType::Int(y)) => NumberValue::Int(x.pow(y as u32)),
+        (NumberType::Int(x), NumberType::Int(y)) => match x.checked_pow(y as u32) {
+            Some(pow) => NumberValue::Int(pow),
+            None => NumberValue::Big(BigInt::from(x).pow(y as u32)).normalize(),
+        },
+        (NumberType::BigInt(x), NumberType::Int(y)) => NumberValue::Big(x.get().pow(y as u32)),
         _ => {
             let x = coerce(x);
             let y = coerce(y);
@@ -152,8 +279,12 @@ fn sqrt(arg: Number) -> f64 {
 #[defun]
 fn abs(arg: Number) -> NumberValue {
     match arg.untag() {
-        NumberType::Int(i) => NumberValue::Int(i.abs()),
+        NumberType::Int(i) => match i.checked_abs() {
+            Some(i) => NumberValue::Int(i),
+            None => NumberValue::Big(BigInt::from(i).abs()),
+        },
         NumberType::Float(f) => NumberValue::Float(f.abs()),
+        NumberType::BigInt(b) => NumberValue::Big(b.get().abs()),
     }
 }
 
@@ -189,3 +320,40 @@ fn frexp<'ob>(x: Number, cx: &'ob Context) -> Object<'ob> {
     let (significand, exponent) = frexp_f(f);
     Cons::new(significand, exponent, cx).into()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_divide() {
+        use Rounding::*;
+        // (n d floor ceiling round truncate)
+        let table = [
+            (7, 2, 3, 4, 4, 3),
+            (-7, 2, -4, -3, -4, -3),
+            (7, -2, -4, -3, -4, -3),
+            (-7, -2, 3, 4, 4, 3),
+            (5, 2, 2, 3, 2, 2),
+            (8, 3, 2, 3, 3, 2),
+            (6, 3, 2, 2, 2, 2),
+        ];
+        for (n, d, floor, ceiling, round, truncate) in table {
+            assert_eq!(Floor.divide(n, d), floor, "(floor {n} {d})");
+            assert_eq!(Ceiling.divide(n, d), ceiling, "(ceiling {n} {d})");
+            assert_eq!(Round.divide(n, d), round, "(round {n} {d})");
+            assert_eq!(Truncate.divide(n, d), truncate, "(truncate {n} {d})");
+            let big = Floor.divide(BigInt::from(n), BigInt::from(d));
+            assert_eq!(big, BigInt::from(floor));
+        }
+    }
+
+    #[test]
+    fn test_exact() {
+        let (mantissa, exp) = exact(NumberValue::Float(-1.5)).unwrap();
+        assert_eq!(mantissa, BigInt::from(-3_i64 << 51));
+        assert_eq!(exp, 1074 - 52);
+        assert_eq!(exact(NumberValue::Float(f64::MIN_POSITIVE / 4.0)).unwrap().1, 0);
+        assert!(exact(NumberValue::Float(f64::INFINITY)).is_none());
+    }
+}
@@ -7,7 +7,8 @@ use crate::{
         gc::{Context, Rt, Rto},
         object::{
             Function, Gc, HashTable, IntoObject, LispHashTable, LispString, LispVec, List,
-            ListType, Object, ObjectType, OptionalFlag, Symbol, WithLifetime, NIL,
+            ListType, Number, NumberType, Object, ObjectType, OptionalFlag, RecordBuilder,
+            Sequence, Symbol, WithLifetime, NIL, TRUE,
         },
     },
     data::aref,
@@ -19,6 +20,9 @@ use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use rune_core::macros::{call, list, rebind, root};
 use rune_macros::defun;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 #[defun]
 fn identity(arg: Object) -> Object {
@@ -63,6 +67,7 @@ pub(crate) fn equal<'ob>(obj1: Object<'ob>, obj2: Object<'ob>) -> bool {
 pub(crate) fn eql<'ob>(obj1: Object<'ob>, obj2: Object<'ob>) -> bool {
     match (obj1.untag(), obj2.untag()) {
         (ObjectType::Float(f1), ObjectType::Float(f2)) => f1.to_bits() == f2.to_bits(),
+        (ObjectType::BigInt(b1), ObjectType::BigInt(b2)) => b1 == b2,
         _ => obj1.ptr_eq(obj2),
     }
 }
@@ -74,7 +79,7 @@ fn equal_including_properties<'ob>(o1: Object<'ob>, o2: Object<'ob>) -> bool {
 }
 
 #[defun]
-fn plist_get<'ob>(plist: Object<'ob>, prop: Object<'ob>) -> Result<Object<'ob>> {
+pub(crate) fn plist_get<'ob>(plist: Object<'ob>, prop: Object<'ob>) -> Result<Object<'ob>> {
     let Ok(plist) = List::try_from(plist) else { return Ok(NIL) };
     // TODO: this function should never fail. Need to implement safe iterator
     let mut iter = plist.elements();
@@ -87,6 +92,41 @@ fn plist_get<'ob>(plist: Object<'ob>, prop: Object<'ob>) -> Result<Object<'ob>>
     Ok(NIL)
 }
 
+#[defun]
+fn plist_put<'ob>(
+    plist: Object<'ob>,
+    prop: Object<'ob>,
+    val: Object<'ob>,
+    predicate: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    ensure!(predicate.is_none(), "plist-put predicate support not implemented");
+    let list: List = plist.try_into()?;
+    let mut last = None;
+    for (idx, tail) in list.conses().enumerate() {
+        let tail = tail?;
+        if idx % 2 != 0 {
+            last = Some(tail);
+            continue;
+        }
+        if eq(tail.car(), prop) {
+            let ObjectType::Cons(value) = tail.cdr().untag() else {
+                bail!(TypeError::new(Type::List, plist))
+            };
+            value.set_car(val)?;
+            return Ok(plist);
+        }
+    }
+    let new = list![prop, val; cx];
+    match last {
+        Some(tail) => {
+            tail.set_cdr(new)?;
+            Ok(plist)
+        }
+        None => Ok(new),
+    }
+}
+
 #[defun]
 fn plist_member<'ob>(
     plist: Object<'ob>,
@@ -108,8 +148,13 @@ fn plist_member<'ob>(
 }
 
 #[defun]
-pub(crate) fn prin1_to_string(object: Object, _noescape: Option<Object>) -> String {
-    format!("{object}")
+pub(crate) fn prin1_to_string(
+    object: Object,
+    _noescape: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    crate::print::print_to_string(object, env, cx)
 }
 
 #[defun]
@@ -138,6 +183,7 @@ pub(crate) fn mapcar<'ob>(
             rooted_iter!(iter, cons, cx);
             root!(outputs, new(Vec), cx);
             while let Some(obj) = iter.next()? {
+                crate::keyboard::maybe_quit(env)?;
                 let output = call!(function, obj; env, cx)?;
                 outputs.push(output);
             }
@@ -149,6 +195,7 @@ pub(crate) fn mapcar<'ob>(
             root!(fun, cx);
             root!(outputs, new(Vec), cx);
             for i in 0..len {
+                crate::keyboard::maybe_quit(env)?;
                 let val = fun.bind(cx).index(i, cx).unwrap();
                 let output = call!(function, val; env, cx)?;
                 outputs.push(output);
@@ -172,6 +219,7 @@ pub(crate) fn mapc<'ob>(
         ListType::Cons(cons) => {
             rooted_iter!(elements, cons, cx);
             while let Some(elem) = elements.next()? {
+                crate::keyboard::maybe_quit(env)?;
                 call!(function, elem; env, cx)?;
             }
             Ok(sequence.bind(cx).into())
@@ -393,6 +441,72 @@ fn copy_alist_elem<'ob>(elem: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
     }
 }
 
+/// Find the element of ALIST whose car matches KEY. Keys are compared with
+/// TESTFN, or with `eq' if it is nil.
+fn find_alist_elem<'ob>(
+    key: &Rto<Object<'ob>>,
+    alist: &Rto<List<'ob>>,
+    testfn: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    match testfn {
+        Some(func) if func.bind(cx) == sym::EQUAL => assoc(key, alist, None, cx, env),
+        Some(func) if func.bind(cx) != sym::EQ => assoc(key, alist, testfn, cx, env),
+        _ => assq(key.bind(cx), alist.bind(cx)),
+    }
+}
+
+/// Find the first element of ALIST whose car is KEY and return its cdr. If
+/// KEY is not found, return DEFAULT. KEY is compared with TESTFN, which
+/// defaults to `eq'. REMOVE is accepted for compatibility with the place
+/// form, and is handled by `alist-set'.
+#[defun]
+fn alist_get<'ob>(
+    key: &Rto<Object<'ob>>,
+    alist: &Rto<List<'ob>>,
+    default: Option<&Rto<Object<'ob>>>,
+    _remove: OptionalFlag,
+    testfn: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let elem = rebind!(find_alist_elem(key, alist, testfn, env, cx)?);
+    match elem.untag() {
+        ObjectType::Cons(cons) => Ok(cons.cdr()),
+        _ => Ok(default.map_or(NIL, |x| x.bind(cx))),
+    }
+}
+
+/// Set the value associated with KEY in ALIST to VALUE and return the new
+/// alist. An existing entry is modified in place, otherwise a new entry is
+/// added to the front. If REMOVE is non-nil and VALUE is nil, the entry for
+/// KEY is removed instead. This is the update half of `alist-get', with KEY
+/// and TESTFN interpreted the same way.
+#[defun]
+fn alist_set<'ob>(
+    key: &Rto<Object<'ob>>,
+    alist: &Rto<List<'ob>>,
+    value: &Rto<Object<'ob>>,
+    remove: OptionalFlag,
+    testfn: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let elem = rebind!(find_alist_elem(key, alist, testfn, env, cx)?);
+    let (key, alist, value) = (key.bind(cx), alist.bind(cx), value.bind(cx));
+    let remove = remove.is_some() && value.is_nil();
+    match elem.untag() {
+        ObjectType::Cons(_) if remove => delq(elem, alist),
+        ObjectType::Cons(cons) => {
+            cons.set_cdr(value)?;
+            Ok(alist.into())
+        }
+        _ if remove => Ok(alist.into()),
+        _ => Ok(Cons::new(Cons::new(key, value, cx), alist, cx).into()),
+    }
+}
+
 fn delete_from_list<'ob>(elt: Object<'ob>, list: List<'ob>, eq_fn: EqFunc) -> Result<Object<'ob>> {
     let mut head = list.into();
     let mut prev: Option<&'ob Cons> = None;
@@ -460,7 +574,6 @@ fn sort<'ob>(
     let mut err = None;
     // TODO: Should we specialize some common predicates (<, >, string<, etc)?
     vec.sort_by(|a, b| {
-        use std::cmp::Ordering;
         if err.is_some() {
             // We previously hit an error and don't want to call predicate
             // anymore, but still need to wait for sort to finish.
@@ -564,54 +677,90 @@ pub(crate) fn vconcat<'ob>(sequences: &[Object], cx: &'ob Context) -> Result<Gc<
 
 #[defun]
 pub(crate) fn length(sequence: Object) -> Result<usize> {
-    let size = match sequence.untag() {
-        ObjectType::Cons(x) => x.elements().len()?,
-        ObjectType::Vec(x) => x.len(),
-        ObjectType::String(x) => x.len(),
-        ObjectType::ByteString(x) => x.len(),
-        ObjectType::ByteFn(x) => x.len(),
-        ObjectType::NIL => 0,
-        obj => bail!(TypeError::new(Type::Sequence, obj)),
-    };
-    Ok(size)
+    sequence.seq_len()
+}
+
+/// Compare the length of SEQUENCE to LENGTH. Lists are only walked as far as
+/// needed, so this is cheaper than calling `length'.
+fn length_cmp(sequence: Object, length: i64) -> Result<Ordering> {
+    match usize::try_from(length) {
+        Ok(length) => sequence.seq_len_cmp(length),
+        // every sequence is longer than a negative length
+        Err(_) => sequence.seq_len_cmp(0).map(|_| Ordering::Greater),
+    }
+}
+
+#[defun(name = "length=")]
+fn length_eq(sequence: Object, length: i64) -> Result<bool> {
+    Ok(length_cmp(sequence, length)? == Ordering::Equal)
+}
+
+#[defun(name = "length<")]
+fn length_less(sequence: Object, length: i64) -> Result<bool> {
+    Ok(length_cmp(sequence, length)? == Ordering::Less)
+}
+
+#[defun(name = "length>")]
+fn length_greater(sequence: Object, length: i64) -> Result<bool> {
+    Ok(length_cmp(sequence, length)? == Ordering::Greater)
 }
 
+/// Return the length of a list, without signaling an error. A dotted list is
+/// counted up to its non-nil tail, and a circular list returns a finite value
+/// that is at least the number of distinct elements. Anything that is not a
+/// list has length 0.
 #[defun]
 pub(crate) fn safe_length(sequence: Object) -> usize {
-    length(sequence).unwrap_or(0)
+    match sequence.untag() {
+        ObjectType::Cons(x) => x.elements().safe_len(),
+        _ => 0,
+    }
 }
 
+/// Return the length of OBJECT if it is a proper list, or nil if it is not a
+/// list or is dotted or circular.
 #[defun]
 pub(crate) fn proper_list_p(object: Object) -> Option<usize> {
-    // TODO: Handle dotted list and circular
     match object.untag() {
         ObjectType::Cons(x) => x.elements().len().ok(),
+        ObjectType::NIL => Some(0),
         _ => None,
     }
 }
 
 #[defun]
-pub(crate) fn nth(n: usize, list: List) -> Result<Object> {
-    Ok(list.elements().fallible().nth(n)?.unwrap_or_default())
+pub(crate) fn nth(n: i64, list: List) -> Result<Object> {
+    match nthcdr(n, list)?.untag() {
+        ListType::Cons(cons) => Ok(cons.car()),
+        ListType::Nil => Ok(NIL),
+    }
 }
 
+/// Take cdr N times on LIST. A negative N is treated as 0, and if LIST is too
+/// short the result is nil.
 #[defun]
-pub(crate) fn nthcdr(n: usize, list: List) -> Result<List> {
+pub(crate) fn nthcdr(n: i64, list: List) -> Result<List> {
+    let n = usize::try_from(n).unwrap_or(0);
     match list.conses().fallible().nth(n)? {
         Some(x) => Ok(x.into()),
         None => Ok(ListType::empty()),
     }
 }
 
+/// Return element of SEQUENCE at index N. Lists behave like `nth', so an
+/// index past the end returns nil. Arrays signal `args-out-of-range' like
+/// `aref'.
 #[defun]
-pub(crate) fn elt<'ob>(sequence: Object<'ob>, n: usize, cx: &'ob Context) -> Result<Object<'ob>> {
+pub(crate) fn elt<'ob>(sequence: Object<'ob>, n: i64, cx: &'ob Context) -> Result<Object<'ob>> {
     match sequence.untag() {
         ObjectType::Cons(x) => nth(n, x.into()),
         ObjectType::NIL => Ok(NIL),
-        ObjectType::Vec(x) => aref(x.into(), n, cx),
-        ObjectType::Record(x) => aref(x.into(), n, cx),
-        ObjectType::String(x) => aref(x.into(), n, cx),
-        ObjectType::ByteFn(x) => aref(x.into(), n, cx),
+        ObjectType::Vec(_)
+        | ObjectType::Record(_)
+        | ObjectType::String(_)
+        | ObjectType::ByteString(_)
+        | ObjectType::ByteFn(_)
+        | ObjectType::BoolVector(_) => aref(sequence, n, cx),
         other => Err(TypeError::new(Type::Sequence, other).into()),
     }
 }
@@ -622,18 +771,38 @@ pub(crate) fn string_equal<'ob>(s1: Object<'ob>, s2: Object<'ob>) -> Result<bool
         ObjectType::String(x) => x.as_bytes(),
         ObjectType::ByteString(x) => x.inner(),
         ObjectType::Symbol(x) => x.get().as_bytes(),
-        _ => bail!(TypeError::new(Type::String, s1)),
+        _ => bail!(TypeError::one_of(&[Type::String, Type::Symbol], s1)),
     };
     let s2 = match s2.untag() {
         ObjectType::String(x) => x.as_bytes(),
         ObjectType::ByteString(x) => x.inner(),
         ObjectType::Symbol(x) => (x.get()).as_bytes(),
-        _ => bail!(TypeError::new(Type::String, s2)),
+        _ => bail!(TypeError::one_of(&[Type::String, Type::Symbol], s2)),
     };
 
     Ok(s1 == s2)
 }
 
+/// Resolve the START and END arguments of `compare-strings` into a char
+/// range of a string with `len` chars. END is clamped to the length.
+fn compare_bounds(start: Object, end: Object, string: &str, len: usize) -> Result<(usize, usize)> {
+    let start = match start.untag() {
+        ObjectType::Int(x) => x,
+        ObjectType::NIL => 0,
+        _ => bail!(TypeError::new(Type::Int, start)),
+    };
+    let end = match end.untag() {
+        ObjectType::Int(x) => x.min(len as i64),
+        ObjectType::NIL => len as i64,
+        _ => bail!(TypeError::new(Type::Int, end)),
+    };
+    ensure!(0 <= start && start <= end, "Args out of range: {string}, {start}, {end}");
+    Ok((start as usize, end as usize))
+}
+
+/// Compare the contents of two strings. Return t if they are equal. If
+/// STRING1 sorts first the value is -N and otherwise it is N, where N - 1 is
+/// the number of leading characters that match.
 #[defun]
 pub(crate) fn compare_strings<'ob>(
     string1: &str,
@@ -644,58 +813,35 @@ pub(crate) fn compare_strings<'ob>(
     end2: Object<'ob>,
     ignore_case: OptionalFlag,
 ) -> Result<Object<'ob>> {
-    let start1 = match start1.untag() {
-        ObjectType::Int(x) => x,
-        ObjectType::NIL => 0,
-        _ => bail!(TypeError::new(Type::Int, start1)),
-    };
-    let end1 = match end1.untag() {
-        ObjectType::Int(x) => x,
-        ObjectType::NIL => string1.chars().count() as i64,
-        _ => bail!(TypeError::new(Type::Int, end1)),
-    };
-
-    if end1 < start1 {
-        bail!("Args out of range: {string1}, {start1}, {end1}");
-    }
-
-    let s1 = string1.chars().skip(start1 as usize).take((end1 - start1) as usize);
-
-    let start2 = match start2.untag() {
-        ObjectType::Int(x) => x,
-        ObjectType::NIL => 0,
-        _ => bail!(TypeError::new(Type::Int, start2)),
-    };
-    let end2 = match end2.untag() {
-        ObjectType::Int(x) => x,
-        ObjectType::NIL => string2.chars().count() as i64,
-        _ => bail!(TypeError::new(Type::Int, end2)),
-    };
-
-    if end2 < start2 {
-        bail!("Args out of range: {string2}, {start2}, {end2}");
-    }
+    let (start1, end1) = compare_bounds(start1, end1, string1, string1.chars().count())?;
+    let (start2, end2) = compare_bounds(start2, end2, string2, string2.chars().count())?;
     // TODO: check if byte strings are supported
-    let s2 = string2.chars().skip(start2 as usize).take((end2 - start2) as usize);
+    let s1 = string1.chars().skip(start1).take(end1 - start1);
+    let s2 = string2.chars().skip(start2).take(end2 - start2);
 
+    let fold = |c: char| {
+        //TODO: use case-table to determine the uppercase of a character
+        if ignore_case.is_some() {
+            c.to_uppercase().next().unwrap()
+        } else {
+            c
+        }
+    };
     let mut leading = 1;
     for (c1, c2) in s1.zip(s2) {
-        let (c1, c2) = if ignore_case.is_some() {
-            //TODO: use case-table to determine the uppercase of a character
-            (c1.to_uppercase().next().unwrap(), c2.to_uppercase().next().unwrap())
-        } else {
-            (c1, c2)
-        };
-
-        match c1.cmp(&c2) {
+        match fold(c1).cmp(&fold(c2)) {
             std::cmp::Ordering::Less => return Ok((-leading).into()),
             std::cmp::Ordering::Greater => return Ok(leading.into()),
             std::cmp::Ordering::Equal => {}
         }
         leading += 1;
     }
-
-    Ok(true.into())
+    // one range is a prefix of the other
+    match (end1 - start1).cmp(&(end2 - start2)) {
+        std::cmp::Ordering::Less => Ok((-leading).into()),
+        std::cmp::Ordering::Greater => Ok(leading.into()),
+        std::cmp::Ordering::Equal => Ok(true.into()),
+    }
 }
 
 #[defun]
@@ -757,7 +903,7 @@ impl<'ob> TryFrom<Object<'ob>> for StringOrSymbol<'ob> {
         match obj.untag() {
             ObjectType::String(x) => Ok(Self(x)),
             ObjectType::Symbol(x) => Ok(Self(x.get().name())),
-            _ => Err(TypeError::new(Type::String, obj)),
+            _ => Err(TypeError::one_of(&[Type::String, Type::Symbol], obj)),
         }
     }
 }
@@ -775,6 +921,77 @@ pub(crate) fn string_lessp<'ob>(
     Ok(string1.0.len() < string2.0.len())
 }
 
+/// Compare two strings using the collation rules of LOCALE. When LOCALE is
+/// nil the locale is taken from the environment (`LC_ALL`, `LC_COLLATE` or
+/// `LANG`). The "C" and "POSIX" locales compare by code point.
+fn collate(
+    string1: &str,
+    string2: &str,
+    locale: Option<&str>,
+    ignore_case: bool,
+) -> Result<std::cmp::Ordering> {
+    let (string1, string2) = if ignore_case {
+        (string1.to_lowercase(), string2.to_lowercase())
+    } else {
+        (string1.to_owned(), string2.to_owned())
+    };
+    let locale = locale.unwrap_or("");
+    if matches!(locale, "C" | "POSIX") || cfg!(not(unix)) {
+        return Ok(string1.cmp(&string2));
+    }
+    os_collate(&string1, &string2, locale)
+}
+
+#[cfg(unix)]
+fn os_collate(string1: &str, string2: &str, locale: &str) -> Result<std::cmp::Ordering> {
+    use std::ffi::CString;
+    let string1 = CString::new(string1)?;
+    let string2 = CString::new(string2)?;
+    let name = CString::new(locale)?;
+    // SAFETY: The locale is only installed for this thread and is restored
+    // before it is freed.
+    unsafe {
+        let new = libc::newlocale(libc::LC_COLLATE_MASK, name.as_ptr(), std::ptr::null_mut());
+        ensure!(!new.is_null(), "Invalid locale {locale}");
+        let old = libc::uselocale(new);
+        let result = libc::strcoll(string1.as_ptr(), string2.as_ptr());
+        libc::uselocale(old);
+        libc::freelocale(new);
+        Ok(result.cmp(&0))
+    }
+}
+
+#[cfg(not(unix))]
+fn os_collate(string1: &str, string2: &str, _locale: &str) -> Result<std::cmp::Ordering> {
+    Ok(string1.cmp(string2))
+}
+
+/// Return t if STRING1 sorts before STRING2 in the collation order of
+/// LOCALE. If IGNORE-CASE is non-nil, characters are compared in lower case.
+#[defun]
+fn string_collate_lessp(
+    string1: StringOrSymbol,
+    string2: StringOrSymbol,
+    locale: Option<&str>,
+    ignore_case: OptionalFlag,
+) -> Result<bool> {
+    let order = collate(string1.0, string2.0, locale, ignore_case.is_some())?;
+    Ok(order == std::cmp::Ordering::Less)
+}
+
+/// Return t if STRING1 and STRING2 are equal in the collation order of
+/// LOCALE. If IGNORE-CASE is non-nil, characters are compared in lower case.
+#[defun]
+fn string_collate_equalp(
+    string1: StringOrSymbol,
+    string2: StringOrSymbol,
+    locale: Option<&str>,
+    ignore_case: OptionalFlag,
+) -> Result<bool> {
+    let order = collate(string1.0, string2.0, locale, ignore_case.is_some())?;
+    Ok(order == std::cmp::Ordering::Equal)
+}
+
 #[defun]
 pub(crate) fn string_version_lessp<'ob>(
     string1: StringOrSymbol<'ob>,
@@ -852,6 +1069,50 @@ fn remhash(key: Object, table: &LispHashTable) -> Result<()> {
     Ok(())
 }
 
+/// How deep and how far into lists and vectors `sxhash-equal' looks, like in
+/// Emacs. Objects that only differ past that hash the same.
+const SXHASH_MAX_DEPTH: usize = 3;
+const SXHASH_MAX_LEN: usize = 7;
+
+/// Hash `obj` by its contents, so that objects that are `equal' hash the same.
+fn hash_equal(obj: Object, depth: usize, state: &mut impl Hasher) {
+    let depth = depth + 1;
+    match obj.untag() {
+        ObjectType::Int(x) => x.hash(state),
+        ObjectType::Cons(_) | ObjectType::Vec(_) | ObjectType::Record(_)
+            if depth > SXHASH_MAX_DEPTH => {}
+        ObjectType::Cons(cons) => {
+            for elem in cons.elements().take(SXHASH_MAX_LEN) {
+                let Ok(elem) = elem else { break };
+                hash_equal(elem, depth, state);
+            }
+        }
+        ObjectType::Vec(vec) => {
+            vec.len().hash(state);
+            for elem in vec.iter().take(SXHASH_MAX_LEN) {
+                hash_equal(elem.get(), depth, state);
+            }
+        }
+        ObjectType::Record(record) => {
+            record.len().hash(state);
+            for elem in record.iter().take(SXHASH_MAX_LEN) {
+                hash_equal(elem.get(), depth, state);
+            }
+        }
+        // strings, floats and bignums are hashed by contents
+        _ => obj.hash(state),
+    }
+}
+
+/// Return a hash of OBJ that is the same for any objects that are `equal'.
+#[defun]
+pub(crate) fn sxhash_equal(obj: Object) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    hash_equal(obj, 0, &mut hasher);
+    // keep the hash within the range of a fixnum
+    (hasher.finish() >> 9) as i64
+}
+
 #[defun]
 fn maphash(
     function: &Rto<Function>,
@@ -876,7 +1137,8 @@ fn maphash(
             let Some(idx) = loop_idx(table) else { break };
             table.get_index(idx).unwrap()
         };
-        let result = call!(function, key, val; env, cx);
+        let result =
+            crate::keyboard::maybe_quit(env).and_then(|()| call!(function, key, val; env, cx));
         if let Err(e) = result {
             table.untag(cx).set_iter_index(0);
             return Err(e.into());
@@ -886,6 +1148,99 @@ fn maphash(
     Ok(false)
 }
 
+/// Return a list of the keys in TABLE.
+#[defun]
+fn hash_table_keys<'ob>(table: &'ob LispHashTable, cx: &'ob Context) -> Object<'ob> {
+    let keys: Vec<_> = (0..table.len()).filter_map(|i| table.get_index(i)).map(|x| x.0).collect();
+    slice_into_list(&keys, None, cx)
+}
+
+/// Return a list of the values in TABLE.
+#[defun]
+fn hash_table_values<'ob>(table: &'ob LispHashTable, cx: &'ob Context) -> Object<'ob> {
+    let values: Vec<_> = (0..table.len()).filter_map(|i| table.get_index(i)).map(|x| x.1).collect();
+    slice_into_list(&values, None, cx)
+}
+
+/// Collect the key/value pairs of MAP, which is an alist, plist, hash table,
+/// or array. A list is treated as a plist if its first element is not a cons.
+/// Arrays map each index to its element.
+fn map_entries<'ob>(map: Object<'ob>, cx: &'ob Context) -> Result<Vec<(Object<'ob>, Object<'ob>)>> {
+    let mut entries = Vec::new();
+    match map.untag() {
+        ObjectType::NIL => {}
+        ObjectType::Cons(cons) if !matches!(cons.car().untag(), ObjectType::Cons(_)) => {
+            let mut iter = cons.elements();
+            while let Some(key) = iter.next() {
+                let Some(value) = iter.next() else { bail!("Odd length plist: {map}") };
+                entries.push((key?, value?));
+            }
+        }
+        ObjectType::Cons(cons) => {
+            for elem in cons.elements() {
+                let elem = elem?;
+                match elem.untag() {
+                    ObjectType::Cons(pair) => entries.push((pair.car(), pair.cdr())),
+                    _ => entries.push((elem, NIL)),
+                }
+            }
+        }
+        ObjectType::HashTable(table) => {
+            entries.extend((0..table.len()).filter_map(|i| table.get_index(i)));
+        }
+        _ => {
+            let len = length(map)?;
+            for idx in 0..len {
+                entries.push((cx.add(idx as i64), aref(map, idx as i64, cx)?));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Return the elements of MAP as an alist of (KEY . VALUE) pairs. MAP can be
+/// an alist, plist, hash table, or array.
+#[defun]
+fn map_pairs<'ob>(map: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let pairs: Vec<_> = map_entries(map, cx)?
+        .into_iter()
+        .map(|(key, value)| Cons::new(key, value, cx).into())
+        .collect();
+    Ok(slice_into_list(&pairs, None, cx))
+}
+
+defsym!(ALIST);
+defsym!(PLIST);
+
+/// Convert MAP into a map of MAP-TYPE, which is one of `list', `alist', `plist',
+/// or `hash-table'. The new hash table matches keys like the ones made by
+/// `make-hash-table': strings and numbers by their contents, and other keys,
+/// including lists, like `eq'.
+#[defun]
+fn map_into<'ob>(map: Object<'ob>, map_type: Symbol, cx: &'ob Context) -> Result<Object<'ob>> {
+    let entries = map_entries(map, cx)?;
+    match map_type {
+        sym::LIST | sym::ALIST => {
+            let pairs: Vec<_> =
+                entries.into_iter().map(|(k, v)| Cons::new(k, v, cx).into()).collect();
+            Ok(slice_into_list(&pairs, None, cx))
+        }
+        sym::PLIST => {
+            let flat: Vec<_> = entries.into_iter().flat_map(|(k, v)| [k, v]).collect();
+            Ok(slice_into_list(&flat, None, cx))
+        }
+        sym::HASH_TABLE => {
+            let table: Gc<&LispHashTable> =
+                cx.add_as(HashTable::with_hasher(std::hash::BuildHasherDefault::default()));
+            for (key, value) in entries {
+                table.untag().insert(key, value);
+            }
+            Ok(table.into())
+        }
+        _ => bail!("Unsupported map type: {map_type}"),
+    }
+}
+
 #[defun]
 fn copy_sequence<'ob>(arg: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
     match arg.untag() {
@@ -942,6 +1297,163 @@ fn secure_hash_algorithms<'ob>(cx: &'ob Context) -> Object<'ob> {
     list![sym::MD5, sym::SHA1, sym::SHA224, sym::SHA256, sym::SHA384, sym::SHA512; cx]
 }
 
+/// The state of a pseudo-random number generator. This uses splitmix64, which
+/// is small enough to store in a `cl-random-state` record, so that scripted
+/// tests can save and restore a sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RandomState(u64);
+
+impl Default for RandomState {
+    fn default() -> Self {
+        Self(crate::replay::random())
+    }
+}
+
+impl RandomState {
+    /// Seed the generator from the bytes of a string. The same string always
+    /// produces the same sequence.
+    fn from_seed(seed: &str) -> Self {
+        // FNV-1a is stable across runs and platforms
+        let hash = seed.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        Self(hash)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random integer in the range [0, limit).
+    fn below(&mut self, limit: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(limit)) >> 64) as u64
+    }
+
+    /// A random float in the range [0, 1).
+    fn unit_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// Return a pseudo-random integer. If LIMIT is a positive integer, the value
+/// is in the range [0, LIMIT). If LIMIT is t, the generator is first seeded
+/// from system entropy, and if it is a string it is seeded from the string's
+/// contents, which gives a reproducible sequence. Otherwise the value may be
+/// any fixnum.
+#[defun]
+fn random<'ob>(
+    limit: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let state = &mut env.random_state;
+    match limit.map(|x| x.untag()) {
+        Some(ObjectType::Int(limit)) if limit > 0 => {
+            return Ok(cx.add(state.below(limit as u64) as i64));
+        }
+        Some(ObjectType::Int(_)) => bail!("Args out of range: {}", limit.unwrap()),
+        Some(ObjectType::TRUE) => *state = RandomState::default(),
+        Some(ObjectType::String(seed)) => *state = RandomState::from_seed(seed),
+        _ => {}
+    }
+    // shift down to the fixnum range
+    Ok(cx.add((state.next_u64() as i64) >> 8))
+}
+
+const RANDOM_STATE_SIZE: usize = 3;
+
+/// Read the generator out of a `cl--random-state` record.
+fn random_state_from_record(state: Object) -> Result<RandomState> {
+    let ObjectType::Record(record) = state.untag() else {
+        bail!(TypeError::new(Type::Record, state))
+    };
+    let (Some(tag), Some(hi), Some(lo)) = (record.first(), record.get(1), record.get(2)) else {
+        bail!("Invalid random state: {state}")
+    };
+    let (ObjectType::Int(hi), ObjectType::Int(lo)) = (hi.get().untag(), lo.get().untag()) else {
+        bail!("Invalid random state: {state}")
+    };
+    ensure!(tag.get() == sym::CL__RANDOM_STATE, "Invalid random state: {state}");
+    Ok(RandomState(((hi as u64) << 32) | (lo as u64 & 0xffff_ffff)))
+}
+
+/// Create a `cl--random-state` record holding the generator. The 64 bit state
+/// is split into two fixnums.
+fn random_state_to_record<'ob>(state: RandomState, cx: &'ob Context) -> Object<'ob> {
+    let mut record = cx.vec_with_capacity(RANDOM_STATE_SIZE);
+    record.push(sym::CL__RANDOM_STATE.into());
+    record.push(cx.add((state.0 >> 32) as i64));
+    record.push(cx.add((state.0 & 0xffff_ffff) as i64));
+    cx.add(RecordBuilder(record))
+}
+
+/// Return the state in `cl--random-state', creating it if it does not exist.
+fn default_random_state<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    match env.vars.get(sym::CL__RANDOM_STATE).map(|x| x.bind(cx)) {
+        Some(state) if !state.is_nil() => state,
+        _ => {
+            let state = random_state_to_record(RandomState::default(), cx);
+            env.vars.insert(sym::CL__RANDOM_STATE, state);
+            state
+        }
+    }
+}
+
+/// Return a copy of the random-state STATE, or of the internal state if STATE
+/// is nil. If STATE is t, return a new state seeded from system entropy.
+#[defun]
+fn cl_make_random_state<'ob>(
+    state: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let state = match state {
+        Some(state) if state == TRUE => RandomState::default(),
+        Some(state) => random_state_from_record(state)?,
+        None => random_state_from_record(default_random_state(env, cx))?,
+    };
+    Ok(random_state_to_record(state, cx))
+}
+
+/// Return t if OBJECT is a random-state object.
+#[defun]
+fn cl_random_state_p(object: Object) -> bool {
+    random_state_from_record(object).is_ok()
+}
+
+/// Return a pseudo-random nonnegative number less than LIM, which is an
+/// integer or a float. The sequence is taken from the random-state STATE,
+/// which defaults to `cl--random-state', and STATE is updated.
+#[defun]
+fn cl_random<'ob>(
+    lim: Number,
+    state: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let state_obj = match state {
+        Some(state) => state,
+        None => default_random_state(env, cx),
+    };
+    let mut state = random_state_from_record(state_obj)?;
+    let value = match lim.untag() {
+        NumberType::Int(lim) if lim > 0 => cx.add(state.below(lim as u64) as i64),
+        NumberType::Float(lim) if **lim > 0.0 => cx.add(state.unit_float() * **lim),
+        _ => bail!("Args out of range: {lim}"),
+    };
+    let ObjectType::Record(record) = state_obj.untag() else { unreachable!() };
+    let slots = record.try_mut()?;
+    slots[1].set(cx.add((state.0 >> 32) as i64));
+    slots[2].set(cx.add((state.0 & 0xffff_ffff) as i64));
+    Ok(value)
+}
+
+defvar!(CL__RANDOM_STATE);
+
 #[defun]
 fn enable_debug() -> bool {
     crate::debug::enable_debug();
@@ -974,6 +1486,29 @@ mod test {
         assert_lisp("(delq t '(t t t))", "nil");
     }
 
+    #[test]
+    fn test_elt() {
+        assert_lisp("(elt '(1 2 3) 1)", "2");
+        assert_lisp("(elt '(1 2 3) 5)", "nil");
+        assert_lisp("(elt '(1 2 3) -1)", "1");
+        assert_lisp("(elt [1 2 3] 2)", "3");
+        assert_lisp("(elt \"abc\" 0)", "97");
+        assert_lisp(
+            "(condition-case err (elt [1 2] 2) (error err))",
+            "(args-out-of-range [1 2] 2)",
+        );
+        assert_lisp(
+            "(condition-case err (aref \"ab\" -1) (error err))",
+            "(args-out-of-range \"ab\" -1)",
+        );
+        assert_lisp(
+            "(condition-case err (aset (vector 1) 1 0) (error err))",
+            "(args-out-of-range [1] 1)",
+        );
+        assert_lisp("(nth -1 '(1 2))", "1");
+        assert_lisp("(nth 2 '(1 2))", "nil");
+    }
+
     #[test]
     fn test_nthcdr() {
         assert_lisp("(nthcdr 1 '(1 2 3))", "(2 3)");
@@ -1022,6 +1557,19 @@ mod test {
         assert_lisp("(compare-strings \"hello\" 0 6 \"hello\" 0 6)", "t");
         assert_lisp("(compare-strings \"hello\" 0 6 \"world\" 0 6)", "-1");
         assert_lisp("(compare-strings \"hello\" 0 6 \"HELLO\" 0 6 t)", "t");
+        assert_lisp("(compare-strings \"abc\" nil nil \"abcd\" nil nil)", "-4");
+        assert_lisp("(compare-strings \"abcd\" nil nil \"abc\" nil nil)", "4");
+        assert_lisp("(compare-strings \"xabc\" 1 nil \"abc\" nil nil)", "t");
+        assert_lisp("(compare-strings \"abc\" 1 nil \"ABD\" 1 nil t)", "-2");
+    }
+
+    #[test]
+    fn test_string_collate() {
+        assert_lisp("(string-collate-lessp \"B\" \"a\" \"POSIX\")", "t");
+        assert_lisp("(string-collate-lessp \"B\" \"a\" \"POSIX\" t)", "nil");
+        assert_lisp("(string-collate-lessp 'abc \"abd\" \"C\")", "t");
+        assert_lisp("(string-collate-equalp \"abc\" \"ABC\" \"POSIX\")", "nil");
+        assert_lisp("(string-collate-equalp \"abc\" \"ABC\" \"POSIX\" t)", "t");
     }
 
     #[test]
@@ -1161,6 +1709,17 @@ mod test {
         assert_lisp("(let ((h (make-hash-table))) (puthash 1 6 h) (puthash 2 8 h) (puthash 3 10 h) (maphash 'eq h))", "nil");
     }
 
+    #[test]
+    fn test_hash_table_keys() {
+        assert_lisp(
+            "(let ((h (make-hash-table :test 'equal)))
+               (puthash \"key\" 1 h)
+               (puthash 0.5 2 h)
+               (list (gethash (concat \"k\" \"ey\") h) (gethash (/ 1.0 2) h) (gethash \"other\" h)))",
+            "(1 2 nil)",
+        );
+    }
+
     #[test]
     fn test_sort() {
         assert_lisp("(sort nil '<)", "nil");
@@ -1177,6 +1736,82 @@ mod test {
         assert_lisp("(condition-case nil (sort '(3 2 1) 'length) (error 7))", "7");
     }
 
+    #[test]
+    fn test_random() {
+        assert_lisp("(progn (random \"seed\") (list (random 100) (random 100)))", &{
+            let mut state = super::RandomState::from_seed("seed");
+            format!("({} {})", state.below(100), state.below(100))
+        });
+        assert_lisp("(let ((x (random 3))) (and (>= x 0) (< x 3)))", "t");
+        assert_lisp("(condition-case nil (random -1) (error 7))", "7");
+        assert_lisp(
+            "(let ((s (cl-make-random-state t))) (= (cl-random 1000 (cl-make-random-state s)) (cl-random 1000 s)))",
+            "t",
+        );
+        assert_lisp("(cl-random-state-p (cl-make-random-state))", "t");
+        assert_lisp("(cl-random-state-p [1 2])", "nil");
+        assert_lisp("(let ((x (cl-random 1.5))) (and (>= x 0.0) (< x 1.5)))", "t");
+    }
+
+    #[test]
+    fn test_plist_put() {
+        assert_lisp("(plist-put nil 'a 1)", "(a 1)");
+        assert_lisp("(plist-put (list 'a 1 'b 2) 'b 3)", "(a 1 b 3)");
+        assert_lisp("(plist-put (list 'a 1) 'b 2)", "(a 1 b 2)");
+    }
+
+    #[test]
+    fn test_length() {
+        assert_lisp("(length '(1 2 3))", "3");
+        assert_lisp("(length [1 2])", "2");
+        assert_lisp("(length \"héllo\")", "5");
+        assert_lisp("(length (make-bool-vector 9 t))", "9");
+        assert_lisp("(let ((h (make-hash-table))) (puthash 1 2 h) (length h))", "1");
+        assert_lisp("(condition-case nil (length 1) (error 7))", "7");
+        assert_lisp("(list (length= '(1 2) 2) (length< '(1 2) 3) (length> '(1 2) 2))", "(t t nil)");
+        assert_lisp("(list (length= \"ab\" 2) (length< [1] 1) (length> nil -1))", "(t nil t)");
+        // only walk as much of the list as needed
+        assert_lisp("(let ((x (list 1 2))) (setcdr (cdr x) x) (length> x 10))", "t");
+    }
+
+    #[test]
+    fn test_list_length() {
+        assert_lisp("(safe-length '(1 2 3))", "3");
+        assert_lisp("(safe-length '(1 2 . 3))", "2");
+        assert_lisp("(safe-length [1 2])", "0");
+        assert_lisp("(let ((x (list 1 2))) (setcdr (cdr x) x) (>= (safe-length x) 2))", "t");
+        assert_lisp("(proper-list-p nil)", "0");
+        assert_lisp("(proper-list-p '(1 2))", "2");
+        assert_lisp("(proper-list-p '(1 . 2))", "nil");
+        assert_lisp("(proper-list-p [1])", "nil");
+        assert_lisp("(let ((x (list 1 2))) (setcdr (cdr x) x) (proper-list-p x))", "nil");
+    }
+
+    #[test]
+    fn test_alist_get() {
+        assert_lisp("(alist-get 'b '((a . 1) (b . 2)))", "2");
+        assert_lisp("(alist-get 'c '((a . 1) (b . 2)) 3)", "3");
+        assert_lisp("(alist-get \"b\" '((\"a\" . 1) (\"b\" . 2)))", "nil");
+        assert_lisp("(alist-get \"b\" '((\"a\" . 1) (\"b\" . 2)) nil nil 'equal)", "2");
+        assert_lisp("(alist-set 'b (list (cons 'a 1) (cons 'b 2)) 3)", "((a . 1) (b . 3))");
+        assert_lisp("(alist-set 'c (list (cons 'a 1)) 3)", "((c . 3) (a . 1))");
+        assert_lisp("(alist-set 'a (list (cons 'a 1) (cons 'b 2)) nil t)", "((b . 2))");
+        assert_lisp("(alist-set 'a (list (cons 'a 1)) nil)", "((a))");
+    }
+
+    #[test]
+    fn test_map_conversions() {
+        assert_lisp("(map-pairs '(a 1 b 2))", "((a . 1) (b . 2))");
+        assert_lisp("(map-pairs '((a . 1) (b . 2)))", "((a . 1) (b . 2))");
+        assert_lisp("(map-pairs [x y])", "((0 . x) (1 . y))");
+        assert_lisp("(map-into '((a . 1) (b . 2)) 'plist)", "(a 1 b 2)");
+        assert_lisp(
+            "(let ((h (map-into '(a 1 b 2) 'hash-table))) (list (hash-table-keys h) (hash-table-values h)))",
+            "((a b) (1 2))",
+        );
+        assert_lisp("(map-into (map-into '(a 1) 'hash-table) 'alist)", "((a . 1))");
+    }
+
     #[test]
     fn test_copy_alist() {
         assert_lisp("(copy-alist '((1 . 2) (3 . 4) (5 . 6)))", "((1 . 2) (3 . 4) (5 . 6))");
@@ -0,0 +1,295 @@
+//! Futures for composing asynchronous elisp.
+//!
+//! A future is settled once with either a value or an error. The function
+//! given to `make-future' runs on its own thread like a
+//! [generator](crate::generator), and `future-then' chains another function
+//! onto the result without nesting callbacks. Futures are
+//! [handles](crate::handle).
+//!
+//! With the `tokio` feature, timers run on a tokio runtime and Rust code can
+//! settle a future from a task with [`spawn_tokio`].
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, ObjectType, RecordBuilder, NIL},
+};
+use crate::eval::EvalError;
+use crate::generator::Detached;
+use crate::reader;
+use anyhow::{anyhow, bail, Result};
+use rune_core::{
+    hashmap::HashMap,
+    macros::{call, list},
+};
+use rune_macros::defun;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Condvar, LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The printed value or error message of a future.
+type Settled = Result<String, String>;
+
+/// All live futures. A future is `None` until it is settled.
+static FUTURES: LazyLock<Mutex<HashMap<i64, Option<Settled>>>> = LazyLock::new(Mutex::default);
+/// Notified every time a future is settled.
+static SETTLED: Condvar = Condvar::new();
+static NEXT_ID: AtomicI64 = AtomicI64::new(0);
+
+fn new_future() -> i64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    FUTURES.lock().unwrap().insert(id, None);
+    id
+}
+
+/// Settle the future with `id`. Returns false if it was already settled.
+fn settle(id: i64, value: Settled) -> bool {
+    let mut futures = FUTURES.lock().unwrap();
+    let Some(slot @ None) = futures.get_mut(&id) else { return false };
+    *slot = Some(value);
+    SETTLED.notify_all();
+    true
+}
+
+fn is_pending(futures: &mut HashMap<i64, Option<Settled>>, id: i64) -> bool {
+    matches!(futures.get(&id), Some(None))
+}
+
+/// Block until the future with `id` is settled.
+fn wait(id: i64) -> Settled {
+    let futures = FUTURES.lock().unwrap();
+    let futures = SETTLED.wait_while(futures, |f| is_pending(f, id)).unwrap();
+    futures[&id].clone().unwrap()
+}
+
+/// Remove the future with `id` once nothing refers to it.
+pub(crate) fn release(id: i64) {
+    FUTURES.lock().unwrap().remove(&id);
+}
+
+/// Block until the future with `id` is settled or `timeout` has passed.
+fn wait_timeout(id: i64, timeout: Duration) -> Option<Settled> {
+    let futures = FUTURES.lock().unwrap();
+    let (futures, _) = SETTLED.wait_timeout_while(futures, timeout, |f| is_pending(f, id)).unwrap();
+    futures[&id].clone()
+}
+
+fn future_object(id: i64, cx: &Context) -> Object {
+    let mut record = cx.vec_with_capacity(2);
+    record.push(sym::RUNE_FUTURE.into());
+    record.push(cx.add(id));
+    cx.add(RecordBuilder(record))
+}
+
+fn future_id(future: Object) -> Result<i64> {
+    if let ObjectType::Record(record) = future.untag() {
+        if let [tag, id] = &record[..] {
+            if let (ObjectType::Symbol(sym::RUNE_FUTURE), ObjectType::Int(id)) =
+                (tag.get().untag(), id.get().untag())
+            {
+                if FUTURES.lock().unwrap().contains_key(&id) {
+                    return Ok(id);
+                }
+            }
+        }
+    }
+    bail!("Not a future: {future}")
+}
+
+/// Run `function` on a new thread and settle the future with `id` with its
+/// result. If `parent` is given, the function is called with its value once
+/// it is settled.
+fn spawn_future(id: i64, function: Object, parent: Option<i64>, env: &Rt<Env>) {
+    let function = Detached::new(function, env);
+    // The parent has to outlive its record, which may be garbage by now
+    if let Some(parent) = parent {
+        crate::handle::retain_id(sym::RUNE_FUTURE, parent);
+    }
+    thread::spawn(move || {
+        function.run(|function, env, cx| {
+            let parent = parent.map(|parent| {
+                let settled = wait(parent);
+                crate::handle::release_id(sym::RUNE_FUTURE, parent);
+                settled
+            });
+            let arg = match parent {
+                Some(Err(e)) => {
+                    settle(id, Err(e));
+                    return;
+                }
+                Some(Ok(value)) => Some(value),
+                None => None,
+            };
+            let result = call_function(function, arg.as_deref(), env, cx);
+            settle(id, result.map_err(|e| e.to_string()));
+        });
+    });
+}
+
+fn call_function(
+    function: &Rto<Object>,
+    arg: Option<&str>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<String> {
+    let function: &Rto<Function> = function.try_as()?;
+    let result = match arg {
+        Some(arg) => {
+            let arg = reader::read(arg, cx)?.0;
+            call!(function, arg; env, cx)?
+        }
+        None => call!(function; env, cx)?,
+    };
+    Ok(result.to_string())
+}
+
+/// Return a new future. If FUNCTION is given, it is called with no arguments
+/// on a new thread and the future is settled with its result. Otherwise the
+/// future is settled with `future-resolve' or `future-reject'.
+#[defun]
+fn make_future<'ob>(function: Option<Object>, env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    let id = new_future();
+    if let Some(function) = function {
+        spawn_future(id, function, None, env);
+    }
+    future_object(id, cx)
+}
+
+#[defun]
+fn futurep(object: Object) -> bool {
+    future_id(object).is_ok()
+}
+
+/// Return a future that is settled with the result of calling FUNCTION on
+/// the value of FUTURE. If FUTURE fails, the new future fails with the same
+/// error and FUNCTION is not called.
+#[defun]
+fn future_then<'ob>(
+    future: Object,
+    function: Object,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let parent = future_id(future)?;
+    let id = new_future();
+    spawn_future(id, function, Some(parent), env);
+    Ok(future_object(id, cx))
+}
+
+/// Settle FUTURE with VALUE. Return nil if it was already settled.
+#[defun]
+fn future_resolve(future: Object, value: Object) -> Result<bool> {
+    Ok(settle(future_id(future)?, Ok(value.to_string())))
+}
+
+/// Settle FUTURE with the error MESSAGE. Return nil if it was already
+/// settled.
+#[defun]
+fn future_reject(future: Object, message: &str) -> Result<bool> {
+    Ok(settle(future_id(future)?, Err(message.to_owned())))
+}
+
+/// Return non-nil if FUTURE has been settled.
+#[defun]
+fn future_done_p(future: Object) -> Result<bool> {
+    let id = future_id(future)?;
+    Ok(!is_pending(&mut FUTURES.lock().unwrap(), id))
+}
+
+/// Wait for FUTURE to be settled and return its value. If it failed, an error
+/// is signaled with its message. If TIMEOUT seconds pass first,
+/// `future-timeout' is signaled.
+#[defun]
+fn future_wait<'ob>(
+    future: Object,
+    timeout: Option<f64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let id = future_id(future)?;
+    let settled = match timeout {
+        Some(secs) => match wait_timeout(id, Duration::from_secs_f64(secs.max(0.0))) {
+            Some(settled) => settled,
+            None => {
+                let data = list![future; cx];
+                return Err(EvalError::signal(sym::FUTURE_TIMEOUT.into(), data, env).into());
+            }
+        },
+        None => wait(id),
+    };
+    match settled {
+        Ok(value) => Ok(reader::read(&value, cx)?.0),
+        Err(message) => Err(anyhow!(message)),
+    }
+}
+
+/// Return a future that is settled with VALUE after SECONDS.
+#[defun]
+fn future_timer<'ob>(seconds: f64, value: Option<Object>, cx: &'ob Context) -> Object<'ob> {
+    let delay = Duration::from_secs_f64(seconds.max(0.0));
+    let value = value.unwrap_or(NIL).to_string();
+    #[cfg(feature = "tokio")]
+    let id = spawn_tokio(RUNTIME.handle(), async move {
+        tokio::time::sleep(delay).await;
+        Ok(value)
+    });
+    #[cfg(not(feature = "tokio"))]
+    let id = {
+        let id = new_future();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            settle(id, Ok(value));
+        });
+        id
+    };
+    future_object(id, cx)
+}
+
+#[cfg(feature = "tokio")]
+static RUNTIME: LazyLock<tokio::runtime::Runtime> =
+    LazyLock::new(|| tokio::runtime::Builder::new_multi_thread().enable_time().build().unwrap());
+
+/// Return the id of a new future that is settled by `task` on the runtime
+/// `handle`. The output must be the printed representation of a lisp object.
+#[cfg(feature = "tokio")]
+pub(crate) fn spawn_tokio<F>(handle: &tokio::runtime::Handle, task: F) -> i64
+where
+    F: std::future::Future<Output = Result<String>> + Send + 'static,
+{
+    let id = new_future();
+    handle.spawn(async move {
+        settle(id, task.await.map_err(|e| e.to_string()));
+    });
+    id
+}
+
+defsym!(RUNE_FUTURE);
+defsym!(FUTURE_TIMEOUT);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_future() {
+        assert_lisp(
+            "(future-wait (future-then (make-future #'(lambda () 20)) #'(lambda (x) (+ x 1))))",
+            "21",
+        );
+        assert_lisp(
+            "(let ((f (make-future)))
+               (list (future-done-p f)
+                     (condition-case e (future-wait f 0) (future-timeout (car e)))
+                     (future-resolve f '(a b))
+                     (future-resolve f 'c) (future-done-p f) (future-wait f)))",
+            "(nil future-timeout t nil t (a b))",
+        );
+        assert_lisp(
+            "(let ((f (make-future)))
+               (future-reject f \"failed\")
+               (condition-case e (future-wait (future-then f #'car)) (error (cdr e))))",
+            "\"failed\"",
+        );
+        assert_lisp("(future-wait (future-timer 0.01 'ready))", "ready");
+    }
+}
@@ -0,0 +1,263 @@
+//! Generators created with `iter-lambda' and `iter-defun'.
+//!
+//! Each generator runs its body on its own thread with a separate heap, the
+//! same way as [`go`](crate::threads). The body only runs while the caller is
+//! blocked in `iter-next', so they never execute at the same time. Values are
+//! passed between the two heaps by printing and reading them, so only readable
+//! objects can be yielded.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Block, Context, RootSet, Rt, Rto},
+    object::{CloneIn, Function, Object, ObjectType, RawObj, RecordBuilder, NIL},
+};
+use crate::eval::EvalError;
+use crate::permissions::Permissions;
+use crate::reader;
+use anyhow::{anyhow, bail, Result};
+use rune_core::{
+    hashmap::HashMap,
+    macros::{call, root},
+};
+use rune_macros::defun;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{LazyLock, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A message from `iter-next' or `iter-close' to the generator.
+enum Resume {
+    Next(String),
+    Close,
+}
+
+/// A message from the generator to its caller.
+enum Reply {
+    Yield(String),
+    Done(String),
+    Error(String),
+}
+
+struct Generator {
+    resume: Sender<Resume>,
+    reply: Receiver<Reply>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// The live generators. The records that refer to them are
+/// [handles](crate::handle).
+static GENERATORS: LazyLock<Mutex<HashMap<i64, Generator>>> = LazyLock::new(Mutex::default);
+static NEXT_ID: AtomicI64 = AtomicI64::new(0);
+
+/// The channels of the generator running on this thread.
+struct Channel {
+    resume: Receiver<Resume>,
+    reply: Sender<Reply>,
+}
+
+thread_local! {
+    static CHANNEL: RefCell<Option<Channel>> = const { RefCell::new(None) };
+    /// Set when `iter-yield' is unwinding because the generator was closed.
+    static CLOSED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Create a generator that will run the function BODY with no arguments.
+/// This is used in the expansion of `iter-lambda'.
+#[defun]
+fn rune__make_generator<'ob>(body: Object, env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    let body = Detached::new(body, env);
+    let (resume_tx, resume_rx) = channel();
+    let (reply_tx, reply_rx) = channel();
+    let thread = thread::spawn(move || run_generator(body, resume_rx, reply_tx));
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let generator = Generator { resume: resume_tx, reply: reply_rx, thread: Some(thread) };
+    GENERATORS.lock().unwrap().insert(id, generator);
+    let mut record = cx.vec_with_capacity(2);
+    record.push(sym::RUNE_GENERATOR.into());
+    record.push(cx.add(id));
+    cx.add(RecordBuilder(record))
+}
+
+/// A function copied to a heap of its own, to be called on another thread.
+pub(crate) struct Detached {
+    block: Block<false>,
+    raw: RawObj,
+    permissions: Permissions,
+}
+
+impl Detached {
+    /// Copy `function`, which will run with the permissions of `env`.
+    pub(crate) fn new(function: Object, env: &Rt<Env>) -> Self {
+        let block = Block::new_local_unchecked();
+        let raw = function.clone_in(&block).into_raw();
+        Self { block, raw, permissions: env.permissions.clone() }
+    }
+
+    /// Call `f` with the function on the thread that runs it, in a new
+    /// environment that has its variables initialized. The copy can only be
+    /// freed by running it.
+    pub(crate) fn run<T>(self, f: impl FnOnce(&Rto<Object>, &mut Rt<Env>, &mut Context) -> T) -> T {
+        let roots = &RootSet::default();
+        let cx = &mut Context::from_block(self.block, roots);
+        root!(env, new(Env), cx);
+        crate::core::env::init_variables(cx, env);
+        env.permissions = self.permissions;
+        let function = unsafe { Object::from_raw(self.raw) };
+        root!(function, cx);
+        f(function, env, cx)
+    }
+}
+
+fn run_generator(body: Detached, resume: Receiver<Resume>, reply: Sender<Reply>) {
+    body.run(|body, env, cx| {
+        // The body does not start until the first `iter-next'
+        if !matches!(resume.recv(), Ok(Resume::Next(_))) {
+            return;
+        }
+        CHANNEL.set(Some(Channel { resume, reply: reply.clone() }));
+        let message = match call_body(body, env, cx) {
+            Ok(value) => Reply::Done(value),
+            Err(_) if CLOSED.get() => Reply::Done(NIL.to_string()),
+            Err(e) => Reply::Error(e.to_string()),
+        };
+        CHANNEL.set(None);
+        _ = reply.send(message);
+    });
+}
+
+/// Call the generator body and return its printed value.
+fn call_body(body: &Rto<Object>, env: &mut Rt<Env>, cx: &mut Context) -> Result<String> {
+    let body: &Rto<Function> = body.try_as()?;
+    Ok(call!(body; env, cx)?.to_string())
+}
+
+/// Yield VALUE from the generator. The value passed to the `iter-next' that
+/// resumes the generator is returned.
+#[defun]
+fn iter_yield<'ob>(value: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let resumed = CHANNEL.with_borrow(|channel| {
+        let Some(channel) = channel else { bail!("`iter-yield' used outside a generator") };
+        channel.reply.send(Reply::Yield(value.to_string()))?;
+        Ok(channel.resume.recv())
+    })?;
+    match resumed {
+        Ok(Resume::Next(value)) => Ok(reader::read(&value, cx)?.0),
+        Ok(Resume::Close) | Err(_) => {
+            CLOSED.set(true);
+            Err(anyhow!("Generator closed"))
+        }
+    }
+}
+
+fn generator_id(iterator: Object) -> Result<i64> {
+    if let ObjectType::Record(record) = iterator.untag() {
+        if let [tag, id] = &record[..] {
+            if let (ObjectType::Symbol(sym::RUNE_GENERATOR), ObjectType::Int(id)) =
+                (tag.get().untag(), id.get().untag())
+            {
+                return Ok(id);
+            }
+        }
+    }
+    bail!("Not an iterator: {iterator}")
+}
+
+/// Resume ITERATOR and return the next value it yields. YIELD-RESULT is
+/// returned from the `iter-yield' that the iterator is suspended in. When the
+/// iterator finishes, `iter-end-of-sequence' is signaled with its return value.
+#[defun]
+fn iter_next<'ob>(
+    iterator: Object,
+    yield_result: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let id = generator_id(iterator)?;
+    // The generator is taken out of the table while it runs, so that its body
+    // can use other generators.
+    let Some(generator) = GENERATORS.lock().unwrap().remove(&id) else {
+        return Err(EvalError::signal(sym::ITER_END_OF_SEQUENCE.into(), NIL, env).into());
+    };
+    let value = yield_result.unwrap_or(NIL).to_string();
+    let reply = match generator.resume.send(Resume::Next(value)) {
+        Ok(()) => generator.reply.recv().ok(),
+        Err(_) => None,
+    };
+    match reply {
+        Some(Reply::Yield(value)) => {
+            GENERATORS.lock().unwrap().insert(id, generator);
+            Ok(reader::read(&value, cx)?.0)
+        }
+        Some(Reply::Done(value)) => {
+            join(generator);
+            let value = reader::read(&value, cx)?.0;
+            Err(EvalError::signal(sym::ITER_END_OF_SEQUENCE.into(), value, env).into())
+        }
+        Some(Reply::Error(message)) => {
+            join(generator);
+            bail!(message)
+        }
+        None => {
+            join(generator);
+            bail!("Iterator exited unexpectedly")
+        }
+    }
+}
+
+/// Wait for the thread of a generator that is finished.
+fn join(generator: Generator) {
+    if let Some(thread) = generator.thread {
+        _ = thread.join();
+    }
+}
+
+/// Remove the generator with `id` once nothing refers to it. Dropping its
+/// channel stops the thread the next time it yields, or before it starts.
+pub(crate) fn release(id: i64) {
+    GENERATORS.lock().unwrap().remove(&id);
+}
+
+/// Stop ITERATOR. If it is suspended in `iter-yield', the body is unwound so
+/// that any cleanup forms run.
+#[defun]
+fn iter_close(iterator: Object) -> Result<()> {
+    let id = generator_id(iterator)?;
+    let Some(generator) = GENERATORS.lock().unwrap().remove(&id) else { return Ok(()) };
+    let Generator { resume, reply, thread } = generator;
+    _ = resume.send(Resume::Close);
+    // Any further `iter-yield' will also see the generator as closed
+    drop(resume);
+    _ = reply.recv();
+    if let Some(thread) = thread {
+        _ = thread.join();
+    }
+    Ok(())
+}
+
+defsym!(RUNE_GENERATOR);
+defsym!(ITER_END_OF_SEQUENCE);
+defsym!(ITER_LAMBDA);
+defsym!(ITER_DEFUN);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_generator() {
+        assert_lisp(
+            "(let ((it (funcall (iter-lambda (n) (iter-yield n) (iter-yield (1+ n)) 'done) 1)))
+               (list (iter-next it) (iter-next it)
+                     (condition-case e (iter-next it) (error (cdr e)))))",
+            "(1 2 done)",
+        );
+        assert_lisp(
+            "(progn (iter-defun gen-test-counter ()
+                      (let ((x 0)) (while t (setq x (+ x (iter-yield x))))))
+                    (let ((it (gen-test-counter)))
+                      (iter-next it)
+                      (list (iter-next it 5) (iter-next it 2) (iter-close it))))",
+            "(5 7 nil)",
+        );
+    }
+}
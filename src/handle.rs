@@ -0,0 +1,114 @@
+//! Records that stand for values kept in Rust.
+//!
+//! Values that can't live on a lisp heap, like generators, futures, streams
+//! and mutexes, are kept in tables shared by all threads. Lisp only sees a
+//! record of the form (TAG ID), where ID is the key of the value in the table
+//! of its module. Every record with one of the tags in [`HANDLES`] is counted
+//! when it is allocated, whether its module made it, it was copied into the
+//! heap of another thread, or it was read back from its printed form. When
+//! the last of them is garbage collected, or the heap holding it is dropped,
+//! the module's release function is called to remove the value from its
+//! table. Ids are never reused, so a record that outlives its value only
+//! fails to find it.
+use crate::core::{
+    env::sym,
+    object::{Object, ObjectType, Symbol},
+};
+use rune_core::hashmap::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// The tags of handle records, and the function that removes the value with
+/// an id from the table of each.
+const HANDLES: &[(Symbol<'static>, fn(i64))] = &[
+    (sym::RUNE_FUTURE, crate::future::release),
+    (sym::RUNE_GENERATOR, crate::generator::release),
+    (sym::RUNE_STREAM, crate::stream::release),
+    (sym::RUNE_MUTEX, crate::threads::release_mutex),
+    (sym::RUNE_CONDITION_VARIABLE, crate::threads::release_condition),
+    (sym::XREF_INDEX, crate::xref::release),
+];
+
+/// The number of references to each value, keyed by tag and id.
+static REFS: LazyLock<Mutex<HashMap<(Symbol<'static>, i64), usize>>> =
+    LazyLock::new(Mutex::default);
+
+fn handle(tag: Object, id: Object) -> Option<(Symbol<'static>, i64)> {
+    let (ObjectType::Symbol(tag), ObjectType::Int(id)) = (tag.untag(), id.untag()) else {
+        return None;
+    };
+    HANDLES.iter().find(|(x, _)| *x == tag).map(|(x, _)| (*x, id))
+}
+
+/// Count a record with the fields TAG and ID as it is allocated. Returns
+/// true if it is a handle, in which case [`release`] has to be called once
+/// it is gone.
+pub(crate) fn retain(tag: Object, id: Object) -> bool {
+    let Some((tag, id)) = handle(tag, id) else { return false };
+    retain_id(tag, id);
+    true
+}
+
+/// Stop counting a handle record with the fields TAG and ID.
+pub(crate) fn release(tag: Object, id: Object) {
+    if let Some((tag, id)) = handle(tag, id) {
+        release_id(tag, id);
+    }
+}
+
+/// Count a reference to the value with `id` that is held by Rust code
+/// instead of a record. It has to be dropped with [`release_id`].
+pub(crate) fn retain_id(tag: Symbol<'static>, id: i64) {
+    *REFS.lock().unwrap().entry((tag, id)).or_default() += 1;
+}
+
+/// Drop a reference to the value with `id`, and remove the value from its
+/// table if it was the last one.
+pub(crate) fn release_id(tag: Symbol<'static>, id: i64) {
+    let mut refs = REFS.lock().unwrap();
+    let Some(count) = refs.get_mut(&(tag, id)) else { return };
+    *count -= 1;
+    if *count > 0 {
+        return;
+    }
+    refs.remove(&(tag, id));
+    // The release function takes the lock of its own table, which may be held
+    // while calling `retain_id`
+    drop(refs);
+    if let Some((_, release)) = HANDLES.iter().find(|(x, _)| *x == tag) {
+        release(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use crate::core::object::RecordBuilder;
+    use rune_core::macros::root;
+
+    fn generator_record(id: i64, cx: &Context) -> Object {
+        let mut record = cx.vec_with_capacity(2);
+        record.push(sym::RUNE_GENERATOR.into());
+        record.push(cx.add(id));
+        cx.add(RecordBuilder(record))
+    }
+
+    #[test]
+    fn test_handle_refs() {
+        let id = i64::MAX;
+        let refs = || REFS.lock().unwrap().get(&(sym::RUNE_GENERATOR, id)).copied();
+        {
+            let roots = &RootSet::default();
+            let cx = &mut Context::new(roots);
+            let kept = generator_record(id, cx);
+            generator_record(id, cx);
+            assert_eq!(refs(), Some(2));
+            root!(kept, cx);
+            cx.garbage_collect(true);
+            assert_eq!(refs(), Some(1));
+            assert_eq!(kept.bind(cx).to_string(), format!("#s(rune-generator {id})"));
+        }
+        // the rest are released with their heap
+        assert_eq!(refs(), None);
+    }
+}
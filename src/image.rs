@@ -0,0 +1,224 @@
+//! Image descriptors.
+//!
+//! There is no display to draw images on, but libraries still create and
+//! measure them. Images are the usual `(image . PROPS)' descriptors, and
+//! their type and size are read from the file header for PNG, JPEG and GIF.
+use crate::core::{
+    cons::Cons,
+    env::sym,
+    gc::Context,
+    object::{Object, ObjectType, OptionalFlag, Symbol, NIL},
+};
+use crate::fns::{plist_get, slice_into_list};
+use anyhow::{anyhow, bail, Result};
+use rune_macros::defun;
+use std::path::Path;
+
+/// Return the image type of `data` based on its header.
+fn type_from_header(data: &[u8]) -> Option<Symbol<'static>> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(sym::PNG)
+    } else if data.starts_with(b"\xff\xd8") {
+        Some(sym::JPEG)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some(sym::GIF)
+    } else {
+        None
+    }
+}
+
+/// Return the width and height in pixels of the image in `data`.
+fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| Some(u16::from_be_bytes(data.get(i..i + 2)?.try_into().ok()?) as u32);
+    match type_from_header(data)? {
+        sym::PNG => {
+            let be32 = |i: usize| Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?));
+            // The IHDR chunk always comes first
+            if data.get(12..16)? != b"IHDR" {
+                return None;
+            }
+            Some((be32(16)?, be32(20)?))
+        }
+        sym::GIF => {
+            let le16 = |i: usize| Some(u16::from_le_bytes(data.get(i..i + 2)?.try_into().ok()?));
+            Some((le16(6)?.into(), le16(8)?.into()))
+        }
+        sym::JPEG => {
+            // Walk the segments until a start of frame marker
+            let mut i = 2;
+            loop {
+                if *data.get(i)? != 0xff {
+                    return None;
+                }
+                let marker = *data.get(i + 1)?;
+                match marker {
+                    // padding before a marker
+                    0xff => i += 1,
+                    // markers without a length
+                    0x01 | 0xd0..=0xd8 => i += 2,
+                    0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                        return Some((be16(i + 7)?, be16(i + 5)?));
+                    }
+                    _ => i += 2 + be16(i + 2)? as usize,
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Return the contents of the image described by `spec`.
+fn image_data(spec: Object) -> Result<Vec<u8>> {
+    match plist_get(spec, sym::KW_DATA.into())?.untag() {
+        ObjectType::String(data) => return Ok(data.as_bytes().to_vec()),
+        ObjectType::ByteString(data) => return Ok(data.to_vec()),
+        ObjectType::NIL => {}
+        other => bail!("Invalid image data: {other}"),
+    }
+    match plist_get(spec, sym::KW_FILE.into())?.untag() {
+        ObjectType::String(file) => {
+            std::fs::read(file.as_ref()).map_err(|e| anyhow!("Cannot read image {file}: {e}"))
+        }
+        _ => bail!("Invalid image specification: {spec}"),
+    }
+}
+
+fn image_plist(spec: Object) -> Result<Object> {
+    match spec.untag() {
+        ObjectType::Cons(cons) if cons.car() == sym::IMAGE => Ok(cons.cdr()),
+        _ => bail!("Invalid image specification: {spec}"),
+    }
+}
+
+/// Return non-nil if images of TYPE can be created. Only types whose size can
+/// be read are supported.
+#[defun]
+fn image_type_available_p(image_type: Symbol) -> bool {
+    matches!(image_type, sym::PNG | sym::JPEG | sym::GIF)
+}
+
+/// Determine the image type from the header of DATA.
+#[defun]
+fn image_type_from_data(data: Object) -> Option<Symbol> {
+    match data.untag() {
+        ObjectType::String(data) => type_from_header(data.as_bytes()),
+        ObjectType::ByteString(data) => type_from_header(data),
+        _ => None,
+    }
+}
+
+/// Determine the image type from the header of FILE.
+#[defun]
+fn image_type_from_file_header(file: &str) -> Option<Symbol> {
+    let data = std::fs::read(file).ok()?;
+    type_from_header(&data)
+}
+
+/// Create an image descriptor for FILE-OR-DATA. If DATA-P is non-nil,
+/// FILE-OR-DATA holds the image contents; otherwise it is a file name. TYPE
+/// is determined from the contents or the file name extension if not given.
+/// PROPS are added to the end of the descriptor.
+#[defun]
+fn create_image<'ob>(
+    file_or_data: Object<'ob>,
+    image_type: Option<Symbol<'ob>>,
+    data_p: OptionalFlag,
+    props: &[Object<'ob>],
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let (source, detected) = if data_p.is_some() {
+        (sym::KW_DATA, image_type_from_data(file_or_data))
+    } else {
+        let ObjectType::String(file) = file_or_data.untag() else {
+            bail!("Invalid image file name: {file_or_data}")
+        };
+        let detected = image_type_from_file_header(file).or_else(|| {
+            let ext = Path::new(file.as_ref()).extension()?.to_str()?.to_ascii_lowercase();
+            match ext.as_str() {
+                "png" => Some(sym::PNG),
+                "jpg" | "jpeg" => Some(sym::JPEG),
+                "gif" => Some(sym::GIF),
+                _ => None,
+            }
+        });
+        (sym::KW_FILE, detected)
+    };
+    let Some(image_type) = image_type.or(detected) else {
+        bail!("Cannot determine image type")
+    };
+    if !image_type_available_p(image_type) {
+        return Ok(NIL);
+    }
+    let props = slice_into_list(props, None, cx);
+    let head = [
+        sym::IMAGE.into(),
+        sym::KW_TYPE.into(),
+        image_type.into(),
+        source.into(),
+        file_or_data,
+    ];
+    Ok(slice_into_list(&head, Some(props), cx))
+}
+
+/// Return the size of image SPEC as (WIDTH . HEIGHT). Without a display to
+/// measure characters against, the size is always in pixels and PIXELS is
+/// ignored.
+#[defun]
+fn image_size<'ob>(
+    spec: Object<'ob>,
+    _pixels: OptionalFlag,
+    _frame: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let data = image_data(image_plist(spec)?)?;
+    let Some((width, height)) = dimensions(&data) else {
+        bail!("Cannot determine the size of image {spec}")
+    };
+    Ok(Cons::new(width as i64, height as i64, cx).into())
+}
+
+defsym!(IMAGE);
+defsym!(PNG);
+defsym!(JPEG);
+defsym!(GIF);
+defsym!(KW_TYPE);
+defsym!(KW_FILE);
+defsym!(KW_DATA);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x01\x2c\0\0\0\x96\x08\x06\0\0\0";
+    const GIF: &[u8] = b"GIF89a\x2c\x01\x96\x00\xf7\x00\x00";
+    // SOI, an APP0 segment, then a baseline SOF0 frame
+    const JPEG: &[u8] = b"\xff\xd8\xff\xe0\0\x04JF\xff\xc0\0\x11\x08\0\x96\x01\x2c\x03";
+
+    #[test]
+    fn test_dimensions() {
+        for data in [PNG, GIF, JPEG] {
+            assert_eq!(dimensions(data), Some((300, 150)));
+        }
+        assert_eq!(type_from_header(JPEG), Some(sym::JPEG));
+        assert_eq!(dimensions(&PNG[..20]), None);
+        assert_eq!(dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_create_image() {
+        let file = std::env::temp_dir().join(format!("rune-image-{}.gif", std::process::id()));
+        std::fs::write(&file, GIF).unwrap();
+        let file = file.to_str().unwrap();
+        assert_lisp(
+            &format!("(image-size (create-image {file:?} nil nil :ascent 'center))"),
+            "(300 . 150)",
+        );
+        assert_lisp(
+            &format!("(nthcdr 3 (create-image {file:?} nil nil :ascent 'center))"),
+            &format!("(:file {file:?} :ascent center)"),
+        );
+        std::fs::remove_file(file).unwrap();
+        assert_lisp("(image-type-available-p 'svg)", "nil");
+    }
+}
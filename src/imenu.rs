@@ -0,0 +1,239 @@
+//! Building imenu indexes from regexps.
+//!
+//! Each element of `imenu-generic-expression' is a list of the form
+//! (MENU-TITLE REGEXP INDEX [FUNCTION] [ARGUMENTS...]). Every match of
+//! REGEXP in the buffer adds an item named by subexpression INDEX, at the
+//! position where that subexpression starts, to the submenu MENU-TITLE, or
+//! to the top level if MENU-TITLE is nil. `^' in REGEXP matches at the start
+//! of any line. Modes choose their patterns by setting
+//! `imenu-generic-expression', for example to `lisp-imenu-generic-expression'
+//! or `rust-imenu-generic-expression'.
+//!
+//! Positions are integers rather than markers, and matches inside comments
+//! and strings are not skipped.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Block, Context, Rt},
+    object::{Gc, IntoObject, List, Object, ObjectType, NIL},
+};
+use crate::fns::{equal, slice_into_list};
+use crate::search::lisp_regex_to_rust;
+use anyhow::{bail, Result};
+use fancy_regex::Regex;
+use rune_core::macros::list;
+use rune_macros::defun;
+
+/// Scan `text` for the matches of `patterns` and return the index alist.
+fn generic_index<'ob>(text: &str, patterns: List<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
+    // the submenus in the order their patterns appear, and the top level
+    let mut menus: Vec<(Object, Vec<(usize, Object)>)> = Vec::new();
+    let mut top_level: Vec<(usize, Object)> = Vec::new();
+    for pattern in patterns.elements() {
+        let pattern = pattern?;
+        let fields: Vec<Object> = List::try_from(pattern)?.elements().collect::<Result<_>>()?;
+        let [title, regexp, index, rest @ ..] = fields.as_slice() else {
+            bail!("Invalid imenu pattern: {pattern}")
+        };
+        let ObjectType::String(regexp) = regexp.untag() else {
+            bail!("Invalid imenu regexp: {regexp}")
+        };
+        let index: usize = (*index).try_into()?;
+        let regex = Regex::new(&format!("(?m){}", lisp_regex_to_rust(regexp)))?;
+        let mut items = Vec::new();
+        // convert byte offsets to buffer positions incrementally
+        let (mut byte, mut position) = (0, 1);
+        for captures in regex.captures_iter(text) {
+            let Some(name) = captures?.get(index) else { continue };
+            position += text[byte..name.start()].chars().count();
+            byte = name.start();
+            let item = match rest {
+                [] => Cons::new(name.as_str(), position, cx).into(),
+                [function, args @ ..] => {
+                    let args = slice_into_list(args, None, cx);
+                    let tail = Cons::new(position, Cons::new(*function, args, cx), cx);
+                    Cons::new(name.as_str(), tail, cx).into()
+                }
+            };
+            items.push((position, item));
+        }
+        if title.is_nil() {
+            top_level.extend(items);
+        } else if let Some((_, menu)) = menus.iter_mut().find(|(x, _)| equal(*x, *title)) {
+            menu.extend(items);
+        } else {
+            menus.push((*title, items));
+        }
+    }
+    let sorted = |mut items: Vec<(usize, Object<'ob>)>| {
+        items.sort_by_key(|(position, _)| *position);
+        items.into_iter().map(|(_, item)| item).collect::<Vec<_>>()
+    };
+    let mut index: Vec<Object> = Vec::new();
+    for (title, items) in menus {
+        if !items.is_empty() {
+            let items = slice_into_list(&sorted(items), None, cx);
+            index.push(Cons::new(title, items, cx).into());
+        }
+    }
+    index.extend(sorted(top_level));
+    Ok(slice_into_list(&index, None, cx))
+}
+
+/// Return an index alist of the current buffer built from PATTERNS, which
+/// has the same form as `imenu-generic-expression'. The submenus come
+/// first, in the order of their patterns, followed by the top level items.
+/// The items in each menu are sorted by position.
+#[defun]
+fn imenu__generic_function<'ob>(
+    patterns: List<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let text = env.current_buffer.get().text.to_string();
+    generic_index(&text, patterns, cx)
+}
+
+/// Return an index alist for the current buffer, built from
+/// `imenu-generic-expression'. Signal an error if there are no items,
+/// unless NOERROR is non-nil. The result is also stored in
+/// `imenu--index-alist'.
+#[defun]
+fn imenu__make_index_alist<'ob>(
+    noerror: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let patterns = match env.vars.get(sym::IMENU_GENERIC_EXPRESSION) {
+        Some(patterns) => patterns.bind(cx),
+        None => NIL,
+    };
+    if patterns.is_nil() {
+        bail!("This buffer cannot use `imenu-default-create-index-function'");
+    }
+    let text = env.current_buffer.get().text.to_string();
+    let index = generic_index(&text, patterns.try_into()?, cx)?;
+    if index.is_nil() && noerror.is_none_or(|x| x.is_nil()) {
+        bail!("No items suitable for an index found in this buffer");
+    }
+    env.set_var(sym::IMENU__INDEX_ALIST, index, cx)?;
+    Ok(index)
+}
+
+/// A list of imenu patterns without functions, used for the default values
+/// of the pattern variables.
+pub(crate) struct Patterns(&'static [(Option<&'static str>, &'static str, i64)]);
+
+impl IntoObject for Patterns {
+    type Out<'ob> = ObjectType<'ob>;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let mut patterns = NIL;
+        for &(title, regexp, index) in self.0.iter().rev() {
+            let pattern = list![title, regexp, index; block];
+            patterns = Cons::new(pattern, patterns, block).into();
+        }
+        patterns
+    }
+}
+
+pub(crate) const LISP_PATTERNS: &[(Option<&str>, &str, i64)] = &[
+    (
+        None,
+        r"^[ \t]*(\(?:cl-\)?def\(?:un\|un\*\|macro\|macro\*\|subst\|generic\|method\|advice\|ine-minor-mode\|ine-derived-mode\|ine-global-minor-mode\)[ \t\n]+\([^ \t\n()]+\)",
+        1,
+    ),
+    (
+        Some("Variables"),
+        r"^[ \t]*(def\(?:var\|var-local\|const\|custom\|parameter\)[ \t\n]+\([^ \t\n()]+\)",
+        1,
+    ),
+    (
+        Some("Types"),
+        r"^[ \t]*(\(?:cl-\)?def\(?:struct\|class\|type\|face\|group\)[ \t\n]+(?\([^ \t\n()]+\)",
+        1,
+    ),
+];
+
+pub(crate) const RUST_PATTERNS: &[(Option<&str>, &str, i64)] = &[
+    (
+        Some("Type"),
+        r"^[ \t]*\(?:pub\(?:([^)]*)\)?[ \t]+\)?\(?:struct\|enum\|union\|type\)[ \t]+\([[:alnum:]_]+\)",
+        1,
+    ),
+    (
+        Some("Trait"),
+        r"^[ \t]*\(?:pub\(?:([^)]*)\)?[ \t]+\)?\(?:unsafe[ \t]+\)?trait[ \t]+\([[:alnum:]_]+\)",
+        1,
+    ),
+    (
+        Some("Impl"),
+        r"^[ \t]*\(?:unsafe[ \t]+\)?impl\(?:<[^>\n]*>\)?[ \t]+\([^{\n]*[^{\n \t]\)",
+        1,
+    ),
+    (Some("Macro"), r"^[ \t]*macro_rules![ \t]+\([[:alnum:]_]+\)", 1),
+    (
+        Some("Fn"),
+        r#"^[ \t]*\(?:pub\(?:([^)]*)\)?[ \t]+\)?\(?:\(?:const\|async\|unsafe\|extern\(?:[ \t]+"[^"]*"\)?\)[ \t]+\)*fn[ \t]+\([[:alnum:]_]+\)"#,
+        1,
+    ),
+];
+
+defvar!(IMENU_GENERIC_EXPRESSION);
+defvar!(IMENU__INDEX_ALIST);
+defvar!(
+    LISP_IMENU_GENERIC_EXPRESSION,
+    crate::imenu::Patterns(crate::imenu::LISP_PATTERNS)
+);
+defvar!(
+    RUST_IMENU_GENERIC_EXPRESSION,
+    crate::imenu::Patterns(crate::imenu::RUST_PATTERNS)
+);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp_with_vars;
+
+    #[test]
+    fn test_lisp_index() {
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"imenu-lisp\"))
+               (insert \"(defvar foo 1)\\n(defun bar ()\\n  (baz))\\n  (defmacro qux (x) x)\\n\")
+               (setq imenu-generic-expression lisp-imenu-generic-expression)
+               (imenu--make-index-alist))",
+            "((\"Variables\" (\"foo\" . 9)) (\"bar\" . 23) (\"qux\" . 51))",
+        );
+    }
+
+    #[test]
+    fn test_rust_index() {
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"imenu-rust\"))
+               (insert \"pub(crate) struct A;\\nimpl<T> Display for A {\\n    pub async fn b() {}\\n}\\nfn c() {}\\n\")
+               (imenu--generic-function rust-imenu-generic-expression))",
+            "((\"Type\" (\"A\" . 19)) (\"Impl\" (\"Display for A\" . 30))
+              (\"Fn\" (\"b\" . 63) (\"c\" . 75)))",
+        );
+    }
+
+    #[test]
+    fn test_functions_and_errors() {
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"imenu-fn\"))
+               (insert \"item one\\nitem two\\n\")
+               (imenu--generic-function '((nil \"^item \\\\(.*\\\\)\" 1 ignore 7))))",
+            "((\"one\" 6 ignore 7) (\"two\" 15 ignore 7))",
+        );
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"imenu-empty\"))
+               (setq imenu-generic-expression '((nil \"^nothing\" 0)))
+               (list (imenu--make-index-alist t)
+                     (condition-case nil (imenu--make-index-alist) (error 'failed))))",
+            "(nil failed)",
+        );
+    }
+}
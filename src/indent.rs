@@ -0,0 +1,341 @@
+//! Indentation.
+//!
+//! The primitives here measure and change the indentation of the current
+//! line. Columns count tabs up to the next multiple of `tab-width', and new
+//! indentation uses tabs if `indent-tabs-mode' is non-nil. How a line should
+//! be indented is up to the function in `indent-line-function', which
+//! `indent-according-to-mode' and `indent-region' call for each line. Rune
+//! has no buffer-local variables yet, so it is global.
+//!
+//! `lisp-indent-line' indents Lisp code. It finds the list that the line
+//! is in with the parse of the buffer kept by [`crate::syntax`], so indenting
+//! a region line by line only parses the buffer once, and indents the line by
+//! the head of that list: bodies of special forms, of forms with a
+//! `lisp-indent-function' property and of definitions are indented two
+//! columns, distinguished arguments four, and other arguments line up with
+//! the first argument.
+use crate::character::{char_columns, tab_width};
+use crate::core::{
+    env::{intern, sym, Env},
+    gc::{Context, Rt},
+    object::{Function, NIL},
+};
+use crate::data::LispError;
+use crate::insdel::replace_region;
+use crate::syntax::parse_to;
+use anyhow::{bail, Result};
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+use text_buffer::Buffer as TextBuffer;
+
+/// The characters of `text` from `pos` to the end.
+fn chars_from(text: &TextBuffer, pos: usize) -> impl Iterator<Item = char> + '_ {
+    let (before, after) = text.slice(pos..);
+    before.chars().chain(after.chars())
+}
+
+/// The characters of `text` before `pos`, nearest first.
+fn chars_before(text: &TextBuffer, pos: usize) -> impl Iterator<Item = char> + '_ {
+    let (before, after) = text.slice(..pos);
+    after.chars().rev().chain(before.chars().rev())
+}
+
+fn line_start(text: &TextBuffer, pos: usize) -> usize {
+    pos - chars_before(text, pos).take_while(|x| *x != '\n').count()
+}
+
+/// The start of the line after the one `pos` is on, if there is one.
+fn next_line_start(text: &TextBuffer, pos: usize) -> Option<usize> {
+    chars_from(text, pos).position(|x| x == '\n').map(|x| pos + x + 1)
+}
+
+/// The end of the indentation of the line starting at `start`.
+fn indentation_end(text: &TextBuffer, start: usize) -> usize {
+    start + chars_from(text, start).take_while(|x| matches!(x, ' ' | '\t')).count()
+}
+
+/// The column that `pos` is at.
+fn column(text: &TextBuffer, pos: usize, tab_width: usize) -> usize {
+    let start = line_start(text, pos);
+    chars_from(text, start).take(pos - start).fold(0, |column, c| match c {
+        '\t' => (column / tab_width + 1) * tab_width,
+        c => column + char_columns(c, tab_width),
+    })
+}
+
+/// The whitespace that goes from column `from` to column `to`.
+fn whitespace(mut from: usize, to: usize, env: &Rt<Env>, cx: &Context) -> String {
+    let tab_width = tab_width(env, cx);
+    let tabs = env.vars.get(sym::INDENT_TABS_MODE).is_some_and(|x| !x.bind(cx).is_nil());
+    let mut string = String::new();
+    while tabs && (from / tab_width + 1) * tab_width <= to {
+        string.push('\t');
+        from = (from / tab_width + 1) * tab_width;
+    }
+    string.extend(std::iter::repeat(' ').take(to.saturating_sub(from)));
+    string
+}
+
+/// Return the horizontal position of point. The beginning of the line is
+/// column 0.
+#[defun]
+fn current_column(env: &Rt<Env>, cx: &Context) -> usize {
+    let text = &env.current_buffer.get().text;
+    column(text, text.cursor().chars(), tab_width(env, cx))
+}
+
+/// Return the indentation of the current line, which is the column of the
+/// first character on it that isn't a space or tab.
+#[defun]
+fn current_indentation(env: &Rt<Env>, cx: &Context) -> usize {
+    let text = &env.current_buffer.get().text;
+    let end = indentation_end(text, line_start(text, text.cursor().chars()));
+    column(text, end, tab_width(env, cx))
+}
+
+/// Indent from point with tabs and spaces until COLUMN is reached, inserting
+/// at least MINIMUM spaces, which defaults to 0. Point ends up after the
+/// inserted whitespace. Return the column reached.
+#[defun]
+fn indent_to(
+    column: usize,
+    minimum: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let text = &env.current_buffer.get().text;
+    let point = text.cursor().chars();
+    let from = self::column(text, point, tab_width(env, cx));
+    let to = column.max(from + minimum.unwrap_or(0));
+    let string = whitespace(from, to, env, cx);
+    replace_region(point + 1, point + 1, &string, env, cx)?;
+    Ok(to)
+}
+
+/// Replace the indentation of the current line with whitespace up to
+/// `column`. If `keep_point` is false or point was in the indentation, it
+/// moves to the end of the indentation, and otherwise it stays on the same
+/// text.
+fn set_indentation(
+    column: usize,
+    keep_point: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let new = whitespace(0, column, env, cx);
+    let text = &env.current_buffer.get().text;
+    let point = text.cursor().chars();
+    let start = line_start(text, point);
+    let end = indentation_end(text, start);
+    if chars_from(text, start).take(end - start).ne(new.chars()) {
+        replace_region(start + 1, end + 1, &new, env, cx)?;
+    }
+    if !keep_point || point < end {
+        let end = start + new.chars().count();
+        env.current_buffer.get_mut().text.set_cursor(end);
+    }
+    Ok(())
+}
+
+/// Indent the current line to COLUMN, changing only the spaces and tabs at
+/// its start. Point moves to the end of the indentation.
+#[defun]
+fn indent_line_to(column: usize, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    set_indentation(column, false, env, cx)
+}
+
+/// The value of `indent-line-function'.
+fn indent_line_function<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Result<Function<'ob>> {
+    let function = env.vars.get(sym::INDENT_LINE_FUNCTION).map_or(NIL, |x| x.bind(cx));
+    Ok(function.try_into()?)
+}
+
+/// Indent the current line by calling `indent-line-function'.
+#[defun]
+fn indent_according_to_mode(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let function = indent_line_function(env, cx)?;
+    root!(function, cx);
+    call!(function; env, cx)?;
+    Ok(())
+}
+
+/// Indent each nonempty line that starts in the region from START to END.
+/// If COLUMN is non-nil, each line is indented to that column, and otherwise
+/// by calling `indent-line-function' with point at the start of the line.
+/// Point stays on the same text if it is after the region.
+#[defun]
+fn indent_region(
+    start: usize,
+    end: usize,
+    column: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let (start, end) = (start.min(end), start.max(end));
+    let text = &env.current_buffer.get().text;
+    let len = text.len_chars();
+    if start < 1 || end > len + 1 {
+        bail!(LispError::region_out_of_range(start, end, cx));
+    }
+    // Indenting a line changes the positions after it, so the end of the
+    // region, the next line and point are kept as distances from the end of
+    // the buffer
+    let end_from_end = len + 1 - end;
+    let point_from_end = len - text.cursor().chars();
+    let first = line_start(text, start - 1);
+    let function = indent_line_function(env, cx)?;
+    root!(function, cx);
+    let mut line = Some(first);
+    while let Some(start) = line {
+        let text = &env.current_buffer.get().text;
+        let len = text.len_chars();
+        if start > first && start >= len - end_from_end {
+            break;
+        }
+        let next_from_end = next_line_start(text, start).map(|x| len - x);
+        if text.char_at(start).is_some_and(|x| x != '\n') {
+            env.current_buffer.get_mut().text.set_cursor(start);
+            match column {
+                Some(column) => set_indentation(column, false, env, cx)?,
+                None => {
+                    call!(function; env, cx)?;
+                }
+            }
+        }
+        let len = env.current_buffer.get().text.len_chars();
+        line = next_from_end.map(|x| len - x);
+    }
+    let text = &mut env.current_buffer.get_mut().text;
+    text.set_cursor(text.len_chars().saturating_sub(point_from_end));
+    Ok(())
+}
+
+/// Indent the current line like the previous nonblank line. The first
+/// nonblank line of the buffer is left alone.
+#[defun]
+fn indent_relative_first_indent_point(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let tab_width = tab_width(env, cx);
+    let text = &env.current_buffer.get().text;
+    let mut start = line_start(text, text.cursor().chars());
+    while start > 0 {
+        start = line_start(text, start - 1);
+        let end = indentation_end(text, start);
+        if text.char_at(end).is_some_and(|x| x != '\n') {
+            let column = column(text, end, tab_width);
+            return set_indentation(column, true, env, cx);
+        }
+    }
+    Ok(())
+}
+
+/// The text of the symbol or number that starts at `start`.
+fn token(text: &TextBuffer, start: usize) -> String {
+    chars_from(text, start)
+        .take_while(|x| !x.is_whitespace() && !matches!(x, '(' | ')' | '[' | ']' | '"' | ';'))
+        .collect()
+}
+
+/// The column to indent the line starting at `start` to as Lisp code, or
+/// None if the line starts inside a string. The buffer is parsed up to
+/// `start` with [`parse_to`], which continues from the last line indented.
+fn lisp_indent(start: usize, env: &mut Rt<Env>, cx: &Context) -> Option<usize> {
+    let state = parse_to(start, env);
+    if state.string.is_some() {
+        return None;
+    }
+    let Some(list) = state.lists.last() else { return Some(0) };
+    let (open, elements) = (list.open, list.elements.len());
+    let (head, first_arg) = (list.elements.first().copied(), list.elements.get(1).copied());
+    let tab_width = tab_width(env, cx);
+    let text = &env.current_buffer.get().text;
+    let open_column = column(text, open, tab_width);
+    let Some(head) = head else { return Some(open_column + 1) };
+    let head_column = column(text, head, tab_width);
+    let name = token(text, head);
+    let is_symbol = !name.is_empty()
+        && !text.char_at(head).is_some_and(|x| "([\"'`,#?".contains(x))
+        && name.parse::<f64>().is_err();
+    if text.char_at(open) == Some('[') || !is_symbol {
+        return Some(head_column);
+    }
+    if let Some(distinguished) = crate::pp::body_indent(intern(&name, cx), env, cx) {
+        return Some(open_column + if elements - 1 < distinguished { 4 } else { 2 });
+    }
+    if name.len() > 3 && name.starts_with("def") {
+        return Some(open_column + 2);
+    }
+    match first_arg {
+        Some(arg) if !chars_from(text, open).take(arg - open).any(|x| x == '\n') => {
+            Some(column(text, arg, tab_width))
+        }
+        _ => Some(head_column),
+    }
+}
+
+/// Return the column the current line should be indented to as Lisp code,
+/// or nil if it starts inside a string.
+#[defun]
+fn calculate_lisp_indent(env: &mut Rt<Env>, cx: &Context) -> Option<usize> {
+    let text = &env.current_buffer.get().text;
+    let start = line_start(text, text.cursor().chars());
+    lisp_indent(start, env, cx)
+}
+
+/// Indent the current line as Lisp code. Lines that start inside a string
+/// are left alone. Point stays on the same text unless it was in the
+/// indentation.
+#[defun]
+fn lisp_indent_line(env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    match calculate_lisp_indent(env, cx) {
+        Some(column) => set_indentation(column, true, env, cx),
+        None => Ok(()),
+    }
+}
+
+defvar!(INDENT_LINE_FUNCTION, sym::INDENT_RELATIVE_FIRST_INDENT_POINT);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::eval_in_buffer;
+
+    #[test]
+    fn test_indentation_primitives() {
+        let (result, text, _) = eval_in_buffer(
+            "  foo\n\tbar",
+            "(list (progn (goto-char 3) (current-column))
+                   (current-indentation)
+                   (progn (indent-line-to 4) (current-column))
+                   (progn (goto-char 9) (current-column))
+                   (current-indentation)
+                   (indent-to 12)
+                   (let ((indent-tabs-mode t)) (indent-line-to 9) (current-column)))",
+        );
+        assert_eq!(result, "(3 2 4 8 8 12 9)");
+        assert_eq!(text, "    foo\n\t bar");
+    }
+
+    #[test]
+    fn test_indent_region() {
+        let (_, text, _) = eval_in_buffer("  a\nb\n\nc", "(indent-region 1 (point-max))");
+        assert_eq!(text, "  a\n  b\n\n  c");
+        let (_, text, _) = eval_in_buffer("x\ny\nz", "(indent-region 3 5 2)");
+        assert_eq!(text, "x\n  y\nz");
+        let form = "(condition-case err (indent-region 0 2) (args-out-of-range err))";
+        assert_eq!(eval_in_buffer("x", form).0, "(args-out-of-range 0 2)");
+    }
+
+    #[test]
+    fn test_lisp_indent() {
+        let source = "(defun foo (x)\n\"doc\nstring\"\n(let ((y 1)\n(z 2))\n(if x\ny\nz)))\n\
+                      (list 1\n2)\n(foo\nbar)\n[a\nb]\n";
+        let (_, text, _) = eval_in_buffer(
+            source,
+            "(let ((indent-line-function 'lisp-indent-line)) (indent-region 1 (point-max)))",
+        );
+        assert_eq!(
+            text,
+            "(defun foo (x)\n  \"doc\nstring\"\n  (let ((y 1)\n        (z 2))\n    (if x\n        y\n      z)))\n\
+             (list 1\n      2)\n(foo\n bar)\n[a\n b]\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Hooks run around changes to buffer text.
+//!
+//! Every primitive that changes the text of the current buffer calls
+//! [`signal_before_change`] before the change and [`signal_after_change`]
+//! after it. The first change to a buffer visiting a file locks the file.
+//! `before-change-functions' is called with the BEG and END of the region
+//! about to change, and `after-change-functions' with the BEG and END of the
+//! changed text and the length of the text it replaced. Neither runs while
+//! `inhibit-modification-hooks' is non-nil, which is bound to t while they
+//! run so that their own changes don't run them again. A hook that signals
+//! an error is set to nil so it can't break every later change.
+//!
+//! The hooks also tell [`crate::syntax`] which part of its last parse of the
+//! buffer is still valid.
+//!
+//! Text inserted into another buffer goes through [`with_current_buffer`] so
+//! that the hooks see the buffer that changed.
+//!
+//! Rune has no buffer-local variables yet, so these hooks are global.
+use crate::core::{
+    env::{sym, CallFrame, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, LispBuffer, Object, ObjectType, Symbol, NIL, TRUE},
+};
+use crate::rooted_iter;
+use anyhow::Result;
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use rune_core::macros::{list, root};
+
+/// Run the hook `hook` with `args`, unless modification hooks are
+/// inhibited.
+fn run_change_hook(
+    hook: Symbol,
+    args: &[usize],
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    if env
+        .vars
+        .get(sym::INHIBIT_MODIFICATION_HOOKS)
+        .is_some_and(|x| !x.bind(cx).is_nil())
+    {
+        return Ok(());
+    }
+    let Some(value) = env.vars.get(hook) else { return Ok(()) };
+    let value = value.bind(cx);
+    // A single function is treated as a hook with one element
+    let functions = match value.untag() {
+        ObjectType::Cons(_) | ObjectType::NIL => value,
+        _ => list![value; cx],
+    };
+    env.varbind(sym::INHIBIT_MODIFICATION_HOOKS, TRUE, cx);
+    let result = call_each(functions, args, env, cx);
+    env.unbind(1, cx);
+    if result.is_err() {
+        env.set_var(hook, NIL, cx)?;
+    }
+    result
+}
+
+fn call_each(functions: Object, args: &[usize], env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    rooted_iter!(functions, functions, cx);
+    while let Some(function) = functions.next()? {
+        // t stands for the global value in a buffer-local hook
+        if function.bind(cx) == TRUE {
+            continue;
+        }
+        let function: &Rto<Function> = function.try_as()?;
+        let frame = &mut CallFrame::new(env);
+        for arg in args {
+            frame.push_arg(cx.add(*arg));
+        }
+        function.call(frame, None, cx)?;
+    }
+    Ok(())
+}
+
+/// Prepare to change the text from `beg` to `end` in the current buffer.
+/// Positions start at 1.
+pub(crate) fn signal_before_change(
+    beg: usize,
+    end: usize,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    crate::filelock::lock_before_modify(env, cx)?;
+    crate::syntax::before_change(beg.saturating_sub(1), env);
+    run_change_hook(sym::BEFORE_CHANGE_FUNCTIONS, &[beg, end], env, cx)
+}
+
+/// Report that the text from `beg` to `end` in the current buffer replaced
+/// `old_len` characters.
+pub(crate) fn signal_after_change(
+    beg: usize,
+    end: usize,
+    old_len: usize,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    crate::syntax::after_change(beg.saturating_sub(1), env);
+    run_change_hook(sym::AFTER_CHANGE_FUNCTIONS, &[beg, end, old_len], env, cx)
+}
+
+/// Replace the text from `beg` to `end` in the current buffer with `text`,
+/// running the change hooks. Point moves with the text after the region, and
+/// keeps its offset into the region if it was inside it, as long as the new
+/// text is long enough.
+pub(crate) fn replace_region(
+    beg: usize,
+    end: usize,
+    text: &str,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    signal_before_change(beg, end, env, cx)?;
+    let buffer = env.current_buffer.get_mut();
+    let point = buffer.text.cursor().chars();
+    buffer.delete(beg, end)?;
+    buffer.text.set_cursor(beg - 1);
+    buffer.text.insert(text);
+    let len = text.chars().count();
+    let point = if point < beg - 1 {
+        point
+    } else if point >= end - 1 {
+        point + len - (end - beg)
+    } else {
+        point.min(beg - 1 + len)
+    };
+    buffer.text.set_cursor(point);
+    signal_after_change(beg, beg + len, end - beg, env, cx)
+}
+
+/// Call `func` with `buffer` as the current buffer and switch back after, so
+/// that changes made to a buffer other than the current one run the change
+/// hooks in the right buffer.
+pub(crate) fn with_current_buffer<T>(
+    buffer: &Rto<&LispBuffer>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+    func: impl FnOnce(&mut Rt<Env>, &mut Context) -> Result<T>,
+) -> Result<T> {
+    let current = env.current_buffer.get().lisp_buffer(cx);
+    root!(current, cx);
+    env.set_buffer(buffer.bind(cx), cx);
+    let result = func(env, cx);
+    env.set_buffer(current.bind(cx), cx);
+    result
+}
+
+defvar!(BEFORE_CHANGE_FUNCTIONS);
+defvar!(AFTER_CHANGE_FUNCTIONS);
+defvar_bool!(INHIBIT_MODIFICATION_HOOKS, false);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_change_hooks() {
+        assert_lisp(
+            "(let* ((changes nil)
+                    (before-change-functions (list (lambda (beg end) (setq changes (cons (list 'before beg end) changes)))))
+                    (after-change-functions (list (lambda (beg end len) (setq changes (cons (list 'after beg end len) changes))))))
+               (set-buffer (get-buffer-create \"change-hooks\"))
+               (insert \"hello\" \" world\")
+               (delete-region 3 5)
+               (delete-region 2 2)
+               (let ((inhibit-modification-hooks t)) (insert \"!\"))
+               (nreverse changes))",
+            "((before 1 1) (after 1 12 0) (before 3 5) (after 3 3 2))",
+        );
+        assert_lisp(
+            "(let ((after-change-functions (list (lambda (_beg _end _len) (insert \"x\") (error \"oops\")))))
+               (set-buffer (get-buffer-create \"change-hooks-error\"))
+               (list (condition-case nil (insert \"a\") (error 'failed))
+                     after-change-functions
+                     (point-max)))",
+            "(failed nil 3)",
+        );
+    }
+
+    #[test]
+    fn test_change_hooks_other_buffer() {
+        assert_lisp(
+            "(let* ((changes nil)
+                    (after-change-functions (list (lambda (beg end len) (setq changes (cons (list (buffer-name) beg end len) changes))))))
+               (set-buffer (get-buffer-create \"change-hooks-current\"))
+               (princ \"abc\" (get-buffer-create \"change-hooks-print\"))
+               (display-warning 'test \"hi\" nil \"change-hooks-warnings\")
+               (cons (buffer-name) (nreverse changes)))",
+            "(\"change-hooks-current\" (\"change-hooks-print\" 1 4 0) (\"change-hooks-warnings\" 1 20 0))",
+        );
+    }
+}
@@ -4,8 +4,11 @@ use crate::{
         cons::{Cons, ElemStreamIter},
         env::{sym, CallFrame, Env},
         error::{Type, TypeError},
-        gc::{Context, Rt, Rto, Slot},
-        object::{Function, Gc, List, ListType, Object, ObjectType, Symbol, TagType, NIL, TRUE},
+        gc::{ArgBuffer, Context, Rt, Rto, Slot},
+        object::{
+            Function, FunctionType, Gc, List, ListType, Object, ObjectType, Symbol, TagType, NIL,
+            TRUE,
+        },
     },
     data::LispError,
     eval::{add_trace, ErrorType, EvalError, EvalResult},
@@ -16,7 +19,7 @@ use anyhow::Result as AnyResult;
 use anyhow::{bail, ensure};
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
-use rune_core::macros::{bail_err, call, error, rebind, root};
+use rune_core::macros::{bail_err, call, error, list, rebind, root};
 use rune_macros::defun;
 
 struct Interpreter<'brw, 'rt> {
@@ -44,6 +47,110 @@ pub(crate) fn eval<'ob>(
     interpreter.eval_form(form, cx).map_err(Into::into)
 }
 
+/// How [`Interpreter::eval_sexp`] evaluates a special form from its
+/// unevaluated arguments.
+type SpecialForm = for<'brw, 'rt, 'ob> fn(
+    &mut Interpreter<'brw, 'rt>,
+    &Rto<Object>,
+    &'ob mut Context,
+) -> EvalResult<'ob>;
+
+/// The special forms that [`Interpreter::eval_sexp`] evaluates, and how.
+const SPECIAL_FORMS: &[(Symbol, SpecialForm)] = &[
+    (sym::QUOTE, |i, forms, cx| i.quote(forms.bind(cx), cx)),
+    (sym::LET, |i, forms, cx| i.eval_let(forms, true, false, cx)),
+    (sym::LET_STAR, |i, forms, cx| i.eval_let(forms, false, false, cx)),
+    (sym::DLET, |i, forms, cx| i.eval_let(forms, true, true, cx)),
+    (sym::IF, |i, forms, cx| i.eval_if(forms, cx)),
+    (sym::AND, |i, forms, cx| i.eval_and(forms, cx)),
+    (sym::OR, |i, forms, cx| i.eval_or(forms, cx)),
+    (sym::COND, |i, forms, cx| i.eval_cond(forms, cx)),
+    (sym::WHILE, |i, forms, cx| i.eval_while(forms, cx)),
+    (sym::PROGN, |i, forms, cx| i.eval_progn(forms, cx)),
+    (sym::INLINE, |i, forms, cx| i.eval_progn(forms, cx)),
+    (sym::WITH_NO_WARNINGS, |i, forms, cx| i.eval_progn(forms, cx)),
+    (sym::WITH_SUPPRESSED_WARNINGS, |i, forms, cx| {
+        i.with_suppressed_warnings(forms, cx)
+    }),
+    (sym::PROG1, |i, forms, cx| i.eval_progx(forms, 1, cx)),
+    (sym::PROG2, |i, forms, cx| i.eval_progx(forms, 2, cx)),
+    (sym::SETQ, |i, forms, cx| i.setq(forms, cx)),
+    (sym::DEFVAR, |i, forms, cx| i.defvar(forms, cx)),
+    (sym::DEFCONST, |i, forms, cx| i.defvar(forms, cx)),
+    (sym::FUNCTION, |i, forms, cx| i.eval_function(forms, cx)),
+    // TODO: implement
+    (sym::INTERACTIVE, |_, _, _| Ok(NIL)),
+    // only meaningful at the start of a function body
+    (sym::DECLARE, |_, _, _| Ok(NIL)),
+    (sym::CATCH, |i, forms, cx| i.catch(forms, cx)),
+    (sym::THROW, |i, forms, cx| i.throw(forms.bind(cx), cx)),
+    (sym::CONDITION_CASE, |i, forms, cx| i.condition_case(forms, cx)),
+    (sym::IGNORE_ERRORS, |i, forms, cx| i.ignore_errors(forms, cx)),
+    (sym::WITH_DEMOTED_ERRORS, |i, forms, cx| i.with_demoted_errors(forms, cx)),
+    (sym::SAVE_CURRENT_BUFFER, |i, forms, cx| i.save_current_buffer(forms, cx)),
+    (sym::SAVE_EXCURSION, |i, forms, cx| i.save_excursion(forms, cx)),
+    (sym::UNWIND_PROTECT, |i, forms, cx| i.unwind_protect(forms, cx)),
+    (sym::ITER_LAMBDA, |i, forms, cx| i.iter_lambda(forms, cx)),
+    (sym::ITER_DEFUN, |i, forms, cx| i.iter_defun(forms, cx)),
+    (sym::WITH_OUTPUT_TO_STRING, |i, forms, cx| i.with_output_to_string(forms, cx)),
+];
+
+fn special_form(symbol: Symbol) -> Option<SpecialForm> {
+    SPECIAL_FORMS.iter().find(|(x, _)| *x == symbol).map(|(_, form)| *form)
+}
+
+/// Special forms that are also defined as subroutines, so that they can be
+/// called with `funcall'.
+const CALLABLE_SPECIAL_FORMS: &[Symbol] = &[sym::DEFVAR];
+
+/// The largest number of positional arguments a function can take, which is
+/// limited by the 7 bits for it in a bytecode argument spec.
+const MAX_POSITIONAL_ARGS: u16 = 0x7F;
+
+/// Check that every builtin subroutine was registered the way its `#[defun]`
+/// declared it: on a symbol with its name, with an argument count that
+/// matches its Rust function and can be represented, and not on a special form whose definition it would never
+/// reach. This catches mistakes in a `#[defun]` when starting up rather than
+/// when the function is first called. Must be called after
+/// [`sym::init_symbols`].
+pub(crate) fn check_subrs(cx: &Context) -> AnyResult<()> {
+    let mut problems = Vec::new();
+    for (symbol, subr) in sym::subrs() {
+        if symbol.name() != subr.name {
+            problems.push(format!("subr `{}' is defined on symbol `{symbol}'", subr.name));
+        }
+        match symbol.func(cx).map(|x| x.untag()) {
+            Some(FunctionType::SubrFn(func)) if std::ptr::eq(func, subr) => {}
+            _ => problems.push(format!("subr `{}' is not the function of `{symbol}'", subr.name)),
+        }
+        let args = subr.args;
+        if args.required + args.optional != subr.params {
+            problems.push(format!(
+                "subr `{}' declares {} positional arguments but takes {}",
+                subr.name,
+                args.required + args.optional,
+                subr.params
+            ));
+        }
+        if subr.params > MAX_POSITIONAL_ARGS {
+            problems.push(format!(
+                "subr `{}' takes {} arguments, more than the limit of {MAX_POSITIONAL_ARGS}",
+                subr.name, subr.params
+            ));
+        }
+        if args.advice {
+            problems.push(format!("subr `{}' is marked as advised", subr.name));
+        }
+        if special_form(symbol).is_some() && !CALLABLE_SPECIAL_FORMS.contains(&symbol) {
+            problems.push(format!("subr `{}' is shadowed by a special form", subr.name));
+        }
+    }
+    if !problems.is_empty() {
+        bail!("Invalid builtin functions:\n  {}", problems.join("\n  "));
+    }
+    Ok(())
+}
+
 impl Interpreter<'_, '_> {
     fn eval_form<'ob>(&mut self, rt: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
         match rt.untag(cx) {
@@ -65,29 +172,9 @@ impl Interpreter<'_, '_> {
         let forms = cons.cdr();
         root!(forms, cx);
         match cons.car().untag() {
-            ObjectType::Symbol(sym) => match sym {
-                sym::QUOTE => self.quote(forms.bind(cx), cx),
-                sym::LET => self.eval_let(forms, true, cx),
-                sym::LET_STAR => self.eval_let(forms, false, cx),
-                sym::IF => self.eval_if(forms, cx),
-                sym::AND => self.eval_and(forms, cx),
-                sym::OR => self.eval_or(forms, cx),
-                sym::COND => self.eval_cond(forms, cx),
-                sym::WHILE => self.eval_while(forms, cx),
-                sym::PROGN | sym::INLINE => self.eval_progn(forms, cx),
-                sym::PROG1 => self.eval_progx(forms, 1, cx),
-                sym::PROG2 => self.eval_progx(forms, 2, cx),
-                sym::SETQ => self.setq(forms, cx),
-                sym::DEFVAR | sym::DEFCONST => self.defvar(forms, cx),
-                sym::FUNCTION => self.eval_function(forms, cx),
-                sym::INTERACTIVE => Ok(NIL), // TODO: implement
-                sym::CATCH => self.catch(forms, cx),
-                sym::THROW => self.throw(forms.bind(cx), cx),
-                sym::CONDITION_CASE => self.condition_case(forms, cx),
-                sym::SAVE_CURRENT_BUFFER => self.save_current_buffer(forms, cx),
-                sym::SAVE_EXCURSION => self.save_excursion(forms, cx),
-                sym::UNWIND_PROTECT => self.unwind_protect(forms, cx),
-                _ => {
+            ObjectType::Symbol(sym) => match special_form(sym) {
+                Some(form) => form(self, forms, cx),
+                None => {
                     root!(sym, cx);
                     self.eval_call(sym, forms, cx)
                 }
@@ -125,12 +212,14 @@ impl Interpreter<'_, '_> {
 
     fn throw<'ob>(&mut self, obj: Object, cx: &'ob Context) -> EvalResult<'ob> {
         let mut forms = obj.as_list()?;
-        let len = forms.len()? as u16;
+        let len = forms.safe_len() as u16;
         if len != 2 {
             bail_err!(LispError::arg_cnt(sym::THROW, 2, len, cx));
         }
         let tag = forms.next().unwrap()?;
         let value = forms.next().unwrap()?;
+        // signal an error if the forms were dotted or circular
+        forms.rest()?;
         // Need to check now that there is a catch, because we may have a
         // condition-case along the unwind path
         if self.env.catch_stack.iter().any(|x| x.bind(cx) == tag) {
@@ -154,7 +243,7 @@ impl Interpreter<'_, '_> {
             // (defvar x)
             None => NIL,
         };
-        self.env.defvar(name.bind(cx), value)?;
+        self.env.defvar(name.bind(cx), value, cx)?;
         Ok(value)
     }
 
@@ -164,10 +253,16 @@ impl Interpreter<'_, '_> {
         args: &Rto<Object>,
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
-        let Some(func) = sym.bind(cx).follow_indirect(cx) else {
-            bail_err!("Invalid function: {sym}")
+        let func = match sym.bind(cx).follow_indirect(cx) {
+            Some(func) => func,
+            None if sym.bind(cx).is_keyword() => {
+                bail_err!("Keyword {sym} cannot be used as a function")
+            }
+            None => rebind!(crate::eval::undefined_function(sym, self.env, cx)?),
         };
         root!(func, cx);
+        crate::keyboard::maybe_quit(self.env)?;
+        crate::sandbox::check_limits(self.env, cx)?;
 
         match func.bind(cx).as_cons_pair() {
             Ok((sym::AUTOLOAD, _)) => {
@@ -192,7 +287,7 @@ impl Interpreter<'_, '_> {
         }
 
         rooted_iter!(iter, args, cx);
-        root!(args, new(Vec), cx);
+        root!(args, new(ArgBuffer), cx);
         while let Some(x) = iter.next()? {
             let result = self.eval_form(x, cx)?;
             args.push(result);
@@ -209,12 +304,14 @@ impl Interpreter<'_, '_> {
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
         let mut forms = obj.bind(cx).as_list()?;
-        let len = forms.len()? as u16;
+        let len = forms.safe_len() as u16;
         if len != 1 {
             bail_err!(LispError::arg_cnt(sym::FUNCTION, 1, len, cx))
         }
 
         let form = forms.next().unwrap()?;
+        // signal an error if the forms were dotted or circular
+        forms.rest()?;
         root!(form, cx); // Polonius
         let Ok((sym::LAMBDA, doc)) = form.bind(cx).as_cons_pair() else {
             return Ok(form.bind(cx));
@@ -314,6 +411,19 @@ impl Interpreter<'_, '_> {
         self.implicit_progn(forms, cx)
     }
 
+    fn with_suppressed_warnings<'ob>(
+        &mut self,
+        obj: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        rooted_iter!(forms, obj, cx);
+        // The warnings are only meaningful to the byte compiler
+        if forms.next()?.is_none() {
+            bail_err!(LispError::arg_cnt(sym::WITH_SUPPRESSED_WARNINGS, 1, 0, cx))
+        }
+        self.implicit_progn(forms, cx)
+    }
+
     fn eval_while<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
         let (condition, body) = {
             let list: List = obj.bind(cx).try_into()?;
@@ -324,7 +434,12 @@ impl Interpreter<'_, '_> {
         };
         root!(condition, cx);
         root!(body, cx);
-        while self.eval_form(condition, cx)? != NIL {
+        loop {
+            crate::keyboard::maybe_quit(self.env)?;
+            crate::sandbox::check_limits(self.env, cx)?;
+            if self.eval_form(condition, cx)? == NIL {
+                break;
+            }
             rooted_iter!(forms, &*body, cx);
             self.implicit_progn(forms, cx)?;
         }
@@ -373,6 +488,32 @@ impl Interpreter<'_, '_> {
         Ok(NIL)
     }
 
+    /// `iter-lambda` is a macro in generator.el. It is expanded to a function
+    /// that returns a new generator running BODY with the arguments bound.
+    fn iter_lambda<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        let ObjectType::Cons(forms) = obj.bind(cx).untag() else {
+            bail_err!(LispError::arg_cnt(sym::ITER_LAMBDA, 1, 0, cx))
+        };
+        let thunk = Cons::new(sym::LAMBDA, Cons::new(NIL, forms.cdr(), cx), cx);
+        let generator = list![sym::RUNE__MAKE_GENERATOR, list![sym::FUNCTION, thunk; cx]; cx];
+        let lambda = list![sym::LAMBDA, forms.car(), generator; cx];
+        let expansion = list![sym::FUNCTION, lambda; cx];
+        root!(expansion, cx);
+        self.eval_form(expansion, cx)
+    }
+
+    /// `iter-defun` is a macro in generator.el that defines NAME as an
+    /// `iter-lambda`.
+    fn iter_defun<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        let ObjectType::Cons(forms) = obj.bind(cx).untag() else {
+            bail_err!(LispError::arg_cnt(sym::ITER_DEFUN, 2, 0, cx))
+        };
+        let lambda = Cons::new(sym::ITER_LAMBDA, forms.cdr(), cx);
+        let expansion = list![sym::DEFALIAS, list![sym::QUOTE, forms.car(); cx], lambda; cx];
+        root!(expansion, cx);
+        self.eval_form(expansion, cx)
+    }
+
     fn eval_if<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
         rooted_iter!(forms, obj, cx);
         let Some(condition) = forms.next()? else {
@@ -401,8 +542,8 @@ impl Interpreter<'_, '_> {
                     root!(var, cx);
                     root!(val, cx);
                     let val = rebind!(self.eval_form(val, cx)?);
-                    self.var_set(var.bind(cx), val, cx)?;
                     last_value.set(val);
+                    self.var_set(var, last_value, cx)?;
                 }
                 (_, Some(_)) => bail_err!(TypeError::new(Type::Symbol, var)),
                 (_, None) => bail_err!(LispError::arg_cnt(sym::SETQ, arg_cnt, arg_cnt + 1, cx)),
@@ -434,35 +575,55 @@ impl Interpreter<'_, '_> {
                 Some(value) => Ok(value),
                 None => match self.env.vars.get(sym) {
                     Some(v) => Ok(v.bind(cx)),
-                    None => Err(error!("Void variable: {sym}")),
+                    None => Err(LispError::void_variable(sym, cx).into()),
                 },
             }
         }
     }
 
-    fn var_set(&mut self, name: Symbol, new_value: Object, cx: &Context) -> AnyResult<()> {
+    fn var_set(
+        &mut self,
+        name: &Rto<Symbol>,
+        new_value: &Rto<Object>,
+        cx: &mut Context,
+    ) -> AnyResult<()> {
         let mut iter = self.vars.iter().rev();
-        match iter.find(|cons| (cons.car(cx) == name)) {
+        match iter.find(|cons| (cons.car(cx) == name.bind(cx))) {
             Some(value) => {
-                value.bind(cx).set_cdr(new_value).expect("variables should never be immutable");
+                // captured variables of closures that have been made constant
+                // can't be modified
+                value
+                    .bind(cx)
+                    .set_cdr(new_value.bind(cx))
+                    .map_err(|_| LispError::setting_constant(name.bind(cx), cx))?;
                 Ok(())
             }
-            None => self.env.set_var(name, new_value),
+            None => crate::watchers::set(name, new_value, self.env, cx),
         }
     }
 
     fn quote<'ob>(&self, value: Object<'ob>, cx: &Context) -> EvalResult<'ob> {
         let mut forms = value.as_list()?;
-        match forms.len()? {
-            1 => Ok(forms.next().unwrap()?),
+        match forms.safe_len() {
+            1 => {
+                let value = forms.next().unwrap()?;
+                // signal an error if the forms were dotted or circular
+                forms.rest()?;
+                Ok(value)
+            }
             x => Err(LispError::arg_cnt(sym::QUOTE, 1, x as u16, cx).into()),
         }
     }
 
+    /// Evaluate a `let' form, or `let*' if `parallel` is false. If `dynamic`
+    /// is true, as for `dlet', every variable is bound dynamically, even if it
+    /// was never declared with `defvar'. The variables don't become special,
+    /// so other bindings of them are still lexical.
     fn eval_let<'ob>(
         &mut self,
         form: &Rto<Object>,
         parallel: bool,
+        dynamic: bool,
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
         rooted_iter!(iter, form, cx);
@@ -470,18 +631,24 @@ impl Interpreter<'_, '_> {
         // (let x ...)                   // (let)
         let Some(obj) = iter.next()? else { bail_err!(LispError::arg_cnt(sym::LET, 1, 0, cx)) };
         let varbind_count = if parallel {
-            self.let_bind_parallel(obj, cx)
+            self.let_bind_parallel(obj, dynamic, cx)
         } else {
-            self.let_bind_serial(obj, cx)
+            self.let_bind_serial(obj, dynamic, cx)
         }?;
         let obj = rebind!(self.implicit_progn(iter, cx)?);
         // Remove old bindings
         self.vars.truncate(prev_len);
-        self.env.unbind(varbind_count, cx);
-        Ok(obj)
+        root!(obj, cx);
+        crate::watchers::unbind(varbind_count, self.env, cx)?;
+        Ok(obj.bind(cx))
     }
 
-    fn let_bind_serial(&mut self, form: &Rto<Object>, cx: &mut Context) -> Result<u16, EvalError> {
+    fn let_bind_serial(
+        &mut self,
+        form: &Rto<Object>,
+        dynamic: bool,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
         let mut varbind_count = 0;
         rooted_iter!(bindings, form, cx);
         while let Some(binding) = bindings.next()? {
@@ -492,11 +659,15 @@ impl Interpreter<'_, '_> {
                     let val = rebind!(self.let_bind_value(cons, cx)?);
                     let var: Symbol =
                         cons.untag(cx).car().try_into().context("let variable must be a symbol")?;
-                    varbind_count += self.create_let_binding(var, val, cx);
+                    root!(var, cx);
+                    root!(val, cx);
+                    varbind_count += self.create_let_binding(var, val, dynamic, cx)?;
                 }
                 // (let (x))
                 ObjectType::Symbol(sym) => {
-                    varbind_count += self.create_let_binding(sym, NIL, cx);
+                    root!(sym, cx);
+                    root!(val, NIL, cx);
+                    varbind_count += self.create_let_binding(sym, val, dynamic, cx)?;
                 }
                 // (let (1))
                 x => bail_err!(TypeError::new(Type::Cons, x)),
@@ -508,6 +679,7 @@ impl Interpreter<'_, '_> {
     fn let_bind_parallel(
         &mut self,
         form: &Rto<Object>,
+        dynamic: bool,
         cx: &mut Context,
     ) -> Result<u16, EvalError> {
         root!(let_bindings, new(Vec<(Slot<Symbol>, Slot<Object>)>), cx);
@@ -531,20 +703,34 @@ impl Interpreter<'_, '_> {
             }
         }
         let mut sum = 0;
-        for (var, val) in let_bindings.bind_ref(cx) {
-            sum += self.create_let_binding(**var, **val, cx);
+        for i in 0..let_bindings.bind_ref(cx).len() {
+            let (var, val) = &let_bindings.bind_ref(cx)[i];
+            let (var, val) = (**var, **val);
+            root!(var, cx);
+            root!(val, cx);
+            sum += self.create_let_binding(var, val, dynamic, cx)?;
         }
         Ok(sum)
     }
 
-    fn create_let_binding(&mut self, var: Symbol, val: Object, cx: &Context) -> u16 {
-        if var.is_special() {
-            self.env.varbind(var, val, cx);
+    fn create_let_binding(
+        &mut self,
+        var: &Rto<Symbol>,
+        val: &Rto<Object>,
+        dynamic: bool,
+        cx: &mut Context,
+    ) -> Result<u16, EvalError> {
+        let symbol = var.bind(cx);
+        if symbol.is_const() {
+            Err(LispError::setting_constant(symbol, cx).into())
+        } else if dynamic || symbol.is_special() {
+            crate::eval::check_specpdl_size(self.env, cx)?;
+            crate::watchers::varbind(var, val, self.env, cx)?;
             // return 1 if the variable is bound
-            1
+            Ok(1)
         } else {
-            self.vars.push(Cons::new(var, val, cx));
-            0
+            self.vars.push(Cons::new(symbol, val.bind(cx), cx));
+            Ok(0)
         }
     }
 
@@ -574,12 +760,43 @@ impl Interpreter<'_, '_> {
     ) -> EvalResult<'ob> {
         root!(last, NIL, cx);
         while let Some(form) = forms.next()? {
+            crate::keyboard::maybe_quit(self.env)?;
             let value = self.eval_form(form, cx)?;
             last.set(value);
         }
         Ok(last.bind(cx))
     }
 
+    /// Evaluate the body of a closure. A leading docstring and any `declare`
+    /// forms are skipped, unless the docstring is the only form, in which case
+    /// it is the return value.
+    fn closure_body<'ob>(
+        &mut self,
+        mut forms: ElemStreamIter<'_>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        let mut first = true;
+        loop {
+            let Some(form) = forms.next()? else { return Ok(NIL) };
+            let form = form.bind(cx);
+            let skip = match form.untag() {
+                ObjectType::String(_) => first && !forms.is_empty(),
+                ObjectType::Cons(cons) => cons.car() == sym::DECLARE,
+                _ => false,
+            };
+            if !skip {
+                break;
+            }
+            first = false;
+        }
+        let form = forms.get().expect("iterator should not be exhausted");
+        if forms.is_empty() {
+            return self.eval_form(form, cx);
+        }
+        self.eval_form(form, cx)?;
+        self.implicit_progn(forms, cx)
+    }
+
     fn unwind_protect<'ob>(&mut self, obj: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
         rooted_iter!(forms, obj, cx);
         let Some(body) = forms.next()? else {
@@ -603,7 +820,7 @@ impl Interpreter<'_, '_> {
         let buffer = self.env.current_buffer.get().lisp_buffer(cx);
         root!(buffer, cx);
         let result = rebind!(self.eval_progn(form, cx)?);
-        self.env.set_buffer(buffer.bind(cx));
+        self.env.set_buffer(buffer.bind(cx), cx);
         let buf = self.env.current_buffer.get_mut();
         buf.text.set_cursor(point.chars());
         Ok(result)
@@ -617,10 +834,69 @@ impl Interpreter<'_, '_> {
         let buffer = self.env.current_buffer.get().lisp_buffer(cx);
         root!(buffer, cx);
         let result = rebind!(self.eval_progn(form, cx)?);
-        self.env.set_buffer(buffer.bind(cx));
+        self.env.set_buffer(buffer.bind(cx), cx);
         Ok(result)
     }
 
+    fn with_output_to_string<'ob>(
+        &mut self,
+        form: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        let buffer = crate::buffer::create_internal_buffer(" *string-output*", cx);
+        root!(buffer, cx);
+        self.env.varbind(sym::STANDARD_OUTPUT, cx.add(buffer.bind(cx)), cx);
+        let result = self.eval_progn(form, cx).map(|_| ());
+        self.env.unbind(1, cx);
+        let output = self.env.with_buffer_mut(buffer.bind(cx), |buffer| {
+            let output = buffer.text.to_string();
+            buffer.kill();
+            output
+        })?;
+        result?;
+        Ok(cx.add(output))
+    }
+
+    fn ignore_errors<'ob>(&mut self, form: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
+        let err = match self.eval_progn(form, cx) {
+            Ok(x) => return Ok(rebind!(x, cx)),
+            Err(e) => e,
+        };
+        if err.handled_by(sym::ERROR.into(), self.env, cx)? {
+            Ok(NIL)
+        } else {
+            Err(err)
+        }
+    }
+
+    fn with_demoted_errors<'ob>(
+        &mut self,
+        form: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        // Without a body the format is the only form
+        let (format, body) = match form.untag(cx) {
+            ObjectType::Cons(cons) if !cons.cdr().is_nil() => match cons.car().untag() {
+                ObjectType::String(format) => (format.to_string(), cons.cdr()),
+                _ => ("Error: %S".to_owned(), form.bind(cx)),
+            },
+            _ => ("Error: %S".to_owned(), form.bind(cx)),
+        };
+        root!(body, cx);
+        let err = match self.eval_progn(body, cx) {
+            Ok(x) => return Ok(rebind!(x, cx)),
+            Err(e) => e,
+        };
+        let debug = self.env.vars.get(sym::DEBUG_ON_ERROR).is_some_and(|x| !x.bind(cx).is_nil());
+        if debug || !err.handled_by(sym::ERROR.into(), self.env, cx)? {
+            return Err(err);
+        }
+        let error = err.to_lisp(self.env, cx);
+        let message = crate::editfns::format_message(&format, &[error.into()])?;
+        crate::editfns::show_message(&message, self.env, cx)?;
+        Ok(NIL)
+    }
+
     fn condition_case<'ob>(&mut self, form: &Rto<Object>, cx: &'ob mut Context) -> EvalResult<'ob> {
         rooted_iter!(forms, form, cx);
         let Some(var) = forms.next()? else {
@@ -640,45 +916,12 @@ impl Interpreter<'_, '_> {
         while let Some(handler) = forms.next()? {
             match handler.untag(cx) {
                 ObjectType::Cons(cons) => {
-                    // Check that conditions match
-                    let condition = cons.car();
-                    match condition.untag() {
-                        ObjectType::Symbol(sym::ERROR | sym::VOID_VARIABLE) => {}
-                        // TODO: Remove this once error handling is correctly implemented
-                        ObjectType::Symbol(s) if s.name() == "cl--generic-cyclic-definition" => {}
-                        ObjectType::Cons(conditions) => {
-                            for condition in conditions {
-                                let condition = condition?;
-                                // TODO: Handle different error symbols
-                                if condition != sym::DEBUG && condition != sym::ERROR {
-                                    bail_err!("non-error conditions {condition} not yet supported")
-                                }
-                            }
-                        }
-                        _ => bail_err!("Invalid condition handler: {condition}"),
+                    if !err.handled_by(cons.car(), self.env, cx)? {
+                        continue;
                     }
 
                     // Call handlers with error
-                    let error = match err.error {
-                        ErrorType::Signal(id) => {
-                            let Some((sym, data)) = self.env.get_exception(id) else {
-                                unreachable!("Exception not found")
-                            };
-                            Cons::new(sym, data, cx)
-                        }
-                        ErrorType::Err(err) => {
-                            let err_str = format!("{err}");
-                            if let Ok(lisp_error) = err.downcast::<LispError>() {
-                                lisp_error.bind(cx)
-                            } else {
-                                // TODO: Need to remove the anyhow branch once
-                                // full errors are implemented
-                                Cons::new(sym::ERROR, err_str, cx)
-                            }
-                        }
-                        _ => unreachable!("Error type throw was not handled"),
-                    };
-
+                    let error = err.to_lisp(self.env, cx);
                     let binding = Cons::new(var, error, cx);
                     self.vars.push(binding);
                     let list: List = match cons.cdr().try_into() {
@@ -714,7 +957,7 @@ pub(crate) fn call_closure<'ob>(
             let vars = bind_variables(&mut forms, args, name, cx)?;
             debug!("call vars: {vars:?}");
             root!(vars, cx);
-            Interpreter { vars, env }.implicit_progn(forms, cx)
+            Interpreter { vars, env }.closure_body(forms, cx)
         }
         other => Err(TypeError::new(Type::Func, other).into()),
     }
@@ -830,10 +1073,55 @@ pub(crate) fn parse_arg_list(
 
 #[cfg(test)]
 pub(crate) fn assert_lisp(compare: &str, expect: &str) {
+    check_lisp(compare, expect, false);
+}
+
+/// Like `assert_lisp`, but with the variables defined with `defvar!`
+/// initialized to their default values.
+#[cfg(test)]
+pub(crate) fn assert_lisp_with_vars(compare: &str, expect: &str) {
+    check_lisp(compare, expect, true);
+}
+
+/// Call `f` with a new environment, with the variables defined with
+/// `defvar!` initialized to their default values.
+#[cfg(test)]
+pub(crate) fn with_env<T>(f: impl FnOnce(&mut Rt<Env>, &mut Context) -> T) -> T {
     let roots = &crate::core::gc::RootSet::default();
     let cx = &mut Context::new(roots);
     sym::init_symbols();
     root!(env, new(Env), cx);
+    crate::core::env::init_variables(cx, env);
+    f(env, cx)
+}
+
+/// Evaluate `form` in a new buffer that contains `text`, with point at the
+/// end. Return the printed value, the text of the buffer and point.
+#[cfg(test)]
+pub(crate) fn eval_in_buffer(text: &str, form: &str) -> (String, String, usize) {
+    with_env(|env, cx| {
+        let name = crate::buffer::generate_new_buffer_name("test", None);
+        let buffer = crate::buffer::get_buffer_create(cx.add(name), None, cx).unwrap();
+        crate::buffer::set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.get_mut().text.insert(text);
+        println!("Test String: {form}");
+        let form = crate::reader::read(form, cx).unwrap().0;
+        root!(form, cx);
+        let value = eval(form, None, env, cx).unwrap().to_string();
+        let text = &env.current_buffer.get().text;
+        (value, text.to_string(), text.cursor().chars())
+    })
+}
+
+#[cfg(test)]
+fn check_lisp(compare: &str, expect: &str, init_vars: bool) {
+    let roots = &crate::core::gc::RootSet::default();
+    let cx = &mut Context::new(roots);
+    sym::init_symbols();
+    root!(env, new(Env), cx);
+    if init_vars {
+        crate::core::env::init_variables(cx, env);
+    }
     println!("Test String: {compare}");
     let compare = {
         let obj = crate::reader::read(compare, cx).unwrap().0;
@@ -935,6 +1223,58 @@ mod test {
         );
     }
 
+    #[test]
+    fn improper_forms() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(condition-case err (progn 1 . 2) (error (equal err '(wrong-type-argument listp 2))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(let ((x (list 1 2))) (setcdr (cdr x) x) (condition-case err (eval (cons 'progn x)) (error (car err))))",
+            sym::CIRCULAR_LIST,
+            cx,
+        );
+        check_interpreter(
+            "(let ((x (list 1))) (setcdr x x) (condition-case err (eval (cons 'quote x)) (error (car err))))",
+            sym::CIRCULAR_LIST,
+            cx,
+        );
+        check_error("(quote 1 . 2)", cx);
+    }
+
+    #[test]
+    fn dlet() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(let ((fn #'(lambda () dlet_test1))) (dlet ((dlet_test1 5)) (funcall fn)))",
+            5,
+            cx,
+        );
+        check_interpreter(
+            "(progn (dlet ((dlet_test2 1)) dlet_test2) (boundp 'dlet_test2))",
+            false,
+            cx,
+        );
+        check_interpreter("(dlet (dlet_test3) dlet_test3)", false, cx);
+        // the variable is only dynamic in the dlet
+        check_interpreter(
+            "(progn (dlet ((dlet_test4 1)) nil)
+                    (let ((dlet_test4 2))
+                      (let ((f (lambda () dlet_test4)))
+                        (let ((dlet_test4 3)) (funcall f)))))",
+            2,
+            cx,
+        );
+        check_interpreter("(with-no-warnings 1 2)", 2, cx);
+        check_interpreter("(with-suppressed-warnings ((obsolete foo)) 1 3)", 3, cx);
+        check_error("(dlet)", cx);
+        check_error("(with-suppressed-warnings)", cx);
+    }
+
     #[test]
     fn conditionals() {
         let roots = &RootSet::default();
@@ -987,6 +1327,14 @@ mod test {
         check_interpreter("(if (and 1 nil) 2 3)", 3, cx);
     }
 
+    #[test]
+    fn test_check_subrs() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        sym::init_symbols();
+        check_subrs(cx).unwrap();
+    }
+
     #[test]
     fn test_functions() {
         let roots = &RootSet::default();
@@ -1050,6 +1398,15 @@ mod test {
             10,
             cx,
         );
+        // Cached functions are invalidated when any function in an alias chain changes
+        check_interpreter(
+            "(progn (fset 'int-test-target #'(lambda () 1)) (defalias 'int-test-alias 'int-test-target)
+                    (let ((x (int-test-alias)))
+                      (fset 'int-test-target #'(lambda () 2))
+                      (+ (* x 10) (int-test-alias))))",
+            12,
+            cx,
+        );
         // Test closures
         check_interpreter("(let* ((y 7)(x #'(lambda () y))) (funcall x))", 7, cx);
         check_interpreter("(let* ((y 7)(x #'(lambda (x) (+ x y)))) (funcall x 3))", 10, cx);
@@ -1079,6 +1436,11 @@ mod test {
             cx,
         );
 
+        // docstrings and declare forms
+        check_interpreter("(funcall #'(lambda (x) \"doc\" (declare (indent 1)) (1+ x)) 3)", 4, cx);
+        check_interpreter("(funcall #'(lambda () \"doc\"))", "doc", cx);
+        check_interpreter("(funcall #'(lambda () \"doc\" \"value\"))", "value", cx);
+
         // takes 1 arg
         check_error("(1+)", cx);
         check_error("(/)", cx);
@@ -1101,11 +1463,212 @@ mod test {
             2,
             cx,
         );
+        check_interpreter(
+            "(condition-case e (int-test-undefined 1) (error (equal e '(void-function int-test-undefined))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(let ((undefined-function-functions (list #'(lambda (s) (if (eq s 'int-test-missing) #'1+))))) (int-test-missing 2))",
+            3,
+            cx,
+        );
         check_error("(condition-case nil (if))", cx);
         check_error("(condition-case nil (if) nil)", cx);
         check_error("(condition-case nil (if) 5 (error 7))", cx);
     }
 
+    #[test]
+    fn test_error_hierarchy() {
+        assert_lisp_with_vars("(condition-case nil (/ 1 0) (arith-error 'caught))", "caught");
+        assert_lisp_with_vars(
+            "(condition-case e (truncate 1.0e+INF) ((file-error range-error) (car e)))",
+            "overflow-error",
+        );
+        assert_lisp_with_vars(
+            "(condition-case nil (condition-case nil (/ 1 0) (file-error 1)) (error 2))",
+            "2",
+        );
+        assert_lisp_with_vars(
+            "(progn (put 'int-test-error 'error-conditions '(int-test-error arith-error error)) (condition-case e (signal 'int-test-error '(1)) (arith-error e)))",
+            "(int-test-error 1)",
+        );
+        // quit is not an error
+        assert_lisp_with_vars(
+            "(condition-case nil (condition-case nil (signal 'quit nil) (error 'error)) (quit 'quit))",
+            "quit",
+        );
+        assert_lisp_with_vars("(condition-case nil (signal 'quit nil) (t 'any))", "any");
+        assert_lisp_with_vars(
+            "(list (condition-case e int-test-unbound (void-variable e))
+                   (condition-case e (default-value 'int-test-unbound) (error (car e))))",
+            "((void-variable int-test-unbound) void-variable)",
+        );
+        assert_lisp_with_vars(
+            "(list (condition-case e (1+ nil) (wrong-type-argument e))
+                   (condition-case e (car 1) (error (car e))))",
+            "((wrong-type-argument numberp nil) wrong-type-argument)",
+        );
+        assert_lisp_with_vars(
+            "(get 'overflow-error 'error-conditions)",
+            "(overflow-error range-error arith-error error)",
+        );
+        assert_lisp_with_vars(
+            "(error-message-string '(args-out-of-range [1] 5))",
+            "\"Args out of range: [1], 5\"",
+        );
+        assert_lisp_with_vars("(error-message-string '(error \"Boom\" 1))", "\"Boom: 1\"");
+        assert_lisp_with_vars("(error-message-string '(user-error \"Oops\"))", "\"Oops\"");
+    }
+
+    #[test]
+    fn test_ignore_errors() {
+        assert_lisp("(ignore-errors (car 1) 2)", "nil");
+        assert_lisp("(ignore-errors 1 2)", "2");
+        assert_lisp("(ignore-errors)", "nil");
+        assert_lisp("(catch 'done (ignore-errors (throw 'done 3)))", "3");
+        assert_lisp("(condition-case nil (ignore-errors (signal 'quit nil)) (quit 'quit))", "quit");
+        assert_lisp("(with-demoted-errors \"Error: %S\" (/ 1 0))", "nil");
+        assert_lisp("(with-demoted-errors \"Error: %S\" 1 2)", "2");
+        assert_lisp("(with-demoted-errors (+ 1 2))", "3");
+        assert_lisp("(with-demoted-errors \"only\")", "\"only\"");
+        assert_lisp_with_vars(
+            "(let ((debug-on-error t)) (condition-case nil (with-demoted-errors \"%S\" (/ 1 0)) (arith-error 'raised)))",
+            "raised",
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (defalias 'int-test-recurse #'(lambda (x) (int-test-recurse (1+ x)))) (condition-case nil (let ((max-lisp-eval-depth 50)) (int-test-recurse 0)) (error 7)))",
+            7,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar int-test-dyn nil) (condition-case nil (let ((max-specpdl-size 2)) (let ((int-test-dyn 1)) (let ((int-test-dyn 2)) 3))) (error 7)))",
+            7,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar heap-size-limit nil) (condition-case nil (let ((heap-size-limit 100000) (x nil)) (while t (setq x (cons (make-vector 100 nil) x)))) (memory-exhausted 7)))",
+            7,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_set_constant() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_error("(setq nil 5)", cx);
+        check_error("(setq t 5)", cx);
+        check_error("(setq :foo 5)", cx);
+        check_error("(let ((nil 5)) nil)", cx);
+        check_error("(let* ((t 5)) t)", cx);
+        check_error("(defvar :foo 5)", cx);
+        check_interpreter(
+            "(car (condition-case err (setq t 5) (error err)))",
+            sym::SETTING_CONSTANT,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_error() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(condition-case err (error \"Bad %s: %d\" 'foo 5) (error (car (cdr err))))",
+            "Bad foo: 5",
+            cx,
+        );
+        check_interpreter("(condition-case err (error \"x\") (error (car err)))", sym::ERROR, cx);
+        check_interpreter(
+            "(condition-case err (user-error \"y\") (error (car err)))",
+            sym::USER_ERROR,
+            cx,
+        );
+        check_error("(error \"%s\")", cx);
+    }
+
+    #[test]
+    fn test_cl_assertion_failed() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(condition-case err (cl--assertion-failed '(eq 1 2))
+               (error (equal err '(cl-assertion-failed (eq 1 2)))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(condition-case err (cl--assertion-failed '(< x 1) nil (list 3) nil)
+               (error (equal err '(cl-assertion-failed (< x 1) 3))))",
+            true,
+            cx,
+        );
+        check_interpreter(
+            "(condition-case err (cl--assertion-failed nil \"bad %s\" nil (list 'foo))
+               (error (car (cdr err))))",
+            "bad foo",
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_output_streams() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(with-output-to-string (princ \"a\") (prin1 \"b\") (princ 1) (terpri))",
+            "a\"b\"1\n",
+            cx,
+        );
+        check_interpreter("(with-output-to-string (print 'x) (write-char ?y))", "\nx\ny", cx);
+        check_interpreter(
+            "(let ((chars nil))
+               (princ \"ab\" (lambda (c) (setq chars (cons c chars))))
+               chars)",
+            list![98, 97; cx],
+            cx,
+        );
+        check_interpreter("(with-output-to-string (princ \"x\" (lambda (c) nil)))", "", cx);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let kw = intern(":kw-test", cx);
+        check_interpreter(":kw-test", kw, cx);
+        check_interpreter("(symbol-value :kw-test)", kw, cx);
+        check_interpreter("(keywordp :kw-test)", true, cx);
+        check_interpreter("(keywordp (make-symbol \":kw-test\"))", false, cx);
+        check_interpreter("(keywordp 'kw-test)", false, cx);
+        check_error("(let ((:kw-test 1)) 1)", cx);
+        check_error("(:kw-test 1)", cx);
+    }
+
+    #[test]
+    fn test_quit() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(condition-case nil (progn (setq quit-flag t) (while t)) (quit 7))",
+            7,
+            cx,
+        );
+        check_interpreter(
+            "(condition-case nil (let ((inhibit-quit t)) (setq quit-flag t) (while nil) 3) (quit 7))",
+            3,
+            cx,
+        );
+        check_error("(condition-case nil (progn (setq quit-flag t) (while t)) (error 7))", cx);
+    }
+
     #[test]
     fn test_throw_catch() {
         let roots = &RootSet::default();
@@ -1122,4 +1685,21 @@ mod test {
         check_error("(throw 1 2)", cx);
         check_error("(catch 2 (throw 3 4))", cx);
     }
+
+    /// Calls that fit in an [`ArgBuffer`] and calls that spill out of it.
+    #[test]
+    fn test_funcall_arg_buffer() {
+        for count in [2, 8, 10] {
+            let params: Vec<_> = (0..count).map(|i| format!("a{i}")).collect();
+            let args: Vec<_> = (0..count).map(|i| i.to_string()).collect();
+            let last = count - 1;
+            let form = format!(
+                "(progn (fset 'arg-buffer-fn (lambda ({}) (list a0 a{last})))
+                        (arg-buffer-fn {}))",
+                params.join(" "),
+                args.join(" ")
+            );
+            assert_lisp(&form, &format!("(0 {last})"));
+        }
+    }
 }
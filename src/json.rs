@@ -0,0 +1,259 @@
+//! JSON serialization.
+//!
+//! JSON objects are hash tables, alists or plists, and arrays are vectors or
+//! lists. `null` and `false` have no natural lisp value, so they are
+//! represented by the `:null-object' and `:false-object' arguments, which
+//! default to `:null' and `:false'.
+use crate::arith::NumberValue;
+use crate::core::{
+    cons::Cons,
+    env::{intern, sym},
+    gc::Context,
+    object::{Gc, HashTable, LispHashTable, Object, ObjectType, Symbol, TRUE},
+};
+use crate::fns::slice_into_list;
+use anyhow::{anyhow, bail, Result};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use rune_macros::defun;
+use serde_json::{Map, Value};
+
+/// The representation of JSON values in lisp.
+#[derive(Clone, Copy)]
+pub(crate) struct JsonOptions<'ob> {
+    pub(crate) object_type: Symbol<'ob>,
+    pub(crate) array_type: Symbol<'ob>,
+    pub(crate) null: Object<'ob>,
+    pub(crate) false_: Object<'ob>,
+}
+
+impl<'ob> Default for JsonOptions<'ob> {
+    fn default() -> Self {
+        Self {
+            object_type: sym::HASH_TABLE,
+            array_type: sym::ARRAY,
+            null: sym::KW_NULL.into(),
+            false_: sym::KW_FALSE.into(),
+        }
+    }
+}
+
+impl<'ob> JsonOptions<'ob> {
+    /// Parse the keyword arguments of the JSON functions.
+    pub(crate) fn new(args: &[Object<'ob>]) -> Result<Self> {
+        let mut options = Self::default();
+        for pair in args.chunks(2) {
+            let [key, value] = *pair else { bail!("Missing keyword value for {}", pair[0]) };
+            let ObjectType::Symbol(key) = key.untag() else { bail!("Invalid keyword: {key}") };
+            match key {
+                sym::KW_OBJECT_TYPE => match value.untag() {
+                    ObjectType::Symbol(x @ (sym::HASH_TABLE | sym::ALIST | sym::PLIST)) => {
+                        options.object_type = x
+                    }
+                    _ => bail!("Invalid :object-type {value}"),
+                },
+                sym::KW_ARRAY_TYPE => match value.untag() {
+                    ObjectType::Symbol(x @ (sym::ARRAY | sym::LIST)) => options.array_type = x,
+                    _ => bail!("Invalid :array-type {value}"),
+                },
+                sym::KW_NULL_OBJECT => options.null = value,
+                sym::KW_FALSE_OBJECT => options.false_ = value,
+                _ => bail!("Invalid keyword: {key}"),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Return the name of the JSON object key `key`, without the colon of a
+/// keyword.
+fn object_key(key: Object) -> Result<String> {
+    match key.untag() {
+        ObjectType::String(x) => Ok(x.to_string()),
+        ObjectType::Symbol(x) => Ok(x.name().strip_prefix(':').unwrap_or(x.name()).to_owned()),
+        _ => bail!("JSON object keys must be strings or symbols: {key}"),
+    }
+}
+
+/// Convert `obj` to a JSON value. The first value of a duplicated key in an
+/// alist or plist is used, like Emacs.
+pub(crate) fn to_json(obj: Object, options: &JsonOptions) -> Result<Value> {
+    if obj == options.null {
+        return Ok(Value::Null);
+    }
+    if obj == options.false_ {
+        return Ok(Value::Bool(false));
+    }
+    let value = match obj.untag() {
+        ObjectType::NIL => Value::Object(Map::new()),
+        ObjectType::TRUE => Value::Bool(true),
+        ObjectType::Int(x) => x.into(),
+        ObjectType::Float(x) => serde_json::Number::from_f64(**x)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("Cannot serialize {obj} to JSON"))?,
+        ObjectType::BigInt(x) => {
+            let x: BigInt = x.get();
+            match (x.to_i64(), x.to_u64()) {
+                (Some(x), _) => x.into(),
+                (_, Some(x)) => x.into(),
+                _ => bail!("Number out of range for JSON: {obj}"),
+            }
+        }
+        ObjectType::String(x) => Value::String(x.to_string()),
+        ObjectType::Vec(vec) => {
+            Value::Array(vec.iter().map(|x| to_json(x.get(), options)).collect::<Result<_>>()?)
+        }
+        ObjectType::HashTable(table) => {
+            let mut map = Map::new();
+            for i in 0..table.len() {
+                let Some((key, value)) = table.get_index(i) else { continue };
+                map.insert(object_key(key)?, to_json(value, options)?);
+            }
+            Value::Object(map)
+        }
+        ObjectType::Cons(cons) => {
+            let mut map = Map::new();
+            let is_plist = matches!(cons.car().untag(), ObjectType::Symbol(x) if x.is_keyword());
+            if is_plist {
+                let elements: Vec<_> = cons.elements().collect::<Result<_>>()?;
+                for pair in elements.chunks(2) {
+                    let [key, value] = *pair else { bail!("Invalid plist: {obj}") };
+                    let key = object_key(key)?;
+                    if !map.contains_key(&key) {
+                        map.insert(key, to_json(value, options)?);
+                    }
+                }
+            } else {
+                for entry in cons.elements() {
+                    let ObjectType::Cons(entry) = entry?.untag() else {
+                        bail!("Invalid alist: {obj}")
+                    };
+                    let key = object_key(entry.car())?;
+                    if !map.contains_key(&key) {
+                        map.insert(key, to_json(entry.cdr(), options)?);
+                    }
+                }
+            }
+            Value::Object(map)
+        }
+        _ => bail!("Cannot serialize {obj} to JSON"),
+    };
+    Ok(value)
+}
+
+/// Convert the JSON `value` to a lisp object.
+pub(crate) fn from_json<'ob>(
+    value: &Value,
+    options: &JsonOptions<'ob>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    match value {
+        Value::Null => options.null,
+        Value::Bool(false) => options.false_,
+        Value::Bool(true) => TRUE,
+        Value::Number(x) => match (x.as_i64(), x.as_u64()) {
+            (Some(x), _) => cx.add(NumberValue::Int(x)),
+            (_, Some(x)) => cx.add(NumberValue::Big(x.into())),
+            _ => cx.add(x.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(x) => cx.add(x.as_str()),
+        Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(|x| from_json(x, options, cx)).collect();
+            match options.array_type {
+                sym::LIST => slice_into_list(&values, None, cx),
+                _ => cx.add(values),
+            }
+        }
+        Value::Object(map) => match options.object_type {
+            sym::ALIST => {
+                let pairs: Vec<_> = map
+                    .iter()
+                    .map(|(k, v)| Cons::new(intern(k, cx), from_json(v, options, cx), cx).into())
+                    .collect();
+                slice_into_list(&pairs, None, cx)
+            }
+            sym::PLIST => {
+                let mut flat = Vec::with_capacity(map.len() * 2);
+                for (k, v) in map {
+                    flat.push(intern(&format!(":{k}"), cx).into());
+                    flat.push(from_json(v, options, cx));
+                }
+                slice_into_list(&flat, None, cx)
+            }
+            _ => {
+                let table: Gc<&LispHashTable> =
+                    cx.add_as(HashTable::with_hasher(std::hash::BuildHasherDefault::default()));
+                for (k, v) in map {
+                    table.untag().insert(cx.add(k.as_str()), from_json(v, options, cx));
+                }
+                table.into()
+            }
+        },
+    }
+}
+
+/// Return the JSON representation of OBJECT as a string. ARGS are the
+/// keyword arguments `:null-object' and `:false-object', which give the
+/// values that are serialized as `null' and `false'.
+#[defun]
+fn json_serialize(object: Object, args: &[Object]) -> Result<String> {
+    let options = JsonOptions::new(args)?;
+    Ok(to_json(object, &options)?.to_string())
+}
+
+/// Parse the JSON in STRING. ARGS are keyword arguments: `:object-type' is
+/// one of `hash-table' (the default), `alist' or `plist', `:array-type' is
+/// `array' (the default) or `list', and `:null-object' and `:false-object'
+/// are the values to use for `null' and `false'.
+#[defun]
+fn json_parse_string<'ob>(
+    string: &str,
+    args: &[Object<'ob>],
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let options = JsonOptions::new(args)?;
+    let value: Value =
+        serde_json::from_str(string).map_err(|e| anyhow!("JSON parse error: {e}"))?;
+    Ok(from_json(&value, &options, cx))
+}
+
+defsym!(ARRAY);
+defsym!(KW_NULL);
+defsym!(KW_FALSE);
+defsym!(KW_OBJECT_TYPE);
+defsym!(KW_ARRAY_TYPE);
+defsym!(KW_NULL_OBJECT);
+defsym!(KW_FALSE_OBJECT);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_json_serialize() {
+        assert_lisp(
+            "(json-serialize [1 2.5 \"a\" t :false :null])",
+            "\"[1,2.5,\\\"a\\\",true,false,null]\"",
+        );
+        assert_lisp(
+            "(json-serialize '(:a 1 :b (:c [])))",
+            "\"{\\\"a\\\":1,\\\"b\\\":{\\\"c\\\":[]}}\"",
+        );
+        assert_lisp("(json-serialize '((a . 1) (a . 2)))", "\"{\\\"a\\\":1}\"");
+        assert_lisp(
+            "(json-serialize '(:a nil :b :json-false) :null-object nil :false-object :json-false)",
+            "\"{\\\"a\\\":null,\\\"b\\\":false}\"",
+        );
+    }
+
+    #[test]
+    fn test_json_parse_string() {
+        assert_lisp("(json-parse-string \"[1, null, false]\")", "[1 :null :false]");
+        assert_lisp(
+            "(json-parse-string \"{\\\"a\\\": [true], \\\"b\\\": {}}\" :object-type 'plist :array-type 'list)",
+            "(:a (t) :b nil)",
+        );
+        assert_lisp("(json-parse-string \"{\\\"a\\\": 1}\" :object-type 'alist)", "((a . 1))");
+        assert_lisp("(gethash \"a\" (json-parse-string \"{\\\"a\\\": 1}\"))", "1");
+    }
+}
@@ -0,0 +1,501 @@
+//! JSON-RPC connections for language server clients.
+//!
+//! `jsonrpc-connect' starts a server process and talks to it over its
+//! standard input and output with the base protocol of the Language Server
+//! Protocol, where each message is a JSON object preceded by a
+//! `Content-Length' header. Messages are read on a background thread. The
+//! requests and notifications sent by the server are dispatched to lisp
+//! handlers on the main thread, while `jsonrpc-request' waits for its
+//! response or when `jsonrpc-process-pending' is called. Like jsonrpc.el,
+//! JSON objects are plists, `null' is nil and `false' is `:json-false'.
+use crate::core::{
+    cons::Cons,
+    env::{intern, sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, ObjectType, RecordBuilder, NIL},
+};
+use crate::eval::EvalError;
+use crate::json::{from_json, to_json, JsonOptions};
+use crate::permissions::{self, Capability};
+use anyhow::{bail, Result};
+use rune_core::macros::{call, list, root};
+use rune_macros::defun;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// The writer and message queue are shared so that they can be used without
+/// holding the table of connections, which would block every other
+/// connection while waiting on one.
+struct Connection {
+    name: String,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    messages: Arc<Mutex<Receiver<Value>>>,
+    child: Option<Child>,
+    next_request: i64,
+}
+
+static CONNECTIONS: LazyLock<Mutex<HashMap<i64, Connection>>> = LazyLock::new(Default::default);
+static NEXT_CONNECTION: AtomicI64 = AtomicI64::new(0);
+
+fn options() -> JsonOptions<'static> {
+    JsonOptions {
+        object_type: sym::PLIST,
+        array_type: sym::ARRAY,
+        null: NIL,
+        false_: sym::KW_JSON_FALSE.into(),
+    }
+}
+
+/// Read the body of the next message from `reader`, or `None` at the end of
+/// the stream.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            // The headers end with an empty line
+            match length {
+                Some(_) => break,
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                let value = value.trim().parse().map_err(io::Error::other)?;
+                length = Some(value);
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// Register a connection that reads messages from `reader` and sends them to
+/// `writer`, and return its id.
+fn add_connection(
+    name: String,
+    reader: impl Read + Send + 'static,
+    writer: impl Write + Send + 'static,
+    child: Option<Child>,
+) -> i64 {
+    let (sender, messages) = channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Ok(Some(body)) = read_message(&mut reader) {
+            // A message that is not valid JSON is dropped, but the framing is
+            // intact so the connection can carry on
+            let Ok(message) = serde_json::from_slice(&body) else { continue };
+            if sender.send(message).is_err() {
+                return;
+            }
+        }
+    });
+    let id = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+    let writer: Box<dyn Write + Send> = Box::new(writer);
+    let connection = Connection {
+        name,
+        writer: Arc::new(Mutex::new(writer)),
+        messages: Arc::new(Mutex::new(messages)),
+        child,
+        next_request: 0,
+    };
+    CONNECTIONS.lock().unwrap().insert(id, connection);
+    id
+}
+
+fn connection_object<'ob>(
+    id: i64,
+    request_dispatcher: Object<'ob>,
+    notification_dispatcher: Object<'ob>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let mut record = cx.vec_with_capacity(4);
+    record.push(sym::JSONRPC_CONNECTION.into());
+    record.push(cx.add(id));
+    record.push(request_dispatcher);
+    record.push(notification_dispatcher);
+    cx.add(RecordBuilder(record))
+}
+
+/// Return the id of `connection` and its request and notification dispatchers.
+fn connection_parts(connection: Object) -> Result<(i64, [Object; 2])> {
+    if let ObjectType::Record(record) = connection.untag() {
+        if let [tag, id, requests, notifications] = &record[..] {
+            if let (ObjectType::Symbol(sym::JSONRPC_CONNECTION), ObjectType::Int(id)) =
+                (tag.get().untag(), id.get().untag())
+            {
+                return Ok((id, [requests.get(), notifications.get()]));
+            }
+        }
+    }
+    bail!("Not a jsonrpc connection: {connection}")
+}
+
+fn connection_id(connection: Object) -> Result<i64> {
+    Ok(connection_parts(connection)?.0)
+}
+
+fn send(id: i64, message: &Value) -> Result<()> {
+    let (name, writer) = match CONNECTIONS.lock().unwrap().get(&id) {
+        Some(connection) => (connection.name.clone(), Arc::clone(&connection.writer)),
+        None => bail!("jsonrpc connection is closed"),
+    };
+    let mut message = message.clone();
+    message["jsonrpc"] = "2.0".into();
+    if let Err(e) = write_message(&mut *writer.lock().unwrap(), &message) {
+        bail!("Cannot send to {name}: {e}");
+    }
+    Ok(())
+}
+
+/// Wait up to `timeout` for the next message from connection `id`.
+fn next_message(id: i64, timeout: Duration) -> Result<Result<Value, RecvTimeoutError>> {
+    let messages = match CONNECTIONS.lock().unwrap().get(&id) {
+        Some(connection) => Arc::clone(&connection.messages),
+        None => bail!("jsonrpc connection is closed"),
+    };
+    let result = messages.lock().unwrap().recv_timeout(timeout);
+    Ok(result)
+}
+
+fn method_name(method: Object) -> Result<String> {
+    match method.untag() {
+        ObjectType::String(x) => Ok(x.to_string()),
+        ObjectType::Symbol(x) => Ok(x.name().strip_prefix(':').unwrap_or(x.name()).to_owned()),
+        _ => bail!("Invalid jsonrpc method: {method}"),
+    }
+}
+
+fn params_json(params: Object) -> Result<Value> {
+    match params.untag() {
+        // Unlike other JSON values, empty parameters are an object and not null
+        ObjectType::NIL => Ok(json!({})),
+        _ => to_json(params, &options()),
+    }
+}
+
+/// Dispatch a request or notification from the server to the lisp handler
+/// of `connection`, and reply to requests with the handler's result.
+fn dispatch(
+    connection: &Rto<Object>,
+    message: &Value,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    // responses to requests that have timed out are dropped
+    let Some(method) = message.get("method").and_then(Value::as_str) else { return Ok(()) };
+    let request_id = message.get("id").cloned();
+    let (id, [requests, notifications]) = connection_parts(connection.bind(cx))?;
+    let handler = if request_id.is_some() { requests } else { notifications };
+    if handler.is_nil() {
+        if let Some(request_id) = request_id {
+            let error = json!({"code": METHOD_NOT_FOUND, "message": "Method not found"});
+            send(id, &json!({"id": request_id, "error": error}))?;
+        }
+        return Ok(());
+    }
+    root!(handler, cx);
+    let function: &Rto<Function> = handler.try_as()?;
+    let name = method;
+    let method: Object = intern(name, cx).into();
+    let params = from_json(message.get("params").unwrap_or(&Value::Null), &options(), cx);
+    let arg = connection.bind(cx);
+    let result = call!(function, arg, method, params; env, cx);
+    let result = result.and_then(|x| Ok(to_json(x, &options())?));
+    match (request_id, result) {
+        (Some(request_id), Ok(result)) => send(id, &json!({"id": request_id, "result": result})),
+        (Some(request_id), Err(e)) => {
+            let message = match e.downcast_ref::<EvalError>() {
+                Some(e) => e.describe(env, cx),
+                None => e.to_string(),
+            };
+            let error = json!({"code": INTERNAL_ERROR, "message": message});
+            send(id, &json!({"id": request_id, "error": error}))
+        }
+        (None, result) => {
+            // Like jsonrpc.el, an error in a notification handler is reported
+            // and not passed on to whatever was waiting for messages
+            if let Err(e) = result {
+                tracing::warn!("Error handling notification {name}: {e}");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Signal a `jsonrpc-error' for the error object of a response.
+fn response_error(error: &Value, env: &mut Rt<Env>, cx: &Context) -> anyhow::Error {
+    let code = error.get("code").and_then(Value::as_i64).unwrap_or(INTERNAL_ERROR);
+    let message = error.get("message").and_then(Value::as_str).unwrap_or("Unknown error");
+    let code: Object = Cons::new(sym::JSONRPC_ERROR_CODE, code, cx).into();
+    let details: Object = Cons::new(sym::JSONRPC_ERROR_MESSAGE, message, cx).into();
+    let data = list![cx.add(message), code, details; cx];
+    EvalError::signal(sym::JSONRPC_ERROR.into(), data, env).into()
+}
+
+fn jsonrpc_error(message: &str, env: &mut Rt<Env>, cx: &Context) -> anyhow::Error {
+    EvalError::signal(sym::JSONRPC_ERROR.into(), list![cx.add(message); cx], env).into()
+}
+
+/// Start COMMAND, a list of the program and its arguments, and return a
+/// connection to it. REQUEST-DISPATCHER and NOTIFICATION-DISPATCHER are
+/// called with the connection, the method symbol and the parameters for each
+/// request and notification that the server sends. The value of
+/// REQUEST-DISPATCHER is sent back as the result of the request. Either may
+/// be nil to ignore those messages.
+#[defun]
+fn jsonrpc_connect<'ob>(
+    command: Object<'ob>,
+    request_dispatcher: Object<'ob>,
+    notification_dispatcher: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    permissions::check(Capability::Subprocess, env, cx)?;
+    let args = match command.untag() {
+        ObjectType::Cons(cons) => cons
+            .elements()
+            .map(|x| match x?.untag() {
+                ObjectType::String(x) => Ok(x.to_string()),
+                other => bail!("Invalid jsonrpc command argument: {other}"),
+            })
+            .collect::<Result<Vec<_>>>()?,
+        _ => bail!("Invalid jsonrpc command: {command}"),
+    };
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        bail!("Cannot connect to {}", args[0])
+    };
+    let id = add_connection(args[0].clone(), stdout, stdin, Some(child));
+    Ok(connection_object(id, request_dispatcher, notification_dispatcher, cx))
+}
+
+/// Send a notification for METHOD with PARAMS to CONNECTION.
+#[defun]
+fn jsonrpc_notify(connection: Object, method: Object, params: Object) -> Result<bool> {
+    let id = connection_id(connection)?;
+    let message = json!({"method": method_name(method)?, "params": params_json(params)?});
+    send(id, &message)?;
+    Ok(false)
+}
+
+/// Send a request for METHOD with PARAMS to CONNECTION and wait for the
+/// result. Requests and notifications from the server are dispatched while
+/// waiting. Signal a `jsonrpc-error' if the server replies with an error or
+/// doesn't reply within TIMEOUT seconds, which defaults to
+/// `jsonrpc-default-request-timeout'.
+#[defun]
+fn jsonrpc_request<'ob>(
+    connection: &Rto<Object>,
+    method: &Rto<Object>,
+    params: &Rto<Object>,
+    timeout: Option<f64>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let id = connection_id(connection.bind(cx))?;
+    let request_id = {
+        let mut connections = CONNECTIONS.lock().unwrap();
+        let Some(conn) = connections.get_mut(&id) else { bail!("jsonrpc connection is closed") };
+        conn.next_request += 1;
+        conn.next_request
+    };
+    let method = method_name(method.bind(cx))?;
+    let params = params_json(params.bind(cx))?;
+    send(id, &json!({"id": request_id, "method": method, "params": params}))?;
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => match env.vars.get(sym::JSONRPC_DEFAULT_REQUEST_TIMEOUT).map(|x| x.bind(cx)) {
+            Some(x) => match x.untag() {
+                ObjectType::Int(x) => x as f64,
+                ObjectType::Float(x) => **x,
+                _ => bail!("`jsonrpc-default-request-timeout' should be a number: {x}"),
+            },
+            None => 10.0,
+        },
+    };
+    let deadline = Instant::now() + Duration::from_secs_f64(timeout.max(0.0));
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let message = match next_message(id, remaining)? {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(jsonrpc_error(&format!("Timed out waiting for {method}"), env, cx));
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(jsonrpc_error("Connection closed", env, cx));
+            }
+        };
+        let is_response = message.get("method").is_none();
+        if is_response && message.get("id") == Some(&json!(request_id)) {
+            if let Some(error) = message.get("error") {
+                return Err(response_error(error, env, cx));
+            }
+            let result = message.get("result").unwrap_or(&Value::Null);
+            return Ok(from_json(result, &options(), cx));
+        }
+        dispatch(connection, &message, env, cx)?;
+    }
+}
+
+/// Dispatch the requests and notifications that CONNECTION has received,
+/// waiting up to TIMEOUT seconds for the first one. Return the number of
+/// messages handled.
+#[defun]
+fn jsonrpc_process_pending(
+    connection: &Rto<Object>,
+    timeout: Option<f64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let id = connection_id(connection.bind(cx))?;
+    let mut timeout = Duration::from_secs_f64(timeout.unwrap_or(0.0).max(0.0));
+    let mut handled = 0;
+    while let Ok(message) = next_message(id, timeout)? {
+        dispatch(connection, &message, env, cx)?;
+        handled += 1;
+        timeout = Duration::ZERO;
+    }
+    Ok(handled)
+}
+
+/// Return non-nil if CONNECTION is open and its server is still running.
+#[defun]
+fn jsonrpc_running_p(connection: Object) -> Result<bool> {
+    let id = connection_id(connection)?;
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let Some(conn) = connections.get_mut(&id) else { return Ok(false) };
+    Ok(match &mut conn.child {
+        Some(child) => matches!(child.try_wait(), Ok(None)),
+        None => true,
+    })
+}
+
+/// Close CONNECTION and kill its server.
+#[defun]
+fn jsonrpc_shutdown(connection: Object) -> Result<bool> {
+    let id = connection_id(connection)?;
+    let Some(conn) = CONNECTIONS.lock().unwrap().remove(&id) else { return Ok(false) };
+    if let Some(mut child) = conn.child {
+        _ = child.kill();
+        _ = child.wait();
+    }
+    Ok(true)
+}
+
+defsym!(JSONRPC_CONNECTION);
+defsym!(JSONRPC_ERROR);
+defsym!(JSONRPC_ERROR_CODE);
+defsym!(JSONRPC_ERROR_MESSAGE);
+defsym!(KW_JSON_FALSE);
+defvar!(JSONRPC_DEFAULT_REQUEST_TIMEOUT, 10);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::with_env;
+
+    #[test]
+    fn test_framing() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({"id": 1})).unwrap();
+        assert_eq!(out, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+        out.extend_from_slice(b"Content-Type: application/json\r\ncontent-length: 2\r\n\r\n{}");
+        let mut reader = &out[..];
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"{\"id\":1}");
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"{}");
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_request() {
+        use std::os::unix::net::UnixStream;
+
+        with_env(|env, cx| {
+            let (client, server) = UnixStream::pair().unwrap();
+            let id = add_connection("test".into(), client.try_clone().unwrap(), client, None);
+
+            // A server that asks the client a question before answering
+            let server = std::thread::spawn(move || {
+                let mut writer = server.try_clone().unwrap();
+                let mut reader = BufReader::new(server);
+                let mut next = || -> Value {
+                    serde_json::from_slice(&read_message(&mut reader).unwrap().unwrap()).unwrap()
+                };
+                let request = next();
+                assert_eq!(request["method"], "add");
+                let notify = json!({"jsonrpc": "2.0", "method": "log", "params": {"x": 1}});
+                write_message(&mut writer, &notify).unwrap();
+                let question = json!({"jsonrpc": "2.0", "id": 7, "method": "ask", "params": {}});
+                write_message(&mut writer, &question).unwrap();
+                let answer = next();
+                assert_eq!(answer["id"], 7);
+                let [a, b] = [&request["params"]["a"], &answer["result"]["b"]];
+                let sum = a.as_i64().unwrap() + b.as_i64().unwrap();
+                let reply = json!({"jsonrpc": "2.0", "id": request["id"], "result": {"sum": sum}});
+                write_message(&mut writer, &reply).unwrap();
+                let request = next();
+                let error = json!({"code": -32601, "message": "nope"});
+                let reply = json!({"jsonrpc": "2.0", "id": request["id"], "error": error});
+                write_message(&mut writer, &reply).unwrap();
+            });
+
+            let code = "(progn
+          (setq jsonrpc-test-log nil)
+          (list (plist-get (jsonrpc-request conn :add '(:a 1) 5) :sum)
+                jsonrpc-test-log
+                (condition-case err (jsonrpc-request conn \"missing\" nil 5)
+                  (error (cdr err)))))";
+            let mut eval = |code: &str| {
+                let form = crate::reader::read(code, cx).unwrap().0;
+                root!(form, cx);
+                crate::interpreter::eval(form, None, env, cx).unwrap().to_string()
+            };
+            eval(
+                "(setq jsonrpc-test-handlers
+                (cons (lambda (_conn method _params) (if (eq method 'ask) '(:b 2)))
+                      (lambda (_conn method params)
+                        (setq jsonrpc-test-log (cons (list method params) jsonrpc-test-log)))))",
+            );
+            let handlers = crate::core::env::intern("jsonrpc-test-handlers", cx);
+            let ObjectType::Cons(handlers) = env.vars.get(handlers).unwrap().bind(cx).untag()
+            else {
+                unreachable!()
+            };
+            let conn = connection_object(id, handlers.car(), handlers.cdr(), cx);
+            env.set_var(crate::core::env::intern("conn", cx), conn, cx).unwrap();
+            assert_eq!(
+            eval(code),
+            "(3 ((log (:x 1))) (\"nope\" (jsonrpc-error-code . -32601) (jsonrpc-error-message . \"nope\")))"
+        );
+            server.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,194 @@
+//! Keyboard input and quit handling.
+//!
+//! Input is read from stdin a line at a time by a background thread, so the
+//! event loop can run timers while it waits. Rune is idle from when it starts
+//! waiting for input until the input arrives, and idle timers are measured
+//! from the start of that period.
+//!
+//! A quit is requested by C-c, which applies to whichever thread reaches a
+//! safe point first, or through the [`InterruptHandle`] of one thread. Safe
+//! points are function calls, loop iterations, the forms of a body and the
+//! elements of a mapping function.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, NIL, TRUE},
+};
+use crate::eval::EvalError;
+use crate::timefns::timestamp;
+use rune_macros::defun;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+/// Set by the SIGINT handler when the user requests a quit. This gets moved
+/// into `quit-flag' the next time the interpreter reaches a safe point.
+static QUIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    // If the previous request was never picked up then the interpreter is not
+    // reaching any safe points, so the only way out is to exit the process.
+    if QUIT_REQUESTED.swap(true, Ordering::AcqRel) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Install the SIGINT handler that turns C-c into a lisp `quit'.
+#[cfg(unix)]
+pub(crate) fn install_sigint_handler() {
+    let handler = handle_sigint as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn install_sigint_handler() {}
+
+thread_local! {
+    /// Set through an [`InterruptHandle`] to quit on this thread only.
+    static INTERRUPTED: Arc<AtomicBool> = Arc::default();
+}
+
+/// Interrupts the interpreter running on one thread. It can be sent to and
+/// used from any other thread.
+#[derive(Debug, Clone)]
+pub(crate) struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Return the handle for the current thread.
+    pub(crate) fn current() -> Self {
+        Self(INTERRUPTED.with(Arc::clone))
+    }
+
+    /// Signal `quit' on the thread of this handle at its next safe point, as
+    /// if C-c had been pressed while it was running.
+    pub(crate) fn interrupt(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// Drop any quit that was requested while we were not evaluating (such as
+/// while waiting for input in the REPL).
+pub(crate) fn discard_pending_quit() {
+    QUIT_REQUESTED.store(false, Ordering::Release);
+    INTERRUPTED.with(|x| x.store(false, Ordering::Release));
+}
+
+/// Signal `quit' if one has been requested and `inhibit-quit' is nil. This is
+/// called by the interpreter and the bytecode VM at points where it is safe to
+/// unwind.
+pub(crate) fn maybe_quit(env: &mut Rt<Env>) -> Result<(), EvalError> {
+    if QUIT_REQUESTED.swap(false, Ordering::AcqRel) {
+        env.vars.insert(sym::QUIT_FLAG, TRUE);
+    }
+    if INTERRUPTED.with(|x| x.swap(false, Ordering::AcqRel)) {
+        env.vars.insert(sym::QUIT_FLAG, TRUE);
+    }
+    let quit_flag = env.vars.get(sym::QUIT_FLAG).is_some_and(|x| x != &NIL);
+    let inhibited = env.vars.get(sym::INHIBIT_QUIT).is_some_and(|x| x != &NIL);
+    if quit_flag && !inhibited {
+        env.vars.insert(sym::QUIT_FLAG, NIL);
+        Err(EvalError::signal(sym::QUIT.into(), NIL, env))
+    } else {
+        Ok(())
+    }
+}
+
+thread_local! {
+    /// When this thread started waiting for input, as a duration since the
+    /// epoch, or `None` while it is busy. Each interpreter thread waits for
+    /// input separately.
+    static IDLE_SINCE: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Record that rune has started waiting for input.
+pub(crate) fn start_idle() {
+    IDLE_SINCE.set(Some(crate::replay::now()));
+}
+
+/// Record that input has arrived, which ends the idle period.
+pub(crate) fn stop_idle() {
+    IDLE_SINCE.set(None);
+}
+
+/// Return when the current idle period started as a duration since the
+/// epoch, if rune is idle.
+pub(crate) fn idle_since() -> Option<Duration> {
+    IDLE_SINCE.get()
+}
+
+/// Return how long rune has been idle as a time value, or nil if it is not
+/// waiting for input.
+#[defun]
+fn current_idle_time(cx: &Context) -> Object {
+    match idle_since() {
+        Some(since) => timestamp(crate::replay::now().saturating_sub(since), cx),
+        None => NIL,
+    }
+}
+
+/// Lines read from stdin by the input thread.
+static INPUT: LazyLock<Mutex<Receiver<String>>> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Mutex::new(receiver)
+});
+
+/// Wait for the next line of input, running timers while rune is idle.
+/// Returns `None` at the end of input.
+pub(crate) fn read_input_line(env: &mut Rt<Env>, cx: &mut Context) -> Option<String> {
+    start_idle();
+    let line = loop {
+        let next = match crate::timer::run_timers(env, cx) {
+            Ok(next) => next,
+            Err(e) => {
+                eprintln!("Error running timers: {e}");
+                None
+            }
+        };
+        // the lock is not held while timers run, since they may read input
+        let input = INPUT.lock().unwrap();
+        match input.recv_timeout(next.unwrap_or(Duration::from_secs(60))) {
+            Ok(line) => break Some(line),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break None,
+        }
+    };
+    stop_idle();
+    line
+}
+
+defsym!(QUIT);
+defvar!(QUIT_FLAG);
+defvar!(INHIBIT_QUIT);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp_with_vars;
+
+    #[test]
+    fn test_interrupt() {
+        let handle = InterruptHandle::current();
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+        assert_lisp_with_vars("(condition-case nil (while t) (quit 'quit))", "quit");
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,17 @@
+//! Run the lisp level tests in `lisp/rune-tests.el` with ERT.
+use crate::core::object::{Gc, LispString};
+use crate::{differential::with_bootstrap, interpreter, lread, reader};
+use rune_core::macros::root;
+
+#[test]
+fn lisp_tests() {
+    with_bootstrap(|env, cx| {
+        let file: Gc<&LispString> = cx.add_as("rune-tests.el");
+        root!(file, cx);
+        lread::load(file, None, None, cx, env).unwrap();
+        let obj = reader::read("(ert-run-tests-batch \"^rune-\")", cx).unwrap().0;
+        root!(obj, cx);
+        let failed = interpreter::eval(obj, None, env, cx).unwrap();
+        assert!(failed.is_nil(), "lisp tests failed: {failed}");
+    });
+}
@@ -0,0 +1,115 @@
+//! Structured logging.
+//!
+//! Diagnostics are emitted with the `tracing` crate and written to stderr.
+//! Only events at or above the current level are printed; it starts as the
+//! value of `--log-level` (or `info`) and can be changed while running with
+//! `set-rune-log-level', so verbose output doesn't need a rebuild. Lisp code
+//! logs through the same facility with `rune-log'.
+use crate::core::{
+    env::intern,
+    gc::Context,
+    object::{Object, Symbol},
+};
+use anyhow::{anyhow, Result};
+use rune_macros::defun;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, Registry,
+};
+
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// The level from before `enable-debug' raised it to show debug messages.
+static BEFORE_DEBUG: Mutex<Option<LevelFilter>> = Mutex::new(None);
+
+fn parse_level(name: &str) -> Result<LevelFilter> {
+    name.parse().map_err(|_| anyhow!("Invalid log level: {name}"))
+}
+
+/// Install the global subscriber at `level`. Returns the handle used to
+/// change the level later.
+fn install(level: LevelFilter) -> reload::Handle<LevelFilter, Registry> {
+    let (filter, handle) = reload::Layer::new(level);
+    let layer = fmt::layer().with_writer(std::io::stderr).with_target(true);
+    // Fails if a subscriber is already installed, like the one used by tests
+    let _ = tracing_subscriber::registry().with(filter).with(layer).try_init();
+    handle
+}
+
+fn handle() -> &'static reload::Handle<LevelFilter, Registry> {
+    LEVEL.get_or_init(|| install(LevelFilter::INFO))
+}
+
+/// Start logging at the level named by `level`, which defaults to `info`.
+pub(crate) fn init(level: Option<&str>) -> Result<()> {
+    let level = level.map_or(Ok(LevelFilter::INFO), parse_level)?;
+    handle().reload(level)?;
+    Ok(())
+}
+
+/// Raise the level to `debug', if it is lower, so that the messages of
+/// `debug!` are printed. [`hide_debug`] puts it back.
+pub(crate) fn show_debug() {
+    let mut before = BEFORE_DEBUG.lock().unwrap();
+    let _ = handle().modify(|level| {
+        if *level < LevelFilter::DEBUG {
+            before.get_or_insert(*level);
+            *level = LevelFilter::DEBUG;
+        }
+    });
+}
+
+/// Restore the level from before [`show_debug`] raised it.
+pub(crate) fn hide_debug() {
+    if let Some(level) = BEFORE_DEBUG.lock().unwrap().take() {
+        let _ = handle().reload(level);
+    }
+}
+
+/// Log a message at LEVEL, which is one of `error', `warn', `info', `debug'
+/// or `trace'. The message is made from FORMAT and ARGS like `format-message'
+/// and is returned.
+#[defun]
+fn rune_log(level: Symbol, format: &str, args: &[Object]) -> Result<String> {
+    let message = crate::editfns::format_message(format, args)?;
+    match level.name() {
+        "error" => tracing::error!(target: "lisp", "{message}"),
+        "warn" => tracing::warn!(target: "lisp", "{message}"),
+        "info" => tracing::info!(target: "lisp", "{message}"),
+        "debug" => tracing::debug!(target: "lisp", "{message}"),
+        "trace" => tracing::trace!(target: "lisp", "{message}"),
+        _ => return Err(anyhow!("Invalid log level: {level}")),
+    }
+    Ok(message)
+}
+
+/// Return the current log level as a symbol. `off' means nothing is logged.
+#[defun]
+fn rune_log_level<'ob>(cx: &'ob Context) -> Result<Symbol<'ob>> {
+    let level = handle().with_current(|x| x.to_string())?;
+    Ok(intern(&level.to_lowercase(), cx))
+}
+
+/// Only log messages at LEVEL or above from now on. LEVEL is one of `off',
+/// `error', `warn', `info', `debug' or `trace'.
+#[defun]
+fn set_rune_log_level(level: Symbol) -> Result<Symbol> {
+    handle().reload(parse_level(level.name())?)?;
+    Ok(level)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_log_level() {
+        assert_lisp(
+            "(let ((old (rune-log-level)))
+               (set-rune-log-level 'trace)
+               (prog1 (list (rune-log-level) (rune-log 'debug \"%s-%d\" \"a\" 1))
+                 (set-rune-log-level old)))",
+            "(trace \"a-1\")",
+        );
+    }
+}
@@ -2,19 +2,23 @@
 use crate::core::cons::Cons;
 use crate::core::env::{sym, Env};
 use crate::core::error::{Type, TypeError};
-use crate::core::gc::{Context, Rt, Rto};
+use crate::core::gc::{Context, Rt, Rto, Slot};
 use crate::core::object::{
-    Function, Gc, LispString, Object, ObjectType, OptionalFlag, Symbol, TagType, WithLifetime, NIL,
-    TRUE,
+    Function, Gc, LispBuffer, LispHashTable, LispString, Object, ObjectType, OptionalFlag, Symbol,
+    TagType, WithLifetime, NIL, TRUE,
 };
+use crate::permissions::{check_file, Capability};
 use crate::reader;
 use crate::{interpreter, rooted_iter};
 use anyhow::{anyhow, Context as _};
 use anyhow::{bail, ensure, Result};
 use fallible_streaming_iterator::FallibleStreamingIterator;
+use rune_core::hashmap::HashMap;
 use rune_core::macros::{call, rebind, root};
 use rune_macros::defun;
+use std::collections::hash_map::DefaultHasher;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 
 fn check_lower_bounds(idx: Option<i64>, len: usize) -> Result<usize> {
@@ -60,27 +64,539 @@ pub(crate) fn read_from_string<'ob>(
     Ok(Cons::new(obj, new_pos as i64, cx).into())
 }
 
+/// A source of text for `read'.
+trait InputStream {
+    /// Return the next chunk of input, or `None` at the end of the stream.
+    fn read_chunk(&mut self, env: &mut Rt<Env>, cx: &mut Context) -> Result<Option<String>>;
+
+    /// Give back `text` that was read past the end of the object.
+    fn unread(&mut self, text: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<()>;
+}
+
+/// Read from the start of a string. The position is not saved, so unread text
+/// is dropped.
+struct StringInput(Option<String>);
+
+impl InputStream for StringInput {
+    fn read_chunk(&mut self, _: &mut Rt<Env>, _: &mut Context) -> Result<Option<String>> {
+        Ok(self.0.take())
+    }
+
+    fn unread(&mut self, _: &str, _: &mut Rt<Env>, _: &mut Context) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Read from point in a buffer, leaving point after the object.
+struct BufferInput<'a, 'ob> {
+    buffer: &'a Rto<&'ob LispBuffer>,
+}
+
+impl InputStream for BufferInput<'_, '_> {
+    fn read_chunk(&mut self, env: &mut Rt<Env>, cx: &mut Context) -> Result<Option<String>> {
+        env.with_buffer_mut(self.buffer.bind(cx), |buffer| {
+            let start = buffer.text.cursor().chars();
+            let end = buffer.text.len_chars();
+            if start == end {
+                return None;
+            }
+            let (before, after) = buffer.text.slice(start..end);
+            let chunk = format!("{before}{after}");
+            buffer.text.set_cursor(end);
+            Some(chunk)
+        })
+    }
+
+    fn unread(&mut self, text: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+        env.with_buffer_mut(self.buffer.bind(cx), |buffer| {
+            let point = buffer.text.cursor().chars();
+            buffer.text.set_cursor(point - text.chars().count());
+        })
+    }
+}
+
+/// Call a function with no arguments to get each character. Calling it with a
+/// character pushes that character back.
+struct FunctionInput<'a, 'ob> {
+    function: &'a Rto<Function<'ob>>,
+}
+
+impl InputStream for FunctionInput<'_, '_> {
+    fn read_chunk(&mut self, env: &mut Rt<Env>, cx: &mut Context) -> Result<Option<String>> {
+        let function = self.function;
+        let chr = call!(function; env, cx)?;
+        match chr.untag() {
+            ObjectType::Int(chr) if chr >= 0 => {
+                let chr = u32::try_from(chr).ok().and_then(char::from_u32);
+                let Some(chr) = chr else { bail!("Invalid character from read function") };
+                Ok(Some(chr.to_string()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn unread(&mut self, text: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+        let function = self.function;
+        for chr in text.chars().rev() {
+            call!(function, chr; env, cx)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a line at a time from stdin. Like reading from the minibuffer, any
+/// text on the line after the object is discarded. Timers run while waiting.
+struct StdinInput;
+
+impl InputStream for StdinInput {
+    fn read_chunk(&mut self, env: &mut Rt<Env>, cx: &mut Context) -> Result<Option<String>> {
+        Ok(crate::keyboard::read_input_line(env, cx))
+    }
+
+    fn unread(&mut self, _: &str, _: &mut Rt<Env>, _: &mut Context) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Read a line at a time from `text`, starting at byte `pos`. This is how
+/// `load' reads the forms of a file.
+struct StrInput<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl InputStream for StrInput<'_> {
+    fn read_chunk(&mut self, _: &mut Rt<Env>, _: &mut Context) -> Result<Option<String>> {
+        let rest = &self.text[self.pos..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        let len = rest.find('\n').map_or(rest.len(), |x| x + 1);
+        self.pos += len;
+        Ok(Some(rest[..len].to_owned()))
+    }
+
+    fn unread(&mut self, text: &str, _: &mut Rt<Env>, _: &mut Context) -> Result<()> {
+        self.pos -= text.len();
+        Ok(())
+    }
+}
+
+/// Finds the places where the text read so far could end an object, so that
+/// it is only parsed there instead of after every chunk. This only tracks
+/// nesting, strings, comments and escapes; the reader has the final say.
+#[derive(Default)]
+struct ObjectEnd {
+    /// How much of the text has been scanned.
+    pos: usize,
+    depth: usize,
+    string: bool,
+    comment: bool,
+    escape: bool,
+    /// After a `?', the next character is part of the literal.
+    char_literal: bool,
+    /// Inside a symbol or number at the top level.
+    atom: bool,
+}
+
+impl ObjectEnd {
+    /// Scan more of `text`, stopping after the first place an object could
+    /// end. Returns true if there is one.
+    fn scan(&mut self, text: &str) -> bool {
+        let base = self.pos;
+        for (idx, chr) in text[base..].char_indices() {
+            let start = base + idx;
+            self.pos = start + chr.len_utf8();
+            if self.escape {
+                self.escape = false;
+                continue;
+            }
+            if self.char_literal {
+                self.char_literal = false;
+                self.escape = chr == '\\';
+                self.atom = self.depth == 0;
+                continue;
+            }
+            if self.string {
+                match chr {
+                    '\\' => self.escape = true,
+                    '"' => {
+                        self.string = false;
+                        if self.depth == 0 {
+                            return true;
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if self.comment {
+                self.comment = chr != '\n';
+                continue;
+            }
+            let atom = std::mem::replace(&mut self.atom, false);
+            let delimiter = matches!(chr, '(' | ')' | '[' | ']' | '"' | ';' | '\'' | '`' | ',');
+            if atom && (delimiter || chr.is_whitespace()) {
+                // The delimiter is not part of the symbol or number
+                self.pos = start;
+                return true;
+            }
+            match chr {
+                '(' | '[' => self.depth += 1,
+                ')' | ']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        return true;
+                    }
+                }
+                '"' => self.string = true,
+                ';' => self.comment = true,
+                '?' if !atom => self.char_literal = true,
+                '\\' => {
+                    self.escape = true;
+                    self.atom = self.depth == 0;
+                }
+                '\'' | '`' | ',' | '#' if !atom => {}
+                _ if chr.is_whitespace() => {}
+                _ => self.atom = self.depth == 0,
+            }
+        }
+        false
+    }
+}
+
+/// The obarray of the current module, if any.
+fn module_obarray<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Option<&'ob LispHashTable> {
+    match env.vars.get(sym::RUNE_MODULE__OBARRAY).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::HashTable(obarray)) => Some(obarray),
+        _ => None,
+    }
+}
+
+/// Read one object from `stream`, pulling in more input until the object is
+/// complete. Symbols are read into the current module, if any.
+fn read_from_stream<'ob>(
+    stream: &mut impl InputStream,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    use reader::Error as E;
+    let mut text = String::new();
+    let mut object_end = ObjectEnd::default();
+    let end = loop {
+        let chunk = stream.read_chunk(env, cx)?;
+        let at_end = chunk.is_none();
+        if let Some(chunk) = chunk {
+            text.push_str(&chunk);
+        }
+        let end = loop {
+            if !object_end.scan(&text) && !at_end {
+                break None;
+            }
+            // At the end of the stream, the last place is the end of the text
+            let last = at_end && object_end.pos == text.len();
+            let obarray = module_obarray(env, cx);
+            match reader::read_in(&text[..object_end.pos], obarray, cx) {
+                Ok((_, end)) => break Some(end),
+                Err(
+                    E::MissingCloseParen(_)
+                    | E::MissingCloseBracket(_)
+                    | E::MissingStringDel(_)
+                    | E::MissingQuotedItem(_)
+                    | E::EmptyStream,
+                ) if !last => {}
+                Err(e) => bail!(e),
+            }
+        };
+        if let Some(end) = end {
+            break end;
+        }
+    };
+    stream.unread(&text[end..], env, cx)?;
+    let obarray = module_obarray(env, cx);
+    Ok(reader::read_in(&text[..end], obarray, cx)?.0)
+}
+
+/// Read one object from stdin for the REPL. Returns `None` at the end of the
+/// input.
+pub(crate) fn read_stdin<'ob>(
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Option<Object<'ob>>> {
+    match read_from_stream(&mut StdinInput, env, cx) {
+        Ok(obj) => Ok(Some(obj)),
+        Err(e) if matches!(e.downcast_ref::<reader::Error>(), Some(reader::Error::EmptyStream)) => {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Read one lisp object from STREAM. STREAM can be a string, a buffer (read
+/// from point), a function, or t for stdin. It defaults to `standard-input'.
+#[defun]
+fn read<'ob>(
+    stream: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let stream = match stream.map(|x| x.bind(cx)) {
+        Some(stream) if !stream.is_nil() => stream,
+        _ => env.vars.get(sym::STANDARD_INPUT).map_or(TRUE, |x| x.bind(cx)),
+    };
+    match stream.untag() {
+        ObjectType::String(string) => {
+            read_from_stream(&mut StringInput(Some(string.to_string())), env, cx)
+        }
+        ObjectType::TRUE => read_from_stream(&mut StdinInput, env, cx),
+        ObjectType::Buffer(buffer) => {
+            root!(buffer, cx);
+            read_from_stream(&mut BufferInput { buffer }, env, cx)
+        }
+        // TODO: read from markers once they are implemented
+        _ => {
+            let function: Function = stream.try_into()?;
+            root!(function, cx);
+            read_from_stream(&mut FunctionInput { function }, env, cx)
+        }
+    }
+}
+
+/// Read a line from `stream`, without the newline. Returns `None` at the end
+/// of the stream.
+fn read_line_from_stream(
+    stream: &mut impl InputStream,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Option<String>> {
+    let mut text = String::new();
+    loop {
+        match stream.read_chunk(env, cx)? {
+            Some(chunk) => {
+                text.push_str(&chunk);
+                if let Some(end) = text.find('\n') {
+                    stream.unread(&text[end + 1..], env, cx)?;
+                    text.truncate(end);
+                    return Ok(Some(text));
+                }
+            }
+            None if text.is_empty() => return Ok(None),
+            None => return Ok(Some(text)),
+        }
+    }
+}
+
+/// Read a line from `standard-input', like the minibuffer does when there is
+/// no terminal. Returns `None` at the end of the input.
+pub(crate) fn read_line(env: &mut Rt<Env>, cx: &mut Context) -> Result<Option<String>> {
+    let stream = env.vars.get(sym::STANDARD_INPUT).map_or(TRUE, |x| x.bind(cx));
+    match stream.untag() {
+        ObjectType::String(string) => {
+            read_line_from_stream(&mut StringInput(Some(string.to_string())), env, cx)
+        }
+        ObjectType::TRUE | ObjectType::NIL => read_line_from_stream(&mut StdinInput, env, cx),
+        ObjectType::Buffer(buffer) => {
+            root!(buffer, cx);
+            read_line_from_stream(&mut BufferInput { buffer }, env, cx)
+        }
+        _ => {
+            let function: Function = stream.try_into()?;
+            root!(function, cx);
+            read_line_from_stream(&mut FunctionInput { function }, env, cx)
+        }
+    }
+}
+
+defvar!(STANDARD_INPUT, true);
+defvar_bool!(LOAD_DEDUPLICATE_LITERALS, true);
+
+/// Mark the data in quoted forms of `form` as read-only. These are literals
+/// in the source, so mutating them would silently change the code.
+fn protect_literals(form: Object) {
+    let ObjectType::Cons(mut cons) = form.untag() else { return };
+    if cons.car() == sym::QUOTE {
+        if let ObjectType::Cons(quoted) = cons.cdr().untag() {
+            make_read_only(quoted.car());
+        }
+        return;
+    }
+    loop {
+        protect_literals(cons.car());
+        match cons.cdr().untag() {
+            ObjectType::Cons(next) => cons = next,
+            _ => break,
+        }
+    }
+}
+
+fn make_read_only(obj: Object) {
+    match obj.untag() {
+        ObjectType::Cons(mut cons) => loop {
+            if !cons.is_mutable() {
+                break;
+            }
+            cons.make_read_only();
+            make_read_only(cons.car());
+            match cons.cdr().untag() {
+                ObjectType::Cons(next) => cons = next,
+                _ => {
+                    make_read_only(cons.cdr());
+                    break;
+                }
+            }
+        },
+        ObjectType::Vec(vec) if !vec.is_read_only() => {
+            vec.make_read_only();
+            for elem in vec.iter() {
+                make_read_only(elem.get());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Literals seen so far in a load, so that equal ones can share storage.
+/// Only quoted data is shared, which is read-only, so sharing it can't be
+/// observed except with `eq'. Strings stay mutable, so they are never shared,
+/// and neither is anything that contains one. Binding
+/// `load-deduplicate-literals' to nil turns sharing off. `index` maps the
+/// hash of each literal to its positions in `literals`.
+struct LiteralPool<'brw, 'rt> {
+    literals: &'brw mut Rt<Vec<Slot<Object<'rt>>>>,
+    index: HashMap<u64, Vec<usize>>,
+}
+
+/// The largest literal that is shared, counting each element. This also stops
+/// circular literals from being walked forever.
+const MAX_LITERAL_SIZE: usize = 1000;
+
+impl LiteralPool<'_, '_> {
+    /// Replace the quoted data in `form` with equal literals from earlier in
+    /// the load. `form` has just been read, so it is still mutable.
+    fn share_form(&mut self, form: Object, cx: &Context) {
+        let ObjectType::Cons(mut cons) = form.untag() else { return };
+        if cons.car() == sym::QUOTE {
+            if let ObjectType::Cons(quoted) = cons.cdr().untag() {
+                let shared = self.share_datum(quoted.car(), 0, cx);
+                quoted.set_car(shared).expect("read forms should be mutable");
+            }
+            return;
+        }
+        for _ in 0..MAX_LITERAL_SIZE {
+            self.share_form(cons.car(), cx);
+            match cons.cdr().untag() {
+                ObjectType::Cons(next) => cons = next,
+                _ => break,
+            }
+        }
+    }
+
+    /// Share the parts of `obj` and then `obj` itself.
+    fn share_datum<'ob>(
+        &mut self,
+        obj: Object<'ob>,
+        depth: usize,
+        cx: &'ob Context,
+    ) -> Object<'ob> {
+        if depth > MAX_LITERAL_SIZE {
+            return obj;
+        }
+        match obj.untag() {
+            ObjectType::Cons(mut cons) => {
+                for _ in 0..MAX_LITERAL_SIZE {
+                    let shared = self.share_datum(cons.car(), depth + 1, cx);
+                    cons.set_car(shared).expect("read forms should be mutable");
+                    match cons.cdr().untag() {
+                        ObjectType::Cons(next) => cons = next,
+                        _ => break,
+                    }
+                }
+            }
+            ObjectType::Vec(vec) => {
+                let Ok(elems) = vec.try_mut() else { return obj };
+                for elem in elems {
+                    elem.set(self.share_datum(elem.get(), depth + 1, cx));
+                }
+            }
+            _ => return obj,
+        }
+        self.intern(obj, cx)
+    }
+
+    /// Return an earlier literal that is `equal' to `obj`, or add `obj` to the
+    /// pool if there is none.
+    fn intern<'ob>(&mut self, obj: Object<'ob>, cx: &'ob Context) -> Object<'ob> {
+        let mut hasher = DefaultHasher::new();
+        if !hash_literal(obj, &mut 0, &mut hasher) {
+            return obj;
+        }
+        let positions = self.index.entry(hasher.finish()).or_default();
+        if let Some(&i) = positions.iter().find(|&&i| self.literals[i].bind(cx) == obj) {
+            return self.literals[i].bind(cx);
+        }
+        positions.push(self.literals.len());
+        self.literals.push(obj);
+        obj
+    }
+}
+
+/// Hash `obj` by its contents. Returns false if it can't be shared, because it
+/// contains something other than numbers, symbols, conses and vectors or is
+/// bigger than [`MAX_LITERAL_SIZE`]. Floats are hashed by their bits so
+/// that 0.0 and -0.0 are kept apart.
+fn hash_literal(obj: Object, size: &mut usize, state: &mut impl Hasher) -> bool {
+    *size += 1;
+    if *size > MAX_LITERAL_SIZE {
+        return false;
+    }
+    match obj.untag() {
+        ObjectType::Int(x) => x.hash(state),
+        ObjectType::Float(x) => x.to_bits().hash(state),
+        ObjectType::Symbol(x) => x.hash(state),
+        ObjectType::Cons(cons) => {
+            return hash_literal(cons.car(), size, state) && hash_literal(cons.cdr(), size, state)
+        }
+        ObjectType::Vec(vec) => {
+            return vec.iter().all(|elem| hash_literal(elem.get(), size, state));
+        }
+        _ => return false,
+    }
+    true
+}
+
 pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>) -> Result<bool> {
-    let mut pos = 0;
     let macroexpand: Option<Function> = None;
     root!(macroexpand, cx);
     if let Some(fun) = sym::INTERNAL_MACROEXPAND_FOR_LOAD.func(cx) {
         macroexpand.set(Some(fun));
     }
+    let dedup = !env
+        .vars
+        .get(sym::LOAD_DEDUPLICATE_LITERALS)
+        .is_some_and(|x| x.bind(cx).is_nil());
+    root!(literals, new(Vec<Slot<Object>>), cx);
+    let mut pool = LiteralPool { literals, index: HashMap::default() };
+    let mut input = StrInput { text: contents, pos: 0 };
     loop {
-        let (obj, new_pos) = match reader::read(&contents[pos..], cx) {
-            Ok((obj, pos)) => (obj, pos),
-            Err(reader::Error::EmptyStream) => return Ok(true),
-            Err(mut e) => {
-                e.update_pos(pos);
-                bail!(e);
-            }
+        let pos = input.pos;
+        let obj = match read_from_stream(&mut input, env, cx) {
+            Ok(obj) => obj,
+            Err(e) => match e.downcast::<reader::Error>() {
+                Ok(reader::Error::EmptyStream) => return Ok(true),
+                Ok(mut e) => {
+                    e.update_pos(pos);
+                    bail!(e);
+                }
+                Err(e) => return Err(e),
+            },
         };
-        if crate::debug::debug_enabled() {
-            let content = &contents[pos..(new_pos + pos)];
-            println!("-----READ START-----\n {content}");
-            println!("-----READ END-----");
+        let obj = rebind!(obj, cx);
+        let new_pos = input.pos - pos;
+        tracing::trace!(form = &contents[pos..(new_pos + pos)], "read");
+        if dedup {
+            pool.share_form(obj, cx);
         }
+        protect_literals(obj);
         root!(obj, cx);
         let result = if let Some(fun) = macroexpand.as_ref() {
             eager_expand(obj, fun, env, cx)
@@ -88,13 +604,13 @@ pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>)
             interpreter::eval(obj, None, env, cx)
         };
         if let Err(e) = result {
-            let content = &contents[pos..(new_pos + pos)];
-            println!("-----LOAD ERROR START-----\n {content}");
-            println!("-----LOAD ERROR END-----");
+            tracing::error!(form = &contents[pos..(new_pos + pos)], "error while loading");
             return Err(e);
         }
         assert_ne!(new_pos, 0);
-        pos += new_pos;
+        // Most of what the form allocated is garbage now, so this is a cheap
+        // time to collect
+        cx.garbage_collect(false);
     }
 }
 
@@ -173,10 +689,11 @@ pub(crate) fn load(
             }
         }
     };
+    check_file(Capability::Read, &final_file.to_string_lossy(), env, cx)?;
 
     let filename = String::from(file);
     if !nomessage {
-        println!("Loading {filename}...");
+        tracing::info!("Loading {filename}...");
     }
     let new_load_file = cx.add(final_file.to_string_lossy().to_string());
     let prev_load_file = match env.vars.get_mut(sym::LOAD_FILE_NAME) {
@@ -188,6 +705,16 @@ pub(crate) fn load(
         None => NIL,
     };
     root!(prev_load_file, cx);
+    // Each file starts outside of any module
+    let prev_module = match env.vars.get_mut(sym::RUNE_MODULE__OBARRAY) {
+        Some(val) => {
+            let prev = val.bind(cx);
+            val.set(NIL);
+            prev
+        }
+        None => NIL,
+    };
+    root!(prev_module, cx);
     let result = match fs::read_to_string(&final_file)
         .with_context(|| format!("Couldn't open file {:?}", final_file.as_os_str()))
     {
@@ -199,20 +726,45 @@ pub(crate) fn load(
     };
 
     if !nomessage && result.is_ok() {
-        println!("Loading {filename} Done");
+        tracing::info!("Loading {filename} Done");
     }
     env.vars.insert(sym::LOAD_FILE_NAME, &*prev_load_file);
+    env.vars.insert(sym::RUNE_MODULE__OBARRAY, &*prev_module);
     result
 }
 
 #[defun]
-pub(crate) fn intern<'ob>(string: &str, cx: &'ob Context) -> Symbol<'ob> {
-    crate::core::env::intern(string, cx)
+pub(crate) fn intern<'ob>(
+    string: &str,
+    obarray: Option<&LispHashTable>,
+    cx: &'ob Context,
+) -> Symbol<'ob> {
+    match obarray {
+        Some(obarray) => crate::module::intern_in(string, obarray, cx),
+        None => crate::core::env::intern(string, cx),
+    }
 }
 
 #[defun]
-pub(crate) fn intern_soft(string: Object, obarray: OptionalFlag) -> Result<Symbol> {
-    ensure!(obarray.is_none(), "intern-soft obarray not implemented");
+pub(crate) fn intern_soft<'ob>(
+    string: Object<'ob>,
+    obarray: Option<&LispHashTable>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    if let Some(obarray) = obarray {
+        let name: &str = match string.untag() {
+            ObjectType::Symbol(sym) => sym.name(),
+            ObjectType::String(string) => string,
+            x => bail!(TypeError::one_of(&[Type::String, Type::Symbol], x)),
+        };
+        let found = crate::module::intern_soft_in(name, obarray, cx);
+        return Ok(match (found, string.untag()) {
+            // A symbol is only found if it is the one in the obarray
+            (Some(sym), ObjectType::Symbol(orig)) if sym != orig => sym::NIL,
+            (Some(sym), _) => sym,
+            (None, _) => sym::NIL,
+        });
+    }
     match string.untag() {
         ObjectType::Symbol(sym) => {
             if sym.interned() {
@@ -262,4 +814,125 @@ mod test {
         let val = interpreter::eval(obj, None, env, cx).unwrap();
         assert_eq!(val, 4.5);
     }
+
+    #[test]
+    fn test_load_lines() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let contents = "; comment (\n(setq foo\n      \"a;)\")\n(setq bar ?\\()";
+        load_internal(contents, cx, env).unwrap();
+        let obj = reader::read("(list foo bar)", cx).unwrap().0;
+        root!(obj, cx);
+        let val = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(val.to_string(), "(\"a;)\" 40)");
+    }
+
+    #[test]
+    fn test_object_end() {
+        let ends = |text: &str| {
+            let mut object_end = ObjectEnd::default();
+            let mut ends = Vec::new();
+            while object_end.scan(text) {
+                ends.push(object_end.pos);
+            }
+            ends
+        };
+        assert_eq!(ends("foo bar"), [3]);
+        assert_eq!(ends("(a (b \")\") c) d"), [13]);
+        assert_eq!(ends("?) ?\\( x"), [2, 6]);
+        assert_eq!(ends("'(a) \"b\\\"\""), [4, 10]);
+        assert_eq!(ends("; (\n[1 2]"), [9]);
+        assert_eq!(ends("f\\ o x"), [4]);
+    }
+
+    #[test]
+    fn test_read_streams() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+
+        fn eval(form: &str, cx: &mut Context, env: &mut Rt<Env>) -> String {
+            let obj = reader::read(form, cx).unwrap().0;
+            root!(obj, cx);
+            interpreter::eval(obj, None, env, cx).unwrap().to_string()
+        }
+        assert_eq!(eval("(read \"(a b) c\")", cx, env), "(a b)");
+        assert_eq!(eval("(read \" foo\")", cx, env), "foo");
+        let form = "(let ((chars '(97 98 32 99)))
+                      (list (read (lambda (&optional chr)
+                                    (if chr
+                                        (setq chars (cons chr chars))
+                                      (prog1 (car chars) (setq chars (cdr chars))))))
+                            chars))";
+        assert_eq!(eval(form, cx, env), "(ab (32 99))");
+
+        let buffer =
+            crate::buffer::get_buffer_create(cx.add("read-stream-test"), None, cx).unwrap();
+        let ObjectType::Buffer(b) = buffer.untag() else { unreachable!() };
+        env.with_buffer_mut(b, |b| {
+            b.text.insert("foo (bar) 1");
+            b.text.set_cursor(0);
+        })
+        .unwrap();
+        root!(buffer, cx);
+        assert_eq!(read(Some(buffer), env, cx).unwrap().to_string(), "foo");
+        assert_eq!(read(Some(buffer), env, cx).unwrap().to_string(), "(bar)");
+        let ObjectType::Buffer(b) = buffer.untag(cx) else { unreachable!() };
+        let point = env.with_buffer(b, |b| b.text.cursor().chars()).unwrap();
+        assert_eq!(point, 9);
+    }
+
+    #[test]
+    fn test_read_only_literals() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        load_internal("(setq foo '(1 (2) [3])) (setq bar (list 1 2))", cx, env).unwrap();
+
+        fn eval(form: &str, cx: &mut Context, env: &mut Rt<Env>) -> bool {
+            let obj = reader::read(form, cx).unwrap().0;
+            root!(obj, cx);
+            interpreter::eval(obj, None, env, cx).is_ok()
+        }
+        assert!(!eval("(setcar foo 2)", cx, env));
+        assert!(!eval("(setcdr (car (cdr foo)) 2)", cx, env));
+        assert!(!eval("(aset (car (cdr (cdr foo))) 0 4)", cx, env));
+        assert!(eval("(setcar bar 2)", cx, env));
+        assert!(eval("(setcar (copy-sequence foo) 2)", cx, env));
+
+        let form = "(condition-case err (setcar foo 2) (error err))";
+        let obj = reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        let err = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(err.to_string(), "(setting-constant (1 (2) [3]))");
+    }
+
+    #[test]
+    fn test_dedup_literals() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let source = "(setq foo '(1 (2) [3]) bar '(1 (2) [3]) baz '((2) 1.5)
+                      quoted1 '(\"x\") quoted2 '(\"x\") str1 \"abc\" str2 \"abc\")";
+        load_internal(source, cx, env).unwrap();
+
+        fn eval(form: &str, cx: &mut Context, env: &mut Rt<Env>) -> String {
+            let obj = reader::read(form, cx).unwrap().0;
+            root!(obj, cx);
+            interpreter::eval(obj, None, env, cx).unwrap().to_string()
+        }
+        // strings are mutable, so they aren't shared
+        let form =
+            "(list (eq foo bar) (eq (nth 1 foo) (car baz)) (eq quoted1 quoted2) (eq str1 str2))";
+        assert_eq!(eval(form, cx, env), "(t t nil nil)");
+
+        eval("(setq load-deduplicate-literals nil)", cx, env);
+        load_internal(source, cx, env).unwrap();
+        assert_eq!(eval(form, cx, env), "(nil nil nil nil)");
+    }
 }
@@ -0,0 +1,189 @@
+//! Stepping through macro expansions.
+//!
+//! `macrostep-expand-at' finds the innermost macro call around a position in
+//! source text and expands it once with [`macroexpand_1`]. The expansion is
+//! spliced back into the text, so calling it again on the result steps into
+//! the next macro.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Gc, LispString, Object, ObjectType, NIL},
+};
+use crate::eval::macroexpand_1;
+use anyhow::{ensure, Result};
+use rune_core::macros::{list, root};
+use rune_macros::defun;
+
+/// Return the byte ranges of the lists in `text` that contain the byte
+/// offset `pos`, innermost first. Strings, comments and character literals
+/// are skipped.
+fn enclosing_lists(text: &str, pos: usize) -> Vec<(usize, usize)> {
+    let mut open = Vec::new();
+    let mut lists = Vec::new();
+    let mut chars = text.char_indices();
+    while let Some((i, chr)) = chars.next() {
+        match chr {
+            ';' => {
+                chars.by_ref().find(|x| x.1 == '\n');
+            }
+            '"' => {
+                while let Some((_, chr)) = chars.next() {
+                    match chr {
+                        '\\' => _ = chars.next(),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '?' => {
+                if let Some((_, '\\')) = chars.next() {
+                    chars.next();
+                }
+            }
+            '\\' => _ = chars.next(),
+            '(' => open.push(i),
+            ')' => {
+                if let Some(start) = open.pop() {
+                    if start <= pos && pos <= i {
+                        lists.push((start, i + 1));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // lists close from the inside out
+    lists
+}
+
+/// Whether the list `lists[index]` is data rather than code, because it or a
+/// list around it is quoted with `'`, `#'` or a backquote, or is an argument
+/// of `quote` or `function`. `lists` are the enclosing lists of a position,
+/// innermost first. A comma evaluates what it is in front of, so it ends the
+/// search.
+fn is_quoted(text: &str, lists: &[(usize, usize)], index: usize) -> bool {
+    for (i, &(start, _)) in lists.iter().enumerate().skip(index) {
+        let before = &text[..start];
+        if before.ends_with(',') || before.ends_with(",@") {
+            return false;
+        }
+        if before.ends_with('\'') || before.ends_with('`') {
+            return true;
+        }
+        if let Some(&(parent, _)) = lists.get(i + 1) {
+            let head = text[parent + 1..]
+                .split(|x: char| x.is_whitespace() || x == '(' || x == ')')
+                .next()
+                .unwrap_or_default();
+            if head == "quote" || head == "function" {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Expand the innermost macro call in STRING around the character position
+/// POS once. Return nil if POS is not inside a macro call, or a plist:
+///
+/// :macro -- the name of the macro that was expanded.
+/// :start, :end -- the character positions of the call in STRING.
+/// :form -- the call.
+/// :expansion -- the result of expanding the call once.
+/// :text -- STRING with the call replaced by the pretty printed expansion.
+///
+/// Calls in quoted data, including inside `function' and a backquote outside
+/// of a comma, are not expanded.
+#[defun]
+fn macrostep_expand_at<'ob>(
+    string: &Rto<Gc<&LispString>>,
+    pos: usize,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let string: &str = string.untag(cx);
+    let string = &string.to_owned();
+    let chars = string.chars().count();
+    ensure!(pos <= chars, "Position {pos} is outside of the string of length {chars}");
+    let byte_pos = string.char_indices().nth(pos).map_or(string.len(), |x| x.0);
+    let lists = enclosing_lists(string, byte_pos);
+    for (i, &(start, end)) in lists.iter().enumerate() {
+        if is_quoted(string, &lists, i) {
+            continue;
+        }
+        let Ok((form, _)) = crate::reader::read(&string[start..end], cx) else { continue };
+        let ObjectType::Cons(call) = form.untag() else { continue };
+        let ObjectType::Symbol(name) = call.car().untag() else { continue };
+        if crate::eval::get_macro_func(name, cx).is_none() {
+            continue;
+        }
+        root!(form, cx);
+        let expansion = macroexpand_1(form, None, cx, env)?;
+        root!(expansion, cx);
+        let column = string[..start].rsplit('\n').next().unwrap_or_default().chars().count();
+        let width = crate::pp::fill_column(env, cx);
+        let printed =
+            crate::pp::pretty_print(expansion.bind(cx), column, width, Some((&*env, &*cx)));
+        let text = format!("{}{printed}{}", &string[..start], &string[end..]);
+        let start_char = string[..start].chars().count();
+        let end_char = start_char + string[start..end].chars().count();
+        let ObjectType::Cons(call) = form.untag(cx) else { unreachable!("form is a call") };
+        let result = list![
+            sym::KW_MACRO, call.car(),
+            sym::KW_START, start_char as i64,
+            sym::KW_END, end_char as i64,
+            sym::KW_FORM, form.bind(cx),
+            sym::KW_EXPANSION, expansion.bind(cx),
+            sym::KW_TEXT, text;
+            cx
+        ];
+        return Ok(result);
+    }
+    Ok(NIL)
+}
+
+defsym!(KW_MACRO);
+defsym!(KW_START);
+defsym!(KW_END);
+defsym!(KW_FORM);
+defsym!(KW_EXPANSION);
+defsym!(KW_TEXT);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_enclosing_lists() {
+        let text = "(a (b \"(\" ?\\( ;; )\n c) d)";
+        assert_eq!(enclosing_lists(text, 4), vec![(3, 22), (0, 25)]);
+        assert_eq!(enclosing_lists(text, 23), vec![(0, 25)]);
+        assert!(enclosing_lists(text, 30).is_empty());
+    }
+
+    #[test]
+    fn test_macrostep() {
+        assert_lisp(
+            "(progn (defalias 'ms-test-twice (cons 'macro #'(lambda (x) (list 'progn x x)))) (plist-get (macrostep-expand-at \"(list (ms-test-twice (ms-test-twice 1)))\" 8) :text))",
+            "\"(list (progn (ms-test-twice 1) (ms-test-twice 1)))\"",
+        );
+        assert_lisp(
+            "(progn (defalias 'ms-test-id (cons 'macro #'(lambda (x) x))) (let ((step (macrostep-expand-at \"(ms-test-id (ms-test-id 2))\" 14))) (list (plist-get step :start) (plist-get step :end) (plist-get step :expansion))))",
+            "(12 26 2)",
+        );
+        assert_lisp(
+            "(progn (defalias 'ms-test-quoted (cons 'macro #'(lambda () 1))) (macrostep-expand-at \"(list '(ms-test-quoted))\" 9))",
+            "nil",
+        );
+        for quoted in
+            ["'(a (ms-test-quoted))", "(function (ms-test-quoted))", "`(a (ms-test-quoted))"]
+        {
+            assert_lisp(&format!("(macrostep-expand-at {quoted:?} 12)"), "nil");
+        }
+        assert_lisp(
+            "(plist-get (macrostep-expand-at \"`(a ,(ms-test-quoted))\" 7) :expansion)",
+            "1",
+        );
+    }
+}
@@ -5,38 +5,96 @@ mod core;
 #[macro_use]
 mod debug;
 mod alloc;
+mod ansi_color;
 mod arith;
 mod buffer;
 mod bytecode;
+mod calc;
 mod casefiddle;
+mod char_fold;
 mod character;
+mod coding;
+mod compile;
+mod csv;
 mod data;
+mod deferred;
+mod diff;
+#[cfg(test)]
+mod differential;
 mod dired;
 mod editfns;
 mod emacs;
 mod eval;
 mod fileio;
 mod filelock;
+mod files;
+mod fill;
 mod floatfns;
 mod fns;
+mod future;
+mod generator;
+mod handle;
+mod image;
+mod imenu;
+mod indent;
+mod insdel;
 mod interpreter;
+mod json;
+mod jsonrpc;
+mod keyboard;
 mod keymap;
 mod library;
+#[cfg(test)]
+mod lisp_tests;
+mod logging;
 mod lread;
+mod macrostep;
+mod memoize;
+mod minibuf;
+mod minibuffer;
+mod module;
+mod newcomment;
+mod panics;
+mod pdumper;
+mod permissions;
+mod persist;
+mod pp;
 mod print;
+mod process;
+mod project;
 mod reader;
+mod recentf;
+mod repl_server;
+mod replay;
+mod sandbox;
+mod savehist;
 mod search;
+mod server;
+mod shell;
+mod sqlite;
+mod startup;
+mod stream;
+mod syntax;
+mod thingatpt;
 mod threads;
 mod timefns;
+mod timer;
+mod treesit;
+mod vc;
+mod warnings;
+mod watchers;
+mod xdg;
+mod xml;
+mod xref;
 
 use crate::core::{
     env::{intern, sym, Env},
     gc::{Context, RootSet, Rt},
-    object::{Gc, LispString, NIL},
+    object::{with_print_limits, Gc, LispString, ObjectType, NIL},
 };
 use crate::eval::EvalError;
 use clap::Parser;
-use rune_core::macros::root;
+use rune_core::macros::{rebind, root};
 use std::io::{self, Write};
 
 #[derive(Parser, Debug)]
@@ -48,24 +106,74 @@ struct Args {
     repl: bool,
     #[arg(short, long)]
     no_bootstrap: bool,
+    #[arg(short, long, value_name = "FILE")]
+    dump_file: Option<String>,
+    /// Do not load the user's init files
+    #[arg(short = 'q', long)]
+    no_init_file: bool,
+    /// Directory to load the init files from instead of `user-emacs-directory`
+    #[arg(long, value_name = "DIR")]
+    init_directory: Option<String>,
+    /// Start the server and handle emacsclient requests until killed
+    #[arg(long)]
+    daemon: bool,
+    /// Serve the JSON REPL protocol on PORT until killed
+    #[arg(long, value_name = "PORT")]
+    repl_port: Option<u16>,
+    /// Record the time, random numbers, environment variables and process
+    /// output used by this run to FILE
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<String>,
+    /// Replay a run recorded with --record, reading its inputs from FILE
+    #[arg(long, value_name = "FILE")]
+    replay: Option<String>,
+    /// Only log diagnostics at LEVEL or above: off, error, warn, info, debug
+    /// or trace
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<String>,
+    /// Abort on a panic in Rust code instead of signaling a lisp error
+    #[arg(long)]
+    abort_on_panic: bool,
 }
 
 fn main() -> Result<(), ()> {
     let args = Args::parse();
+    logging::init(args.log_level.as_deref()).map_err(|e| eprintln!("Error: {e}"))?;
+    panics::set_abort_on_panic(args.abort_on_panic);
+    keyboard::install_sigint_handler();
+
+    // Start before anything reads the environment or the random seed
+    if let Some(file) = &args.record {
+        replay::start_recording(file).map_err(|e| eprintln!("Error: {e}"))?;
+    } else if let Some(file) = &args.replay {
+        replay::start_replaying(file).map_err(|e| eprintln!("Error: {e}"))?;
+    }
 
     let roots = &RootSet::default();
     let cx = &mut Context::new(roots);
     root!(env, new(Env), cx);
 
     sym::init_symbols();
+    interpreter::check_subrs(cx).map_err(|e| eprintln!("Error: {e}"))?;
     crate::core::env::init_variables(cx, env);
-    crate::data::defalias(intern("not", cx), (sym::NULL).into(), None)
+    crate::data::defalias(intern("not", cx), (sym::NULL).into(), None, env)
         .expect("null should be defined");
 
-    if !args.no_bootstrap {
+    if let Some(dump) = &args.dump_file {
+        restore_dump(dump, env, cx)?;
+    } else if !args.no_bootstrap {
         bootstrap(env, cx)?;
     }
 
+    startup::startup(args.init_directory.as_deref(), args.no_init_file, env, cx);
+
+    if let Err(e) = savehist::savehist_load(env, cx) {
+        eprintln!("Error restoring history: {e}");
+    }
+    if let Err(e) = recentf::recentf_load_list(env, cx) {
+        eprintln!("Error restoring recent files: {e}");
+    }
+
     for file in args.load {
         load(&file, cx, env)?;
     }
@@ -73,58 +181,85 @@ fn main() -> Result<(), ()> {
     if args.repl {
         repl(env, cx);
     }
-    Ok(())
-}
 
-fn parens_closed(buffer: &str) -> bool {
-    let open = buffer.chars().filter(|&x| x == '(').count();
-    let close = buffer.chars().filter(|&x| x == ')').count();
-    open <= close
+    if args.daemon {
+        daemon(env, cx)?;
+    }
+
+    if let Some(port) = args.repl_port {
+        let dir = std::path::PathBuf::from(server::default_socket_dir());
+        let port = repl_server::start(port, &dir).map_err(|e| eprintln!("Error: {e}"))?;
+        let token = repl_server::token_file(&dir, port);
+        println!("REPL server listening on port {port}, token in {}", token.display());
+        repl_server::handle_requests(None, env, cx).map_err(|e| eprintln!("Error: {e}"))?;
+    }
+
+    if let Err(e) = savehist::savehist_save(env, cx) {
+        eprintln!("Error saving history: {e}");
+    }
+    if let Err(e) = recentf::recentf_save_list(env, cx) {
+        eprintln!("Error saving recent files: {e}");
+    }
+    Ok(())
 }
 
 fn repl(env: &mut Rt<Env>, cx: &mut Context) {
-    let mut buffer = String::new();
-    let stdin = io::stdin();
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
-        stdin.read_line(&mut buffer).unwrap();
-        if buffer.trim() == "exit" {
-            return;
-        }
-        if buffer.trim().is_empty() {
-            continue;
-        }
-        if !parens_closed(&buffer) {
-            continue;
-        }
-        let (obj, _) = match reader::read(&buffer, cx) {
-            Ok(obj) => obj,
+        let obj = match lread::read_stdin(env, cx) {
+            Ok(Some(obj)) => obj,
+            Ok(None) => return,
             Err(e) => {
                 eprintln!("Error: {e}");
-                buffer.clear();
                 continue;
             }
         };
-
+        let obj = rebind!(obj, cx);
+        if matches!(obj.untag(), ObjectType::Symbol(x) if x.name() == "exit") {
+            return;
+        }
         root!(obj, cx);
-        match interpreter::eval(obj, None, env, cx) {
-            Ok(val) => println!("{val}"),
-            Err(e) => {
-                eprintln!("Error: {e}");
-                if let Ok(e) = e.downcast::<EvalError>() {
+        keyboard::discard_pending_quit();
+        let printed = panics::catch_panic(env, cx, |env, cx| {
+            let val = rebind!(interpreter::eval(obj, None, env, cx)?, cx);
+            let width = pp::fill_column(env, cx);
+            let limits = print::eval_expression_limits(env, cx);
+            Ok(with_print_limits(limits, || {
+                pp::pretty_print(val, 0, width, Some((&*env, &*cx)))
+            }))
+        });
+        match printed {
+            Ok(printed) => println!("{printed}"),
+            Err(e) => match e.downcast::<EvalError>() {
+                Ok(e) => {
+                    let limits = print::eval_expression_limits(env, cx);
+                    eprintln!("Error: {}", with_print_limits(limits, || e.describe(env, cx)));
                     e.print_backtrace();
                 }
-            }
+                Err(e) => eprintln!("Error: {e}"),
+            },
         }
-        buffer.clear();
     }
 }
 
+fn daemon(env: &mut Rt<Env>, cx: &mut Context) -> Result<(), ()> {
+    let start = reader::read("(server-start)", cx).unwrap().0;
+    root!(start, cx);
+    if let Err(e) = interpreter::eval(start, None, env, cx) {
+        eprintln!("Error starting server: {e}");
+        return Err(());
+    }
+    server::handle_requests(None, env, cx).map_err(|e| eprintln!("Error: {e}"))?;
+    Ok(())
+}
+
 fn load(file: &str, cx: &mut Context, env: &mut Rt<Env>) -> Result<(), ()> {
     let file: Gc<&LispString> = cx.add_as(file);
     root!(file, cx);
-    match crate::lread::load(file, None, None, cx, env) {
+    let loaded =
+        panics::catch_panic(env, cx, |env, cx| crate::lread::load(file, None, None, cx, env));
+    match loaded {
         Ok(val) => {
             println!("{val}");
             Ok(())
@@ -144,6 +279,11 @@ fn bootstrap(env: &mut Rt<Env>, cx: &mut Context) -> Result<(), ()> {
     load("bootstrap.el", cx, env)
 }
 
+fn restore_dump(file: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<(), ()> {
+    buffer::get_buffer_create(cx.add("*scratch*"), Some(NIL), cx).unwrap();
+    crate::pdumper::load_dump_file(file, env, cx).map_err(|e| eprintln!("Error: {e}"))
+}
+
 #[test]
 fn verify_cli() {
     use clap::CommandFactory;
@@ -0,0 +1,225 @@
+//! Caching the results of pure functions.
+//!
+//! `memoize' replaces the definition of a function with one that looks up
+//! its argument list in a memo table before calling the original one. The
+//! table is keyed on the `sxhash-equal' of the arguments, and each key holds
+//! an alist of (ARGS . RESULT) for the argument lists with that hash, so
+//! arguments are compared with `equal'. Rune has no weak references, so
+//! instead of holding results weakly the table is bounded: once it holds
+//! LIMIT keys, the oldest one is dropped for each new one. `unmemoize'
+//! restores the original definition and drops the table.
+//!
+//! The memo is kept in the `memoize--memo' property of the symbol as
+//! (ORIGINAL TABLE LIMIT).
+use crate::core::{
+    cons::Cons,
+    env::{sym, CallFrame, Env},
+    gc::{Context, Rt, Rto},
+    object::{
+        Function, FunctionType, Gc, HashTable, LispHashTable, Object, ObjectType, Symbol, NIL,
+    },
+};
+use crate::data::LispError;
+use anyhow::{bail, Result};
+use rune_core::macros::{list, rebind, root};
+use rune_macros::defun;
+
+/// The parts of a memo.
+struct Memo<'ob> {
+    original: Function<'ob>,
+    table: &'ob LispHashTable,
+    limit: usize,
+}
+
+fn memo<'ob>(symbol: Symbol, env: &Rt<Env>, cx: &'ob Context) -> Result<Option<Memo<'ob>>> {
+    let memo = crate::data::get(symbol, sym::MEMOIZE__MEMO, env, cx);
+    if memo.is_nil() {
+        return Ok(None);
+    }
+    let parts: Vec<_> = memo.as_list()?.collect::<Result<_, _>>()?;
+    let [original, table, limit] = parts[..] else {
+        bail!("Invalid memo for {symbol}: {memo}")
+    };
+    let table: Gc<&LispHashTable> = table.try_into()?;
+    let original = original.try_into()?;
+    Ok(Some(Memo { original, table: table.untag(), limit: limit.try_into()? }))
+}
+
+/// Whether `func` is the definition `memoize' gives a function.
+fn is_memoized(func: Function) -> bool {
+    let FunctionType::Cons(func) = func.untag() else { return false };
+    match func.elements().nth(3) {
+        Some(Ok(body)) => {
+            matches!(body.untag(), ObjectType::Cons(x) if x.car() == sym::MEMOIZE__CALL)
+        }
+        _ => false,
+    }
+}
+
+/// Make the function of SYMBOL cache its results. Later calls with arguments
+/// that are `equal' to those of an earlier call return the earlier result
+/// without calling the function, so it should have no side effects. At most
+/// LIMIT results are kept, which defaults to `memoize-default-limit'. Does
+/// nothing if the function is already memoized.
+#[defun]
+fn memoize<'ob>(
+    symbol: Symbol<'ob>,
+    limit: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    let Some(original) = symbol.func(cx) else { bail!(LispError::void_function(symbol, cx)) };
+    if is_memoized(original) {
+        return Ok(symbol);
+    }
+    if matches!(original.untag(), FunctionType::Cons(x) if x.car() == sym::MACRO) {
+        bail!("Can't memoize a macro: {symbol}");
+    }
+    let limit = match limit {
+        Some(limit) => limit,
+        None => match env.vars.get(sym::MEMOIZE_DEFAULT_LIMIT) {
+            Some(limit) => limit.bind(cx).try_into()?,
+            None => 0,
+        },
+    };
+    let table = cx.add(HashTable::with_hasher(std::hash::BuildHasherDefault::default()));
+    let memo = list![original, table, limit as i64; cx];
+    crate::data::put(symbol, sym::MEMOIZE__MEMO, memo, env);
+    // (closure (t) (&rest args) (memoize--call 'SYMBOL args))
+    let call = list![sym::MEMOIZE__CALL, list![sym::QUOTE, symbol; cx], sym::ARGS; cx];
+    let arglist = list![sym::AND_REST, sym::ARGS; cx];
+    let definition = list![sym::CLOSURE, list![true; cx], arglist, call; cx];
+    crate::data::fset(symbol, definition)?;
+    Ok(symbol)
+}
+
+/// Restore the definition SYMBOL had before it was memoized and drop its
+/// cached results.
+#[defun]
+fn unmemoize<'ob>(symbol: Symbol<'ob>, env: &mut Rt<Env>, cx: &'ob Context) -> Result<Symbol<'ob>> {
+    if let Some(memo) = memo(symbol, env, cx)? {
+        if symbol.func(cx).is_some_and(is_memoized) {
+            crate::data::fset(symbol, memo.original.into())?;
+        }
+        crate::data::put(symbol, sym::MEMOIZE__MEMO, NIL, env);
+    }
+    Ok(symbol)
+}
+
+/// Return the result cached for `args` in `table`, if any.
+fn lookup<'ob>(
+    table: &'ob LispHashTable,
+    hash: i64,
+    args: Object,
+    cx: &Context,
+) -> Result<Option<Object<'ob>>> {
+    let Some(bucket) = table.get(cx.add(hash)) else { return Ok(None) };
+    for entry in bucket.as_list()? {
+        if let ObjectType::Cons(entry) = entry?.untag() {
+            if entry.car() == args {
+                return Ok(Some(entry.cdr()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn remember<'ob>(
+    memo: &Memo<'ob>,
+    hash: i64,
+    args: Object<'ob>,
+    result: Object<'ob>,
+    cx: &'ob Context,
+) {
+    let key = cx.add(hash);
+    let bucket = memo.table.get(key).unwrap_or(NIL);
+    let entry = Cons::new(args, result, cx);
+    memo.table.insert(key, Cons::new(entry, bucket, cx).into());
+    while memo.table.len() > memo.limit {
+        let Some((oldest, _)) = memo.table.get_index(0) else { break };
+        memo.table.shift_remove(oldest);
+    }
+}
+
+/// Return the result of calling the original function of SYMBOL with ARGS,
+/// from the cache if it was called with `equal' arguments before. This is the
+/// body of every memoized function.
+#[defun]
+fn memoize__call<'ob>(
+    symbol: &Rto<Gc<Symbol>>,
+    args: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let Some(memo) = memo(symbol.untag(cx), env, cx)? else {
+        bail!("{} is not memoized", symbol.untag(cx))
+    };
+    let hash = crate::fns::sxhash_equal(args.bind(cx));
+    if let Some(result) = lookup(memo.table, hash, args.bind(cx), cx)? {
+        return Ok(result);
+    }
+    let original = memo.original;
+    root!(original, cx);
+    let result = {
+        let frame = &mut CallFrame::new(env);
+        for arg in args.bind(cx).as_list()? {
+            frame.push_arg(arg?);
+        }
+        rebind!(original.call(frame, None, cx)?)
+    };
+    // The call might have unmemoized the function
+    if let Some(memo) = memo(symbol.untag(cx), env, cx)? {
+        remember(&memo, hash, args.bind(cx), result, cx);
+    }
+    Ok(result)
+}
+
+defsym!(MEMOIZE__MEMO);
+defsym!(ARGS);
+defvar!(MEMOIZE_DEFAULT_LIMIT, 1000);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp_with_vars;
+
+    #[test]
+    fn test_memoize() {
+        assert_lisp_with_vars(
+            "(progn
+               (defvar memo-calls 0)
+               (fset 'memo-add (lambda (x y) (setq memo-calls (1+ memo-calls)) (list x y)))
+               (memoize 'memo-add)
+               (memoize 'memo-add)
+               (let ((a (memo-add \"a\" '(1 2)))
+                     (b (memo-add \"a\" (list 1 2)))
+                     (c (memo-add \"b\" nil)))
+                 (unmemoize 'memo-add)
+                 (memo-add \"a\" '(1 2))
+                 (list (eq a b) c memo-calls)))",
+            "(t (\"b\" nil) 3)",
+        );
+        assert_lisp_with_vars(
+            "(progn
+               (defvar memo-calls 0)
+               (fset 'memo-id (lambda (x) (setq memo-calls (1+ memo-calls)) x))
+               (memoize 'memo-id 2)
+               (memo-id 1) (memo-id 2) (memo-id 3) (memo-id 3) (memo-id 1)
+               (list memo-calls (= (sxhash-equal (list 1 \"a\")) (sxhash-equal (list 1 \"a\")))))",
+            "(4 t)",
+        );
+        assert_lisp_with_vars(
+            "(progn
+               (defvar memo-calls 0)
+               (fset 'memo-big (lambda (x) (setq memo-calls (1+ memo-calls)) x))
+               (memoize 'memo-big)
+               (memo-big (* most-positive-fixnum 4))
+               (memo-big (* most-positive-fixnum 4))
+               memo-calls)",
+            "1",
+        );
+        assert_lisp_with_vars(
+            "(condition-case err (memoize 'memo-undefined) (void-function err))",
+            "(void-function memo-undefined)",
+        );
+    }
+}
@@ -0,0 +1,646 @@
+//! Reading input from the minibuffer and completing it.
+//!
+//! There is no interactive minibuffer, so input is read a line at a time
+//! from `standard-input', the same as Emacs does in batch mode. The prompt
+//! is only printed when reading from stdin.
+//!
+//! A completion collection is one of:
+//!
+//! - A list of strings or symbols, or an alist whose keys are strings or
+//!   symbols. The predicate is called with each element.
+//! - A hash table whose keys are strings or symbols. The predicate is called
+//!   with each key and value.
+//! - An obarray vector, such as the value of `obarray'. Every interned symbol
+//!   is a candidate, since symbols are kept in one global table rather than
+//!   in the vector. The predicate is called with each symbol.
+//! - A function, which is called with the string, the predicate and the kind
+//!   of completion wanted, and does the completion itself.
+use crate::core::{
+    env::{sym, Env, INTERNED_SYMBOLS},
+    error::{Type, TypeError},
+    gc::{Block, Context, Rt, Rto},
+    object::{
+        Function, Gc, IntoObject, LispString, LispVec, Object, ObjectType, OptionalFlag, Symbol,
+        NIL, TRUE,
+    },
+};
+use crate::eval::EvalError;
+use crate::fns::{equal, slice_into_list};
+use crate::print::{Stdout, Stream};
+use crate::search::lisp_regex_to_rust;
+use anyhow::{bail, Result};
+use fancy_regex::Regex;
+use rune_core::macros::{call, list, rebind, root};
+use rune_macros::defun;
+
+/// The initial value of `obarray'. Completing over any vector completes over
+/// the interned symbols.
+pub(crate) struct Obarray;
+
+impl IntoObject for Obarray {
+    type Out<'ob> = &'ob LispVec;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        vec![Object::from(0)].into_obj(block)
+    }
+}
+
+fn chars_eq(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+/// The number of leading characters `a` and `b` have in common.
+fn common_prefix(a: &str, b: &str, ignore_case: bool) -> usize {
+    a.chars()
+        .zip(b.chars())
+        .take_while(|(a, b)| chars_eq(*a, *b, ignore_case))
+        .count()
+}
+
+/// The result of `try-completion'.
+#[derive(Debug, PartialEq)]
+pub(crate) enum TryCompletion {
+    NoMatch,
+    /// The string is the only completion.
+    Unique,
+    /// The longest prefix the completions have in common.
+    Prefix(String),
+}
+
+/// Complete `string` to the longest prefix of `names`, which all start with
+/// it.
+pub(crate) fn try_names(string: &str, names: &[String], ignore_case: bool) -> TryCompletion {
+    let len = |x: &str| x.chars().count();
+    let Some((first, rest)) = names.split_first() else { return TryCompletion::NoMatch };
+    let mut best = first.as_str();
+    let mut size = len(best);
+    let mut count = 1;
+    for name in rest {
+        let common = common_prefix(best, name, ignore_case).min(size);
+        if ignore_case {
+            // Prefer a completion that ends at the common prefix, and then
+            // one that matches the case of the string.
+            let exact = |x: &str| len(x) == common;
+            let same_case = |x: &str| x.starts_with(string);
+            if (exact(name) && common < len(best))
+                || (exact(name) == exact(best) && same_case(name) && !same_case(best))
+            {
+                best = name;
+            }
+        }
+        // don't count the same name more than once
+        if size != len(name) || size != common {
+            count += 1;
+        }
+        size = common;
+    }
+    // if no text was added don't change the case of the string
+    if ignore_case && size == len(string) && len(best) > size {
+        return TryCompletion::Prefix(string.to_owned());
+    }
+    if count == 1 && best == string {
+        return TryCompletion::Unique;
+    }
+    TryCompletion::Prefix(best.chars().take(size).collect())
+}
+
+/// Whether `collection` is a function that does completion itself.
+pub(crate) fn is_function(collection: Object) -> bool {
+    match collection.untag() {
+        ObjectType::NIL | ObjectType::Vec(_) | ObjectType::HashTable(_) => false,
+        ObjectType::Cons(_) => crate::data::functionp(collection),
+        _ => true,
+    }
+}
+
+/// The name of a completion candidate, if `key` can be one.
+pub(crate) fn candidate_name(key: Object) -> Option<String> {
+    match key.untag() {
+        ObjectType::String(x) => Some(x.to_string()),
+        ObjectType::Symbol(x) => Some(x.name().to_owned()),
+        _ => None,
+    }
+}
+
+pub(crate) fn ignore_case(env: &Rt<Env>, cx: &Context) -> bool {
+    env.vars.get(sym::COMPLETION_IGNORE_CASE).is_some_and(|x| !x.bind(cx).is_nil())
+}
+
+/// Return the names in `collection` that complete `string`, or that are
+/// equal to it if `exact`. Names must also match every regexp in
+/// `completion-regexp-list' and satisfy `predicate`.
+pub(crate) fn completions(
+    string: &str,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    exact: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Vec<String>> {
+    let ignore_case = ignore_case(env, cx);
+    let mut regexps = Vec::new();
+    if let Some(list) = env.vars.get(sym::COMPLETION_REGEXP_LIST) {
+        for regexp in list.bind(cx).as_list()? {
+            let regexp: &str = regexp?.try_into()?;
+            let flags = if ignore_case { "(?i)" } else { "" };
+            regexps.push(Regex::new(&format!("{flags}{}", lisp_regex_to_rust(regexp)))?);
+        }
+    }
+    let string_len = string.chars().count();
+    let matches = |name: &str| {
+        let common = common_prefix(name, string, ignore_case);
+        common == string_len
+            && (!exact || name.chars().count() == string_len)
+            && regexps.iter().all(|x| x.is_match(name).unwrap_or(false))
+    };
+
+    let mut names = Vec::new();
+    // the arguments of the predicate, two for each name
+    root!(args, new(Vec), cx);
+    let mut hash_table = false;
+    match collection.bind(cx).untag() {
+        ObjectType::HashTable(table) => {
+            hash_table = true;
+            for i in 0..table.len() {
+                let Some((key, value)) = table.get_index(i) else { continue };
+                if let Some(name) = candidate_name(key).filter(|x| matches(x)) {
+                    names.push(name);
+                    args.push(key);
+                    args.push(value);
+                }
+            }
+        }
+        ObjectType::Vec(_) => {
+            let map = INTERNED_SYMBOLS.lock().unwrap();
+            for symbol in map.symbols() {
+                if matches(symbol.name()) {
+                    names.push(symbol.name().to_owned());
+                    args.push(cx.bind(symbol));
+                    args.push(NIL);
+                }
+            }
+        }
+        _ => {
+            for elem in collection.bind(cx).as_list()? {
+                let elem = elem?;
+                let key = match elem.untag() {
+                    ObjectType::Cons(cons) => cons.car(),
+                    _ => elem,
+                };
+                if let Some(name) = candidate_name(key).filter(|x| matches(x)) {
+                    names.push(name);
+                    args.push(elem);
+                    args.push(NIL);
+                }
+            }
+        }
+    }
+
+    let Some(predicate) = predicate.filter(|x| !x.bind(cx).is_nil()) else {
+        return Ok(names);
+    };
+    let predicate: Function = predicate.bind(cx).try_into()?;
+    root!(predicate, cx);
+    let mut kept = Vec::new();
+    for (i, name) in names.into_iter().enumerate() {
+        let key = args[2 * i].bind(cx);
+        let result = if hash_table {
+            let value = args[2 * i + 1].bind(cx);
+            call!(predicate, key, value; env, cx)?
+        } else {
+            call!(predicate, key; env, cx)?
+        };
+        if !result.is_nil() {
+            kept.push(name);
+        }
+    }
+    Ok(kept)
+}
+
+/// Call the completion function `collection` with `string`, `predicate` and
+/// `action`.
+pub(crate) fn call_collection<'ob>(
+    string: &Rto<Object>,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    action: Object,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let function: Function = collection.bind(cx).try_into()?;
+    root!(function, cx);
+    let predicate = predicate.map_or(NIL, |x| x.bind(cx));
+    let result = call!(function, string, predicate, action; env, cx)?;
+    Ok(rebind!(result, cx))
+}
+
+/// Return the longest common prefix of all completions of STRING in
+/// COLLECTION. COLLECTION can be a list of strings or symbols, an alist, a
+/// hash table, an obarray or a function.
+///
+/// If PREDICATE is non-nil only completions it returns non-nil for are used.
+/// It is called with the element of a list or alist, the key and value of a
+/// hash table, or the symbol of an obarray. Completions must also match every
+/// regexp in `completion-regexp-list'.
+///
+/// Return nil if there are no completions, and t if STRING is the only
+/// completion. If COLLECTION is a function it is called with STRING,
+/// PREDICATE and nil and its value is returned.
+#[defun]
+fn try_completion<'ob>(
+    string: &Rto<Object>,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    if is_function(collection.bind(cx)) {
+        return call_collection(string, collection, predicate, NIL, env, cx);
+    }
+    let name: &str = string.bind(cx).try_into()?;
+    let name = name.to_owned();
+    let names = completions(&name, collection, predicate, false, env, cx)?;
+    Ok(match try_names(&name, &names, ignore_case(env, cx)) {
+        TryCompletion::NoMatch => NIL,
+        TryCompletion::Unique => TRUE,
+        TryCompletion::Prefix(prefix) => cx.add(prefix),
+    })
+}
+
+/// Return a list of all completions of STRING in COLLECTION. COLLECTION and
+/// PREDICATE are the same as for `try-completion'. If COLLECTION is a
+/// function it is called with STRING, PREDICATE and t.
+#[defun]
+fn all_completions<'ob>(
+    string: &Rto<Object>,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    if is_function(collection.bind(cx)) {
+        return call_collection(string, collection, predicate, TRUE, env, cx);
+    }
+    let name: &str = string.bind(cx).try_into()?;
+    let name = name.to_owned();
+    let names = completions(&name, collection, predicate, false, env, cx)?;
+    let names: Vec<Object> = names.into_iter().map(|x| cx.add(x)).collect();
+    Ok(slice_into_list(&names, None, cx))
+}
+
+/// Return non-nil if STRING is a valid completion in COLLECTION.
+/// COLLECTION and PREDICATE are the same as for `try-completion'. If
+/// COLLECTION is a function it is called with STRING, PREDICATE and
+/// `lambda'.
+#[defun]
+fn test_completion<'ob>(
+    string: &Rto<Object>,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    if is_function(collection.bind(cx)) {
+        let action = sym::LAMBDA.into();
+        return call_collection(string, collection, predicate, action, env, cx);
+    }
+    let name: &str = match string.bind(cx).untag() {
+        ObjectType::String(x) => x,
+        ObjectType::Symbol(x) => x.name(),
+        x => bail!(TypeError::new(Type::String, x)),
+    };
+    let name = name.to_owned();
+    let names = completions(&name, collection, predicate, true, env, cx)?;
+    Ok(if names.is_empty() { NIL } else { TRUE })
+}
+
+/// Add `input` to the front of the history list in `var`, like
+/// `add-to-history'. Empty input and repeats of the last input are not
+/// added. Other copies of `input` are removed if `history-delete-duplicates'
+/// is non-nil, and the list is truncated to the `history-length' property
+/// of `var`, or else the value of `history-length'.
+pub(crate) fn add_to_history(
+    var: Symbol,
+    input: &str,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let history = env.vars.get(var).map_or(NIL, |x| x.bind(cx));
+    if input.is_empty() || !matches!(history.untag(), ObjectType::NIL | ObjectType::Cons(_)) {
+        return Ok(());
+    }
+    let input = cx.add(input);
+    let mut entries = history.as_list()?.collect::<Result<Vec<_>, _>>()?;
+    if entries.first().is_some_and(|x| equal(*x, input)) {
+        return Ok(());
+    }
+    if env
+        .vars
+        .get(sym::HISTORY_DELETE_DUPLICATES)
+        .is_some_and(|x| !x.bind(cx).is_nil())
+    {
+        entries.retain(|x| !equal(*x, input));
+    }
+    entries.insert(0, input);
+    let mut max = crate::data::get(var, sym::HISTORY_LENGTH, env, cx);
+    if max.is_nil() {
+        max = env.vars.get(sym::HISTORY_LENGTH).map_or(NIL, |x| x.bind(cx));
+    }
+    if let ObjectType::Int(max) = max.untag() {
+        entries.truncate(max.max(0) as usize);
+    }
+    env.set_var(var, slice_into_list(&entries, None, cx), cx)
+}
+
+/// Add `input` to the history named by `hist`, which is a symbol or (SYMBOL
+/// . POSITION). nil means `minibuffer-history' and t means no history.
+fn add_history(
+    hist: Option<&Rto<Object>>,
+    input: &str,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    if !env.vars.get(sym::HISTORY_ADD_NEW_INPUT).is_some_and(|x| !x.bind(cx).is_nil()) {
+        return Ok(());
+    }
+    let hist = hist.map_or(NIL, |x| x.bind(cx));
+    let var: Symbol = match hist.untag() {
+        ObjectType::NIL => sym::MINIBUFFER_HISTORY,
+        ObjectType::TRUE => return Ok(()),
+        ObjectType::Cons(cons) => cons.car().try_into()?,
+        _ => hist.try_into()?,
+    };
+    add_to_history(var, input, env, cx)
+}
+
+/// The string of a DEFAULT argument, which can also be a list of defaults.
+fn default_string(default: Option<&Rto<Object>>, cx: &Context) -> Option<String> {
+    let default = default?.bind(cx);
+    let default = match default.untag() {
+        ObjectType::Cons(cons) => cons.car(),
+        _ => default,
+    };
+    match default.untag() {
+        ObjectType::String(string) => Some(string.to_string()),
+        _ => None,
+    }
+}
+
+/// Read a line of input after printing `prompt`.
+fn read_input(
+    prompt: &Rto<Gc<&LispString>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<String> {
+    let stream = env.vars.get(sym::STANDARD_INPUT).map_or(TRUE, |x| x.bind(cx));
+    if matches!(stream.untag(), ObjectType::TRUE | ObjectType::NIL) {
+        Stdout.write_str(prompt.untag(cx))?;
+    }
+    let Some(input) = crate::lread::read_line(env, cx)? else {
+        let data = list!["Error reading from stdin"; cx];
+        return Err(EvalError::signal(sym::END_OF_FILE.into(), data, env).into());
+    };
+    Ok(input)
+}
+
+/// Read a line of input after printing `prompt`, and add it to the history
+/// in `hist`. If the input is empty the default is added instead.
+fn read_minibuf(
+    prompt: &Rto<Gc<&LispString>>,
+    hist: Option<&Rto<Object>>,
+    default: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<String> {
+    let input = read_input(prompt, env, cx)?;
+    let entry = match default_string(default, cx) {
+        Some(default) if input.is_empty() => default,
+        _ => input.clone(),
+    };
+    add_history(hist, &entry, env, cx)?;
+    Ok(input)
+}
+
+/// Read a string from the minibuffer, prompting with PROMPT. If READ is
+/// non-nil the input is read as a lisp object, and DEFAULT-VALUE is read
+/// when the input is empty. The input is added to the history list in HIST,
+/// which defaults to `minibuffer-history'. INITIAL-CONTENTS, KEYMAP and
+/// INHERIT-INPUT-METHOD are ignored.
+#[defun]
+#[expect(clippy::too_many_arguments)]
+fn read_from_minibuffer<'ob>(
+    prompt: &Rto<Gc<&LispString>>,
+    _initial_contents: Option<&Rto<Object>>,
+    _keymap: Option<&Rto<Object>>,
+    read: OptionalFlag,
+    hist: Option<&Rto<Object>>,
+    default_value: Option<&Rto<Object>>,
+    _inherit_input_method: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let mut input = read_minibuf(prompt, hist, default_value, env, cx)?;
+    if read.is_none() {
+        return Ok(cx.add(input));
+    }
+    if input.is_empty() {
+        input = default_string(default_value, cx).unwrap_or_default();
+    }
+    Ok(crate::reader::read(&input, cx)?.0)
+}
+
+/// Read a string from the minibuffer, prompting with PROMPT. If the input
+/// is empty return DEFAULT-VALUE, or its first element if it is a list. The
+/// input is added to the history list in HISTORY. INITIAL-INPUT and
+/// INHERIT-INPUT-METHOD are ignored.
+#[defun]
+fn read_string(
+    prompt: &Rto<Gc<&LispString>>,
+    _initial_input: Option<&Rto<Object>>,
+    history: Option<&Rto<Object>>,
+    default_value: Option<&Rto<Object>>,
+    _inherit_input_method: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<String> {
+    let input = read_minibuf(prompt, history, default_value, env, cx)?;
+    match default_string(default_value, cx) {
+        Some(default) if input.is_empty() => Ok(default),
+        _ => Ok(input),
+    }
+}
+
+/// Read a string from the minibuffer with completion, prompting with
+/// PROMPT. If the input is empty return DEF, or its first element if it is a
+/// list. The input is added to the history list in HIST.
+///
+/// If REQUIRE-MATCH is non-nil, input other than an empty string has to be
+/// a completion in COLLECTION that satisfies PREDICATE, as checked by
+/// `test-completion'. Without an interactive minibuffer there is no way to
+/// ask again or to confirm, so any other input signals an error and isn't
+/// added to the history. INITIAL-INPUT and INHERIT-INPUT-METHOD are ignored.
+#[defun]
+#[expect(clippy::too_many_arguments)]
+fn completing_read(
+    prompt: &Rto<Gc<&LispString>>,
+    collection: Option<&Rto<Object>>,
+    predicate: Option<&Rto<Object>>,
+    require_match: Option<&Rto<Object>>,
+    _initial_input: Option<&Rto<Object>>,
+    hist: Option<&Rto<Object>>,
+    def: Option<&Rto<Object>>,
+    _inherit_input_method: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<String> {
+    let input = read_input(prompt, env, cx)?;
+    let input = match default_string(def, cx) {
+        Some(default) if input.is_empty() => default,
+        _ => input,
+    };
+    if require_match.is_some_and(|x| !x.bind(cx).is_nil()) && !input.is_empty() {
+        let matched = match collection {
+            Some(collection) => {
+                let string = cx.add(input.as_str());
+                root!(string, cx);
+                !test_completion(string, collection, predicate, env, cx)?.is_nil()
+            }
+            None => false,
+        };
+        if !matched {
+            bail!("No match: {input}");
+        }
+    }
+    add_history(hist, &input, env, cx)?;
+    Ok(input)
+}
+
+defvar!(OBARRAY, crate::minibuf::Obarray);
+defvar_bool!(COMPLETION_IGNORE_CASE, false);
+defvar!(COMPLETION_REGEXP_LIST);
+defvar!(MINIBUFFER_HISTORY);
+defvar!(HISTORY_LENGTH, 100);
+defvar_bool!(HISTORY_DELETE_DUPLICATES, false);
+defvar_bool!(HISTORY_ADD_NEW_INPUT, true);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::{assert_lisp, assert_lisp_with_vars};
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| (*x).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_try_names() {
+        use TryCompletion::*;
+        assert_eq!(try_names("fo", &[], false), NoMatch);
+        assert_eq!(try_names("fo", &names(&["foo"]), false), Prefix("foo".into()));
+        assert_eq!(try_names("foo", &names(&["foo", "foo"]), false), Unique);
+        assert_eq!(try_names("f", &names(&["foobar", "foobaz"]), false), Prefix("fooba".into()));
+        assert_eq!(try_names("foo", &names(&["foo", "foobar"]), false), Prefix("foo".into()));
+        assert_eq!(try_names("fo", &names(&["FOO", "Foo"]), true), Prefix("FOO".into()));
+        assert_eq!(try_names("fo", &names(&["FOOx", "foo"]), true), Prefix("foo".into()));
+        assert_eq!(try_names("fo", &names(&["FOO", "FOOBAR"]), true), Prefix("FOO".into()));
+    }
+
+    #[test]
+    fn test_completion_collections() {
+        assert_lisp("(try-completion \"fo\" '(\"foo\" \"foobar\" \"bar\"))", "\"foo\"");
+        assert_lisp("(try-completion \"foo\" '(\"foo\"))", "t");
+        assert_lisp("(try-completion \"x\" '(\"foo\"))", "nil");
+        assert_lisp("(try-completion \"b\" '((bar . 1) (baz . 2)))", "\"ba\"");
+        assert_lisp("(all-completions \"b\" '(\"bar\" baz \"foo\" 3))", "(\"bar\" \"baz\")");
+        assert_lisp(
+            "(all-completions \"b\" '((\"bar\" . 1) (\"baz\" . 2)) (lambda (x) (= (cdr x) 2)))",
+            "(\"baz\")",
+        );
+        assert_lisp(
+            "(let ((table (make-hash-table :test 'equal))) (puthash \"apple\" 1 table) (puthash 'apricot 2 table) (puthash \"banana\" 3 table) (list (sort (all-completions \"ap\" table) #'string<) (all-completions \"a\" table (lambda (_ v) (= v 2)))))",
+            "((\"apple\" \"apricot\") (\"apricot\"))",
+        );
+        assert_lisp("(test-completion \"foo\" '(\"foo\" \"foobar\"))", "t");
+        assert_lisp("(test-completion \"fo\" '(\"foo\" \"foobar\"))", "nil");
+        assert_lisp("(test-completion \"foo\" '((\"foo\" . 1)) (lambda (x) (= (cdr x) 2)))", "nil");
+        assert_lisp(
+            "(all-completions \"x\" (lambda (string pred action) (list string pred action)))",
+            "(\"x\" nil t)",
+        );
+        assert_lisp("(test-completion \"x\" (lambda (string pred action) action))", "lambda");
+    }
+
+    #[test]
+    fn test_completion_variables() {
+        assert_lisp_with_vars(
+            "(let ((completion-ignore-case t)) (list (try-completion \"FO\" '(\"foo\" \"foobar\")) (test-completion \"FOO\" '(\"foo\"))))",
+            "(\"foo\" t)",
+        );
+        assert_lisp_with_vars(
+            "(let ((completion-regexp-list '(\"r$\"))) (all-completions \"f\" '(\"foo\" \"foobar\")))",
+            "(\"foobar\")",
+        );
+        assert_lisp_with_vars(
+            "(all-completions \"try-completio\" obarray #'fboundp)",
+            "(\"try-completion\")",
+        );
+        assert_lisp_with_vars(
+            "(all-completions \"try-completio\" obarray (lambda (x) (not (fboundp x))))",
+            "nil",
+        );
+    }
+
+    #[test]
+    fn test_read_string() {
+        assert_lisp_with_vars(
+            "(let ((standard-input \"foo\nbar\n\") (minibuffer-history nil)) (list (read-string \"? \") minibuffer-history))",
+            "(\"foo\" (\"foo\"))",
+        );
+        assert_lisp_with_vars(
+            "(let ((standard-input \"\n\") (minibuffer-history nil)) (list (read-string \"? \" nil nil '(\"a\" \"b\")) minibuffer-history))",
+            "(\"a\" (\"a\"))",
+        );
+        assert_lisp_with_vars(
+            "(let ((standard-input \"(1 2)\")) (read-from-minibuffer \"? \" nil nil t t))",
+            "(1 2)",
+        );
+        assert_lisp_with_vars(
+            "(let ((standard-input \"x\")) (completing-read \"? \" '(\"a\") nil nil nil t))",
+            "\"x\"",
+        );
+        assert_lisp_with_vars(
+            "(let ((standard-input \"ab\") (minibuffer-history nil))
+               (list (completing-read \"? \" '(\"ab\" \"cd\") nil t)
+                     (condition-case nil (completing-read \"? \" '(\"a\" \"cd\") nil t)
+                       (error 'no-match))
+                     (condition-case nil (completing-read \"? \" '(\"ab\") (lambda (x) nil) t)
+                       (error 'no-match))
+                     minibuffer-history))",
+            "(\"ab\" no-match no-match (\"ab\"))",
+        );
+        assert_lisp_with_vars(
+            "(condition-case err (let ((standard-input \"\")) (read-string \"? \")) (end-of-file (cadr err)))",
+            "\"Error reading from stdin\"",
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        assert_lisp_with_vars(
+            "(progn (defvar mb-test-history '(\"b\" \"a\")) (let ((standard-input \"c\") (history-length 2)) (read-string \"\" nil 'mb-test-history)) mb-test-history)",
+            "(\"c\" \"b\")",
+        );
+        assert_lisp_with_vars(
+            "(progn (defvar mb-test-dups '(\"b\" \"a\")) (let ((standard-input \"a\") (history-delete-duplicates t)) (read-string \"\" nil 'mb-test-dups)) mb-test-dups)",
+            "(\"a\" \"b\")",
+        );
+        assert_lisp_with_vars(
+            "(progn (defvar mb-test-repeat '(\"a\")) (put 'mb-test-repeat 'history-length 1) (let ((standard-input \"a\")) (read-string \"\" nil '(mb-test-repeat . 1))) (let ((standard-input \"b\")) (read-string \"\" nil 'mb-test-repeat)) mb-test-repeat)",
+            "(\"b\")",
+        );
+        assert_lisp_with_vars(
+            "(let ((standard-input \"a\") (minibuffer-history nil) (history-add-new-input nil)) (read-string \"\") minibuffer-history)",
+            "nil",
+        );
+    }
+}
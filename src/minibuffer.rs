@@ -0,0 +1,425 @@
+//! Completion styles.
+//!
+//! `completion-all-completions' and `completion-try-completion' match the
+//! input against the candidates of a collection with each style in
+//! `completion-styles' in turn, and use the first style that matches
+//! anything. The styles are:
+//!
+//! - `basic': candidates that start with the text before point and contain
+//!   the text after it.
+//! - `substring': candidates that contain the text before point followed by
+//!   the text after it.
+//! - `flex': candidates that contain the characters of the input in order.
+//!
+//! Other styles in `completion-styles' are skipped.
+//!
+//! Matches are sorted by score, then by length and then alphabetically, so
+//! the order does not depend on the collection. The more of a candidate is
+//! matched and the closer together the matched characters are, the higher
+//! the score. Matches that don't start at the beginning score half as much.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Gc, LispString, Object, ObjectType, Symbol, NIL, TRUE},
+};
+use crate::minibuf::{
+    call_collection, candidate_name, completions, ignore_case, is_function, try_names,
+    TryCompletion,
+};
+use anyhow::{ensure, Result};
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Basic,
+    Substring,
+    Flex,
+}
+
+impl Style {
+    /// The style named `symbol`, or `None` if it isn't implemented.
+    fn from_symbol(symbol: Symbol) -> Option<Self> {
+        match symbol {
+            sym::BASIC => Some(Self::Basic),
+            sym::SUBSTRING => Some(Self::Substring),
+            sym::FLEX => Some(Self::Flex),
+            _ => None,
+        }
+    }
+
+    /// The positions of the characters of `candidate` that match `before`
+    /// and `after` point, or `None` if it doesn't match.
+    fn positions(self, before: &[char], after: &[char], candidate: &[char]) -> Option<Vec<usize>> {
+        let start = match self {
+            Self::Basic => candidate.starts_with(before).then_some(0..before.len())?,
+            Self::Substring => find(candidate, before, 0)?,
+            Self::Flex => return flex(&[before, after].concat(), candidate),
+        };
+        let rest = find(candidate, after, start.end)?;
+        Some(start.chain(rest).collect())
+    }
+}
+
+/// The range of the first occurrence of `needle` in `haystack` at or after
+/// `from`.
+fn find(haystack: &[char], needle: &[char], from: usize) -> Option<Range<usize>> {
+    if needle.is_empty() {
+        return Some(from..from);
+    }
+    let last = haystack.len().checked_sub(needle.len())?;
+    let start = (from..=last).find(|&i| haystack[i..].starts_with(needle))?;
+    Some(start..start + needle.len())
+}
+
+/// The positions of the characters of `pattern` in `candidate`, picking the
+/// match that is closest together.
+fn flex(pattern: &[char], candidate: &[char]) -> Option<Vec<usize>> {
+    let Some((&first, rest)) = pattern.split_first() else { return Some(Vec::new()) };
+    let span = |x: &[usize]| x[x.len() - 1] - x[0];
+    let mut best: Option<Vec<usize>> = None;
+    for start in (0..candidate.len()).filter(|&i| candidate[i] == first) {
+        let mut positions = vec![start];
+        for chr in rest {
+            let from = positions[positions.len() - 1] + 1;
+            match candidate[from..].iter().position(|x| x == chr) {
+                Some(i) => positions.push(from + i),
+                // later starts can't match either
+                None => return best,
+            }
+        }
+        if !best.as_ref().is_some_and(|x| span(x) <= span(&positions)) {
+            best = Some(positions);
+        }
+    }
+    best
+}
+
+/// Score a match at `positions` in a candidate of `len` characters.
+fn score(positions: &[usize], len: usize) -> f64 {
+    let (Some(&first), Some(&last)) = (positions.first(), positions.last()) else {
+        return 0.0;
+    };
+    let matched = positions.len() as f64;
+    let score = (matched / len as f64) * (matched / (last - first + 1) as f64);
+    if first == 0 {
+        score
+    } else {
+        score / 2.0
+    }
+}
+
+fn chars(string: &str, ignore_case: bool) -> Vec<char> {
+    let fold = |x: char| if ignore_case { x.to_lowercase().next().unwrap_or(x) } else { x };
+    string.chars().map(fold).collect()
+}
+
+/// Return the `candidates` that match `before` and `after` point with
+/// `style`, best first and without duplicates.
+fn style_matches(
+    style: Style,
+    before: &str,
+    after: &str,
+    candidates: &[String],
+    ignore_case: bool,
+) -> Vec<String> {
+    let before = chars(before, ignore_case);
+    let after = chars(after, ignore_case);
+    let mut matches: Vec<_> = candidates
+        .iter()
+        .filter_map(|candidate| {
+            let chars = chars(candidate, ignore_case);
+            let positions = style.positions(&before, &after, &chars)?;
+            Some((score(&positions, chars.len()), chars.len(), candidate))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(b.2)));
+    let mut matches: Vec<String> = matches.into_iter().map(|x| x.2.clone()).collect();
+    matches.dedup();
+    matches
+}
+
+/// Return the boundaries of the field of `string` and `suffix` that
+/// `collection` completes, in characters from the start of `string` and of
+/// `suffix`.
+fn boundaries(
+    string: &str,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    suffix: &str,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<(usize, usize)> {
+    let default = (0, suffix.chars().count());
+    if !is_function(collection.bind(cx)) {
+        return Ok(default);
+    }
+    let function: Function = collection.bind(cx).try_into()?;
+    root!(function, cx);
+    let string = cx.add(string);
+    root!(string, cx);
+    let action = Cons::new(sym::BOUNDARIES, cx.add(suffix), cx);
+    root!(action, cx);
+    let predicate = predicate.map_or(NIL, |x| x.bind(cx));
+    let result = call!(function, &*string, predicate, &*action; env, cx)?;
+    // (boundaries START . END)
+    let ObjectType::Cons(result) = result.untag() else { return Ok(default) };
+    match result.cdr().untag() {
+        ObjectType::Cons(bounds) if result.car() == sym::BOUNDARIES => {
+            Ok((bounds.car().try_into()?, bounds.cdr().try_into()?))
+        }
+        _ => Ok(default),
+    }
+}
+
+/// Return the names of all completions of `string` in `collection`.
+fn all_names(
+    string: &str,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Vec<String>> {
+    if !is_function(collection.bind(cx)) {
+        return completions(string, collection, predicate, false, env, cx);
+    }
+    let string = cx.add(string);
+    root!(string, cx);
+    let names = call_collection(string, collection, predicate, TRUE, env, cx)?;
+    let mut result = Vec::new();
+    for name in names.as_list()? {
+        result.extend(candidate_name(name?));
+    }
+    Ok(result)
+}
+
+/// The input of a completion, split at point and at the boundaries of the
+/// field being completed.
+struct Input {
+    /// The text before the field.
+    prefix: String,
+    before: String,
+    after: String,
+    /// The text after the field.
+    suffix: String,
+}
+
+/// The matches of the first style in `completion-styles' that matches any
+/// candidate.
+struct Matches {
+    input: Input,
+    style: Style,
+    matches: Vec<String>,
+}
+
+fn complete(
+    string: &Rto<Gc<&LispString>>,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    point: usize,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<Option<Matches>> {
+    let string: &str = string.untag(cx);
+    let string: Vec<char> = string.chars().collect();
+    ensure!(point <= string.len(), "Point {point} is outside of the input");
+    let before: String = string[..point].iter().collect();
+    let after: String = string[point..].iter().collect();
+    let (start, end) = boundaries(&before, collection, predicate, &after, env, cx)?;
+    ensure!(start <= point && end <= string.len() - point, "Invalid completion boundaries");
+    let input = Input {
+        prefix: string[..start].iter().collect(),
+        before: string[start..point].iter().collect(),
+        after: string[point..point + end].iter().collect(),
+        suffix: string[point + end..].iter().collect(),
+    };
+    let candidates = all_names(&input.prefix, collection, predicate, env, cx)?;
+    let ignore_case = ignore_case(env, cx);
+    let styles = match env.vars.get(sym::COMPLETION_STYLES) {
+        Some(styles) => styles.bind(cx),
+        None => NIL,
+    };
+    for style in styles.as_list()? {
+        // styles like `partial-completion' that aren't implemented are skipped
+        let Some(style) = Style::from_symbol(style?.try_into()?) else { continue };
+        let matches = style_matches(style, &input.before, &input.after, &candidates, ignore_case);
+        if !matches.is_empty() {
+            return Ok(Some(Matches { input, style, matches }));
+        }
+    }
+    Ok(None)
+}
+
+/// Return the start and end of the field of STRING and SUFFIX that
+/// COLLECTION completes, as (START . END). START is the position in STRING
+/// the field starts at and END the position in SUFFIX it ends at. If
+/// COLLECTION is a function it is called with STRING, PREDICATE and
+/// \(boundaries . SUFFIX), otherwise the field is all of STRING and SUFFIX.
+#[defun]
+fn completion_boundaries<'ob>(
+    string: &Rto<Gc<&LispString>>,
+    collection: &Rto<Object>,
+    predicate: Option<&Rto<Object>>,
+    suffix: &Rto<Gc<&LispString>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let string: &str = string.untag(cx);
+    let string = string.to_owned();
+    let suffix: &str = suffix.untag(cx);
+    let suffix = suffix.to_owned();
+    let (start, end) = boundaries(&string, collection, predicate, &suffix, env, cx)?;
+    Ok(Cons::new(start, end, cx).into())
+}
+
+/// Return the completions of STRING in COLLECTION with the first style in
+/// `completion-styles' that matches any. POINT is the position of point in
+/// STRING and PREDICATE is the same as for `all-completions'.
+///
+/// The completions are sorted best first. The last cdr of the list is the
+/// number of characters at the start of STRING that are not part of the
+/// completions. Return nil if no style matches. METADATA is ignored.
+#[defun]
+fn completion_all_completions<'ob>(
+    string: &Rto<Gc<&LispString>>,
+    collection: &Rto<Object>,
+    predicate: &Rto<Object>,
+    point: usize,
+    _metadata: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let Some(found) = complete(string, collection, Some(predicate), point, env, cx)? else {
+        return Ok(NIL);
+    };
+    let base_size = found.input.prefix.chars().count();
+    let matches: Vec<Object> = found.matches.into_iter().map(|x| cx.add(x)).collect();
+    Ok(crate::fns::slice_into_list(&matches, Some(base_size.into()), cx))
+}
+
+/// Complete STRING in COLLECTION with the first style in `completion-styles'
+/// that matches any completion. POINT is the position of point in STRING and
+/// PREDICATE is the same as for `all-completions'.
+///
+/// Return nil if no style matches and t if STRING is the only completion.
+/// Otherwise return (NEWSTRING . NEWPOINT): the `basic' style completes to
+/// the longest common prefix of the matches, and the other styles only
+/// complete a single match. METADATA is ignored.
+#[defun]
+fn completion_try_completion<'ob>(
+    string: &Rto<Gc<&LispString>>,
+    collection: &Rto<Object>,
+    predicate: &Rto<Object>,
+    point: usize,
+    _metadata: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let Some(Matches { input, style, matches }) =
+        complete(string, collection, Some(predicate), point, env, cx)?
+    else {
+        return Ok(NIL);
+    };
+    let field = format!("{}{}", input.before, input.after);
+    if matches.len() == 1 && matches[0] == field {
+        return Ok(TRUE);
+    }
+    let completed = match style {
+        Style::Basic if input.after.is_empty() => {
+            match try_names(&input.before, &matches, ignore_case(env, cx)) {
+                TryCompletion::Prefix(prefix) => Some(prefix),
+                _ => None,
+            }
+        }
+        _ if matches.len() == 1 => matches.into_iter().next(),
+        _ => None,
+    };
+    let (field, point) = match completed {
+        Some(completed) => {
+            let point = input.prefix.chars().count() + completed.chars().count();
+            (completed, point)
+        }
+        None => (field, point),
+    };
+    let string = format!("{}{field}{}", input.prefix, input.suffix);
+    Ok(Cons::new(string, point, cx).into())
+}
+
+defsym!(BASIC);
+defsym!(FLEX);
+defsym!(BOUNDARIES);
+defvar!(COMPLETION_STYLES, list![sym::BASIC, sym::SUBSTRING, sym::FLEX]);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp_with_vars;
+
+    fn matches(style: Style, before: &str, after: &str, candidates: &[&str]) -> Vec<String> {
+        let candidates: Vec<String> = candidates.iter().map(|x| (*x).to_owned()).collect();
+        style_matches(style, before, after, &candidates, false)
+    }
+
+    #[test]
+    fn test_styles() {
+        let candidates = ["foobar", "barfoo", "fxoxo", "foo", "fo"];
+        assert_eq!(matches(Style::Basic, "fo", "", &candidates), ["fo", "foo", "foobar"]);
+        assert_eq!(matches(Style::Basic, "f", "bar", &candidates), ["foobar"]);
+        assert_eq!(matches(Style::Substring, "foo", "", &candidates), ["foo", "foobar", "barfoo"]);
+        assert_eq!(
+            matches(Style::Flex, "foo", "", &candidates),
+            ["foo", "foobar", "fxoxo", "barfoo"]
+        );
+        assert_eq!(matches(Style::Flex, "fb", "", &candidates), ["foobar"]);
+        assert!(matches(Style::Flex, "of", "", &["fo"]).is_empty());
+    }
+
+    #[test]
+    fn test_flex() {
+        let chars = |x: &str| x.chars().collect::<Vec<_>>();
+        // the closest match is used
+        assert_eq!(flex(&chars("ab"), &chars("a-a-ab")), Some(vec![4, 5]));
+        assert_eq!(flex(&chars(""), &chars("abc")), Some(vec![]));
+        assert_eq!(flex(&chars("abc"), &chars("ab")), None);
+        assert!(score(&[0, 1], 2) > score(&[0, 2], 3));
+        assert!(score(&[0, 1], 4) > score(&[2, 3], 4));
+    }
+
+    #[test]
+    fn test_completion_styles() {
+        assert_lisp_with_vars(
+            "(completion-all-completions \"fo\" '(\"bar\" \"foo\" \"xfoo\") nil 2)",
+            "(\"foo\" . 0)",
+        );
+        assert_lisp_with_vars(
+            "(completion-all-completions \"oo\" '(\"bar\" \"foo\" \"xfoo\") nil 2)",
+            "(\"foo\" \"xfoo\" . 0)",
+        );
+        assert_lisp_with_vars(
+            "(let ((completion-styles '(flex))) (completion-all-completions \"fr\" '(\"foobar\" \"fr\" \"xfoo\") nil 2))",
+            "(\"fr\" \"foobar\" . 0)",
+        );
+        assert_lisp_with_vars("(completion-all-completions \"zz\" '(\"foo\") nil 2)", "nil");
+        assert_lisp_with_vars(
+            "(let ((completion-styles '(partial-completion emacs22 substring)))
+               (completion-all-completions \"oo\" '(\"foo\") nil 2))",
+            "(\"foo\" . 0)",
+        );
+        assert_lisp_with_vars(
+            "(completion-try-completion \"fo\" '(\"foobar\" \"foobaz\") nil 2)",
+            "(\"fooba\" . 5)",
+        );
+        assert_lisp_with_vars("(completion-try-completion \"foo\" '(\"foo\") nil 3)", "t");
+        assert_lisp_with_vars(
+            "(completion-try-completion \"bz\" '(\"foobaz\" \"foobar\") nil 2)",
+            "(\"foobaz\" . 6)",
+        );
+        assert_lisp_with_vars("(completion-boundaries \"ab\" '(\"abc\") nil \"cd\")", "(0 . 2)");
+        assert_lisp_with_vars(
+            "(let ((table (lambda (string _pred action) (if (eq (car-safe action) 'boundaries) '(boundaries 2 . 0) (all-completions (substring string 2) '(\"xy\" \"xz\")))))) (list (completion-boundaries \"a/x\" table nil \"\") (completion-all-completions \"a/x\" table nil 3)))",
+            "((2 . 0) (\"xy\" \"xz\" . 2))",
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Experimental namespaced modules.
+//!
+//! A file that contains `(rune-module NAME EXPORTS...)' has the symbols read
+//! after that form resolved in a private obarray. Names that are already
+//! global (like `defun' or `car') and the EXPORTS resolve to the global
+//! symbols, while any new names become uninterned symbols private to the
+//! module. Files without the form are loaded as plain elisp.
+//!
+//! Obarrays are hash tables mapping symbol names to symbols.
+use crate::core::{
+    env::{sym, ArgSlice, Env, INTERNED_SYMBOLS},
+    gc::{Context, Rt},
+    object::{HashTable, LispHashTable, Object, ObjectType, Symbol, WithLifetime},
+};
+use crate::data::put;
+use crate::permissions::{check, Capability};
+use anyhow::Result;
+use rune_macros::defun;
+
+/// Return the symbol named `name` in `obarray`, adding a new uninterned symbol
+/// if there is none.
+pub(crate) fn intern_in<'ob>(name: &str, obarray: &LispHashTable, cx: &'ob Context) -> Symbol<'ob> {
+    let key = cx.add(name);
+    if let Some(ObjectType::Symbol(sym)) = obarray.get(key).map(Object::untag) {
+        return cx.bind(sym);
+    }
+    let sym = Symbol::new_uninterned(name, cx);
+    obarray.insert(key, sym.into());
+    sym
+}
+
+/// Return the symbol named `name` in `obarray` if there is one.
+pub(crate) fn intern_soft_in<'ob>(
+    name: &str,
+    obarray: &LispHashTable,
+    cx: &'ob Context,
+) -> Option<Symbol<'ob>> {
+    match obarray.get(cx.add(name))?.untag() {
+        ObjectType::Symbol(sym) => Some(cx.bind(sym)),
+        _ => None,
+    }
+}
+
+/// Resolve the symbol named `name` read inside the module with `obarray`.
+/// Symbols in the module are used first, then keywords and existing global
+/// symbols. Anything else is added to the module.
+pub(crate) fn module_intern<'ob>(
+    name: &str,
+    obarray: &LispHashTable,
+    cx: &'ob Context,
+) -> Symbol<'ob> {
+    if let Some(sym) = intern_soft_in(name, obarray, cx) {
+        return sym;
+    }
+    if name.starts_with(':') {
+        return crate::core::env::intern(name, cx);
+    }
+    let global = INTERNED_SYMBOLS.lock().unwrap().get(name).map(|x| unsafe { x.with_lifetime() });
+    match global {
+        Some(sym) => cx.bind(sym),
+        None => intern_in(name, obarray, cx),
+    }
+}
+
+#[defun]
+fn obarray_make<'ob>(_size: Option<usize>, cx: &'ob Context) -> Object<'ob> {
+    cx.add(HashTable::with_hasher(std::hash::BuildHasherDefault::default()))
+}
+
+#[defun]
+fn obarrayp(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::HashTable(_))
+}
+
+/// Read the rest of the file being loaded in a new module NAME. EXPORTS are
+/// the names in the module that refer to the global symbols. The module's
+/// obarray is stored in the `rune-module-obarray' property of NAME. Signals
+/// `permission-denied' if loading is not permitted.
+#[defun]
+fn rune_module<'ob>(
+    name: Symbol<'ob>,
+    exports: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Symbol<'ob>> {
+    check(Capability::Load, env, cx)?;
+    let obarray = obarray_make(None, cx);
+    let ObjectType::HashTable(table) = obarray.untag() else { unreachable!() };
+    for export in env.stack.arg_slice(exports) {
+        let export: Symbol = export.bind(cx).try_into()?;
+        table.insert(cx.add(export.name()), export.into());
+    }
+    env.set_var(sym::RUNE_MODULE__OBARRAY, obarray, cx)?;
+    put(name, sym::RUNE_MODULE_OBARRAY, obarray, env);
+    Ok(name)
+}
+
+defvar!(RUNE_MODULE__OBARRAY);
+defsym!(RUNE_MODULE_OBARRAY);
+
+#[cfg(test)]
+mod test {
+    use crate::core::{
+        env::{sym, Env},
+        gc::{Context, RootSet},
+    };
+    use crate::lread::{intern_soft, load_internal};
+    use crate::{interpreter, reader};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_module() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        sym::init_symbols();
+        let src = "(rune-module 'module-test 'module-test-public)
+                   (defalias 'module-test-public #'(lambda () (module-test-private)))
+                   (defalias 'module-test-private #'(lambda () 7))";
+        load_internal(src, cx, env).unwrap();
+        let public = intern_soft(cx.add("module-test-public"), None, cx).unwrap();
+        assert_eq!(public.name(), "module-test-public");
+        let private = intern_soft(cx.add("module-test-private"), None, cx).unwrap();
+        assert!(private.is_nil());
+
+        let form = "(let ((table (get 'module-test 'rune-module-obarray)))
+                      (list (module-test-public)
+                            (symbol-name (intern-soft \"module-test-private\" table))
+                            (eq (intern \"module-test-new\" table)
+                                (intern \"module-test-new\" table))))";
+        let obj = reader::read(form, cx).unwrap().0;
+        root!(obj, cx);
+        let result = interpreter::eval(obj, None, env, cx).unwrap();
+        assert_eq!(result.to_string(), "(7 \"module-test-private\" t)");
+    }
+}
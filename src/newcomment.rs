@@ -0,0 +1,303 @@
+//! Commenting and uncommenting code.
+//!
+//! Comments are recognized by the strings in `comment-start' and
+//! `comment-end' rather than by the [syntax table](crate::syntax), which
+//! ignores the flags that make two character delimiters like "/*" comment
+//! starters. A line is commented if it starts with `comment-start' after its
+//! indentation. If `comment-end' is empty, comments end at the end of the
+//! line, and otherwise they are closed on the same line, like "/* foo */".
+//!
+//! Commenting a region puts the comment starts of all its lines in the same
+//! column, the smallest indentation of any line in the region, so that the
+//! code keeps its shape. Blank lines are left alone.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{ObjectType, Symbol, NIL},
+};
+use crate::data::LispError;
+use crate::insdel::replace_region;
+use anyhow::{bail, Result};
+use rune_macros::defun;
+
+/// The comment syntax of the current buffer, read from the environment.
+struct CommentSyntax {
+    /// `comment-start' without trailing whitespace.
+    start: String,
+    /// `comment-end' without leading whitespace.
+    end: String,
+    padding: String,
+    add: usize,
+}
+
+impl CommentSyntax {
+    fn new(env: &Rt<Env>, cx: &Context) -> Result<Self> {
+        let var = |name: Symbol| env.vars.get(name).map_or(NIL, |x| x.bind(cx));
+        let start = match var(sym::COMMENT_START).untag() {
+            ObjectType::String(start) if !start.trim().is_empty() => start.trim_end().to_owned(),
+            _ => bail!("No comment syntax is defined"),
+        };
+        let end = match var(sym::COMMENT_END).untag() {
+            ObjectType::String(end) => end.trim_start().to_owned(),
+            _ => String::new(),
+        };
+        let padding = match var(sym::COMMENT_PADDING).untag() {
+            ObjectType::String(padding) => padding.to_string(),
+            ObjectType::Int(n) => " ".repeat(n.max(0) as usize),
+            _ => String::new(),
+        };
+        let add = match var(sym::COMMENT_ADD).untag() {
+            ObjectType::Int(n) => n.max(0) as usize,
+            _ => 0,
+        };
+        Ok(Self { start, end, padding, add })
+    }
+
+    /// How many comment starts to put before a line by default.
+    /// `comment-add' only applies to comments that end at the end of the
+    /// line.
+    fn default_count(&self) -> usize {
+        if self.end.is_empty() {
+            1 + self.add
+        } else {
+            1
+        }
+    }
+
+    /// The text of `line` after its indentation, if it is commented.
+    fn comment_body<'a>(&self, line: &'a str) -> Option<&'a str> {
+        line.trim_start().strip_prefix(self.start.as_str())
+    }
+
+    /// Comment `line` with `count` comment starts at byte `column`.
+    fn comment(&self, line: &str, column: usize, count: usize) -> String {
+        let (indent, code) = line.split_at(column);
+        let mut new = format!("{indent}{}{}{code}", self.start.repeat(count), self.padding);
+        if !self.end.is_empty() {
+            new.push_str(&self.padding);
+            new.push_str(&self.end);
+        }
+        new
+    }
+
+    /// Remove up to `count` comment starts from `line`, or all of them if
+    /// `count` is None, along with the padding and comment end.
+    fn uncomment(&self, line: &str, count: Option<usize>) -> String {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let Some(mut body) = self.comment_body(line) else { return line.to_owned() };
+        let mut removed = 1;
+        while count.is_none_or(|x| removed < x) {
+            let Some(rest) = body.strip_prefix(self.start.as_str()) else { break };
+            body = rest;
+            removed += 1;
+        }
+        body = body.strip_prefix(self.padding.as_str()).unwrap_or(body);
+        if !self.end.is_empty() {
+            if let Some(rest) = body.trim_end().strip_suffix(self.end.as_str()) {
+                body = rest.strip_suffix(self.padding.as_str()).unwrap_or(rest);
+            }
+        }
+        format!("{indent}{body}")
+    }
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// The indexes of the start and end of the lines in the region from `beg` to
+/// `end`, which are buffer positions. The newline of the last line isn't
+/// included, and a line that the region only ends at the start of is left
+/// out.
+fn region_lines(chars: &[char], beg: usize, end: usize, cx: &Context) -> Result<(usize, usize)> {
+    let (beg, end) = (beg.min(end), beg.max(end));
+    if beg < 1 || end > chars.len() + 1 {
+        bail!(LispError::region_out_of_range(beg, end, cx));
+    }
+    let (beg, end) = (beg - 1, end - 1);
+    let start = chars[..beg].iter().rposition(|x| *x == '\n').map_or(0, |x| x + 1);
+    let last = if end > beg && chars[end - 1] == '\n' { end - 1 } else { end };
+    let stop = chars[last..].iter().position(|x| *x == '\n').map_or(chars.len(), |x| last + x);
+    Ok((start, stop))
+}
+
+/// Replace the lines of the region from `beg` to `end` with the result of
+/// calling `edit` on them.
+fn edit_lines(
+    beg: usize,
+    end: usize,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+    edit: impl FnOnce(Vec<&str>) -> Vec<String>,
+) -> Result<()> {
+    let chars: Vec<char> = env.current_buffer.get().text.to_string().chars().collect();
+    let (start, stop) = region_lines(&chars, beg, end, cx)?;
+    let old: String = chars[start..stop].iter().collect();
+    let new = edit(old.split('\n').collect()).join("\n");
+    if new != old {
+        replace_region(start + 1, stop + 1, &new, env, cx)?;
+    }
+    Ok(())
+}
+
+fn comment_lines(syntax: &CommentSyntax, lines: Vec<&str>, count: usize) -> Vec<String> {
+    let column = lines
+        .iter()
+        .filter(|x| !is_blank(x))
+        .map(|x| x.len() - x.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let comment = |line: &str| {
+        if is_blank(line) {
+            line.to_owned()
+        } else {
+            syntax.comment(line, column, count)
+        }
+    };
+    lines.into_iter().map(comment).collect()
+}
+
+/// Comment out each nonblank line in the region from BEG to END. The comment
+/// starts are lined up at the smallest indentation in the region. ARG is the
+/// number of copies of `comment-start' to use, which defaults to 1 plus
+/// `comment-add'. If ARG is negative, uncomment the region instead, removing
+/// -ARG comment starts from each line.
+#[defun]
+fn comment_region(
+    beg: usize,
+    end: usize,
+    arg: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let syntax = CommentSyntax::new(env, cx)?;
+    let count = match arg {
+        Some(arg) if arg < 0 => return uncomment_region(beg, end, Some(-arg), env, cx),
+        Some(arg) => arg as usize,
+        None => syntax.default_count(),
+    };
+    edit_lines(beg, end, env, cx, |lines| comment_lines(&syntax, lines, count))
+}
+
+/// Uncomment each commented line in the region from BEG to END, removing
+/// the comment starts along with the padding after them and any comment
+/// end. If ARG is non-nil, remove at most ARG comment starts from each line.
+#[defun]
+fn uncomment_region(
+    beg: usize,
+    end: usize,
+    arg: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let syntax = CommentSyntax::new(env, cx)?;
+    let count = arg.map(|x| x.max(1) as usize);
+    edit_lines(beg, end, env, cx, |lines| {
+        lines.into_iter().map(|x| syntax.uncomment(x, count)).collect()
+    })
+}
+
+/// Toggle the comments of the lines in the region from BEG to END, or of
+/// the current line if they are nil. The lines are uncommented if every
+/// nonblank line is commented, and commented otherwise. On a single blank
+/// line, insert a comment at its indentation and move point into it.
+#[defun]
+fn comment_dwim_lite(
+    beg: Option<usize>,
+    end: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let syntax = CommentSyntax::new(env, cx)?;
+    let text = &env.current_buffer.get().text;
+    let chars: Vec<char> = text.to_string().chars().collect();
+    let point = text.cursor().chars() + 1;
+    let (beg, end) = (beg.unwrap_or(point), end.unwrap_or(point));
+    let (start, stop) = region_lines(&chars, beg, end, cx)?;
+    let lines: String = chars[start..stop].iter().collect();
+    if is_blank(&lines) && !lines.contains('\n') {
+        let open =
+            format!("{lines}{}{}", syntax.start.repeat(syntax.default_count()), syntax.padding);
+        let close = match syntax.end.as_str() {
+            "" => String::new(),
+            end => format!("{}{end}", syntax.padding),
+        };
+        replace_region(start + 1, stop + 1, &format!("{open}{close}"), env, cx)?;
+        let cursor = start + open.chars().count();
+        env.current_buffer.get_mut().text.set_cursor(cursor);
+        return Ok(());
+    }
+    let commented = lines.split('\n').all(|x| is_blank(x) || syntax.comment_body(x).is_some());
+    if commented {
+        edit_lines(beg, end, env, cx, |lines| {
+            lines.into_iter().map(|x| syntax.uncomment(x, None)).collect()
+        })
+    } else {
+        let count = syntax.default_count();
+        edit_lines(beg, end, env, cx, |lines| comment_lines(&syntax, lines, count))
+    }
+}
+
+defvar!(COMMENT_START);
+defvar!(COMMENT_END, "");
+defvar!(COMMENT_PADDING, " ");
+defvar!(COMMENT_ADD, 0);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::eval_in_buffer;
+
+    /// Evaluate `form` in a buffer that contains `text`, with Lisp comment
+    /// syntax. Return the text of the buffer and point.
+    fn with_comments(text: &str, form: &str) -> (String, usize) {
+        let form = format!("(progn (setq comment-start \"; \" comment-add 1) {form})");
+        let (_, text, point) = eval_in_buffer(text, &form);
+        (text, point)
+    }
+
+    #[test]
+    fn test_comment_region() {
+        let code = "(foo)\n  (bar\n\n   baz)\n";
+        let commented = ";; (foo)\n;;   (bar\n\n;;    baz)\n";
+        let (text, _) = with_comments(code, "(comment-region 1 (point-max))");
+        assert_eq!(text, commented);
+        let (text, _) = with_comments(commented, "(uncomment-region 1 (point-max))");
+        assert_eq!(text, code);
+        let (text, _) = with_comments(commented, "(comment-region 1 (point-max) -1)");
+        assert_eq!(text, "; (foo)\n;   (bar\n\n;    baz)\n");
+        let (text, _) = with_comments("  a\n    b\nc", "(comment-region 3 8 1)");
+        assert_eq!(text, "  ; a\n  ;   b\nc");
+        let (text, _) = with_comments(
+            "a\n b",
+            "(let ((comment-start \"/* \") (comment-end \" */\"))
+               (comment-region 1 (point-max)))",
+        );
+        assert_eq!(text, "/* a */\n/*  b */");
+        let (text, _) = with_comments(
+            "/* a */\n/*  b */",
+            "(let ((comment-start \"/* \") (comment-end \" */\"))
+               (uncomment-region 1 (point-max)))",
+        );
+        assert_eq!(text, "a\n b");
+        let (text, _) = with_comments(
+            "a",
+            "(insert (format \"%S\" (condition-case err (comment-region 1 9)
+                                      (args-out-of-range err))))",
+        );
+        assert_eq!(text, "a(args-out-of-range 1 9)");
+    }
+
+    #[test]
+    fn test_comment_dwim_lite() {
+        let (text, _) =
+            with_comments("(foo)\n;; (bar)", "(progn (goto-char 0) (comment-dwim-lite))");
+        assert_eq!(text, ";; (foo)\n;; (bar)");
+        let (text, _) =
+            with_comments("(foo)\n;; (bar)", "(progn (goto-char 8) (comment-dwim-lite))");
+        assert_eq!(text, "(foo)\n(bar)");
+        let (text, _) = with_comments(";; (foo)\n\n;; (bar)", "(comment-dwim-lite 1 (point-max))");
+        assert_eq!(text, "(foo)\n\n(bar)");
+        let (text, point) = with_comments("x\n  ", "(progn (goto-char 4) (comment-dwim-lite))");
+        assert_eq!((text.as_str(), point), ("x\n  ;; ", 7));
+    }
+}
@@ -0,0 +1,164 @@
+//! Surviving panics in Rust code.
+//!
+//! A panic in a subroutine is a bug, but it shouldn't take down a long
+//! running REPL or server with it. [`catch_panic`] runs the evaluation of a
+//! top level form and converts a panic into a `rust-panic' error with data
+//! (MESSAGE BACKTRACE), and then undoes the dynamic bindings, catches and
+//! `call-with-permissions' grants that were left behind by the unwinding, and
+//! makes the buffer that was current before the form current again. Rust
+//! frames clean up after themselves as they unwind. With `--abort-on-panic`
+//! panics are not caught, so they can be debugged where they happen.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+};
+use crate::eval::EvalError;
+use anyhow::Result;
+use rune_core::macros::{list, root};
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+static ABORT_ON_PANIC: AtomicBool = AtomicBool::new(false);
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    /// How many calls of `catch_panic` are active on this thread.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    /// The backtrace of the last panic caught on this thread.
+    static BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Let panics propagate instead of turning them into lisp errors.
+pub(crate) fn set_abort_on_panic(abort: bool) {
+    ABORT_ON_PANIC.store(abort, Ordering::Relaxed);
+}
+
+/// Record the backtrace of panics that will be caught instead of printing
+/// them. Other panics go to the previous hook.
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if DEPTH.get() == 0 {
+                default(info);
+            } else {
+                let backtrace = Backtrace::force_capture().to_string();
+                BACKTRACE.set(Some(backtrace));
+            }
+        }));
+    });
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic".to_owned()
+    }
+}
+
+/// Call `f`, converting a panic into a `rust-panic' error.
+pub(crate) fn catch_panic<T>(
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+    f: impl FnOnce(&mut Rt<Env>, &mut Context) -> Result<T>,
+) -> Result<T> {
+    if ABORT_ON_PANIC.load(Ordering::Relaxed) {
+        return f(env, cx);
+    }
+    install_hook();
+    let bindings = env.binding_depth();
+    let catches = env.catch_stack.len();
+    let permissions = env.permissions.clone();
+    let buffer = env.current_buffer.get().lisp_buffer(cx);
+    root!(buffer, cx);
+    DEPTH.set(DEPTH.get() + 1);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(env, cx)));
+    DEPTH.set(DEPTH.get() - 1);
+    let payload = match result {
+        Ok(x) => return x,
+        Err(payload) => payload,
+    };
+    while env.binding_depth() > bindings {
+        env.unbind(1, cx);
+    }
+    env.catch_stack.truncate(catches);
+    env.permissions = permissions;
+    env.set_buffer(buffer.bind(cx), cx);
+    let message = panic_message(&*payload);
+    let backtrace = BACKTRACE.take().unwrap_or_default();
+    tracing::error!("Caught panic: {message}");
+    let data = list![message, backtrace; cx];
+    Err(EvalError::signal(sym::RUST_PANIC.into(), data, env).into())
+}
+
+defsym!(RUST_PANIC);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{
+        env::intern,
+        gc::RootSet,
+        object::{FnArgs, Function, Object, SubrFn},
+    };
+    use crate::permissions::{check, Capability};
+
+    #[test]
+    fn test_catch_panic() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let bindings = env.binding_depth();
+        let result: Result<()> = catch_panic(env, cx, |env, cx| {
+            env.varbind(sym::RUST_PANIC, cx.add(1), cx);
+            panic!("subr bug");
+        });
+        let err = result.unwrap_err().downcast::<EvalError>().unwrap();
+        assert!(err.is_signal(sym::RUST_PANIC, env));
+        assert!(err.describe(env, cx).contains("subr bug"));
+        assert_eq!(env.binding_depth(), bindings);
+        let value = catch_panic(env, cx, |_, _| Ok(1)).unwrap();
+        assert_eq!(value, 1);
+    }
+
+    fn panicking<'ob>(_: usize, _: &mut Rt<Env>, _: &'ob mut Context) -> Result<Object<'ob>> {
+        panic!("subr bug")
+    }
+
+    static PANICKING: SubrFn = SubrFn {
+        subr: panicking,
+        params: 0,
+        args: FnArgs { rest: false, required: 0, optional: 0, advice: false },
+        name: "panicking",
+    };
+
+    #[test]
+    fn test_panic_with_permissions() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let function: Function = (&PANICKING).into();
+        crate::data::fset(intern("panics-test-panicking", cx), function.into()).unwrap();
+        let buffer = env.current_buffer.get().name.clone();
+        let form = "(progn
+                      (set-buffer (get-buffer-create \"panics-test-buffer\"))
+                      (call-with-permissions '(:read t) #'panics-test-panicking))";
+        let form = crate::reader::read(form, cx).unwrap().0;
+        root!(form, cx);
+        let result = catch_panic(env, cx, |env, cx| {
+            crate::interpreter::eval(form, None, env, cx).map(|_| ())
+        });
+        let err = result.unwrap_err().downcast::<EvalError>().unwrap();
+        assert!(err.is_signal(sym::RUST_PANIC, env));
+        assert_eq!(env.current_buffer.get().name, buffer);
+        assert!(check(Capability::Network, env, cx).is_ok());
+    }
+}
@@ -0,0 +1,308 @@
+//! Dumping and restoring the lisp environment.
+//!
+//! Loading a large prelude is slow, so the state it produces can be written to
+//! a dump file and restored at startup instead. A dump is a file of readable
+//! lisp forms. The first form is the header `(rune-dump VERSION)` and every
+//! other form describes a single symbol:
+//!
+//! ```lisp
+//! (NAME SPECIAL VALUE FUNCTION PLIST)
+//! ```
+//!
+//! VALUE and FUNCTION are wrapped in a list so that an unbound cell (`nil`) can
+//! be told apart from one that is bound to `nil`. Objects that have no
+//! readable representation (buffers, hash tables, builtin functions, etc.) are
+//! left out of the dump.
+use crate::core::{
+    cons::Cons,
+    env::{Env, INTERNED_SYMBOLS},
+    gc::{Context, Rt},
+    object::{FunctionType, LispString, LispVec, Object, ObjectType, Symbol},
+};
+use crate::reader::{self, symbol_char};
+use anyhow::{bail, ensure, Result};
+use rune_core::hashmap::HashSet;
+use rune_macros::defun;
+use std::fmt::Write as _;
+
+const DUMP_VERSION: i64 = 1;
+
+/// Print `obj` so that reading it back produces an equal object. Returns
+/// `None` if the object has no readable representation or if it contains
+/// shared structure, which would be printed as a back reference.
+pub(crate) fn readable(obj: Object) -> Option<String> {
+    let mut out = String::new();
+    print_readable(obj, &mut out, &mut HashSet::default())?;
+    Some(out)
+}
+
+fn print_readable(obj: Object, out: &mut String, seen: &mut HashSet<*const u8>) -> Option<()> {
+    match obj.untag() {
+        ObjectType::Int(x) => write!(out, "{x}").ok(),
+        ObjectType::Float(x) if x.is_finite() => write!(out, "{x}").ok(),
+        ObjectType::Symbol(x) => print_symbol(x, out),
+        ObjectType::String(x) => {
+            print_string(x, out);
+            Some(())
+        }
+        ObjectType::Cons(mut cons) => {
+            out.push('(');
+            loop {
+                if !seen.insert((cons as *const Cons).cast()) {
+                    return None;
+                }
+                print_readable(cons.car(), out, seen)?;
+                match cons.cdr().untag() {
+                    ObjectType::Cons(tail) => {
+                        out.push(' ');
+                        cons = tail;
+                    }
+                    ObjectType::NIL => break,
+                    _ => {
+                        out.push_str(" . ");
+                        print_readable(cons.cdr(), out, seen)?;
+                        break;
+                    }
+                }
+            }
+            out.push(')');
+            Some(())
+        }
+        ObjectType::Vec(vec) => {
+            if !seen.insert((vec as *const LispVec).cast()) {
+                return None;
+            }
+            let elements: Vec<Object> = vec.iter().map(|x| x.get()).collect();
+            print_elements(&elements, out, seen)
+        }
+        ObjectType::ByteFn(func) => {
+            write!(out, "#[{} [", func.args.into_arg_spec()).ok()?;
+            for (i, code) in func.codes().iter().enumerate() {
+                if i != 0 {
+                    out.push(' ');
+                }
+                write!(out, "{code}").ok()?;
+            }
+            out.push_str("] ");
+            print_elements(func.consts(), out, seen)?;
+            write!(out, " {}]", func.depth).ok()
+        }
+        _ => None,
+    }
+}
+
+fn print_elements(
+    elements: &[Object],
+    out: &mut String,
+    seen: &mut HashSet<*const u8>,
+) -> Option<()> {
+    out.push('[');
+    for (i, x) in elements.iter().enumerate() {
+        if i != 0 {
+            out.push(' ');
+        }
+        print_readable(*x, out, seen)?;
+    }
+    out.push(']');
+    Some(())
+}
+
+/// Print a symbol name, escaping any characters that the reader would not
+/// treat as part of a symbol.
+fn print_symbol(symbol: Symbol, out: &mut String) -> Option<()> {
+    let name = symbol.name();
+    if name.is_empty() || !symbol.interned() {
+        return None;
+    }
+    // Names like `1' or `.' would be read back as something other than a symbol
+    if name == "." || name.parse::<f64>().is_ok() {
+        out.push('\\');
+    }
+    for (i, chr) in name.chars().enumerate() {
+        if !symbol_char(chr) || chr == '\\' || (i == 0 && chr == '?') {
+            out.push('\\');
+        }
+        out.push(chr);
+    }
+    Some(())
+}
+
+fn print_string(string: &LispString, out: &mut String) {
+    out.push('"');
+    for chr in string.chars() {
+        if chr == '"' || chr == '\\' {
+            out.push('\\');
+        }
+        out.push(chr);
+    }
+    out.push('"');
+}
+
+/// Return the dump entry for `symbol`, or `None` if it has nothing worth
+/// saving.
+fn dump_symbol(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> Option<String> {
+    let special = symbol.is_special();
+    let value = env.vars.get(symbol).and_then(|x| readable(x.bind(cx)));
+    let func = symbol.func(cx).and_then(|func| match func.untag() {
+        // builtin functions are defined at startup
+        FunctionType::SubrFn(_) => None,
+        _ => readable(func.into()),
+    });
+    let plist: Vec<String> = match env.props.get(symbol) {
+        Some(plist) => plist
+            .iter()
+            .filter_map(|x| {
+                let mut prop = String::new();
+                print_symbol(x.0.bind(cx), &mut prop)?;
+                let value = readable(cx.bind(x.1.bind(cx)))?;
+                Some(format!("{prop} {value}"))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    if !special && value.is_none() && func.is_none() && plist.is_empty() {
+        return None;
+    }
+    let mut name = String::new();
+    print_symbol(symbol, &mut name)?;
+    let special = if special { "t" } else { "nil" };
+    let cell = |x: Option<String>| x.map_or_else(|| "nil".to_owned(), |x| format!("({x})"));
+    let (value, func, plist) = (cell(value), cell(func), plist.join(" "));
+    Some(format!("({name} {special} {value} {func} ({plist}))"))
+}
+
+/// Serialize the interned symbols of `env` into the dump format.
+pub(crate) fn dump_environment(env: &Rt<Env>, cx: &Context) -> String {
+    let mut symbols: Vec<Symbol> =
+        INTERNED_SYMBOLS.lock().unwrap().symbols().map(|x| cx.bind(x)).collect();
+    symbols.sort_by(|a, b| a.name().cmp(b.name()));
+    let mut out = format!("(rune-dump {DUMP_VERSION})\n");
+    for symbol in symbols {
+        if let Some(entry) = dump_symbol(symbol, env, cx) {
+            out.push_str(&entry);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn restore_symbol(entry: Object, env: &mut Rt<Env>) -> Result<()> {
+    let elements = entry.as_list()?.collect::<Result<Vec<_>, _>>()?;
+    let [name, special, value, func, plist] = elements[..] else {
+        bail!("Invalid dump entry: {entry}");
+    };
+    let symbol: Symbol = name.try_into()?;
+    if !special.is_nil() {
+        symbol.make_special();
+    }
+    if let ObjectType::Cons(value) = value.untag() {
+        env.vars.insert(symbol, value.car());
+    }
+    if let ObjectType::Cons(func) = func.untag() {
+        crate::data::fset(symbol, func.car())?;
+    }
+    let mut plist = plist.as_list()?;
+    while let Some(prop) = plist.next() {
+        let prop: Symbol = prop?.try_into()?;
+        let Some(value) = plist.next() else {
+            bail!("Invalid property list in dump entry: {entry}");
+        };
+        env.set_prop(symbol, prop, value?);
+    }
+    Ok(())
+}
+
+/// Restore an environment from the contents of a dump created by
+/// [`dump_environment`].
+pub(crate) fn load_dump(contents: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let (header, mut pos) = reader::read(contents, cx)?;
+    let expect = format!("(rune-dump {DUMP_VERSION})");
+    ensure!(
+        header.to_string() == expect,
+        "Invalid dump header: expected {expect}, found {header}"
+    );
+    loop {
+        let (entry, new_pos) = match reader::read(&contents[pos..], cx) {
+            Ok((obj, pos)) => (obj, pos),
+            Err(reader::Error::EmptyStream) => return Ok(()),
+            Err(mut e) => {
+                e.update_pos(pos);
+                bail!(e);
+            }
+        };
+        restore_symbol(entry, env)?;
+        pos += new_pos;
+    }
+}
+
+/// Restore the environment from the dump file `filename`.
+pub(crate) fn load_dump_file(filename: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let contents = std::fs::read_to_string(filename)?;
+    load_dump(&contents, env, cx)
+}
+
+#[defun]
+fn dump_emacs_portable(
+    filename: &str,
+    _track_referents: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    std::fs::write(filename, dump_environment(env, cx))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{
+        env::{intern, sym},
+        gc::RootSet,
+    };
+    use rune_core::macros::{list, root};
+
+    #[test]
+    fn test_readable() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        for input in [
+            "1",
+            "1.5",
+            "\"a\\\"b\\\\\"",
+            "(a b . c)",
+            "[1 (2) \"x\"]",
+            "\\1",
+            "a\\ b",
+            "#[257 [1 84 135] [x] 2]",
+        ] {
+            let obj = reader::read(input, cx).unwrap().0;
+            assert_eq!(readable(obj).as_deref(), Some(input));
+        }
+        let shared = list![1; cx];
+        assert_eq!(readable(list![shared, shared; cx]), None);
+        assert_eq!(readable(cx.add(f64::NAN)), None);
+    }
+
+    #[test]
+    fn test_load_dump() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let dump = "(rune-dump 1)\n\
+                    (pdump-test-var t (42) nil (pdump-test-prop \"x\"))\n\
+                    (pdump-test-fn nil nil ((lambda (x) x)) ())\n";
+        load_dump(dump, env, cx).unwrap();
+
+        let var = intern("pdump-test-var", cx);
+        assert!(var.is_special());
+        let expect: Object = 42.into();
+        assert_eq!(env.vars.get(var).unwrap().bind(cx), expect);
+        let prop = intern("pdump-test-prop", cx);
+        assert_eq!(crate::data::get(var, prop, env, cx).to_string(), "\"x\"");
+        assert!(intern("pdump-test-fn", cx).has_func());
+
+        assert!(load_dump("(rune-dump 0)", env, cx).is_err());
+        assert!(load_dump("(rune-dump 1) (foo)", env, cx).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! Capabilities granted to lisp code.
+//!
+//! Every [`Env`] carries a [`Permissions`] that the primitives which reach
+//! outside the process consult before reading or writing files, listening on
+//! the network, starting subprocesses or loading code that changes how rune
+//! runs. Everything is permitted by default.
+//! `call-with-permissions' narrows the permissions while a function runs.
+//! Grants are layered, so an inner grant can only take capabilities away;
+//! an action must be permitted by every active grant.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, ObjectType, NIL},
+};
+use crate::eval::EvalError;
+use anyhow::{bail, Result};
+use rune_core::macros::{call, list};
+use rune_macros::defun;
+use std::path::{Component, Path, PathBuf};
+
+/// A kind of access to the outside world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Capability {
+    Read,
+    Write,
+    Network,
+    Subprocess,
+    /// Loading tree-sitter grammars, which are native code, and entering a
+    /// `rune-module', which changes how the rest of a load is read.
+    Load,
+}
+
+impl Capability {
+    fn symbol(self) -> Object<'static> {
+        match self {
+            Self::Read => sym::READ.into(),
+            Self::Write => sym::WRITE.into(),
+            Self::Network => sym::NETWORK.into(),
+            Self::Subprocess => sym::SUBPROCESS.into(),
+            Self::Load => sym::LOAD.into(),
+        }
+    }
+}
+
+/// The files that a grant allows access to.
+#[derive(Debug, Clone)]
+enum Paths {
+    Any,
+    /// Only files under one of these directories.
+    Under(Vec<PathBuf>),
+}
+
+impl Paths {
+    fn new(value: Object) -> Result<Self> {
+        match value.untag() {
+            ObjectType::NIL => Ok(Self::Under(Vec::new())),
+            ObjectType::TRUE => Ok(Self::Any),
+            ObjectType::String(dir) => Ok(Self::Under(vec![resolve(dir)])),
+            ObjectType::Cons(dirs) => {
+                let mut paths = Vec::new();
+                for dir in dirs {
+                    match dir?.untag() {
+                        ObjectType::String(dir) => paths.push(resolve(dir)),
+                        other => bail!("Invalid permitted directory: {other}"),
+                    }
+                }
+                Ok(Self::Under(paths))
+            }
+            _ => bail!("Invalid permitted paths: {value}"),
+        }
+    }
+
+    fn allows(&self, file: &Path) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Under(dirs) => dirs.iter().any(|dir| file.starts_with(dir)),
+        }
+    }
+}
+
+/// One layer of permissions added by `call-with-permissions'.
+#[derive(Debug, Clone)]
+struct Grant {
+    read: Paths,
+    write: Paths,
+    network: bool,
+    subprocess: bool,
+    load: bool,
+}
+
+impl Grant {
+    /// Parse a plist of the form (:read PATHS :write PATHS :network BOOL
+    /// :subprocess BOOL :load BOOL). Omitted capabilities are not granted.
+    fn new(plist: Object) -> Result<Self> {
+        let mut grant = Self {
+            read: Paths::Under(Vec::new()),
+            write: Paths::Under(Vec::new()),
+            network: false,
+            subprocess: false,
+            load: false,
+        };
+        let elements = plist.as_list()?.collect::<Result<Vec<_>, _>>()?;
+        for pair in elements.chunks(2) {
+            let [key, value] = *pair else { bail!("Missing keyword value for {}", pair[0]) };
+            match key.untag() {
+                ObjectType::Symbol(sym::KW_READ) => grant.read = Paths::new(value)?,
+                ObjectType::Symbol(sym::KW_WRITE) => grant.write = Paths::new(value)?,
+                ObjectType::Symbol(sym::KW_NETWORK) => grant.network = !value.is_nil(),
+                ObjectType::Symbol(sym::KW_SUBPROCESS) => grant.subprocess = !value.is_nil(),
+                ObjectType::Symbol(sym::KW_LOAD) => grant.load = !value.is_nil(),
+                _ => bail!("Invalid keyword: {key}"),
+            }
+        }
+        Ok(grant)
+    }
+
+    fn allows(&self, capability: Capability, file: Option<&Path>) -> bool {
+        match (capability, file) {
+            (Capability::Read, Some(file)) => self.read.allows(file),
+            (Capability::Write, Some(file)) => self.write.allows(file),
+            (Capability::Read, None) => matches!(self.read, Paths::Any),
+            (Capability::Write, None) => matches!(self.write, Paths::Any),
+            (Capability::Network, _) => self.network,
+            (Capability::Subprocess, _) => self.subprocess,
+            (Capability::Load, _) => self.load,
+        }
+    }
+}
+
+/// The capabilities of the code running in an [`Env`]. This is cloned into
+/// the environment of threads, futures and generators so that they cannot be
+/// used to escape it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Permissions {
+    grants: Vec<Grant>,
+}
+
+impl Permissions {
+    fn allows(&self, capability: Capability, file: Option<&Path>) -> bool {
+        self.grants.iter().all(|x| x.allows(capability, file))
+    }
+
+    fn allows_file(&self, capability: Capability, file: &str) -> bool {
+        self.grants.is_empty() || self.allows(capability, Some(&resolve(file)))
+    }
+}
+
+/// Make `file` absolute and remove `.` and `..` components, so that it can't
+/// escape a permitted directory. Symlinks are resolved for the part of the
+/// path that exists.
+fn resolve(file: &str) -> PathBuf {
+    let path = std::path::absolute(file).unwrap_or_else(|_| PathBuf::from(file));
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => _ = normal.pop(),
+            c => normal.push(c),
+        }
+    }
+    // Files that don't exist yet are resolved relative to their directory
+    let mut missing = Vec::new();
+    let mut existing = normal.as_path();
+    loop {
+        if let Ok(real) = existing.canonicalize() {
+            return missing.into_iter().rev().fold(real, |path, name| path.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_owned());
+                existing = parent;
+            }
+            _ => return normal,
+        }
+    }
+}
+
+fn denied(
+    capability: Capability,
+    file: Option<&str>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> EvalError {
+    let target = file.map_or(NIL, |x| cx.add(x));
+    let data = list![capability.symbol(), target; cx];
+    EvalError::signal(sym::PERMISSION_DENIED.into(), data, env)
+}
+
+/// Signal `permission-denied' unless reading or writing `file` is permitted.
+pub(crate) fn check_file(
+    capability: Capability,
+    file: &str,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    if env.permissions.allows_file(capability, file) {
+        Ok(())
+    } else {
+        Err(denied(capability, Some(file), env, cx).into())
+    }
+}
+
+/// Signal `permission-denied' unless `capability` is permitted. Use
+/// [`check_file`] for file access.
+pub(crate) fn check(capability: Capability, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    if env.permissions.allows(capability, None) {
+        Ok(())
+    } else {
+        Err(denied(capability, None, env, cx).into())
+    }
+}
+
+/// Call FUNCTION with no arguments and with only the capabilities in
+/// PERMISSIONS, a plist of the form:
+///
+/// :read PATHS -- the files that may be read.
+/// :write PATHS -- the files that may be written.
+/// :network BOOL -- whether network servers may be started.
+/// :subprocess BOOL -- whether subprocesses may be started.
+/// :load BOOL -- whether tree-sitter grammars may be loaded and
+///   `rune-module' may be used.
+///
+/// PATHS is t for any file, or a directory or list of directories whose
+/// files are permitted. Capabilities that are omitted are not granted, and
+/// nothing that is denied by an enclosing call can be granted again. A denied
+/// action signals `permission-denied' with data (CAPABILITY FILE).
+#[defun]
+fn call_with_permissions<'ob>(
+    permissions: &Rto<Object>,
+    function: &Rto<Function>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let grant = Grant::new(permissions.bind(cx))?;
+    env.permissions.grants.push(grant);
+    let result = call!(function; env, cx);
+    env.permissions.grants.pop();
+    result.map_err(Into::into)
+}
+
+defsym!(PERMISSION_DENIED);
+defsym!(WRITE);
+defsym!(NETWORK);
+defsym!(SUBPROCESS);
+defsym!(KW_READ);
+defsym!(KW_WRITE);
+defsym!(KW_NETWORK);
+defsym!(KW_SUBPROCESS);
+defsym!(KW_LOAD);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_resolve() {
+        let dir = std::env::temp_dir().canonicalize().unwrap();
+        let dir_str = dir.to_str().unwrap();
+        assert_eq!(resolve(&format!("{dir_str}/a/../b")), dir.join("b"));
+        let escape = format!("{dir_str}/rune-perm/../../etc/passwd");
+        let paths = Paths::Under(vec![resolve(&format!("{dir_str}/rune-perm"))]);
+        assert!(!paths.allows(&resolve(&escape)));
+        assert!(paths.allows(&resolve(&format!("{dir_str}/rune-perm/new/file"))));
+    }
+
+    #[test]
+    fn test_permissions() {
+        let dir = std::env::temp_dir().join("rune-permissions-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_str().unwrap();
+        assert_lisp(
+            &format!(
+                "(call-with-permissions '(:write \"{dir}\") (lambda () (write-region \"x\" nil \"{dir}/ok\")))"
+            ),
+            "nil",
+        );
+        assert_lisp(
+            "(condition-case err (call-with-permissions nil (lambda () (write-region \"x\" nil \"/tmp/rune-denied\"))) (error err))",
+            "(permission-denied write \"/tmp/rune-denied\")",
+        );
+        // an inner grant can't add back what an outer one denied
+        assert_lisp(
+            "(condition-case err (call-with-permissions '(:network nil) (lambda () (call-with-permissions '(:network t) (lambda () (repl-server-start))))) (error (car err)))",
+            "permission-denied",
+        );
+        assert_lisp(
+            "(condition-case err (call-with-permissions '(:read t) (lambda () (rune-module 'perm-test))) (error err))",
+            "(permission-denied load nil)",
+        );
+        assert_lisp("(call-with-permissions '(:read t) (lambda () 3))", "3");
+    }
+}
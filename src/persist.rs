@@ -0,0 +1,163 @@
+//! Caching lisp data between sessions.
+//!
+//! `persist-save' prints a value to a file after a header form
+//! `(persist-format FORMAT VERSION)', where VERSION is supplied by the caller.
+//! `persist-load' only reads the file and never evaluates it, so a corrupt or
+//! malicious cache cannot run code. A file written with a different format
+//! or version is treated as missing, which lets packages invalidate their
+//! caches by bumping the version. Values must have a readable printed
+//! representation; buffers, functions and shared structure are rejected.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, NIL},
+};
+use crate::pdumper::readable;
+use crate::permissions::{check_file, Capability};
+use crate::reader;
+use anyhow::{anyhow, bail, ensure, Result};
+use rune_macros::defun;
+
+/// The version of the file layout itself.
+const FORMAT: i64 = 1;
+
+const HEADER: &str = ";; -*- mode: lisp-data; coding: utf-8-unix -*-\n\
+                      ;; Written by `persist-save'; do not edit.\n";
+
+/// Return the contents of a file holding `data` saved with `version`.
+pub(crate) fn persist_contents(data: Object, version: Object) -> Result<String> {
+    let print = |obj| readable(obj).ok_or_else(|| anyhow!("Value cannot be saved: {obj}"));
+    let version = print(version)?;
+    let data = print(data)?;
+    Ok(format!("{HEADER}(persist-format {FORMAT} {version})\n{data}\n"))
+}
+
+/// Read the next form of `contents` starting at `pos`. Returns `None` at the
+/// end of the contents.
+fn read_form<'ob>(
+    contents: &str,
+    pos: usize,
+    cx: &'ob Context,
+) -> Result<Option<(Object<'ob>, usize)>> {
+    match reader::read(&contents[pos..], cx) {
+        Ok((obj, len)) => Ok(Some((obj, pos + len))),
+        Err(reader::Error::EmptyStream) => Ok(None),
+        Err(mut e) => {
+            e.update_pos(pos);
+            bail!(e)
+        }
+    }
+}
+
+/// Read the value saved in `contents` by [`persist_contents`]. Returns `None`
+/// if it was saved with a different format or version.
+pub(crate) fn restore_contents<'ob>(
+    contents: &str,
+    version: Object,
+    cx: &'ob Context,
+) -> Result<Option<Object<'ob>>> {
+    let Some((header, pos)) = read_form(contents, 0, cx)? else {
+        bail!("Missing persist header")
+    };
+    let elements = header.as_list()?.collect::<Result<Vec<_>, _>>()?;
+    let [tag, format, saved] = elements[..] else {
+        bail!("Invalid persist header: {header}")
+    };
+    ensure!(tag == sym::PERSIST_FORMAT, "Invalid persist header: {header}");
+    if format != cx.add(FORMAT) || saved != version {
+        return Ok(None);
+    }
+    let Some((data, pos)) = read_form(contents, pos, cx)? else {
+        bail!("Missing persist data")
+    };
+    if let Some((extra, _)) = read_form(contents, pos, cx)? {
+        bail!("Unexpected data after persisted value: {extra}");
+    }
+    Ok(Some(data))
+}
+
+/// Save DATA to FILE so that it can be restored by `persist-load' in a
+/// later session. VERSION is any readable value recorded with the data;
+/// loading with a different version returns nil. DATA must have a readable
+/// printed representation. Directories leading to FILE are created.
+#[defun]
+fn persist_save(
+    file: &str,
+    data: Object,
+    version: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    check_file(Capability::Write, file, env, cx)?;
+    let contents = persist_contents(data, version.unwrap_or(NIL))?;
+    if let Some(dir) = std::path::Path::new(file).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(file, contents)?;
+    Ok(true)
+}
+
+/// Return the value saved in FILE by `persist-save'. Return nil if FILE does
+/// not exist or was saved with a version other than VERSION. The file is
+/// read but never evaluated; a malformed file signals an error.
+#[defun]
+fn persist_load<'ob>(
+    file: &str,
+    version: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    check_file(Capability::Read, file, env, cx)?;
+    let contents = match std::fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(NIL),
+        Err(e) => bail!("Failed to read {file}: {e}"),
+    };
+    let value = restore_contents(&contents, version.unwrap_or(NIL), cx)?;
+    Ok(value.unwrap_or(NIL))
+}
+
+defsym!(PERSIST_FORMAT);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::interpreter::assert_lisp;
+    use rune_core::macros::list;
+
+    #[test]
+    fn test_contents() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let data = list!["a\"b", 1, list![2.5, NIL; cx]; cx];
+        let contents = persist_contents(data, cx.add(3)).unwrap();
+        assert!(contents.ends_with("(persist-format 1 3)\n(\"a\\\"b\" 1 (2.5 nil))\n"));
+        let restored = restore_contents(&contents, cx.add(3), cx).unwrap().unwrap();
+        assert_eq!(restored, data);
+        assert!(restore_contents(&contents, cx.add(4), cx).unwrap().is_none());
+
+        let buffer = crate::buffer::get_buffer_create(cx.add("persist-test"), None, cx).unwrap();
+        assert!(persist_contents(buffer, NIL).is_err());
+        assert!(restore_contents("(delete-file \"x\")", NIL, cx).is_err());
+        assert!(restore_contents("(persist-format 1 nil) 1 2", NIL, cx).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let file = std::env::temp_dir().join(format!("rune-persist-{}.eld", std::process::id()));
+        let file = file.to_str().unwrap();
+        assert_lisp(
+            &format!(
+                "(progn
+                   (persist-save \"{file}\" '(1 \"two\" [three]) \"v1\")
+                   (list (persist-load \"{file}\" \"v1\")
+                         (persist-load \"{file}\" \"v2\")
+                         (persist-load \"{file}.missing\")))"
+            ),
+            "((1 \"two\" [three]) nil nil)",
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+}
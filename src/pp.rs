@@ -0,0 +1,379 @@
+//! Pretty printing of lisp data.
+//!
+//! An object that fits in the remaining width is printed on one line, the
+//! same as `prin1'. Lists and vectors that don't fit are broken with one
+//! element per line. Function calls align their arguments under the first
+//! one, forms with a `lisp-indent-function' indent their body by two columns,
+//! and other lists align their elements under the first element. Like the
+//! flat output, a broken list honors the current [`PrintLimits`].
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{with_print_limits, Object, ObjectType, PrintLimits, RawObj, Symbol},
+};
+use anyhow::Result;
+use rune_core::hashmap::HashSet;
+use rune_macros::defun;
+
+/// The width used when `fill-column' is not set.
+pub(crate) const DEFAULT_WIDTH: usize = 70;
+
+/// The number of distinguished arguments of special forms, which don't have a
+/// `lisp-indent-function' property.
+fn special_form_indent(symbol: Symbol) -> Option<usize> {
+    match symbol {
+        sym::PROGN | sym::SAVE_EXCURSION | sym::SAVE_CURRENT_BUFFER => Some(0),
+        sym::LAMBDA
+        | sym::LET
+        | sym::LET_STAR
+        | sym::DLET
+        | sym::WHILE
+        | sym::CATCH
+        | sym::UNWIND_PROTECT => Some(1),
+        sym::IF | sym::CONDITION_CASE => Some(2),
+        _ => None,
+    }
+}
+
+/// Collects the flat printed form of an object, and fails as soon as it is
+/// wider than `room` or has a newline.
+struct Flat {
+    out: String,
+    room: usize,
+}
+
+impl std::fmt::Write for Flat {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        for c in s.chars() {
+            if c == '\n' || self.room == 0 {
+                return Err(std::fmt::Error);
+            }
+            self.room -= 1;
+            self.out.push(c);
+        }
+        Ok(())
+    }
+}
+
+/// The flat printed form of `obj`, if it fits on one line in `room`
+/// columns. Printing stops once it doesn't fit, so checking each node of a
+/// deeply nested object only prints as much of it as the width allows.
+fn flat_within(obj: Object, room: usize) -> Option<String> {
+    let mut flat = Flat { out: String::new(), room };
+    std::fmt::Write::write_fmt(&mut flat, format_args!("{obj}")).ok()?;
+    Some(flat.out)
+}
+
+struct Printer<'a> {
+    width: usize,
+    /// The number of distinguished arguments of a form, from its
+    /// `lisp-indent-function'.
+    indent_function: &'a dyn Fn(Symbol) -> Option<usize>,
+    out: String,
+    /// The column the output starts at.
+    start: usize,
+    /// The lists and vectors being printed, so that cycles are printed flat.
+    path: Vec<RawObj>,
+}
+
+impl Printer<'_> {
+    fn column(&self) -> usize {
+        match self.out.rfind('\n') {
+            Some(i) => self.out[i + 1..].chars().count(),
+            None => self.start + self.out.chars().count(),
+        }
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.extend(std::iter::repeat(' ').take(indent));
+    }
+
+    fn print(&mut self, obj: Object) {
+        if let Some(flat) = flat_within(obj, self.width.saturating_sub(self.column())) {
+            self.out.push_str(&flat);
+            return;
+        }
+        if self.path.contains(&obj.into_raw()) {
+            self.out.push_str(&obj.to_string());
+            return;
+        }
+        let limits = PrintLimits::current();
+        match obj.untag() {
+            ObjectType::Cons(_) => {
+                let mut items = Vec::new();
+                let mut seen = HashSet::default();
+                let mut tail = obj;
+                while let ObjectType::Cons(cons) = tail.untag() {
+                    if !seen.insert(tail.into_raw()) {
+                        // a circular list can't be broken into elements
+                        self.out.push_str(&obj.to_string());
+                        return;
+                    }
+                    items.push(cons.car());
+                    tail = cons.cdr();
+                }
+                let mut tail = (!tail.is_nil()).then_some(tail);
+                let truncated = limits.exceeds_length(items.len());
+                if let (true, Some(length)) = (truncated, limits.length) {
+                    items.truncate(length);
+                    tail = None;
+                }
+                if items.is_empty() {
+                    self.out.push_str(&obj.to_string());
+                    return;
+                }
+                self.path.push(obj.into_raw());
+                with_print_limits(limits.nested(), || self.list(&items, tail, truncated));
+                self.path.pop();
+            }
+            ObjectType::Vec(vec) if !vec.is_empty() && limits.length != Some(0) => {
+                let mut items: Vec<_> = vec.iter().map(|x| x.get()).collect();
+                let truncated = limits.exceeds_length(items.len());
+                if let (true, Some(length)) = (truncated, limits.length) {
+                    items.truncate(length);
+                }
+                let column = self.column();
+                self.path.push(obj.into_raw());
+                self.out.push('[');
+                with_print_limits(limits.nested(), || {
+                    self.print(items[0]);
+                    self.rest(&items[1..], None, truncated, column + 1);
+                });
+                self.out.push(']');
+                self.path.pop();
+            }
+            _ => self.out.push_str(&obj.to_string()),
+        }
+    }
+
+    fn list(&mut self, items: &[Object], tail: Option<Object>, truncated: bool) {
+        let column = self.column();
+        self.out.push('(');
+        let (head, rest) = items.split_first().expect("list should not be empty");
+        self.print(*head);
+        let (rest, indent) = match head.untag() {
+            ObjectType::Symbol(head) => match self.body_indent(head) {
+                Some(distinguished) => {
+                    let (first, rest) = rest.split_at(distinguished.min(rest.len()));
+                    for (i, arg) in first.iter().enumerate() {
+                        if i == 0 {
+                            self.out.push(' ');
+                        } else {
+                            self.newline(column + 4);
+                        }
+                        self.print(*arg);
+                    }
+                    (rest, column + 2)
+                }
+                None => self.first_argument(rest, column),
+            },
+            _ => (rest, column + 1),
+        };
+        self.rest(rest, tail, truncated, indent);
+        self.out.push(')');
+    }
+
+    /// Print the first argument of a call on the same line as the function,
+    /// if the other arguments can be aligned under it. Returns the remaining
+    /// arguments and their indentation.
+    fn first_argument<'a>(&mut self, args: &'a [Object], column: usize) -> (&'a [Object], usize) {
+        let indent = self.column() + 1;
+        match args.split_first() {
+            Some((first, rest)) if indent - column <= self.width / 2 => {
+                self.out.push(' ');
+                self.print(*first);
+                (rest, indent)
+            }
+            _ => (args, column + 2),
+        }
+    }
+
+    /// Print each of `items` on its own line, followed by `...` if some
+    /// were left out.
+    fn rest(&mut self, items: &[Object], tail: Option<Object>, truncated: bool, indent: usize) {
+        for item in items {
+            self.newline(indent);
+            self.print(*item);
+        }
+        if truncated {
+            self.newline(indent);
+            self.out.push_str("...");
+        }
+        if let Some(tail) = tail {
+            self.newline(indent);
+            self.out.push_str(". ");
+            self.print(tail);
+        }
+    }
+
+    fn body_indent(&self, symbol: Symbol) -> Option<usize> {
+        (self.indent_function)(symbol).or_else(|| special_form_indent(symbol))
+    }
+}
+
+fn indent_function(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> Option<usize> {
+    match crate::data::get(symbol, sym::LISP_INDENT_FUNCTION, env, cx).untag() {
+        ObjectType::Int(n) if n >= 0 => Some(n as usize),
+        ObjectType::Symbol(s) if s.name() == "defun" => Some(2),
+        _ => None,
+    }
+}
+
+/// The number of distinguished arguments of a form headed by `symbol`, from
+/// its `lisp-indent-function' property or because it is a special form.
+pub(crate) fn body_indent(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> Option<usize> {
+    indent_function(symbol, env, cx).or_else(|| special_form_indent(symbol))
+}
+
+/// Pretty print `obj` as if it started at `column`, breaking lines that
+/// would go past `width`. If `env` is given, symbols are indented by their
+/// `lisp-indent-function'.
+pub(crate) fn pretty_print(
+    obj: Object,
+    column: usize,
+    width: usize,
+    env: Option<(&Rt<Env>, &Context)>,
+) -> String {
+    let indent_function =
+        |symbol: Symbol| env.and_then(|(env, cx)| indent_function(symbol, env, cx));
+    let mut printer = Printer {
+        width,
+        indent_function: &indent_function,
+        out: String::new(),
+        start: column,
+        path: Vec::new(),
+    };
+    printer.print(obj);
+    printer.out
+}
+
+/// Pretty print a call of the function `name` with `args`, like a backtrace
+/// frame.
+pub(crate) fn pretty_print_call(name: &str, args: &[Object], width: usize) -> String {
+    let mut flat = format!("({name}");
+    for arg in args {
+        flat.push(' ');
+        flat.push_str(&arg.to_string());
+    }
+    flat.push(')');
+    if !flat.contains('\n') && flat.chars().count() <= width {
+        return flat;
+    }
+    let mut printer = Printer {
+        width,
+        indent_function: &|_| None,
+        out: String::new(),
+        start: 0,
+        path: Vec::new(),
+    };
+    printer.out.push('(');
+    printer.out.push_str(name);
+    let (rest, indent) = printer.first_argument(args, 0);
+    printer.rest(rest, None, false, indent);
+    printer.out.push(')');
+    printer.out
+}
+
+/// The width to pretty print to in `env`.
+pub(crate) fn fill_column(env: &Rt<Env>, cx: &Context) -> usize {
+    match env.vars.get(sym::FILL_COLUMN).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::Int(width)) if width > 0 => width as usize,
+        _ => DEFAULT_WIDTH,
+    }
+}
+
+/// Return a string containing the pretty-printed representation of OBJECT.
+/// Lines are broken to fit in `fill-column'. PP-FUNCTION is ignored.
+#[defun]
+fn pp_to_string(
+    object: Object,
+    _pp_function: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    let mut string = pretty_print(object, 0, fill_column(env, cx), Some((env, cx)));
+    string.push('\n');
+    string
+}
+
+/// Output the pretty-printed representation of OBJECT to STREAM, which
+/// defaults to `standard-output'.
+#[defun]
+fn pp(
+    object: &Rto<Object>,
+    stream: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let mut string = pretty_print(object.bind(cx), 0, fill_column(env, cx), Some((&*env, &*cx)));
+    string.push('\n');
+    crate::print::write_to_stream(&string, stream, env, cx)?;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::interpreter::assert_lisp_with_vars;
+    use crate::reader::read;
+
+    fn check(input: &str, width: usize, expect: &str) {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let obj = read(input, cx).unwrap().0;
+        assert_eq!(pretty_print(obj, 0, width, None), expect);
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        check("(a b c)", 70, "(a b c)");
+        check("(foo bar baz)", 10, "(foo bar\n     baz)");
+        check("(let ((x 1)) (foo x) (bar x))", 20, "(let ((x 1))\n  (foo x)\n  (bar x))");
+        check("((1 2) (3 4))", 10, "((1 2)\n (3 4))");
+        check("[aaa bbb ccc]", 8, "[aaa\n bbb\n ccc]");
+        check("(aaa bbb . ccc)", 10, "(aaa bbb\n     . ccc)");
+        check(
+            "(if (and a b) (progn (foo 1) (bar 2)) nil)",
+            30,
+            "(if (and a b)\n    (progn (foo 1) (bar 2))\n  nil)",
+        );
+        check("\"a long string\"", 4, "\"a long string\"");
+    }
+
+    #[test]
+    fn test_print_limits() {
+        let limits = PrintLimits { level: Some(1), length: Some(2) };
+        with_print_limits(limits, || {
+            check("(1 (2) 3 4)", 70, "(1 ... ...)");
+            check("(aaa bbb ccc)", 8, "(aaa bbb\n     ...)");
+            check("[aaa [bbb] ccc]", 8, "[aaa\n ...\n ...]");
+        });
+        assert_lisp_with_vars(
+            "(let ((print-level 2) (print-length 3)) (prin1-to-string '(a (b (c)) [1 2 3 4])))",
+            "\"(a (b ...) [1 2 3 ...])\"",
+        );
+    }
+
+    #[test]
+    fn test_pp_to_string() {
+        assert_lisp_with_vars("(pp-to-string '(a \"b\"))", "\"(a \\\"b\\\")\n\"");
+        assert_lisp_with_vars(
+            "(let ((fill-column 12)) (pp-to-string '(aaaa bbbb cccc)))",
+            "\"(aaaa bbbb\n      cccc)\n\"",
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_call() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let args = [cx.add("first argument"), cx.add("second argument")];
+        assert_eq!(
+            pretty_print_call("concat", &args, 30),
+            "(concat \"first argument\"\n        \"second argument\")"
+        );
+    }
+}
@@ -1,13 +1,311 @@
 //! Printing utilities.
-use crate::core::object::Object;
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{
+        with_print_limits, Function, LispBuffer, Object, ObjectType, PrintLimits, Symbol, NIL, TRUE,
+    },
+};
+use anyhow::Result;
+use rune_core::macros::{call, root};
 use rune_macros::defun;
+use std::cell::RefCell;
+use std::io::Write;
 
+/// A destination for printed output. Printing functions write to the stream
+/// named by their PRINTCHARFUN argument, which defaults to `standard-output'.
+///
+/// Objects are printed to a string before they are written, because writing
+/// to a buffer or a function can run lisp code that moves them.
+pub(crate) trait Stream {
+    fn write_str(&mut self, string: &str) -> Result<()>;
+
+    fn write_char(&mut self, chr: char) -> Result<()> {
+        self.write_str(chr.encode_utf8(&mut [0; 4]))
+    }
+}
+
+/// Output collected by [`capture_output`] instead of going to stdout and
+/// stderr.
+#[derive(Default)]
+pub(crate) struct Captured {
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+/// Call `f`, collecting anything it writes to stdout or stderr.
+pub(crate) fn capture_output<T>(f: impl FnOnce() -> T) -> (T, Captured) {
+    let prev = CAPTURED.replace(Some(Captured::default()));
+    let result = f();
+    let captured = CAPTURED.replace(prev).unwrap_or_default();
+    (result, captured)
+}
+
+/// Write `string` to stderr, or to the captured output if there is any.
+pub(crate) fn write_stderr(string: &str) -> Result<()> {
+    let captured = CAPTURED.with_borrow_mut(|x| x.as_mut().map(|x| x.stderr.push_str(string)));
+    if captured.is_none() {
+        let mut stderr = std::io::stderr();
+        stderr.write_all(string.as_bytes())?;
+        stderr.flush()?;
+    }
+    Ok(())
+}
+
+/// The stream used for `t`. There is no echo area, so this is always stdout.
+pub(crate) struct Stdout;
+
+impl Stream for Stdout {
+    fn write_str(&mut self, string: &str) -> Result<()> {
+        let captured = CAPTURED.with_borrow_mut(|x| x.as_mut().map(|x| x.stdout.push_str(string)));
+        if captured.is_some() {
+            return Ok(());
+        }
+        let mut stdout = std::io::stdout();
+        stdout.write_all(string.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Insert the output into a buffer at point.
+struct BufferStream<'a, 'ob, 'rt, 'cx> {
+    buffer: &'a Rto<&'ob LispBuffer>,
+    env: &'a mut Rt<Env<'rt>>,
+    cx: &'a mut Context<'cx>,
+}
+
+impl Stream for BufferStream<'_, '_, '_, '_> {
+    fn write_str(&mut self, string: &str) -> Result<()> {
+        crate::insdel::with_current_buffer(self.buffer, self.env, self.cx, |env, cx| {
+            let point = env.current_buffer.get().text.cursor().chars() + 1;
+            crate::insdel::replace_region(point, point, string, env, cx)
+        })
+    }
+}
+
+/// Call a function with each character of the output.
+struct FunctionStream<'a, 'ob, 'rt, 'cx> {
+    function: &'a Rto<Function<'ob>>,
+    env: &'a mut Rt<Env<'rt>>,
+    cx: &'a mut Context<'cx>,
+}
+
+impl Stream for FunctionStream<'_, '_, '_, '_> {
+    fn write_str(&mut self, string: &str) -> Result<()> {
+        let function = self.function;
+        for chr in string.chars() {
+            call!(function, chr; self.env, self.cx)?;
+        }
+        Ok(())
+    }
+}
+
+/// Call `f` with the stream PRINTCHARFUN. If it is nil, the value of
+/// `standard-output' is used instead. Positions are plain integers rather
+/// than markers, so a stream is t, a buffer or a function.
+pub(crate) fn with_stream<T>(
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+    f: impl FnOnce(&mut dyn Stream) -> Result<T>,
+) -> Result<T> {
+    let stream = match printcharfun.map(|x| x.bind(cx)) {
+        Some(stream) if !stream.is_nil() => stream,
+        _ => env.vars.get(sym::STANDARD_OUTPUT).map_or(TRUE, |x| x.bind(cx)),
+    };
+    match stream.untag() {
+        ObjectType::TRUE | ObjectType::NIL => f(&mut Stdout),
+        ObjectType::Buffer(buffer) => {
+            root!(buffer, cx);
+            f(&mut BufferStream { buffer, env, cx })
+        }
+        _ => {
+            let function: Function = stream.try_into()?;
+            root!(function, cx);
+            f(&mut FunctionStream { function, env, cx })
+        }
+    }
+}
+
+/// Write `string` to the stream PRINTCHARFUN, as for [`with_stream`].
+pub(crate) fn write_to_stream(
+    string: &str,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    with_stream(printcharfun, env, cx, |stream| stream.write_str(string))
+}
+
+/// Write the printed representation of `object` to the stream PRINTCHARFUN.
+/// Strings are quoted if `escape` is true.
+fn print_object(
+    object: &Rto<Object>,
+    escape: bool,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let string = match object.untag(cx) {
+        ObjectType::String(string) if !escape => string.to_string(),
+        _ => print_to_string(object.bind(cx), env, cx),
+    };
+    write_to_stream(&string, printcharfun, env, cx)
+}
+
+fn limit_var(var: Symbol, env: &Rt<Env>, cx: &Context) -> Option<usize> {
+    match env.vars.get(var)?.bind(cx).untag() {
+        ObjectType::Int(n) if n >= 0 => Some(n as usize),
+        _ => None,
+    }
+}
+
+/// The limits set by `print-level' and `print-length'.
+pub(crate) fn print_limits(env: &Rt<Env>, cx: &Context) -> PrintLimits {
+    PrintLimits {
+        level: limit_var(sym::PRINT_LEVEL, env, cx),
+        length: limit_var(sym::PRINT_LENGTH, env, cx),
+    }
+}
+
+/// The limits for printing the results of evaluation in the REPL, set by
+/// `eval-expression-print-level' and `eval-expression-print-length'.
+pub(crate) fn eval_expression_limits(env: &Rt<Env>, cx: &Context) -> PrintLimits {
+    PrintLimits {
+        level: limit_var(sym::EVAL_EXPRESSION_PRINT_LEVEL, env, cx),
+        length: limit_var(sym::EVAL_EXPRESSION_PRINT_LENGTH, env, cx),
+    }
+}
+
+/// Print `object` under the limits of `print-level' and `print-length'.
+pub(crate) fn print_to_string(object: Object, env: &Rt<Env>, cx: &Context) -> String {
+    with_print_limits(print_limits(env, cx), || object.to_string())
+}
+
+/// Output the printed representation of OBJECT to PRINTCHARFUN. Strings are
+/// quoted so that the output can be read back.
+#[defun]
+fn prin1<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    _overrides: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    print_object(object, true, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+/// Output the printed representation of OBJECT to PRINTCHARFUN without any
+/// quoting. This is meant for output to people rather than to `read'.
+#[defun]
+fn princ<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    print_object(object, false, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+/// Output OBJECT like `prin1', surrounded by newlines.
+#[defun]
+fn print<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    write_to_stream("\n", printcharfun, env, cx)?;
+    print_object(object, true, printcharfun, env, cx)?;
+    write_to_stream("\n", printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+/// Output a newline to PRINTCHARFUN.
+#[defun]
+fn terpri(
+    printcharfun: Option<&Rto<Object>>,
+    _ensure: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    write_to_stream("\n", printcharfun, env, cx)?;
+    Ok(true)
+}
+
+/// Output the character CHARACTER to PRINTCHARFUN.
+#[defun]
+fn write_char(
+    character: char,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<char> {
+    with_stream(printcharfun, env, cx, |stream| stream.write_char(character))?;
+    Ok(character)
+}
+
+/// Convert an error value (ERROR-SYMBOL . DATA) to an error message. The
+/// message is the `error-message' property of ERROR-SYMBOL followed by the
+/// elements of DATA.
 #[defun]
-fn error_message_string(obj: Object) -> String {
-    // TODO: implement
-    format!("Error: {obj}")
+fn error_message_string(obj: Object, env: &Rt<Env>, cx: &Context) -> String {
+    let ObjectType::Cons(obj) = obj.untag() else { return "peculiar error".into() };
+    let errname = obj.car();
+    let (mut message, mut tail, file_error) = if errname == sym::ERROR {
+        let data = obj.cdr();
+        let (message, tail) = match data.untag() {
+            ObjectType::Cons(data) => (data.car(), data.cdr()),
+            _ => (NIL, NIL),
+        };
+        (message, tail, false)
+    } else {
+        let ObjectType::Symbol(errname) = errname.untag() else {
+            return "peculiar error".into();
+        };
+        let conditions = crate::eval::error_conditions(errname, env, cx);
+        let mut conditions = conditions.as_list().into_iter().flatten();
+        let file_error = conditions.any(|x| x.is_ok_and(|x| x == sym::FILE_ERROR));
+        let message = crate::data::get(errname, sym::ERROR_MESSAGE, env, cx);
+        (message, obj.cdr(), file_error)
+    };
+    // file errors are made by concatenating the data
+    if let (true, ObjectType::Cons(cons)) = (file_error, tail.untag()) {
+        message = cons.car();
+        tail = cons.cdr();
+    }
+    let mut output = String::new();
+    let mut separator = Some(": ");
+    match message.untag() {
+        ObjectType::String(message) if message.is_empty() => separator = None,
+        ObjectType::String(message) => output.push_str(message),
+        _ => output.push_str("peculiar error"),
+    }
+    let princ = file_error || errname == sym::END_OF_FILE || errname == sym::USER_ERROR;
+    while let ObjectType::Cons(cons) = tail.untag() {
+        if let Some(separator) = separator {
+            output.push_str(separator);
+        }
+        separator = Some(", ");
+        match cons.car().untag() {
+            ObjectType::String(string) if princ => output.push_str(string),
+            _ => output.push_str(&print_to_string(cons.car(), env, cx)),
+        }
+        tail = cons.cdr();
+    }
+    output
 }
 
+defvar!(STANDARD_OUTPUT, true);
 defvar!(PRINT_LENGTH);
 defvar!(PRINT_LEVEL);
+defvar!(EVAL_EXPRESSION_PRINT_LENGTH, 12);
+defvar!(EVAL_EXPRESSION_PRINT_LEVEL, 4);
 defvar_bool!(PRINT_ESCAPE_NEWLINES, false);
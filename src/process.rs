@@ -0,0 +1,699 @@
+//! Subprocesses.
+//!
+//! Processes are records holding an id into a table shared by all threads,
+//! along with their buffer and filter function. Unlike
+//! [handles](crate::handle), a process stays in the table until it is
+//! deleted, even if nothing refers to it, as in Emacs. Output is only read while
+//! `accept-process-output' waits for it, and is passed to the filter or
+//! inserted at the end of the process buffer.
+//!
+//! Besides the usual pipes and ptys, the `:stdin' argument of `make-process'
+//! connects the output of another process directly to the input of the new
+//! one, so a pipeline doesn't need a shell or a round trip through lisp. The
+//! processes feeding a pipeline are deleted along with it.
+use crate::buffer::get_buffer_create;
+use crate::core::{
+    env::{sym, ArgSlice, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, ObjectType, RecordBuilder, Symbol, NIL},
+};
+use crate::fns::slice_into_list;
+use crate::insdel::{replace_region, with_current_buffer};
+use crate::permissions::{self, Capability};
+use anyhow::{anyhow, bail, Result};
+use rune_core::macros::{call, root};
+use rune_macros::defun;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{PipeReader, PipeWriter, Read, Write};
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where the output of a process comes from.
+enum Output {
+    Pipe(ChildStdout),
+    Pty(File),
+    PipeProcess(PipeReader),
+}
+
+impl Output {
+    fn into_reader(self) -> Box<dyn Read + Send> {
+        match self {
+            Output::Pipe(x) => Box::new(x),
+            Output::Pty(x) => Box::new(x),
+            Output::PipeProcess(x) => Box::new(x),
+        }
+    }
+}
+
+impl From<Output> for Stdio {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Pipe(x) => x.into(),
+            Output::Pty(x) => x.into(),
+            Output::PipeProcess(x) => x.into(),
+        }
+    }
+}
+
+struct Process {
+    name: String,
+    command: Vec<String>,
+    /// `None` for pipe processes
+    child: Option<Child>,
+    input: Option<Box<dyn Write + Send>>,
+    /// The write end of a pipe process, used as the stderr of other processes
+    pipe: Option<PipeWriter>,
+    /// The output that has not been claimed by a reader or another process
+    output: Option<Output>,
+    /// Chunks of output read by a background thread. `None` once the output
+    /// has ended. Shared so that a reader can wait for output without holding
+    /// the lock on [`PROCESSES`].
+    received: Option<Arc<Mutex<Receiver<Vec<u8>>>>>,
+    /// Bytes of an incomplete character at the end of the last chunk
+    pending: Vec<u8>,
+    tty: Option<String>,
+    /// Processes whose output feeds this one
+    upstream: Vec<i64>,
+    deleted: bool,
+}
+
+impl Process {
+    fn new(name: String, command: Vec<String>) -> Self {
+        Self {
+            name,
+            command,
+            child: None,
+            input: None,
+            pipe: None,
+            output: None,
+            received: None,
+            pending: Vec::new(),
+            tty: None,
+            upstream: Vec::new(),
+            deleted: false,
+        }
+    }
+
+    fn exit_status(&mut self) -> Option<ExitStatus> {
+        self.child.as_mut()?.try_wait().ok().flatten()
+    }
+
+    fn is_live(&mut self) -> bool {
+        match self.child {
+            Some(_) => self.exit_status().is_none(),
+            None => !self.deleted,
+        }
+    }
+}
+
+static PROCESSES: LazyLock<Mutex<HashMap<i64, Process>>> = LazyLock::new(Default::default);
+static NEXT_PROCESS: AtomicI64 = AtomicI64::new(0);
+
+fn process_object<'ob>(
+    id: i64,
+    buffer: Object<'ob>,
+    filter: Object<'ob>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let mut record = cx.vec_with_capacity(4);
+    record.push(sym::PROCESS.into());
+    record.push(cx.add(id));
+    record.push(buffer);
+    record.push(filter);
+    cx.add(RecordBuilder(record))
+}
+
+/// Return the id, buffer and filter of `process`.
+fn process_parts(process: Object) -> Result<(i64, Object, Object)> {
+    if let ObjectType::Record(record) = process.untag() {
+        if let [tag, id, buffer, filter] = &record[..] {
+            if let (ObjectType::Symbol(sym::PROCESS), ObjectType::Int(id)) =
+                (tag.get().untag(), id.get().untag())
+            {
+                return Ok((id, buffer.get(), filter.get()));
+            }
+        }
+    }
+    bail!("Not a process: {process}")
+}
+
+fn id_of(process: Object) -> Result<i64> {
+    Ok(process_parts(process)?.0)
+}
+
+fn with_process<T>(process: Object, f: impl FnOnce(&mut Process) -> Result<T>) -> Result<T> {
+    let id = id_of(process)?;
+    let mut processes = PROCESSES.lock().unwrap();
+    let process = processes.get_mut(&id).ok_or_else(|| anyhow!("Not a process: {process}"))?;
+    f(process)
+}
+
+fn add_process(process: Process) -> i64 {
+    let id = NEXT_PROCESS.fetch_add(1, Ordering::Relaxed);
+    PROCESSES.lock().unwrap().insert(id, process);
+    id
+}
+
+/// The keyword arguments shared by `make-process' and `make-pipe-process'.
+#[derive(Default)]
+struct ProcessArgs<'ob> {
+    name: Option<String>,
+    command: Vec<String>,
+    buffer: Option<Object<'ob>>,
+    filter: Option<Object<'ob>>,
+    pty: bool,
+    stdin: Option<Object<'ob>>,
+    stderr: Option<Object<'ob>>,
+}
+
+impl<'ob> ProcessArgs<'ob> {
+    fn new(args: &[Object<'ob>]) -> Result<Self> {
+        let mut parsed = Self::default();
+        for pair in args.chunks(2) {
+            let [key, value] = *pair else { bail!("Missing keyword value for {}", pair[0]) };
+            let ObjectType::Symbol(key) = key.untag() else { bail!("Invalid keyword: {key}") };
+            let value = Some(value).filter(|x| !x.is_nil());
+            match key {
+                sym::KW_NAME => match value.map(|x| x.untag()) {
+                    Some(ObjectType::String(name)) => parsed.name = Some(name.to_string()),
+                    _ => bail!(":name must be a string"),
+                },
+                sym::KW_COMMAND => {
+                    let Some(ObjectType::Cons(command)) = value.map(|x| x.untag()) else {
+                        bail!(":command must be a non-empty list of strings")
+                    };
+                    for arg in command {
+                        match arg?.untag() {
+                            ObjectType::String(arg) => parsed.command.push(arg.to_string()),
+                            other => bail!("Invalid command argument: {other}"),
+                        }
+                    }
+                }
+                sym::KW_BUFFER => parsed.buffer = value,
+                sym::KW_FILTER => parsed.filter = value,
+                sym::KW_CONNECTION_TYPE => match value.map(|x| x.untag()) {
+                    None | Some(ObjectType::Symbol(sym::PIPE)) => parsed.pty = false,
+                    Some(ObjectType::Symbol(sym::PTY)) => parsed.pty = true,
+                    _ => bail!("Invalid :connection-type {}", pair[1]),
+                },
+                sym::KW_STDIN => parsed.stdin = value,
+                sym::KW_STDERR => parsed.stderr = value,
+                // Only the default coding system is supported, and processes
+                // never query before exiting
+                sym::KW_CODING | sym::KW_NOQUERY => {}
+                _ => bail!("Invalid keyword: {key}"),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn name(&self) -> Result<String> {
+        self.name.clone().ok_or_else(|| anyhow!("Missing :name"))
+    }
+
+    fn buffer(&self, cx: &'ob Context) -> Result<Object<'ob>> {
+        match self.buffer {
+            Some(buffer) => get_buffer_create(buffer, None, cx),
+            None => Ok(NIL),
+        }
+    }
+}
+
+/// Open a pseudo terminal and return the master, the slave and the name of
+/// the slave device.
+#[cfg(unix)]
+fn open_pty() -> Result<(File, File, String)> {
+    use std::os::fd::FromRawFd;
+    let error = || anyhow!("Cannot open a pty: {}", std::io::Error::last_os_error());
+    let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    if fd < 0 {
+        return Err(error());
+    }
+    // SAFETY: the descriptor was just opened and nothing else owns it
+    let master = unsafe { File::from_raw_fd(fd) };
+    if unsafe { libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 } {
+        return Err(error());
+    }
+    // ptsname uses a static buffer, so copy the name out while holding a lock
+    static PTSNAME: Mutex<()> = Mutex::new(());
+    let name = {
+        let _guard = PTSNAME.lock().unwrap();
+        let name = unsafe { libc::ptsname(fd) };
+        if name.is_null() {
+            return Err(error());
+        }
+        unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy().into_owned()
+    };
+    let slave = File::options().read(true).write(true).open(&name)?;
+    Ok((master, slave, name))
+}
+
+#[cfg(not(unix))]
+fn open_pty() -> Result<(File, File, String)> {
+    bail!("ptys are not supported on this platform")
+}
+
+/// Start a process. ARGS are keyword arguments:
+///
+/// :name NAME -- the name of the process.
+/// :command COMMAND -- a list of the program and its arguments.
+/// :buffer BUFFER -- the buffer or buffer name that output is inserted into.
+/// :filter FILTER -- a function called with the process and each chunk of
+/// output instead of inserting it into the buffer.
+/// :connection-type TYPE -- `pipe' (the default) or `pty'.
+/// :stdin PROCESS -- connect the output of PROCESS to the input of this one.
+/// The output of PROCESS can then no longer be read from lisp.
+/// :stderr STDERR -- a pipe process that receives the error output, which is
+/// otherwise treated the same as the rest of the output.
+#[defun]
+fn make_process<'ob>(args: ArgSlice, env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let args = ProcessArgs::new(Rt::bind_slice(env.stack.arg_slice(args), cx))?;
+    spawn(args, env, cx)
+}
+
+/// Start `command` with its output inserted into `buffer`, the same as
+/// `make-process' with only `:name', `:command' and `:buffer'.
+pub(crate) fn start_process<'ob>(
+    name: &str,
+    command: Vec<String>,
+    buffer: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let args = ProcessArgs {
+        name: Some(name.to_owned()),
+        command,
+        buffer: Some(buffer),
+        ..Default::default()
+    };
+    spawn(args, env, cx)
+}
+
+fn spawn<'ob>(args: ProcessArgs<'ob>, env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    permissions::check(Capability::Subprocess, env, cx)?;
+    let name = args.name()?;
+    let Some(program) = args.command.first() else { bail!("Missing :command") };
+    let buffer = args.buffer(cx)?;
+    let mut process = Process::new(name, args.command.clone());
+    let mut command = Command::new(program);
+    command.args(&args.command[1..]);
+
+    let mut pty_master = None;
+    if args.pty {
+        let (master, slave, tty) = open_pty()?;
+        command.stdin(slave.try_clone()?).stdout(slave.try_clone()?).stderr(slave);
+        pty_master = Some(master);
+        process.tty = Some(tty);
+    } else {
+        command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let mut processes = PROCESSES.lock().unwrap();
+    if let Some(stdin) = args.stdin {
+        let id = id_of(stdin)?;
+        let upstream = processes.get_mut(&id).ok_or_else(|| anyhow!("Not a process: {stdin}"))?;
+        let Some(output) = upstream.output.take() else {
+            bail!("The output of process {} is already in use", upstream.name)
+        };
+        command.stdin(output);
+        process.upstream.push(id);
+    }
+    if let Some(stderr) = args.stderr {
+        let id = id_of(stderr)?;
+        let pipe = processes.get(&id).and_then(|x| x.pipe.as_ref());
+        let Some(pipe) = pipe else { bail!("{stderr} is not a pipe process") };
+        command.stderr(pipe.try_clone()?);
+    } else if !args.pty {
+        // Like Emacs, error output goes to the same place as the rest of the
+        // output
+        let (reader, writer) = std::io::pipe()?;
+        command.stdout(writer.try_clone()?).stderr(writer);
+        process.output = Some(Output::PipeProcess(reader));
+    }
+    let mut child =
+        command.spawn().map_err(|e| anyhow!("Searching for program: {e}, {program}"))?;
+    // Close our copies of the child's ends of the pipes, so that reading the
+    // output ends when the child exits
+    drop(command);
+    if let Some(master) = pty_master {
+        process.input = Some(Box::new(master.try_clone()?));
+        process.output = Some(Output::Pty(master));
+    }
+    if let Some(stdin) = child.stdin.take() {
+        process.input = Some(Box::new(stdin));
+    }
+    if let Some(stdout) = child.stdout.take() {
+        process.output = Some(Output::Pipe(stdout));
+    }
+    process.child = Some(child);
+    let id = NEXT_PROCESS.fetch_add(1, Ordering::Relaxed);
+    processes.insert(id, process);
+    Ok(process_object(id, buffer, args.filter.unwrap_or(NIL), cx))
+}
+
+/// Create a pipe process. Text sent to it with `process-send-string' is read
+/// back as its output, and it can be given as the `:stderr' of
+/// `make-process'. ARGS are the keyword arguments `:name', `:buffer' and
+/// `:filter', the same as `make-process'.
+#[defun]
+fn make_pipe_process<'ob>(args: &[Object<'ob>], cx: &'ob Context) -> Result<Object<'ob>> {
+    let args = ProcessArgs::new(args)?;
+    let mut process = Process::new(args.name()?, Vec::new());
+    let (reader, writer) = std::io::pipe()?;
+    process.input = Some(Box::new(writer.try_clone()?));
+    process.pipe = Some(writer);
+    process.output = Some(Output::PipeProcess(reader));
+    let id = add_process(process);
+    Ok(process_object(id, args.buffer(cx)?, args.filter.unwrap_or(NIL), cx))
+}
+
+/// Return t if OBJECT is a process.
+#[defun]
+fn processp(object: Object) -> bool {
+    process_parts(object).is_ok()
+}
+
+/// Return the name of PROCESS.
+#[defun]
+fn process_name(process: Object) -> Result<String> {
+    with_process(process, |x| Ok(x.name.clone()))
+}
+
+/// Return the command that was used to start PROCESS, or nil for a pipe
+/// process.
+#[defun]
+fn process_command<'ob>(process: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let command = with_process(process, |x| Ok(x.command.clone()))?;
+    let command: Vec<_> = command.into_iter().map(|x| cx.add(x)).collect();
+    Ok(slice_into_list(&command, None, cx))
+}
+
+/// Return the buffer of PROCESS.
+#[defun]
+fn process_buffer(process: Object) -> Result<Object> {
+    Ok(process_parts(process)?.1)
+}
+
+/// Return the filter function of PROCESS.
+#[defun]
+fn process_filter(process: Object) -> Result<Object> {
+    Ok(process_parts(process)?.2)
+}
+
+/// Return the operating system process id of PROCESS, or nil for a pipe
+/// process.
+#[defun]
+fn process_id(process: Object) -> Result<Option<i64>> {
+    with_process(process, |x| Ok(x.child.as_ref().map(|x| x.id().into())))
+}
+
+/// Return non-nil if PROCESS is running.
+#[defun]
+fn process_live_p(process: Object) -> Result<bool> {
+    with_process(process, |x| Ok(x.is_live()))
+}
+
+/// Return the status of PROCESS: `run' if it is running, `exit' if it has
+/// exited or `signal' if it was killed by a signal. Pipe processes are
+/// `open' until they are deleted, then `closed'.
+#[defun]
+fn process_status(process: Object) -> Result<Symbol> {
+    with_process(process, |x| {
+        if x.child.is_none() {
+            return Ok(if x.deleted { sym::CLOSED } else { sym::OPEN });
+        }
+        Ok(match x.exit_status() {
+            None => sym::RUN,
+            Some(status) if status.code().is_some() => sym::EXIT,
+            Some(_) => sym::SIGNAL,
+        })
+    })
+}
+
+/// Return the exit code of PROCESS, or the number of the signal that killed
+/// it. Return 0 while it is running.
+#[defun]
+fn process_exit_status(process: Object) -> Result<i64> {
+    with_process(process, |x| {
+        let Some(status) = x.exit_status() else { return Ok(0) };
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+        Ok(status.code().or(signal).unwrap_or_default().into())
+    })
+}
+
+/// Return the name of the terminal of PROCESS, or nil if it doesn't use a
+/// pty. STREAM is ignored since all three streams use the same terminal.
+#[defun]
+fn process_tty_name(process: Object, _stream: Option<Object>) -> Result<Option<String>> {
+    with_process(process, |x| Ok(x.tty.clone()))
+}
+
+/// Send STRING to the input of PROCESS.
+#[defun]
+fn process_send_string(process: Object, string: &str) -> Result<bool> {
+    with_process(process, |x| {
+        let Some(input) = &mut x.input else { bail!("Process {} has no input", x.name) };
+        input.write_all(string.as_bytes())?;
+        input.flush()?;
+        Ok(false)
+    })
+}
+
+/// Close the input of PROCESS, so that it reads end of file.
+#[defun]
+fn process_send_eof(process: Object) -> Result<Object> {
+    with_process(process, |x| {
+        x.input = None;
+        // A pipe process only ends once every writer is closed
+        x.pipe = None;
+        Ok(())
+    })?;
+    Ok(process)
+}
+
+/// Kill PROCESS and the processes that feed its input.
+#[defun]
+fn delete_process(process: Object) -> Result<bool> {
+    let mut processes = PROCESSES.lock().unwrap();
+    let mut ids = vec![id_of(process)?];
+    while let Some(id) = ids.pop() {
+        let Some(process) = processes.get_mut(&id) else { continue };
+        if let Some(child) = &mut process.child {
+            _ = child.kill();
+            _ = child.wait();
+        }
+        process.input = None;
+        process.pipe = None;
+        process.deleted = true;
+        ids.append(&mut process.upstream);
+    }
+    Ok(false)
+}
+
+/// Decode the complete characters at the start of `pending`, leaving the
+/// bytes of a character that was split between reads.
+fn decode(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => pending.len(),
+    };
+    let rest = pending.split_off(valid);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    text
+}
+
+/// Wait up to `timeout` for output from process `id`, then take whatever
+/// else is available. Returns `None` if the output has ended.
+fn read_output(id: i64, timeout: Option<Duration>) -> Result<Option<String>> {
+    let received = {
+        let mut processes = PROCESSES.lock().unwrap();
+        let Some(process) = processes.get_mut(&id) else { return Ok(None) };
+        if process.received.is_none() {
+            let Some(output) = process.output.take() else { return Ok(None) };
+            let mut reader = output.into_reader();
+            let (sender, received) = channel();
+            std::thread::spawn(move || {
+                let mut buf = [0; 4096];
+                // a pty reports an error instead of end of file once the child exits
+                while let Ok(len @ 1..) = reader.read(&mut buf) {
+                    if sender.send(buf[..len].to_vec()).is_err() {
+                        return;
+                    }
+                }
+            });
+            process.received = Some(Arc::new(Mutex::new(received)));
+        }
+        process.received.clone().unwrap()
+    };
+    // other threads can use their processes while this one waits
+    let mut chunks = Vec::new();
+    let ended = {
+        let received = received.lock().unwrap();
+        let first = match timeout {
+            Some(timeout) => received.recv_timeout(timeout),
+            None => received.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match first {
+            Ok(chunk) => {
+                chunks.extend(chunk);
+                while let Ok(chunk) = received.try_recv() {
+                    chunks.extend(chunk);
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(String::new())),
+            Err(RecvTimeoutError::Disconnected) => true,
+        }
+    };
+    let mut processes = PROCESSES.lock().unwrap();
+    let Some(process) = processes.get_mut(&id) else { return Ok(None) };
+    process.pending.extend(chunks);
+    if ended {
+        process.received = None;
+        let rest = String::from_utf8_lossy(&std::mem::take(&mut process.pending)).into();
+        return Ok(Some(rest).filter(|x: &String| !x.is_empty()));
+    }
+    Ok(Some(decode(&mut process.pending)))
+}
+
+/// Pass `output` to the filter of `process`, or insert it at the end of its
+/// buffer.
+fn deliver(process: &Rto<Object>, output: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let (_, buffer, filter) = process_parts(process.bind(cx))?;
+    if !filter.is_nil() {
+        root!(filter, cx);
+        let filter: &Rto<Function> = filter.try_as()?;
+        let arg = process.bind(cx);
+        let output = cx.add(output);
+        call!(filter, arg, output; env, cx)?;
+    } else if let ObjectType::Buffer(buffer) = buffer.untag() {
+        root!(buffer, cx);
+        with_current_buffer(buffer, env, cx, |env, cx| {
+            let point = env.current_buffer.get().text.cursor().chars();
+            let end = env.current_buffer.get().text.len_chars() + 1;
+            replace_region(end, end, output, env, cx)?;
+            env.current_buffer.get_mut().text.set_cursor(point);
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Wait for output from PROCESS and pass it to its filter, or insert it into
+/// its buffer. Wait at most SECONDS plus MILLISEC milliseconds, or until
+/// there is output or the process ends if neither is given. Return non-nil
+/// if there was output. Only the output of PROCESS is read, so without it
+/// this just waits.
+#[defun]
+fn accept_process_output(
+    process: &Rto<Object>,
+    seconds: Option<f64>,
+    millisec: Option<i64>,
+    _just_this_one: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    let timeout = match (seconds, millisec) {
+        (None, None) => None,
+        (seconds, millisec) => {
+            let secs = seconds.unwrap_or(0.0) + millisec.unwrap_or(0) as f64 / 1000.0;
+            Some(Duration::from_secs_f64(secs.max(0.0)))
+        }
+    };
+    if process.bind(cx).is_nil() {
+        if let Some(timeout) = timeout {
+            std::thread::sleep(timeout);
+        }
+        return Ok(false);
+    }
+    let id = id_of(process.bind(cx))?;
+    let deadline = timeout.map(|x| Instant::now() + x);
+    loop {
+        let remaining = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+        match crate::replay::input("process-output", || read_output(id, remaining))? {
+            Some(output) if !output.is_empty() => {
+                deliver(process, &output, env, cx)?;
+                return Ok(true);
+            }
+            // only part of a character has arrived
+            Some(_) if remaining.is_none_or(|x| !x.is_zero()) => {}
+            _ => return Ok(false),
+        }
+    }
+}
+
+defsym!(PROCESS);
+defsym!(PIPE);
+defsym!(PTY);
+defsym!(RUN);
+defsym!(EXIT);
+defsym!(OPEN);
+defsym!(CLOSED);
+defsym!(KW_NAME);
+defsym!(KW_COMMAND);
+defsym!(KW_BUFFER);
+defsym!(KW_FILTER);
+defsym!(KW_CONNECTION_TYPE);
+defsym!(KW_STDIN);
+defsym!(KW_STDERR);
+defsym!(KW_CODING);
+defsym!(KW_NOQUERY);
+
+#[cfg(test)]
+#[cfg(unix)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_pipeline() {
+        assert_lisp(
+            "(progn
+               (setq process-test-output nil)
+               (let* ((producer (make-process :name \"printf\" :command '(\"printf\" \"b\\na\\n\")))
+                      (sorter (make-process
+                               :name \"sort\" :command '(\"sort\") :stdin producer
+                               :filter (lambda (_process string)
+                                         (setq process-test-output (concat process-test-output string))))))
+                 (while (accept-process-output sorter 5))
+                 process-test-output))",
+            "\"a\\nb\\n\"",
+        );
+    }
+
+    #[test]
+    fn test_process_status() {
+        assert_lisp(
+            "(let ((process (make-process :name \"exit\" :command '(\"sh\" \"-c\" \"exit 3\"))))
+               (while (process-live-p process))
+               (list (process-status process) (process-exit-status process) (process-tty-name process)))",
+            "(exit 3 nil)",
+        );
+        assert_lisp(
+            "(let ((process (make-process :name \"tty\" :command '(\"true\") :connection-type 'pty)))
+               (stringp (process-tty-name process)))",
+            "t",
+        );
+    }
+
+    #[test]
+    fn test_pipe_process() {
+        assert_lisp(
+            "(progn
+               (setq process-test-pipe nil)
+               (let ((pipe (make-pipe-process
+                            :name \"pipe\" :filter (lambda (_process string) (setq process-test-pipe string)))))
+                 (process-send-string pipe \"hello\")
+                 (accept-process-output pipe 5)
+                 (list process-test-pipe (process-status pipe)
+                       (progn (delete-process pipe) (process-status pipe)))))",
+            "(\"hello\" open closed)",
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Finding projects and the files in them.
+//!
+//! A project is the nearest directory above a file that contains one of the
+//! files or directories in `project-root-markers'. Projects are records of
+//! the form (project ROOT). Listing the files of a project honors the
+//! .gitignore, .ignore and git exclude files in it, whether or not it is a
+//! git repository.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{List, Object, ObjectType, RecordBuilder, NIL},
+};
+use crate::fileio::expand_file_name;
+use crate::fns::slice_into_list;
+use anyhow::{bail, Result};
+use rune_macros::defun;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+/// Return the nearest directory at or above `dir` that contains one of
+/// `markers`.
+fn find_root(dir: &Path, markers: &[&str]) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|dir| markers.iter().any(|marker| dir.join(marker).exists()))
+        .map(Path::to_owned)
+}
+
+/// Return the files under `dirs` that are not ignored, sorted by name.
+fn list_files(dirs: &[PathBuf]) -> Result<Vec<String>> {
+    let Some((first, rest)) = dirs.split_first() else { return Ok(Vec::new()) };
+    let mut builder = ignore::WalkBuilder::new(first);
+    for dir in rest {
+        builder.add(dir);
+    }
+    // include dotfiles like .gitignore, but not the repository itself
+    builder
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|x| x.file_name() != ".git");
+    let mut files = Vec::new();
+    for entry in builder.build() {
+        let entry = entry?;
+        if entry.file_type().is_some_and(|x| x.is_file()) {
+            files.push(entry.path().to_string_lossy().into_owned());
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn as_directory(dir: &Path) -> String {
+    let dir = dir.to_string_lossy();
+    if dir.ends_with(MAIN_SEPARATOR) {
+        dir.into_owned()
+    } else {
+        format!("{dir}{MAIN_SEPARATOR}")
+    }
+}
+
+/// Return the project containing DIRECTORY, which defaults to
+/// `default-directory', or nil if it is not in a project. The project root
+/// is the nearest directory that contains one of `project-root-markers'.
+/// MAYBE-PROMPT is ignored, since there is no way to ask for a project.
+#[defun]
+fn project_current<'ob>(
+    _maybe_prompt: Option<Object>,
+    directory: Option<&str>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let dir = expand_file_name(directory.unwrap_or_default(), None, env, cx)?;
+    let markers = match env.vars.get(sym::PROJECT_ROOT_MARKERS) {
+        Some(markers) => markers.bind(cx),
+        None => NIL,
+    };
+    let markers: Vec<&str> = List::try_from(markers)?
+        .elements()
+        .map(|x| x?.try_into())
+        .collect::<Result<_>>()?;
+    let Some(root) = find_root(Path::new(&dir), &markers) else { return Ok(NIL) };
+    let mut record = cx.vec_with_capacity(2);
+    record.push(sym::PROJECT.into());
+    record.push(cx.add(as_directory(&root)));
+    Ok(cx.add(RecordBuilder(record)))
+}
+
+/// Return the root directory of PROJECT.
+#[defun]
+fn project_root(project: Object) -> Result<Object> {
+    if let ObjectType::Record(record) = project.untag() {
+        if record.len() == 2 && record[0].get() == sym::PROJECT {
+            return Ok(record[1].get());
+        }
+    }
+    bail!("Not a project: {project}")
+}
+
+/// Return a list of the files in PROJECT, skipping the files that are
+/// ignored by its ignore files. If DIRS is non-nil, only the files in those
+/// directories are listed.
+#[defun]
+fn project_files<'ob>(
+    project: Object,
+    dirs: Option<List>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let root: &str = project_root(project)?.try_into()?;
+    let dirs = match dirs {
+        Some(dirs) => dirs
+            .elements()
+            .map(|x| Ok(expand_file_name(x?.try_into()?, Some(root), env, cx)?.into()))
+            .collect::<Result<_>>()?,
+        None => vec![PathBuf::from(root)],
+    };
+    let files: Vec<_> = list_files(&dirs)?.into_iter().map(|x| cx.add(x)).collect();
+    Ok(slice_into_list(&files, None, cx))
+}
+
+defvar!(
+    PROJECT_ROOT_MARKERS,
+    list![
+        ".git",
+        ".hg",
+        ".svn",
+        ".project",
+        "Cargo.toml",
+        "go.mod",
+        "package.json",
+        "pyproject.toml"
+    ]
+);
+defsym!(PROJECT);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp_with_vars;
+    use std::fs;
+
+    #[test]
+    fn test_project() {
+        let dir = std::env::temp_dir().join(format!("rune-project-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::write(dir.join("Cargo.toml"), "").unwrap();
+        fs::write(dir.join(".gitignore"), "/target\n*.log\n").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join("src/nested/mod.rs"), "").unwrap();
+        fs::write(dir.join("src/debug.log"), "").unwrap();
+        fs::write(dir.join("target/debug/rune"), "").unwrap();
+
+        let root = find_root(&dir.join("src/nested"), &["Cargo.toml"]).unwrap();
+        assert_eq!(root, dir);
+        assert_eq!(find_root(&dir, &["no-such-marker"]), None);
+        let files = list_files(&[dir.clone()]).unwrap();
+        let relative: Vec<_> =
+            files.iter().map(|x| Path::new(x).strip_prefix(&dir).unwrap()).collect();
+        let expected = [".gitignore", "Cargo.toml", "src/main.rs", "src/nested/mod.rs"];
+        assert_eq!(relative, expected.map(Path::new));
+
+        let root = as_directory(&dir);
+        assert_lisp_with_vars(
+            &format!("(project-root (project-current nil \"{}\"))", dir.join("src").display()),
+            &format!("\"{root}\""),
+        );
+        assert_lisp_with_vars(
+            &format!("(length (project-files (project-current nil \"{root}\") '(\"src\")))"),
+            "2",
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,9 +2,10 @@
 use crate::core::{
     env::{intern, sym},
     gc::Context,
-    object::{Object, Symbol},
+    object::{ByteFn, FnArgs, IntoObject, LispHashTable, Object, ObjectType, Symbol},
 };
-use crate::fns;
+use crate::{arith::NumberValue, fns};
+use num_bigint::BigInt;
 use rune_core::macros::list;
 use std::fmt::Display;
 use std::str;
@@ -26,6 +27,7 @@ pub(crate) enum Error {
     UnknownMacroCharacter(char, usize),
     ParseInt(u8, usize),
     MalformedUnicdoe(usize),
+    InvalidByteCode(usize),
     EmptyStream,
 }
 
@@ -39,6 +41,7 @@ impl Display for Error {
             Error::ExtraCloseBracket(i) => write!(f, "Extra Closing brace: at {i}"),
             Error::UnexpectedChar(chr, i) => write!(f, "Unexpected character {chr}: at {i}"),
             Error::MalformedUnicdoe(i) => write!(f, "Malformed unicode: at {i}"),
+            Error::InvalidByteCode(i) => write!(f, "Invalid byte-code object: at {i}"),
             Error::EmptyStream => write!(f, "Empty Stream"),
             Error::ExtraItemInCdr(i) => write!(f, "Extra item in cdr: at {i}"),
             Error::MissingQuotedItem(i) => write!(f, "Missing element after quote: at {i}"),
@@ -66,6 +69,7 @@ impl Error {
             | Error::ExtraItemInCdr(x)
             | Error::UnexpectedChar(_, x)
             | Error::MalformedUnicdoe(x)
+            | Error::InvalidByteCode(x)
             | Error::ParseInt(_, x)
             | Error::UnknownMacroCharacter(_, x) => *x,
             Error::EmptyStream => 0,
@@ -79,6 +83,7 @@ impl Error {
             | Error::MissingStringDel(i)
             | Error::UnexpectedChar(_, i)
             | Error::MalformedUnicdoe(i)
+            | Error::InvalidByteCode(i)
             | Error::ExtraItemInCdr(i)
             | Error::ExtraCloseParen(i)
             | Error::ExtraCloseBracket(i)
@@ -304,7 +309,15 @@ impl<'a> Iterator for Tokenizer<'a> {
     }
 }
 
-fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
+fn intern_symbol<'ob>(
+    symbol: &str,
+    obarray: Option<&LispHashTable>,
+    cx: &'ob Context,
+) -> Symbol<'ob> {
+    let resolve = |name: &str| match obarray {
+        Some(obarray) => crate::module::module_intern(name, obarray, cx),
+        None => intern(name, cx),
+    };
     let mut escaped = false;
     let is_not_escape = |c: &char| {
         if escaped {
@@ -319,24 +332,47 @@ fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
     };
     if symbol.contains('\\') {
         let escaped_slice: String = symbol.chars().filter(is_not_escape).collect();
-        intern(escaped_slice.as_str(), cx)
+        resolve(escaped_slice.as_str())
     } else {
-        intern(symbol, cx)
+        resolve(symbol)
     }
 }
 
 /// Parse a symbol from a string. This will either by a true symbol or a number
 /// literal.
-fn parse_symbol<'a>(slice: &str, cx: &'a Context) -> Object<'a> {
-    match slice.parse::<i64>() {
-        Ok(num) => cx.add(num),
-        Err(_) => match slice.parse::<f64>() {
-            Ok(num) => cx.add(num),
-            Err(_) => cx.add(intern_symbol(slice, cx)),
+fn parse_symbol<'a>(slice: &str, obarray: Option<&LispHashTable>, cx: &'a Context) -> Object<'a> {
+    // integers can have a trailing decimal point
+    let int = slice.strip_suffix('.').unwrap_or(slice);
+    match int.parse::<i64>() {
+        Ok(num) => cx.add(NumberValue::Int(num)),
+        Err(_) if int.parse::<BigInt>().is_ok() => cx.add(NumberValue::Big(int.parse().unwrap())),
+        Err(_) => match parse_float(slice) {
+            Some(num) => cx.add(num),
+            None => cx.add(intern_symbol(slice, obarray, cx)),
         },
     }
 }
 
+/// Parse a float, including the syntax for infinities and NaNs like
+/// `-1.0e+INF' and `0.0e+NaN'.
+fn parse_float(slice: &str) -> Option<f64> {
+    // Rust also accepts names like "inf" and "NaN", which are symbols in lisp
+    if !slice.bytes().any(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    let special = |mantissa: &str, value: f64| {
+        let mantissa = mantissa.parse::<f64>().ok()?;
+        Some(if mantissa.is_sign_negative() { -value } else { value })
+    };
+    if let Some(mantissa) = slice.strip_suffix("e+INF") {
+        special(mantissa, f64::INFINITY)
+    } else if let Some(mantissa) = slice.strip_suffix("e+NaN") {
+        special(mantissa, f64::NAN)
+    } else {
+        slice.parse().ok()
+    }
+}
+
 /// process escape characters in the string slice and return the resulting
 /// string.
 fn unescape_string<'a>(string: &str, cx: &'a Context) -> Object<'a> {
@@ -367,7 +403,7 @@ fn unescape_string<'a>(string: &str, cx: &'a Context) -> Object<'a> {
 }
 
 /// Return true if `chr` is a valid symbol character.
-const fn symbol_char(chr: char) -> bool {
+pub(crate) const fn symbol_char(chr: char) -> bool {
     !matches!(chr, '\x00'..=' ' | '(' | ')' | '[' | ']' | '#' | ',' | '`' | ';' | '"' | '\'')
 }
 
@@ -389,6 +425,8 @@ struct Reader<'a, 'ob> {
     tokens: Tokenizer<'a>,
     /// New objects are allocated in the context.
     cx: &'ob Context<'ob>,
+    /// The obarray of the module being read, if any.
+    obarray: Option<&'ob LispHashTable>,
 }
 
 impl<'a, 'ob> Reader<'a, 'ob> {
@@ -420,7 +458,7 @@ impl<'a, 'ob> Reader<'a, 'ob> {
                 Token::Ident(".") => {
                     let cdr = self.read_cdr(delim)?;
                     if cdr.is_none() {
-                        objects.push(parse_symbol(".", self.cx));
+                        objects.push(parse_symbol(".", self.obarray, self.cx));
                     }
                     return Ok(fns::slice_into_list(&objects, cdr, self.cx));
                 }
@@ -441,6 +479,48 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         Err(Error::MissingCloseBracket(delim))
     }
 
+    /// Read a byte-code function literal of the form `#[ARGS CODE CONSTANTS
+    /// DEPTH]`. CODE can be either a string of op codes or a vector of bytes,
+    /// which is how byte-code functions are printed.
+    fn read_byte_code(&mut self, pos: usize) -> Result<Object<'ob>> {
+        let mut elems = Vec::new();
+        loop {
+            match self.tokens.next() {
+                Some(Token::CloseBracket(_)) => break,
+                Some(tok) => elems.push(self.read_sexp(tok)?),
+                None => return Err(Error::MissingCloseBracket(pos)),
+            }
+        }
+        let [args, code, consts, depth, ..] = elems[..] else {
+            return Err(Error::InvalidByteCode(pos));
+        };
+        let invalid = || Error::InvalidByteCode(pos);
+        let ObjectType::Int(args) = args.untag() else { return Err(invalid()) };
+        let args = FnArgs::from_arg_spec(args).map_err(|_| invalid())?;
+        let code: Vec<u8> = match code.untag() {
+            ObjectType::ByteString(x) => x.to_vec(),
+            ObjectType::String(x) => x
+                .chars()
+                .map(|c| u8::try_from(u32::from(c)).map_err(|_| invalid()))
+                .collect::<Result<_>>()?,
+            ObjectType::Vec(x) => x
+                .iter()
+                .map(|x| match x.get().untag() {
+                    ObjectType::Int(x) => u8::try_from(x).map_err(|_| invalid()),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid()),
+        };
+        let ObjectType::Vec(consts) = consts.untag() else { return Err(invalid()) };
+        let ObjectType::Int(depth) = depth.untag() else { return Err(invalid()) };
+        let depth = usize::try_from(depth).map_err(|_| invalid())?;
+        // SAFETY: the constants were allocated in the same context as the
+        // function and it is put in the heap immediately.
+        let bytefn = unsafe { ByteFn::make(&code, consts, args, depth) };
+        Ok(bytefn.into_obj(self.cx).into())
+    }
+
     /// Quote an item using `symbol`.
     fn quote_item(&mut self, pos: usize, symbol: Symbol) -> Result<Object<'ob>> {
         let obj: Object = match self.tokens.next() {
@@ -479,6 +559,7 @@ impl<'a, 'ob> Reader<'a, 'ob> {
             Some('b') => self.read_radix(pos, 2),
             Some('o') => self.read_radix(pos, 8),
             Some('x') => self.read_radix(pos, 16),
+            Some('[') => self.read_byte_code(pos),
             Some(chr) => Err(Error::UnknownMacroCharacter(chr, pos)),
             None => Err(Error::MissingQuotedItem(pos)),
         }
@@ -496,7 +577,7 @@ impl<'a, 'ob> Reader<'a, 'ob> {
             Token::Backquote(i) => self.quote_item(i, sym::BACKQUOTE),
             Token::Sharp(i) => self.read_sharp(i),
             Token::QuestionMark(_, c) => Ok((c as i64).into()),
-            Token::Ident(x) => Ok(parse_symbol(x, self.cx)),
+            Token::Ident(x) => Ok(parse_symbol(x, self.obarray, self.cx)),
             Token::String(x) => Ok(unescape_string(x, self.cx)),
             Token::Error(e) => Err(e),
         }
@@ -506,7 +587,17 @@ impl<'a, 'ob> Reader<'a, 'ob> {
 /// read a lisp object from `slice`. Return the object and index of next
 /// remaining character in the slice.
 pub(crate) fn read<'ob>(slice: &str, cx: &'ob Context) -> Result<(Object<'ob>, usize)> {
-    let mut reader = Reader { tokens: Tokenizer::new(slice), cx };
+    read_in(slice, None, cx)
+}
+
+/// Like [`read`], but symbols are resolved in the module with `obarray`. See
+/// [`module_intern`](crate::module::module_intern).
+pub(crate) fn read_in<'ob>(
+    slice: &str,
+    obarray: Option<&'ob LispHashTable>,
+    cx: &'ob Context,
+) -> Result<(Object<'ob>, usize)> {
+    let mut reader = Reader { tokens: Tokenizer::new(slice), cx, obarray };
     match reader.tokens.next() {
         Some(t) => reader.read_sexp(t).map(|x| (x, reader.tokens.cur_pos())),
         None => Err(Error::EmptyStream),
@@ -559,6 +650,30 @@ mod test {
         check_reader!(0x1, "#x001", cx);
         check_reader!(0x10, "#x10", cx);
         check_reader!(0xdead_beef_i64, "#xDeAdBeEf", cx);
+        check_reader!(1, "1.", cx);
+        let float = |text| match read(text, cx).unwrap().0.untag() {
+            ObjectType::Float(x) => **x,
+            x => panic!("expected a float, found {x}"),
+        };
+        assert_eq!(float("1.0e+INF"), f64::INFINITY);
+        assert_eq!(float("-1.0e+INF"), f64::NEG_INFINITY);
+        assert!(float("0.0e+NaN").is_nan());
+        check_reader!(intern("inf", cx), "inf", cx);
+        check_reader!(intern("NaN", cx), "NaN", cx);
+    }
+
+    #[test]
+    fn test_read_bignum() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let text = "123456789012345678901234567890";
+        let big: BigInt = text.parse().unwrap();
+        check_reader!(big.clone(), text, cx);
+        check_reader!(-big, "-123456789012345678901234567890", cx);
+        let max = crate::core::object::MAX_FIXNUM;
+        check_reader!(max, &max.to_string(), cx);
+        let obj = read(&(max + 1).to_string(), cx).unwrap().0;
+        assert!(matches!(obj.untag(), ObjectType::BigInt(_)));
     }
 
     #[test]
@@ -663,6 +778,19 @@ baz""#,
         assert_error("#a", Error::UnknownMacroCharacter('a', 0), cx);
     }
 
+    #[test]
+    fn read_byte_code() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let obj = read("#[257 [1 84 135 ] [x] 2 ]", cx).unwrap().0;
+        assert_eq!(format!("{obj}"), "#[257 [1 84 135 ] [x ] 2]");
+        let obj = read("#[0 \"ab\" [5] 1]", cx).unwrap().0;
+        assert_eq!(format!("{obj}"), "#[0 [97 98 ] [5 ] 1]");
+        assert_error("#[1 2]", Error::InvalidByteCode(0), cx);
+        assert_error("#[0 [256] [] 0]", Error::InvalidByteCode(0), cx);
+        assert_error("#[0 [] []", Error::MissingCloseBracket(0), cx);
+    }
+
     #[test]
     fn test_read_vec() {
         let roots = &RootSet::default();
@@ -0,0 +1,195 @@
+//! A list of recently opened files, like recentf.
+//!
+//! Files visited with `find-file' are added to the front of
+//! `recentf-list', which keeps at most `recentf-max-saved-items' files.
+//! Files matching a regexp in `recentf-exclude' are not added. If
+//! `recentf-save-file' is set the list is restored from it at startup and
+//! written back on exit. The file is written by the persistence layer, so it
+//! is only read and never evaluated.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, NIL},
+};
+use crate::fns::slice_into_list;
+use crate::permissions::{check_file, Capability};
+use crate::persist::{persist_contents, restore_contents};
+use crate::search::lisp_regex_to_rust;
+use anyhow::{bail, Result};
+use fancy_regex::Regex;
+use rune_macros::defun;
+use std::path::Path;
+
+/// The version of the saved list.
+const VERSION: i64 = 1;
+
+fn recent_files<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Result<Vec<Object<'ob>>> {
+    let list = env.vars.get(sym::RECENTF_LIST).map_or(NIL, |x| x.bind(cx));
+    Ok(list.as_list()?.collect::<Result<_, _>>()?)
+}
+
+/// Set `recentf-list' to `files`, keeping at most `recentf-max-saved-items'.
+fn set_recent_files<'ob>(
+    mut files: Vec<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<()> {
+    let max = env.vars.get(sym::RECENTF_MAX_SAVED_ITEMS).map(|x| x.bind(cx).untag());
+    if let Some(ObjectType::Int(max)) = max {
+        files.truncate(max.max(0) as usize);
+    }
+    env.set_var(sym::RECENTF_LIST, slice_into_list(&files, None, cx), cx)
+}
+
+fn is_file(obj: Object, file: &str) -> bool {
+    matches!(obj.untag(), ObjectType::String(x) if &**x == file)
+}
+
+fn excluded(file: &str, env: &Rt<Env>, cx: &Context) -> Result<bool> {
+    let Some(exclude) = env.vars.get(sym::RECENTF_EXCLUDE) else { return Ok(false) };
+    for regexp in exclude.bind(cx).as_list()? {
+        let regexp: &str = regexp?.try_into()?;
+        if Regex::new(&lisp_regex_to_rust(regexp))?.is_match(file)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Move `file` to the front of `recentf-list', unless it is excluded.
+pub(crate) fn add_file(file: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    if excluded(file, env, cx)? {
+        return Ok(());
+    }
+    let mut files = recent_files(env, cx)?;
+    files.retain(|x| !is_file(*x, file));
+    files.insert(0, cx.add(file));
+    set_recent_files(files, env, cx)
+}
+
+/// Add FILENAME to the front of `recentf-list', removing any other entry
+/// for it. Files matching a regexp in `recentf-exclude' are not added.
+#[defun]
+fn recentf_add_file(filename: &str, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let filename = crate::fileio::expand_file_name(filename, None, env, cx)?;
+    add_file(&filename, env, cx)?;
+    Ok(false)
+}
+
+/// Remove FILENAME from `recentf-list'.
+#[defun]
+fn recentf_remove_file(filename: &str, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let filename = crate::fileio::expand_file_name(filename, None, env, cx)?;
+    let mut files = recent_files(env, cx)?;
+    files.retain(|x| !is_file(*x, &filename));
+    set_recent_files(files, env, cx)?;
+    Ok(false)
+}
+
+/// Return a list of the N most recent files.
+#[defun]
+fn recentf_elements<'ob>(n: usize, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let files = recent_files(env, cx)?;
+    Ok(slice_into_list(&files[..n.min(files.len())], None, cx))
+}
+
+/// Remove the files that no longer exist from `recentf-list'. Return the
+/// number of files removed.
+#[defun]
+fn recentf_cleanup(env: &mut Rt<Env>, cx: &Context) -> Result<usize> {
+    let files = recent_files(env, cx)?;
+    let len = files.len();
+    let kept: Vec<_> = files
+        .into_iter()
+        .filter(|x| matches!(x.untag(), ObjectType::String(file) if Path::new(&**file).exists()))
+        .collect();
+    let removed = len - kept.len();
+    set_recent_files(kept, env, cx)?;
+    Ok(removed)
+}
+
+fn save_file(env: &Rt<Env>, cx: &Context) -> Option<String> {
+    match env.vars.get(sym::RECENTF_SAVE_FILE)?.bind(cx).untag() {
+        ObjectType::String(file) => Some(file.to_string()),
+        _ => None,
+    }
+}
+
+/// Save `recentf-list' to `recentf-save-file'. Does nothing if
+/// `recentf-save-file' is nil.
+#[defun]
+pub(crate) fn recentf_save_list(env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let Some(file) = save_file(env, cx) else { return Ok(false) };
+    check_file(Capability::Write, &file, env, cx)?;
+    let files = recent_files(env, cx)?;
+    let contents = persist_contents(slice_into_list(&files, None, cx), cx.add(VERSION))?;
+    if let Some(dir) = Path::new(&file).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&file, contents)?;
+    Ok(true)
+}
+
+/// Restore `recentf-list' from `recentf-save-file'. Does nothing if the file
+/// does not exist or was saved in another format.
+#[defun]
+pub(crate) fn recentf_load_list(env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let Some(file) = save_file(env, cx) else { return Ok(false) };
+    check_file(Capability::Read, &file, env, cx)?;
+    let contents = match std::fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => bail!("Failed to read {file}: {e}"),
+    };
+    let Some(list) = restore_contents(&contents, cx.add(VERSION), cx)? else {
+        return Ok(false);
+    };
+    let mut files = Vec::new();
+    for file in list.as_list()? {
+        let file = file?;
+        if matches!(file.untag(), ObjectType::String(_)) {
+            files.push(file);
+        }
+    }
+    set_recent_files(files, env, cx)?;
+    Ok(true)
+}
+
+defvar!(RECENTF_LIST);
+defvar!(RECENTF_MAX_SAVED_ITEMS, 20);
+defvar!(RECENTF_EXCLUDE);
+defvar!(RECENTF_SAVE_FILE);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_recent_files() {
+        assert_lisp(
+            "(let ((recentf-list nil) (recentf-max-saved-items 2)) (recentf-add-file \"/a\") (recentf-add-file \"/b\") (recentf-add-file \"/a\") (recentf-add-file \"/c\") recentf-list)",
+            "(\"/c\" \"/a\")",
+        );
+        assert_lisp(
+            "(let ((recentf-list nil) (recentf-exclude '(\"\\\\.tmp\\\\'\"))) (recentf-add-file \"/a.tmp\") (recentf-add-file \"/b\") (recentf-remove-file \"/c\") recentf-list)",
+            "(\"/b\")",
+        );
+        assert_lisp(
+            "(let ((recentf-list '(\"/rune/missing\" \"/\"))) (list (recentf-cleanup) (recentf-elements 5)))",
+            "(1 (\"/\"))",
+        );
+    }
+
+    #[test]
+    fn test_save_list() {
+        let file = std::env::temp_dir().join(format!("rune-recentf-{}.eld", std::process::id()));
+        let file = file.to_str().unwrap();
+        assert_lisp(
+            &format!(
+                "(let ((recentf-save-file \"{file}\") (recentf-list '(\"/a\" \"/b\"))) (recentf-save-list) (setq recentf-list nil) (list (recentf-load-list) recentf-list))"
+            ),
+            "(t (\"/a\" \"/b\"))",
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,330 @@
+//! A network REPL for editor integration.
+//!
+//! `repl-server-start' listens on a local TCP port. Each line a client sends
+//! is a JSON request with an `op` and an optional `id`, which is copied into
+//! every response to that request. Any local user can connect to the port, so
+//! the server writes a random token to a file only its owner can read, and the
+//! first request of a connection has to present it. The supported ops are:
+//!
+//! - `auth`: authenticate with the `token` from the file. Any other request
+//!   before this one closes the connection.
+//! - `eval`: evaluate the forms in `code`. Output to stdout and stderr is sent
+//!   as `out` and `err` responses, followed by one with the printed `value`
+//!   of the last form and `status` `done`, or an `error` with `status` `error`
+//!   or `interrupted`.
+//! - `interrupt`: quit the running evaluation, or only the one with the id
+//!   `interrupt-id` if it is given.
+//! - `describe`: list the supported ops.
+//! - `close`: close the connection.
+//!
+//! Like the emacsclient server, requests are read on background threads and
+//! evaluated on the main thread by [`handle_requests`].
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+};
+use crate::eval::EvalError;
+use crate::keyboard::InterruptHandle;
+use crate::permissions::{self, Capability};
+use crate::print::capture_output;
+use crate::reader;
+use anyhow::{bail, Result};
+use rune_core::macros::root;
+use rune_macros::defun;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const OPS: [&str; 5] = ["auth", "eval", "interrupt", "describe", "close"];
+
+type Client = Arc<Mutex<TcpStream>>;
+
+struct Request {
+    id: Value,
+    code: String,
+    client: Client,
+}
+
+static REQUESTS: Mutex<Option<Receiver<Request>>> = Mutex::new(None);
+/// The id of the request being evaluated and the handle to interrupt it.
+static CURRENT: Mutex<Option<(Value, InterruptHandle)>> = Mutex::new(None);
+
+fn send(client: &Client, response: Value) {
+    let mut line = response.to_string();
+    line.push('\n');
+    _ = client.lock().unwrap().write_all(line.as_bytes());
+}
+
+/// The file in `dir` that holds the token of the server on `port`.
+pub(crate) fn token_file(dir: &Path, port: u16) -> PathBuf {
+    dir.join(format!("repl-server-{port}"))
+}
+
+/// Write `token` to a new file that only the current user can read.
+fn write_token(path: &Path, token: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        if let Some(dir) = path.parent() {
+            std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        }
+        options.mode(0o600);
+    }
+    // A token left behind by a server that used the same port
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(())
+}
+
+/// Compare the tokens without returning early, so the time taken does not tell
+/// how much of `given` was right.
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Start listening on `port` of the loopback interface and return the port
+/// that was bound, which is only different when `port` is 0. The token that
+/// clients have to send is written to [`token_file`] in `dir`.
+pub(crate) fn start(port: u16, dir: &Path) -> Result<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let port = listener.local_addr()?.port();
+    let token = format!("{:032x}", rand::random::<u128>());
+    write_token(&token_file(dir, port), &token)?;
+    let token: Arc<str> = token.into();
+    let (sender, requests) = channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            let token = token.clone();
+            std::thread::spawn(move || read_client(stream, sender, &token));
+        }
+    });
+    *REQUESTS.lock().unwrap() = Some(requests);
+    Ok(port)
+}
+
+fn read_client(stream: TcpStream, sender: Sender<Request>, token: &str) {
+    let Ok(writer) = stream.try_clone() else { return };
+    let client = Arc::new(Mutex::new(writer));
+    let mut authenticated = false;
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { return };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = format!("Invalid request: {e}");
+                send(&client, json!({"error": error, "status": "error"}));
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let op = request.get("op").and_then(Value::as_str);
+        if !authenticated {
+            let given = request.get("token").and_then(Value::as_str);
+            if op != Some("auth") || !given.is_some_and(|x| token_matches(x, token)) {
+                send(&client, json!({"id": id, "error": "Not authenticated", "status": "error"}));
+                return;
+            }
+            authenticated = true;
+            send(&client, json!({"id": id, "status": "done"}));
+            continue;
+        }
+        match op {
+            Some("eval") => {
+                let code = request.get("code").and_then(Value::as_str).unwrap_or_default();
+                let request = Request { id, code: code.to_owned(), client: client.clone() };
+                if sender.send(request).is_err() {
+                    return;
+                }
+            }
+            Some("interrupt") => {
+                let running = CURRENT.lock().unwrap().clone();
+                let status = match (running, request.get("interrupt-id")) {
+                    (Some((running, _)), Some(target)) if &running != target => "session-idle",
+                    (Some((_, handle)), _) => {
+                        handle.interrupt();
+                        "done"
+                    }
+                    (None, _) => "session-idle",
+                };
+                send(&client, json!({"id": id, "status": status}));
+            }
+            Some("describe") => send(&client, json!({"id": id, "ops": OPS, "status": "done"})),
+            Some("close") => {
+                send(&client, json!({"id": id, "status": "done"}));
+                return;
+            }
+            Some("auth") => send(&client, json!({"id": id, "status": "done"})),
+            op => {
+                let error = format!("Unknown op: {}", op.unwrap_or("nil"));
+                send(&client, json!({"id": id, "error": error, "status": "error"}));
+            }
+        }
+    }
+}
+
+/// Evaluate the forms in `code` and return the printed value of the last one.
+fn eval_string(code: &str, env: &mut Rt<Env>, cx: &mut Context) -> Result<String> {
+    let mut pos = 0;
+    let mut value = "nil".to_owned();
+    loop {
+        let (form, new_pos) = match reader::read(&code[pos..], cx) {
+            Ok(x) => x,
+            Err(reader::Error::EmptyStream) => return Ok(value),
+            Err(mut e) => {
+                e.update_pos(pos);
+                bail!(e);
+            }
+        };
+        pos += new_pos;
+        root!(form, cx);
+        value = crate::panics::catch_panic(env, cx, |env, cx| {
+            Ok(crate::interpreter::eval(form, None, env, cx)?.to_string())
+        })?;
+    }
+}
+
+fn evaluate(request: Request, env: &mut Rt<Env>, cx: &mut Context) {
+    let Request { id, code, client } = request;
+    *CURRENT.lock().unwrap() = Some((id.clone(), InterruptHandle::current()));
+    crate::keyboard::discard_pending_quit();
+    let (result, output) = capture_output(|| {
+        eval_string(&code, env, cx).map_err(|e| match e.downcast::<EvalError>() {
+            Ok(e) => (e.is_signal(sym::QUIT, env), e.describe(env, cx)),
+            Err(e) => (false, e.to_string()),
+        })
+    });
+    *CURRENT.lock().unwrap() = None;
+    if !output.stdout.is_empty() {
+        send(&client, json!({"id": id, "out": output.stdout}));
+    }
+    if !output.stderr.is_empty() {
+        send(&client, json!({"id": id, "err": output.stderr}));
+    }
+    let response = match result {
+        Ok(value) => json!({"id": id, "value": value, "status": "done"}),
+        Err((true, _)) => json!({"id": id, "error": "Quit", "status": "interrupted"}),
+        Err((false, error)) => json!({"id": id, "error": error, "status": "error"}),
+    };
+    send(&client, response);
+}
+
+/// Evaluate requests from clients. If `timeout` is `None`, wait for requests
+/// forever. Otherwise wait up to `timeout` for a request, then handle any
+/// others that are already pending. Returns the number of requests handled.
+pub(crate) fn handle_requests(
+    timeout: Option<Duration>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let mut handled = 0;
+    let mut timeout = timeout;
+    loop {
+        let request = {
+            let requests = REQUESTS.lock().unwrap();
+            let Some(requests) = requests.as_ref() else { bail!("The REPL server is not running") };
+            match timeout {
+                Some(timeout) => requests.recv_timeout(timeout),
+                None => requests.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            }
+        };
+        let Ok(request) = request else { return Ok(handled) };
+        evaluate(request, env, cx);
+        handled += 1;
+        if timeout.is_some() {
+            timeout = Some(Duration::ZERO);
+        }
+    }
+}
+
+/// Start the REPL server on PORT, or any free port if it is nil. Return the
+/// port that the server is listening on. The token that clients have to
+/// authenticate with is written to the file `repl-server-PORT' in
+/// `server-socket-dir'.
+#[defun]
+fn repl_server_start(port: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> Result<i64> {
+    permissions::check(Capability::Network, env, cx)?;
+    let dir = crate::server::string_var(sym::SERVER_SOCKET_DIR, env, cx)?;
+    Ok(start(u16::try_from(port.unwrap_or(0))?, Path::new(&dir))?.into())
+}
+
+/// Evaluate the requests sent to the REPL server, waiting up to TIMEOUT
+/// seconds for one to arrive. Return the number of requests handled.
+#[defun]
+fn repl_server_handle_requests(
+    timeout: Option<f64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let timeout = Duration::from_secs_f64(timeout.unwrap_or(0.0).max(0.0));
+    handle_requests(Some(timeout), env, cx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::with_env;
+
+    #[test]
+    fn test_repl_server() {
+        with_env(|env, cx| {
+            let dir = std::env::temp_dir().join(format!("rune-repl-{}", std::process::id()));
+            let port = start(0, &dir).unwrap();
+            let token = std::fs::read_to_string(token_file(&dir, port)).unwrap();
+
+            // a connection that does not authenticate first is closed
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            writeln!(stream, "{}", json!({"id": 0, "op": "eval", "code": "1"})).unwrap();
+            let rejected: Vec<String> =
+                BufReader::new(stream).lines().map(Result::unwrap).collect();
+            assert_eq!(rejected.len(), 1);
+            let rejected: Value = serde_json::from_str(&rejected[0]).unwrap();
+            assert_eq!(rejected["error"], "Not authenticated");
+
+            let client = std::thread::spawn(move || {
+                let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+                let requests = [
+                    json!({"id": 0, "op": "auth", "token": token}),
+                    json!({"id": 1, "op": "eval", "code": "(princ \"hi\") (+ 1 2)"}),
+                    json!({"id": 2, "op": "eval", "code": "(car 1)"}),
+                    json!({"id": 3, "op": "close"}),
+                ];
+                for request in requests {
+                    writeln!(stream, "{request}").unwrap();
+                }
+                let reader = BufReader::new(stream);
+                reader
+                    .lines()
+                    .map(|x| serde_json::from_str(&x.unwrap()).unwrap())
+                    .collect::<Vec<Value>>()
+            });
+            let mut handled = 0;
+            while handled < 2 {
+                handled += handle_requests(Some(Duration::from_secs(5)), env, cx).unwrap();
+            }
+            let responses = client.join().unwrap();
+            let for_id = |id: i64| responses.iter().filter(move |x| x["id"] == id);
+            let first: Vec<_> = for_id(1).collect();
+            assert_eq!(first[0]["out"], "hi");
+            assert_eq!(first[1]["value"], "3");
+            assert_eq!(first[1]["status"], "done");
+            let second: Vec<_> = for_id(2).collect();
+            assert_eq!(second[0]["status"], "error");
+            assert_eq!(for_id(3).next().unwrap()["status"], "done");
+            assert_eq!(for_id(0).next().unwrap()["status"], "done");
+            _ = std::fs::remove_dir_all(&dir);
+        });
+    }
+}
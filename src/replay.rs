@@ -0,0 +1,278 @@
+//! Recording and replaying the non-deterministic inputs of a run.
+//!
+//! Started with `--record FILE`, every value that the interpreter takes from
+//! outside the program (the time, random seeds, environment variables and
+//! process output) is appended to FILE as a line of JSON. Started with
+//! `--replay FILE`, the same values are read back from FILE in order instead,
+//! so a run that went wrong can be reproduced exactly. If the replayed run
+//! asks for a different kind of input than was recorded it has diverged, and
+//! it continues with live inputs after a warning. Each line is flushed as it
+//! is written so that the log survives a crash. Inputs taken on other threads
+//! are logged too, but their order is only reproducible for single threaded
+//! runs.
+use anyhow::{bail, ensure, Context as _, Result};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// The version of the log format.
+const VERSION: u64 = 1;
+
+/// A value that can be saved in the log.
+pub(crate) trait Input: Sized {
+    fn to_json(&self) -> Value;
+    fn from_json(value: Value) -> Option<Self>;
+}
+
+impl Input for u64 {
+    fn to_json(&self) -> Value {
+        Value::from(*self)
+    }
+
+    fn from_json(value: Value) -> Option<Self> {
+        value.as_u64()
+    }
+}
+
+impl Input for Duration {
+    fn to_json(&self) -> Value {
+        json!([self.as_secs(), self.subsec_nanos()])
+    }
+
+    fn from_json(value: Value) -> Option<Self> {
+        let [secs, nanos] = value.as_array()?.as_slice() else { return None };
+        Some(Duration::new(secs.as_u64()?, u32::try_from(nanos.as_u64()?).ok()?))
+    }
+}
+
+impl Input for Option<String> {
+    fn to_json(&self) -> Value {
+        Value::from(self.clone())
+    }
+
+    fn from_json(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            Value::String(string) => Some(Some(string)),
+            _ => None,
+        }
+    }
+}
+
+/// Errors are replayed with the same message.
+impl<T: Input> Input for Result<T> {
+    fn to_json(&self) -> Value {
+        match self {
+            Ok(value) => json!({ "ok": value.to_json() }),
+            Err(e) => json!({ "error": e.to_string() }),
+        }
+    }
+
+    fn from_json(mut value: Value) -> Option<Self> {
+        if let Some(ok) = value.get_mut("ok") {
+            return T::from_json(ok.take()).map(Ok);
+        }
+        Some(Err(anyhow::anyhow!(value.get("error")?.as_str()?.to_owned())))
+    }
+}
+
+/// Return the log line recording that `value` was taken as a `kind` input.
+fn encode(kind: &str, value: &impl Input) -> String {
+    let mut line = json!([kind, value.to_json()]).to_string();
+    line.push('\n');
+    line
+}
+
+/// The inputs of a recorded run that have not been replayed yet.
+struct Replay {
+    events: VecDeque<(String, Value)>,
+}
+
+impl Replay {
+    fn parse(contents: &str) -> Result<Self> {
+        let mut lines = contents.lines();
+        let header: Value = serde_json::from_str(lines.next().unwrap_or_default())?;
+        ensure!(header == json!(["rune-replay", VERSION]), "Not a replay log: {header}");
+        let mut events = VecDeque::new();
+        for (number, line) in lines.enumerate() {
+            let event = serde_json::from_str(line).ok().and_then(|x: Value| match x {
+                Value::Array(mut x) if x.len() == 2 && x[0].is_string() => {
+                    let value = x.pop().unwrap();
+                    Some((x.pop().unwrap().as_str().unwrap().to_owned(), value))
+                }
+                _ => None,
+            });
+            let Some(event) = event else { bail!("Invalid event on line {}: {line}", number + 2) };
+            events.push_back(event);
+        }
+        Ok(Self { events })
+    }
+
+    /// Take the next input, which should be a `kind` input. Returns `None` if
+    /// every input has been replayed.
+    fn next<T: Input>(&mut self, kind: &str) -> Result<Option<T>> {
+        let Some((recorded, value)) = self.events.pop_front() else { return Ok(None) };
+        ensure!(recorded == kind, "expected a `{recorded}' input, but `{kind}' was requested");
+        match T::from_json(value) {
+            Some(value) => Ok(Some(value)),
+            None => bail!("invalid value for a `{kind}' input"),
+        }
+    }
+}
+
+enum Mode {
+    Record(LineWriter<File>),
+    Replay(Replay),
+}
+
+static MODE: Mutex<Option<Mode>> = Mutex::new(None);
+
+/// Start recording the inputs of this run to `file`.
+pub(crate) fn start_recording(file: &str) -> Result<()> {
+    let mut log = LineWriter::new(File::create(file)?);
+    log.write_all(json!(["rune-replay", VERSION]).to_string().as_bytes())?;
+    log.write_all(b"\n")?;
+    *MODE.lock().unwrap() = Some(Mode::Record(log));
+    Ok(())
+}
+
+/// Start replaying the inputs recorded in `file`.
+pub(crate) fn start_replaying(file: &str) -> Result<()> {
+    let contents = std::fs::read_to_string(file).with_context(|| format!("Reading {file}"))?;
+    let replay = Replay::parse(&contents).with_context(|| format!("Reading {file}"))?;
+    *MODE.lock().unwrap() = Some(Mode::Replay(replay));
+    Ok(())
+}
+
+/// Return the result of `live`, which reads a `kind` input from outside the
+/// program. When recording it is logged, and when replaying the logged value
+/// is returned without calling `live`.
+pub(crate) fn input<T: Input>(kind: &str, live: impl FnOnce() -> T) -> T {
+    let mut mode = MODE.lock().unwrap();
+    match &mut *mode {
+        None => {
+            drop(mode);
+            live()
+        }
+        Some(Mode::Record(_)) => {
+            // don't hold the lock while waiting for input
+            drop(mode);
+            let value = live();
+            if let Some(Mode::Record(log)) = &mut *MODE.lock().unwrap() {
+                if let Err(e) = log.write_all(encode(kind, &value).as_bytes()) {
+                    tracing::warn!("Error recording input: {e}");
+                }
+            }
+            value
+        }
+        Some(Mode::Replay(replay)) => match replay.next(kind) {
+            Ok(Some(value)) => value,
+            Ok(None) => {
+                tracing::info!("Replay finished, continuing with live input");
+                *mode = None;
+                drop(mode);
+                live()
+            }
+            Err(e) => {
+                tracing::warn!("Replay diverged: {e}; continuing with live input");
+                *mode = None;
+                drop(mode);
+                live()
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The time on this thread while a test runs with [`with_test_clock`].
+    static TEST_CLOCK: std::cell::Cell<Option<Duration>> = const { std::cell::Cell::new(None) };
+}
+
+/// Run `f` with a clock that starts at a fixed time and only moves forward
+/// when this thread calls [`sleep`], so timing tests don't depend on how
+/// fast they run.
+#[cfg(test)]
+pub(crate) fn with_test_clock<T>(f: impl FnOnce() -> T) -> T {
+    TEST_CLOCK.set(Some(Duration::from_secs(1_700_000_000)));
+    let result = f();
+    TEST_CLOCK.set(None);
+    result
+}
+
+/// The current time as a duration since the unix epoch.
+pub(crate) fn now() -> Duration {
+    #[cfg(test)]
+    if let Some(now) = TEST_CLOCK.get() {
+        return now;
+    }
+    input("time", || {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("System time is before the epoch")
+    })
+}
+
+/// Wait for `duration`, as measured by [`now`].
+pub(crate) fn sleep(duration: Duration) {
+    #[cfg(test)]
+    if let Some(now) = TEST_CLOCK.get() {
+        TEST_CLOCK.set(Some(now + duration));
+        return;
+    }
+    std::thread::sleep(duration);
+}
+
+/// A random number from system entropy.
+pub(crate) fn random() -> u64 {
+    input("random", rand::random)
+}
+
+/// The value of the environment variable `name`, if it is set to valid
+/// unicode.
+pub(crate) fn env_var(name: &str) -> Option<String> {
+    input(&format!("env {name}"), || std::env::var(name).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let output: Result<Option<String>> = Ok(Some("out".into()));
+        let failed: Result<Option<String>> = Err(anyhow::anyhow!("broken pipe"));
+        let log = [
+            json!(["rune-replay", VERSION]).to_string() + "\n",
+            encode("time", &Duration::new(1_700_000_000, 5)),
+            encode("random", &u64::MAX),
+            encode("env HOME", &Some("/home/user".to_owned())),
+            encode("env UNSET", &None::<String>),
+            encode("process-output", &output),
+            encode("process-output", &failed),
+        ]
+        .concat();
+        let mut replay = Replay::parse(&log).unwrap();
+        assert_eq!(replay.next::<Duration>("time").unwrap(), Some(Duration::new(1_700_000_000, 5)));
+        assert_eq!(replay.next::<u64>("random").unwrap(), Some(u64::MAX));
+        assert_eq!(replay.next("env HOME").unwrap(), Some(Some("/home/user".to_owned())));
+        assert_eq!(replay.next::<Option<String>>("env UNSET").unwrap(), Some(None));
+        let output: Result<Option<String>> = replay.next("process-output").unwrap().unwrap();
+        assert_eq!(output.unwrap(), Some("out".to_owned()));
+        let failed: Result<Option<String>> = replay.next("process-output").unwrap().unwrap();
+        assert_eq!(failed.unwrap_err().to_string(), "broken pipe");
+        assert!(replay.next::<u64>("random").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_divergence() {
+        let log = json!(["rune-replay", VERSION]).to_string() + "\n" + &encode("random", &1_u64);
+        let mut replay = Replay::parse(&log).unwrap();
+        assert!(replay.next::<Duration>("time").is_err());
+        assert!(Replay::parse("[\"something-else\", 1]").is_err());
+        assert!(Replay::parse(&(json!(["rune-replay", VERSION]).to_string() + "\n[1]")).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Evaluating untrusted code with limits.
+//!
+//! `eval-sandboxed' evaluates a form with a budget of evaluation steps, a
+//! budget of allocated bytes, a wall clock timeout and a list of functions
+//! that may not be called. Steps are counted at the same safe points where
+//! quit is checked: function calls and loop iterations. When a limit is
+//! exceeded `sandbox-violation' is signaled with data (KIND LIMIT), where KIND
+//! is one of `steps', `allocation', `timeout' or `denied'. A violation is
+//! sticky: catching it inside the sandbox only lasts until the next safe
+//! point, and it is signaled again when the form returns.
+use crate::core::{
+    env::{intern, sym, ArgSlice, Env},
+    gc::{Context, Rt, Rto},
+    object::{Object, ObjectType, NIL},
+};
+use crate::eval::EvalError;
+use anyhow::{bail, Result};
+use rune_core::macros::{list, rebind};
+use rune_macros::defun;
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+/// The limits of one `eval-sandboxed' call. `None` means unlimited.
+#[derive(Debug, Default)]
+struct Limits {
+    steps: Option<u64>,
+    bytes: Option<usize>,
+    timeout: Option<Duration>,
+    denied: Vec<String>,
+}
+
+impl Limits {
+    fn new(args: &[Object], env: &Rt<Env>, cx: &Context) -> Result<Self> {
+        let mut limits = Self::default();
+        let mut denied = env.vars.get(sym::SANDBOX_DENIED_FUNCTIONS).map(|x| x.bind(cx));
+        for pair in args.chunks(2) {
+            let [key, value] = *pair else { bail!("Missing keyword value for {}", pair[0]) };
+            let natural = |what| match value.untag() {
+                ObjectType::NIL => Ok(None),
+                ObjectType::Int(x) if x >= 0 => Ok(Some(x as u64)),
+                _ => bail!("{what} must be a natural number or nil: {value}"),
+            };
+            match key.untag() {
+                ObjectType::Symbol(sym::KW_MAX_STEPS) => limits.steps = natural(":max-steps")?,
+                ObjectType::Symbol(sym::KW_MAX_BYTES) => {
+                    limits.bytes = natural(":max-bytes")?.map(|x| x as usize);
+                }
+                ObjectType::Symbol(sym::KW_TIMEOUT) => {
+                    limits.timeout = match value.untag() {
+                        ObjectType::NIL => None,
+                        _ => match Duration::try_from_secs_f64(value.try_into()?) {
+                            Ok(timeout) => Some(timeout),
+                            Err(_) => bail!(":timeout must be a positive number or nil: {value}"),
+                        },
+                    };
+                }
+                ObjectType::Symbol(sym::KW_DENY) => denied = Some(value),
+                _ => bail!("Invalid keyword: {key}"),
+            }
+        }
+        for function in denied.unwrap_or(NIL).as_list()? {
+            match function?.untag() {
+                ObjectType::Symbol(function) => limits.denied.push(function.name().to_owned()),
+                other => bail!("Denied function must be a symbol: {other}"),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Violation {
+    Steps(u64),
+    Allocation(usize),
+    Timeout(Duration),
+    Denied(&'static str),
+}
+
+impl Violation {
+    fn signal(self, env: &mut Rt<Env>, cx: &Context) -> EvalError {
+        let data = match self {
+            Self::Steps(limit) => list![sym::STEPS, limit as i64; cx],
+            Self::Allocation(limit) => list![sym::ALLOCATION, limit as i64; cx],
+            Self::Timeout(limit) => list![sym::TIMEOUT, limit.as_secs_f64(); cx],
+            Self::Denied(name) => list![sym::DENIED, intern(name, cx); cx],
+        };
+        EvalError::signal(sym::SANDBOX_VIOLATION.into(), data, env)
+    }
+}
+
+/// An active `eval-sandboxed' call.
+struct Sandbox {
+    limits: Limits,
+    steps: u64,
+    start_bytes: usize,
+    deadline: Option<Instant>,
+    violation: Option<Violation>,
+}
+
+impl Sandbox {
+    fn new(limits: Limits, cx: &Context) -> Self {
+        let deadline = limits.timeout.map(|x| Instant::now() + x);
+        let start_bytes = cx.total_allocated_bytes();
+        Self { limits, steps: 0, start_bytes, deadline, violation: None }
+    }
+
+    /// Count one step, returning the violation if any limit has been
+    /// exceeded.
+    fn step(&mut self, bytes: usize) -> Option<Violation> {
+        if self.violation.is_some() {
+            return self.violation;
+        }
+        self.steps += 1;
+        let limits = &self.limits;
+        self.violation = if limits.steps.is_some_and(|max| self.steps > max) {
+            limits.steps.map(Violation::Steps)
+        } else if limits.bytes.is_some_and(|max| bytes.saturating_sub(self.start_bytes) > max) {
+            limits.bytes.map(Violation::Allocation)
+        } else if self.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            limits.timeout.map(Violation::Timeout)
+        } else {
+            None
+        };
+        self.violation
+    }
+}
+
+thread_local! {
+    /// The active sandboxes, innermost last. Nested sandboxes are all
+    /// checked, so an inner call cannot loosen the limits of an outer one.
+    static SANDBOXES: RefCell<Vec<Sandbox>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Signal `sandbox-violation' if evaluation has exceeded the limits of any
+/// active sandbox. This is called at the same safe points as
+/// [`crate::keyboard::maybe_quit`].
+pub(crate) fn check_limits(env: &mut Rt<Env>, cx: &Context) -> Result<(), EvalError> {
+    let violation = SANDBOXES.with_borrow_mut(|sandboxes| {
+        if sandboxes.is_empty() {
+            return None;
+        }
+        let bytes = cx.total_allocated_bytes();
+        sandboxes.iter_mut().find_map(|x| x.step(bytes))
+    });
+    match violation {
+        Some(violation) => Err(violation.signal(env, cx)),
+        None => Ok(()),
+    }
+}
+
+/// Signal `sandbox-violation' if the subr `name` is denied by any active
+/// sandbox.
+pub(crate) fn check_subr(
+    name: &'static str,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<(), EvalError> {
+    let violation = SANDBOXES.with_borrow_mut(|sandboxes| {
+        let sandbox = sandboxes.iter_mut().find(|x| x.limits.denied.iter().any(|f| f == name))?;
+        Some(*sandbox.violation.get_or_insert(Violation::Denied(name)))
+    });
+    match violation {
+        Some(violation) => Err(violation.signal(env, cx)),
+        None => Ok(()),
+    }
+}
+
+/// Evaluate FORM with limits on what it can do. ARGS are keyword arguments:
+///
+/// :max-steps STEPS -- the number of function calls and loop iterations.
+/// :max-bytes BYTES -- the number of bytes that may be allocated.
+/// :timeout SECONDS -- the wall clock time FORM may run for.
+/// :deny FUNCTIONS -- a list of functions that may not be called. Defaults
+/// to `sandbox-denied-functions'.
+///
+/// Limits that are omitted or nil are unlimited. If a limit is exceeded
+/// `sandbox-violation' is signaled with data (KIND LIMIT), where KIND is
+/// `steps', `allocation', `timeout' or `denied'. For `denied' LIMIT is the
+/// function that was called.
+#[defun]
+fn eval_sandboxed<'ob>(
+    form: &Rto<Object>,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let limits = Limits::new(Rt::bind_slice(env.stack.arg_slice(args), cx), env, cx)?;
+    SANDBOXES.with_borrow_mut(|x| x.push(Sandbox::new(limits, cx)));
+    let result = match crate::interpreter::eval(form, None, env, cx) {
+        Ok(value) => Ok(rebind!(value, cx)),
+        Err(e) => Err(e),
+    };
+    let sandbox = SANDBOXES.with_borrow_mut(Vec::pop).expect("sandbox stack was empty");
+    if let Some(violation) = sandbox.violation {
+        return Err(violation.signal(env, cx).into());
+    }
+    result
+}
+
+defsym!(SANDBOX_VIOLATION);
+defsym!(STEPS);
+defsym!(ALLOCATION);
+defsym!(TIMEOUT);
+defsym!(DENIED);
+defsym!(KW_MAX_STEPS);
+defsym!(KW_MAX_BYTES);
+defsym!(KW_TIMEOUT);
+defsym!(KW_DENY);
+
+// The primitives that touch files, processes or the network, including
+// through streams. New ones have to be added here.
+defvar!(
+    SANDBOX_DENIED_FUNCTIONS,
+    list![
+        sym::WRITE_REGION,
+        sym::SAVE_BUFFER,
+        sym::DO_AUTO_SAVE,
+        sym::LOCK_FILE,
+        sym::UNLOCK_FILE,
+        sym::LOCK_BUFFER,
+        sym::UNLOCK_BUFFER,
+        sym::PERSIST_SAVE,
+        sym::SAVEHIST_SAVE,
+        sym::RECENTF_SAVE_LIST,
+        sym::INSERT_FILE_CONTENTS,
+        sym::FIND_FILE,
+        sym::FIND_FILE_NOSELECT,
+        sym::REVERT_BUFFER,
+        sym::RECOVER_FILE,
+        sym::PERSIST_LOAD,
+        sym::SAVEHIST_LOAD,
+        sym::RECENTF_LOAD_LIST,
+        sym::LOAD,
+        sym::RUNE_MODULE,
+        sym::STREAM_FILE_LINES,
+        sym::STREAM_DIRECTORY_FILES,
+        sym::STREAM_PROCESS_LINES,
+        sym::DUMP_EMACS_PORTABLE,
+        sym::DUMP_HEAP_SNAPSHOT,
+        sym::KILL_EMACS,
+        sym::MAKE_PROCESS,
+        sym::MAKE_PIPE_PROCESS,
+        sym::PROCESS_SEND_STRING,
+        sym::PROCESS_SEND_EOF,
+        sym::DELETE_PROCESS,
+        sym::COMPILATION_START,
+        sym::JSONRPC_CONNECT,
+        sym::JSONRPC_REQUEST,
+        sym::JSONRPC_NOTIFY,
+        sym::SERVER_START,
+        sym::REPL_SERVER_START,
+        sym::SQLITE_OPEN,
+        sym::SQLITE_EXECUTE,
+        sym::SQLITE_TRANSACTION,
+        sym::SQLITE_COMMIT,
+        sym::SQLITE_ROLLBACK,
+        sym::SQLITE_PRAGMA,
+    ]
+);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::{assert_lisp, assert_lisp_with_vars};
+
+    #[test]
+    fn test_limits() {
+        assert_lisp("(eval-sandboxed '(+ 1 2) :max-steps 10)", "3");
+        assert_lisp(
+            "(condition-case err (eval-sandboxed '(while t) :max-steps 100) (error err))",
+            "(sandbox-violation steps 100)",
+        );
+        // catching the violation inside the sandbox does not escape it
+        assert_lisp(
+            "(condition-case err (eval-sandboxed '(condition-case nil (while t) (error 1)) :max-steps 5) (error err))",
+            "(sandbox-violation steps 5)",
+        );
+        assert_lisp(
+            "(condition-case err (eval-sandboxed '(while t (make-vector 100 nil)) :max-bytes 10000) (error err))",
+            "(sandbox-violation allocation 10000)",
+        );
+        assert_lisp(
+            "(condition-case err (eval-sandboxed '(while t) :timeout 0.01) (error (car err)))",
+            "sandbox-violation",
+        );
+        assert_lisp("(eval-sandboxed '(funcall 'car '(1)))", "1");
+        assert_lisp(
+            "(condition-case err (eval-sandboxed '(funcall 'car '(1)) :deny '(car)) (error err))",
+            "(sandbox-violation denied car)",
+        );
+    }
+
+    #[test]
+    fn test_default_denied() {
+        assert_lisp_with_vars(
+            "(condition-case err (eval-sandboxed '(write-region \"x\" nil \"/tmp/rune-sandbox\")) (error err))",
+            "(sandbox-violation denied write-region)",
+        );
+        assert_lisp_with_vars(
+            "(condition-case err (eval-sandboxed '(stream-process-lines \"true\")) (error err))",
+            "(sandbox-violation denied stream-process-lines)",
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Saving variables between sessions, like savehist.
+//!
+//! The variables in `savehist-additional-variables' are written to
+//! `savehist-file' on exit and restored at startup. The file uses the same
+//! `(setq VAR 'VALUE)' format as Emacs, but it is restored by reading rather
+//! than evaluating it. Values with no readable representation are skipped.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, Symbol},
+};
+use crate::pdumper::readable;
+use crate::permissions::{check_file, Capability};
+use crate::reader;
+use anyhow::{bail, Result};
+use rune_macros::defun;
+use std::fmt::Write as _;
+
+const HEADER: &str = ";; -*- mode: emacs-lisp; coding: utf-8-unix -*-\n\
+                      ;; History file, automatically generated by `savehist'.\n\n";
+
+/// Return the contents of a history file holding the values of `variables`.
+pub(crate) fn history_contents(variables: Object, env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let mut out = HEADER.to_owned();
+    for var in variables.as_list()? {
+        let var: Symbol = var?.try_into()?;
+        let Some(value) = env.vars.get(var) else { continue };
+        if let (Some(name), Some(value)) = (readable(var.into()), readable(value.bind(cx))) {
+            writeln!(out, "(setq {name} '{value})")?;
+        }
+    }
+    Ok(out)
+}
+
+/// Set the variables saved in `contents` by [`history_contents`].
+pub(crate) fn restore_history(contents: &str, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let mut pos = 0;
+    loop {
+        let (form, new_pos) = match reader::read(&contents[pos..], cx) {
+            Ok(x) => x,
+            Err(reader::Error::EmptyStream) => return Ok(()),
+            Err(mut e) => {
+                e.update_pos(pos);
+                bail!(e);
+            }
+        };
+        pos += new_pos;
+        let elements = form.as_list()?.collect::<Result<Vec<_>, _>>()?;
+        let [setq, var, value] = elements[..] else { bail!("Invalid history entry: {form}") };
+        let ObjectType::Cons(quoted) = value.untag() else {
+            bail!("Invalid history entry: {form}")
+        };
+        if setq != sym::SETQ || quoted.car() != sym::QUOTE {
+            bail!("Invalid history entry: {form}");
+        }
+        let var: Symbol = var.try_into()?;
+        let Some(value) = quoted.cdr().as_list()?.next() else {
+            bail!("Invalid history entry: {form}")
+        };
+        env.set_var(var, value?, cx)?;
+    }
+}
+
+fn history_file(env: &Rt<Env>, cx: &Context) -> Option<String> {
+    match env.vars.get(sym::SAVEHIST_FILE)?.bind(cx).untag() {
+        ObjectType::String(file) => Some(file.to_string()),
+        _ => None,
+    }
+}
+
+/// Save the variables in `savehist-additional-variables' to
+/// `savehist-file'. Does nothing if `savehist-file' is nil.
+#[defun]
+pub(crate) fn savehist_save(env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let Some(file) = history_file(env, cx) else { return Ok(false) };
+    check_file(Capability::Write, &file, env, cx)?;
+    let variables = match env.vars.get(sym::SAVEHIST_ADDITIONAL_VARIABLES) {
+        Some(vars) => vars.bind(cx),
+        None => return Ok(false),
+    };
+    let contents = history_contents(variables, env, cx)?;
+    if let Some(dir) = std::path::Path::new(&file).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&file, contents)?;
+    Ok(true)
+}
+
+/// Restore the variables saved in `savehist-file'. Does nothing if the file
+/// does not exist.
+#[defun]
+pub(crate) fn savehist_load(env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let Some(file) = history_file(env, cx) else { return Ok(false) };
+    check_file(Capability::Read, &file, env, cx)?;
+    let contents = match std::fs::read_to_string(&file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => bail!("Failed to read {file}: {e}"),
+    };
+    restore_history(&contents, env, cx)?;
+    Ok(true)
+}
+
+defvar!(SAVEHIST_FILE);
+defvar!(SAVEHIST_ADDITIONAL_VARIABLES);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{env::intern, gc::RootSet};
+    use rune_core::macros::{list, root};
+
+    #[test]
+    fn test_round_trip() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        let history = intern("savehist-test-history", cx);
+        let buffer = intern("savehist-test-buffer", cx);
+        let unbound = intern("savehist-test-unbound", cx);
+        let value = list!["b\"c", 1, list![2.5; cx]; cx];
+        env.set_var(history, value, cx).unwrap();
+        let current = crate::buffer::get_buffer_create(cx.add("savehist-test"), None, cx).unwrap();
+        env.set_var(buffer, current, cx).unwrap();
+
+        let vars = list![history, buffer, unbound; cx];
+        let contents = history_contents(vars, env, cx).unwrap();
+        assert!(contents.ends_with("(setq savehist-test-history '(\"b\\\"c\" 1 (2.5)))\n"));
+        assert!(!contents.contains("savehist-test-buffer"));
+
+        env.set_var(history, cx.add(0), cx).unwrap();
+        restore_history(&contents, env, cx).unwrap();
+        let restored = env.vars.get(history).unwrap().bind(cx);
+        assert_eq!(restored.to_string(), value.to_string());
+
+        assert!(
+            restore_history("(setq savehist-test-history (delete-file \"x\"))", env, cx).is_err()
+        );
+    }
+}
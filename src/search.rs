@@ -77,7 +77,7 @@ fn replace_match(
 }
 
 #[defun]
-fn regexp_quote(string: &str) -> String {
+pub(crate) fn regexp_quote(string: &str) -> String {
     let mut quoted = String::new();
     for ch in string.chars() {
         if let '[' | '*' | '.' | '\\' | '?' | '+' | '^' | '$' = ch {
@@ -88,18 +88,18 @@ fn regexp_quote(string: &str) -> String {
     quoted
 }
 
-fn lisp_regex_to_rust(regexp: &str) -> String {
+pub(crate) fn lisp_regex_to_rust(regexp: &str) -> String {
     let mut norm_regex = String::new();
     let mut chars = regexp.char_indices();
     while let Some((idx, ch)) = chars.next() {
         match ch {
-            // Invert the escaping of parens. i.e. \( => ( and ( => \(
-            '(' | ')' | '{' | '}' => {
+            // Invert the escaping of parens and alternation. i.e. \( => ( and ( => \(
+            '(' | ')' | '{' | '}' | '|' => {
                 norm_regex.push('\\');
                 norm_regex.push(ch);
             }
             '\\' => match chars.next() {
-                Some((_, c @ '('..=')' | c @ '{' | c @ '}')) => norm_regex.push(c),
+                Some((_, c @ ('('..=')' | '{' | '}' | '|'))) => norm_regex.push(c),
                 Some((_, '`')) => norm_regex += "\\A",
                 Some((_, '\'')) => norm_regex += "\\z",
                 Some((_, c)) => {
@@ -185,6 +185,7 @@ mod test {
         assert_eq!(lisp_regex_to_rust("\\foo"), "\\foo");
         assert_eq!(lisp_regex_to_rust("\\(foo\\)"), "(foo)");
         assert_eq!(lisp_regex_to_rust("(foo)"), "\\(foo\\)");
+        assert_eq!(lisp_regex_to_rust("a\\|b|c"), "a|b\\|c");
         assert_eq!(lisp_regex_to_rust("\\`"), "\\A");
         assert_eq!(lisp_regex_to_rust("\\'"), "\\z");
         assert_eq!(lisp_regex_to_rust("[[:word:]]"), "[a-zA-Z]");
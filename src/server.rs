@@ -0,0 +1,345 @@
+//! A server for emacsclient.
+//!
+//! `server-start' listens on a unix domain socket using the emacsclient
+//! protocol. A connection sends a single line of space separated commands
+//! like `-eval EXPR' or `-file NAME', and receives lines such as
+//! `-print VALUE' or `-error MESSAGE' before the server closes it. Arguments
+//! are quoted so that they contain no spaces or newlines.
+//!
+//! Each connection is read on a thread of its own, so a slow client doesn't
+//! hold up the others, but lisp evaluation only happens on the main thread
+//! when it calls [`handle_requests`], either from the `--daemon` loop or from
+//! `server-handle-requests'.
+use crate::core::{
+    env::{intern, sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Object, ObjectType, OptionalFlag, Symbol},
+};
+use crate::permissions::{self, Capability};
+use anyhow::{bail, Result};
+use rune_core::macros::{list, root};
+use rune_macros::defun;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Quote `arg` so it can be sent as a single protocol argument.
+pub(crate) fn quote_arg(arg: &str) -> String {
+    let mut out = String::with_capacity(arg.len());
+    for (i, chr) in arg.chars().enumerate() {
+        match chr {
+            '&' => out.push_str("&&"),
+            '-' if i == 0 => out.push_str("&-"),
+            '\n' => out.push_str("&n"),
+            ' ' => out.push_str("&_"),
+            _ => out.push(chr),
+        }
+    }
+    out
+}
+
+/// Reverse [`quote_arg`].
+pub(crate) fn unquote_arg(arg: &str) -> String {
+    let mut out = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '&' {
+            out.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('_') => out.push(' '),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+struct Request {
+    commands: Vec<String>,
+    stream: UnixStream,
+}
+
+#[cfg(unix)]
+struct Server {
+    path: PathBuf,
+    /// Shared so that [`handle_requests`] can wait for a request without
+    /// holding the lock on [`SERVER`]
+    requests: Arc<Mutex<Receiver<Request>>>,
+    stop: Arc<AtomicBool>,
+}
+
+#[cfg(unix)]
+static SERVER: Mutex<Option<Server>> = Mutex::new(None);
+
+pub(crate) fn default_socket_dir() -> String {
+    match crate::replay::env_var("XDG_RUNTIME_DIR") {
+        Some(dir) if Path::new(&dir).is_absolute() => format!("{dir}/emacs"),
+        #[cfg(unix)]
+        _ => format!("/tmp/emacs{}", unsafe { libc::getuid() }),
+        #[cfg(not(unix))]
+        _ => std::env::temp_dir().join("emacs").to_string_lossy().into_owned(),
+    }
+}
+
+pub(crate) fn string_var(var: Symbol, env: &Rt<Env>, cx: &Context) -> Result<String> {
+    match env.vars.get(var).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::String(value)) => Ok(value.to_string()),
+        _ => bail!("`{var}' should be a string"),
+    }
+}
+
+/// Start a server listening on the socket `server-name' in
+/// `server-socket-dir'. Any running server is stopped first. If LEAVE-DEAD
+/// is non-nil, only stop the server.
+#[defun]
+fn server_start(
+    leave_dead: OptionalFlag,
+    _inhibit_prompt: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    #[cfg(unix)]
+    {
+        stop_server();
+        if leave_dead.is_some() {
+            return Ok(false);
+        }
+        permissions::check(Capability::Network, env, cx)?;
+        let dir = PathBuf::from(string_var(sym::SERVER_SOCKET_DIR, env, cx)?);
+        let path = dir.join(string_var(sym::SERVER_NAME, env, cx)?);
+        start_server(&dir, path)?;
+        Ok(true)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (leave_dead, env, cx);
+        bail!("The server requires unix domain sockets")
+    }
+}
+
+/// Return non-nil if the server is running.
+#[defun]
+fn server_running_p() -> bool {
+    #[cfg(unix)]
+    let running = SERVER.lock().unwrap().is_some();
+    #[cfg(not(unix))]
+    let running = false;
+    running
+}
+
+#[cfg(unix)]
+fn start_server(dir: &Path, path: PathBuf) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    // A socket left behind by a server that was not shut down cleanly
+    if path.exists() && UnixStream::connect(&path).is_err() {
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    let (sender, requests) = channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::Acquire) {
+                return;
+            }
+            let Ok(stream) = stream else { continue };
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                if let Some(commands) = read_commands(&stream) {
+                    _ = sender.send(Request { commands, stream });
+                }
+            });
+        }
+    });
+    let requests = Arc::new(Mutex::new(requests));
+    *SERVER.lock().unwrap() = Some(Server { path, requests, stop });
+    Ok(())
+}
+
+#[cfg(unix)]
+fn stop_server() {
+    if let Some(server) = SERVER.lock().unwrap().take() {
+        server.stop.store(true, Ordering::Release);
+        // wake up the listener so it sees that it was stopped
+        _ = UnixStream::connect(&server.path);
+        _ = std::fs::remove_file(&server.path);
+    }
+}
+
+/// Read the line of commands sent by a client.
+#[cfg(unix)]
+fn read_commands(stream: &UnixStream) -> Option<Vec<String>> {
+    stream.set_read_timeout(Some(Duration::from_secs(10))).ok()?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).ok()?;
+    Some(line.split_ascii_whitespace().map(String::from).collect())
+}
+
+/// Evaluate `form` and return the printed result.
+fn eval_form(form: &Rto<Object>, env: &mut Rt<Env>, cx: &mut Context) -> Result<String> {
+    crate::panics::catch_panic(env, cx, |env, cx| {
+        Ok(crate::interpreter::eval(form, None, env, cx)?.to_string())
+    })
+}
+
+/// Run the commands of a request and return the response.
+fn serve(commands: &[String], env: &mut Rt<Env>, cx: &mut Context) -> String {
+    let mut out = format!("-emacs-pid {}\n", std::process::id());
+    let mut dir = None;
+    let mut commands = commands.iter();
+    while let Some(command) = commands.next() {
+        let mut arg = || commands.next().map(|x| unquote_arg(x)).unwrap_or_default();
+        let result = match command.as_str() {
+            "-eval" => {
+                let expr = arg();
+                match crate::reader::read(&expr, cx) {
+                    Ok((form, _)) => {
+                        root!(form, cx);
+                        eval_form(form, env, cx).map(Some)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+            "-file" => {
+                let file = arg();
+                let file = match &dir {
+                    Some(dir) => Path::new(dir).join(file).to_string_lossy().into_owned(),
+                    None => file,
+                };
+                let form = list![intern("find-file-noselect", cx), cx.add(file); cx];
+                root!(form, cx);
+                eval_form(form, env, cx).map(|_| None)
+            }
+            "-dir" => {
+                dir = Some(arg());
+                Ok(None)
+            }
+            // commands with an argument that only matter with a display
+            "-version" | "-env" | "-position" | "-display" | "-frame-parameters" | "-auth" => {
+                arg();
+                Ok(None)
+            }
+            "-tty" => {
+                arg();
+                arg();
+                Ok(None)
+            }
+            _ => Ok(None),
+        };
+        match result {
+            Ok(Some(value)) => out.push_str(&format!("-print {}\n", quote_arg(&value))),
+            Ok(None) => {}
+            Err(e) => out.push_str(&format!("-error {}\n", quote_arg(&e.to_string()))),
+        }
+    }
+    out
+}
+
+/// Handle client requests. If `timeout` is `None`, wait for requests forever.
+/// Otherwise wait up to `timeout` for a request, then handle any others that
+/// are already pending. Returns the number of requests handled.
+pub(crate) fn handle_requests(
+    timeout: Option<Duration>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    #[cfg(unix)]
+    {
+        let requests = match SERVER.lock().unwrap().as_ref() {
+            Some(server) => server.requests.clone(),
+            None => bail!("The server is not running"),
+        };
+        let mut handled = 0;
+        let mut timeout = timeout;
+        loop {
+            let request = {
+                let requests = requests.lock().unwrap();
+                match timeout {
+                    Some(timeout) => requests.recv_timeout(timeout),
+                    None => requests.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                }
+            };
+            let Ok(mut request) = request else { return Ok(handled) };
+            let response = serve(&request.commands, env, cx);
+            _ = request.stream.write_all(response.as_bytes());
+            handled += 1;
+            if timeout.is_some() {
+                timeout = Some(Duration::ZERO);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (timeout, env, cx);
+        bail!("The server requires unix domain sockets")
+    }
+}
+
+/// Handle the requests sent to the server, waiting up to TIMEOUT seconds for
+/// one to arrive. Return the number of requests handled.
+#[defun]
+fn server_handle_requests(
+    timeout: Option<f64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<usize> {
+    let timeout = Duration::from_secs_f64(timeout.unwrap_or(0.0).max(0.0));
+    handle_requests(Some(timeout), env, cx)
+}
+
+defvar!(SERVER_NAME, "server");
+defvar!(SERVER_SOCKET_DIR, crate::server::default_socket_dir());
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quote_arg() {
+        let arg = "-a b&c\nd";
+        assert_eq!(quote_arg(arg), "&-a&_b&&c&nd");
+        assert_eq!(unquote_arg(&quote_arg(arg)), arg);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_server() {
+        use crate::interpreter::with_env;
+        use std::io::Read;
+
+        with_env(|env, cx| {
+            let dir = std::env::temp_dir().join(format!("rune-server-{}", std::process::id()));
+            let path = dir.join("server");
+            start_server(&dir, path.clone()).unwrap();
+
+            let client = std::thread::spawn(move || {
+                let mut stream = UnixStream::connect(path).unwrap();
+                stream.write_all(b"-dir /tmp/ -eval (+&_1&_2) -eval (car&_1)\n").unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            });
+            let handled = handle_requests(Some(Duration::from_secs(5)), env, cx).unwrap();
+            assert_eq!(handled, 1);
+            let response = client.join().unwrap();
+            let lines: Vec<_> = response.lines().collect();
+            assert!(lines[0].starts_with("-emacs-pid "));
+            assert_eq!(lines[1], "-print 3");
+            assert!(lines[2].starts_with("-error "));
+            stop_server();
+            std::fs::remove_dir_all(dir).unwrap();
+        });
+    }
+}
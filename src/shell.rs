@@ -0,0 +1,227 @@
+//! Quoting and splitting shell commands.
+//!
+//! `shell-quote-argument' uses the rules of the platform's shell, which is
+//! cmd.exe on Windows, so commands built by joining quoted arguments can't be
+//! broken by arguments containing spaces or metacharacters.
+use crate::core::{
+    gc::Context,
+    object::{List, Object},
+};
+use crate::fns::slice_into_list;
+use anyhow::{bail, Result};
+use rune_macros::defun;
+
+/// Quote `arg` for a POSIX shell. Every character that could be special is
+/// escaped with a backslash, except newlines which can only be quoted.
+fn quote_posix(arg: &str) -> String {
+    if arg.is_empty() {
+        return "''".to_owned();
+    }
+    let mut out = String::with_capacity(arg.len());
+    for chr in arg.chars() {
+        match chr {
+            '\n' => out.push_str("'\n'"),
+            '-' | '_' | '.' | '/' => out.push(chr),
+            _ if chr.is_ascii_alphanumeric() => out.push(chr),
+            _ => {
+                out.push('\\');
+                out.push(chr);
+            }
+        }
+    }
+    out
+}
+
+/// Quote `arg` for cmd.exe. The argument is first quoted so that
+/// `CommandLineToArgvW` reads it back unchanged, and then the characters that
+/// cmd.exe would expand even inside quotes are escaped with carets.
+fn quote_windows(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    let mut backslashes = 0;
+    for chr in arg.chars() {
+        match chr {
+            '\\' => backslashes += 1,
+            '"' => {
+                // backslashes are only special before a quote
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if chr != '\\' {
+            quoted.push(chr);
+        }
+    }
+    // double the trailing backslashes so they don't escape the closing quote
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    if !quoted.contains(['%', '!', '"']) {
+        return format!("\"{quoted}\"");
+    }
+    let mut out = String::from("^\"");
+    for chr in quoted.chars() {
+        if matches!(chr, '%' | '!' | '(' | ')' | '"' | '<' | '>' | '&' | '|' | '^') {
+            out.push('^');
+        }
+        out.push(chr);
+    }
+    out.push_str("^\"");
+    out
+}
+
+/// Quote ARGUMENT so that the shell passes it to a command unchanged. The
+/// quoting of the platform's shell is used unless POSIX is non-nil, in which
+/// case it is always quoted for a POSIX shell.
+#[defun]
+fn shell_quote_argument(argument: &str, posix: Option<Object>) -> String {
+    if cfg!(windows) && posix.is_none_or(|x| x.is_nil()) {
+        quote_windows(argument)
+    } else {
+        quote_posix(argument)
+    }
+}
+
+/// Split `command` into arguments the same way as a POSIX shell, without
+/// performing any expansions.
+fn split_shell_command(command: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut arg = None::<String>;
+    let mut chars = command.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            ' ' | '\t' | '\n' => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(chr) => arg.push(chr),
+                        None => bail!("Unterminated single quote in {command}"),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // inside double quotes a backslash only escapes the
+                        // characters that would otherwise be special
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(chr @ ('"' | '\\' | '$' | '`')) => arg.push(chr),
+                            Some(chr) => {
+                                arg.push('\\');
+                                arg.push(chr);
+                            }
+                            None => bail!("Unterminated double quote in {command}"),
+                        },
+                        Some(chr) => arg.push(chr),
+                        None => bail!("Unterminated double quote in {command}"),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // a backslash before a newline continues the line
+                Some('\n') => {}
+                Some(chr) => arg.get_or_insert_with(String::new).push(chr),
+                None => arg.get_or_insert_with(String::new).push('\\'),
+            },
+            _ => arg.get_or_insert_with(String::new).push(chr),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Split COMMAND into a list of arguments, following the quoting rules of a
+/// POSIX shell.
+#[defun]
+fn split_string_shell_command<'ob>(command: &str, cx: &'ob Context) -> Result<Object<'ob>> {
+    let args: Vec<_> = split_shell_command(command)?.into_iter().map(|x| cx.add(x)).collect();
+    Ok(slice_into_list(&args, None, cx))
+}
+
+/// Concatenate STRINGS with SEPARATOR, which defaults to a space. A string
+/// that contains the separator, a double quote or a backslash is put in
+/// double quotes, with backslashes before any double quotes or backslashes
+/// in it, so that `split-string-and-unquote' can split the result again.
+#[defun]
+fn combine_and_quote_strings(strings: List, separator: Option<&str>) -> Result<String> {
+    let separator = separator.unwrap_or(" ");
+    let mut out = String::new();
+    for (i, string) in strings.elements().enumerate() {
+        let string: &str = string?.try_into()?;
+        if i > 0 {
+            out.push_str(separator);
+        }
+        let needs_quotes =
+            string.contains(['"', '\\']) || (!separator.is_empty() && string.contains(separator));
+        if needs_quotes {
+            out.push('"');
+            for chr in string.chars() {
+                if matches!(chr, '"' | '\\') {
+                    out.push('\\');
+                }
+                out.push(chr);
+            }
+            out.push('"');
+        } else {
+            out.push_str(string);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_quote_posix() {
+        assert_eq!(quote_posix(""), "''");
+        assert_eq!(quote_posix("a-b_c./d"), "a-b_c./d");
+        assert_eq!(quote_posix("a b"), "a\\ b");
+        assert_eq!(quote_posix("$(rm -rf ~)"), "\\$\\(rm\\ -rf\\ \\~\\)");
+        assert_eq!(quote_posix("it's \"x\""), "it\\'s\\ \\\"x\\\"");
+        assert_eq!(quote_posix("a\nb"), "a'\n'b");
+        assert_eq!(quote_posix("é"), "\\é");
+    }
+
+    #[test]
+    fn test_quote_windows() {
+        assert_eq!(quote_windows(""), "\"\"");
+        assert_eq!(quote_windows("a b"), "\"a b\"");
+        assert_eq!(quote_windows("C:\\dir\\"), "\"C:\\dir\\\\\"");
+        assert_eq!(quote_windows("a\\\"b"), "^\"a\\\\\\^\"b^\"");
+        assert_eq!(quote_windows("100% & more"), "^\"100^% ^& more^\"");
+    }
+
+    #[test]
+    fn test_split_shell_command() {
+        let split = |x| split_shell_command(x).unwrap();
+        assert_eq!(split("  ls  -l\t/tmp "), ["ls", "-l", "/tmp"]);
+        assert_eq!(split("echo 'a  b' \"c \\\"d\\\" \\x\""), ["echo", "a  b", "c \"d\" \\x"]);
+        assert_eq!(split("a\\ b ''  \"\"x"), ["a b", "", "x"]);
+        assert_eq!(split("'it'\\''s fine'"), ["it's fine"]);
+        assert!(split_shell_command("echo 'oops").is_err());
+        assert!(split_shell_command("echo \"oops").is_err());
+        // quoting and splitting are inverses
+        let args = ["$HOME", "a b", "it's", "\"\\", "\n", ""];
+        let command: Vec<_> = args.iter().map(|x| quote_posix(x)).collect();
+        assert_eq!(split(&command.join(" ")), args);
+    }
+
+    #[test]
+    fn test_combine_and_quote_strings() {
+        assert_lisp(
+            "(combine-and-quote-strings '(\"a\" \"b c\" \"d\\\"e\"))",
+            "\"a \\\"b c\\\" \\\"d\\\\\\\"e\\\"\"",
+        );
+        assert_lisp("(combine-and-quote-strings '(\"a,b\" \"c\") \",\")", "\"\\\"a,b\\\",c\"");
+        assert_lisp("(split-string-shell-command \"ls 'a b'\")", "(\"ls\" \"a b\")");
+    }
+}
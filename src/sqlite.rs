@@ -0,0 +1,372 @@
+//! SQLite databases.
+//!
+//! A database is a `sqlite' object that refers to a connection held in a
+//! registry. The connection is closed by `sqlite-close', or when the object
+//! is garbage collected. Values are bound to the `?' placeholders of a
+//! statement in order: integers and floats are bound as numbers, strings as
+//! text, unibyte strings as blobs, nil as NULL, and t and :false as 1 and 0.
+//!
+//! These functions are only available when rune is built with the `sqlite`
+//! feature; otherwise `sqlite-open' signals an error. Statement objects
+//! (`sqlite-select' with a RETURN-TYPE of `set') and extensions are not
+//! supported.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{LispSqlite, Object, ObjectType, SqliteHandle, Symbol, NIL},
+};
+use crate::fns::slice_into_list;
+use crate::permissions::{check_file, Capability};
+use anyhow::{bail, Result};
+use rune_macros::defun;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// A value bound to a statement or read from a row.
+#[derive(Debug, Clone, PartialEq)]
+enum SqlValue {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+/// The column names and rows returned by a query.
+type Rows = (Vec<String>, Vec<Vec<SqlValue>>);
+
+#[cfg(feature = "sqlite")]
+mod backend {
+    use super::{Rows, SqlValue};
+    use anyhow::Result;
+    use rusqlite::params_from_iter;
+    use rusqlite::types::{Value, ValueRef};
+
+    pub(super) struct Connection(rusqlite::Connection);
+
+    fn to_value(value: &SqlValue) -> Value {
+        match value {
+            SqlValue::Null => Value::Null,
+            SqlValue::Int(x) => Value::Integer(*x),
+            SqlValue::Real(x) => Value::Real(*x),
+            SqlValue::Text(x) => Value::Text(x.clone()),
+            SqlValue::Blob(x) => Value::Blob(x.clone()),
+        }
+    }
+
+    fn from_value(value: ValueRef<'_>) -> SqlValue {
+        match value {
+            ValueRef::Null => SqlValue::Null,
+            ValueRef::Integer(x) => SqlValue::Int(x),
+            ValueRef::Real(x) => SqlValue::Real(x),
+            ValueRef::Text(x) => SqlValue::Text(String::from_utf8_lossy(x).into_owned()),
+            ValueRef::Blob(x) => SqlValue::Blob(x.to_vec()),
+        }
+    }
+
+    impl Connection {
+        pub(super) fn open(file: Option<&str>) -> Result<Self> {
+            let connection = match file {
+                Some(file) => rusqlite::Connection::open(file)?,
+                None => rusqlite::Connection::open_in_memory()?,
+            };
+            Ok(Self(connection))
+        }
+
+        pub(super) fn execute(&mut self, sql: &str, values: &[SqlValue]) -> Result<usize> {
+            let params = params_from_iter(values.iter().map(to_value));
+            Ok(self.0.execute(sql, params)?)
+        }
+
+        pub(super) fn select(&mut self, sql: &str, values: &[SqlValue]) -> Result<Rows> {
+            let mut statement = self.0.prepare(sql)?;
+            let names: Vec<String> =
+                statement.column_names().into_iter().map(String::from).collect();
+            let mut rows = statement.query(params_from_iter(values.iter().map(to_value)))?;
+            let mut result = Vec::new();
+            while let Some(row) = rows.next()? {
+                let row = (0..names.len())
+                    .map(|i| Ok(from_value(row.get_ref(i)?)))
+                    .collect::<Result<_>>()?;
+                result.push(row);
+            }
+            Ok((names, result))
+        }
+
+        pub(super) fn batch(&mut self, sql: &str) -> Result<()> {
+            Ok(self.0.execute_batch(sql)?)
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+mod backend {
+    use super::{Rows, SqlValue};
+    use anyhow::{bail, Result};
+    use std::convert::Infallible;
+
+    /// A connection can never be opened without SQLite.
+    pub(super) struct Connection(Infallible);
+
+    impl Connection {
+        pub(super) fn open(_file: Option<&str>) -> Result<Self> {
+            bail!("SQLite support is not available; rebuild with the `sqlite` feature")
+        }
+
+        pub(super) fn execute(&mut self, _sql: &str, _values: &[SqlValue]) -> Result<usize> {
+            match self.0 {}
+        }
+
+        pub(super) fn select(&mut self, _sql: &str, _values: &[SqlValue]) -> Result<Rows> {
+            match self.0 {}
+        }
+
+        pub(super) fn batch(&mut self, _sql: &str) -> Result<()> {
+            match self.0 {}
+        }
+    }
+}
+
+struct Database {
+    /// None once the database has been closed.
+    connection: Option<backend::Connection>,
+    /// The number of live objects that refer to this database.
+    refs: usize,
+}
+
+static DATABASES: LazyLock<Mutex<HashMap<i64, Database>>> = LazyLock::new(Default::default);
+static NEXT_DATABASE: AtomicI64 = AtomicI64::new(0);
+
+/// Record that another object refers to database `id`.
+pub(crate) fn retain(id: i64) {
+    if let Some(db) = DATABASES.lock().unwrap().get_mut(&id) {
+        db.refs += 1;
+    }
+}
+
+/// Called when an object referring to database `id` is garbage collected.
+/// The connection is closed once no objects refer to it.
+pub(crate) fn finalize(id: i64) {
+    let mut databases = DATABASES.lock().unwrap();
+    if let Some(db) = databases.get_mut(&id) {
+        db.refs -= 1;
+        if db.refs == 0 {
+            databases.remove(&id);
+        }
+    }
+}
+
+fn with_connection<T>(
+    db: &LispSqlite,
+    f: impl FnOnce(&mut backend::Connection) -> Result<T>,
+) -> Result<T> {
+    let mut databases = DATABASES.lock().unwrap();
+    match databases.get_mut(&db.id()).and_then(|x| x.connection.as_mut()) {
+        Some(connection) => f(connection),
+        None => bail!("Database is closed: {db}"),
+    }
+}
+
+fn to_sql(value: Object) -> Result<SqlValue> {
+    Ok(match value.untag() {
+        ObjectType::NIL => SqlValue::Null,
+        ObjectType::TRUE => SqlValue::Int(1),
+        ObjectType::Symbol(sym::KW_FALSE) => SqlValue::Int(0),
+        ObjectType::Int(x) => SqlValue::Int(x),
+        ObjectType::Float(x) => SqlValue::Real(**x),
+        ObjectType::String(x) => SqlValue::Text(x.to_string()),
+        ObjectType::ByteString(x) => SqlValue::Blob(x.to_vec()),
+        _ => bail!("Invalid SQLite value: {value}"),
+    })
+}
+
+/// Convert VALUES, a list or vector, into values to bind.
+fn to_sql_values(values: Option<Object>) -> Result<Vec<SqlValue>> {
+    let Some(values) = values else { return Ok(Vec::new()) };
+    match values.untag() {
+        ObjectType::NIL => Ok(Vec::new()),
+        ObjectType::Cons(cons) => cons.elements().map(|x| to_sql(x?)).collect(),
+        ObjectType::Vec(vec) => vec.iter().map(|x| to_sql(x.get())).collect(),
+        _ => bail!("VALUES must be a list or a vector: {values}"),
+    }
+}
+
+fn from_sql(value: SqlValue, cx: &Context) -> Object {
+    match value {
+        SqlValue::Null => NIL,
+        SqlValue::Int(x) => cx.add(x),
+        SqlValue::Real(x) => cx.add(x),
+        SqlValue::Text(x) => cx.add(x),
+        SqlValue::Blob(x) => cx.add(x),
+    }
+}
+
+/// Return non-nil if SQLite support is built in.
+#[defun]
+fn sqlite_available_p() -> bool {
+    cfg!(feature = "sqlite")
+}
+
+/// Return non-nil if OBJECT is an SQLite database.
+#[defun]
+fn sqlitep(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::Sqlite(_))
+}
+
+/// Open the SQLite database in FILE, creating it if it does not exist, and
+/// return a database object. If FILE is nil, open a database in memory.
+#[defun]
+fn sqlite_open<'ob>(
+    file: Option<&str>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    if let Some(file) = file {
+        check_file(Capability::Write, file, env, cx)?;
+    }
+    let connection = backend::Connection::open(file)?;
+    let id = NEXT_DATABASE.fetch_add(1, Ordering::Relaxed);
+    let db = Database { connection: Some(connection), refs: 1 };
+    DATABASES.lock().unwrap().insert(id, db);
+    Ok(cx.add(SqliteHandle(id)))
+}
+
+/// Close the database DB. It is an error to use DB afterwards.
+#[defun]
+fn sqlite_close(db: &LispSqlite) -> Result<bool> {
+    let mut databases = DATABASES.lock().unwrap();
+    match databases.get_mut(&db.id()).and_then(|x| x.connection.take()) {
+        Some(_) => Ok(true),
+        None => bail!("Database is closed: {db}"),
+    }
+}
+
+/// Execute the SQL STATEMENT in DB and return the number of rows changed.
+/// VALUES is a list or vector of values for the `?' placeholders in
+/// STATEMENT.
+#[defun]
+fn sqlite_execute(db: &LispSqlite, statement: &str, values: Option<Object>) -> Result<usize> {
+    let values = to_sql_values(values)?;
+    with_connection(db, |x| x.execute(statement, &values))
+}
+
+/// Run the SQL QUERY in DB and return the resulting rows as a list of lists.
+/// VALUES is a list or vector of values for the `?' placeholders in QUERY.
+/// If RETURN-TYPE is `full', the first element of the result is the list of
+/// column names.
+#[defun]
+fn sqlite_select<'ob>(
+    db: &LispSqlite,
+    query: &str,
+    values: Option<Object>,
+    return_type: Option<Symbol>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let full = match return_type {
+        None => false,
+        Some(sym::FULL) => true,
+        Some(x) => bail!("Unsupported return type: {x}"),
+    };
+    let values = to_sql_values(values)?;
+    let (names, rows) = with_connection(db, |x| x.select(query, &values))?;
+    let mut result = Vec::with_capacity(rows.len() + 1);
+    if full {
+        let names: Vec<Object> = names.into_iter().map(|x| cx.add(x)).collect();
+        result.push(slice_into_list(&names, None, cx));
+    }
+    for row in rows {
+        let row: Vec<Object> = row.into_iter().map(|x| from_sql(x, cx)).collect();
+        result.push(slice_into_list(&row, None, cx));
+    }
+    Ok(slice_into_list(&result, None, cx))
+}
+
+/// Start a transaction in DB.
+#[defun]
+fn sqlite_transaction(db: &LispSqlite) -> Result<bool> {
+    with_connection(db, |x| x.batch("BEGIN"))?;
+    Ok(true)
+}
+
+/// Commit the current transaction in DB.
+#[defun]
+fn sqlite_commit(db: &LispSqlite) -> Result<bool> {
+    with_connection(db, |x| x.batch("COMMIT"))?;
+    Ok(true)
+}
+
+/// Roll back the current transaction in DB.
+#[defun]
+fn sqlite_rollback(db: &LispSqlite) -> Result<bool> {
+    with_connection(db, |x| x.batch("ROLLBACK"))?;
+    Ok(true)
+}
+
+/// Execute PRAGMA in DB, for instance "journal_mode = WAL".
+#[defun]
+fn sqlite_pragma(db: &LispSqlite, pragma: &str) -> Result<bool> {
+    with_connection(db, |x| x.batch(&format!("PRAGMA {pragma}")))?;
+    Ok(true)
+}
+
+defsym!(SQLITE);
+defsym!(FULL);
+
+#[cfg(all(test, feature = "sqlite"))]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::interpreter::assert_lisp;
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_execute_and_select() {
+        assert_lisp(
+            "(let ((db (sqlite-open)))
+               (sqlite-execute db \"create table t (a integer, b text, c real)\")
+               (list (sqlitep db)
+                     (sqlite-execute db \"insert into t values (?, ?, ?)\" '(1 \"one\" 1.5))
+                     (sqlite-execute db \"insert into t values (?, ?, ?)\" [2 nil t])
+                     (sqlite-select db \"select * from t order by a\")
+                     (sqlite-select db \"select b from t where a = ?\" '(1) 'full)))",
+            "(t 1 1 ((1 \"one\" 1.5) (2 nil 1.0)) ((\"b\") (\"one\")))",
+        );
+    }
+
+    #[test]
+    fn test_transactions() {
+        assert_lisp(
+            "(let ((db (sqlite-open)))
+               (sqlite-execute db \"create table t (a)\")
+               (sqlite-transaction db)
+               (sqlite-execute db \"insert into t values (1)\")
+               (sqlite-rollback db)
+               (sqlite-transaction db)
+               (sqlite-execute db \"insert into t values (2)\")
+               (sqlite-commit db)
+               (sqlite-select db \"select a from t\"))",
+            "((2))",
+        );
+        assert_lisp(
+            "(let ((db (sqlite-open)))
+               (sqlite-close db)
+               (condition-case nil (sqlite-select db \"select 1\") (error 'closed)))",
+            "closed",
+        );
+    }
+
+    #[test]
+    fn test_finalize() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let ObjectType::Sqlite(db) = sqlite_open(None, env, cx).unwrap().untag() else {
+            unreachable!()
+        };
+        let id = db.id();
+        assert!(DATABASES.lock().unwrap().contains_key(&id));
+        cx.garbage_collect(true);
+        assert!(!DATABASES.lock().unwrap().contains_key(&id));
+    }
+}
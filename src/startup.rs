@@ -0,0 +1,111 @@
+//! Loading the user's init files at startup.
+//!
+//! `early-init.el' and then `init.el' are loaded from `user-emacs-directory'.
+//! An error in either file is reported, but startup carries on with the rest
+//! of the sequence, the same as Emacs.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Gc, LispString, Symbol, NIL},
+};
+use crate::eval::EvalError;
+use rune_core::macros::root;
+use std::path::Path;
+
+/// Load `name` from `user-emacs-directory' if it exists, and return the full
+/// name of the file.
+fn load_user_file(name: &str, env: &mut Rt<Env>, cx: &mut Context) -> Option<String> {
+    let file = match crate::xdg::locate_user_emacs_file(name, None, env, cx) {
+        Ok(file) if Path::new(&file).is_file() => file,
+        Ok(_) => return None,
+        Err(e) => {
+            eprintln!("Error finding {name}: {e}");
+            return None;
+        }
+    };
+    let lisp_file: Gc<&LispString> = cx.add_as(file.as_str());
+    root!(lisp_file, cx);
+    if let Err(e) = crate::lread::load(lisp_file, None, None, cx, env) {
+        eprintln!("Error in init file {file}: {e}");
+        if let Ok(e) = e.downcast::<EvalError>() {
+            e.print_backtrace();
+        }
+    }
+    Some(file)
+}
+
+fn set_file_var(var: Symbol, file: Option<String>, env: &mut Rt<Env>, cx: &Context) {
+    let value = match file {
+        Some(file) => cx.add(file),
+        None => NIL,
+    };
+    // these are plain variables, so setting them can't fail
+    _ = env.set_var(var, value, cx);
+}
+
+/// Run the init sequence. If `init_directory` is given it replaces
+/// `user-emacs-directory'. When `no_init_file` is set, no files are loaded
+/// but the hooks are still run.
+pub(crate) fn startup(
+    init_directory: Option<&str>,
+    no_init_file: bool,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) {
+    if let Some(dir) = init_directory {
+        let mut dir = dir.to_owned();
+        if !dir.ends_with(std::path::MAIN_SEPARATOR) {
+            dir.push(std::path::MAIN_SEPARATOR);
+        }
+        set_file_var(sym::USER_EMACS_DIRECTORY, Some(dir), env, cx);
+    }
+    if !no_init_file {
+        let early = load_user_file("early-init.el", env, cx);
+        set_file_var(sym::EARLY_INIT_FILE, early, env, cx);
+        let init = load_user_file("init.el", env, cx);
+        set_file_var(sym::USER_INIT_FILE, init, env, cx);
+    }
+    let time = crate::timefns::current_time(cx, env);
+    _ = env.set_var(sym::AFTER_INIT_TIME, time, cx);
+    let hook = sym::AFTER_INIT_HOOK;
+    root!(hook, cx);
+    if let Err(e) = crate::eval::run_hook(hook, env, cx) {
+        eprintln!("Error running after-init-hook: {e}");
+    }
+}
+
+defvar!(EARLY_INIT_FILE);
+defvar!(USER_INIT_FILE);
+defvar!(AFTER_INIT_HOOK);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::with_env;
+
+    #[test]
+    fn test_startup() {
+        with_env(|env, cx| {
+            let dir = std::env::temp_dir().join(format!("rune-startup-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("early-init.el"), "(defvar startup-test-order '(early))")
+                .unwrap();
+            // the error is reported and the rest of the sequence still runs
+            std::fs::write(
+                dir.join("init.el"),
+                "(setq startup-test-order (cons 'init startup-test-order))
+             (setq after-init-hook
+                   (list #'(lambda () (setq startup-test-order (cons 'hook startup-test-order)))))
+             (car nil nil)",
+            )
+            .unwrap();
+            startup(dir.to_str(), false, env, cx);
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            let order = crate::core::env::intern("startup-test-order", cx);
+            assert_eq!(env.vars.get(order).unwrap().bind(cx).to_string(), "(hook init early)");
+            let init = env.vars.get(sym::USER_INIT_FILE).unwrap().bind(cx);
+            assert!(init.to_string().ends_with("init.el\""));
+        });
+    }
+}
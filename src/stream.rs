@@ -0,0 +1,243 @@
+//! Lazy streams of data from Rust.
+//!
+//! A stream wraps a Rust iterator so that a large data source, like the lines
+//! of a big file or the output of a process, can be consumed one element at a
+//! time instead of being read into a list first. Streams are
+//! [handles](crate::handle). Elements are only converted to lisp objects when
+//! `stream-next' takes them, and a stream is dropped as soon as it is
+//! exhausted, closed or no longer referred to, which closes the file or
+//! process behind it.
+use crate::core::{
+    env::{sym, ArgSlice, Env},
+    gc::{Context, Rt},
+    object::{Object, ObjectType, OptionalFlag, RecordBuilder, NIL},
+};
+use crate::fileio::expand_file_name;
+use crate::permissions::{self, check_file, Capability};
+use anyhow::{bail, Result};
+use rune_core::hashmap::HashMap;
+use rune_macros::defun;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::iter::Peekable;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+type Source = Peekable<Box<dyn Iterator<Item = std::io::Result<String>> + Send>>;
+
+/// The sources of all open streams.
+static STREAMS: LazyLock<Mutex<HashMap<i64, Source>>> = LazyLock::new(Mutex::default);
+static NEXT_ID: AtomicI64 = AtomicI64::new(0);
+
+fn new_stream<I>(iter: I, cx: &Context) -> Object
+where
+    I: Iterator<Item = std::io::Result<String>> + Send + 'static,
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let iter: Box<dyn Iterator<Item = _> + Send> = Box::new(iter);
+    STREAMS.lock().unwrap().insert(id, iter.peekable());
+    let mut record = cx.vec_with_capacity(2);
+    record.push(sym::RUNE_STREAM.into());
+    record.push(cx.add(id));
+    cx.add(RecordBuilder(record))
+}
+
+fn stream_id(stream: Object) -> Result<i64> {
+    if let ObjectType::Record(record) = stream.untag() {
+        if let [tag, id] = &record[..] {
+            if let (ObjectType::Symbol(sym::RUNE_STREAM), ObjectType::Int(id)) =
+                (tag.get().untag(), id.get().untag())
+            {
+                return Ok(id);
+            }
+        }
+    }
+    bail!("Not a stream: {stream}")
+}
+
+/// The lines written to stdout by a child process. The process is killed if
+/// the stream is dropped before its output is exhausted.
+struct ProcessLines {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl Iterator for ProcessLines {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines.next()
+    }
+}
+
+impl Drop for ProcessLines {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Return a stream of the lines of FILE, without their line endings. The file
+/// is read as it is consumed, so it can be larger than memory.
+#[defun]
+fn stream_file_lines<'ob>(file: &str, env: &mut Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let file = expand_file_name(file, None, env, cx)?;
+    check_file(Capability::Read, &file, env, cx)?;
+    let lines = match File::open(&file) {
+        Ok(x) => BufReader::new(x).lines(),
+        Err(e) => bail!("Opening input file {file}: {e}"),
+    };
+    Ok(new_stream(lines, cx))
+}
+
+/// Return a stream of the names of the files in DIRECTORY. If FULL is
+/// non-nil the names are absolute. Unlike `directory-files', the names are
+/// not sorted and do not include "." and "..".
+#[defun]
+fn stream_directory_files<'ob>(
+    directory: &str,
+    full: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let directory = expand_file_name(directory, None, env, cx)?;
+    check_file(Capability::Read, &directory, env, cx)?;
+    let entries = match std::fs::read_dir(&directory) {
+        Ok(x) => x,
+        Err(e) => bail!("Opening directory {directory}: {e}"),
+    };
+    let full = full.is_some();
+    let names = entries.map(move |entry| {
+        let entry = entry?;
+        Ok(if full {
+            entry.path().to_string_lossy().into_owned()
+        } else {
+            entry.file_name().to_string_lossy().into_owned()
+        })
+    });
+    Ok(new_stream(names, cx))
+}
+
+/// Run PROGRAM with ARGS and return a stream of the lines of its output. The
+/// process is killed if the stream is closed before its output ends.
+#[defun]
+fn stream_process_lines<'ob>(
+    program: &str,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let args = Rt::bind_slice(env.stack.arg_slice(args), cx);
+    let args = args.iter().map(|x| (*x).try_into()).collect::<Result<Vec<&str>, _>>()?;
+    permissions::check(Capability::Subprocess, env, cx)?;
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(x) => x,
+        Err(e) => bail!("Searching for program {program}: {e}"),
+    };
+    let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    Ok(new_stream(ProcessLines { child, lines }, cx))
+}
+
+/// Drop the source of the stream with `id` once nothing refers to it.
+pub(crate) fn release(id: i64) {
+    STREAMS.lock().unwrap().remove(&id);
+}
+
+/// Take the source of the stream with `id` out of the table while it is read,
+/// so that a slow source doesn't block other streams. It is put back if `f`
+/// returns true.
+fn with_source<T>(id: i64, f: impl FnOnce(&mut Source) -> (T, bool)) -> Option<T> {
+    let mut source = STREAMS.lock().unwrap().remove(&id)?;
+    let (value, keep) = f(&mut source);
+    if keep {
+        STREAMS.lock().unwrap().insert(id, source);
+    }
+    Some(value)
+}
+
+/// Return the next element of STREAM, or nil if it is exhausted.
+#[defun]
+fn stream_next<'ob>(stream: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let id = stream_id(stream)?;
+    let next = with_source(id, |source| {
+        let next = source.next();
+        let keep = matches!(next, Some(Ok(_)));
+        (next, keep)
+    });
+    match next.flatten() {
+        Some(Ok(x)) => Ok(cx.add(x)),
+        Some(Err(e)) => bail!("Error reading {stream}: {e}"),
+        None => Ok(NIL),
+    }
+}
+
+/// Return non-nil if STREAM has no more elements. This reads ahead one
+/// element if needed.
+#[defun]
+fn stream_empty_p(stream: Object) -> Result<bool> {
+    let id = stream_id(stream)?;
+    let empty = with_source(id, |source| {
+        let empty = source.peek().is_none();
+        (empty, !empty)
+    });
+    Ok(empty.unwrap_or(true))
+}
+
+/// Stop reading STREAM and release the file or process behind it. Any
+/// remaining elements are discarded.
+#[defun]
+fn stream_close(stream: Object) -> Result<bool> {
+    let id = stream_id(stream)?;
+    STREAMS.lock().unwrap().remove(&id);
+    Ok(false)
+}
+
+/// Return t if OBJECT is a stream.
+#[defun]
+fn streamp(object: Object) -> bool {
+    stream_id(object).is_ok()
+}
+
+defsym!(RUNE_STREAM);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_streams() {
+        let dir = std::env::temp_dir().join(format!("rune-stream-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lines.txt"), "a\nb\r\n\nc").unwrap();
+        let dir = dir.to_str().unwrap();
+        assert_lisp(
+            &format!(
+                "(let ((s (stream-file-lines \"{dir}/lines.txt\")) (acc nil))
+                   (while (not (stream-empty-p s))
+                     (setq acc (cons (stream-next s) acc)))
+                   (list (streamp s) (nreverse acc) (stream-next s) (stream-empty-p s)))"
+            ),
+            "(t (\"a\" \"b\" \"\" \"c\") nil t)",
+        );
+        assert_lisp(
+            &format!(
+                "(let ((s (stream-directory-files \"{dir}\")))
+                   (list (stream-next s) (stream-next s)))"
+            ),
+            "(\"lines.txt\" nil)",
+        );
+        assert_lisp(
+            "(let ((s (stream-process-lines \"echo\" \"one\")))
+               (list (stream-next s) (progn (stream-close s) (stream-empty-p s)) (streamp 1)))",
+            "(\"one\" t nil)",
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,747 @@
+//! Syntax classes and motion over balanced expressions.
+//!
+//! Every buffer has a [`SyntaxTable`] that gives the [`Syntax`] class of each
+//! character. It starts out with the standard classes as modified by
+//! `emacs-lisp-mode', and `modify-syntax-entry' changes them for the current
+//! buffer. Syntax tables are not lisp objects, so they can't be shared
+//! between buffers, and the matching paren and flags of a syntax descriptor
+//! are ignored. A comment runs from a comment starter to the next comment
+//! ender, and comments are always skipped, as if `parse-sexp-ignore-comments'
+//! were t.
+//!
+//! [`Scanner`] moves over lists and sexps like `scan-lists' and
+//! `scan-sexps', which signal `scan-error' with a message and the bounds of
+//! the offending text when the parens don't balance. The motion commands
+//! like `forward-sexp' and `up-list' are built on them.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{BufferData, Object, ObjectType},
+};
+use crate::eval::EvalError;
+use crate::reader::symbol_char;
+use anyhow::{bail, Result};
+use rune_core::hashmap::HashMap;
+use rune_core::macros::list;
+use rune_macros::defun;
+
+/// The syntax class of a character.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Syntax {
+    Whitespace,
+    Word,
+    Symbol,
+    Open,
+    Close,
+    StringQuote,
+    Escape,
+    Prefix,
+    CommentStart,
+    CommentEnd,
+    Punctuation,
+}
+
+impl Syntax {
+    /// The class of `c` in the standard syntax table as modified by
+    /// `emacs-lisp-mode'.
+    pub(crate) fn standard(c: char) -> Self {
+        match c {
+            '\n' => Syntax::CommentEnd,
+            c if c.is_whitespace() => Syntax::Whitespace,
+            c if c.is_alphanumeric() => Syntax::Word,
+            '(' | '[' => Syntax::Open,
+            ')' | ']' => Syntax::Close,
+            '"' => Syntax::StringQuote,
+            '\\' => Syntax::Escape,
+            '\'' | '`' | ',' | '#' => Syntax::Prefix,
+            ';' => Syntax::CommentStart,
+            c if symbol_char(c) => Syntax::Symbol,
+            _ => Syntax::Punctuation,
+        }
+    }
+
+    /// The character that stands for this class in a syntax descriptor.
+    fn designator(self) -> char {
+        match self {
+            Syntax::Whitespace => ' ',
+            Syntax::Word => 'w',
+            Syntax::Symbol => '_',
+            Syntax::Open => '(',
+            Syntax::Close => ')',
+            Syntax::StringQuote => '"',
+            Syntax::Escape => '\\',
+            Syntax::Prefix => '\'',
+            Syntax::CommentStart => '<',
+            Syntax::CommentEnd => '>',
+            Syntax::Punctuation => '.',
+        }
+    }
+
+    fn from_designator(c: char) -> Option<Self> {
+        Some(match c {
+            ' ' | '-' => Syntax::Whitespace,
+            'w' => Syntax::Word,
+            '_' => Syntax::Symbol,
+            '(' => Syntax::Open,
+            ')' => Syntax::Close,
+            '"' => Syntax::StringQuote,
+            '\\' => Syntax::Escape,
+            '\'' => Syntax::Prefix,
+            '<' => Syntax::CommentStart,
+            '>' => Syntax::CommentEnd,
+            '.' => Syntax::Punctuation,
+            _ => return None,
+        })
+    }
+}
+
+/// The syntax classes of the characters in a buffer, as changes to the
+/// standard classes.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct SyntaxTable {
+    entries: HashMap<char, Syntax>,
+}
+
+impl SyntaxTable {
+    pub(crate) fn get(&self, c: char) -> Syntax {
+        self.entries.get(&c).copied().unwrap_or_else(|| Syntax::standard(c))
+    }
+}
+
+/// A list that is open at the end of a parse.
+#[derive(Debug, Clone)]
+pub(crate) struct OpenList {
+    pub(crate) open: usize,
+    /// The start of each element of the list that was parsed.
+    pub(crate) elements: Vec<usize>,
+}
+
+/// The state of a parse from the start of some text, like the one
+/// `parse-partial-sexp' returns.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ParseState {
+    /// The position the parse has reached.
+    pub(crate) pos: usize,
+    /// The lists that are open, innermost last.
+    pub(crate) lists: Vec<OpenList>,
+    /// The character that ends the string the parse is in.
+    pub(crate) string: Option<char>,
+    /// Whether the parse is in a comment.
+    comment: bool,
+    /// Whether the next character is escaped.
+    escaped: bool,
+    /// Whether the last character was part of an element, so that the next
+    /// one doesn't start another.
+    in_element: bool,
+}
+
+impl ParseState {
+    /// Parse `c`, the character at [`ParseState::pos`], and return its
+    /// class. Escaped characters are symbol constituents.
+    pub(crate) fn step(&mut self, c: char, table: &SyntaxTable) -> Syntax {
+        let i = self.pos;
+        self.pos += 1;
+        let class = table.get(c);
+        if self.escaped {
+            self.escaped = false;
+            return Syntax::Symbol;
+        }
+        if let Some(quote) = self.string {
+            match class {
+                Syntax::Escape => self.escaped = true,
+                Syntax::StringQuote if c == quote => {
+                    self.string = None;
+                    self.in_element = false;
+                }
+                _ => {}
+            }
+            return class;
+        }
+        if self.comment {
+            self.comment = class != Syntax::CommentEnd;
+            return class;
+        }
+        match class {
+            Syntax::Whitespace | Syntax::CommentEnd => self.in_element = false,
+            Syntax::CommentStart => {
+                self.comment = true;
+                self.in_element = false;
+            }
+            Syntax::Close => {
+                self.lists.pop();
+                self.in_element = false;
+            }
+            _ => {
+                if let (false, Some(list)) = (self.in_element, self.lists.last_mut()) {
+                    list.elements.push(i);
+                }
+                self.in_element = true;
+                match class {
+                    Syntax::Open => {
+                        self.lists.push(OpenList { open: i, elements: Vec::new() });
+                        self.in_element = false;
+                    }
+                    Syntax::StringQuote => self.string = Some(c),
+                    Syntax::Escape => self.escaped = true,
+                    _ => {}
+                }
+            }
+        }
+        class
+    }
+}
+
+/// The parse of the start of a buffer's text, kept so that parsing further
+/// only has to parse the text after it.
+#[derive(Debug)]
+pub(crate) struct ParseCache {
+    state: ParseState,
+    /// The modified tick of the text when it was parsed.
+    tick: usize,
+}
+
+/// Parse the current buffer from its start to `pos`, continuing the last
+/// parse if the text before `pos` hasn't changed since.
+pub(crate) fn parse_to(pos: usize, env: &mut Rt<Env>) -> &ParseState {
+    let buffer: &mut BufferData = env.current_buffer.get_mut();
+    let tick = buffer.text.modified_tick();
+    let pos = pos.min(buffer.text.len_chars());
+    let mut state = match buffer.parse.take() {
+        Some(cache) if cache.tick == tick && cache.state.pos <= pos => cache.state,
+        _ => ParseState::default(),
+    };
+    let (before, after) = buffer.text.slice(state.pos..pos);
+    for c in before.chars().chain(after.chars()) {
+        state.step(c, &buffer.syntax);
+    }
+    &buffer.parse.insert(ParseCache { state, tick }).state
+}
+
+/// Forget the parse of the current buffer before the text from index `beg`
+/// changes, unless the parse ends before it. A parse is also forgotten if the
+/// text was changed since without calling this.
+pub(crate) fn before_change(beg: usize, env: &mut Rt<Env>) {
+    let buffer: &mut BufferData = env.current_buffer.get_mut();
+    let tick = buffer.text.modified_tick();
+    if buffer.parse.as_ref().is_some_and(|x| x.tick != tick || x.state.pos > beg) {
+        buffer.parse = None;
+    }
+}
+
+/// Keep the parse of the current buffer after the text from index `beg`
+/// changed, if it ends before the change.
+pub(crate) fn after_change(beg: usize, env: &mut Rt<Env>) {
+    let buffer: &mut BufferData = env.current_buffer.get_mut();
+    let tick = buffer.text.modified_tick();
+    match &mut buffer.parse {
+        Some(cache) if cache.state.pos <= beg => cache.tick = tick,
+        _ => buffer.parse = None,
+    }
+}
+
+/// Why a scan failed, with the bounds of the text that caused it.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ScanError {
+    message: &'static str,
+    start: usize,
+    end: usize,
+}
+
+impl ScanError {
+    /// Signal this as `scan-error'. The bounds are given as buffer positions.
+    fn signal(&self, env: &mut Rt<Env>, cx: &Context) -> anyhow::Error {
+        let data = list![self.message, self.start + 1, self.end + 1; cx];
+        EvalError::signal(sym::SCAN_ERROR.into(), data, env).into()
+    }
+}
+
+/// Scans text for balanced expressions. Positions are indexes into the
+/// text, where position `i` is before the character at index `i`.
+pub(crate) struct Scanner<'a> {
+    chars: &'a [char],
+    /// The class of each character. Escaped characters are symbol
+    /// constituents.
+    classes: Vec<Syntax>,
+    /// Whether each character is escaped by the one before it.
+    escaped: Vec<bool>,
+    /// Whether each character is in a comment, including the comment starter.
+    comments: Vec<bool>,
+}
+
+impl<'a> Scanner<'a> {
+    pub(crate) fn new(chars: &'a [char], table: &SyntaxTable) -> Self {
+        let mut classes = Vec::with_capacity(chars.len());
+        let mut escaped = Vec::with_capacity(chars.len());
+        let mut comments = Vec::with_capacity(chars.len());
+        let mut state = ParseState::default();
+        for c in chars {
+            escaped.push(state.escaped);
+            classes.push(state.step(*c, table));
+            comments.push(state.comment);
+        }
+        Self { chars, classes, escaped, comments }
+    }
+
+    pub(crate) fn syntax(&self, i: usize) -> Syntax {
+        self.classes[i]
+    }
+
+    fn is_constituent(&self, i: usize) -> bool {
+        matches!(self.classes[i], Syntax::Word | Syntax::Symbol | Syntax::Escape)
+    }
+
+    /// The end of the symbol that starts at `i`.
+    fn symbol_end(&self, mut i: usize) -> usize {
+        while i < self.chars.len() && self.is_constituent(i) {
+            i += 1;
+        }
+        i
+    }
+
+    /// The start of the symbol that ends at `i`.
+    fn symbol_start(&self, mut i: usize) -> usize {
+        while i > 0 && self.is_constituent(i - 1) {
+            i -= 1;
+        }
+        i
+    }
+
+    /// The end of the string that starts at `start`.
+    fn string_end(&self, start: usize) -> Result<usize, ScanError> {
+        let quote = self.chars[start];
+        let end =
+            (start + 1..self.chars.len()).find(|x| self.chars[*x] == quote && !self.escaped[*x]);
+        let message = "Unbalanced parentheses";
+        end.map(|x| x + 1).ok_or(ScanError { message, start, end: self.chars.len() })
+    }
+
+    /// The start of the string that ends at `end`.
+    fn string_start(&self, end: usize) -> Result<usize, ScanError> {
+        let quote = self.chars[end - 1];
+        let start = (0..end - 1).rev().find(|x| {
+            self.chars[*x] == quote && self.classes[*x] == Syntax::StringQuote && !self.comments[*x]
+        });
+        let message = "Unbalanced parentheses";
+        start.ok_or(ScanError { message, start: 0, end })
+    }
+
+    /// Scan forward from `i` until `depth` gets back to zero, or if `sexp`
+    /// is true, until the end of a sexp at depth zero. Returns None if the
+    /// text ends first at depth zero.
+    fn forward(
+        &self,
+        mut i: usize,
+        depth: &mut i64,
+        min_depth: i64,
+        sexp: bool,
+    ) -> Result<Option<usize>, ScanError> {
+        let from = i;
+        while i < self.chars.len() {
+            match self.syntax(i) {
+                Syntax::Word | Syntax::Symbol | Syntax::Escape => {
+                    i = self.symbol_end(i);
+                    if sexp && *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                Syntax::StringQuote => {
+                    i = self.string_end(i)?;
+                    if sexp && *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                Syntax::CommentStart => {
+                    i += 1;
+                    while i < self.chars.len() && self.comments[i] {
+                        i += 1;
+                    }
+                }
+                Syntax::Open => {
+                    *depth += 1;
+                    i += 1;
+                    if *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                Syntax::Close => {
+                    *depth -= 1;
+                    if *depth < min_depth {
+                        let message = "Containing expression ends prematurely";
+                        return Err(ScanError { message, start: i, end: i + 1 });
+                    }
+                    i += 1;
+                    if *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+        if *depth == 0 {
+            Ok(None)
+        } else {
+            let message = "Unbalanced parentheses";
+            Err(ScanError { message, start: from, end: self.chars.len() })
+        }
+    }
+
+    /// Like [`Scanner::forward`], but scanning backward from `i`.
+    fn backward(
+        &self,
+        mut i: usize,
+        depth: &mut i64,
+        min_depth: i64,
+        sexp: bool,
+    ) -> Result<Option<usize>, ScanError> {
+        let from = i;
+        while i > 0 {
+            if self.comments[i - 1] {
+                i -= 1;
+                continue;
+            }
+            match self.syntax(i - 1) {
+                Syntax::Word | Syntax::Symbol | Syntax::Escape => {
+                    i = self.symbol_start(i);
+                    if sexp && *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                Syntax::StringQuote => {
+                    i = self.string_start(i)?;
+                    if sexp && *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                Syntax::Close => {
+                    *depth += 1;
+                    i -= 1;
+                    if *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                Syntax::Open => {
+                    *depth -= 1;
+                    if *depth < min_depth {
+                        let message = "Containing expression ends prematurely";
+                        return Err(ScanError { message, start: i - 1, end: i });
+                    }
+                    i -= 1;
+                    if *depth == 0 {
+                        return Ok(Some(i));
+                    }
+                }
+                _ => i -= 1,
+            }
+        }
+        if *depth == 0 {
+            Ok(None)
+        } else {
+            Err(ScanError { message: "Unbalanced parentheses", start: 0, end: from })
+        }
+    }
+
+    /// Scan from `from` over `count` lists, or sexps if `sexp` is true,
+    /// starting at `depth`. A negative `count` scans backward. Scanning stops
+    /// early if the depth gets back to zero. Returns None if the text ends
+    /// first at depth zero.
+    pub(crate) fn scan(
+        &self,
+        from: usize,
+        count: i64,
+        mut depth: i64,
+        sexp: bool,
+    ) -> Result<Option<usize>, ScanError> {
+        let min_depth = depth.min(0);
+        let mut pos = from.min(self.chars.len());
+        for _ in 0..count.unsigned_abs() {
+            let next = if count > 0 {
+                self.forward(pos, &mut depth, min_depth, sexp)?
+            } else {
+                self.backward(pos, &mut depth, min_depth, sexp)?
+            };
+            let Some(next) = next else { return Ok(None) };
+            pos = next;
+        }
+        Ok(Some(pos))
+    }
+
+    /// Move back from `i` over any prefix characters.
+    pub(crate) fn prefix_start(&self, mut i: usize) -> usize {
+        while i > 0 && self.syntax(i - 1) == Syntax::Prefix {
+            i -= 1;
+        }
+        i
+    }
+}
+
+/// The text of the current buffer, the index of point in it, and the syntax
+/// table of the buffer.
+pub(crate) struct BufferText {
+    pub(crate) chars: Vec<char>,
+    pub(crate) point: usize,
+    pub(crate) table: SyntaxTable,
+}
+
+impl BufferText {
+    pub(crate) fn current(env: &Rt<Env>) -> Self {
+        let buffer = env.current_buffer.get();
+        let text = &buffer.text;
+        let chars = text.to_string().chars().collect();
+        Self { chars, point: text.cursor().chars(), table: buffer.syntax.clone() }
+    }
+
+    pub(crate) fn scanner(&self) -> Scanner<'_> {
+        Scanner::new(&self.chars, &self.table)
+    }
+}
+
+fn scan(
+    from: usize,
+    count: i64,
+    depth: i64,
+    sexp: bool,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<Option<usize>> {
+    let from = from.saturating_sub(1);
+    match BufferText::current(env).scanner().scan(from, count, depth, sexp) {
+        Ok(pos) => Ok(pos.map(|x| x + 1)),
+        Err(e) => Err(e.signal(env, cx)),
+    }
+}
+
+/// Scan from FROM over COUNT balanced groups of parens, starting at
+/// parenthesis depth DEPTH, and return the position where the scan stops.
+/// Scan backward if COUNT is negative. The scan stops early if the depth
+/// gets back to zero, so with DEPTH 1 the scan moves out of a list and with
+/// DEPTH -1 into one. Return nil if the buffer ends first at depth zero, and
+/// signal `scan-error' if the parens don't balance.
+#[defun]
+fn scan_lists(
+    from: usize,
+    count: i64,
+    depth: i64,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<Option<usize>> {
+    scan(from, count, depth, false, env, cx)
+}
+
+/// Scan from FROM over COUNT sexps and return the position where the scan
+/// stops. Scan backward if COUNT is negative. Return nil if the buffer ends
+/// first, and signal `scan-error' if the scan ends in the middle of a list.
+#[defun]
+fn scan_sexps(from: usize, count: i64, env: &mut Rt<Env>, cx: &Context) -> Result<Option<usize>> {
+    scan(from, count, 0, true, env, cx)
+}
+
+/// Scan from point with `scan-lists' and move point to where it stops, or to
+/// the end of the buffer in the direction of COUNT if it returns nil.
+fn scan_and_move(
+    count: i64,
+    depth: i64,
+    sexp: bool,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let text = BufferText::current(env);
+    let scanner = text.scanner();
+    let pos = match scanner.scan(text.point, count, depth, sexp) {
+        Ok(Some(pos)) => pos,
+        Ok(None) if count > 0 => text.chars.len(),
+        Ok(None) => 0,
+        Err(e) => return Err(e.signal(env, cx)),
+    };
+    let pos = if sexp && count < 0 { scanner.prefix_start(pos) } else { pos };
+    env.current_buffer.get_mut().text.set_cursor(pos);
+    Ok(())
+}
+
+/// Move forward across ARG sexps, or backward if ARG is negative. ARG
+/// defaults to 1. Moving backward also moves over prefix characters like
+/// `''. Signal `scan-error' if a list ends before ARG sexps are found.
+#[defun]
+fn forward_sexp(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    scan_and_move(arg.unwrap_or(1), 0, true, env, cx)
+}
+
+/// Move backward across ARG sexps, like `forward-sexp' with -ARG.
+#[defun]
+fn backward_sexp(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    scan_and_move(-arg.unwrap_or(1), 0, true, env, cx)
+}
+
+/// Move forward out of ARG levels of parens, or backward if ARG is
+/// negative. ARG defaults to 1.
+#[defun]
+fn up_list(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let arg = arg.unwrap_or(1);
+    for _ in 0..arg.unsigned_abs() {
+        scan_and_move(arg.signum(), 1, false, env, cx)?;
+    }
+    Ok(())
+}
+
+/// Move backward out of ARG levels of parens, like `up-list' with -ARG.
+#[defun]
+fn backward_up_list(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    up_list(Some(-arg.unwrap_or(1)), env, cx)
+}
+
+/// Move forward down ARG levels of parens, or backward if ARG is negative.
+/// ARG defaults to 1.
+#[defun]
+fn down_list(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let arg = arg.unwrap_or(1);
+    for _ in 0..arg.unsigned_abs() {
+        scan_and_move(arg.signum(), -1, false, env, cx)?;
+    }
+    Ok(())
+}
+
+/// Move point backward over any prefix characters, like `''.
+#[defun]
+fn backward_prefix_chars(env: &mut Rt<Env>) {
+    let text = BufferText::current(env);
+    let start = text.scanner().prefix_start(text.point);
+    env.current_buffer.get_mut().text.set_cursor(start);
+}
+
+/// Return the syntax class of CHARACTER in the current buffer, as the
+/// character that stands for it in a syntax descriptor.
+#[defun]
+fn char_syntax(character: char, env: &Rt<Env>) -> char {
+    env.current_buffer.get().syntax.get(character).designator()
+}
+
+/// Set the syntax of CHAR in the current buffer to NEWENTRY, a syntax
+/// descriptor whose first character is the class. CHAR may also be a cons
+/// (MIN . MAX) to set the syntax of every character in that range. Only the
+/// table of the current buffer can be changed, so TABLE has to be nil. The
+/// matching paren and flags in NEWENTRY are ignored.
+#[defun]
+fn modify_syntax_entry(
+    char: Object,
+    newentry: &str,
+    table: Option<Object>,
+    env: &mut Rt<Env>,
+) -> Result<bool> {
+    if table.is_some() {
+        bail!("Syntax tables are not objects, so TABLE has to be nil");
+    }
+    let class = newentry.chars().next().unwrap_or(' ');
+    let Some(syntax) = Syntax::from_designator(class) else {
+        bail!("Unsupported syntax class: {class}")
+    };
+    let (min, max): (char, char) = match char.untag() {
+        ObjectType::Cons(range) => (range.car().try_into()?, range.cdr().try_into()?),
+        _ => (char.try_into()?, char.try_into()?),
+    };
+    let buffer = env.current_buffer.get_mut();
+    buffer.syntax.entries.extend((min..=max).map(|x| (x, syntax)));
+    buffer.parse = None;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp_with_vars;
+
+    fn scan(text: &str, from: usize, count: i64, depth: i64, sexp: bool) -> Option<usize> {
+        let chars: Vec<char> = text.chars().collect();
+        Scanner::new(&chars, &SyntaxTable::default())
+            .scan(from, count, depth, sexp)
+            .unwrap()
+    }
+
+    fn scan_error(text: &str, from: usize, count: i64, depth: i64) -> &'static str {
+        let chars: Vec<char> = text.chars().collect();
+        Scanner::new(&chars, &SyntaxTable::default())
+            .scan(from, count, depth, true)
+            .unwrap_err()
+            .message
+    }
+
+    #[test]
+    fn test_scan_sexps() {
+        let text = "(a \"b)\" ?\\( ;c)\n d) 'e";
+        assert_eq!(scan(text, 0, 1, 0, true), Some(19));
+        assert_eq!(scan(text, 1, 2, 0, true), Some(7));
+        assert_eq!(scan(text, 1, 3, 0, true), Some(11));
+        assert_eq!(scan(text, 1, 4, 0, true), Some(18));
+        assert_eq!(scan(text, 19, 1, 0, true), Some(22));
+        assert_eq!(scan(text, 22, 1, 0, true), None);
+        assert_eq!(scan(text, 22, -1, 0, true), Some(21));
+        assert_eq!(scan(text, 19, -1, 0, true), Some(0));
+        assert_eq!(scan(text, 18, -1, 0, true), Some(17));
+        assert_eq!(scan(text, 17, -1, 0, true), Some(8));
+        assert_eq!(scan(text, 7, -1, 0, true), Some(3));
+        assert_eq!(scan_error(text, 18, 1, 0), "Containing expression ends prematurely");
+        assert_eq!(scan_error("(a", 0, 1, 0), "Unbalanced parentheses");
+        assert_eq!(scan_error("a\"b", 1, 1, 0), "Unbalanced parentheses");
+    }
+
+    #[test]
+    fn test_scan_lists() {
+        let text = "(a (b c) [d]) e";
+        assert_eq!(scan(text, 0, 1, 0, false), Some(13));
+        assert_eq!(scan(text, 1, 1, 0, false), Some(8));
+        assert_eq!(scan(text, 1, 2, 0, false), Some(12));
+        assert_eq!(scan(text, 5, 1, 1, false), Some(8));
+        assert_eq!(scan(text, 5, -1, 1, false), Some(3));
+        assert_eq!(scan(text, 2, 1, -1, false), Some(4));
+        assert_eq!(scan(text, 13, 1, 0, false), None);
+        let chars: Vec<char> = text.chars().collect();
+        let error =
+            Scanner::new(&chars, &SyntaxTable::default()).scan(13, 1, 1, false).unwrap_err();
+        assert_eq!(error, ScanError { message: "Unbalanced parentheses", start: 13, end: 15 });
+    }
+
+    #[test]
+    fn test_syntax_table() {
+        let text: Vec<char> = "(foo?) # c)\nd".chars().collect();
+        let scanner = Scanner::new(&text, &SyntaxTable::default());
+        assert_eq!(scanner.scan(0, 1, 0, true), Ok(Some(6)));
+        assert!(scanner.scan(6, 1, 0, true).is_err());
+        let mut table = SyntaxTable::default();
+        table.entries.extend([('#', Syntax::CommentStart), ('|', Syntax::Close)]);
+        let scanner = Scanner::new(&text, &table);
+        assert_eq!(scanner.scan(6, 1, 0, true), Ok(Some(13)));
+        assert_eq!(scanner.scan(12, -1, 0, true), Ok(Some(0)));
+        let text: Vec<char> = "(a|".chars().collect();
+        assert_eq!(Scanner::new(&text, &table).scan(0, 1, 0, true), Ok(Some(3)));
+    }
+
+    #[test]
+    fn test_modify_syntax_entry() {
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"modify-syntax-entry\"))
+               (insert \"a|b c\")
+               (list (char-syntax ?|) (char-syntax ?a) (char-syntax ?\\n)
+                     (progn (modify-syntax-entry ?| \"w\") (char-syntax ?|))
+                     (progn (goto-char 0) (forward-sexp) (point))))",
+            "(95 119 62 119 3)",
+        );
+    }
+
+    #[test]
+    fn test_sexp_motion() {
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"sexp-motion\"))
+               (insert \"(foo '(bar baz) qux)\")
+               (list (progn (goto-char 0) (forward-sexp) (point))
+                     (progn (backward-sexp) (point))
+                     (progn (down-list) (forward-sexp 2) (point))
+                     (progn (backward-sexp) (point))
+                     (progn (down-list) (up-list) (point))
+                     (progn (backward-up-list) (point))
+                     (scan-lists 2 1 0)
+                     (scan-sexps 1 -1)
+                     (condition-case err (up-list 2) (scan-error (cdr err)))))",
+            "(20 0 15 5 15 0 16 nil (\"Unbalanced parentheses\" 1 21))",
+        );
+    }
+}
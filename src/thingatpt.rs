@@ -0,0 +1,303 @@
+//! Finding the thing at point.
+//!
+//! `bounds-of-thing-at-point' and `thing-at-point' find the word, symbol,
+//! line, sexp or URL around point in the current buffer. Characters are
+//! classified by the syntax table of the buffer, and sexps are found with
+//! [`Scanner`].
+//!
+//! Other kinds of things are added by giving a symbol a
+//! `bounds-of-thing-at-point' property, a function of no arguments that
+//! returns (START . END) or nil, or a `thing-at-point' property, a function
+//! that returns the thing itself.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Gc, Object, ObjectType, OptionalFlag, Symbol, NIL},
+};
+use crate::syntax::{BufferText, Scanner, Syntax};
+use anyhow::{bail, Result};
+use rune_core::macros::{call, rebind, root};
+use rune_macros::defun;
+
+/// Finds things in the text of the current buffer.
+struct Things<'a> {
+    text: &'a BufferText,
+    scanner: Scanner<'a>,
+}
+
+impl<'a> Things<'a> {
+    fn new(text: &'a BufferText) -> Self {
+        Self { text, scanner: text.scanner() }
+    }
+
+    /// The run of characters around point that satisfy `pred`, including one
+    /// that ends at point.
+    fn run(&self, pred: impl Fn(char) -> bool) -> Option<(usize, usize)> {
+        let chars = &self.text.chars;
+        let at = |i: usize| chars.get(i).is_some_and(|x| pred(*x));
+        let p = self.text.point;
+        if !at(p) && (p == 0 || !at(p - 1)) {
+            return None;
+        }
+        let start = (0..p).rev().take_while(|x| at(*x)).last().unwrap_or(p);
+        let end = (p..chars.len()).find(|x| !at(*x)).unwrap_or(chars.len());
+        Some((start, end))
+    }
+
+    fn word(&self) -> Option<(usize, usize)> {
+        self.run(|x| self.text.table.get(x) == Syntax::Word)
+    }
+
+    fn symbol(&self) -> Option<(usize, usize)> {
+        self.run(|x| matches!(self.text.table.get(x), Syntax::Word | Syntax::Symbol))
+    }
+
+    /// The line around point, including its newline.
+    fn line(&self) -> Option<(usize, usize)> {
+        let (chars, p) = (&self.text.chars, self.text.point);
+        let start = chars[..p].iter().rposition(|x| *x == '\n').map_or(0, |x| x + 1);
+        let end = chars[p..].iter().position(|x| *x == '\n').map_or(chars.len(), |x| p + x + 1);
+        (start < end).then_some((start, end))
+    }
+
+    fn syntax(&self, i: usize) -> Option<Syntax> {
+        (i < self.text.chars.len()).then(|| self.scanner.syntax(i))
+    }
+
+    /// Move from `i` to the end of a sexp, or past the close paren at `i`.
+    fn end_of_sexp(&self, i: usize) -> Option<usize> {
+        if self.syntax(i) == Some(Syntax::Close) {
+            return Some(i + 1);
+        }
+        self.scanner.scan(i, 1, 0, true).ok()?
+    }
+
+    /// Move from `i` to the start of a sexp, including its prefix characters,
+    /// or before the open paren that ends at `i`.
+    fn beginning_of_sexp(&self, i: usize) -> Option<usize> {
+        if i > 0 && self.syntax(i - 1) == Some(Syntax::Open) {
+            return Some(i - 1);
+        }
+        let start = self.scanner.scan(i, -1, 0, true).ok()??;
+        Some(self.scanner.prefix_start(start))
+    }
+
+    /// The sexp around point, or else the one that ends at point, found like
+    /// `bounds-of-thing-at-point' does with `forward-sexp'.
+    fn sexp(&self) -> Option<(usize, usize)> {
+        let p = self.text.point;
+        if let Some(end) = self.end_of_sexp(p) {
+            let start = self.beginning_of_sexp(end)?;
+            if start <= p {
+                return Some((start, end));
+            }
+        }
+        let start = self.beginning_of_sexp(p)?;
+        let end = self.end_of_sexp(start)?;
+        let start = self.beginning_of_sexp(end)?;
+        (start <= p && p <= end && start < end).then_some((start, end))
+    }
+
+    /// The URL around point. A URL starts with a scheme followed by "://",
+    /// with "mailto:", or with "www.", and doesn't end with punctuation.
+    fn url(&self) -> Option<(usize, usize)> {
+        let chars = &self.text.chars;
+        let (run_start, mut end) = self.run(|x| {
+            !x.is_whitespace()
+                && !matches!(x, '"' | '\'' | '<' | '>' | '[' | ']' | '^' | '`' | '{' | '}')
+        })?;
+        let run: String = chars[run_start..end].iter().collect();
+        let is_scheme = |x: char| x.is_ascii_alphanumeric() || matches!(x, '+' | '.' | '-');
+        let start = run.char_indices().find_map(|(byte, c)| {
+            let rest = &run[byte..];
+            let scheme = rest.find("://").is_some_and(|x| {
+                x > 0 && c.is_ascii_alphabetic() && rest[..x].chars().all(is_scheme)
+            });
+            let url = scheme || rest.starts_with("mailto:") || rest.starts_with("www.");
+            url.then(|| run_start + run[..byte].chars().count())
+        })?;
+        let url = &chars[start..end];
+        let balanced = url.contains(&'(');
+        while end > start
+            && (matches!(chars[end - 1], '.' | ',' | ';' | ':' | '!' | '?')
+                || (!balanced && chars[end - 1] == ')'))
+        {
+            end -= 1;
+        }
+        (start < end && self.text.point <= end).then_some((start, end))
+    }
+}
+
+fn thing_bounds(thing: Symbol, text: &BufferText) -> Result<Option<(usize, usize)>> {
+    let text = Things::new(text);
+    Ok(match thing {
+        sym::WORD => text.word(),
+        sym::SYMBOL => text.symbol(),
+        sym::LINE => text.line(),
+        sym::SEXP => text.sexp(),
+        sym::URL => text.url(),
+        _ => bail!("Unknown thing: {thing}"),
+    })
+}
+
+/// The function in the property `prop` of `thing`, if it has one.
+fn property_function<'ob>(
+    thing: Symbol,
+    prop: Symbol,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Option<Function<'ob>>> {
+    let func = crate::data::get(thing, prop, env, cx);
+    if func.is_nil() {
+        Ok(None)
+    } else {
+        Ok(Some(func.try_into()?))
+    }
+}
+
+/// Return the bounds of THING at point as (START . END), or nil if there is
+/// none. THING is `word', `symbol', `line', `sexp', `url' or a symbol with
+/// a `bounds-of-thing-at-point' property. A word or symbol is found if it
+/// is at point or ends at point. A line includes its newline.
+#[defun]
+fn bounds_of_thing_at_point<'ob>(
+    thing: &Rto<Gc<Symbol>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let prop = sym::BOUNDS_OF_THING_AT_POINT;
+    if let Some(func) = property_function(thing.untag(cx), prop, env, cx)? {
+        root!(func, cx);
+        let bounds = call!(func; env, cx)?;
+        return Ok(rebind!(bounds, cx));
+    }
+    match thing_bounds(thing.untag(cx), &BufferText::current(env))? {
+        Some((start, end)) => Ok(Cons::new(start + 1, end + 1, cx).into()),
+        None => Ok(NIL),
+    }
+}
+
+/// Return THING at point as a string, or nil if there is none. THING is one
+/// of the things understood by `bounds-of-thing-at-point', or a symbol with
+/// a `thing-at-point' property. A URL that starts with "www." is returned
+/// with "http://" added. Text properties are not supported, so
+/// NO-PROPERTIES is ignored.
+#[defun]
+fn thing_at_point<'ob>(
+    thing: &Rto<Gc<Symbol>>,
+    _no_properties: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let prop = sym::THING_AT_POINT;
+    if let Some(func) = property_function(thing.untag(cx), prop, env, cx)? {
+        root!(func, cx);
+        let value = call!(func; env, cx)?;
+        return Ok(rebind!(value, cx));
+    }
+    let prop = sym::BOUNDS_OF_THING_AT_POINT;
+    if let Some(func) = property_function(thing.untag(cx), prop, env, cx)? {
+        root!(func, cx);
+        let bounds = call!(func; env, cx)?;
+        let ObjectType::Cons(bounds) = bounds.untag() else { return Ok(NIL) };
+        let start: usize = bounds.car().try_into()?;
+        let end: usize = bounds.cdr().try_into()?;
+        let text = BufferText::current(env);
+        let (start, end) = (start.saturating_sub(1), end.saturating_sub(1).min(text.chars.len()));
+        let string: String = text.chars[start.min(end)..end].iter().collect();
+        return Ok(cx.add(string));
+    }
+    let text = BufferText::current(env);
+    let Some((start, end)) = thing_bounds(thing.untag(cx), &text)? else { return Ok(NIL) };
+    let mut string: String = text.chars[start..end].iter().collect();
+    if thing.untag(cx) == sym::URL && string.starts_with("www.") {
+        string.insert_str(0, "http://");
+    }
+    Ok(cx.add(string))
+}
+
+defsym!(WORD);
+defsym!(LINE);
+defsym!(SEXP);
+defsym!(URL);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp_with_vars;
+
+    fn check(buffer: &str, text: &str, point: usize, things: &str, expect: &str) {
+        assert_lisp_with_vars(
+            &format!(
+                "(progn
+                   (set-buffer (get-buffer-create {buffer:?}))
+                   (delete-region 1 (point-max))
+                   (insert {text:?})
+                   (goto-char {point})
+                   (mapcar (lambda (x) (list (thing-at-point x) (bounds-of-thing-at-point x)))
+                           '{things}))"
+            ),
+            expect,
+        );
+    }
+
+    #[test]
+    fn test_words_and_lines() {
+        check(
+            "words_and_lines",
+            "foo-bar baz\nnext",
+            2,
+            "(word symbol line)",
+            "((\"foo\" (1 . 4)) (\"foo-bar\" (1 . 8)) (\"foo-bar baz\n\" (1 . 13)))",
+        );
+        check("words_and_lines", "foo bar", 3, "(word)", "((\"foo\" (1 . 4)))");
+        check("words_and_lines", "foo  bar", 4, "(word symbol)", "((nil nil) (nil nil))");
+    }
+
+    #[test]
+    fn test_sexps() {
+        check(
+            "sexps",
+            "(a (b \")\") c) d",
+            0,
+            "(sexp)",
+            "((\"(a (b \\\")\\\") c)\" (1 . 14)))",
+        );
+        check("sexps", "x '(a [b]) y", 10, "(sexp)", "((\"'(a [b])\" (3 . 11)))");
+        check("sexps", "x \"a\\\"b\" y", 2, "(sexp)", "((\"\\\"a\\\\\\\"b\\\"\" (3 . 9)))");
+        check("sexps", "(foo bar)", 6, "(sexp)", "((\"bar\" (6 . 9)))");
+    }
+
+    #[test]
+    fn test_urls() {
+        check(
+            "urls",
+            "see https://example.com/a_(b). ok",
+            8,
+            "(url)",
+            "((\"https://example.com/a_(b)\" (5 . 30)))",
+        );
+        check(
+            "urls",
+            "(www.example.com).",
+            3,
+            "(url)",
+            "((\"http://www.example.com\" (2 . 17)))",
+        );
+        check("urls", "no url here", 1, "(url)", "((nil nil))");
+    }
+
+    #[test]
+    fn test_thing_properties() {
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"thingatpt-prop\"))
+               (insert \"abcdef\")
+               (put 'two 'bounds-of-thing-at-point (lambda () (cons 2 4)))
+               (put 'constant 'thing-at-point (lambda () \"value\"))
+               (list (thing-at-point 'two) (bounds-of-thing-at-point 'two)
+                     (thing-at-point 'constant)))",
+            "(\"bc\" (2 . 4) \"value\")",
+        );
+    }
+}
@@ -1,19 +1,26 @@
 //! Multi-threaded elisp support.
+//!
+//! Each thread has its own heap, so mutexes and condition variables are
+//! [handles](crate::handle). Like Emacs, mutexes are recursive and a
+//! condition variable is tied to one mutex, which it keeps alive.
 use crate::core::{
-    env::Env,
-    gc::{Block, Context, RootSet},
-    object::{CloneIn, Object},
+    env::{sym, Env},
+    gc::{Block, Context, RootSet, Rt},
+    object::{CloneIn, Object, ObjectType, OptionalFlag, RecordBuilder, Symbol},
 };
-use rune_core::macros::root;
+use crate::permissions::Permissions;
+use anyhow::{bail, ensure, Result};
+use rune_core::{hashmap::HashMap, macros::root};
 use rune_macros::defun;
-use std::thread::{self, JoinHandle};
+use std::sync::{Condvar, LazyLock, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle, ThreadId};
 
 #[defun]
-fn go(obj: Object) {
-    go_internal(obj);
+fn go(obj: Object, env: &Rt<Env>) {
+    go_internal(obj, env.permissions.clone());
 }
 
-fn go_internal(obj: Object) -> JoinHandle<()> {
+fn go_internal(obj: Object, permissions: Permissions) -> JoinHandle<()> {
     let block = Block::new_local_unchecked();
     let sexp = obj.clone_in(&block);
     let raw = sexp.into_raw();
@@ -22,22 +29,285 @@ fn go_internal(obj: Object) -> JoinHandle<()> {
         let roots = &RootSet::default();
         let cx = &mut Context::from_block(block, roots);
         root!(env, new(Env), cx);
+        env.permissions = permissions;
         let obj = unsafe { Object::from_raw(raw) };
         root!(obj, cx);
         _ = crate::interpreter::eval(obj, None, env, cx);
     })
 }
 
+struct LispMutex {
+    name: Option<String>,
+    owner: Option<ThreadId>,
+    /// Number of times the owner has locked the mutex
+    count: usize,
+}
+
+struct Condition {
+    mutex: i64,
+    name: Option<String>,
+    waiters: usize,
+    /// Number of waiters that have been notified but not yet woken
+    notified: usize,
+}
+
+#[derive(Default)]
+struct SyncState {
+    next_id: i64,
+    mutexes: HashMap<i64, LispMutex>,
+    conditions: HashMap<i64, Condition>,
+    /// The mutex each blocked thread is waiting for
+    waiting: HashMap<ThreadId, i64>,
+}
+
+static SYNC: LazyLock<Mutex<SyncState>> = LazyLock::new(Mutex::default);
+/// Notified whenever a mutex is released or a condition variable notified.
+static SYNC_CHANGED: Condvar = Condvar::new();
+
+impl SyncState {
+    fn add_id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Return true if blocking `thread` on `mutex` would never finish
+    /// because the owner is itself waiting, directly or not, on `thread`.
+    fn would_deadlock(&self, thread: ThreadId, mut mutex: i64) -> bool {
+        // each step follows a different thread, so the chain is bounded
+        for _ in 0..=self.waiting.len() {
+            let Some(owner) = self.mutexes[&mutex].owner else { return false };
+            if owner == thread {
+                return true;
+            }
+            let Some(&next) = self.waiting.get(&owner) else { return false };
+            mutex = next;
+        }
+        false
+    }
+}
+
+fn lock_mutex(mut state: MutexGuard<'_, SyncState>, id: i64, count: usize) -> Result<()> {
+    let thread = thread::current().id();
+    loop {
+        let mutex = state.mutexes.get_mut(&id).unwrap();
+        match mutex.owner {
+            None => {
+                mutex.owner = Some(thread);
+                mutex.count = count;
+                return Ok(());
+            }
+            Some(owner) if owner == thread => {
+                mutex.count += count;
+                return Ok(());
+            }
+            Some(_) => {
+                if cfg!(debug_assertions) && state.would_deadlock(thread, id) {
+                    bail!("Deadlock detected while locking {}", mutex_display(&state, id));
+                }
+                state.waiting.insert(thread, id);
+                state = SYNC_CHANGED.wait(state).unwrap();
+                state.waiting.remove(&thread);
+            }
+        }
+    }
+}
+
+/// Remove the mutex with `id` once nothing refers to it.
+pub(crate) fn release_mutex(id: i64) {
+    SYNC.lock().unwrap().mutexes.remove(&id);
+}
+
+/// Remove the condition variable with `id` once nothing refers to it, along
+/// with its reference to its mutex.
+pub(crate) fn release_condition(id: i64) {
+    let condition = SYNC.lock().unwrap().conditions.remove(&id);
+    if let Some(condition) = condition {
+        crate::handle::release_id(sym::RUNE_MUTEX, condition.mutex);
+    }
+}
+
+fn mutex_display(state: &SyncState, id: i64) -> String {
+    match &state.mutexes[&id].name {
+        Some(name) => format!("mutex {name}"),
+        None => format!("mutex {id}"),
+    }
+}
+
+fn sync_object(tag: Symbol, id: i64, cx: &Context) -> Object {
+    let mut record = cx.vec_with_capacity(2);
+    record.push(tag.into());
+    record.push(cx.add(id));
+    cx.add(RecordBuilder(record))
+}
+
+fn sync_id(object: Object, tag: crate::core::object::Symbol) -> Option<i64> {
+    let ObjectType::Record(record) = object.untag() else { return None };
+    let [record_tag, id] = &record[..] else { return None };
+    match (record_tag.get().untag(), id.get().untag()) {
+        (ObjectType::Symbol(x), ObjectType::Int(id)) if x == tag => Some(id),
+        _ => None,
+    }
+}
+
+fn mutex_id(mutex: Object) -> Result<i64> {
+    match sync_id(mutex, sym::RUNE_MUTEX) {
+        Some(id) if SYNC.lock().unwrap().mutexes.contains_key(&id) => Ok(id),
+        _ => bail!("Not a mutex: {mutex}"),
+    }
+}
+
+fn condition_id(cond: Object) -> Result<i64> {
+    match sync_id(cond, sym::RUNE_CONDITION_VARIABLE) {
+        Some(id) if SYNC.lock().unwrap().conditions.contains_key(&id) => Ok(id),
+        _ => bail!("Not a condition variable: {cond}"),
+    }
+}
+
+/// Create a mutex. The optional NAME is for debugging.
+#[defun]
+fn make_mutex<'ob>(name: Option<&str>, cx: &'ob Context) -> Object<'ob> {
+    let mut state = SYNC.lock().unwrap();
+    let id = state.add_id();
+    let name = name.map(ToOwned::to_owned);
+    state.mutexes.insert(id, LispMutex { name, owner: None, count: 0 });
+    sync_object(sym::RUNE_MUTEX, id, cx)
+}
+
+#[defun]
+fn mutexp(object: Object) -> bool {
+    mutex_id(object).is_ok()
+}
+
+#[defun]
+fn mutex_name(mutex: Object) -> Result<Option<String>> {
+    let id = mutex_id(mutex)?;
+    Ok(SYNC.lock().unwrap().mutexes[&id].name.clone())
+}
+
+/// Acquire MUTEX, blocking until it is free. A thread can lock a mutex it
+/// already holds, and must unlock it the same number of times.
+#[defun]
+fn mutex_lock(mutex: Object) -> Result<bool> {
+    let id = mutex_id(mutex)?;
+    lock_mutex(SYNC.lock().unwrap(), id, 1)?;
+    Ok(false)
+}
+
+/// Release MUTEX, which must be held by the current thread.
+#[defun]
+fn mutex_unlock(mutex: Object) -> Result<bool> {
+    let id = mutex_id(mutex)?;
+    let mut state = SYNC.lock().unwrap();
+    ensure!(
+        state.mutexes[&id].owner == Some(thread::current().id()),
+        "Cannot unlock {} not held by this thread",
+        mutex_display(&state, id)
+    );
+    let lock = state.mutexes.get_mut(&id).unwrap();
+    lock.count -= 1;
+    if lock.count == 0 {
+        lock.owner = None;
+        SYNC_CHANGED.notify_all();
+    }
+    Ok(false)
+}
+
+/// Create a condition variable associated with MUTEX. The optional NAME is
+/// for debugging.
+#[defun]
+fn make_condition_variable<'ob>(
+    mutex: Object,
+    name: Option<&str>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let mutex = mutex_id(mutex)?;
+    let mut state = SYNC.lock().unwrap();
+    let id = state.add_id();
+    let name = name.map(ToOwned::to_owned);
+    crate::handle::retain_id(sym::RUNE_MUTEX, mutex);
+    state.conditions.insert(id, Condition { mutex, name, waiters: 0, notified: 0 });
+    Ok(sync_object(sym::RUNE_CONDITION_VARIABLE, id, cx))
+}
+
+#[defun]
+fn condition_variable_p(object: Object) -> bool {
+    condition_id(object).is_ok()
+}
+
+#[defun]
+fn condition_name(cond: Object) -> Result<Option<String>> {
+    let id = condition_id(cond)?;
+    Ok(SYNC.lock().unwrap().conditions[&id].name.clone())
+}
+
+/// Return the mutex associated with COND.
+#[defun]
+fn condition_mutex<'ob>(cond: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let id = condition_id(cond)?;
+    let mutex = SYNC.lock().unwrap().conditions[&id].mutex;
+    Ok(sync_object(sym::RUNE_MUTEX, mutex, cx))
+}
+
+/// Wait for COND to be notified. The associated mutex must be held by the
+/// current thread. It is released while waiting and reacquired before
+/// returning.
+#[defun]
+fn condition_wait(cond: Object) -> Result<bool> {
+    let id = condition_id(cond)?;
+    let thread = thread::current().id();
+    let mut state = SYNC.lock().unwrap();
+    let mutex_id = state.conditions[&id].mutex;
+    ensure!(
+        state.mutexes[&mutex_id].owner == Some(thread),
+        "Condition variable's {} is not held by this thread",
+        mutex_display(&state, mutex_id)
+    );
+    let mutex = state.mutexes.get_mut(&mutex_id).unwrap();
+    let count = std::mem::take(&mut mutex.count);
+    mutex.owner = None;
+    state.conditions.get_mut(&id).unwrap().waiters += 1;
+    SYNC_CHANGED.notify_all();
+    let mut state = SYNC_CHANGED.wait_while(state, |s| s.conditions[&id].notified == 0).unwrap();
+    let cond = state.conditions.get_mut(&id).unwrap();
+    cond.notified -= 1;
+    cond.waiters -= 1;
+    lock_mutex(state, mutex_id, count)?;
+    Ok(false)
+}
+
+/// Wake one thread waiting on COND, or all of them if ALL is non-nil. The
+/// associated mutex must be held by the current thread.
+#[defun]
+fn condition_notify(cond: Object, all: OptionalFlag) -> Result<bool> {
+    let id = condition_id(cond)?;
+    let mut state = SYNC.lock().unwrap();
+    let mutex_id = state.conditions[&id].mutex;
+    ensure!(
+        state.mutexes[&mutex_id].owner == Some(thread::current().id()),
+        "Condition variable's {} is not held by this thread",
+        mutex_display(&state, mutex_id)
+    );
+    let cond = state.conditions.get_mut(&id).unwrap();
+    let sleeping = cond.waiters - cond.notified;
+    cond.notified += if all.is_some() { sleeping } else { sleeping.min(1) };
+    SYNC_CHANGED.notify_all();
+    Ok(false)
+}
+
+defsym!(RUNE_MUTEX);
+defsym!(RUNE_CONDITION_VARIABLE);
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rune_core::macros::list;
 
     #[test]
     fn test_go() {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
         let obj = cx.add("test string");
-        go_internal(obj).join().unwrap();
+        go_internal(obj, Permissions::default()).join().unwrap();
     }
 
     #[test]
@@ -45,9 +315,9 @@ mod tests {
         let roots = &RootSet::default();
         let cx = &mut Context::new(roots);
         let threads = [
-            go_internal(crate::reader::read("(if nil 1 2 3)", cx).unwrap().0),
-            go_internal(crate::reader::read("(progn (defvar foo 1) foo)", cx).unwrap().0),
-            go_internal(crate::reader::read("(progn (defvar foo 1) (makunbound 'foo) (let ((fn #'(lambda () (defvar foo 3))) (foo 7)) (funcall fn)) foo)", cx).unwrap().0),
+            go_internal(crate::reader::read("(if nil 1 2 3)", cx).unwrap().0, Permissions::default()),
+            go_internal(crate::reader::read("(progn (defvar foo 1) foo)", cx).unwrap().0, Permissions::default()),
+            go_internal(crate::reader::read("(progn (defvar foo 1) (makunbound 'foo) (let ((fn #'(lambda () (defvar foo 3))) (foo 7)) (funcall fn)) foo)", cx).unwrap().0, Permissions::default()),
         ];
         for thread in threads {
             thread.join().unwrap();
@@ -60,6 +330,63 @@ mod tests {
         println!("hello main thread");
         let cx = &mut Context::new(roots);
         let obj = crate::reader::read("(message \"hello from thread\")", cx).unwrap().0;
-        go_internal(obj).join().unwrap();
+        go_internal(obj, Permissions::default()).join().unwrap();
+    }
+
+    #[test]
+    fn test_mutex() {
+        crate::interpreter::assert_lisp(
+            "(let ((m (make-mutex \"m\")))
+               (mutex-lock m) (mutex-lock m) (mutex-unlock m) (mutex-unlock m)
+               (list (mutex-name m) (mutexp m) (condition-case nil (mutex-unlock m) (error 'unowned))))",
+            "(\"m\" t unowned)",
+        );
+    }
+
+    #[test]
+    fn test_condition_variable() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let mutex = make_mutex(None, cx);
+        let cond = make_condition_variable(mutex, None, cx).unwrap();
+        mutex_lock(mutex).unwrap();
+        let notify = list![
+            sym::PROGN,
+            list![sym::MUTEX_LOCK, mutex; cx],
+            list![sym::CONDITION_NOTIFY, cond; cx],
+            list![sym::MUTEX_UNLOCK, mutex; cx];
+            cx
+        ];
+        // the thread can only notify once the mutex is released by waiting
+        let thread = go_internal(notify, Permissions::default());
+        condition_wait(cond).unwrap();
+        mutex_unlock(mutex).unwrap();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_deadlock() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let first = make_mutex(None, cx);
+        let second = make_mutex(None, cx);
+        mutex_lock(first).unwrap();
+        let form = list![
+            sym::PROGN,
+            list![sym::MUTEX_LOCK, second; cx],
+            list![sym::MUTEX_LOCK, first; cx],
+            list![sym::MUTEX_UNLOCK, first; cx],
+            list![sym::MUTEX_UNLOCK, second; cx];
+            cx
+        ];
+        let thread = go_internal(form, Permissions::default());
+        let first_id = mutex_id(first).unwrap();
+        while !SYNC.lock().unwrap().waiting.values().any(|&x| x == first_id) {
+            thread::yield_now();
+        }
+        assert!(mutex_lock(second).is_err());
+        mutex_unlock(first).unwrap();
+        thread.join().unwrap();
     }
 }
@@ -6,20 +6,21 @@ use crate::core::{
 };
 use rune_core::macros::list;
 use rune_macros::defun;
-use std::time::SystemTime;
+use std::time::Duration;
 
 defvar!(CURRENT_TIME_LIST, true);
 
 #[defun]
-fn current_time<'ob>(cx: &'ob Context, env: &Rt<Env>) -> Object<'ob> {
+pub(crate) fn current_time<'ob>(cx: &'ob Context, env: &Rt<Env>) -> Object<'ob> {
     assert!(
         env.vars.get(sym::CURRENT_TIME_LIST).unwrap() == &sym::TRUE,
         "current-time-list is nil"
     );
-    let duration = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .expect("System time is before the epoch");
+    timestamp(crate::replay::now(), cx)
+}
 
+/// Return `duration` since the epoch as a list (HIGH LOW USEC PSEC).
+pub(crate) fn timestamp(duration: Duration, cx: &Context) -> Object {
     let secs = duration.as_secs();
     let micros = duration.subsec_micros();
     let low = secs & 0xffff;
@@ -0,0 +1,403 @@
+//! Timers that call a function after a delay.
+//!
+//! A timer is a record `#s(timer TIME REPEAT FUNCTION ARGS IDLE)`. For an
+//! ordinary timer TIME is when it is next due in seconds since the epoch, and
+//! IDLE is nil. An idle timer is due once rune has been waiting for input for
+//! IDLE seconds, and runs at most once in each idle period; its TIME is the
+//! start of the idle period it last ran in. A timer is active while it is in
+//! `timer-list' or `timer-idle-list'. Timers only run while rune is waiting,
+//! in `sleep-for' and while the event loop waits for input, never in the
+//! middle of evaluating other code.
+use crate::core::{
+    cons::Cons,
+    env::{sym, ArgSlice, CallFrame, Env},
+    gc::{Context, Rt},
+    object::{Function, Object, ObjectType, RecordBuilder, Symbol, NIL},
+};
+use crate::fns::slice_into_list;
+use anyhow::{bail, Result};
+use rune_core::macros::root;
+use rune_macros::defun;
+use std::time::Duration;
+
+/// The parts of a timer record.
+struct Timer<'ob> {
+    time: Option<f64>,
+    repeat: Option<f64>,
+    function: Object<'ob>,
+    args: Object<'ob>,
+    idle: Option<f64>,
+}
+
+impl Timer<'_> {
+    /// Return when the timer is due, given the start of the current idle
+    /// period. Idle timers are never due while rune is busy.
+    fn due(&self, idle_since: Option<f64>) -> Option<f64> {
+        match self.idle {
+            None => self.time,
+            Some(secs) => {
+                let since = idle_since?;
+                // it already ran in this idle period
+                if self.time == Some(since) {
+                    return None;
+                }
+                Some(since + secs)
+            }
+        }
+    }
+}
+
+fn float(obj: Object) -> Option<f64> {
+    match obj.untag() {
+        ObjectType::Float(x) => Some(**x),
+        _ => None,
+    }
+}
+
+fn timer_parts(timer: Object) -> Option<Timer> {
+    let ObjectType::Record(record) = timer.untag() else { return None };
+    let [tag, time, repeat, function, args, idle] = &record[..] else { return None };
+    if tag.get() != sym::TIMER {
+        return None;
+    }
+    let idle = float(idle.get());
+    let time = float(time.get());
+    if time.is_none() && idle.is_none() {
+        return None;
+    }
+    Some(Timer {
+        time,
+        repeat: float(repeat.get()),
+        function: function.get(),
+        args: args.get(),
+        idle,
+    })
+}
+
+fn now() -> f64 {
+    crate::replay::now().as_secs_f64()
+}
+
+fn idle_since() -> Option<f64> {
+    crate::keyboard::idle_since().map(|x| x.as_secs_f64())
+}
+
+fn seconds(obj: Object, name: &str) -> Result<f64> {
+    match obj.untag() {
+        ObjectType::NIL => Ok(0.0),
+        ObjectType::Int(x) => Ok(x as f64),
+        ObjectType::Float(x) => Ok(**x),
+        _ => bail!("Invalid {name}: {obj}"),
+    }
+}
+
+fn timer_list<'ob>(var: Symbol, env: &Rt<Env>, cx: &'ob Context) -> Result<Vec<Object<'ob>>> {
+    let list = env.vars.get(var).map_or(NIL, |x| x.bind(cx));
+    Ok(list.as_list()?.collect::<Result<_, _>>()?)
+}
+
+/// Return the active timers, not including idle timers.
+pub(crate) fn timers<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Result<Vec<Object<'ob>>> {
+    timer_list(sym::TIMER_LIST, env, cx)
+}
+
+/// Return all the active timers, including idle timers.
+fn all_timers<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Result<Vec<Object<'ob>>> {
+    let mut timers = timers(env, cx)?;
+    timers.extend(timer_list(sym::TIMER_IDLE_LIST, env, cx)?);
+    Ok(timers)
+}
+
+fn make_timer<'ob>(
+    time: Object<'ob>,
+    repeat: Option<f64>,
+    function: Object<'ob>,
+    args: Object<'ob>,
+    idle: Object<'ob>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let mut record = cx.vec_with_capacity(6);
+    record.push(sym::TIMER.into());
+    record.push(time);
+    record.push(repeat.filter(|x| *x > 0.0).map_or(NIL, |x| cx.add(x)));
+    record.push(function);
+    record.push(args);
+    record.push(idle);
+    cx.add(RecordBuilder(record))
+}
+
+fn push_timer<'ob>(
+    var: Symbol,
+    timer: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<()> {
+    let list = env.vars.get(var).map_or(NIL, |x| x.bind(cx));
+    env.set_var(var, Cons::new(timer, list, cx).into(), cx)
+}
+
+/// Return the function called by `timer`.
+pub(crate) fn timer_function(timer: Object) -> Option<Object> {
+    Some(timer_parts(timer)?.function)
+}
+
+/// Schedule a call to `function` with the list `args` after `delay` seconds,
+/// and every `repeat` seconds after that. Returns the timer.
+pub(crate) fn add_timer<'ob>(
+    delay: f64,
+    repeat: Option<f64>,
+    function: Object<'ob>,
+    args: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let time = cx.add(now() + delay.max(0.0));
+    let timer = make_timer(time, repeat, function, args, NIL, cx);
+    push_timer(sym::TIMER_LIST, timer, env, cx)?;
+    Ok(timer)
+}
+
+/// Schedule a call to `function` with the list `args` once rune has been idle
+/// for `secs` seconds, and in every idle period after that if `repeat` is
+/// true. Returns the timer.
+pub(crate) fn add_idle_timer<'ob>(
+    secs: f64,
+    repeat: bool,
+    function: Object<'ob>,
+    args: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    // the repeat interval of an idle timer is only used as a flag
+    let repeat = repeat.then_some(1.0);
+    let timer = make_timer(NIL, repeat, function, args, cx.add(secs.max(0.0)), cx);
+    push_timer(sym::TIMER_IDLE_LIST, timer, env, cx)?;
+    Ok(timer)
+}
+
+/// Deactivate `timer`. Returns true if it was active.
+pub(crate) fn remove_timer(timer: Object, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    let mut removed = false;
+    for var in [sym::TIMER_LIST, sym::TIMER_IDLE_LIST] {
+        let mut list = timer_list(var, env, cx)?;
+        let len = list.len();
+        list.retain(|x| *x != timer);
+        if list.len() != len {
+            env.set_var(var, slice_into_list(&list, None, cx), cx)?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Call FUNCTION with ARGS after TIME seconds, and then every REPEAT
+/// seconds if REPEAT is non-nil. TIME may be nil to run as soon as
+/// possible. Returns a timer that can be passed to `cancel-timer'.
+#[defun]
+fn run_at_time<'ob>(
+    time: Object<'ob>,
+    repeat: Object<'ob>,
+    function: Object<'ob>,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let delay = seconds(time, "time")?;
+    let repeat = if repeat.is_nil() { None } else { Some(seconds(repeat, "repeat")?) };
+    let args = slice_into_list(Rt::bind_slice(env.stack.arg_slice(args), cx), None, cx);
+    add_timer(delay, repeat, function, args, env, cx)
+}
+
+/// Call FUNCTION with ARGS after SECS seconds, and then every REPEAT seconds
+/// if REPEAT is non-nil. This is the same as `run-at-time' with a relative
+/// time.
+#[defun]
+fn run_with_timer<'ob>(
+    secs: Object<'ob>,
+    repeat: Object<'ob>,
+    function: Object<'ob>,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    run_at_time(secs, repeat, function, args, env, cx)
+}
+
+/// Call FUNCTION with ARGS once rune has been idle for SECS seconds. If
+/// REPEAT is non-nil, call it again each time rune has been idle for SECS
+/// seconds, otherwise only once. Idle time starts when rune begins waiting
+/// for input. Returns a timer that can be passed to `cancel-timer'.
+#[defun]
+fn run_with_idle_timer<'ob>(
+    secs: Object<'ob>,
+    repeat: Object<'ob>,
+    function: Object<'ob>,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let secs = seconds(secs, "idle time")?;
+    let args = slice_into_list(Rt::bind_slice(env.stack.arg_slice(args), cx), None, cx);
+    add_idle_timer(secs, !repeat.is_nil(), function, args, env, cx)
+}
+
+/// Stop TIMER from running. Returns t if it was still active.
+#[defun]
+fn cancel_timer(timer: Object, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    if timer_parts(timer).is_none() {
+        bail!("Not a timer: {timer}");
+    }
+    remove_timer(timer, env, cx)
+}
+
+/// Return t if OBJECT is a timer.
+#[defun]
+fn timerp(object: Object) -> bool {
+    timer_parts(object).is_some()
+}
+
+/// Return the next timer that is due, and schedule it again if it repeats.
+/// Otherwise it is no longer active.
+fn next_due<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<Option<Object<'ob>>> {
+    let now = now();
+    let idle_since = idle_since();
+    let due = all_timers(env, cx)?
+        .into_iter()
+        .filter_map(|x| Some((x, timer_parts(x)?.due(idle_since)?)))
+        .filter(|(_, due)| *due <= now)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    let Some((timer, due)) = due else { return Ok(None) };
+    let Some(parts) = timer_parts(timer) else { unreachable!() };
+    let next = match (parts.repeat, parts.idle) {
+        (None, _) => None,
+        // run again in the next idle period
+        (Some(_), Some(_)) => idle_since,
+        // don't try to catch up on runs that were missed
+        (Some(repeat), None) => Some((due + repeat).max(now)),
+    };
+    match next {
+        Some(next) => {
+            let ObjectType::Record(record) = timer.untag() else { unreachable!() };
+            record.try_mut()?[1].set(cx.add(next));
+        }
+        None => {
+            remove_timer(timer, env, cx)?;
+        }
+    }
+    Ok(Some(timer))
+}
+
+/// Return the time until the next timer is due.
+fn time_until_next(env: &Rt<Env>, cx: &Context) -> Result<Option<Duration>> {
+    let now = now();
+    let idle_since = idle_since();
+    let next = all_timers(env, cx)?
+        .into_iter()
+        .filter_map(|x| timer_parts(x)?.due(idle_since))
+        .min_by(f64::total_cmp);
+    Ok(next.map(|x| Duration::from_secs_f64((x - now).max(0.0))))
+}
+
+/// Run the timers that are due. An error in a timer is reported and does not
+/// stop the others. Returns the time until the next timer is due.
+pub(crate) fn run_timers(env: &mut Rt<Env>, cx: &mut Context) -> Result<Option<Duration>> {
+    while let Some(timer) = next_due(env, cx)? {
+        let Some(Timer { function, args, .. }) = timer_parts(timer) else { unreachable!() };
+        let function: Function = function.try_into()?;
+        root!(function, cx);
+        root!(args, cx);
+        let result = {
+            let frame = &mut CallFrame::new(env);
+            for arg in args.bind(cx).as_list()? {
+                frame.push_arg(arg?);
+            }
+            function.call(frame, None, cx)
+        };
+        if let Err(e) = result {
+            let message = format!("Error running timer: {e}");
+            crate::editfns::show_message(&message, env, cx)?;
+        }
+    }
+    time_until_next(env, cx)
+}
+
+/// Wait for SECONDS plus MILLISECONDS milliseconds, running timers as they
+/// become due.
+#[defun]
+fn sleep_for(
+    seconds: f64,
+    milliseconds: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let secs = seconds + milliseconds.unwrap_or(0) as f64 / 1000.0;
+    let deadline = crate::replay::now() + Duration::try_from_secs_f64(secs.max(0.0))?;
+    loop {
+        let next = run_timers(env, cx)?;
+        let remaining = deadline.saturating_sub(crate::replay::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        crate::replay::sleep(next.map_or(remaining, |x| x.min(remaining)));
+    }
+}
+
+defsym!(TIMER);
+defvar!(TIMER_LIST);
+defvar!(TIMER_IDLE_LIST);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+    use crate::replay::with_test_clock;
+
+    #[test]
+    fn test_timers() {
+        with_test_clock(|| {
+            assert_lisp(
+                "(let* ((runs nil)
+                        (once (run-at-time 0 nil (lambda (x) (setq runs (cons x runs))) 'once))
+                        (repeat (run-with-timer 0.01 0.01 (lambda () (setq runs (cons 'repeat runs)))))
+                        (never (run-at-time 10 nil (lambda () (setq runs (cons 'never runs))))))
+                   (sleep-for 0.035)
+                   (list (timerp once)
+                         (cancel-timer repeat)
+                         (cancel-timer once)
+                         (cancel-timer never)
+                         runs
+                         timer-list))",
+                "(t t nil t (repeat repeat repeat once) nil)",
+            );
+        });
+    }
+
+    #[test]
+    fn test_idle_timers() {
+        with_test_clock(|| {
+            assert_lisp(
+                "(let* ((ran nil))
+                   (run-with-idle-timer 0 nil (lambda () (setq ran t)))
+                   (sleep-for 0.01)
+                   (list (current-idle-time) ran (length timer-idle-list)))",
+                "(nil nil 1)",
+            );
+            crate::keyboard::start_idle();
+            assert_lisp(
+                "(let* ((runs nil)
+                        (once (run-with-idle-timer 0 nil (lambda () (setq runs (cons 'once runs)))))
+                        (repeat (run-with-idle-timer 0.01 t (lambda () (setq runs (cons 'repeat runs)))))
+                        (later (run-with-idle-timer 10 nil (lambda () (setq runs (cons 'later runs))))))
+                   (sleep-for 0.5)
+                   (list (current-idle-time)
+                         (nreverse runs)
+                         (timerp repeat)
+                         (cancel-timer repeat)
+                         (cancel-timer once)
+                         (cancel-timer later)
+                         timer-idle-list))",
+                "((0 0 500000 0) (once repeat) t t nil t nil)",
+            );
+            crate::keyboard::stop_idle();
+        });
+    }
+}
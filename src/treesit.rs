@@ -0,0 +1,906 @@
+//! Tree-sitter parsers for buffers.
+//!
+//! A parser is a record of the form (treesit-parser ID) that owns the parse
+//! tree of a buffer. Nodes are `treesit-node' objects that refer back to the
+//! tree of their parser. Whenever a parser's tree is requested, the buffer is
+//! compared against the text that was last parsed and the tree is updated
+//! incrementally from the changed region. Nodes from an earlier tree are then
+//! outdated and signal an error when used.
+//!
+//! Grammars are loaded from the shared libraries libtree-sitter-LANG in
+//! `treesit-extra-load-path' or the system library path, if the permissions
+//! of the caller allow it. These functions are
+//! only available when rune is built with the `tree-sitter` feature;
+//! otherwise they signal an error.
+use crate::core::{
+    cons::Cons,
+    env::{intern, sym, Env},
+    gc::{Context, Rt},
+    object::{
+        Gc, LispBuffer, LispTreesitNode, List, Object, ObjectType, RecordBuilder, Symbol,
+        TreesitNode, NIL,
+    },
+};
+use crate::fns::slice_into_list;
+use crate::permissions::{check, Capability};
+use anyhow::{anyhow, bail, Result};
+use rune_macros::defun;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// A move from one node to another.
+#[cfg_attr(not(feature = "tree-sitter"), allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+enum Step<'a> {
+    Parent,
+    /// The Nth child, counting from the end if N is negative.
+    Child(i64, bool),
+    NextSibling(bool),
+    PrevSibling(bool),
+    Field(&'a str),
+}
+
+/// The properties of a node. Positions are buffer positions.
+#[cfg_attr(not(feature = "tree-sitter"), allow(dead_code))]
+struct NodeInfo {
+    kind: String,
+    start: usize,
+    end: usize,
+    named: bool,
+    missing: bool,
+    extra: bool,
+    has_error: bool,
+    field: Option<&'static str>,
+}
+
+#[cfg(feature = "tree-sitter")]
+mod backend {
+    use super::{Captures, NodeInfo, Step};
+    use anyhow::{anyhow, Context as _, Result};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{LazyLock, Mutex};
+    use streaming_iterator::StreamingIterator;
+    use tree_sitter::{InputEdit, Language, LanguageFn, Node, Point, Query, QueryCursor, Tree};
+
+    static LANGUAGES: LazyLock<Mutex<HashMap<String, Language>>> = LazyLock::new(Default::default);
+
+    /// Make `language` available under `name` without loading a library.
+    #[cfg(test)]
+    pub(super) fn register_language(name: &str, language: Language) {
+        LANGUAGES.lock().unwrap().insert(name.to_owned(), language);
+    }
+
+    /// Find the grammar for `name`, loading it from the first of `dirs` that
+    /// has it, or from the system library path.
+    fn language(name: &str, dirs: &[PathBuf]) -> Result<Language> {
+        let mut languages = LANGUAGES.lock().unwrap();
+        if let Some(language) = languages.get(name) {
+            return Ok(language.clone());
+        }
+        let file = format!("libtree-sitter-{name}{}", std::env::consts::DLL_SUFFIX);
+        let library = dirs
+            .iter()
+            .map(|dir| dir.join(&file))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| PathBuf::from(&file));
+        let symbol = format!("tree_sitter_{}", name.replace('-', "_"));
+        let language = unsafe {
+            let library = libloading::Library::new(&library)
+                .with_context(|| format!("Cannot load grammar for {name}"))?;
+            let func: libloading::Symbol<unsafe extern "C" fn() -> *const ()> =
+                library.get(symbol.as_bytes())?;
+            let language = Language::new(LanguageFn::from_raw(*func));
+            // the language points into the library, so it can never be unloaded
+            std::mem::forget(library);
+            language
+        };
+        languages.insert(name.to_owned(), language.clone());
+        Ok(language)
+    }
+
+    pub(super) fn language_available(name: &str, dirs: &[PathBuf]) -> bool {
+        language(name, dirs).is_ok()
+    }
+
+    pub(super) struct Parser {
+        parser: tree_sitter::Parser,
+        language: Language,
+        tree: Tree,
+        /// The text the tree was parsed from.
+        text: String,
+        generation: u64,
+    }
+
+    /// Return the row and byte column of `byte` in `text`.
+    fn point(text: &str, byte: usize) -> Point {
+        let before = &text[..byte];
+        let row = before.bytes().filter(|x| *x == b'\n').count();
+        let column = before.rfind('\n').map_or(byte, |x| byte - x - 1);
+        Point { row, column }
+    }
+
+    /// Return the children of `node`, paired with their index.
+    fn children(node: Node<'_>) -> Vec<(usize, Node<'_>)> {
+        let mut cursor = node.walk();
+        node.children(&mut cursor).enumerate().collect()
+    }
+
+    /// Return the path of child indices from the root to `node`.
+    fn path_of(node: Node<'_>) -> Vec<u32> {
+        let mut path = Vec::new();
+        let mut node = node;
+        while let Some(parent) = node.parent() {
+            let index = children(parent).into_iter().find(|(_, x)| x.id() == node.id());
+            path.push(index.expect("node missing from parent").0 as u32);
+            node = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    impl Parser {
+        pub(super) fn new(name: &str, dirs: &[PathBuf], text: String) -> Result<Self> {
+            let language = language(name, dirs)?;
+            let mut parser = tree_sitter::Parser::new();
+            parser.set_language(&language)?;
+            let tree = parser.parse(&text, None).ok_or_else(|| anyhow!("Parsing failed"))?;
+            Ok(Self { parser, language, tree, text, generation: 0 })
+        }
+
+        pub(super) fn generation(&self) -> u64 {
+            self.generation
+        }
+
+        /// Update the tree to match `text`, reusing the parts of the old
+        /// tree outside of the changed region.
+        pub(super) fn sync(&mut self, text: String) -> Result<()> {
+            if text == self.text {
+                return Ok(());
+            }
+            let old = &self.text;
+            let mut prefix = old.bytes().zip(text.bytes()).take_while(|(a, b)| a == b).count();
+            while !old.is_char_boundary(prefix) {
+                prefix -= 1;
+            }
+            let max_suffix = old.len().min(text.len()) - prefix;
+            let mut suffix = old
+                .bytes()
+                .rev()
+                .zip(text.bytes().rev())
+                .take(max_suffix)
+                .take_while(|(a, b)| a == b)
+                .count();
+            while !old.is_char_boundary(old.len() - suffix) {
+                suffix -= 1;
+            }
+            let (old_end, new_end) = (old.len() - suffix, text.len() - suffix);
+            self.tree.edit(&InputEdit {
+                start_byte: prefix,
+                old_end_byte: old_end,
+                new_end_byte: new_end,
+                start_position: point(old, prefix),
+                old_end_position: point(old, old_end),
+                new_end_position: point(&text, new_end),
+            });
+            let tree = self.parser.parse(&text, Some(&self.tree));
+            self.tree = tree.ok_or_else(|| anyhow!("Parsing failed"))?;
+            self.text = text;
+            self.generation += 1;
+            Ok(())
+        }
+
+        fn resolve(&self, path: &[u32]) -> Option<Node<'_>> {
+            let mut node = self.tree.root_node();
+            for &index in path {
+                node = node.child(index as usize)?;
+            }
+            Some(node)
+        }
+
+        fn position(&self, byte: usize) -> usize {
+            self.text[..byte].chars().count() + 1
+        }
+
+        pub(super) fn info(&self, path: &[u32]) -> Option<NodeInfo> {
+            let node = self.resolve(path)?;
+            let field = match path.split_last() {
+                Some((index, parent)) => self.resolve(parent)?.field_name_for_child(*index),
+                None => None,
+            };
+            Some(NodeInfo {
+                kind: node.kind().to_owned(),
+                start: self.position(node.start_byte()),
+                end: self.position(node.end_byte()),
+                named: node.is_named(),
+                missing: node.is_missing(),
+                extra: node.is_extra(),
+                has_error: node.has_error(),
+                field,
+            })
+        }
+
+        pub(super) fn text(&self, path: &[u32]) -> Option<String> {
+            let node = self.resolve(path)?;
+            Some(self.text[node.byte_range()].to_owned())
+        }
+
+        pub(super) fn sexp(&self, path: &[u32]) -> Option<String> {
+            Some(self.resolve(path)?.to_sexp())
+        }
+
+        pub(super) fn children(&self, path: &[u32], named: bool) -> Option<Vec<Vec<u32>>> {
+            let node = self.resolve(path)?;
+            let children = children(node).into_iter().filter(|(_, x)| !named || x.is_named());
+            let child_path = |index: usize| [path, &[index as u32]].concat();
+            Some(children.map(|(index, _)| child_path(index)).collect())
+        }
+
+        pub(super) fn step(&self, path: &[u32], step: Step<'_>) -> Option<Vec<u32>> {
+            let node = self.resolve(path)?;
+            match step {
+                Step::Parent => path.split_last().map(|(_, parent)| parent.to_vec()),
+                Step::Child(n, named) => {
+                    let children = self.children(path, named)?;
+                    let index = if n < 0 { children.len() as i64 + n } else { n };
+                    children.into_iter().nth(usize::try_from(index).ok()?)
+                }
+                Step::NextSibling(named) | Step::PrevSibling(named) => {
+                    let (&index, parent) = path.split_last()?;
+                    let siblings = children(node.parent()?);
+                    let wanted = |x: &&(usize, Node<'_>)| !named || x.1.is_named();
+                    let sibling = match step {
+                        Step::NextSibling(_) => {
+                            siblings.iter().skip(index as usize + 1).find(wanted)
+                        }
+                        _ => siblings.iter().take(index as usize).rev().find(wanted),
+                    };
+                    sibling.map(|(index, _)| [parent, &[*index as u32]].concat())
+                }
+                Step::Field(name) => {
+                    let (index, _) = children(node).into_iter().find(|(index, _)| {
+                        node.field_name_for_child(*index as u32) == Some(name)
+                    })?;
+                    Some([path, &[index as u32]].concat())
+                }
+            }
+        }
+
+        /// Return the leaf node covering `position`, or the first leaf after
+        /// it if it is between nodes.
+        pub(super) fn node_at(&self, position: usize, named: bool) -> Option<Vec<u32>> {
+            let byte = self.text.char_indices().nth(position - 1).map_or(self.text.len(), |x| x.0);
+            let mut node = self.tree.root_node();
+            if node.end_byte() <= byte {
+                return None;
+            }
+            let mut path = Vec::new();
+            while let Some((index, child)) = children(node)
+                .into_iter()
+                .find(|(_, x)| x.end_byte() > byte && (!named || x.is_named()))
+            {
+                path.push(index as u32);
+                node = child;
+            }
+            Some(path)
+        }
+
+        /// Return the name and node of each capture of `query` in the node at
+        /// `path`, limited to the buffer positions `range`.
+        pub(super) fn query(
+            &self,
+            path: &[u32],
+            source: &str,
+            range: Option<(usize, usize)>,
+        ) -> Result<Option<Captures>> {
+            let Some(node) = self.resolve(path) else { return Ok(None) };
+            let query = Query::new(&self.language, source)?;
+            let mut cursor = QueryCursor::new();
+            if let Some((start, end)) = range {
+                let byte =
+                    |x: usize| self.text.char_indices().nth(x - 1).map_or(self.text.len(), |x| x.0);
+                cursor.set_byte_range(byte(start)..byte(end));
+            }
+            let names = query.capture_names();
+            let mut captures = cursor.captures(&query, node, self.text.as_bytes());
+            let mut result = Vec::new();
+            while let Some((found, index)) = captures.next() {
+                let capture = found.captures[*index];
+                let name = names[capture.index as usize].to_owned();
+                result.push((name, path_of(capture.node)));
+            }
+            Ok(Some(result))
+        }
+    }
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+mod backend {
+    use super::{Captures, NodeInfo, Step};
+    use anyhow::{bail, Result};
+    use std::convert::Infallible;
+    use std::path::PathBuf;
+
+    const DISABLED: &str =
+        "Tree-sitter support is not available; rebuild with the `tree-sitter` feature";
+
+    pub(super) fn language_available(_name: &str, _dirs: &[PathBuf]) -> bool {
+        false
+    }
+
+    /// A parser can never be created without tree-sitter.
+    pub(super) struct Parser(Infallible);
+
+    impl Parser {
+        pub(super) fn new(_name: &str, _dirs: &[PathBuf], _text: String) -> Result<Self> {
+            bail!(DISABLED)
+        }
+
+        pub(super) fn generation(&self) -> u64 {
+            match self.0 {}
+        }
+
+        pub(super) fn sync(&mut self, _text: String) -> Result<()> {
+            match self.0 {}
+        }
+
+        pub(super) fn info(&self, _path: &[u32]) -> Option<NodeInfo> {
+            match self.0 {}
+        }
+
+        pub(super) fn text(&self, _path: &[u32]) -> Option<String> {
+            match self.0 {}
+        }
+
+        pub(super) fn sexp(&self, _path: &[u32]) -> Option<String> {
+            match self.0 {}
+        }
+
+        pub(super) fn children(&self, _path: &[u32], _named: bool) -> Option<Vec<Vec<u32>>> {
+            match self.0 {}
+        }
+
+        pub(super) fn step(&self, _path: &[u32], _step: Step<'_>) -> Option<Vec<u32>> {
+            match self.0 {}
+        }
+
+        pub(super) fn node_at(&self, _position: usize, _named: bool) -> Option<Vec<u32>> {
+            match self.0 {}
+        }
+
+        pub(super) fn query(
+            &self,
+            _path: &[u32],
+            _source: &str,
+            _range: Option<(usize, usize)>,
+        ) -> Result<Option<Captures>> {
+            match self.0 {}
+        }
+    }
+}
+
+type Captures = Vec<(String, Vec<u32>)>;
+
+struct Entry {
+    language: String,
+    buffer: &'static LispBuffer,
+    parser: backend::Parser,
+}
+
+static PARSERS: LazyLock<Mutex<HashMap<i64, Entry>>> = LazyLock::new(Default::default);
+static NEXT_PARSER: AtomicI64 = AtomicI64::new(0);
+
+fn parser_record(id: i64, cx: &Context) -> Object {
+    let mut record = cx.vec_with_capacity(2);
+    record.push(sym::TREESIT_PARSER.into());
+    record.push(cx.add(id));
+    cx.add(RecordBuilder(record))
+}
+
+fn parser_id(parser: Object) -> Result<i64> {
+    if let ObjectType::Record(record) = parser.untag() {
+        if record.len() == 2 && record[0].get() == sym::TREESIT_PARSER {
+            if let ObjectType::Int(id) = record[1].get().untag() {
+                return Ok(id);
+            }
+        }
+    }
+    bail!("Not a tree-sitter parser: {parser}")
+}
+
+fn static_buffer(buffer: Gc<&LispBuffer>) -> &'static LispBuffer {
+    let ObjectType::Buffer(buffer) = Object::from(buffer).untag() else { unreachable!() };
+    buffer
+}
+
+/// The directories to load grammars from. Signals `permission-denied' if
+/// grammars may not be loaded.
+fn load_path(env: &mut Rt<Env>, cx: &Context) -> Result<Vec<std::path::PathBuf>> {
+    check(Capability::Load, env, cx)?;
+    let dirs = match env.vars.get(sym::TREESIT_EXTRA_LOAD_PATH) {
+        Some(dirs) => dirs.bind(cx),
+        None => NIL,
+    };
+    List::try_from(dirs)?
+        .elements()
+        .map(|x| Ok(<&str>::try_from(x?)?.into()))
+        .collect()
+}
+
+/// Bring the tree of parser `id` up to date with its buffer, and return its
+/// generation.
+fn sync(id: i64, env: &Rt<Env>) -> Result<u64> {
+    let buffer = match PARSERS.lock().unwrap().get(&id) {
+        Some(entry) => entry.buffer,
+        None => bail!("Tree-sitter parser was deleted"),
+    };
+    let text = env.with_buffer(buffer, |x| x.text.to_string())?;
+    let mut parsers = PARSERS.lock().unwrap();
+    let entry = parsers.get_mut(&id).ok_or_else(|| anyhow!("Tree-sitter parser was deleted"))?;
+    entry.parser.sync(text)?;
+    Ok(entry.parser.generation())
+}
+
+/// Call `func` with the parser of `node` and the path to it, signaling an
+/// error if the node is outdated.
+fn with_node<T>(
+    node: &LispTreesitNode,
+    func: impl FnOnce(&backend::Parser, &[u32]) -> Option<T>,
+) -> Result<T> {
+    let parsers = PARSERS.lock().unwrap();
+    let result = match parsers.get(&node.parser()) {
+        Some(entry) if entry.parser.generation() == node.generation() => {
+            func(&entry.parser, node.path())
+        }
+        _ => None,
+    };
+    drop(parsers);
+    result.ok_or_else(|| anyhow!("Tree-sitter node is outdated: {node}"))
+}
+
+/// Describe the node for printing, or return `None` if it is outdated.
+pub(crate) fn describe_node(parser: i64, generation: u64, path: &[u32]) -> Option<String> {
+    // the node may be printed while the parsers are locked
+    let parsers = PARSERS.try_lock().ok()?;
+    let entry = parsers.get(&parser).filter(|x| x.parser.generation() == generation)?;
+    let info = entry.parser.info(path)?;
+    Some(format!("{} in {}-{}", info.kind, info.start, info.end))
+}
+
+fn node_object(parser: i64, generation: u64, path: Option<Vec<u32>>, cx: &Context) -> Object {
+    cx.add(path.map(|path| TreesitNode { parser, generation, path }))
+}
+
+/// Return non-nil if tree-sitter support is built in.
+#[defun]
+fn treesit_available_p() -> bool {
+    cfg!(feature = "tree-sitter")
+}
+
+/// Return non-nil if the grammar for LANGUAGE can be loaded.
+#[defun]
+fn treesit_language_available_p(language: Symbol, env: &mut Rt<Env>, cx: &Context) -> Result<bool> {
+    Ok(backend::language_available(language.name(), &load_path(env, cx)?))
+}
+
+/// Create a parser for LANGUAGE in BUFFER, which defaults to the current
+/// buffer, and return it. If BUFFER already has a parser for LANGUAGE, that
+/// parser is returned instead, unless NO-REUSE is non-nil.
+#[defun]
+fn treesit_parser_create<'ob>(
+    language: Symbol,
+    buffer: Option<Gc<&LispBuffer>>,
+    no_reuse: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let buffer = match buffer {
+        Some(buffer) => static_buffer(buffer),
+        None => static_buffer(cx.add(env.current_buffer.get().lisp_buffer(cx))),
+    };
+    let language = language.name().to_owned();
+    if no_reuse.is_none_or(|x| x.is_nil()) {
+        let parsers = PARSERS.lock().unwrap();
+        let existing = parsers
+            .iter()
+            .filter(|(_, x)| x.buffer == buffer && x.language == language)
+            .map(|(id, _)| *id)
+            .min();
+        if let Some(id) = existing {
+            return Ok(parser_record(id, cx));
+        }
+    }
+    let text = env.with_buffer(buffer, |x| x.text.to_string())?;
+    let parser = backend::Parser::new(&language, &load_path(env, cx)?, text)?;
+    let id = NEXT_PARSER.fetch_add(1, Ordering::Relaxed);
+    PARSERS.lock().unwrap().insert(id, Entry { language, buffer, parser });
+    Ok(parser_record(id, cx))
+}
+
+/// Return non-nil if OBJECT is a tree-sitter parser.
+#[defun]
+fn treesit_parser_p(object: Object) -> bool {
+    parser_id(object).is_ok()
+}
+
+fn with_entry<T>(parser: Object, func: impl FnOnce(&Entry) -> T) -> Result<T> {
+    let id = parser_id(parser)?;
+    let parsers = PARSERS.lock().unwrap();
+    let entry = parsers.get(&id).ok_or_else(|| anyhow!("Tree-sitter parser was deleted"))?;
+    Ok(func(entry))
+}
+
+/// Return the buffer of PARSER.
+#[defun]
+fn treesit_parser_buffer<'ob>(parser: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let buffer = with_entry(parser, |x| x.buffer)?;
+    Ok(cx.add(buffer))
+}
+
+/// Return the language of PARSER.
+#[defun]
+fn treesit_parser_language<'ob>(parser: Object, cx: &'ob Context) -> Result<Symbol<'ob>> {
+    let language = with_entry(parser, |x| x.language.clone())?;
+    Ok(intern(&language, cx))
+}
+
+/// Return the parsers of BUFFER, which defaults to the current buffer, in the
+/// order they were created. If LANGUAGE is non-nil, only return the parsers
+/// for that language.
+#[defun]
+fn treesit_parser_list<'ob>(
+    buffer: Option<Gc<&LispBuffer>>,
+    language: Option<Symbol>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let buffer = match buffer {
+        Some(buffer) => static_buffer(buffer),
+        None => static_buffer(cx.add(env.current_buffer.get().lisp_buffer(cx))),
+    };
+    let mut ids: Vec<i64> = PARSERS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, x)| x.buffer == buffer && language.is_none_or(|l| x.language == l.name()))
+        .map(|(id, _)| *id)
+        .collect();
+    ids.sort_unstable();
+    let parsers: Vec<_> = ids.into_iter().map(|id| parser_record(id, cx)).collect();
+    slice_into_list(&parsers, None, cx)
+}
+
+/// Delete PARSER. Its nodes become outdated.
+#[defun]
+fn treesit_parser_delete(parser: Object) -> Result<bool> {
+    let id = parser_id(parser)?;
+    PARSERS.lock().unwrap().remove(&id);
+    Ok(false)
+}
+
+/// Return the root node of PARSER, reparsing its buffer if it has changed.
+#[defun]
+fn treesit_parser_root_node<'ob>(
+    parser: Object,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let id = parser_id(parser)?;
+    let generation = sync(id, env)?;
+    Ok(node_object(id, generation, Some(Vec::new()), cx))
+}
+
+/// Return the leaf node at POS. If POS is between nodes, return the first
+/// leaf after it. PARSER-OR-LANG is a parser or a language whose first
+/// parser in the current buffer is used; it defaults to the first parser of
+/// the current buffer. If NAMED is non-nil, only named nodes are considered.
+#[defun]
+fn treesit_node_at<'ob>(
+    pos: usize,
+    parser_or_lang: Option<Object>,
+    named: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let id = match parser_or_lang {
+        Some(parser) if !matches!(parser.untag(), ObjectType::Symbol(_)) => parser_id(parser)?,
+        _ => {
+            let language = parser_or_lang.map(Symbol::try_from).transpose()?;
+            let parsers = treesit_parser_list(None, language, env, cx);
+            match List::try_from(parsers)?.elements().next() {
+                Some(parser) => parser_id(parser?)?,
+                None => bail!("No tree-sitter parser in the current buffer"),
+            }
+        }
+    };
+    let generation = sync(id, env)?;
+    let named = named.is_some_and(|x| !x.is_nil());
+    let path = match PARSERS.lock().unwrap().get(&id) {
+        Some(entry) => entry.parser.node_at(pos.max(1), named),
+        None => bail!("Tree-sitter parser was deleted"),
+    };
+    Ok(node_object(id, generation, path, cx))
+}
+
+/// Return non-nil if OBJECT is a tree-sitter node.
+#[defun]
+fn treesit_node_p(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::TreesitNode(_))
+}
+
+/// Return the parser that NODE belongs to.
+#[defun]
+fn treesit_node_parser<'ob>(node: &LispTreesitNode, cx: &'ob Context) -> Object<'ob> {
+    parser_record(node.parser(), cx)
+}
+
+/// Return the type of NODE as a string. Anonymous nodes are named by their
+/// text.
+#[defun]
+fn treesit_node_type(node: &LispTreesitNode) -> Result<String> {
+    with_node(node, |parser, path| Some(parser.info(path)?.kind))
+}
+
+/// Return the position where NODE starts.
+#[defun]
+fn treesit_node_start(node: &LispTreesitNode) -> Result<usize> {
+    with_node(node, |parser, path| Some(parser.info(path)?.start))
+}
+
+/// Return the position where NODE ends.
+#[defun]
+fn treesit_node_end(node: &LispTreesitNode) -> Result<usize> {
+    with_node(node, |parser, path| Some(parser.info(path)?.end))
+}
+
+/// Return the text covered by NODE.
+#[defun]
+fn treesit_node_text(node: &LispTreesitNode) -> Result<String> {
+    with_node(node, backend::Parser::text)
+}
+
+/// Return NODE and its descendants as an s-expression string.
+#[defun]
+fn treesit_node_string(node: &LispTreesitNode) -> Result<String> {
+    with_node(node, backend::Parser::sexp)
+}
+
+/// Return the field name of NODE in its parent, or nil if it has none.
+#[defun]
+fn treesit_node_field_name(node: &LispTreesitNode) -> Result<Option<&'static str>> {
+    with_node(node, |parser, path| Some(parser.info(path)?.field))
+}
+
+/// Return non-nil if NODE has PROPERTY, which is one of `named', `missing',
+/// `extra', `has-error' or `outdated'.
+#[defun]
+fn treesit_node_check(node: &LispTreesitNode, property: Symbol) -> Result<bool> {
+    if property == sym::OUTDATED {
+        return Ok(with_node(node, |_, _| Some(())).is_err());
+    }
+    let info = with_node(node, |parser, path| parser.info(path))?;
+    Ok(match property {
+        sym::NAMED => info.named,
+        sym::MISSING => info.missing,
+        sym::EXTRA => info.extra,
+        sym::HAS_ERROR => info.has_error,
+        _ => bail!("Invalid node property: {property}"),
+    })
+}
+
+/// Return non-nil if NODE1 and NODE2 are the same node.
+#[defun]
+fn treesit_node_eq(node1: &LispTreesitNode, node2: &LispTreesitNode) -> bool {
+    node1 == node2
+}
+
+fn step<'ob>(node: &LispTreesitNode, step: Step, cx: &'ob Context) -> Result<Object<'ob>> {
+    let path = with_node(node, |parser, path| Some(parser.step(path, step)))?;
+    Ok(node_object(node.parser(), node.generation(), path, cx))
+}
+
+/// Return the parent of NODE, or nil if it is the root.
+#[defun]
+fn treesit_node_parent<'ob>(node: &LispTreesitNode, cx: &'ob Context) -> Result<Object<'ob>> {
+    step(node, Step::Parent, cx)
+}
+
+/// Return the Nth child of NODE, counting from the end if N is negative. If
+/// NAMED is non-nil, only named children are counted.
+#[defun]
+fn treesit_node_child<'ob>(
+    node: &LispTreesitNode,
+    n: i64,
+    named: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    step(node, Step::Child(n, named.is_some_and(|x| !x.is_nil())), cx)
+}
+
+/// Return the child of NODE in the field named FIELD-NAME, or nil.
+#[defun]
+fn treesit_node_child_by_field_name<'ob>(
+    node: &LispTreesitNode,
+    field_name: &str,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    step(node, Step::Field(field_name), cx)
+}
+
+/// Return the sibling after NODE, or nil. If NAMED is non-nil, skip
+/// anonymous siblings.
+#[defun]
+fn treesit_node_next_sibling<'ob>(
+    node: &LispTreesitNode,
+    named: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    step(node, Step::NextSibling(named.is_some_and(|x| !x.is_nil())), cx)
+}
+
+/// Return the sibling before NODE, or nil. If NAMED is non-nil, skip
+/// anonymous siblings.
+#[defun]
+fn treesit_node_prev_sibling<'ob>(
+    node: &LispTreesitNode,
+    named: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    step(node, Step::PrevSibling(named.is_some_and(|x| !x.is_nil())), cx)
+}
+
+/// Return the children of NODE. If NAMED is non-nil, only return the named
+/// children.
+#[defun]
+fn treesit_node_children<'ob>(
+    node: &LispTreesitNode,
+    named: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let named = named.is_some_and(|x| !x.is_nil());
+    let paths = with_node(node, |parser, path| parser.children(path, named))?;
+    let (id, generation) = (node.parser(), node.generation());
+    let children: Vec<_> = paths
+        .into_iter()
+        .map(|path| node_object(id, generation, Some(path), cx))
+        .collect();
+    Ok(slice_into_list(&children, None, cx))
+}
+
+/// Return the number of children of NODE. If NAMED is non-nil, only count
+/// the named children.
+#[defun]
+fn treesit_node_child_count(node: &LispTreesitNode, named: Option<Object>) -> Result<usize> {
+    let named = named.is_some_and(|x| !x.is_nil());
+    with_node(node, |parser, path| Some(parser.children(path, named)?.len()))
+}
+
+/// Match QUERY against NODE and return the captures as a list of
+/// (CAPTURE-NAME . NODE). NODE may also be a parser, in which case its root
+/// node is used. QUERY is a string in tree-sitter query syntax. If BEG and
+/// END are non-nil, only captures in that range are returned. If NODE-ONLY
+/// is non-nil, return only the nodes.
+#[defun]
+fn treesit_query_capture<'ob>(
+    node: Object<'ob>,
+    query: &str,
+    beg: Option<usize>,
+    end: Option<usize>,
+    node_only: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let node: Gc<&LispTreesitNode> = match node.untag() {
+        ObjectType::TreesitNode(_) => node.try_into()?,
+        _ => treesit_parser_root_node(node, env, cx)?.try_into()?,
+    };
+    let node = node.untag();
+    let range = beg.zip(end);
+    let parsers = PARSERS.lock().unwrap();
+    let captures = match parsers.get(&node.parser()) {
+        Some(entry) if entry.parser.generation() == node.generation() => {
+            entry.parser.query(node.path(), query, range)?
+        }
+        _ => None,
+    };
+    drop(parsers);
+    let captures = captures.ok_or_else(|| anyhow!("Tree-sitter node is outdated: {node}"))?;
+    let node_only = node_only.is_some_and(|x| !x.is_nil());
+    let (id, generation) = (node.parser(), node.generation());
+    let captures: Vec<_> = captures
+        .into_iter()
+        .map(|(name, path)| {
+            let node = node_object(id, generation, Some(path), cx);
+            if node_only {
+                node
+            } else {
+                let name = intern(&name, cx);
+                Cons::new(name, node, cx).into()
+            }
+        })
+        .collect();
+    Ok(slice_into_list(&captures, None, cx))
+}
+
+defvar!(TREESIT_EXTRA_LOAD_PATH);
+defsym!(TREESIT_PARSER);
+defsym!(TREESIT_NODE);
+defsym!(NAMED);
+defsym!(EXTRA);
+defsym!(HAS_ERROR);
+defsym!(OUTDATED);
+
+#[cfg(all(test, feature = "tree-sitter"))]
+mod test {
+    use crate::interpreter::assert_lisp_with_vars;
+
+    fn register_json() {
+        super::backend::register_language("json", tree_sitter_json::LANGUAGE.into());
+    }
+
+    #[test]
+    fn test_navigation() {
+        register_json();
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"treesit-nav\"))
+               (insert \"{\\\"a\\\": [1, 2]}\")
+               (let* ((root (treesit-parser-root-node (treesit-parser-create 'json)))
+                      (object (treesit-node-child root 0))
+                      (pair (treesit-node-child object 0 t))
+                      (array (treesit-node-child-by-field-name pair \"value\")))
+                 (list (treesit-node-type root)
+                       (treesit-node-type pair)
+                       (treesit-node-text array)
+                       (treesit-node-start array) (treesit-node-end array)
+                       (treesit-node-field-name array)
+                       (mapcar #'treesit-node-text (treesit-node-children array t))
+                       (treesit-node-text (treesit-node-next-sibling (treesit-node-child array 0 t) t))
+                       (treesit-node-type (treesit-node-prev-sibling array))
+                       (treesit-node-eq (treesit-node-parent pair) object)
+                       (treesit-node-child-count array))))",
+            "(\"document\" \"pair\" \"[1, 2]\" 7 13 \"value\" (\"1\" \"2\") \"2\" \":\" t 5)",
+        );
+    }
+
+    #[test]
+    fn test_node_at_and_query() {
+        register_json();
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"treesit-query\"))
+               (insert \"[1, \\\"x\\\", 3]\")
+               (let ((parser (treesit-parser-create 'json)))
+                 (list (treesit-node-text (treesit-node-at 2))
+                       (treesit-node-text (treesit-node-at 4 'json t))
+                       (length (treesit-parser-list))
+                       (mapcar (lambda (x) (cons (car x) (treesit-node-text (cdr x))))
+                               (treesit-query-capture parser \"(number) @num (string) @str\"))
+                       (mapcar #'treesit-node-text
+                               (treesit-query-capture parser \"(number) @num\" 5 11 t)))))",
+            "(\"1\" \"x\" 1 ((num . \"1\") (str . \"\\\"x\\\"\") (num . \"3\")) (\"3\"))",
+        );
+    }
+
+    #[test]
+    fn test_incremental_update() {
+        register_json();
+        assert_lisp_with_vars(
+            "(progn
+               (set-buffer (get-buffer-create \"treesit-edit\"))
+               (insert \"[1, 2]\")
+               (let* ((parser (treesit-parser-create 'json))
+                      (old (treesit-node-at 5)))
+                 (goto-char 6)
+                 (insert \", 30\")
+                 (let ((new (treesit-node-at 8 parser)))
+                   (list (treesit-node-check old 'outdated)
+                         (condition-case nil (treesit-node-text old) (error 'outdated))
+                         (treesit-node-text new)
+                         (treesit-node-check new 'named)
+                         (treesit-node-string (treesit-parser-root-node parser))))))",
+            "(t outdated \"30\" t \"(document (array (number) (number) (number)))\")",
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Git primitives for the version control functions.
+//!
+//! These query the repository directly through libgit2 instead of running
+//! git and parsing its output. They are only available when rune is built
+//! with the `git` feature; otherwise they signal an error.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Object, Symbol, NIL},
+};
+use crate::fileio::expand_file_name;
+use crate::fns::slice_into_list;
+use anyhow::Result;
+use rune_core::macros::list;
+use rune_macros::defun;
+use std::path::PathBuf;
+
+/// The version control state of a file, named as in `vc-state'.
+#[cfg_attr(not(feature = "git"), allow(dead_code))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    UpToDate,
+    Edited,
+    Added,
+    Removed,
+    Missing,
+    Ignored,
+    Unregistered,
+    Conflict,
+}
+
+impl State {
+    fn symbol(self) -> Symbol<'static> {
+        match self {
+            State::UpToDate => sym::UP_TO_DATE,
+            State::Edited => sym::EDITED,
+            State::Added => sym::ADDED,
+            State::Removed => sym::REMOVED,
+            State::Missing => sym::MISSING,
+            State::Ignored => sym::IGNORED,
+            State::Unregistered => sym::UNREGISTERED,
+            State::Conflict => sym::CONFLICT,
+        }
+    }
+}
+
+/// A run of lines that were last changed by the same commit.
+#[cfg_attr(not(feature = "git"), allow(dead_code))]
+struct BlameHunk {
+    start_line: usize,
+    lines: usize,
+    revision: String,
+    author: String,
+    time: i64,
+}
+
+#[cfg(feature = "git")]
+mod backend {
+    use super::{BlameHunk, State};
+    use anyhow::Result;
+    use git2::{ErrorCode, Repository, Status};
+    use std::path::{Path, PathBuf};
+
+    /// Open the repository containing `file`, and return it along with the
+    /// path of `file` relative to its working tree.
+    fn open(file: &Path) -> Result<Option<(Repository, PathBuf)>> {
+        let dir = if file.is_dir() { file } else { file.parent().unwrap_or(file) };
+        let repo = match Repository::discover(dir) {
+            Ok(repo) => repo,
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let Some(workdir) = repo.workdir() else { return Ok(None) };
+        let workdir = workdir.canonicalize()?;
+        // the file itself may have been deleted
+        let file = match file.canonicalize() {
+            Ok(file) => file,
+            Err(_) => match (file.parent(), file.file_name()) {
+                (Some(parent), Some(name)) => parent.canonicalize()?.join(name),
+                _ => return Ok(None),
+            },
+        };
+        let Ok(relative) = file.strip_prefix(&workdir) else { return Ok(None) };
+        let relative = relative.to_owned();
+        Ok(Some((repo, relative)))
+    }
+
+    pub(super) fn root(file: &Path) -> Result<Option<PathBuf>> {
+        let Some((repo, _)) = open(file)? else { return Ok(None) };
+        Ok(repo.workdir().map(Path::to_owned))
+    }
+
+    pub(super) fn state(file: &Path) -> Result<Option<State>> {
+        let Some((repo, relative)) = open(file)? else { return Ok(None) };
+        let status = match repo.status_file(&relative) {
+            Ok(status) => status,
+            // neither tracked nor present in the working tree
+            Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let state = if status.is_empty() {
+            State::UpToDate
+        } else if status.contains(Status::CONFLICTED) {
+            State::Conflict
+        } else if status.contains(Status::IGNORED) {
+            State::Ignored
+        } else if status.contains(Status::INDEX_NEW) {
+            State::Added
+        } else if status.contains(Status::WT_NEW) {
+            State::Unregistered
+        } else if status.contains(Status::INDEX_DELETED) {
+            State::Removed
+        } else if status.contains(Status::WT_DELETED) {
+            State::Missing
+        } else {
+            State::Edited
+        };
+        Ok(Some(state))
+    }
+
+    pub(super) fn working_revision(file: &Path) -> Result<Option<String>> {
+        let Some((repo, _)) = open(file)? else { return Ok(None) };
+        let head = match repo.head() {
+            Ok(head) => head,
+            // there are no commits yet
+            Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(head.peel_to_commit()?.id().to_string()))
+    }
+
+    pub(super) fn blame(file: &Path) -> Result<Option<Vec<BlameHunk>>> {
+        let Some((repo, relative)) = open(file)? else { return Ok(None) };
+        let blame = repo.blame_file(&relative, None)?;
+        let hunks = blame
+            .iter()
+            .map(|hunk| {
+                let author = hunk.final_signature();
+                BlameHunk {
+                    start_line: hunk.final_start_line(),
+                    lines: hunk.lines_in_hunk(),
+                    revision: hunk.final_commit_id().to_string(),
+                    author: author.name().unwrap_or_default().to_owned(),
+                    time: author.when().seconds(),
+                }
+            })
+            .collect();
+        Ok(Some(hunks))
+    }
+}
+
+#[cfg(not(feature = "git"))]
+mod backend {
+    use super::{BlameHunk, State};
+    use anyhow::{bail, Result};
+    use std::path::{Path, PathBuf};
+
+    const DISABLED: &str = "Git support is not available; rebuild with the `git` feature";
+
+    pub(super) fn root(_file: &Path) -> Result<Option<PathBuf>> {
+        bail!(DISABLED)
+    }
+
+    pub(super) fn state(_file: &Path) -> Result<Option<State>> {
+        bail!(DISABLED)
+    }
+
+    pub(super) fn working_revision(_file: &Path) -> Result<Option<String>> {
+        bail!(DISABLED)
+    }
+
+    pub(super) fn blame(_file: &Path) -> Result<Option<Vec<BlameHunk>>> {
+        bail!(DISABLED)
+    }
+}
+
+fn expand(file: &str, env: &Rt<Env>, cx: &Context) -> Result<PathBuf> {
+    Ok(PathBuf::from(expand_file_name(file, None, env, cx)?))
+}
+
+/// Return the top directory of the git working tree containing FILE, or nil
+/// if it is not in one.
+#[defun]
+fn vc_git_root(file: &str, env: &Rt<Env>, cx: &Context) -> Result<Option<String>> {
+    let root = backend::root(&expand(file, env, cx)?)?;
+    Ok(root.map(|root| {
+        let root = root.to_string_lossy().into_owned();
+        let separator = std::path::MAIN_SEPARATOR;
+        if root.ends_with(separator) {
+            root
+        } else {
+            format!("{root}{separator}")
+        }
+    }))
+}
+
+/// Return non-nil if FILE is tracked by git.
+#[defun]
+fn vc_git_registered(file: &str, env: &Rt<Env>, cx: &Context) -> Result<bool> {
+    let state = backend::state(&expand(file, env, cx)?)?;
+    Ok(state.is_some_and(|x| !matches!(x, State::Unregistered | State::Ignored)))
+}
+
+/// Return the version control state of FILE. This is one of the symbols
+/// `up-to-date', `edited', `added', `removed', `missing', `ignored',
+/// `unregistered' or `conflict', or nil if FILE is not in a git working tree.
+#[defun]
+fn vc_git_state(file: &str, env: &Rt<Env>, cx: &Context) -> Result<Option<Symbol<'static>>> {
+    Ok(backend::state(&expand(file, env, cx)?)?.map(State::symbol))
+}
+
+/// Return the id of the commit checked out in the working tree containing
+/// FILE, or nil if there is none.
+#[defun]
+fn vc_git_working_revision(file: &str, env: &Rt<Env>, cx: &Context) -> Result<Option<String>> {
+    backend::working_revision(&expand(file, env, cx)?)
+}
+
+/// Return the annotations of the committed contents of FILE, as a list of
+/// (START-LINE LINE-COUNT REVISION AUTHOR TIME), one for each run of lines
+/// that was last changed by the same commit. START-LINE counts from 1 and
+/// TIME is in seconds since the epoch. Return nil if FILE is not in a git
+/// working tree.
+#[defun]
+fn vc_git_annotate_data<'ob>(file: &str, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let Some(hunks) = backend::blame(&expand(file, env, cx)?)? else {
+        return Ok(NIL);
+    };
+    let hunks: Vec<_> = hunks
+        .into_iter()
+        .map(|x| list![x.start_line, x.lines, cx.add(x.revision), cx.add(x.author), x.time; cx])
+        .collect();
+    Ok(slice_into_list(&hunks, None, cx))
+}
+
+defsym!(UP_TO_DATE);
+defsym!(EDITED);
+defsym!(ADDED);
+defsym!(REMOVED);
+defsym!(MISSING);
+defsym!(IGNORED);
+defsym!(UNREGISTERED);
+defsym!(CONFLICT);
+
+#[cfg(all(test, feature = "git"))]
+mod test {
+    use super::*;
+    use git2::{Repository, Signature};
+    use std::fs;
+
+    fn commit_all(repo: &Repository, message: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let author = Signature::new("Ada", "ada@example.com", &git2::Time::new(1000, 0)).unwrap();
+        let parent = repo.head().ok().map(|x| x.peel_to_commit().unwrap());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &author, &author, message, &tree, &parents).unwrap();
+    }
+
+    #[test]
+    fn test_git_state() {
+        let dir = std::env::temp_dir().join(format!("rune-vc-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        let state = |name: &str| backend::state(&dir.join(name)).unwrap();
+
+        fs::write(dir.join("tracked"), "one\ntwo\n").unwrap();
+        fs::write(dir.join("deleted"), "gone\n").unwrap();
+        assert_eq!(backend::working_revision(&dir).unwrap(), None);
+        commit_all(&repo, "first");
+        assert_eq!(state("tracked"), Some(State::UpToDate));
+
+        fs::write(dir.join("tracked"), "one\n2\n").unwrap();
+        fs::write(dir.join("new"), "new\n").unwrap();
+        fs::remove_file(dir.join("deleted")).unwrap();
+        assert_eq!(state("tracked"), Some(State::Edited));
+        assert_eq!(state("new"), Some(State::Unregistered));
+        assert_eq!(state("deleted"), Some(State::Missing));
+        assert_eq!(state("nonexistent"), None);
+
+        let root = backend::root(&dir.join("tracked")).unwrap().unwrap();
+        assert_eq!(root.canonicalize().unwrap(), dir.canonicalize().unwrap());
+        let head = repo.head().unwrap().peel_to_commit().unwrap().id().to_string();
+        assert_eq!(backend::working_revision(&dir).unwrap(), Some(head.clone()));
+
+        let blame = backend::blame(&dir.join("tracked")).unwrap().unwrap();
+        assert_eq!(blame.len(), 1);
+        assert_eq!((blame[0].start_line, blame[0].lines), (1, 2));
+        assert_eq!((blame[0].revision.as_str(), blame[0].author.as_str()), (&*head, "Ada"));
+        assert_eq!(blame[0].time, 1000);
+
+        let outside = std::env::temp_dir().join(format!("rune-vc-outside-{}", std::process::id()));
+        fs::create_dir_all(&outside).unwrap();
+        assert_eq!(backend::state(&outside.join("file")).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! Warning messages.
+//!
+//! Warnings are collected in the `*Warnings*` buffer. When running in batch
+//! mode there is no one to look at that buffer, so they are written to stderr
+//! instead.
+use crate::core::{
+    env::{sym, ArgSlice, Env},
+    gc::{Context, Rt, Rto},
+    object::{Object, ObjectType, Symbol},
+};
+use crate::insdel::{replace_region, with_current_buffer};
+use anyhow::Result;
+use rune_core::macros::root;
+use rune_macros::defun;
+
+const WARNINGS_BUFFER: &str = "*Warnings*";
+
+fn format_warning(type_: Object, message: &str, level: Option<Symbol>) -> String {
+    let label = match level {
+        Some(sym::KW_EMERGENCY) => "Emergency",
+        Some(sym::KW_ERROR) => "Error",
+        Some(sym::KW_DEBUG) => "Debug",
+        _ => "Warning",
+    };
+    // A list type is a hierarchy, and only the top level is shown
+    let type_ = match type_.untag() {
+        ObjectType::Cons(cons) => cons.car(),
+        _ => type_,
+    };
+    format!("{label} ({type_}): {message}")
+}
+
+/// Display a warning MESSAGE of TYPE with severity LEVEL. LEVEL is one of
+/// `:emergency', `:error', `:warning' (the default) or `:debug'. The warning
+/// is added to BUFFER-NAME, which defaults to `*Warnings*'.
+#[defun]
+pub(crate) fn display_warning(
+    type_: &Rto<Object>,
+    message: &str,
+    level: Option<Symbol>,
+    buffer_name: Option<&str>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let text = format_warning(type_.bind(cx), message, level);
+    add_warning(&text, buffer_name, env, cx)
+}
+
+/// Write the warning `text` to stderr in batch mode, and otherwise add it to
+/// the end of the buffer `buffer_name`.
+fn add_warning(
+    text: &str,
+    buffer_name: Option<&str>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let batch = env.vars.get(sym::NONINTERACTIVE).is_some_and(|x| !x.bind(cx).is_nil());
+    if batch {
+        eprintln!("{text}");
+        return Ok(());
+    }
+    let name = cx.add(buffer_name.unwrap_or(WARNINGS_BUFFER));
+    let ObjectType::Buffer(buffer) = crate::buffer::get_buffer_create(name, None, cx)?.untag()
+    else {
+        unreachable!("get-buffer-create did not return a buffer")
+    };
+    root!(buffer, cx);
+    with_current_buffer(buffer, env, cx, |env, cx| {
+        let end = env.current_buffer.get().text.len_chars();
+        env.current_buffer.get_mut().text.set_cursor(end);
+        replace_region(end + 1, end + 1, &format!("{text}\n"), env, cx)
+    })
+}
+
+/// Display a warning of type `emacs', formatting MESSAGE with ARGS like
+/// `format-message'.
+#[defun]
+fn warn(message: &str, args: ArgSlice, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let args = Rt::bind_slice(env.stack.arg_slice(args), cx);
+    let message = crate::editfns::format_message(message, args)?;
+    add_warning(&format_warning(sym::EMACS.into(), &message, None), None, env, cx)
+}
+
+/// Display a warning of TYPE and LEVEL, formatting MESSAGE with ARGS like
+/// `format-message'.
+#[defun]
+fn lwarn(
+    type_: &Rto<Object>,
+    level: Option<Symbol>,
+    message: &str,
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let args = Rt::bind_slice(env.stack.arg_slice(args), cx);
+    let message = crate::editfns::format_message(message, args)?;
+    add_warning(&format_warning(type_.bind(cx), &message, level), None, env, cx)
+}
+
+defsym!(EMACS);
+defsym!(KW_EMERGENCY);
+defsym!(KW_ERROR);
+defsym!(KW_DEBUG);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{env::intern, gc::RootSet, object::NIL};
+    use rune_core::macros::list;
+
+    #[test]
+    fn test_format_warning() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let foo = intern("foo", cx);
+        assert_eq!(format_warning(foo.into(), "bar", None), "Warning (foo): bar");
+        let hierarchy = list![foo, intern("baz", cx); cx];
+        assert_eq!(format_warning(hierarchy, "bar", Some(sym::KW_ERROR)), "Error (foo): bar");
+    }
+
+    #[test]
+    fn test_warnings_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        env.vars.insert(sym::NONINTERACTIVE, NIL);
+        let name = "*warnings-test*";
+        let emacs: Object = sym::EMACS.into();
+        root!(emacs, cx);
+        display_warning(emacs, "first", None, Some(name), env, cx).unwrap();
+        display_warning(emacs, "second", None, Some(name), env, cx).unwrap();
+        let ObjectType::Buffer(buffer) =
+            crate::buffer::get_buffer_create(cx.add(name), None, cx).unwrap().untag()
+        else {
+            unreachable!()
+        };
+        let text = env.with_buffer(buffer, |b| b.text.to_string()).unwrap();
+        assert_eq!(text, "Warning (emacs): first\nWarning (emacs): second\n");
+    }
+}
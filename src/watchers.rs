@@ -0,0 +1,182 @@
+//! Functions called when a variable changes.
+//!
+//! `add-variable-watcher' registers a function to be called as (WATCHER
+//! SYMBOL NEWVAL OPERATION WHERE) just before the value of SYMBOL changes.
+//! OPERATION is `set', `let', `unlet' or `makunbound', and WHERE is always nil
+//! because rune has no buffer-local variables yet. Like in Emacs, the
+//! watchers are kept in the `watchers' property of the symbol. The symbol is
+//! also flagged as trapped so that setting a variable without watchers only
+//! costs a check of that flag. A watcher can be any function, so Rust code can
+//! watch a variable by registering a subroutine defined with `#[defun]`.
+use crate::core::{
+    env::{sym, CallFrame, Env},
+    gc::{Context, Rt, Rto},
+    object::{Function, Object, Symbol, NIL},
+};
+use crate::data::LispError;
+use crate::rooted_iter;
+use anyhow::Result;
+use fallible_streaming_iterator::FallibleStreamingIterator;
+use rune_core::macros::root;
+use rune_macros::defun;
+
+fn watchers<'ob>(symbol: Symbol, env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    crate::data::get(symbol, sym::WATCHERS, env, cx)
+}
+
+/// Call the watchers of `symbol` with `newval` and `operation`.
+fn notify(
+    symbol: &Rto<Symbol>,
+    newval: &Rto<Object>,
+    operation: Symbol,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let functions = watchers(symbol.bind(cx), env, cx);
+    rooted_iter!(functions, functions, cx);
+    while let Some(function) = functions.next()? {
+        let function: &Rto<Function> = function.try_as()?;
+        let frame = &mut CallFrame::new(env);
+        frame.push_arg(symbol.bind(cx));
+        frame.push_arg(newval.bind(cx));
+        frame.push_arg(operation);
+        frame.push_arg(NIL);
+        function.call(frame, None, cx)?;
+    }
+    Ok(())
+}
+
+/// Set the global value of `symbol` to `value`, calling its watchers first.
+pub(crate) fn set(
+    symbol: &Rto<Symbol>,
+    value: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let var = symbol.bind(cx);
+    if var.is_trapped_write() && !var.is_const() {
+        notify(symbol, value, sym::SET, env, cx)?;
+    }
+    env.set_var(symbol.bind(cx), value.bind(cx), cx)
+}
+
+/// Dynamically bind `symbol` to `value`, calling its watchers first.
+pub(crate) fn varbind(
+    symbol: &Rto<Symbol>,
+    value: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    let var = symbol.bind(cx);
+    if var.is_const() {
+        return Err(LispError::setting_constant(var, cx).into());
+    }
+    if var.is_trapped_write() {
+        notify(symbol, value, sym::LET, env, cx)?;
+    }
+    env.varbind(symbol.bind(cx), value.bind(cx), cx);
+    Ok(())
+}
+
+/// Remove the last `count` dynamic bindings, calling the watchers of each
+/// variable with the value it goes back to. Every binding is removed even if
+/// a watcher signals an error, and the first error is returned.
+pub(crate) fn unbind(count: u16, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let mut result = Ok(());
+    for _ in 0..count {
+        if let Some((var, value)) = env.last_binding(cx).filter(|x| x.0.is_trapped_write()) {
+            let value = value.unwrap_or_default();
+            root!(var, cx);
+            root!(value, cx);
+            let notified = notify(var, value, sym::UNLET, env, cx);
+            result = result.and(notified);
+        }
+        env.unbind(1, cx);
+    }
+    result
+}
+
+/// Make `symbol` void, calling its watchers first.
+pub(crate) fn makunbound(symbol: &Rto<Symbol>, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let var = symbol.bind(cx);
+    if var.is_trapped_write() && !var.is_const() {
+        root!(value, NIL, cx);
+        notify(symbol, value, sym::MAKUNBOUND, env, cx)?;
+    }
+    env.vars.remove(symbol.bind(cx));
+    Ok(())
+}
+
+/// Call WATCH-FUNCTION before SYMBOL changes. It is called with four
+/// arguments: SYMBOL, the new value, the operation (`set', `let', `unlet' or
+/// `makunbound') and the buffer the change is local to, which is always nil.
+#[defun]
+fn add_variable_watcher(
+    symbol: Symbol,
+    watch_function: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let functions = watchers(symbol, env, cx);
+    if !crate::fns::member(watch_function, functions.try_into()?)?.is_nil() {
+        return Ok(());
+    }
+    let functions = crate::data::cons(watch_function, functions, cx);
+    crate::data::put(symbol, sym::WATCHERS, functions, env);
+    symbol.set_trapped_write(true);
+    Ok(())
+}
+
+/// Stop calling WATCH-FUNCTION when SYMBOL changes.
+#[defun]
+fn remove_variable_watcher(
+    symbol: Symbol,
+    watch_function: Object,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let functions: Vec<_> = watchers(symbol, env, cx)
+        .as_list()?
+        .filter(|x| !x.as_ref().is_ok_and(|x| crate::fns::equal(*x, watch_function)))
+        .collect::<Result<_, _>>()?;
+    if functions.is_empty() {
+        symbol.set_trapped_write(false);
+    }
+    let functions = crate::fns::slice_into_list(&functions, None, cx);
+    crate::data::put(symbol, sym::WATCHERS, functions, env);
+    Ok(())
+}
+
+/// Return the list of functions watching SYMBOL.
+#[defun]
+fn get_variable_watchers<'ob>(symbol: Symbol, env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    watchers(symbol, env, cx)
+}
+
+defsym!(WATCHERS);
+defsym!(UNLET);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_variable_watchers() {
+        assert_lisp(
+            "(progn
+               (defvar watch-log nil)
+               (defvar watched 1)
+               (fset 'watch-fn (lambda (sym val op where) (setq watch-log (cons (list sym val op where) watch-log))))
+               (add-variable-watcher 'watched 'watch-fn)
+               (add-variable-watcher 'watched 'watch-fn)
+               (setq watched 2)
+               (set 'watched 3)
+               (let ((watched 4)) nil)
+               (makunbound 'watched)
+               (remove-variable-watcher 'watched 'watch-fn)
+               (setq watched 5)
+               (list (get-variable-watchers 'watched) (nreverse watch-log)))",
+            "(nil ((watched 2 set nil) (watched 3 set nil) (watched 4 let nil) (watched 3 unlet nil) (watched nil makunbound nil)))",
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Standard directories for user files.
+//!
+//! These follow the XDG base directory specification on every platform, like
+//! Emacs' xdg.el. `user-emacs-directory' is `~/.rune.d/' if it exists,
+//! otherwise the `rune' directory under `xdg-config-home' if that exists,
+//! falling back to `~/.rune.d/'.
+use crate::core::{
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::ObjectType,
+};
+use crate::replay;
+use anyhow::{bail, Result};
+use rune_macros::defun;
+use std::path::{Path, PathBuf};
+
+/// Return the home directory of the current user.
+pub(crate) fn home_dir() -> Option<PathBuf> {
+    let home = replay::env_var("HOME").or_else(|| replay::env_var("USERPROFILE"))?;
+    Some(PathBuf::from(home))
+}
+
+/// Return the directory named by an XDG environment variable with `value`,
+/// or `default` under `home` if it is unset or not absolute, as the
+/// specification requires.
+fn dir_home(value: Option<String>, home: Option<&Path>, default: &str) -> Option<String> {
+    match value {
+        Some(dir) if Path::new(&dir).is_absolute() => Some(dir),
+        _ => Some(home?.join(default).to_string_lossy().into_owned()),
+    }
+}
+
+fn xdg_dir(var: &str, default: &str) -> Option<String> {
+    dir_home(replay::env_var(var), home_dir().as_deref(), default)
+}
+
+/// Return the base directory for user configuration files.
+#[defun]
+pub(crate) fn xdg_config_home() -> Option<String> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Return the base directory for user cache files.
+#[defun]
+fn xdg_cache_home() -> Option<String> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Return the base directory for user data files.
+#[defun]
+fn xdg_data_home() -> Option<String> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// Return the directory for user configuration, with a trailing slash.
+pub(crate) fn default_user_emacs_directory() -> String {
+    let legacy = home_dir().map(|home| home.join(".rune.d"));
+    let xdg = xdg_config_home().map(|config| PathBuf::from(config).join("rune"));
+    let dir = match (legacy, xdg) {
+        (Some(legacy), _) if legacy.is_dir() => legacy,
+        (_, Some(xdg)) if xdg.is_dir() => xdg,
+        (Some(legacy), _) => legacy,
+        (None, xdg) => xdg.unwrap_or_else(|| PathBuf::from(".rune.d")),
+    };
+    format!("{}{}", dir.to_string_lossy(), std::path::MAIN_SEPARATOR)
+}
+
+/// Return the absolute name of NEW-NAME in `user-emacs-directory'. If
+/// OLD-NAME is given and exists in the home directory, it is returned
+/// instead so that files from before the directory existed are still found.
+#[defun]
+pub(crate) fn locate_user_emacs_file(
+    new_name: &str,
+    old_name: Option<&str>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<String> {
+    if let (Some(old), Some(home)) = (old_name, home_dir()) {
+        let old = home.join(old);
+        if old.exists() {
+            return Ok(old.to_string_lossy().into_owned());
+        }
+    }
+    let dir = match env.vars.get(sym::USER_EMACS_DIRECTORY).map(|x| x.bind(cx).untag()) {
+        Some(ObjectType::String(dir)) => dir.to_string(),
+        _ => bail!("`user-emacs-directory' is not a directory name"),
+    };
+    // Like Emacs, create the directory so the file can be written
+    _ = std::fs::create_dir_all(&dir);
+    Ok(Path::new(&dir).join(new_name).to_string_lossy().into_owned())
+}
+
+defvar!(USER_EMACS_DIRECTORY, crate::xdg::default_user_emacs_directory());
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_dir_home() {
+        let home = Some(Path::new("/home/user"));
+        let dir = |value: Option<&str>| dir_home(value.map(String::from), home, ".config");
+        assert_eq!(dir(None).as_deref(), Some("/home/user/.config"));
+        assert_eq!(dir(Some("relative")).as_deref(), Some("/home/user/.config"));
+        assert_eq!(dir(Some("/etc/xdg")).as_deref(), Some("/etc/xdg"));
+        assert_eq!(dir_home(None, None, ".config"), None);
+    }
+
+    #[test]
+    fn test_locate_user_emacs_file() {
+        let dir = std::env::temp_dir().join(format!("rune-xdg-{}/", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        assert_lisp(
+            &format!("(let ((user-emacs-directory {dir:?})) (locate-user-emacs-file \"history\"))"),
+            &format!("{:?}", format!("{dir}history")),
+        );
+        assert!(Path::new(dir).is_dir());
+        std::fs::remove_dir(dir).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+//! Parsing XML and HTML into lists.
+//!
+//! Elements are returned in the usual DOM list format (TAG ATTRIBUTES
+//! . CHILDREN), where TAG is a symbol, ATTRIBUTES is an alist of
+//! (NAME . VALUE) with symbol names and string values, and CHILDREN are
+//! elements and strings. Text that is only whitespace is dropped.
+//!
+//! The HTML mode is tolerant of the usual HTML sloppiness: tag names are
+//! case insensitive, void elements like <br> need no end tag, attributes may
+//! be unquoted or have no value, unclosed elements are closed by the end of
+//! their parent, stray end tags are ignored, and the contents of <script>
+//! and <style> are kept as text.
+use crate::core::{
+    cons::Cons,
+    env::{intern, Env},
+    gc::{Context, Rt},
+    object::{Object, NIL},
+};
+use crate::fns::slice_into_list;
+use anyhow::{bail, Result};
+use quick_xml::escape::{resolve_predefined_entity, unescape_with};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rune_macros::defun;
+
+enum Node {
+    Element {
+        tag: String,
+        attrs: Vec<(String, String)>,
+        children: Vec<Node>,
+    },
+    Text(String),
+    Comment(String),
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Resolve the named entities that are common in HTML, in addition to the
+/// ones predefined by XML.
+fn resolve_html_entity(entity: &str) -> Option<&'static str> {
+    resolve_predefined_entity(entity).or(match entity {
+        "nbsp" => Some("\u{a0}"),
+        "copy" => Some("©"),
+        "reg" => Some("®"),
+        "trade" => Some("™"),
+        "mdash" => Some("—"),
+        "ndash" => Some("–"),
+        "hellip" => Some("…"),
+        "laquo" => Some("«"),
+        "raquo" => Some("»"),
+        "lsquo" => Some("‘"),
+        "rsquo" => Some("’"),
+        "ldquo" => Some("“"),
+        "rdquo" => Some("”"),
+        "middot" => Some("·"),
+        "bull" => Some("•"),
+        "times" => Some("×"),
+        "deg" => Some("°"),
+        _ => None,
+    })
+}
+
+/// Replace the entities in `raw`. In HTML, unknown entities and bare
+/// ampersands are kept as they are.
+fn unescape(raw: &[u8], html: bool) -> Result<String> {
+    let raw = String::from_utf8_lossy(raw);
+    if !html {
+        return Ok(unescape_with(&raw, resolve_predefined_entity)?.into_owned());
+    }
+    let mut text = String::new();
+    let mut rest = &*raw;
+    while let Some(start) = rest.find('&') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| &rest[..=end]);
+        match entity.and_then(|x| unescape_with(x, resolve_html_entity).ok()) {
+            Some(resolved) => {
+                text.push_str(&resolved);
+                rest = &rest[entity.unwrap().len()..];
+            }
+            None => {
+                text.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+fn name(raw: &[u8], html: bool) -> String {
+    let name = String::from_utf8_lossy(raw);
+    if html {
+        name.to_lowercase()
+    } else {
+        name.into_owned()
+    }
+}
+
+fn attributes(start: &BytesStart, html: bool) -> Result<Vec<(String, String)>> {
+    let mut attrs = Vec::new();
+    if html {
+        for attr in start.html_attributes().with_checks(false) {
+            let Ok(attr) = attr else { continue };
+            attrs.push((name(attr.key.as_ref(), html), unescape(&attr.value, html)?));
+        }
+    } else {
+        for attr in start.attributes() {
+            let attr = attr?;
+            attrs.push((name(attr.key.as_ref(), html), unescape(&attr.value, html)?));
+        }
+    }
+    Ok(attrs)
+}
+
+/// The elements that are still open, from the outermost.
+struct Stack {
+    top_level: Vec<Node>,
+    open: Vec<(String, Vec<(String, String)>, Vec<Node>)>,
+}
+
+impl Stack {
+    fn push(&mut self, node: Node) {
+        let children = match self.open.last_mut() {
+            Some((_, _, children)) => children,
+            None => &mut self.top_level,
+        };
+        // adjacent text, such as text around CDATA, is joined
+        match (children.last_mut(), node) {
+            (Some(Node::Text(text)), Node::Text(next)) => text.push_str(&next),
+            (_, node) => children.push(node),
+        }
+    }
+
+    fn close(&mut self) {
+        let (tag, attrs, children) = self.open.pop().unwrap();
+        self.push(Node::Element { tag, attrs, children });
+    }
+}
+
+fn parse(text: &str, html: bool, comments: bool) -> Result<Vec<Node>> {
+    let mut reader = Reader::from_str(text);
+    let config = reader.config_mut();
+    config.check_end_names = !html;
+    config.allow_unmatched_ends = html;
+    let mut stack = Stack { top_level: Vec::new(), open: Vec::new() };
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let tag = name(start.name().as_ref(), html);
+                let attrs = attributes(&start, html)?;
+                if html && VOID_ELEMENTS.contains(&tag.as_str()) {
+                    stack.push(Node::Element { tag, attrs, children: Vec::new() });
+                } else if html && RAW_TEXT_ELEMENTS.contains(&tag.as_str()) {
+                    let text = reader.read_text(start.name())?;
+                    let children = if text.trim().is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![Node::Text(text.into_owned())]
+                    };
+                    stack.push(Node::Element { tag, attrs, children });
+                } else {
+                    stack.open.push((tag, attrs, Vec::new()));
+                }
+            }
+            Event::Empty(start) => {
+                let tag = name(start.name().as_ref(), html);
+                let attrs = attributes(&start, html)?;
+                stack.push(Node::Element { tag, attrs, children: Vec::new() });
+            }
+            Event::End(end) => {
+                let tag = name(end.name().as_ref(), html);
+                // close any unclosed elements inside the one that ends here,
+                // and ignore end tags that match nothing
+                if let Some(index) = stack.open.iter().rposition(|x| x.0 == tag) {
+                    while stack.open.len() > index {
+                        stack.close();
+                    }
+                }
+            }
+            Event::Text(text) => {
+                let text = unescape(&text, html)?;
+                if !text.trim().is_empty() {
+                    stack.push(Node::Text(text));
+                }
+            }
+            Event::CData(text) => {
+                stack.push(Node::Text(String::from_utf8_lossy(&text).into_owned()));
+            }
+            Event::Comment(text) if comments => {
+                stack.push(Node::Comment(String::from_utf8_lossy(&text).into_owned()));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if let (false, Some((tag, ..))) = (html, stack.open.last()) {
+        bail!("Unclosed element <{tag}>");
+    }
+    while !stack.open.is_empty() {
+        stack.close();
+    }
+    Ok(stack.top_level)
+}
+
+fn element<'ob>(
+    tag: &str,
+    attrs: Object<'ob>,
+    children: &[Object<'ob>],
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let children = slice_into_list(children, None, cx);
+    Cons::new(intern(tag, cx), Cons::new(attrs, children, cx), cx).into()
+}
+
+fn to_list<'ob>(node: Node, cx: &'ob Context) -> Object<'ob> {
+    match node {
+        Node::Text(text) => cx.add(text),
+        Node::Comment(text) => element("comment", NIL, &[cx.add(text)], cx),
+        Node::Element { tag, attrs, children } => {
+            let attrs: Vec<Object> = attrs
+                .into_iter()
+                .map(|(name, value)| Cons::new(intern(&name, cx), value, cx).into())
+                .collect();
+            let attrs = slice_into_list(&attrs, None, cx);
+            let children: Vec<Object> = children.into_iter().map(|x| to_list(x, cx)).collect();
+            element(&tag, attrs, &children, cx)
+        }
+    }
+}
+
+fn nodes_to_list<'ob>(nodes: Vec<Node>, cx: &'ob Context) -> Object<'ob> {
+    let nodes: Vec<Object> = nodes.into_iter().map(|x| to_list(x, cx)).collect();
+    slice_into_list(&nodes, None, cx)
+}
+
+/// Return the text of the current buffer between BEG and END, which default
+/// to the whole buffer.
+fn region(beg: Option<usize>, end: Option<usize>, env: &Rt<Env>) -> Result<String> {
+    let buffer = env.current_buffer.get();
+    let beg = beg.unwrap_or(1);
+    let end = end.unwrap_or(buffer.text.len_chars() + 1);
+    let (beg, end) = if beg <= end { (beg, end) } else { (end, beg) };
+    let (before, after) = buffer.slice_with_gap(beg, end)?;
+    Ok(format!("{before}{after}"))
+}
+
+/// Parse STRING as XML and return the list of its top level elements. If
+/// HTML is non-nil, parse it as HTML instead, tolerating unclosed and
+/// unquoted markup. Comments are dropped.
+#[defun]
+fn xml_parse_string<'ob>(
+    string: &str,
+    html: Option<Object>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let html = html.is_some_and(|x| !x.is_nil());
+    Ok(nodes_to_list(parse(string, html, false)?, cx))
+}
+
+/// Parse the text of the current buffer between BEG and END as XML, like
+/// `xml-parse-string'. BEG and END default to the whole buffer.
+#[defun]
+fn xml_parse_region<'ob>(
+    beg: Option<usize>,
+    end: Option<usize>,
+    html: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let html = html.is_some_and(|x| !x.is_nil());
+    Ok(nodes_to_list(parse(&region(beg, end, env)?, html, false)?, cx))
+}
+
+/// Parse the region between BEG and END as XML and return its root element.
+/// Comments are returned as (comment nil TEXT) unless DISCARD-COMMENTS is
+/// non-nil. BASE-URL is ignored.
+#[defun]
+fn libxml_parse_xml_region<'ob>(
+    beg: Option<usize>,
+    end: Option<usize>,
+    _base_url: Option<Object>,
+    discard_comments: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let comments = discard_comments.is_none_or(|x| x.is_nil());
+    let nodes = parse(&region(beg, end, env)?, false, comments)?;
+    let root = nodes.into_iter().find(|x| matches!(x, Node::Element { .. }));
+    Ok(root.map_or(NIL, |x| to_list(x, cx)))
+}
+
+/// Parse the region between BEG and END as HTML and return an `html'
+/// element. Documents without an <html> element are wrapped in (html nil
+/// (body nil ...)). Comments are returned as (comment nil TEXT) unless
+/// DISCARD-COMMENTS is non-nil. BASE-URL is ignored.
+#[defun]
+fn libxml_parse_html_region<'ob>(
+    beg: Option<usize>,
+    end: Option<usize>,
+    _base_url: Option<Object>,
+    discard_comments: Option<Object>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let comments = discard_comments.is_none_or(|x| x.is_nil());
+    let mut nodes = parse(&region(beg, end, env)?, true, comments)?;
+    let is_html = |x: &Node| matches!(x, Node::Element { tag, .. } if tag == "html");
+    if let Some(index) = nodes.iter().position(is_html) {
+        return Ok(to_list(nodes.swap_remove(index), cx));
+    }
+    let children: Vec<Object> = nodes.into_iter().map(|x| to_list(x, cx)).collect();
+    let body = element("body", NIL, &children, cx);
+    Ok(element("html", NIL, &[body], cx))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::assert_lisp;
+
+    #[test]
+    fn test_xml() {
+        assert_lisp(
+            "(xml-parse-string \"<?xml version=\\\"1.0\\\"?>
+<root a=\\\"1 &amp; 2\\\"><!-- note --><item/>  <item id='x'>text &lt; <![CDATA[<raw>]]></item></root>\")",
+            "((root ((a . \"1 & 2\")) (item nil) (item ((id . \"x\")) \"text < <raw>\")))",
+        );
+        assert_lisp(
+            "(condition-case nil (xml-parse-string \"<a><b></a>\") (error 'failed))",
+            "failed",
+        );
+        assert_lisp("(condition-case nil (xml-parse-string \"<a>\") (error 'failed))", "failed");
+    }
+
+    #[test]
+    fn test_html() {
+        assert_lisp(
+            "(xml-parse-string \"<P CLASS=intro>one<br>two &nbsp;&copy; & more<li>x</ul><script>if (a < b) {}</script>\" t)",
+            "((p ((class . \"intro\")) \"one\" (br nil) \"two \u{a0}© & more\"
+               (li nil \"x\" (script nil \"if (a < b) {}\"))))",
+        );
+        assert_lisp(
+            "(progn
+               (set-buffer (get-buffer-create \"xml-html\"))
+               (insert \"<!-- c --><p>hi</p>\")
+               (list (libxml-parse-html-region (point-min) (point-max))
+                     (libxml-parse-html-region (point-min) (point-max) nil t)))",
+            "((html nil (body nil (comment nil \" c \") (p nil \"hi\")))
+              (html nil (body nil (p nil \"hi\"))))",
+        );
+        assert_lisp(
+            "(progn
+               (set-buffer (get-buffer-create \"xml-region\"))
+               (insert \"<a><b x=\\\"y\\\">z</b></a>\")
+               (libxml-parse-xml-region))",
+            "(a nil (b ((x . \"y\")) \"z\"))",
+        );
+    }
+}
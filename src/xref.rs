@@ -0,0 +1,391 @@
+//! Cross reference items and an identifier index.
+//!
+//! Xref items and locations are records, like the structs of xref.el:
+//!
+//! - (xref-item SUMMARY LOCATION)
+//! - (xref-file-location FILE LINE COLUMN)
+//! - (xref-buffer-location BUFFER POSITION)
+//! - (xref-bogus-location MESSAGE)
+//!
+//! An index records where each identifier occurs in a set of buffers.
+//! Identifiers are runs of letters, digits, `_' and `-' that start with a
+//! letter or `_', and an occurrence is a definition if the identifier
+//! before it on the same line is in `xref-definition-keywords'. Indexes are
+//! [handles](crate::handle) of the form (xref-index ID) that refer to the
+//! occurrences stored on the Rust side, and are not updated when the buffers
+//! change.
+use crate::core::{
+    cons::Cons,
+    env::{sym, Env},
+    gc::{Context, Rt},
+    object::{Gc, LispBuffer, List, Object, ObjectType, RecordBuilder, Symbol, NIL},
+};
+use crate::fns::slice_into_list;
+use anyhow::{anyhow, bail, Result};
+use rune_macros::defun;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+fn record<'ob>(tag: Symbol, fields: &[Object<'ob>], cx: &'ob Context) -> Object<'ob> {
+    let mut record = cx.vec_with_capacity(fields.len() + 1);
+    record.push(tag.into());
+    record.extend_from_slice(fields);
+    cx.add(RecordBuilder(record))
+}
+
+/// Return the fields of `object` if it is a record of type `tag` with `len`
+/// fields.
+fn fields<'ob>(object: Object<'ob>, tag: Symbol, len: usize) -> Option<Vec<Object<'ob>>> {
+    match object.untag() {
+        ObjectType::Record(record) if record.len() == len + 1 && record[0].get() == tag => {
+            Some(record.iter().skip(1).map(|x| x.get()).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Create and return a new xref item. SUMMARY is a short string to describe
+/// the item and LOCATION is an xref location.
+#[defun]
+fn xref_make<'ob>(summary: &str, location: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
+    Location::parse(location)?;
+    Ok(record(sym::XREF_ITEM, &[cx.add(summary), location], cx))
+}
+
+/// Create and return a new location for LINE and COLUMN in FILE. LINE
+/// counts from 1 and COLUMN from 0.
+#[defun]
+fn xref_make_file_location<'ob>(
+    file: &str,
+    line: usize,
+    column: usize,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    record(sym::XREF_FILE_LOCATION, &[cx.add(file), cx.add(line), cx.add(column)], cx)
+}
+
+/// Create and return a new location for POSITION in BUFFER.
+#[defun]
+fn xref_make_buffer_location<'ob>(
+    buffer: Gc<&LispBuffer>,
+    position: usize,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    record(sym::XREF_BUFFER_LOCATION, &[cx.add(buffer), cx.add(position)], cx)
+}
+
+/// Create and return a location that can't be visited, with MESSAGE
+/// explaining why.
+#[defun]
+fn xref_make_bogus_location<'ob>(message: &str, cx: &'ob Context) -> Object<'ob> {
+    record(sym::XREF_BOGUS_LOCATION, &[cx.add(message)], cx)
+}
+
+/// Return non-nil if OBJECT is an xref item.
+#[defun]
+fn xref_item_p(object: Object) -> bool {
+    fields(object, sym::XREF_ITEM, 2).is_some()
+}
+
+fn item_field(item: Object, index: usize) -> Result<Object> {
+    let fields = fields(item, sym::XREF_ITEM, 2).ok_or_else(|| anyhow!("Not an xref: {item}"))?;
+    Ok(fields[index])
+}
+
+/// Return the summary of the xref item ITEM.
+#[defun]
+fn xref_item_summary(item: Object) -> Result<Object> {
+    item_field(item, 0)
+}
+
+/// Return the location of the xref item ITEM.
+#[defun]
+fn xref_item_location(item: Object) -> Result<Object> {
+    item_field(item, 1)
+}
+
+enum Location<'ob> {
+    File {
+        file: Object<'ob>,
+        line: Object<'ob>,
+    },
+    Buffer {
+        buffer: &'ob LispBuffer,
+        position: usize,
+    },
+    Bogus,
+}
+
+impl<'ob> Location<'ob> {
+    fn parse(location: Object<'ob>) -> Result<Self> {
+        if let Some(fields) = fields(location, sym::XREF_FILE_LOCATION, 3) {
+            Ok(Location::File { file: fields[0], line: fields[1] })
+        } else if let Some(fields) = fields(location, sym::XREF_BUFFER_LOCATION, 2) {
+            let buffer: Gc<&LispBuffer> = fields[0].try_into()?;
+            Ok(Location::Buffer { buffer: buffer.untag(), position: fields[1].try_into()? })
+        } else if fields(location, sym::XREF_BOGUS_LOCATION, 1).is_some() {
+            Ok(Location::Bogus)
+        } else {
+            bail!("Not an xref location: {location}")
+        }
+    }
+}
+
+/// Return the group that LOCATION belongs to, which is its file name for a
+/// file location and its buffer name for a buffer location.
+#[defun]
+fn xref_location_group<'ob>(
+    location: Object<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    Ok(match Location::parse(location)? {
+        Location::File { file, .. } => file,
+        Location::Buffer { buffer, .. } => cx.add(env.with_buffer(buffer, |x| x.name.to_string())?),
+        Location::Bogus => cx.add("(No location)"),
+    })
+}
+
+/// Return the line number of LOCATION, or nil if it doesn't have one.
+#[defun]
+fn xref_location_line<'ob>(location: Object<'ob>, env: &Rt<Env>) -> Result<Object<'ob>> {
+    Ok(match Location::parse(location)? {
+        Location::File { line, .. } => line,
+        Location::Buffer { buffer, position } => {
+            let text = env.with_buffer(buffer, |x| x.text.to_string())?;
+            let before = text.chars().take(position.saturating_sub(1));
+            (before.filter(|&x| x == '\n').count() + 1).into()
+        }
+        Location::Bogus => NIL,
+    })
+}
+
+/// Group ITEMS by the group of their locations. Return an alist of
+/// (GROUP . ITEMS), with the groups and the items in each group in the
+/// order they first appear.
+#[defun]
+fn xref_group_items<'ob>(items: List<'ob>, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let mut groups: Vec<(String, Vec<Object>)> = Vec::new();
+    for item in items.elements() {
+        let item = item?;
+        let group = xref_location_group(xref_item_location(item)?, env, cx)?;
+        let group: &str = group.try_into()?;
+        match groups.iter_mut().find(|(name, _)| name == group) {
+            Some((_, items)) => items.push(item),
+            None => groups.push((group.to_owned(), vec![item])),
+        }
+    }
+    let groups: Vec<Object> = groups
+        .into_iter()
+        .map(|(name, items)| Cons::new(name, slice_into_list(&items, None, cx), cx).into())
+        .collect();
+    Ok(slice_into_list(&groups, None, cx))
+}
+
+struct Occurrence {
+    buffer: &'static LispBuffer,
+    /// The buffer position, counting from 1.
+    position: usize,
+    /// The text of the line, used as the summary.
+    summary: String,
+    definition: bool,
+}
+
+type Index = HashMap<String, Vec<Occurrence>>;
+
+static INDEXES: LazyLock<Mutex<HashMap<i64, Index>>> = LazyLock::new(Default::default);
+static NEXT_INDEX: AtomicI64 = AtomicI64::new(0);
+
+/// Drop the index with `id` once nothing refers to it.
+pub(crate) fn release(id: i64) {
+    INDEXES.lock().unwrap().remove(&id);
+}
+
+/// Add the identifiers in `text` to `index`.
+fn index_text(text: &str, buffer: &'static LispBuffer, keywords: &[&str], index: &mut Index) {
+    let mut line_start = 1;
+    for line in text.split_inclusive('\n') {
+        let summary = line.trim_end_matches(['\n', '\r']);
+        let mut previous: Option<&str> = None;
+        let mut chars = summary.char_indices().enumerate().peekable();
+        while let Some((column, (start, chr))) = chars.next() {
+            if !(chr.is_alphanumeric() || chr == '_') {
+                continue;
+            }
+            let mut end = start + chr.len_utf8();
+            while let Some(&(_, (i, chr))) = chars.peek() {
+                if !(chr.is_alphanumeric() || chr == '_' || chr == '-') {
+                    break;
+                }
+                end = i + chr.len_utf8();
+                chars.next();
+            }
+            // skip numbers and the words that start with them
+            if chr.is_numeric() {
+                continue;
+            }
+            let identifier = &summary[start..end];
+            let occurrence = Occurrence {
+                buffer,
+                position: line_start + column,
+                summary: summary.to_owned(),
+                definition: previous.is_some_and(|x| keywords.contains(&x)),
+            };
+            index.entry(identifier.to_owned()).or_default().push(occurrence);
+            previous = Some(identifier);
+        }
+        line_start += line.chars().count();
+    }
+}
+
+/// Build an index of the identifiers in BUFFERS and return it.
+#[defun]
+fn xref_make_index<'ob>(buffers: List, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    let keywords = match env.vars.get(sym::XREF_DEFINITION_KEYWORDS) {
+        Some(keywords) => keywords.bind(cx),
+        None => NIL,
+    };
+    let keywords: Vec<&str> = List::try_from(keywords)?
+        .elements()
+        .map(|x| x?.try_into())
+        .collect::<Result<_>>()?;
+    let mut index = Index::new();
+    for buffer in buffers.elements() {
+        let buffer = buffer?;
+        let ObjectType::Buffer(buffer) = buffer.untag() else { bail!("Not a buffer: {buffer}") };
+        let text = env.with_buffer(buffer, |x| x.text.to_string())?;
+        index_text(&text, buffer, &keywords, &mut index);
+    }
+    let id = NEXT_INDEX.fetch_add(1, Ordering::Relaxed);
+    INDEXES.lock().unwrap().insert(id, index);
+    Ok(record(sym::XREF_INDEX, &[cx.add(id)], cx))
+}
+
+fn with_index<T>(index: Object, f: impl FnOnce(&Index) -> T) -> Result<T> {
+    let id = match fields(index, sym::XREF_INDEX, 1).map(|x| x[0].untag()) {
+        Some(ObjectType::Int(id)) => id,
+        _ => bail!("Not an xref index: {index}"),
+    };
+    let indexes = INDEXES.lock().unwrap();
+    let index = indexes.get(&id).ok_or_else(|| anyhow!("Not an xref index: {index}"))?;
+    Ok(f(index))
+}
+
+/// Return a sorted list of the identifiers in INDEX.
+#[defun]
+fn xref_index_identifiers<'ob>(index: Object, cx: &'ob Context) -> Result<Object<'ob>> {
+    let mut identifiers = with_index(index, |x| x.keys().cloned().collect::<Vec<_>>())?;
+    identifiers.sort();
+    let identifiers: Vec<_> = identifiers.into_iter().map(|x| cx.add(x)).collect();
+    Ok(slice_into_list(&identifiers, None, cx))
+}
+
+fn find_occurrences<'ob>(
+    index: Object,
+    identifier: &str,
+    definitions: bool,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let items = with_index(index, |index| {
+        let occurrences = index.get(identifier).map(Vec::as_slice).unwrap_or_default();
+        occurrences
+            .iter()
+            .filter(|x| x.definition == definitions)
+            .map(|x| {
+                let location =
+                    record(sym::XREF_BUFFER_LOCATION, &[cx.add(x.buffer), cx.add(x.position)], cx);
+                record(sym::XREF_ITEM, &[cx.add(x.summary.as_str()), location], cx)
+            })
+            .collect::<Vec<_>>()
+    })?;
+    Ok(slice_into_list(&items, None, cx))
+}
+
+/// Return a list of xref items for the definitions of IDENTIFIER in INDEX.
+#[defun]
+fn xref_index_definitions<'ob>(
+    index: Object,
+    identifier: &str,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    find_occurrences(index, identifier, true, cx)
+}
+
+/// Return a list of xref items for the uses of IDENTIFIER in INDEX that are
+/// not definitions.
+#[defun]
+fn xref_index_references<'ob>(
+    index: Object,
+    identifier: &str,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    find_occurrences(index, identifier, false, cx)
+}
+
+defvar!(
+    XREF_DEFINITION_KEYWORDS,
+    list![
+        "defun",
+        "defmacro",
+        "defvar",
+        "defconst",
+        "defcustom",
+        "defsubst",
+        "fn",
+        "struct",
+        "enum",
+        "trait",
+        "type",
+        "def",
+        "class",
+        "function"
+    ]
+);
+defsym!(XREF_ITEM);
+defsym!(XREF_FILE_LOCATION);
+defsym!(XREF_BUFFER_LOCATION);
+defsym!(XREF_BOGUS_LOCATION);
+defsym!(XREF_INDEX);
+
+#[cfg(test)]
+mod test {
+    use crate::interpreter::{assert_lisp, assert_lisp_with_vars};
+
+    #[test]
+    fn test_xref_items() {
+        assert_lisp(
+            "(let ((item (xref-make \"foo\" (xref-make-file-location \"a.el\" 3 4))))
+               (list (xref-item-p item) (xref-item-summary item)
+                     (xref-location-group (xref-item-location item))
+                     (xref-location-line (xref-item-location item))))",
+            "(t \"foo\" \"a.el\" 3)",
+        );
+        assert_lisp("(xref-location-line (xref-make-bogus-location \"gone\"))", "nil");
+        assert_lisp(
+            "(mapcar (lambda (x) (cons (car x) (length (cdr x))))
+               (xref-group-items
+                 (list (xref-make \"a\" (xref-make-file-location \"x\" 1 0))
+                       (xref-make \"b\" (xref-make-file-location \"y\" 1 0))
+                       (xref-make \"c\" (xref-make-file-location \"x\" 2 0)))))",
+            "((\"x\" . 2) (\"y\" . 1))",
+        );
+        assert_lisp("(xref-item-p '(xref-item \"a\" nil))", "nil");
+    }
+
+    #[test]
+    fn test_xref_index() {
+        assert_lisp_with_vars(
+            "(let ((buffer (get-buffer-create \"xref-test\")))
+               (set-buffer buffer)
+               (insert \"(defun my-fn (x)\\n  (1+ x))\\n(my-fn 2) (my-fn 3)\\n\")
+               (let ((index (xref-make-index (list buffer))))
+                 (list (mapcar (lambda (x) (xref-location-line (xref-item-location x)))
+                               (xref-index-definitions index \"my-fn\"))
+                       (mapcar #'xref-item-summary (xref-index-references index \"my-fn\"))
+                       (xref-index-identifiers index))))",
+            "((1) (\"(my-fn 2) (my-fn 3)\" \"(my-fn 2) (my-fn 3)\")
+              (\"defun\" \"my-fn\" \"x\"))",
+        );
+    }
+}